@@ -0,0 +1,51 @@
+use actix_web::{App, HttpResponse, HttpServer, web};
+use nostr::Keys;
+use sentrystr::{Config, Level, NostrSentryClient};
+use sentrystr_actix::SentryStrMiddleware;
+use std::sync::Arc;
+
+/// Captures an event from inside the handler; it carries the request from the scope the
+/// middleware sets up.
+async fn get_user(client: web::Data<Arc<NostrSentryClient>>, id: web::Path<u64>) -> HttpResponse {
+    let id = id.into_inner();
+    if id == 0 {
+        let _ = client
+            .capture_message(Level::Warning, "Lookup of user 0")
+            .await;
+        // Reported by the middleware as a failed request.
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok().body(format!("user {}", id))
+}
+
+async fn crash() -> &'static str {
+    panic!("handler crashed");
+}
+
+#[actix_web::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let keys = Keys::generate();
+    let config = Config::from_keys(&keys, vec!["wss://relay.damus.io".to_string()])?;
+    let client = Arc::new(NostrSentryClient::new(config).await?);
+
+    let app_client = Arc::clone(&client);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(
+                SentryStrMiddleware::new(Arc::clone(&app_client))
+                    .with_breadcrumbs(true)
+                    .exclude_path("/health"),
+            )
+            .app_data(web::Data::new(Arc::clone(&app_client)))
+            .route("/health", web::get().to(|| async { "ok" }))
+            .route("/users/{id}", web::get().to(get_user))
+            .route("/crash", web::get().to(crash))
+    })
+    .bind(("127.0.0.1", 8080))?;
+
+    println!("Listening on http://127.0.0.1:8080, try /users/0 and /crash");
+    server.run().await?;
+
+    client.close(std::time::Duration::from_secs(5)).await;
+    Ok(())
+}
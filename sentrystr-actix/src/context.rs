@@ -0,0 +1,78 @@
+use actix_web::dev::ServiceRequest;
+use sentrystr::http::{REQUEST_ID_HEADER, scrub_query, scrub_url, selected_headers};
+use sentrystr::{Event, Level, Scope};
+use std::time::Duration;
+
+/// What is known of a request before it is handled.
+pub(crate) struct RequestContext {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    /// The actix resource pattern the request matches, such as `/users/{id}`.
+    pub(crate) route: Option<String>,
+    pub(crate) request_id: String,
+    request: sentrystr::Request,
+}
+
+impl RequestContext {
+    pub(crate) fn new(request: &ServiceRequest, header_allowlist: &[String]) -> Self {
+        let request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        Self {
+            method: request.method().to_string(),
+            path: request.path().to_string(),
+            route: request.match_pattern(),
+            request_id,
+            request: sentrystr::Request {
+                url: Some(scrub_url(&request.uri().to_string())),
+                method: Some(request.method().to_string()),
+                headers: Some(selected_headers(
+                    request
+                        .headers()
+                        .iter()
+                        .map(|(name, value)| (name.as_str(), value.as_bytes())),
+                    header_allowlist,
+                )),
+                query_string: request.uri().query().map(scrub_query),
+                cookies: None,
+                data: None,
+                env: None,
+            },
+        }
+    }
+
+    /// The route when known, else the path, which may hold ids.
+    pub(crate) fn route_or_path(&self) -> &str {
+        self.route.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Puts the request into the scope of its handler, so the events it captures carry
+    /// the request and its id.
+    pub(crate) fn configure(&self, scope: &mut Scope) {
+        scope.set_tag("request_id", self.request_id.clone());
+        scope.set_tag("http.method", self.method.clone());
+        scope.set_tag("http.route", self.route_or_path());
+        scope.set_request(Some(self.request.clone()));
+    }
+
+    /// The Error event of a failed request.
+    pub(crate) fn event(&self, message: String, status: Option<u16>, latency: Duration) -> Event {
+        let mut event = Event::new()
+            .with_level(Level::Error)
+            .with_message(message)
+            .with_transaction(format!("{} {}", self.method, self.route_or_path()))
+            .with_duration(latency)
+            .with_tag("request_id", self.request_id.clone())
+            .with_tag("http.method", self.method.clone())
+            .with_tag("http.route", self.route_or_path());
+        if let Some(status) = status {
+            event = event.with_tag("http.status_code", status.to_string());
+        }
+        event.request = Some(self.request.clone());
+        event
+    }
+}
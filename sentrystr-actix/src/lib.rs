@@ -0,0 +1,34 @@
+//! # SentryStr Actix
+//!
+//! An actix-web middleware reporting failed HTTP requests to SentryStr, behaving like
+//! the tower layer of sentrystr-tower.
+//!
+//! ## Quick Start
+//!
+//! ```rust,no_run
+//! use actix_web::{App, HttpServer, web};
+//! use sentrystr::{Config, NostrSentryClient};
+//! use sentrystr_actix::SentryStrMiddleware;
+//! use std::sync::Arc;
+//!
+//! #[actix_web::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let client = Arc::new(NostrSentryClient::new(Config::from_env()?).await?);
+//!
+//!     HttpServer::new(move || {
+//!         App::new()
+//!             .wrap(SentryStrMiddleware::new(Arc::clone(&client)))
+//!             .route("/", web::get().to(|| async { "hello" }))
+//!     })
+//!     .bind(("127.0.0.1", 8080))?
+//!     .run()
+//!     .await?;
+//!     Ok(())
+//! }
+//! ```
+
+mod context;
+pub mod middleware;
+
+pub use middleware::{SentryStrMiddleware, SentryStrMiddlewareService};
+pub use sentrystr::http::{DEFAULT_HEADER_ALLOWLIST, REQUEST_ID_HEADER};
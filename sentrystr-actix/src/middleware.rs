@@ -0,0 +1,371 @@
+use crate::context::RequestContext;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready};
+use futures_util::FutureExt;
+use futures_util::future::LocalBoxFuture;
+use sentrystr::http::DEFAULT_HEADER_ALLOWLIST;
+use sentrystr::{Breadcrumb, Level, NostrSentryClient, with_scope};
+use std::future::{Ready, ready};
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// An actix-web middleware reporting failed HTTP requests like sentrystr-tower's layer:
+/// responses and errors with a 5xx status, and panics, are captured as Error events
+/// with the request, its id, the matched resource pattern and the allowlisted headers.
+/// Handlers run in a [`sentrystr::with_scope`] holding the request, so the events they
+/// capture carry it too.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use actix_web::{App, HttpServer, web};
+/// use sentrystr::NostrSentryClient;
+/// use sentrystr_actix::SentryStrMiddleware;
+/// use std::sync::Arc;
+///
+/// # async fn example(client: Arc<NostrSentryClient>) -> std::io::Result<()> {
+/// HttpServer::new(move || {
+///     App::new()
+///         .wrap(SentryStrMiddleware::new(Arc::clone(&client)).exclude_path("/health"))
+///         .route("/users/{id}", web::get().to(|| async { "user" }))
+/// })
+/// .bind(("127.0.0.1", 8080))?
+/// .run()
+/// .await
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SentryStrMiddleware {
+    client: Arc<NostrSentryClient>,
+    breadcrumbs: bool,
+    header_allowlist: Vec<String>,
+    excluded_paths: Vec<String>,
+}
+
+impl SentryStrMiddleware {
+    pub fn new(client: Arc<NostrSentryClient>) -> Self {
+        Self {
+            client,
+            breadcrumbs: false,
+            header_allowlist: DEFAULT_HEADER_ALLOWLIST
+                .iter()
+                .map(|header| header.to_string())
+                .collect(),
+            excluded_paths: Vec::new(),
+        }
+    }
+
+    /// Records a breadcrumb on the client for every request, with its method, path,
+    /// status and latency, so captured events show the requests that led up to them.
+    pub fn with_breadcrumbs(mut self, record: bool) -> Self {
+        self.breadcrumbs = record;
+        self
+    }
+
+    /// Headers recorded on captured events, instead of [`DEFAULT_HEADER_ALLOWLIST`].
+    /// Credentials such as `authorization` and `cookie` are always filtered.
+    pub fn with_header_allowlist(mut self, headers: Vec<String>) -> Self {
+        self.header_allowlist = headers;
+        self
+    }
+
+    /// Leaves requests for `path`, such as a health check, alone: they get no scope,
+    /// breadcrumb or event. A `path` ending in `*` excludes every path starting with
+    /// the rest, so `/static/*` excludes `/static/app.js`; any other `path` matches
+    /// exactly.
+    pub fn exclude_path(mut self, path: impl Into<String>) -> Self {
+        self.excluded_paths.push(path.into());
+        self
+    }
+
+    /// [`Self::exclude_path`] for each of `paths`.
+    pub fn with_excluded_paths(mut self, paths: Vec<String>) -> Self {
+        self.excluded_paths.extend(paths);
+        self
+    }
+
+    fn is_excluded(&self, path: &str) -> bool {
+        self.excluded_paths
+            .iter()
+            .any(|excluded| match excluded.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => excluded == path,
+            })
+    }
+
+    /// Records the end of a request, capturing an event on a spawned task when it failed.
+    fn finish(
+        &self,
+        context: &RequestContext,
+        status: Option<u16>,
+        failure: Option<String>,
+        latency: Duration,
+    ) {
+        if self.breadcrumbs {
+            let mut breadcrumb = Breadcrumb::new(format!("{} {}", context.method, context.path))
+                .with_category("http")
+                .with_level(if failure.is_some() {
+                    Level::Error
+                } else {
+                    Level::Info
+                })
+                .with_data("latency_ms", (latency.as_millis() as u64).into())
+                .with_data("request_id", context.request_id.clone().into());
+            if let Some(status) = status {
+                breadcrumb = breadcrumb.with_data("status_code", status.into());
+            }
+            self.client.add_breadcrumb(breadcrumb);
+        }
+
+        if let Some(message) = failure {
+            let event = context.event(message, status, latency);
+            let client = Arc::clone(&self.client);
+            actix_web::rt::spawn(async move {
+                if let Err(e) = client.capture_event(event).await {
+                    eprintln!("Failed to capture HTTP error: {}", e);
+                }
+            });
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SentryStrMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = SentryStrMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SentryStrMiddlewareService {
+            service,
+            middleware: Arc::new(self.clone()),
+        }))
+    }
+}
+
+/// The service of [`SentryStrMiddleware`].
+pub struct SentryStrMiddlewareService<S> {
+    service: S,
+    middleware: Arc<SentryStrMiddleware>,
+}
+
+impl<S, B> Service<ServiceRequest> for SentryStrMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        if self.middleware.is_excluded(request.path()) {
+            return Box::pin(self.service.call(request));
+        }
+
+        let middleware = Arc::clone(&self.middleware);
+        let context = RequestContext::new(&request, &middleware.header_allowlist);
+        let response = self.service.call(request);
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = with_scope(
+                |scope| context.configure(scope),
+                AssertUnwindSafe(response).catch_unwind(),
+            )
+            .await;
+            let latency = started.elapsed();
+            let name = format!("{} {}", context.method, context.route_or_path());
+
+            match result {
+                Ok(Ok(response)) => {
+                    let status = response.status();
+                    let failure = match response.response().error() {
+                        _ if !status.is_server_error() => None,
+                        Some(e) => Some(format!("{} returned {}: {}", name, status, e)),
+                        None => Some(format!("{} returned {}", name, status)),
+                    };
+                    middleware.finish(&context, Some(status.as_u16()), failure, latency);
+                    Ok(response)
+                }
+                Ok(Err(e)) => {
+                    let status = e.as_response_error().status_code();
+                    let failure = status
+                        .is_server_error()
+                        .then(|| format!("{} failed: {}", name, e));
+                    middleware.finish(&context, Some(status.as_u16()), failure, latency);
+                    Err(e)
+                }
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    middleware.finish(
+                        &context,
+                        None,
+                        Some(format!("{} panicked: {}", name, message)),
+                        latency,
+                    );
+                    std::panic::resume_unwind(panic)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, HttpResponse, test, web};
+    use nostr::Keys;
+    use sentrystr::transport::TransportFuture;
+    use sentrystr::{Config, Event, Transport};
+    use std::sync::Mutex;
+
+    #[derive(Debug)]
+    struct RecordingTransport(Arc<Mutex<Vec<Event>>>);
+
+    impl Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn client(recorded: &Arc<Mutex<Vec<Event>>>) -> Arc<NostrSentryClient> {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(10, Duration::from_secs(1))
+            .with_additional_transport(RecordingTransport(Arc::clone(recorded)));
+        Arc::new(NostrSentryClient::new(config).await.unwrap())
+    }
+
+    /// The events recorded once `count` arrived.
+    async fn wait_for(recorded: &Mutex<Vec<Event>>, count: usize) -> Vec<Event> {
+        for _ in 0..100 {
+            if recorded.lock().unwrap().len() >= count {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        recorded.lock().unwrap().clone()
+    }
+
+    #[actix_web::test]
+    async fn server_errors_are_captured_with_the_resource_pattern() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let app = test::init_service(
+            App::new()
+                .wrap(SentryStrMiddleware::new(client(&recorded).await))
+                .route(
+                    "/users/{id}",
+                    web::get().to(|| async { HttpResponse::InternalServerError().finish() }),
+                ),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/users/7")
+            .insert_header(("x-request-id", "req-1"))
+            .insert_header(("authorization", "Bearer secret"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), 500);
+
+        let events = wait_for(&recorded, 1).await;
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(
+            event.message.as_deref(),
+            Some("GET /users/{id} returned 500 Internal Server Error")
+        );
+        assert_eq!(event.tags["http.route"], "/users/{id}");
+        assert_eq!(event.tags["http.status_code"], "500");
+        assert_eq!(event.tags["request_id"], "req-1");
+        let headers = event.request.as_ref().unwrap().headers.as_ref().unwrap();
+        assert!(!headers.contains_key("authorization"));
+    }
+
+    #[actix_web::test]
+    async fn excluded_and_successful_requests_are_not_captured() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let failing = || web::get().to(|| async { HttpResponse::InternalServerError().finish() });
+        let app = test::init_service(
+            App::new()
+                .wrap(
+                    SentryStrMiddleware::new(client(&recorded).await)
+                        .exclude_path("/health")
+                        .exclude_path("/static/*"),
+                )
+                .route("/health", failing())
+                .route("/static/{file}", failing())
+                .route("/ok", web::get().to(|| async { "ok" })),
+        )
+        .await;
+
+        for uri in ["/health", "/static/app.js", "/ok"] {
+            let request = test::TestRequest::get().uri(uri).to_request();
+            test::call_service(&app, request).await;
+        }
+
+        actix_web::rt::time::sleep(Duration::from_millis(200)).await;
+        assert!(recorded.lock().unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn handler_events_carry_the_request_scope() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(&recorded).await;
+        let handler_client = Arc::clone(&client);
+        let app = test::init_service(
+            App::new()
+                .wrap(SentryStrMiddleware::new(Arc::clone(&client)))
+                .route(
+                    "/orders/{id}",
+                    web::get().to(move || {
+                        let client = Arc::clone(&handler_client);
+                        async move {
+                            client
+                                .capture_message(Level::Warning, "slow order")
+                                .await
+                                .unwrap();
+                            "ok"
+                        }
+                    }),
+                ),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .uri("/orders/3?expand=items&token=abc")
+            .insert_header(("x-request-id", "req-2"))
+            .to_request();
+        test::call_service(&app, request).await;
+
+        let events = wait_for(&recorded, 1).await;
+        let event = &events[0];
+        assert_eq!(event.message.as_deref(), Some("slow order"));
+        assert_eq!(event.tags["request_id"], "req-2");
+        assert_eq!(event.tags["http.route"], "/orders/{id}");
+        let request = event.request.as_ref().unwrap();
+        assert_eq!(request.method.as_deref(), Some("GET"));
+        assert_eq!(
+            request.query_string.as_deref(),
+            Some("expand=items&token=[Filtered]")
+        );
+        assert!(!request.url.as_deref().unwrap().contains("abc"));
+    }
+}
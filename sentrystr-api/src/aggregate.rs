@@ -0,0 +1,271 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use nostr::{PublicKey, ToBech32};
+use sentrystr_collector::CollectedEvent;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::str::FromStr;
+
+use crate::models::{AuthorSummary, GroupCount, TimeseriesPoint, TimeseriesSeries};
+
+/// Key used for events that don't carry the grouped dimension.
+pub const UNKNOWN_GROUP: &str = "unknown";
+/// Key of the single series returned when a time series is not grouped.
+pub const ALL_GROUP: &str = "all";
+
+/// Dimensions events can be grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Level,
+    Author,
+    Service,
+    Environment,
+    /// The publisher's fingerprint, see [`CollectedEvent::fingerprint`].
+    Fingerprint,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "level" => Ok(GroupBy::Level),
+            "author" => Ok(GroupBy::Author),
+            "service" => Ok(GroupBy::Service),
+            "environment" => Ok(GroupBy::Environment),
+            "fingerprint" => Ok(GroupBy::Fingerprint),
+            _ => Err(format!(
+                "Invalid group_by '{}'. Expected one of: level, author, service, environment, fingerprint",
+                s
+            )),
+        }
+    }
+}
+
+impl GroupBy {
+    fn key(&self, event: &CollectedEvent) -> Option<String> {
+        match self {
+            GroupBy::Level => Some(event.event.level.to_string()),
+            GroupBy::Author => Some(event.author.to_string()),
+            GroupBy::Service => event.nostr_tag("service").map(str::to_string),
+            GroupBy::Environment => event
+                .nostr_tag("env")
+                .map(str::to_string)
+                .or_else(|| event.event.environment.clone()),
+            GroupBy::Fingerprint => {
+                Some(event.fingerprint().join(sentrystr::FINGERPRINT_SEPARATOR))
+            }
+        }
+    }
+}
+
+/// Counts `events` per value of `group_by`, largest groups first.
+pub fn group_counts(events: &[CollectedEvent], group_by: GroupBy) -> Vec<GroupCount> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+
+    for event in events {
+        let key = group_by
+            .key(event)
+            .unwrap_or_else(|| UNKNOWN_GROUP.to_string());
+        *counts.entry(key).or_default() += 1;
+    }
+
+    let mut groups: Vec<GroupCount> = counts
+        .into_iter()
+        .map(|(key, count)| GroupCount { key, count })
+        .collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    groups
+}
+
+/// Start of the bucket containing `time`. Buckets are aligned to multiples of
+/// `bucket_seconds` since the unix epoch, so they don't shift with the request time.
+pub fn bucket_start(time: DateTime<Utc>, bucket_seconds: i64) -> DateTime<Utc> {
+    let start = time.timestamp().div_euclid(bucket_seconds) * bucket_seconds;
+    DateTime::from_timestamp(start, 0).unwrap_or(time)
+}
+
+/// Number of buckets between the buckets containing `since` and `until`, inclusive.
+pub fn bucket_count(since: DateTime<Utc>, until: DateTime<Utc>, bucket_seconds: i64) -> i64 {
+    let first = bucket_start(since, bucket_seconds).timestamp();
+    let last = bucket_start(until, bucket_seconds).timestamp();
+    (last - first).div_euclid(bucket_seconds) + 1
+}
+
+/// Counts `events` per time bucket, one series per value of `group_by` (or a single
+/// [`ALL_GROUP`] series). Every series has a point for every bucket from `since` to
+/// `until`, with zero counts where no events fell, so charts show no gaps.
+pub fn timeseries(
+    events: &[CollectedEvent],
+    group_by: Option<GroupBy>,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    bucket_seconds: i64,
+) -> Vec<TimeseriesSeries> {
+    let first = bucket_start(since, bucket_seconds);
+    let buckets = bucket_count(since, until, bucket_seconds).max(0) as usize;
+
+    let mut counts: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    if group_by.is_none() {
+        counts.insert(ALL_GROUP.to_string(), vec![0; buckets]);
+    }
+
+    for event in events {
+        let offset = (event.event.timestamp - first).num_seconds();
+        if offset < 0 {
+            continue;
+        }
+        let index = (offset / bucket_seconds) as usize;
+        if index >= buckets {
+            continue;
+        }
+
+        let key = match group_by {
+            Some(group_by) => group_by
+                .key(event)
+                .unwrap_or_else(|| UNKNOWN_GROUP.to_string()),
+            None => ALL_GROUP.to_string(),
+        };
+        counts.entry(key).or_insert_with(|| vec![0; buckets])[index] += 1;
+    }
+
+    counts
+        .into_iter()
+        .map(|(key, counts)| TimeseriesSeries {
+            key,
+            points: counts
+                .into_iter()
+                .enumerate()
+                .map(|(index, count)| TimeseriesPoint {
+                    t: first + TimeDelta::seconds(index as i64 * bucket_seconds),
+                    count,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Folds events into one summary per author, most recently seen first.
+pub fn summarize_authors(events: &[CollectedEvent]) -> Vec<AuthorSummary> {
+    let mut by_author: HashMap<PublicKey, (usize, DateTime<Utc>, BTreeSet<String>)> =
+        HashMap::new();
+
+    for event in events {
+        let entry = by_author
+            .entry(event.author)
+            .or_insert_with(|| (0, event.event.timestamp, BTreeSet::new()));
+        entry.0 += 1;
+        entry.1 = entry.1.max(event.event.timestamp);
+        entry.2.insert(event.event.level.to_string());
+    }
+
+    let mut authors: Vec<AuthorSummary> = by_author
+        .into_iter()
+        .map(
+            |(author, (event_count, last_seen, levels_seen))| AuthorSummary {
+                pubkey_hex: author.to_hex(),
+                npub: author.to_bech32().unwrap_or_else(|_| author.to_hex()),
+                event_count,
+                last_seen,
+                levels_seen: levels_seen.into_iter().collect(),
+            },
+        )
+        .collect();
+    authors.sort_by_key(|author| Reverse(author.last_seen));
+    authors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventId, Keys, Timestamp};
+    use sentrystr::{Event, Level};
+
+    fn collected(event: Event) -> CollectedEvent {
+        CollectedEvent {
+            event,
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            created_at: Timestamp::now(),
+            received_at: Utc::now(),
+            nostr_tags: Vec::new(),
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn events_are_grouped_by_the_publisher_fingerprint() {
+        let events = [
+            collected(
+                Event::new()
+                    .with_message("timeout after 30s")
+                    .with_fingerprint(["payments", "timeout"]),
+            ),
+            collected(
+                Event::new()
+                    .with_message("timeout after 45s")
+                    .with_fingerprint(["payments", "timeout"]),
+            ),
+            collected(Event::new().with_message("disk full")),
+        ];
+
+        let groups = group_counts(&events, "fingerprint".parse().unwrap());
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].key, "payments|timeout");
+        assert_eq!(groups[0].count, 2);
+        assert_eq!(groups[1].count, 1);
+    }
+
+    fn at(seconds: i64, level: Level) -> CollectedEvent {
+        collected(
+            Event::new()
+                .with_level(level)
+                .with_timestamp(DateTime::from_timestamp(seconds, 0).unwrap()),
+        )
+    }
+
+    #[test]
+    fn empty_buckets_are_zero_filled_in_every_series() {
+        let since = DateTime::from_timestamp(600, 0).unwrap();
+        let until = DateTime::from_timestamp(899, 0).unwrap();
+        let events = [at(610, Level::Error), at(850, Level::Warning)];
+
+        let series = timeseries(&events, Some(GroupBy::Level), since, until, 60);
+
+        assert_eq!(series.len(), 2);
+        for series in &series {
+            assert_eq!(series.points.len(), 5);
+        }
+        let counts = |key: &str| -> Vec<usize> {
+            let series = series.iter().find(|series| series.key == key).unwrap();
+            series.points.iter().map(|point| point.count).collect()
+        };
+        assert_eq!(counts("error"), vec![1, 0, 0, 0, 0]);
+        assert_eq!(counts("warning"), vec![0, 0, 0, 0, 1]);
+
+        let series = timeseries(&[], None, since, until, 60);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].key, ALL_GROUP);
+        assert!(series[0].points.iter().all(|point| point.count == 0));
+    }
+
+    #[test]
+    fn buckets_align_to_the_bucket_size_not_to_since() {
+        let since = DateTime::from_timestamp(1_000, 0).unwrap();
+        let until = DateTime::from_timestamp(1_250, 0).unwrap();
+        let events = [at(1_190, Level::Error), at(1_210, Level::Error)];
+
+        let series = timeseries(&events, None, since, until, 100);
+
+        let points: Vec<(i64, usize)> = series[0]
+            .points
+            .iter()
+            .map(|point| (point.t.timestamp(), point.count))
+            .collect();
+        assert_eq!(points, vec![(1_000, 0), (1_100, 1), (1_200, 1)]);
+
+        let since = DateTime::from_timestamp(1_050, 0).unwrap();
+        assert_eq!(bucket_start(since, 100).timestamp(), 1_000);
+        assert_eq!(bucket_count(since, until, 100), 3);
+    }
+}
@@ -1,11 +1,332 @@
-use axum::{Router, routing::get};
+use axum::{
+    Json, Router, middleware,
+    routing::{delete, get, post},
+};
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{NotForContentType, Predicate, SizeAbove},
+};
 use tower_http::cors::CorsLayer;
+use tower_http::limit::RequestBodyLimitLayer;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
 
-use crate::handlers::{get_events, health};
+use crate::AppState;
+use crate::grafana::{
+    self, GrafanaAnnotation, GrafanaAnnotationRequest, GrafanaColumn, GrafanaQueryRequest,
+    GrafanaQueryResult, GrafanaRange, GrafanaSearchRequest, GrafanaSeries, GrafanaTable,
+    GrafanaTarget, GrafanaTargetType,
+};
+use crate::handlers::{
+    count_events, create_filter, create_webhook, delete_filter, delete_webhook, get_author_events,
+    get_authors, get_events, get_filter_events, get_timeseries, get_webhook, health, list_filters,
+    list_webhooks, ready, test_alert,
+};
+use crate::limits::{payload_too_large_body, request_timeout};
+use crate::metrics::{gated_metrics, metrics, track_requests};
+use crate::models::{
+    AuthorSummary, CountResponse, CreateFilterRequest, CreateWebhookRequest, ErrorBody, ErrorCode,
+    ErrorResponse, EventData, EventResponse, EventsResponse, GroupCount, GroupsResponse,
+    HealthResponse, NdjsonTrailer, RelayHealth, RelayPublishResult, SavedFilter, TestAlertRequest,
+    TestAlertResponse, TimeseriesPoint, TimeseriesResponse, TimeseriesSeries, WebhookInfo,
+    WebhookStatus,
+};
+use crate::request_id::trace_requests;
 
-pub fn create_app() -> Router {
-    Router::new()
+/// OpenAPI description of the server. Every route added to [`create_app`] must also be
+/// listed here.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "SentryStr API",
+        description = "Query SentryStr events collected from Nostr relays"
+    ),
+    paths(
+        crate::handlers::health,
+        crate::handlers::ready,
+        crate::handlers::get_events,
+        crate::handlers::count_events,
+        crate::handlers::get_timeseries,
+        crate::handlers::get_author_events,
+        crate::handlers::get_authors,
+        crate::handlers::list_filters,
+        crate::handlers::create_filter,
+        crate::handlers::delete_filter,
+        crate::handlers::get_filter_events,
+        crate::handlers::list_webhooks,
+        crate::handlers::create_webhook,
+        crate::handlers::get_webhook,
+        crate::handlers::delete_webhook,
+        crate::handlers::test_alert,
+        crate::grafana::grafana_health,
+        crate::grafana::grafana_search,
+        crate::grafana::grafana_query,
+        crate::grafana::grafana_annotations
+    ),
+    modifiers(&SecurityAddon),
+    components(schemas(
+        AuthorSummary,
+        SavedFilter,
+        CreateFilterRequest,
+        CreateWebhookRequest,
+        WebhookInfo,
+        WebhookStatus,
+        TestAlertRequest,
+        TestAlertResponse,
+        RelayPublishResult,
+        EventResponse,
+        EventData,
+        EventsResponse,
+        CountResponse,
+        TimeseriesResponse,
+        TimeseriesSeries,
+        TimeseriesPoint,
+        GroupCount,
+        GroupsResponse,
+        NdjsonTrailer,
+        HealthResponse,
+        RelayHealth,
+        ErrorResponse,
+        ErrorBody,
+        ErrorCode,
+        GrafanaRange,
+        GrafanaSearchRequest,
+        GrafanaQueryRequest,
+        GrafanaTarget,
+        GrafanaTargetType,
+        GrafanaQueryResult,
+        GrafanaSeries,
+        GrafanaTable,
+        GrafanaColumn,
+        GrafanaAnnotationRequest,
+        GrafanaAnnotation
+    ))
+)]
+pub struct ApiDoc;
+
+/// Registers the bearer token scheme referenced by endpoints that require `--auth-token`.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+pub fn create_app(state: AppState) -> Router {
+    let mut router = Router::new()
         .route("/health", get(health))
+        .route("/ready", get(ready))
         .route("/events", get(get_events))
+        .route("/events/count", get(count_events))
+        .route("/events/timeseries", get(get_timeseries))
+        .route("/events/:author", get(get_author_events))
+        .route("/authors", get(get_authors))
+        .route("/filters", get(list_filters).post(create_filter))
+        .route("/filters/:name", delete(delete_filter))
+        .route("/filters/:name/events", get(get_filter_events))
+        .route("/webhooks", get(list_webhooks).post(create_webhook))
+        .route("/webhooks/:id", get(get_webhook).delete(delete_webhook))
+        .route("/alerts/test", post(test_alert))
+        .nest("/grafana", grafana::routes());
+
+    router = if state.config.enable_docs {
+        router.merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+    } else {
+        router.route("/openapi.json", get(openapi_json))
+    };
+
+    if state.config.metrics_addr.is_none() {
+        router = router.route("/metrics", get(gated_metrics));
+    }
+
+    router = router
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            request_timeout,
+        ))
+        .layer(RequestBodyLimitLayer::new(state.config.max_body_bytes))
+        .layer(middleware::map_response(payload_too_large_body));
+
+    if state.config.compression {
+        router = router.layer(compression(state.config.compression_min_size));
+    }
+
+    router
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            track_requests,
+        ))
+        .layer(middleware::from_fn(trace_requests))
         .layer(CorsLayer::permissive())
+        .with_state(state)
+}
+
+/// Gzip and brotli compression of responses larger than `min_size` bytes. Streamed
+/// NDJSON has no known size, so it is always compressed. SSE is excluded because
+/// buffering in the encoder would delay event delivery.
+fn compression(min_size: u16) -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(min_size)
+        .and(NotForContentType::GRPC)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE);
+    CompressionLayer::new()
+        .gzip(true)
+        .br(true)
+        .compress_when(predicate)
+}
+
+/// Standalone `/metrics` router for serving metrics on [`ApiConfig::metrics_addr`].
+///
+/// [`ApiConfig::metrics_addr`]: crate::ApiConfig::metrics_addr
+pub fn create_metrics_app(state: AppState) -> Router {
+    Router::new()
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiConfig;
+    use axum::body::Body;
+    use axum::http::{HeaderMap, Request, StatusCode, header};
+    use axum::response::{IntoResponse, Response};
+    use nostr_relay_builder::MockRelay;
+    use sentrystr_collector::EventCollector;
+    use std::io::Read;
+    use tower::ServiceExt;
+
+    async fn state(config: ApiConfig) -> AppState {
+        let collector = EventCollector::new(config.relays.clone()).await.unwrap();
+        AppState::with_collector(config, collector)
+    }
+
+    fn config() -> ApiConfig {
+        ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+    }
+
+    fn gzip_request(uri: &str) -> Request<Body> {
+        Request::get(uri)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// The encoding and the decoded body of `response`.
+    async fn decoded(response: Response) -> (Option<String>, Vec<u8>) {
+        assert_eq!(response.status(), StatusCode::OK);
+        let encoding = response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|encoding| encoding.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = match encoding.as_deref() {
+            Some("gzip") => {
+                let mut decoded = Vec::new();
+                flate2::read::GzDecoder::new(&body[..])
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                decoded
+            }
+            _ => body.to_vec(),
+        };
+        (encoding, body)
+    }
+
+    #[tokio::test]
+    async fn large_json_responses_are_compressed_on_request() {
+        let app = create_app(state(config()).await);
+        let plain = app
+            .clone()
+            .oneshot(Request::get("/openapi.json").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let (encoding, plain) = decoded(plain).await;
+        assert_eq!(encoding, None);
+
+        let response = app.oneshot(gzip_request("/openapi.json")).await.unwrap();
+        let (encoding, body) = decoded(response).await;
+
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        assert_eq!(body, plain);
+    }
+
+    #[tokio::test]
+    async fn small_responses_and_disabled_compression_stay_plain() {
+        let app = create_app(state(config()).await);
+        let (encoding, body) = decoded(app.oneshot(gzip_request("/health")).await.unwrap()).await;
+        assert_eq!(encoding, None);
+        assert!(body.len() < 1024);
+
+        let app = create_app(state(config().with_compression(false, 1024)).await);
+        let response = app.oneshot(gzip_request("/openapi.json")).await.unwrap();
+        assert_eq!(decoded(response).await.0, None);
+    }
+
+    #[tokio::test]
+    async fn event_streams_are_not_compressed() {
+        async fn stream() -> Response {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, "text/event-stream".parse().unwrap());
+            (headers, "data: tick\n\n".repeat(1000)).into_response()
+        }
+        let app = Router::new()
+            .route("/stream", get(stream))
+            .layer(compression(1024));
+
+        let (encoding, body) = decoded(app.oneshot(gzip_request("/stream")).await.unwrap()).await;
+
+        assert_eq!(encoding, None);
+        assert!(body.starts_with(b"data: tick\n\n"));
+    }
+
+    #[tokio::test]
+    async fn streamed_ndjson_is_compressed_and_still_decodes() {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let publisher = sentrystr::NostrSentryClient::new(
+            sentrystr::Config::from_keys(&nostr::Keys::generate(), [url.as_str()]).unwrap(),
+        )
+        .await
+        .unwrap();
+        for message in ["first", "second"] {
+            publisher
+                .capture_message(sentrystr::Level::Error, message)
+                .await
+                .unwrap();
+        }
+        let app = create_app(state(ApiConfig::new(vec![url])).await);
+
+        let response = app
+            .oneshot(gzip_request("/events?format=ndjson"))
+            .await
+            .unwrap();
+        let (encoding, body) = decoded(response).await;
+
+        assert_eq!(encoding.as_deref(), Some("gzip"));
+        let lines: Vec<serde_json::Value> = body
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        let mut messages: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line["event"]["message"].as_str())
+            .collect();
+        messages.sort();
+        assert_eq!(messages, ["first", "second"]);
+    }
 }
@@ -0,0 +1,38 @@
+use axum::http::{HeaderMap, header};
+
+use crate::{ApiError, AppState, Result};
+
+/// Checks the `Authorization: Bearer <token>` header against [`ApiConfig::auth_token`].
+/// Endpoints that change server state call this first; without a configured token they
+/// are disabled rather than left open.
+///
+/// [`ApiConfig::auth_token`]: crate::ApiConfig::auth_token
+pub fn require_auth(state: &AppState, headers: &HeaderMap) -> Result<()> {
+    let Some(ref expected) = state.config.auth_token else {
+        return Err(ApiError::Forbidden(
+            "This endpoint is disabled; start the server with --auth-token to enable it"
+                .to_string(),
+        ));
+    };
+
+    let provided = bearer_token(headers)
+        .ok_or_else(|| ApiError::Unauthorized("Missing bearer token".to_string()))?;
+
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return Err(ApiError::Unauthorized("Invalid bearer token".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Token from an `Authorization: Bearer <token>` header, if present.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,108 @@
+use chrono::DateTime;
+use nostr::{EventId, Timestamp};
+use sentrystr_collector::{EventPosition, SortOrder};
+
+use crate::{ApiError, Result};
+
+/// Encodes the position of the last event on a page as an opaque `/events` cursor.
+///
+/// The sort direction is part of the cursor so that it cannot be replayed with the
+/// opposite order, which would silently skip the events between the two pages.
+pub fn encode_cursor(sort: SortOrder, position: &EventPosition) -> String {
+    format!(
+        "{}.{}.{}.{}",
+        sort.as_str(),
+        position.created_at.as_u64(),
+        position.timestamp.timestamp_micros(),
+        position.event_id.to_hex()
+    )
+}
+
+/// Decodes a cursor produced by [`encode_cursor`], rejecting it when it was issued for a
+/// different sort order than `sort`. Cursors issued before they carried `created_at`
+/// take it from the event timestamp.
+pub fn decode_cursor(cursor: &str, sort: SortOrder) -> Result<EventPosition> {
+    let invalid = || ApiError::InvalidParameter {
+        param: "cursor",
+        message: format!("Invalid cursor '{}'", cursor),
+    };
+
+    let parts: Vec<&str> = cursor.splitn(4, '.').collect();
+    let (direction, created_at, micros, event_id) = match parts[..] {
+        [direction, created_at, micros, event_id] => {
+            (direction, Some(created_at), micros, event_id)
+        }
+        [direction, micros, event_id] => (direction, None, micros, event_id),
+        _ => return Err(invalid()),
+    };
+
+    let direction: SortOrder = direction.parse().map_err(|_| invalid())?;
+    if direction != sort {
+        return Err(ApiError::InvalidParameter {
+            param: "cursor",
+            message: format!(
+                "Cursor was issued for sort={} and cannot be used with sort={}",
+                direction.as_str(),
+                sort.as_str()
+            ),
+        });
+    }
+
+    let timestamp = micros
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_micros)
+        .ok_or_else(invalid)?;
+    let created_at = match created_at {
+        Some(created_at) => Timestamp::from_secs(created_at.parse().map_err(|_| invalid())?),
+        None => Timestamp::from_secs(u64::try_from(timestamp.timestamp()).map_err(|_| invalid())?),
+    };
+    let event_id = EventId::from_hex(event_id).map_err(|_| invalid())?;
+
+    Ok(EventPosition {
+        created_at,
+        timestamp,
+        event_id,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position() -> EventPosition {
+        EventPosition {
+            created_at: Timestamp::from_secs(1_704_067_199),
+            timestamp: DateTime::from_timestamp_micros(1_704_067_200_123_456).unwrap(),
+            event_id: EventId::all_zeros(),
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip_with_the_relay_timestamp() {
+        let cursor = encode_cursor(SortOrder::Desc, &position());
+        assert!(cursor.starts_with("desc.1704067199.1704067200123456."));
+        assert_eq!(decode_cursor(&cursor, SortOrder::Desc).unwrap(), position());
+    }
+
+    #[test]
+    fn cursors_are_tied_to_their_sort() {
+        let cursor = encode_cursor(SortOrder::Asc, &position());
+        assert!(matches!(
+            decode_cursor(&cursor, SortOrder::Desc),
+            Err(ApiError::InvalidParameter {
+                param: "cursor",
+                ..
+            })
+        ));
+        assert!(decode_cursor("asc.not-a-cursor", SortOrder::Asc).is_err());
+    }
+
+    #[test]
+    fn older_cursors_take_created_at_from_the_timestamp() {
+        let cursor = format!("desc.1704067200123456.{}", EventId::all_zeros().to_hex());
+        let decoded = decode_cursor(&cursor, SortOrder::Desc).unwrap();
+        assert_eq!(decoded.created_at, Timestamp::from_secs(1_704_067_200));
+        assert_eq!(decoded.timestamp, position().timestamp);
+    }
+}
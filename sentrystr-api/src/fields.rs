@@ -0,0 +1,292 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use crate::models::EventResponse;
+use crate::{ApiError, Result};
+
+/// `EventData` fields that `fields=` may select.
+const EVENT_FIELDS: &[&str] = &[
+    "event_id",
+    "timestamp",
+    "platform",
+    "level",
+    "logger",
+    "transaction",
+    "server_name",
+    "release",
+    "environment",
+    "message",
+    "exception",
+    "stacktrace",
+    "tags",
+    "extra",
+];
+
+/// Map fields whose individual keys can be selected as `tags.<key>` / `extra.<key>`.
+const MAP_FIELDS: &[&str] = &["tags", "extra"];
+
+/// Preset shapes of `EventData` in responses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum View {
+    /// Everything except `extra` and `stacktrace`.
+    Summary,
+    #[default]
+    Full,
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "summary" => Ok(View::Summary),
+            "full" => Ok(View::Full),
+            _ => Err(format!(
+                "Invalid view '{}'. Expected 'summary' or 'full'",
+                s
+            )),
+        }
+    }
+}
+
+/// Which parts of each event's `EventData` a response includes, from the `fields` and
+/// `view` parameters. `nostr_event_id`, `author` and `received_at` are always kept.
+#[derive(Debug, Clone, Default)]
+pub struct FieldSelection {
+    view: View,
+    /// Selected fields; `None` keeps the whole field, `Some` only the listed map keys.
+    fields: Option<BTreeMap<String, Option<BTreeSet<String>>>>,
+}
+
+impl FieldSelection {
+    /// Parses `fields` (comma-separated paths such as `timestamp,level,tags.service`) and
+    /// `view`. When `fields` is given it fully determines the shape and `view` is ignored.
+    pub fn parse(fields: Option<&str>, view: Option<&str>) -> Result<Self> {
+        let view = view
+            .map(str::parse::<View>)
+            .transpose()
+            .map_err(|message| ApiError::InvalidParameter {
+                param: "view",
+                message,
+            })?
+            .unwrap_or_default();
+
+        let Some(fields) = fields else {
+            return Ok(Self { view, fields: None });
+        };
+
+        let mut selected: BTreeMap<String, Option<BTreeSet<String>>> = BTreeMap::new();
+        for path in fields.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (field, key) = match path.split_once('.') {
+                Some((field, key)) if MAP_FIELDS.contains(&field) && !key.is_empty() => {
+                    (field, Some(key))
+                }
+                None if EVENT_FIELDS.contains(&path) => (path, None),
+                _ => {
+                    return Err(ApiError::InvalidParameter {
+                        param: "fields",
+                        message: format!(
+                            "Unknown field '{}'. Expected one of: {}, or tags.<key> / extra.<key>",
+                            path,
+                            EVENT_FIELDS.join(", ")
+                        ),
+                    });
+                }
+            };
+
+            match key {
+                None => {
+                    selected.insert(field.to_string(), None);
+                }
+                // Once the whole field is selected (`None`), single keys add nothing.
+                Some(key) => {
+                    if let Some(keys) = selected
+                        .entry(field.to_string())
+                        .or_insert_with(|| Some(BTreeSet::new()))
+                    {
+                        keys.insert(key.to_string());
+                    }
+                }
+            }
+        }
+
+        if selected.is_empty() {
+            return Err(ApiError::InvalidParameter {
+                param: "fields",
+                message: "Expected at least one field".to_string(),
+            });
+        }
+
+        Ok(Self {
+            view,
+            fields: Some(selected),
+        })
+    }
+
+    /// Whether responses are left untouched.
+    pub fn is_full(&self) -> bool {
+        self.fields.is_none() && self.view == View::Full
+    }
+
+    /// Serializes `value`, pruning every `EventResponse` in it. `events_key` names the
+    /// array of events inside `value`, or is `None` when `value` is a single event.
+    pub fn to_json<T: Serialize>(&self, value: &T, events_key: Option<&str>) -> Result<Vec<u8>> {
+        if self.is_full() {
+            return serde_json::to_vec(value).map_err(|e| ApiError::Internal(e.to_string()));
+        }
+
+        let mut json =
+            serde_json::to_value(value).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        match events_key {
+            Some(key) => {
+                if let Some(Value::Array(events)) = json.get_mut(key) {
+                    events.iter_mut().for_each(|event| self.prune(event));
+                }
+            }
+            None => self.prune(&mut json),
+        }
+
+        serde_json::to_vec(&json).map_err(|e| ApiError::Internal(e.to_string()))
+    }
+
+    /// Serializes a single event, as written per line when streaming.
+    pub fn event_to_json(&self, event: &EventResponse) -> Result<Vec<u8>> {
+        self.to_json(event, None)
+    }
+
+    fn prune(&self, event: &mut Value) {
+        let Some(Value::Object(data)) = event.get_mut("event") else {
+            return;
+        };
+
+        match self.fields {
+            Some(ref fields) => {
+                data.retain(|name, _| fields.contains_key(name));
+
+                for (name, keys) in fields {
+                    if let Some(keys) = keys
+                        && let Some(Value::Object(map)) = data.get_mut(name)
+                    {
+                        map.retain(|key, _| keys.contains(key));
+                    }
+                }
+            }
+            None if self.view == View::Summary => {
+                data.remove("extra");
+                data.remove("stacktrace");
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::to_event_response;
+    use crate::models::EventsResponse;
+    use chrono::Utc;
+    use nostr::{EventId, Keys, Timestamp};
+    use sentrystr::{Event, Level};
+    use sentrystr_collector::CollectedEvent;
+
+    fn events() -> EventsResponse {
+        let event = Event::new()
+            .with_message("payment failed")
+            .with_level(Level::Error)
+            .with_tag("service", "payments")
+            .with_tag("region", "eu")
+            .with_extra("order", serde_json::json!({ "id": 42, "items": [1, 2, 3] }))
+            .with_extra("customer", serde_json::json!("c-17"));
+        let collected = CollectedEvent {
+            event,
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            created_at: Timestamp::now(),
+            received_at: Utc::now(),
+            nostr_tags: Vec::new(),
+            seq: None,
+        };
+        EventsResponse {
+            events: vec![to_event_response(collected)],
+            total: 1,
+            next_cursor: None,
+            relays: None,
+        }
+    }
+
+    fn pruned(fields: Option<&str>, view: Option<&str>) -> (usize, Value) {
+        let selection = FieldSelection::parse(fields, view).unwrap();
+        let body = selection.to_json(&events(), Some("events")).unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        (body.len(), json["events"][0].clone())
+    }
+
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    #[test]
+    fn selected_fields_and_map_keys_are_the_only_ones_kept() {
+        let (full_size, full) = pruned(None, None);
+        let (size, event) = pruned(Some("timestamp, level,tags.service,extra.order"), None);
+
+        assert_eq!(
+            keys(&event),
+            ["author", "event", "nostr_event_id", "received_at"]
+        );
+        assert_eq!(
+            keys(&event["event"]),
+            ["extra", "level", "tags", "timestamp"]
+        );
+        assert_eq!(keys(&event["event"]["tags"]), ["service"]);
+        assert_eq!(keys(&event["event"]["extra"]), ["order"]);
+        assert_eq!(
+            event["event"]["extra"]["order"],
+            full["event"]["extra"]["order"]
+        );
+        assert!(size < full_size);
+    }
+
+    #[test]
+    fn whole_fields_win_over_single_keys() {
+        let (_, event) = pruned(Some("tags.service,tags"), None);
+        assert_eq!(keys(&event["event"]), ["tags"]);
+        assert_eq!(keys(&event["event"]["tags"]), ["region", "service"]);
+    }
+
+    #[test]
+    fn summaries_drop_extra_and_stacktrace_unless_fields_are_given() {
+        let (full_size, _) = pruned(None, Some("full"));
+        let (size, event) = pruned(None, Some("summary"));
+        assert!(event["event"].get("extra").is_none());
+        assert!(event["event"].get("stacktrace").is_none());
+        assert!(event["event"].get("message").is_some());
+        assert!(size < full_size);
+
+        let (_, event) = pruned(Some("extra"), Some("summary"));
+        assert_eq!(keys(&event["event"]), ["extra"]);
+    }
+
+    #[test]
+    fn unknown_or_empty_fields_are_rejected() {
+        for fields in ["nope", "tags.", "message.text", " , "] {
+            assert!(
+                FieldSelection::parse(Some(fields), None).is_err(),
+                "{}",
+                fields
+            );
+        }
+        assert!(FieldSelection::parse(None, Some("compact")).is_err());
+    }
+}
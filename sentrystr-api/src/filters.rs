@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+use crate::models::SavedFilter;
+use crate::{ApiError, Result};
+
+const MAX_NAME_LEN: usize = 64;
+
+/// Named filters saved through `POST /filters`, optionally persisted to a JSON file so
+/// they survive restarts.
+pub struct FilterStore {
+    filters: RwLock<BTreeMap<String, SavedFilter>>,
+    path: Option<PathBuf>,
+}
+
+impl FilterStore {
+    pub fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            filters: RwLock::new(BTreeMap::new()),
+            path,
+        }
+    }
+
+    /// Loads previously saved filters from the persistence file, if one is configured
+    /// and exists.
+    pub async fn load(&self) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+
+        let contents = match tokio::fs::read(path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(ApiError::Internal(format!(
+                    "Failed to read filters file {}: {}",
+                    path.display(),
+                    e
+                )));
+            }
+        };
+
+        let saved: Vec<SavedFilter> = serde_json::from_slice(&contents).map_err(|e| {
+            ApiError::Internal(format!(
+                "Failed to parse filters file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let mut filters = self.filters.write().await;
+        for filter in saved {
+            filters.insert(filter.name.clone(), filter);
+        }
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Vec<SavedFilter> {
+        self.filters.read().await.values().cloned().collect()
+    }
+
+    pub async fn get(&self, name: &str) -> Result<SavedFilter> {
+        self.filters
+            .read()
+            .await
+            .get(name)
+            .cloned()
+            .ok_or_else(|| ApiError::NotFound(format!("No saved filter named '{}'", name)))
+    }
+
+    pub async fn insert(&self, filter: SavedFilter) -> Result<()> {
+        validate_name(&filter.name)?;
+
+        let mut filters = self.filters.write().await;
+        if filters.contains_key(&filter.name) {
+            return Err(ApiError::Conflict(format!(
+                "A filter named '{}' already exists",
+                filter.name
+            )));
+        }
+
+        filters.insert(filter.name.clone(), filter);
+        self.persist(&filters).await
+    }
+
+    pub async fn remove(&self, name: &str) -> Result<()> {
+        let mut filters = self.filters.write().await;
+        if filters.remove(name).is_none() {
+            return Err(ApiError::NotFound(format!(
+                "No saved filter named '{}'",
+                name
+            )));
+        }
+
+        self.persist(&filters).await
+    }
+
+    /// Writes all filters to a temporary file and renames it over the persistence file,
+    /// so a crash mid-write never leaves a truncated file behind.
+    async fn persist(&self, filters: &BTreeMap<String, SavedFilter>) -> Result<()> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+
+        let saved: Vec<&SavedFilter> = filters.values().collect();
+        let contents =
+            serde_json::to_vec_pretty(&saved).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        let tmp_path = path.with_extension("tmp");
+        let written = async {
+            tokio::fs::write(&tmp_path, contents).await?;
+            tokio::fs::rename(&tmp_path, path).await
+        };
+
+        written.await.map_err(|e| {
+            ApiError::Internal(format!(
+                "Failed to write filters file {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// Filter names are slugs: lowercase letters, digits, `-` and `_`, starting with a
+/// letter or digit.
+fn validate_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidParameter {
+            param: "name",
+            message: format!(
+                "Invalid filter name '{}'. Use up to {} lowercase letters, digits, '-' or '_'",
+                name, MAX_NAME_LEN
+            ),
+        })
+    }
+}
@@ -0,0 +1,673 @@
+//! Endpoints of the Grafana SimpleJSON datasource contract, also understood by the
+//! Infinity datasource, under `/grafana`.
+//!
+//! Metrics are named `all`, `level:<level>` or `service:<service>`. `/grafana/query`
+//! answers `timeserie` targets with event counts per interval and `table` targets with
+//! the matching events; `/grafana/annotations` marks errors and fatal events.
+//!
+//! The SimpleJSON responses are plain arrays, so when more than [`GRAFANA_MAX_EVENTS`]
+//! events match, the response carries `X-Truncated: true` instead of a field.
+
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderName, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use sentrystr::Level;
+use sentrystr_collector::{CollectedEvent, EventFilter};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::aggregate::{GroupBy, group_counts, timeseries};
+use crate::{ApiError, AppState, Result};
+
+/// Metric counting every event.
+pub const ALL_METRIC: &str = "all";
+/// Upper bound on events folded into one Grafana response.
+pub const GRAFANA_MAX_EVENTS: usize = 10_000;
+/// Set to `true` on responses computed from only the first [`GRAFANA_MAX_EVENTS`].
+pub const X_TRUNCATED: &str = "x-truncated";
+/// Most rows of a table response.
+const TABLE_MAX_ROWS: usize = 1_000;
+/// How far back `/grafana/search` looks for services.
+const SERVICE_DISCOVERY_WINDOW: TimeDelta = TimeDelta::hours(24);
+/// How long `/grafana/search` reuses the services it discovered.
+const SERVICE_DISCOVERY_TTL: Duration = Duration::from_secs(300);
+const LEVELS: [Level; 5] = [
+    Level::Debug,
+    Level::Info,
+    Level::Warning,
+    Level::Error,
+    Level::Fatal,
+];
+
+/// The `/grafana` route group.
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(grafana_health))
+        .route("/search", post(grafana_search))
+        .route("/query", post(grafana_query))
+        .route("/annotations", post(grafana_annotations))
+}
+
+/// Time range of a panel.
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct GrafanaRange {
+    #[schema(value_type = String, example = "2024-01-01T00:00:00Z")]
+    pub from: DateTime<Utc>,
+    #[schema(value_type = String, example = "2024-01-01T06:00:00Z")]
+    pub to: DateTime<Utc>,
+}
+
+impl GrafanaRange {
+    fn bounds(self) -> Result<(DateTime<Utc>, DateTime<Utc>)> {
+        if self.from >= self.to {
+            return Err(ApiError::BadRequest(
+                "`range.from` must be before `range.to`".to_string(),
+            ));
+        }
+        Ok((self.from, self.to))
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GrafanaSearchRequest {
+    /// Text the metric names must contain; all metrics when empty.
+    #[serde(default)]
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GrafanaQueryRequest {
+    pub range: GrafanaRange,
+    /// Width of a point in milliseconds; widened when the range would need more points
+    /// than the server allows.
+    pub interval_ms: Option<i64>,
+    pub targets: Vec<GrafanaTarget>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GrafanaTarget {
+    /// The metric, as returned by `/grafana/search`.
+    #[serde(default)]
+    pub target: String,
+    pub ref_id: Option<String>,
+    /// `timeserie` (the default) or `table`.
+    #[serde(rename = "type", default)]
+    pub target_type: GrafanaTargetType,
+    /// Grafana sends hidden targets too; they are left out of the response.
+    #[serde(default)]
+    pub hide: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GrafanaTargetType {
+    #[default]
+    Timeserie,
+    Table,
+}
+
+/// A response entry of `/grafana/query`: a series or a table.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum GrafanaQueryResult {
+    Series(GrafanaSeries),
+    Table(GrafanaTable),
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GrafanaSeries {
+    pub target: String,
+    /// `[count, unix milliseconds]` pairs, one per interval, zero-filled.
+    #[schema(value_type = Vec<Vec<i64>>)]
+    pub datapoints: Vec<(usize, i64)>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GrafanaTable {
+    /// Always `table`.
+    #[serde(rename = "type")]
+    #[schema(value_type = String)]
+    pub table_type: &'static str,
+    pub columns: Vec<GrafanaColumn>,
+    /// Rows in column order, newest event first.
+    #[schema(value_type = Vec<Vec<Object>>)]
+    pub rows: Vec<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GrafanaColumn {
+    #[schema(value_type = String)]
+    pub text: &'static str,
+    #[serde(rename = "type")]
+    #[schema(value_type = String)]
+    pub column_type: &'static str,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GrafanaAnnotationRequest {
+    pub range: GrafanaRange,
+    /// The annotation definition, echoed back on each annotation.
+    #[schema(value_type = Object)]
+    pub annotation: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GrafanaAnnotation {
+    #[schema(value_type = Object)]
+    pub annotation: serde_json::Value,
+    /// Unix milliseconds.
+    pub time: i64,
+    pub title: String,
+    pub text: String,
+    pub tags: Vec<String>,
+}
+
+/// What a metric name selects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Metric {
+    All,
+    Level(Level),
+    Service(String),
+}
+
+impl Metric {
+    /// Parses `all`, `level:<level>` or `service:<service>`. An empty target means
+    /// `all`, as Grafana sends before a metric is picked.
+    pub fn parse(target: &str) -> Result<Self> {
+        let target = target.trim();
+        if target.is_empty() || target == ALL_METRIC {
+            return Ok(Metric::All);
+        }
+        if let Some(level) = target.strip_prefix("level:") {
+            return level
+                .parse()
+                .map(Metric::Level)
+                .map_err(|e: sentrystr::ParseLevelError| ApiError::BadRequest(e.to_string()));
+        }
+        match target.strip_prefix("service:") {
+            Some(service) if !service.is_empty() => Ok(Metric::Service(service.to_string())),
+            _ => Err(ApiError::BadRequest(format!(
+                "Invalid target '{}'. Expected all, level:<level> or service:<service>",
+                target
+            ))),
+        }
+    }
+
+    /// Matches the events of this metric. Only used on events already collected through
+    /// [`collect`], which scopes them to the visible authors.
+    fn filter(&self) -> EventFilter {
+        match self {
+            Metric::All => EventFilter::new(),
+            Metric::Level(level) => EventFilter::new().with_level(level.clone()),
+            Metric::Service(service) => EventFilter::new().with_service_filter(service.clone()),
+        }
+    }
+}
+
+/// Grafana's "Save & test" checks this answers 200.
+#[utoipa::path(
+    get,
+    path = "/grafana",
+    responses((status = 200, description = "The Grafana endpoints are available"))
+)]
+pub async fn grafana_health() -> &'static str {
+    "ok"
+}
+
+#[utoipa::path(
+    post,
+    path = "/grafana/search",
+    request_body = GrafanaSearchRequest,
+    responses(
+        (status = 200, description = "`all`, the levels, and the services of the last 24 hours, discovered at most every 5 minutes", body = Vec<String>),
+        (status = 403, description = "The server restricts the visible authors strictly", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn grafana_search(
+    State(state): State<AppState>,
+    request: Option<Json<GrafanaSearchRequest>>,
+) -> Result<Json<Vec<String>>> {
+    let target = request
+        .map(|Json(request)| request.target)
+        .unwrap_or_default();
+    let services = state.grafana_services.services(&state).await?;
+
+    let metrics = std::iter::once(ALL_METRIC.to_string())
+        .chain(LEVELS.iter().map(|level| format!("level:{}", level)))
+        .chain(
+            services
+                .into_iter()
+                .map(|service| format!("service:{}", service)),
+        )
+        .filter(|metric| metric.contains(target.trim()))
+        .collect();
+    Ok(Json(metrics))
+}
+
+/// The services `/grafana/search` offers, discovered from the events of the last
+/// [`SERVICE_DISCOVERY_WINDOW`] at most once per [`SERVICE_DISCOVERY_TTL`], so
+/// Grafana's frequent searches do not each scan a day of events.
+#[derive(Default)]
+pub struct ServiceDiscovery {
+    /// Held while discovering, so concurrent searches wait for one scan.
+    services: Mutex<Option<(Instant, Vec<String>)>>,
+}
+
+impl ServiceDiscovery {
+    async fn services(&self, state: &AppState) -> Result<Vec<String>> {
+        let mut services = self.services.lock().await;
+        if let Some((discovered_at, ref cached)) = *services
+            && discovered_at.elapsed() < SERVICE_DISCOVERY_TTL
+        {
+            return Ok(cached.clone());
+        }
+
+        let now = Utc::now();
+        let (events, _) = collect(state, now - SERVICE_DISCOVERY_WINDOW, now).await?;
+        let discovered: Vec<String> = group_counts(&events, GroupBy::Service)
+            .into_iter()
+            .filter(|group| group.key != crate::aggregate::UNKNOWN_GROUP)
+            .map(|group| group.key)
+            .collect();
+        *services = Some((Instant::now(), discovered.clone()));
+        Ok(discovered)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/grafana/query",
+    request_body = GrafanaQueryRequest,
+    responses(
+        (status = 200, description = "A series or table per visible target, with `X-Truncated: true` when more than 10000 events matched", body = Vec<GrafanaQueryResult>),
+        (status = 400, description = "Invalid range or target", body = ErrorResponse),
+        (status = 403, description = "The server restricts the visible authors strictly", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn grafana_query(
+    State(state): State<AppState>,
+    Json(request): Json<GrafanaQueryRequest>,
+) -> Result<Response> {
+    let (from, to) = request.range.bounds()?;
+    let targets: Vec<(&GrafanaTarget, Metric)> = request
+        .targets
+        .iter()
+        .filter(|target| !target.hide)
+        .map(|target| Metric::parse(&target.target).map(|metric| (target, metric)))
+        .collect::<Result<_>>()?;
+    let bucket_seconds = bucket_seconds(
+        from,
+        to,
+        request.interval_ms,
+        state.config.timeseries_max_points,
+    );
+
+    let (events, truncated) = collect(&state, from, to).await?;
+    let results: Vec<GrafanaQueryResult> = targets
+        .into_iter()
+        .map(|(target, metric)| {
+            let filter = metric.filter();
+            let matching: Vec<CollectedEvent> = events
+                .iter()
+                .filter(|event| filter.matches_collected(event))
+                .cloned()
+                .collect();
+            match target.target_type {
+                GrafanaTargetType::Timeserie => GrafanaQueryResult::Series(series(
+                    &target.target,
+                    &matching,
+                    from,
+                    to,
+                    bucket_seconds,
+                )),
+                GrafanaTargetType::Table => GrafanaQueryResult::Table(table(matching)),
+            }
+        })
+        .collect();
+    Ok(truncatable_json(results, truncated))
+}
+
+#[utoipa::path(
+    post,
+    path = "/grafana/annotations",
+    request_body = GrafanaAnnotationRequest,
+    responses(
+        (status = 200, description = "The error and fatal events of the range, with `X-Truncated: true` when more than 10000 events matched", body = Vec<GrafanaAnnotation>),
+        (status = 400, description = "Invalid range or query", body = ErrorResponse),
+        (status = 403, description = "The server restricts the visible authors strictly", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn grafana_annotations(
+    State(state): State<AppState>,
+    Json(request): Json<GrafanaAnnotationRequest>,
+) -> Result<Response> {
+    let (from, to) = request.range.bounds()?;
+    // The annotation's query narrows it down like a target, e.g. `service:api`.
+    let query = request
+        .annotation
+        .get("query")
+        .and_then(|query| query.as_str())
+        .unwrap_or_default();
+    let filter = Metric::parse(query)?.filter().with_min_level(Level::Error);
+
+    let (events, truncated) = collect(&state, from, to).await?;
+    let annotations: Vec<GrafanaAnnotation> = events
+        .iter()
+        .filter(|event| filter.matches_collected(event))
+        .map(|event| {
+            let level = event.event.level.to_string();
+            let service = event.nostr_tag("service");
+            GrafanaAnnotation {
+                annotation: request.annotation.clone(),
+                time: event.event.timestamp.timestamp_millis(),
+                title: match service {
+                    Some(service) => format!("{} in {}", level, service),
+                    None => level.clone(),
+                },
+                text: event.event.message.clone().unwrap_or_default(),
+                tags: std::iter::once(level)
+                    .chain(service.map(str::to_string))
+                    .collect(),
+            }
+        })
+        .collect();
+    Ok(truncatable_json(annotations, truncated))
+}
+
+/// `body` as JSON, marked with [`X_TRUNCATED`] when it was computed from a capped scan.
+fn truncatable_json(body: impl Serialize, truncated: bool) -> Response {
+    let mut response = Json(body).into_response();
+    if truncated {
+        response.headers_mut().insert(
+            HeaderName::from_static(X_TRUNCATED),
+            HeaderValue::from_static("true"),
+        );
+    }
+    response
+}
+
+/// Width of the points between `from` and `to`: Grafana's interval, widened so there are
+/// at most `max_points`, and at least a second.
+pub fn bucket_seconds(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    interval_ms: Option<i64>,
+    max_points: usize,
+) -> i64 {
+    let interval = (interval_ms.unwrap_or(60_000).max(1) as u64).div_ceil(1000) as i64;
+    let range = (to - from).num_seconds().max(1) as u64;
+    let widest = range.div_ceil(max_points.max(1) as u64) as i64;
+    interval.max(widest)
+}
+
+/// Events of the range visible to this server, from the warm cache when it covers it,
+/// and whether there were more than [`GRAFANA_MAX_EVENTS`] of them.
+async fn collect(
+    state: &AppState,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<(Vec<CollectedEvent>, bool)> {
+    // One event past the cap tells a complete scan apart from a truncated one.
+    let filter = state.config.restrict_authors(
+        EventFilter::new()
+            .with_since(from)
+            .with_until(to)
+            .with_limit(GRAFANA_MAX_EVENTS + 1),
+    )?;
+    let cached = match state.warm_cache {
+        Some(ref warm_cache) => warm_cache.query(&filter).await,
+        None => None,
+    };
+    let mut events = match cached {
+        Some(events) => events,
+        None => state
+            .collector
+            .collect_events(filter)
+            .await
+            .map_err(ApiError::from)?,
+    };
+
+    let truncated = events.len() > GRAFANA_MAX_EVENTS;
+    events.truncate(GRAFANA_MAX_EVENTS);
+    Ok((events, truncated))
+}
+
+fn series(
+    target: &str,
+    events: &[CollectedEvent],
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    bucket_seconds: i64,
+) -> GrafanaSeries {
+    let datapoints = timeseries(events, None, from, to, bucket_seconds)
+        .into_iter()
+        .flat_map(|series| series.points)
+        .map(|point| (point.count, point.t.timestamp_millis()))
+        .collect();
+    GrafanaSeries {
+        target: if target.trim().is_empty() {
+            ALL_METRIC.to_string()
+        } else {
+            target.to_string()
+        },
+        datapoints,
+    }
+}
+
+fn table(mut events: Vec<CollectedEvent>) -> GrafanaTable {
+    events.sort_by_key(|event| Reverse(event.event.timestamp));
+    events.truncate(TABLE_MAX_ROWS);
+
+    let column = |text, column_type| GrafanaColumn { text, column_type };
+    GrafanaTable {
+        table_type: "table",
+        columns: vec![
+            column("Time", "time"),
+            column("Level", "string"),
+            column("Service", "string"),
+            column("Environment", "string"),
+            column("Message", "string"),
+            column("Author", "string"),
+            column("Event id", "string"),
+        ],
+        rows: events
+            .iter()
+            .map(|event| {
+                vec![
+                    event.event.timestamp.timestamp_millis().into(),
+                    event.event.level.to_string().into(),
+                    event.nostr_tag("service").into(),
+                    event
+                        .nostr_tag("env")
+                        .map(str::to_string)
+                        .or_else(|| event.event.environment.clone())
+                        .into(),
+                    event.event.message.clone().into(),
+                    event.author.to_hex().into(),
+                    event.nostr_event_id.to_hex().into(),
+                ]
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventId, Keys, Tag, TagKind, Timestamp};
+
+    /// Payloads recorded from Grafana 10 with the SimpleJSON datasource.
+    const SEARCH: &str = include_str!("../tests/fixtures/grafana/search.json");
+    const QUERY_TIMESERIE: &str = include_str!("../tests/fixtures/grafana/query_timeserie.json");
+    const QUERY_TABLE: &str = include_str!("../tests/fixtures/grafana/query_table.json");
+    const ANNOTATIONS: &str = include_str!("../tests/fixtures/grafana/annotations.json");
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn collected(time: &str, level: Level, service: Option<&str>) -> CollectedEvent {
+        CollectedEvent {
+            event: sentrystr::Event::new()
+                .with_message(format!("event at {}", time))
+                .with_level(level)
+                .with_timestamp(at(time)),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            created_at: Timestamp::from_secs(at(time).timestamp() as u64),
+            received_at: Utc::now(),
+            nostr_tags: service
+                .map(|service| Tag::custom(TagKind::custom("service"), [service]))
+                .into_iter()
+                .collect(),
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn parses_recorded_search_request() {
+        let request: GrafanaSearchRequest = serde_json::from_str(SEARCH).unwrap();
+        assert_eq!(request.target, "service");
+    }
+
+    #[test]
+    fn parses_recorded_timeserie_query() {
+        let request: GrafanaQueryRequest = serde_json::from_str(QUERY_TIMESERIE).unwrap();
+        assert_eq!(request.range.from, at("2024-01-01T00:00:00Z"));
+        assert_eq!(request.range.to, at("2024-01-01T00:03:00Z"));
+        assert_eq!(request.interval_ms, Some(60_000));
+        assert_eq!(request.targets.len(), 3);
+        assert_eq!(request.targets[0].target_type, GrafanaTargetType::Timeserie);
+        assert!(request.targets[1].hide);
+        // A target added but not picked yet has no metric, which means `all`.
+        assert_eq!(
+            Metric::parse(&request.targets[2].target).unwrap(),
+            Metric::All
+        );
+    }
+
+    #[test]
+    fn parses_recorded_table_query() {
+        let request: GrafanaQueryRequest = serde_json::from_str(QUERY_TABLE).unwrap();
+        assert_eq!(request.targets[0].target_type, GrafanaTargetType::Table);
+        assert_eq!(
+            Metric::parse(&request.targets[0].target).unwrap(),
+            Metric::Service("api".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_recorded_annotation_request() {
+        let request: GrafanaAnnotationRequest = serde_json::from_str(ANNOTATIONS).unwrap();
+        assert!(request.range.bounds().is_ok());
+        assert_eq!(request.annotation["query"], "service:api");
+    }
+
+    #[test]
+    fn parses_metrics() {
+        assert_eq!(Metric::parse("all").unwrap(), Metric::All);
+        assert_eq!(
+            Metric::parse("level:error").unwrap(),
+            Metric::Level(Level::Error)
+        );
+        assert!(Metric::parse("level:loud").is_err());
+        assert!(Metric::parse("service:").is_err());
+        assert!(Metric::parse("cpu").is_err());
+    }
+
+    #[test]
+    fn rejects_empty_range() {
+        let range = GrafanaRange {
+            from: at("2024-01-01T01:00:00Z"),
+            to: at("2024-01-01T01:00:00Z"),
+        };
+        assert!(range.bounds().is_err());
+    }
+
+    #[test]
+    fn widens_interval_to_max_points() {
+        let from = at("2024-01-01T00:00:00Z");
+        let to = at("2024-01-02T00:00:00Z");
+        assert_eq!(bucket_seconds(from, to, Some(60_000), 10_000), 60);
+        assert_eq!(bucket_seconds(from, to, Some(60_000), 100), 864);
+        assert_eq!(bucket_seconds(from, to, Some(10), 10_000), 9);
+    }
+
+    #[test]
+    fn answers_timeserie_with_zero_filled_points() {
+        let events = [
+            collected("2024-01-01T00:00:10Z", Level::Error, None),
+            collected("2024-01-01T00:00:50Z", Level::Info, None),
+            collected("2024-01-01T00:02:30Z", Level::Error, None),
+        ];
+        let from = at("2024-01-01T00:00:00Z");
+        let to = at("2024-01-01T00:03:00Z");
+
+        let series = series("", &events, from, to, 60);
+        assert_eq!(series.target, ALL_METRIC);
+        let counts: Vec<usize> = series.datapoints.iter().map(|(count, _)| *count).collect();
+        assert_eq!(counts, [2, 0, 1, 0]);
+        assert_eq!(series.datapoints[1].1, from.timestamp_millis() + 60_000);
+
+        let json = serde_json::to_value(GrafanaQueryResult::Series(series)).unwrap();
+        assert_eq!(
+            json["datapoints"][0],
+            serde_json::json!([2, 1704067200000i64])
+        );
+    }
+
+    #[test]
+    fn answers_table_newest_first() {
+        let events = vec![
+            collected("2024-01-01T00:00:10Z", Level::Error, Some("api")),
+            collected("2024-01-01T00:05:00Z", Level::Warning, Some("api")),
+        ];
+
+        let json = serde_json::to_value(GrafanaQueryResult::Table(table(events))).unwrap();
+        assert_eq!(json["type"], "table");
+        assert_eq!(json["columns"][0]["text"], "Time");
+        assert_eq!(json["columns"][0]["type"], "time");
+        assert_eq!(json["rows"].as_array().unwrap().len(), 2);
+        assert_eq!(json["rows"][0][1], "warning");
+        assert_eq!(json["rows"][0][2], "api");
+    }
+
+    #[test]
+    fn metric_filters_select_level_and_service() {
+        let error_in_api = collected("2024-01-01T00:00:10Z", Level::Error, Some("api"));
+        let info_in_web = collected("2024-01-01T00:00:10Z", Level::Info, Some("web"));
+
+        let errors = Metric::Level(Level::Error).filter();
+        assert!(errors.matches_collected(&error_in_api));
+        assert!(!errors.matches_collected(&info_in_web));
+
+        let api = Metric::Service("api".to_string()).filter();
+        assert!(api.matches_collected(&error_in_api));
+        assert!(!api.matches_collected(&info_in_web));
+    }
+
+    #[test]
+    fn marks_truncated_responses() {
+        let response = truncatable_json(Vec::<String>::new(), true);
+        assert_eq!(response.headers()[X_TRUNCATED], "true");
+
+        let response = truncatable_json(Vec::<String>::new(), false);
+        assert!(response.headers().get(X_TRUNCATED).is_none());
+    }
+}
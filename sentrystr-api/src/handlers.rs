@@ -1,113 +1,1738 @@
-use axum::{Json, extract::Query};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::Query;
 use chrono::Utc;
-use nostr::PublicKey;
-use sentrystr::Level;
-use sentrystr_collector::{EventCollector, EventFilter};
+use futures_util::{StreamExt, stream};
+use nostr::{PublicKey, ToBech32};
+use sentrystr_collector::{
+    CollectedEvent, EventCollector, EventFilter, RelayStatusInfo, SortOrder,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
+use std::future::Future;
+use tokio::sync::{mpsc, watch};
+use uuid::Uuid;
 
-use crate::models::{EventQuery, EventResponse, EventsResponse, HealthResponse};
-use crate::{ApiError, Result};
+use crate::aggregate::{GroupBy, bucket_count, group_counts, summarize_authors, timeseries};
+use crate::auth::{bearer_token, require_auth};
+use crate::cursor::{decode_cursor, encode_cursor};
+use crate::fields::FieldSelection;
+use crate::models::{
+    AuthorsQuery, CountResponse, CreateFilterRequest, CreateWebhookRequest, EventQuery,
+    EventResponse, EventsResponse, FilterEventsQuery, GroupsResponse, HealthQuery, HealthResponse,
+    NdjsonTrailer, RelayHealth, RelayPublishResult, SavedFilter, TestAlertRequest,
+    TestAlertResponse, TimeseriesQuery, TimeseriesResponse, WebhookInfo, parse_time_param,
+};
+use crate::{ApiError, AppState, Result};
+
+const X_CACHE: &str = "x-cache";
+/// `cache` when `/events` was answered from the warm cache, `relay` otherwise.
+const X_SERVED_FROM: &str = "x-served-from";
+const NDJSON: &str = "application/x-ndjson";
+/// Events requested from the relays per page when streaming NDJSON.
+const NDJSON_PAGE_SIZE: usize = 500;
+/// Upper bound on events folded into a `group_by` response.
+const GROUP_BY_MAX_EVENTS: usize = 10_000;
+/// Most relays a single `relays` override may name.
+const MAX_OVERRIDE_RELAYS: usize = 3;
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "The process is alive; relay status is informational", body = HealthResponse)
+    )
+)]
+pub async fn health(
+    State(state): State<AppState>,
+    Query(params): Query<HealthQuery>,
+) -> Json<HealthResponse> {
+    let relays = relay_report(&state, params.verbose.unwrap_or(false)).await;
+    let degraded = relays.iter().any(|relay| !relay.connected);
 
-pub async fn health() -> Json<HealthResponse> {
     Json(HealthResponse {
         status: "ok".to_string(),
         timestamp: Utc::now(),
+        relays,
+        degraded,
     })
 }
 
-pub async fn get_events(Query(params): Query<EventQuery>) -> Result<Json<EventsResponse>> {
-    let relays = vec!["wss://relay.damus.io".to_string()];
+#[utoipa::path(
+    get,
+    path = "/ready",
+    params(HealthQuery),
+    responses(
+        (status = 200, description = "Setup completed and at least one relay is connected", body = HealthResponse),
+        (status = 503, description = "Still `starting`, or `unavailable` because no relay is connected", body = HealthResponse)
+    )
+)]
+pub async fn ready(
+    State(state): State<AppState>,
+    Query(params): Query<HealthQuery>,
+) -> (StatusCode, Json<HealthResponse>) {
+    let relays = relay_report(&state, params.verbose.unwrap_or(false)).await;
+
+    let connected = relays.iter().filter(|relay| relay.connected).count();
+    let degraded = connected < relays.len();
+
+    let (status_code, status) = if connected > 0 && state.setup_complete() {
+        (StatusCode::OK, if degraded { "degraded" } else { "ready" })
+    } else if state.in_readiness_grace() {
+        (StatusCode::SERVICE_UNAVAILABLE, "starting")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
 
-    let collector = EventCollector::new(relays)
+    (
+        status_code,
+        Json(HealthResponse {
+            status: status.to_string(),
+            timestamp: Utc::now(),
+            relays,
+            degraded,
+        }),
+    )
+}
+
+/// Cached relay status for the health endpoints; never touches the network.
+async fn relay_report(state: &AppState, verbose: bool) -> Vec<RelayHealth> {
+    state
+        .relay_status()
         .await
-        .map_err(|e| ApiError::Collection(e.to_string()))?;
+        .into_iter()
+        .map(|relay| relay_health(relay, verbose))
+        .collect()
+}
 
-    let mut filter = EventFilter::new();
+#[utoipa::path(
+    get,
+    path = "/events",
+    params(EventQuery),
+    responses(
+        (
+            status = 200,
+            description = "Matching events, or a `GroupsResponse` when `group_by` is set; as NDJSON, one `EventResponse` per line followed by an `NdjsonTrailer`",
+            content(
+                ("application/json" = EventsResponse),
+                ("application/x-ndjson" = EventResponse)
+            )
+        ),
+        (status = 400, description = "Invalid query parameter", body = ErrorResponse),
+        (status = 403, description = "`relays` was given but relay overrides are disabled", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn get_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EventQuery>,
+) -> Result<Response> {
+    let override_relays = params
+        .relays
+        .as_deref()
+        .map(|relays| parse_override_relays(&state, relays))
+        .transpose()?;
+    let selection = FieldSelection::parse(params.fields.as_deref(), params.view.as_deref())?;
 
-    if let Some(limit) = params.limit {
-        filter = filter.with_limit(limit);
-    } else {
-        filter = filter.with_limit(100);
+    if !selection.is_full() && params.group_by.is_some() {
+        return Err(ApiError::BadRequest(
+            "`fields` and `view` do not apply to `group_by` responses".to_string(),
+        ));
     }
 
-    if let Some(author_str) = params.author {
-        let author = PublicKey::parse(&author_str)
-            .map_err(|e| ApiError::BadRequest(format!("Invalid public key: {}", e)))?;
-        filter = filter.with_author(author);
+    if override_relays.is_some() && (wants_ndjson(&headers, &params) || params.group_by.is_some()) {
+        return Err(ApiError::BadRequest(
+            "`relays` overrides only apply to paged JSON responses".to_string(),
+        ));
     }
 
-    if let Some(level_str) = params.level {
-        let level = match level_str.to_lowercase().as_str() {
-            "debug" => Level::Debug,
-            "info" => Level::Info,
-            "warning" => Level::Warning,
-            "error" => Level::Error,
-            "fatal" => Level::Fatal,
-            _ => return Err(ApiError::BadRequest("Invalid level".to_string())),
-        };
-        filter = filter.with_level(level);
+    if wants_ndjson(&headers, &params) {
+        if params.group_by.is_some() {
+            return Err(ApiError::BadRequest(
+                "`group_by` responses cannot be streamed".to_string(),
+            ));
+        }
+
+        let limit = params.limit;
+        let mut filter = params.into_filter(&state.config)?;
+        if filter.sort == SortOrder::Asc {
+            return Err(ApiError::BadRequest(
+                "Streaming responses are ordered newest first; sort=asc is not supported"
+                    .to_string(),
+            ));
+        }
+        if let Some(limit) = limit {
+            filter = filter.with_limit(limit);
+        }
+
+        let events = state.collector.stream_events(filter, NDJSON_PAGE_SIZE);
+        return Ok(ndjson_response(&state, events, selection));
     }
 
-    if let Some(service) = params.service {
-        filter = filter.with_service_filter(service);
+    let cache_key = format!("events:{}", params.cache_key());
+
+    if params.group_by.is_none()
+        && override_relays.is_none()
+        && let Some(ref warm_cache) = state.warm_cache
+    {
+        let limit = params.limit.unwrap_or(100);
+        let filter = params.clone().into_filter(&state.config)?.with_limit(limit);
+
+        if let Some(events) = warm_cache.query(&filter).await {
+            let page = page_from_events(events, filter.limit, filter.sort);
+            let body = selection.to_json(&page, Some("events"))?;
+
+            // Warm answers are always current, so they skip the response cache.
+            let mut response = conditional_json(&headers, &cache_key, Bytes::from(body), "BYPASS");
+            response.headers_mut().insert(
+                header::HeaderName::from_static(X_SERVED_FROM),
+                HeaderValue::from_static("cache"),
+            );
+            return Ok(response);
+        }
     }
 
-    if let Some(environment) = params.environment {
-        filter = filter.with_environment_filter(environment);
+    let mut response = cached_json(&state, &headers, cache_key, || async {
+        match params.group_by.clone() {
+            Some(group_by) => group_events(&state, &group_by, params).await,
+            None => page_events(&state, params, override_relays, &selection).await,
+        }
+    })
+    .await?;
+
+    response.headers_mut().insert(
+        header::HeaderName::from_static(X_SERVED_FROM),
+        HeaderValue::from_static("relay"),
+    );
+    Ok(response)
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/count",
+    params(EventQuery),
+    responses(
+        (status = 200, description = "Number of matching events", body = CountResponse),
+        (status = 400, description = "Invalid query parameter", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn count_events(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EventQuery>,
+) -> Result<Response> {
+    if params.limit.is_some()
+        || params.cursor.is_some()
+        || params.group_by.is_some()
+        || params.relays.is_some()
+    {
+        return Err(ApiError::BadRequest(
+            "`/events/count` does not accept `limit`, `cursor`, `group_by` or `relays`".to_string(),
+        ));
     }
 
-    if let Some(component) = params.component {
-        filter = filter.with_component_filter(component);
+    let cache_key = format!("count:{}", params.cache_key());
+
+    cached_json(&state, &headers, cache_key, || async {
+        let filter = params.into_filter(&state.config)?;
+        let response = count_matching(&state, filter).await?;
+        let body = serde_json::to_vec(&response).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        Ok(Bytes::from(body))
+    })
+    .await
+}
+
+/// Counts the events matching `filter`: exactly from the warm cache when it holds the
+/// whole window, otherwise by scanning the relays for at most
+/// [`ApiConfig::count_scan_limit`] events.
+///
+/// [`ApiConfig::count_scan_limit`]: crate::ApiConfig::count_scan_limit
+async fn count_matching(state: &AppState, filter: EventFilter) -> Result<CountResponse> {
+    if let Some(ref warm_cache) = state.warm_cache
+        && let Some(count) = warm_cache.count(&filter).await
+    {
+        return Ok(CountResponse {
+            count,
+            exact: true,
+            scan_limit: None,
+        });
     }
 
-    if let Some(severity) = params.severity {
-        filter = filter.with_severity_filter(severity);
+    // One event past the cap tells a complete scan apart from a truncated one.
+    let scan_limit = state.config.count_scan_limit;
+    let mut events = state
+        .collector
+        .stream_events(filter.with_limit(scan_limit + 1), NDJSON_PAGE_SIZE);
+
+    let mut count = 0;
+    while let Some(event) = events.recv().await {
+        event.map_err(ApiError::from)?;
+        count += 1;
     }
 
-    if let Some(since) = params.since {
-        filter = filter.with_since(since);
+    let exact = count <= scan_limit;
+    Ok(CountResponse {
+        count: count.min(scan_limit),
+        exact,
+        scan_limit: (!exact).then_some(scan_limit),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/timeseries",
+    params(TimeseriesQuery, EventQuery),
+    responses(
+        (status = 200, description = "Zero-filled event counts per time bucket", body = TimeseriesResponse),
+        (status = 400, description = "Invalid query parameter, or more buckets than allowed", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn get_timeseries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EventQuery>,
+    Query(series): Query<TimeseriesQuery>,
+) -> Result<Response> {
+    if params.limit.is_some() || params.cursor.is_some() || params.relays.is_some() {
+        return Err(ApiError::BadRequest(
+            "`/events/timeseries` does not accept `limit`, `cursor` or `relays`".to_string(),
+        ));
     }
 
-    if let Some(until) = params.until {
-        filter = filter.with_until(until);
+    let bucket_seconds = sentrystr_collector::parse_duration(&series.bucket)
+        .map(|bucket| bucket.num_seconds())
+        .filter(|seconds| *seconds > 0)
+        .ok_or_else(|| ApiError::InvalidParameter {
+            param: "bucket",
+            message: format!(
+                "Invalid bucket '{}'. Expected a duration such as 1m, 5m or 1h",
+                series.bucket
+            ),
+        })?;
+
+    let group_by = params
+        .group_by
+        .as_deref()
+        .map(|group_by| match group_by.parse::<GroupBy>() {
+            Ok(GroupBy::Environment | GroupBy::Fingerprint) | Err(_) => {
+                Err(ApiError::InvalidParameter {
+                    param: "group_by",
+                    message: format!(
+                        "Invalid group_by '{}'. Expected one of: level, service, author",
+                        group_by
+                    ),
+                })
+            }
+            Ok(group_by) => Ok(group_by),
+        })
+        .transpose()?;
+
+    let cache_key = format!("timeseries:{}:{}", bucket_seconds, params.cache_key());
+
+    cached_json(&state, &headers, cache_key, || async {
+        let mut filter = params.into_filter(&state.config)?;
+        let since = filter.since.ok_or_else(|| ApiError::InvalidParameter {
+            param: "since",
+            message: "A time series needs a `since` to bound its buckets".to_string(),
+        })?;
+        let until = *filter.until.get_or_insert_with(Utc::now);
+
+        let buckets = bucket_count(since, until, bucket_seconds);
+        let max_points = state.config.timeseries_max_points;
+        if buckets < 1 || buckets as usize > max_points {
+            return Err(ApiError::BadRequest(format!(
+                "`since`/`until` with bucket {} yields {} buckets; at most {} are allowed",
+                series.bucket, buckets, max_points
+            )));
+        }
+
+        let (events, exact) = collect_for_counts(&state, filter, GROUP_BY_MAX_EVENTS).await?;
+
+        let body = serde_json::to_vec(&TimeseriesResponse {
+            bucket_seconds,
+            series: timeseries(&events, group_by, since, until, bucket_seconds),
+            exact,
+            scan_limit: (!exact).then_some(GROUP_BY_MAX_EVENTS),
+        })
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+        Ok(Bytes::from(body))
+    })
+    .await
+}
+
+#[utoipa::path(
+    get,
+    path = "/authors",
+    params(AuthorsQuery),
+    responses(
+        (status = 200, description = "Authors that published events in the window, most recently seen first", body = Vec<AuthorSummary>),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn get_authors(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AuthorsQuery>,
+) -> Result<Response> {
+    let cache_key = format!(
+        "authors:{}",
+        serde_json::to_string(&params).unwrap_or_default()
+    );
+
+    cached_json(&state, &headers, cache_key, || async {
+        let mut filter = state
+            .config
+            .restrict_authors(EventFilter::new().with_limit(GROUP_BY_MAX_EVENTS))?;
+        if let Some(ref since) = params.since {
+            filter = filter.with_since(parse_time_param("since", since)?);
+        }
+        if let Some(ref until) = params.until {
+            filter = filter.with_until(parse_time_param("until", until)?);
+        }
+
+        let events = state
+            .collector
+            .collect_events(filter)
+            .await
+            .map_err(ApiError::from)?;
+
+        let mut authors = summarize_authors(&events);
+        authors.truncate(params.limit.unwrap_or(100));
+
+        serde_json::to_vec(&authors)
+            .map(Bytes::from)
+            .map_err(|e| ApiError::Internal(e.to_string()))
+    })
+    .await
+}
+
+/// Serves `cache_key` from the response cache, or computes, caches and returns it.
+/// Honors `Cache-Control: no-cache` and reports the outcome in `X-Cache`.
+async fn cached_json<F, Fut>(
+    state: &AppState,
+    headers: &HeaderMap,
+    cache_key: String,
+    compute: F,
+) -> Result<Response>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Bytes>>,
+{
+    let (body, cache_status) = match state.cache.get(&cache_key) {
+        Some(body) if !bypasses_cache(headers) => {
+            state.metrics.cache_hits.inc();
+            (body, "HIT")
+        }
+        _ => {
+            state.metrics.cache_misses.inc();
+            let body = compute().await?;
+            state.cache.insert(cache_key.clone(), body.clone());
+            (body, "MISS")
+        }
+    };
+
+    Ok(conditional_json(headers, &cache_key, body, cache_status))
+}
+
+/// JSON response carrying an [`entity_tag`], or an empty 304 when the client already
+/// holds that representation.
+fn conditional_json(
+    headers: &HeaderMap,
+    cache_key: &str,
+    body: Bytes,
+    cache_status: &'static str,
+) -> Response {
+    let etag = entity_tag(cache_key, &body);
+    if if_none_match(headers, &etag) {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, etag),
+                (
+                    header::HeaderName::from_static(X_CACHE),
+                    HeaderValue::from_static(cache_status),
+                ),
+            ],
+        )
+            .into_response();
     }
 
+    let mut response = json_response(body, cache_status);
+    response.headers_mut().insert(header::ETAG, etag);
+    response
+}
+
+/// The parts of an events page that identify its data. `received_at` differs on every
+/// relay fetch and is left out so that refetching unchanged events keeps the tag.
+#[derive(Deserialize)]
+struct PageIdentity {
+    events: Vec<EventIdentity>,
+    total: usize,
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EventIdentity {
+    nostr_event_id: String,
+}
+
+/// Strong ETag over the normalized query and the response data: for event pages the
+/// event ids in order, the total and any cursor, for other responses the whole body.
+/// The tag changes whenever the data, the page or the sort order does.
+fn entity_tag(cache_key: &str, body: &[u8]) -> HeaderValue {
+    let mut hasher = Sha256::new();
+    hasher.update(cache_key.as_bytes());
+    hasher.update([0]);
+
+    match serde_json::from_slice::<PageIdentity>(body) {
+        Ok(page) => {
+            for event in &page.events {
+                hasher.update(event.nostr_event_id.as_bytes());
+                hasher.update([0]);
+            }
+            hasher.update(page.total.to_be_bytes());
+            hasher.update(page.next_cursor.unwrap_or_default().as_bytes());
+        }
+        Err(_) => hasher.update(body),
+    }
+
+    let digest = hex::encode(hasher.finalize());
+    HeaderValue::from_str(&format!("\"{}\"", &digest[..32])).expect("hex is a valid header value")
+}
+
+/// Whether `If-None-Match` lists `etag` (or `*`). Weak validators compare equal too,
+/// as RFC 9110 requires for `If-None-Match`.
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+async fn page_events(
+    state: &AppState,
+    params: EventQuery,
+    override_relays: Option<Vec<String>>,
+    selection: &FieldSelection,
+) -> Result<Bytes> {
+    let limit = params.limit.unwrap_or(100);
+    let filter = params.into_filter(&state.config)?.with_limit(limit);
+
+    let page = match override_relays {
+        Some(relays) => collect_override_page(relays, filter).await?,
+        None => collect_page(&state.collector, filter).await?,
+    };
+    let body = selection.to_json(&page, Some("events"))?;
+
+    Ok(Bytes::from(body))
+}
+
+/// Runs `filter` and wraps the results in a page, with a cursor when the page is full.
+async fn collect_page(collector: &EventCollector, filter: EventFilter) -> Result<EventsResponse> {
+    let limit = filter.limit;
+    let sort = filter.sort;
+
     let events = collector
         .collect_events(filter)
         .await
-        .map_err(|e| ApiError::Collection(e.to_string()))?;
+        .map_err(ApiError::from)?;
+
+    Ok(page_from_events(events, limit, sort))
+}
+
+fn page_from_events(
+    events: Vec<CollectedEvent>,
+    limit: Option<usize>,
+    sort: SortOrder,
+) -> EventsResponse {
+    let next_cursor = match (events.last(), limit) {
+        (Some(last), Some(limit)) if events.len() >= limit => {
+            Some(encode_cursor(sort, &last.position()))
+        }
+        _ => None,
+    };
+
+    let events: Vec<EventResponse> = events.into_iter().map(to_event_response).collect();
+
+    EventsResponse {
+        total: events.len(),
+        events,
+        next_cursor,
+        relays: None,
+    }
+}
+
+/// Runs `filter` through a short-lived collector connected only to `relays`, reporting
+/// the status of each relay alongside the page.
+async fn collect_override_page(relays: Vec<String>, filter: EventFilter) -> Result<EventsResponse> {
+    let collector = EventCollector::new(relays).await.map_err(ApiError::from)?;
+
+    let page = collect_page(&collector, filter).await;
+    let status = collector.relay_status().await;
+
+    if let Err(e) = collector.disconnect().await {
+        eprintln!("Failed to disconnect override collector: {}", e);
+    }
+
+    let mut page = page?;
+    page.relays = Some(
+        status
+            .into_iter()
+            .map(|relay| relay_health(relay, true))
+            .collect(),
+    );
+    Ok(page)
+}
+
+/// Validates the `relays` override: enabled by `--allow-relay-override`, at most
+/// [`MAX_OVERRIDE_RELAYS`] distinct `ws://` or `wss://` URLs.
+fn parse_override_relays(state: &AppState, relays: &str) -> Result<Vec<String>> {
+    if !state.config.allow_relay_override {
+        return Err(ApiError::Forbidden(
+            "Relay overrides are disabled; start the server with --allow-relay-override"
+                .to_string(),
+        ));
+    }
+
+    let mut urls: Vec<String> = Vec::new();
+    for relay in relays.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let url = nostr::RelayUrl::parse(relay).map_err(|e| ApiError::InvalidParameter {
+            param: "relays",
+            message: format!("Invalid relay URL '{}': {}", relay, e),
+        })?;
+
+        let url = url.to_string();
+        if !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+
+    if urls.is_empty() {
+        return Err(ApiError::InvalidParameter {
+            param: "relays",
+            message: "Expected at least one relay URL".to_string(),
+        });
+    }
+
+    if urls.len() > MAX_OVERRIDE_RELAYS {
+        return Err(ApiError::InvalidParameter {
+            param: "relays",
+            message: format!(
+                "At most {} override relays are allowed",
+                MAX_OVERRIDE_RELAYS
+            ),
+        });
+    }
+
+    Ok(urls)
+}
+
+fn relay_health(relay: RelayStatusInfo, verbose: bool) -> RelayHealth {
+    RelayHealth {
+        error: if verbose && !relay.connected {
+            Some(format!("Relay is {}", relay.status))
+        } else {
+            None
+        },
+        url: relay.url,
+        connected: relay.connected,
+        last_event_at: relay.last_event_at,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters",
+    responses((status = 200, description = "Saved filters", body = Vec<SavedFilter>))
+)]
+pub async fn list_filters(State(state): State<AppState>) -> Json<Vec<SavedFilter>> {
+    Json(state.filters.list().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/filters",
+    request_body = CreateFilterRequest,
+    responses(
+        (status = 201, description = "Filter saved", body = SavedFilter),
+        (status = 400, description = "Invalid filter name", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 409, description = "A filter with this name exists", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateFilterRequest>,
+) -> Result<(StatusCode, Json<SavedFilter>)> {
+    require_auth(&state, &headers)?;
+
+    let saved = SavedFilter {
+        name: request.name,
+        filter: request.filter,
+        created_at: Utc::now(),
+    };
+    state.filters.insert(saved.clone()).await?;
+
+    Ok((StatusCode::CREATED, Json(saved)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/filters/{name}",
+    params(("name" = String, Path, description = "Saved filter name")),
+    responses(
+        (status = 204, description = "Filter deleted"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No filter with this name", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_filter(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode> {
+    require_auth(&state, &headers)?;
+    state.filters.remove(&name).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/filters/{name}/events",
+    params(
+        ("name" = String, Path, description = "Saved filter name"),
+        FilterEventsQuery
+    ),
+    responses(
+        (status = 200, description = "Events matching the saved filter", body = EventsResponse),
+        (status = 404, description = "No filter with this name", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse)
+    )
+)]
+pub async fn get_filter_events(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(params): Query<FilterEventsQuery>,
+) -> Result<Json<EventsResponse>> {
+    let mut filter = state
+        .config
+        .restrict_authors(state.filters.get(&name).await?.filter)?;
+
+    let limit = params.limit.or(filter.limit).unwrap_or(100);
+    filter = filter.with_limit(limit);
+
+    if let Some(ref cursor) = params.cursor {
+        let after = decode_cursor(cursor, filter.sort)?;
+        filter = filter.with_after(after);
+    }
+
+    Ok(Json(collect_page(&state.collector, filter).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    responses(
+        (status = 200, description = "Registered webhooks", body = Vec<WebhookInfo>),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<WebhookInfo>>> {
+    require_auth(&state, &headers)?;
+    Ok(Json(state.webhooks.list().await))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Webhook registered", body = WebhookInfo),
+        (status = 400, description = "Invalid URL, secret or min_level", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 502, description = "Relay subscription failed", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<WebhookInfo>)> {
+    require_auth(&state, &headers)?;
+
+    let mut filter = state.config.restrict_authors(request.filter)?;
+    if let Some(ref min_level) = request.min_level {
+        let level =
+            min_level
+                .parse::<sentrystr::Level>()
+                .map_err(|e| ApiError::InvalidParameter {
+                    param: "min_level",
+                    message: e.to_string(),
+                })?;
+        filter = filter.with_min_level(level);
+    }
+
+    let webhook = state
+        .webhooks
+        .register(request.url, request.secret, filter, request.min_level)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
 
-    collector
-        .disconnect()
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    params(("id" = Uuid, Path, description = "Webhook id")),
+    responses(
+        (status = 200, description = "Webhook and its delivery state", body = WebhookInfo),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No webhook with this id", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn get_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<Json<WebhookInfo>> {
+    require_auth(&state, &headers)?;
+    Ok(Json(state.webhooks.get(id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    params(("id" = Uuid, Path, description = "Webhook id")),
+    responses(
+        (status = 204, description = "Webhook removed"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No webhook with this id", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode> {
+    require_auth(&state, &headers)?;
+    state.webhooks.remove(id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/alerts/test",
+    request_body = TestAlertRequest,
+    responses(
+        (status = 200, description = "Test alert published; per-relay results", body = TestAlertResponse),
+        (status = 400, description = "Invalid recipient", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 429, description = "A test alert was sent too recently", body = ErrorResponse),
+        (status = 502, description = "No relay accepted the alert", body = ErrorResponse)
+    ),
+    security(("bearer" = []))
+)]
+pub async fn test_alert(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<TestAlertRequest>,
+) -> Result<Json<TestAlertResponse>> {
+    require_auth(&state, &headers)?;
+
+    let recipient = PublicKey::parse(&request.recipient)
+        .map_err(|e| ApiError::InvalidPublicKey(format!("Invalid recipient: {}", e)))?;
+
+    state
+        .alert_test_limiter
+        .check(bearer_token(&headers).unwrap_or_default())?;
+
+    let sender = state
+        .collector
+        .direct_message_sender(recipient, request.encryption())
+        .map_err(ApiError::from)?;
+
+    let message = request.message.unwrap_or_else(|| {
+        format!(
+            "SentryStr test alert\n\nSent at {} to verify alert delivery.",
+            Utc::now().to_rfc3339()
+        )
+    });
+
+    let delivery = sender
+        .send_custom_message_with_output(&message)
         .await
-        .map_err(|e| ApiError::Internal(e.to_string()))?;
+        .map_err(|e| ApiError::Collection(format!("Failed to send test alert: {}", e)))?;
+
+    let output = delivery.output;
+    let relays = relay_results(
+        output.success.iter().map(ToString::to_string).collect(),
+        output
+            .failed
+            .iter()
+            .map(|(url, error)| (url.to_string(), error.clone()))
+            .collect(),
+    )?;
+
+    let sender_pubkey = sender.sender_pubkey();
+    Ok(Json(TestAlertResponse {
+        sender_npub: sender_pubkey
+            .to_bech32()
+            .unwrap_or_else(|_| sender_pubkey.to_hex()),
+        event_id: output.val.to_string(),
+        relays,
+        inbox_relays: delivery
+            .inbox_relays
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        discovery_error: delivery.discovery_error,
+    }))
+}
+
+/// Per-relay results of a test alert, sorted by URL. Fails with a 502 when no relay
+/// accepted it.
+fn relay_results(
+    accepted: Vec<String>,
+    rejected: Vec<(String, String)>,
+) -> Result<Vec<RelayPublishResult>> {
+    if accepted.is_empty() {
+        let reasons: Vec<String> = rejected
+            .iter()
+            .map(|(url, error)| format!("{}: {}", url, error))
+            .collect();
+        return Err(ApiError::Collection(format!(
+            "No relay accepted the test alert ({})",
+            reasons.join(", ")
+        )));
+    }
 
-    let response_events: Vec<EventResponse> = events
+    let mut relays: Vec<RelayPublishResult> = accepted
         .into_iter()
-        .map(|event| EventResponse {
-            nostr_event_id: event.nostr_event_id.to_string(),
-            author: event.author.to_string(),
-            received_at: event.received_at,
-            event: crate::models::EventData {
-                event_id: event.event.event_id,
-                timestamp: event.event.timestamp,
-                platform: event.event.platform,
-                level: event.event.level,
-                logger: event.event.logger,
-                transaction: event.event.transaction,
-                server_name: event.event.server_name,
-                release: event.event.release,
-                environment: event.event.environment,
-                message: event.event.message,
-                tags: event.event.tags,
-                extra: event.event.extra,
-            },
+        .map(|url| RelayPublishResult {
+            url,
+            accepted: true,
+            error: None,
         })
+        .chain(rejected.into_iter().map(|(url, error)| RelayPublishResult {
+            url,
+            accepted: false,
+            error: Some(error),
+        }))
         .collect();
+    relays.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(relays)
+}
 
-    let total = response_events.len();
+/// Collects the newest `max_events` events matching `filter`, from the warm cache when
+/// it covers the window. The flag is `false` when more events matched.
+async fn collect_for_counts(
+    state: &AppState,
+    filter: EventFilter,
+    max_events: usize,
+) -> Result<(Vec<CollectedEvent>, bool)> {
+    // One event past the cap tells a complete scan apart from a truncated one.
+    let filter = filter.with_limit(max_events + 1);
+    let cached = match state.warm_cache {
+        Some(ref warm_cache) => warm_cache.query(&filter).await,
+        None => None,
+    };
+    let mut events = match cached {
+        Some(events) => events,
+        None => state
+            .collector
+            .collect_events(filter)
+            .await
+            .map_err(ApiError::from)?,
+    };
 
-    Ok(Json(EventsResponse {
-        events: response_events,
-        total,
-    }))
+    let exact = events.len() <= max_events;
+    events.truncate(max_events);
+    Ok((events, exact))
+}
+
+/// Folds every matching event into counts per `group_by` dimension instead of returning
+/// a page of events.
+async fn group_events(state: &AppState, group_by: &str, params: EventQuery) -> Result<Bytes> {
+    let group_by: GroupBy = group_by
+        .parse()
+        .map_err(|message| ApiError::InvalidParameter {
+            param: "group_by",
+            message,
+        })?;
+
+    if params.limit.is_some() || params.cursor.is_some() {
+        return Err(ApiError::BadRequest(
+            "`group_by` counts every matching event and cannot be combined with `limit` or `cursor`"
+                .to_string(),
+        ));
+    }
+
+    let filter = params.into_filter(&state.config)?;
+    let (events, exact) = collect_for_counts(state, filter, GROUP_BY_MAX_EVENTS).await?;
+
+    let body = serde_json::to_vec(&GroupsResponse {
+        groups: group_counts(&events, group_by),
+        total: events.len(),
+        exact,
+        scan_limit: (!exact).then_some(GROUP_BY_MAX_EVENTS),
+    })
+    .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    Ok(Bytes::from(body))
+}
+
+#[utoipa::path(
+    get,
+    path = "/events/{author}",
+    params(
+        ("author" = String, Path, description = "Author public key (hex or npub)"),
+        EventQuery
+    ),
+    responses(
+        (status = 200, description = "Events published by the author", body = EventsResponse),
+        (status = 400, description = "Invalid query parameter", body = ErrorResponse),
+        (status = 502, description = "Relay collection failed", body = ErrorResponse),
+        (status = 504, description = "Relays did not respond in time", body = ErrorResponse)
+    )
+)]
+pub async fn get_author_events(
+    state: State<AppState>,
+    Path(author): Path<String>,
+    headers: HeaderMap,
+    Query(mut params): Query<EventQuery>,
+) -> Result<Response> {
+    params.author = Some(author);
+    get_events(state, headers, Query(params)).await
+}
+
+/// Whether the client asked for NDJSON, through `format=ndjson` or an `Accept` header;
+/// `format` takes precedence.
+fn wants_ndjson(headers: &HeaderMap, params: &EventQuery) -> bool {
+    if let Some(ref format) = params.format {
+        return format.eq_ignore_ascii_case("ndjson");
+    }
+
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media| {
+            media
+                .split(';')
+                .next()
+                .is_some_and(|media| media.trim().eq_ignore_ascii_case(NDJSON))
+        })
+}
+
+/// Streams one `EventResponse` per line as the collector pages through the relays.
+/// Headers are already sent when events start flowing, so a failure is reported as a
+/// final `{"error": ...}` line; otherwise the stream ends with an [`NdjsonTrailer`].
+fn ndjson_response(
+    state: &AppState,
+    events: mpsc::Receiver<sentrystr_collector::Result<CollectedEvent>>,
+    selection: FieldSelection,
+) -> Response {
+    let shutdown = state.shutdown_signal();
+
+    let lines = stream::unfold(
+        Some((events, shutdown, selection, 0usize)),
+        |stream_state| async move {
+            let (mut events, mut shutdown, selection, total) = stream_state?;
+
+            let next = tokio::select! {
+                next = events.recv() => next,
+                _ = shutting_down(&mut shutdown) => {
+                    let trailer = NdjsonTrailer { total, complete: false };
+                    return Some((ndjson_line(&trailer), None));
+                }
+            };
+
+            match next {
+                Some(Ok(event)) => match selection.event_to_json(&to_event_response(event)) {
+                    Ok(mut line) => {
+                        line.push(b'\n');
+                        Some((
+                            Bytes::from(line),
+                            Some((events, shutdown, selection, total + 1)),
+                        ))
+                    }
+                    Err(e) => Some((ndjson_line(&e.to_response_body()), None)),
+                },
+                Some(Err(e)) => {
+                    let error = ApiError::from(e).to_response_body();
+                    Some((ndjson_line(&error), None))
+                }
+                None => {
+                    let trailer = NdjsonTrailer {
+                        total,
+                        complete: true,
+                    };
+                    Some((ndjson_line(&trailer), None))
+                }
+            }
+        },
+    );
+
+    // Fused: the compression layer polls the body again after it ended.
+    (
+        [(header::CONTENT_TYPE, HeaderValue::from_static(NDJSON))],
+        Body::from_stream(lines.fuse().map(Ok::<_, Infallible>)),
+    )
+        .into_response()
+}
+
+/// Resolves once the server begins shutting down; never when the state was dropped
+/// without doing so.
+async fn shutting_down(shutdown: &mut watch::Receiver<bool>) {
+    if shutdown.wait_for(|stopping| *stopping).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// A control line: an error body or the trailer, which always serialize.
+fn ndjson_line<T: Serialize>(value: &T) -> Bytes {
+    let mut line = serde_json::to_vec(value).expect("NDJSON control lines serialize");
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+fn bypasses_cache(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-cache"))
+}
+
+fn json_response(body: Bytes, cache_status: &'static str) -> Response {
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            ),
+            (
+                header::HeaderName::from_static(X_CACHE),
+                HeaderValue::from_static(cache_status),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+pub(crate) fn to_event_response(event: CollectedEvent) -> EventResponse {
+    EventResponse {
+        nostr_event_id: event.nostr_event_id.to_string(),
+        author: event.author.to_string(),
+        received_at: event.received_at,
+        event: crate::models::EventData {
+            event_id: event.event.event_id,
+            timestamp: event.event.timestamp,
+            platform: event.event.platform,
+            level: event.event.level,
+            logger: event.event.logger,
+            transaction: event.event.transaction,
+            server_name: event.event.server_name,
+            release: event.event.release,
+            environment: event.event.environment,
+            message: event.event.message,
+            exception: event.event.exception,
+            stacktrace: event.event.stacktrace,
+            tags: event.event.tags,
+            extra: event.event.extra,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiConfig;
+    use nostr_relay_builder::MockRelay;
+    use std::time::Duration;
+
+    async fn state(token: Option<&str>) -> AppState {
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let mut config = ApiConfig::new(relays.clone());
+        if let Some(token) = token {
+            config = config.with_auth_token(token);
+        }
+        let collector = EventCollector::new(relays).await.unwrap();
+        AppState::with_collector(config, collector)
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        headers.insert(header::AUTHORIZATION, value);
+        headers
+    }
+
+    /// Sends `request` through the full router of `state`.
+    async fn send(state: &AppState, request: axum::http::Request<Body>) -> Response {
+        use tower::ServiceExt;
+
+        crate::create_app(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    fn get(uri: &str) -> axum::http::Request<Body> {
+        axum::http::Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn the_openapi_document_lists_the_events_endpoint_and_its_parameters() {
+        let response = send(&state(None).await, get("/openapi.json")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let document = json_body(response).await;
+        let paths = document["paths"].as_object().unwrap();
+        for path in ["/events", "/events/count", "/events/timeseries", "/health"] {
+            assert!(paths.contains_key(path), "{} is not documented", path);
+        }
+        let parameters: Vec<&str> = document["paths"]["/events"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|parameter| parameter["name"].as_str())
+            .collect();
+        for parameter in [
+            "author",
+            "level",
+            "min_level",
+            "since",
+            "until",
+            "limit",
+            "cursor",
+        ] {
+            assert!(
+                parameters.contains(&parameter),
+                "/events does not document `{}`",
+                parameter
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn reading_webhooks_requires_the_token() {
+        let state = state(Some("secret")).await;
+
+        let listed = list_webhooks(State(state.clone()), HeaderMap::new()).await;
+        assert!(matches!(listed, Err(ApiError::Unauthorized(_))));
+        let listed = list_webhooks(State(state.clone()), bearer("wrong")).await;
+        assert!(matches!(listed, Err(ApiError::Unauthorized(_))));
+        let fetched =
+            get_webhook(State(state.clone()), HeaderMap::new(), Path(Uuid::new_v4())).await;
+        assert!(matches!(fetched, Err(ApiError::Unauthorized(_))));
+
+        let listed = list_webhooks(State(state), bearer("secret")).await.unwrap();
+        assert!(listed.0.is_empty());
+    }
+
+    async fn ndjson_lines(
+        items: Vec<sentrystr_collector::Result<CollectedEvent>>,
+    ) -> Vec<serde_json::Value> {
+        let (tx, rx) = mpsc::channel(items.len().max(1));
+        for item in items {
+            tx.send(item).await.unwrap();
+        }
+        drop(tx);
+
+        let response = ndjson_response(&state(None).await, rx, FieldSelection::default());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        body.split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect()
+    }
+
+    fn collected(message: &str) -> CollectedEvent {
+        CollectedEvent {
+            event: sentrystr::Event::new().with_message(message),
+            author: nostr::Keys::generate().public_key(),
+            nostr_event_id: nostr::EventId::all_zeros(),
+            created_at: nostr::Timestamp::now(),
+            received_at: Utc::now(),
+            nostr_tags: Vec::new(),
+            seq: None,
+        }
+    }
+
+    async fn readiness(state: &AppState) -> (StatusCode, String) {
+        let (status_code, Json(health)) =
+            ready(State(state.clone()), Query(HealthQuery { verbose: None })).await;
+        (status_code, health.status)
+    }
+
+    #[tokio::test]
+    async fn readiness_waits_for_a_relay_during_the_grace_period() {
+        let relay = MockRelay::run().await.unwrap();
+        let config = ApiConfig::new(vec![relay.url().to_string()])
+            .with_readiness_grace(Duration::from_secs(10));
+        let state = AppState::new(config).await.unwrap();
+        assert_eq!(
+            readiness(&state).await,
+            (StatusCode::SERVICE_UNAVAILABLE, "starting".to_string())
+        );
+
+        for _ in 0..100 {
+            if state.setup_complete() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(
+            readiness(&state).await,
+            (StatusCode::OK, "ready".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn readiness_is_unavailable_once_the_grace_period_passed() {
+        let unreachable = vec!["ws://127.0.0.1:1".to_string()];
+        let config = ApiConfig::new(unreachable.clone()).with_readiness_grace(Duration::ZERO);
+        let state = AppState::new(config).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(state.setup_complete());
+        assert_eq!(
+            readiness(&state).await,
+            (StatusCode::SERVICE_UNAVAILABLE, "unavailable".to_string())
+        );
+
+        let config = ApiConfig::new(unreachable).with_readiness_grace(Duration::from_secs(60));
+        let state = AppState::new(config).await.unwrap();
+        assert_eq!(
+            readiness(&state).await,
+            (StatusCode::SERVICE_UNAVAILABLE, "starting".to_string())
+        );
+    }
+
+    /// A relay holding one event per message, and its URL.
+    async fn relay_with(messages: &[&str]) -> (MockRelay, String) {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        publish(&url, messages).await;
+        (relay, url)
+    }
+
+    async fn publish(url: &str, messages: &[&str]) {
+        let config = sentrystr::Config::from_keys(&nostr::Keys::generate(), [url]).unwrap();
+        let client = sentrystr::NostrSentryClient::new(config).await.unwrap();
+        for message in messages {
+            client
+                .capture_event(sentrystr::Event::new().with_message(*message))
+                .await
+                .unwrap();
+        }
+    }
+
+    /// The `X-Cache` header and event total of `request`.
+    async fn cached_total(state: &AppState, request: axum::http::Request<Body>) -> (String, u64) {
+        let response = send(state, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cache_status = response.headers()[X_CACHE].to_str().unwrap().to_string();
+        let total = json_body(response).await["total"].as_u64().unwrap();
+        (cache_status, total)
+    }
+
+    #[tokio::test]
+    async fn identical_queries_are_served_from_the_response_cache() {
+        let (_relay, url) = relay_with(&["first"]).await;
+        let collector = EventCollector::new(vec![url.clone()]).await.unwrap();
+        let state = AppState::with_collector(ApiConfig::new(vec![url.clone()]), collector);
+
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("MISS".to_string(), 1)
+        );
+        publish(&url, &["second"]).await;
+
+        // The collector is not asked again, so the new event is not seen yet.
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("HIT".to_string(), 1)
+        );
+        assert_eq!(
+            cached_total(&state, get("/events?limit=10")).await,
+            ("MISS".to_string(), 2)
+        );
+
+        let no_cache = axum::http::Request::get("/events")
+            .header(header::CACHE_CONTROL, "max-age=0, no-cache")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            cached_total(&state, no_cache).await,
+            ("MISS".to_string(), 2)
+        );
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("HIT".to_string(), 2)
+        );
+
+        state.set_relays(vec![url]).await.unwrap();
+        assert!(state.cache.is_empty());
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("MISS".to_string(), 2)
+        );
+    }
+
+    fn conditional(uri: &str, etag: &HeaderValue) -> axum::http::Request<Body> {
+        axum::http::Request::get(uri)
+            .header(header::IF_NONE_MATCH, etag)
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn unchanged_events_are_not_modified_until_new_events_arrive() {
+        let (_relay, url) = relay_with(&["first"]).await;
+        let collector = EventCollector::new(vec![url.clone()]).await.unwrap();
+        let state = AppState::with_collector(ApiConfig::new(vec![url.clone()]), collector);
+
+        let response = send(&state, get("/events")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = response.headers()[header::ETAG].clone();
+
+        let response = send(&state, conditional("/events", &etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+
+        let response = send(&state, conditional("/events?sort=asc", &etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        publish(&url, &["second"]).await;
+        let response = send(&state, conditional("/events", &etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[header::ETAG], etag);
+        assert_eq!(json_body(response).await["total"], 2);
+    }
+
+    #[tokio::test]
+    async fn cached_responses_expire_after_the_ttl() {
+        let (_relay, url) = relay_with(&["first"]).await;
+        let config = ApiConfig::new(vec![url.clone()]).with_cache_ttl(Duration::from_millis(200));
+        let collector = EventCollector::new(vec![url.clone()]).await.unwrap();
+        let state = AppState::with_collector(config, collector);
+
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("MISS".to_string(), 1)
+        );
+        publish(&url, &["second"]).await;
+        tokio::time::sleep(Duration::from_millis(250)).await;
+
+        assert_eq!(
+            cached_total(&state, get("/events")).await,
+            ("MISS".to_string(), 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_report_when_the_scan_was_cut_short() {
+        let (_relay, url) = relay_with(&["first", "second", "third"]).await;
+        let collector = EventCollector::new(vec![url.clone()]).await.unwrap();
+        let state = AppState::with_collector(ApiConfig::new(vec![url]), collector);
+
+        let (events, exact) = collect_for_counts(&state, EventFilter::new(), 3)
+            .await
+            .unwrap();
+        assert_eq!((events.len(), exact), (3, true));
+
+        let (events, exact) = collect_for_counts(&state, EventFilter::new(), 2)
+            .await
+            .unwrap();
+        assert_eq!((events.len(), exact), (2, false));
+    }
+
+    #[tokio::test]
+    async fn relay_counts_stop_at_the_scan_limit() {
+        let (_relay, url) = relay_with(&["first", "second", "third"]).await;
+        for (scan_limit, expected) in [(3, (3, true, None)), (2, (2, false, Some(2)))] {
+            let config = ApiConfig::new(vec![url.clone()]).with_count_scan_limit(scan_limit);
+            let collector = EventCollector::new(vec![url.clone()]).await.unwrap();
+            let state = AppState::with_collector(config, collector);
+
+            let response = count_matching(&state, EventFilter::new()).await.unwrap();
+            assert_eq!(
+                (response.count, response.exact, response.scan_limit),
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_inside_the_warm_window_are_exact() {
+        let (_relay, url) = relay_with(&["first", "second", "third"]).await;
+        let config = ApiConfig::new(vec![url])
+            .with_count_scan_limit(2)
+            .with_warm_cache(crate::WarmCacheConfig::default());
+        let state = AppState::new(config).await.unwrap();
+        let warm_cache = state.warm_cache.clone().unwrap();
+        for _ in 0..100 {
+            if warm_cache.is_ready() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let filter = EventFilter::new().with_since(Utc::now() - chrono::Duration::minutes(10));
+        let response = count_matching(&state, filter).await.unwrap();
+        assert_eq!((response.count, response.exact), (3, true));
+    }
+
+    fn filters_request(
+        method: &str,
+        uri: &str,
+        token: &str,
+        body: serde_json::Value,
+    ) -> axum::http::Request<Body> {
+        axum::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    async fn event_count(state: &AppState, uri: &str) -> usize {
+        let response = send(state, get(uri)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json_body(response).await["events"]
+            .as_array()
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn saved_filters_are_executed_deleted_and_survive_a_restart() {
+        let (_relay, url) = relay_with(&["first", "second"]).await;
+        let path =
+            std::env::temp_dir().join(format!("sentrystr-filters-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = ApiConfig::new(vec![url])
+            .with_auth_token("secret")
+            .with_filters_file(&path);
+        let create = serde_json::json!({
+            "name": "latest",
+            "filter": EventFilter::new().with_limit(1),
+        });
+
+        let invalid = serde_json::json!({ "name": "Latest Events", "filter": {} });
+
+        let state = AppState::new(config.clone()).await.unwrap();
+        for (token, body, status) in [
+            ("wrong", &create, StatusCode::UNAUTHORIZED),
+            ("secret", &invalid, StatusCode::BAD_REQUEST),
+            ("secret", &create, StatusCode::CREATED),
+            ("secret", &create, StatusCode::CONFLICT),
+        ] {
+            let request = filters_request("POST", "/filters", token, body.clone());
+            assert_eq!(send(&state, request).await.status(), status);
+        }
+
+        assert_eq!(event_count(&state, "/filters/latest/events").await, 1);
+        assert_eq!(
+            event_count(&state, "/filters/latest/events?limit=5").await,
+            2
+        );
+
+        let state = AppState::new(config.clone()).await.unwrap();
+        let listed = json_body(send(&state, get("/filters")).await).await;
+        assert_eq!(listed[0]["name"], "latest");
+        assert_eq!(event_count(&state, "/filters/latest/events").await, 1);
+
+        let delete = filters_request(
+            "DELETE",
+            "/filters/latest",
+            "secret",
+            serde_json::Value::Null,
+        );
+        assert_eq!(send(&state, delete).await.status(), StatusCode::NO_CONTENT);
+        let response = send(&state, get("/filters/latest/events")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let state = AppState::new(config).await.unwrap();
+        let listed = json_body(send(&state, get("/filters")).await).await;
+        assert_eq!(listed, serde_json::json!([]));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn ndjson_streams_end_with_a_trailer_or_an_error() {
+        let lines = ndjson_lines(vec![Ok(collected("first")), Ok(collected("second"))]).await;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["event"]["message"], "second");
+        assert_eq!(lines[2], serde_json::json!({"total": 2, "complete": true}));
+
+        let lines = ndjson_lines(vec![
+            Ok(collected("first")),
+            Err(sentrystr_collector::CollectorError::Collection(
+                "relay closed".to_string(),
+            )),
+        ])
+        .await;
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1]["error"]["message"].is_string());
+    }
+
+    #[test]
+    fn test_alerts_no_relay_accepted_are_bad_gateway() {
+        let rejected = vec![(
+            "wss://relay.example.com".to_string(),
+            "blocked: rate-limited".to_string(),
+        )];
+
+        let error = relay_results(Vec::new(), rejected.clone()).unwrap_err();
+        assert!(
+            matches!(error, ApiError::Collection(ref message) if message.contains("rate-limited"))
+        );
+        assert_eq!(error.into_response().status(), StatusCode::BAD_GATEWAY);
+
+        let relays = relay_results(vec!["wss://other.example.com".to_string()], rejected).unwrap();
+        assert_eq!(
+            relays
+                .iter()
+                .map(|relay| relay.accepted)
+                .collect::<Vec<_>>(),
+            [true, false]
+        );
+    }
+
+    #[test]
+    fn ndjson_is_negotiated_from_format_or_accept() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            HeaderValue::from_static("application/json, application/x-ndjson; q=0.9"),
+        );
+        let query = |format: Option<&str>| EventQuery {
+            format: format.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert!(wants_ndjson(&headers, &query(None)));
+        assert!(!wants_ndjson(&headers, &query(Some("json"))));
+        assert!(wants_ndjson(&HeaderMap::new(), &query(Some("NDJSON"))));
+        assert!(!wants_ndjson(&HeaderMap::new(), &query(None)));
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_disabled_without_a_token() {
+        let state = state(None).await;
+        let listed = list_webhooks(State(state), bearer("anything")).await;
+        assert!(matches!(listed, Err(ApiError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn relay_overrides_are_forbidden_unless_enabled() {
+        let response = send(
+            &state(None).await,
+            get("/events?relays=wss://other.example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn malformed_relay_overrides_are_bad_requests() {
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let config = ApiConfig::new(relays.clone()).with_relay_override(true);
+        let state = AppState::with_collector(config, EventCollector::new(relays).await.unwrap());
+
+        for query in [
+            "relays=,%20,",
+            "relays=not-a-url",
+            "relays=https://relay.example.com",
+            "relays=wss://a.example.com,wss://b.example.com,wss://c.example.com,wss://d.example.com",
+            "relays=wss://a.example.com&format=ndjson",
+        ] {
+            let response = send(&state, get(&format!("/events?{}", query))).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn relay_overrides_read_only_the_given_relays() {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        sentrystr::NostrSentryClient::new(
+            sentrystr::Config::from_keys(&nostr::Keys::generate(), [url.as_str()]).unwrap(),
+        )
+        .await
+        .unwrap()
+        .capture_message(sentrystr::Level::Error, "from the override")
+        .await
+        .unwrap();
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let config = ApiConfig::new(relays.clone()).with_relay_override(true);
+        let state = AppState::with_collector(config, EventCollector::new(relays).await.unwrap());
+
+        let response = send(&state, get(&format!("/events?relays={},{}", url, url))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = json_body(response).await;
+        assert_eq!(page["events"][0]["event"]["message"], "from the override");
+        let reported: Vec<&str> = page["relays"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|relay| relay["url"].as_str().unwrap())
+            .collect();
+        assert_eq!(reported, [url.as_str()]);
+    }
 }
@@ -5,11 +5,13 @@
 //! ## Quick Start
 //!
 //! ```rust
-//! use sentrystr_api::create_app;
+//! use sentrystr_api::{ApiConfig, AppState, create_app};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let _app = create_app();
+//!     let config = ApiConfig::new(vec!["wss://relay.damus.io".to_string()]);
+//!     // let state = AppState::new(config).await?;
+//!     // let _app = create_app(state);
 //!     println!("SentryStr API server would run on http://localhost:3000");
 //!     Ok(())
 //! }
@@ -24,6 +26,9 @@
 //! curl "http://localhost:3000/events?author=npub1...&since=2024-01-01T00:00:00Z"
 //! ```
 //!
+//! Responses are cached in memory for a short TTL and carry an `X-Cache: HIT|MISS`
+//! header. Send `Cache-Control: no-cache` to force a fresh relay fetch.
+//!
 //! ### GET /events/{author}
 //! Get events by specific author:
 //! ```bash
@@ -36,26 +41,65 @@
 //! curl "http://localhost:3000/health"
 //! ```
 //!
+//! ### GET /ready
+//! Readiness check: 503 until setup completed and a relay is connected.
+//!
+//! ### GET /metrics
+//! Prometheus metrics for HTTP requests, the response cache and the collector. Needs the
+//! bearer token unless the server runs with `--public-metrics`.
+//!
+//! ### GET /openapi.json
+//! OpenAPI document describing every endpoint. Swagger UI is served at `/docs` when
+//! the server runs with `--enable-docs`.
+//!
 //! ## With Tracing Integration
 //!
+//! Every request runs inside a `request` span with `request_id`, `method`, `route`,
+//! `path`, `status` and `latency_ms` fields, and the id is returned in `X-Request-Id`.
+//! With a tracing subscriber such as sentrystr-tracing installed, events emitted while
+//! serving a request can be correlated with it.
+//!
 //! ```rust
-//! use sentrystr_api::create_app;
+//! use sentrystr_api::{ApiConfig, AppState, create_app};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let _app = create_app();
+//!     let config = ApiConfig::new(vec!["wss://relay.damus.io".to_string()]);
+//!     // let state = AppState::new(config).await?;
+//!     // let _app = create_app(state);
 //!     println!("Starting SentryStr API server");
 //!     Ok(())
 //! }
 //! ```
 
+pub mod aggregate;
 pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod cursor;
+pub mod fields;
+pub mod filters;
+pub mod grafana;
 pub mod handlers;
+pub mod limits;
+pub mod metrics;
 pub mod models;
+pub mod request_id;
+pub mod state;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod warm;
+pub mod webhooks;
 
-pub use api::create_app;
+pub use api::{ApiDoc, create_app, create_metrics_app};
+pub use cache::ResponseCache;
+pub use filters::FilterStore;
 pub use handlers::*;
+pub use metrics::Metrics;
 pub use models::*;
+pub use state::{ApiConfig, AppState};
+pub use warm::{WarmCache, WarmCacheConfig};
+pub use webhooks::WebhookRegistry;
 
 pub type Result<T> = std::result::Result<T, ApiError>;
 
@@ -64,6 +108,97 @@ pub enum ApiError {
     Collection(String),
     Internal(String),
     BadRequest(String),
+    /// A query parameter failed validation; `param` is reported in the error details.
+    InvalidParameter {
+        param: &'static str,
+        message: String,
+    },
+    InvalidPublicKey(String),
+    Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
+    Conflict(String),
+    UpstreamTimeout(String),
+    RequestTimeout(String),
+    PayloadTooLarge(String),
+    RateLimited(String),
+}
+
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::Collection(_) => ErrorCode::RelayError,
+            ApiError::Internal(_) => ErrorCode::Internal,
+            ApiError::BadRequest(_) => ErrorCode::BadRequest,
+            ApiError::InvalidParameter { .. } => ErrorCode::InvalidParameter,
+            ApiError::InvalidPublicKey(_) => ErrorCode::InvalidPublicKey,
+            ApiError::Unauthorized(_) => ErrorCode::Unauthorized,
+            ApiError::Forbidden(_) => ErrorCode::Forbidden,
+            ApiError::NotFound(_) => ErrorCode::NotFound,
+            ApiError::Conflict(_) => ErrorCode::Conflict,
+            ApiError::UpstreamTimeout(_) => ErrorCode::RelayTimeout,
+            ApiError::RequestTimeout(_) => ErrorCode::RequestTimeout,
+            ApiError::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            ApiError::RateLimited(_) => ErrorCode::RateLimited,
+        }
+    }
+
+    pub fn status(&self) -> axum::http::StatusCode {
+        use axum::http::StatusCode;
+
+        match self {
+            ApiError::Collection(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::BadRequest(_)
+            | ApiError::InvalidParameter { .. }
+            | ApiError::InvalidPublicKey(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::UpstreamTimeout(_) | ApiError::RequestTimeout(_) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            ApiError::Collection(msg)
+            | ApiError::Internal(msg)
+            | ApiError::BadRequest(msg)
+            | ApiError::InvalidPublicKey(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::UpstreamTimeout(msg)
+            | ApiError::RequestTimeout(msg)
+            | ApiError::PayloadTooLarge(msg)
+            | ApiError::RateLimited(msg) => msg,
+            ApiError::InvalidParameter { message, .. } => message,
+        }
+    }
+
+    fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            ApiError::InvalidParameter { param, .. } => Some(serde_json::json!({ "param": param })),
+            _ => None,
+        }
+    }
+
+    /// JSON body sent for this error, also used for the error line of NDJSON streams.
+    pub fn to_response_body(&self) -> ErrorResponse {
+        ErrorResponse {
+            error: ErrorBody {
+                code: self.code(),
+                message: self.message().to_string(),
+                details: self.details(),
+            },
+        }
+    }
 }
 
 impl std::fmt::Display for ApiError {
@@ -72,24 +207,151 @@ impl std::fmt::Display for ApiError {
             ApiError::Collection(msg) => write!(f, "Collection error: {}", msg),
             ApiError::Internal(msg) => write!(f, "Internal error: {}", msg),
             ApiError::BadRequest(msg) => write!(f, "Bad request: {}", msg),
+            ApiError::InvalidParameter { param, message } => {
+                write!(f, "Invalid parameter `{}`: {}", param, message)
+            }
+            ApiError::InvalidPublicKey(msg) => write!(f, "Invalid public key: {}", msg),
+            ApiError::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            ApiError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            ApiError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            ApiError::Conflict(msg) => write!(f, "Conflict: {}", msg),
+            ApiError::UpstreamTimeout(msg) => write!(f, "Upstream timeout: {}", msg),
+            ApiError::RequestTimeout(msg) => write!(f, "Request timeout: {}", msg),
+            ApiError::PayloadTooLarge(msg) => write!(f, "Payload too large: {}", msg),
+            ApiError::RateLimited(msg) => write!(f, "Rate limited: {}", msg),
         }
     }
 }
 
 impl std::error::Error for ApiError {}
 
+impl From<sentrystr_collector::CollectorError> for ApiError {
+    fn from(error: sentrystr_collector::CollectorError) -> Self {
+        match error {
+            sentrystr_collector::CollectorError::Timeout(msg) => ApiError::UpstreamTimeout(msg),
+            other => ApiError::Collection(other.to_string()),
+        }
+    }
+}
+
 impl axum::response::IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
-        let (status, error_message) = match self {
-            ApiError::Collection(msg) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg),
-            ApiError::Internal(msg) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg),
-            ApiError::BadRequest(msg) => (axum::http::StatusCode::BAD_REQUEST, msg),
-        };
+        (self.status(), axum::Json(self.to_response_body())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    #[tokio::test]
+    async fn each_error_maps_to_a_stable_code_and_status() {
+        let message = || "message".to_string();
+        let cases = [
+            (
+                ApiError::Collection(message()),
+                "relay_error",
+                StatusCode::BAD_GATEWAY,
+            ),
+            (
+                ApiError::Internal(message()),
+                "internal",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (
+                ApiError::BadRequest(message()),
+                "bad_request",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                ApiError::InvalidParameter {
+                    param: "since",
+                    message: message(),
+                },
+                "invalid_parameter",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                ApiError::InvalidPublicKey(message()),
+                "invalid_public_key",
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                ApiError::Unauthorized(message()),
+                "unauthorized",
+                StatusCode::UNAUTHORIZED,
+            ),
+            (
+                ApiError::Forbidden(message()),
+                "forbidden",
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                ApiError::NotFound(message()),
+                "not_found",
+                StatusCode::NOT_FOUND,
+            ),
+            (
+                ApiError::Conflict(message()),
+                "conflict",
+                StatusCode::CONFLICT,
+            ),
+            (
+                ApiError::UpstreamTimeout(message()),
+                "relay_timeout",
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+            (
+                ApiError::RequestTimeout(message()),
+                "request_timeout",
+                StatusCode::GATEWAY_TIMEOUT,
+            ),
+            (
+                ApiError::PayloadTooLarge(message()),
+                "payload_too_large",
+                StatusCode::PAYLOAD_TOO_LARGE,
+            ),
+            (
+                ApiError::RateLimited(message()),
+                "rate_limited",
+                StatusCode::TOO_MANY_REQUESTS,
+            ),
+        ];
+
+        for (error, code, status) in cases {
+            let response = error.into_response();
+            assert_eq!(response.status(), status, "{}", code);
 
-        let body = serde_json::json!({
-            "error": error_message
-        });
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"]["code"], code);
+            assert_eq!(body["error"]["message"], "message");
+        }
+    }
+
+    #[test]
+    fn invalid_parameters_name_the_parameter() {
+        let error = ApiError::InvalidParameter {
+            param: "since",
+            message: "message".to_string(),
+        };
+        assert_eq!(
+            error.to_response_body().error.details,
+            Some(serde_json::json!({ "param": "since" }))
+        );
+        assert_eq!(ApiError::NotFound("message".to_string()).details(), None);
+    }
 
-        (status, axum::Json(body)).into_response()
+    #[test]
+    fn collector_timeouts_are_upstream_timeouts() {
+        let error = ApiError::from(sentrystr_collector::CollectorError::Timeout(
+            "relays did not answer".to_string(),
+        ));
+        assert_eq!(error.code(), ErrorCode::RelayTimeout);
+        assert_eq!(error.status(), StatusCode::GATEWAY_TIMEOUT);
     }
 }
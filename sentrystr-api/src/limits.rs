@@ -0,0 +1,180 @@
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{ApiError, AppState, Result};
+
+/// Allows one call per `interval` for each key, e.g. per bearer token on endpoints with
+/// side effects outside the server.
+pub struct KeyedRateLimiter {
+    interval: Duration,
+    last_allowed: Mutex<HashMap<String, Instant>>,
+}
+
+impl KeyedRateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_allowed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records a call for `key`, or fails with a 429 if the previous one was less than
+    /// `interval` ago.
+    pub fn check(&self, key: &str) -> Result<()> {
+        let mut last_allowed = self.last_allowed.lock().unwrap();
+        let interval = self.interval;
+        last_allowed.retain(|_, at| at.elapsed() < interval);
+
+        if let Some(at) = last_allowed.get(key) {
+            let retry_after = interval.saturating_sub(at.elapsed());
+            return Err(ApiError::RateLimited(format!(
+                "Rate limit exceeded; retry in {}s",
+                retry_after.as_secs().max(1)
+            )));
+        }
+
+        last_allowed.insert(key.to_string(), Instant::now());
+        Ok(())
+    }
+}
+
+/// Fails requests that take longer than [`ApiConfig::request_timeout`] to produce a
+/// response with a 504. The handler future is dropped on expiry, which cancels any
+/// collector fetch it was awaiting. Only the time until the response head counts, so
+/// streaming bodies (NDJSON, SSE) are not cut off once they started.
+///
+/// [`ApiConfig::request_timeout`]: crate::ApiConfig::request_timeout
+pub async fn request_timeout(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let timeout = state.config.request_timeout;
+
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => ApiError::RequestTimeout(format!(
+            "Request did not complete within {}s",
+            timeout.as_secs()
+        ))
+        .into_response(),
+    }
+}
+
+/// Replaces the plain-text 413 produced by the body limit layer with the structured
+/// error body.
+pub async fn payload_too_large_body(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return ApiError::PayloadTooLarge("Request body exceeds the configured limit".to_string())
+            .into_response();
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ApiConfig;
+    use axum::body::Body;
+    use axum::routing::get;
+    use axum::{Router, middleware};
+    use sentrystr_collector::EventCollector;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tower::ServiceExt;
+
+    async fn state(config: ApiConfig) -> AppState {
+        let collector = EventCollector::new(config.relays.clone()).await.unwrap();
+        AppState::with_collector(config, collector)
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Sets its flag when dropped, i.e. when the handler holding it was cancelled.
+    struct Cancelled(Arc<AtomicBool>);
+
+    impl Drop for Cancelled {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_handlers_time_out_with_a_504_and_are_cancelled() {
+        let config = ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+            .with_request_timeout(Duration::from_millis(50));
+        let state = state(config).await;
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let flag = Arc::clone(&cancelled);
+        let app = Router::new()
+            .route(
+                "/never",
+                get(move || async move {
+                    let _guard = Cancelled(flag);
+                    std::future::pending::<()>().await;
+                }),
+            )
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                request_timeout,
+            ))
+            .with_state(state);
+
+        let request = Request::get("/never").body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "request_timeout"
+        );
+        assert!(cancelled.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_get_a_json_413() {
+        let config = ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+            .with_auth_token("secret")
+            .with_max_body_bytes(64);
+        let app = crate::create_app(state(config).await);
+        let body = serde_json::json!({ "name": "x".repeat(128), "filter": {} }).to_string();
+
+        for content_length in [Some(body.len()), None] {
+            let mut request = Request::post("/filters")
+                .header(header::AUTHORIZATION, "Bearer secret")
+                .header(header::CONTENT_TYPE, "application/json");
+            if let Some(content_length) = content_length {
+                request = request.header(header::CONTENT_LENGTH, content_length);
+            }
+            let request = request.body(Body::from(body.clone())).unwrap();
+
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            assert_eq!(
+                json_body(response).await["error"]["code"],
+                "payload_too_large"
+            );
+        }
+    }
+}
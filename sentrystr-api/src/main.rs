@@ -1,6 +1,14 @@
+use axum::Router;
 use clap::Parser;
-use sentrystr_api::create_app;
+use sentrystr_api::{ApiConfig, AppState, WarmCacheConfig, create_app, create_metrics_app};
+use sentrystr_tracing::SentryStrTracingBuilder;
+use std::future::Future;
 use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
 
 #[derive(Parser)]
 #[command(name = "sentrystr-api")]
@@ -11,22 +19,543 @@ struct Cli {
 
     #[arg(long, default_value = "127.0.0.1")]
     host: String,
+
+    #[arg(short, long, help = "Relay URLs", default_values = &["wss://relay.damus.io"])]
+    relays: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = "15",
+        help = "Seconds to cache identical /events queries (0 disables caching)"
+    )]
+    cache_ttl_secs: u64,
+
+    #[arg(
+        long,
+        default_value = "1000",
+        help = "Maximum number of cached responses"
+    )]
+    cache_max_entries: usize,
+
+    #[arg(
+        long,
+        default_value = "2",
+        help = "Minimum length of the q search parameter"
+    )]
+    min_search_len: usize,
+
+    #[arg(long, help = "Forward q searches to relays as NIP-50 search filters")]
+    nip50_search: bool,
+
+    #[arg(
+        long,
+        default_value = "10",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Seconds between background relay status refreshes for /health"
+    )]
+    relay_status_refresh_secs: u64,
+
+    #[arg(long, help = "Serve Swagger UI at /docs")]
+    enable_docs: bool,
+
+    #[arg(
+        long,
+        help = "Serve /metrics on this address instead of the main listener (e.g. 127.0.0.1:9100)"
+    )]
+    metrics_addr: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        help = "Serve /metrics on the main listener without the --auth-token bearer token"
+    )]
+    public_metrics: bool,
+
+    #[arg(
+        long,
+        default_value = "30",
+        help = "Seconds to wait for in-flight requests to finish on shutdown"
+    )]
+    shutdown_timeout_secs: u64,
+
+    #[arg(
+        long,
+        help = "Report the server's own warnings and errors to Nostr with sentrystr-tracing, configured from the SENTRYSTR_* variables"
+    )]
+    report_errors: bool,
+
+    #[arg(long, help = "Disable gzip/brotli response compression")]
+    no_compression: bool,
+
+    #[arg(
+        long,
+        default_value = "1024",
+        help = "Minimum response size in bytes before compressing"
+    )]
+    compression_min_bytes: u16,
+
+    #[arg(
+        long,
+        default_value = "30",
+        help = "Seconds a request may take before failing with 504"
+    )]
+    request_timeout_secs: u64,
+
+    #[arg(
+        long,
+        default_value = "1048576",
+        help = "Maximum request body size in bytes"
+    )]
+    max_body_bytes: usize,
+
+    #[arg(
+        long,
+        env = "SENTRYSTR_API_TOKEN",
+        help = "Bearer token required by endpoints that modify server state"
+    )]
+    auth_token: Option<String>,
+
+    #[arg(
+        long,
+        help = "File with the collector's secret key (hex, nsec or NIP-49 ncryptsec); an ncryptsec is decrypted with SENTRYSTR_KEY_PASSPHRASE"
+    )]
+    key_file: Option<std::path::PathBuf>,
+
+    #[arg(long, help = "JSON file to persist saved filters to")]
+    filters_file: Option<std::path::PathBuf>,
+
+    #[arg(
+        long,
+        help = "Allow /events?relays=... to query up to 3 caller-chosen relays instead of the shared pool"
+    )]
+    allow_relay_override: bool,
+
+    #[arg(
+        long,
+        default_value = "5000",
+        help = "Most events /events/count scans before returning an inexact count"
+    )]
+    count_scan_limit: usize,
+
+    #[arg(
+        long,
+        default_value = "30",
+        help = "Seconds after startup /ready reports 'starting' while waiting for a relay and the warm cache"
+    )]
+    readiness_grace_secs: u64,
+
+    #[arg(
+        long,
+        help = "Keep recent events in memory and answer /events from them"
+    )]
+    warm_cache: bool,
+
+    #[arg(
+        long,
+        default_value = "1h",
+        value_parser = parse_duration_arg,
+        help = "How far back the warm cache keeps events (e.g. 30m, 1h, 1d)"
+    )]
+    warm_window: Duration,
+
+    #[arg(
+        long,
+        default_value = "30s",
+        value_parser = parse_interval_arg,
+        help = "How often the warm cache evicts expired events and re-fetches recent ones"
+    )]
+    warm_refresh: Duration,
+
+    #[arg(
+        long,
+        default_value = "50000",
+        help = "Most events the warm cache holds"
+    )]
+    warm_max_events: usize,
+
+    #[arg(
+        long,
+        default_value = "67108864",
+        help = "Most bytes of serialized events the warm cache holds"
+    )]
+    warm_max_bytes: usize,
+
+    #[arg(
+        long,
+        default_value = "1000",
+        help = "Most buckets a single /events/timeseries response may contain"
+    )]
+    timeseries_max_points: usize,
+
+    #[arg(
+        long,
+        env = "SENTRYSTR_VISIBLE_AUTHORS",
+        value_delimiter = ',',
+        help = "Only expose events from these authors (npub or hex, comma-separated)"
+    )]
+    visible_authors: Vec<String>,
+
+    #[arg(
+        long,
+        requires = "visible_authors",
+        help = "Reject queries for authors outside --visible-authors with 403 instead of returning nothing"
+    )]
+    strict_authors: bool,
+
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        requires = "tls_key",
+        help = "PEM certificate chain; serves HTTPS instead of HTTP when set"
+    )]
+    tls_cert: Option<PathBuf>,
+
+    #[cfg(feature = "tls")]
+    #[arg(long, requires = "tls_cert", help = "PEM private key for --tls-cert")]
+    tls_key: Option<PathBuf>,
+
+    #[cfg(feature = "tls")]
+    #[arg(
+        long,
+        requires = "tls_cert",
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Re-read the TLS certificate and key every N seconds"
+    )]
+    tls_reload_secs: Option<u64>,
+}
+
+impl Cli {
+    #[cfg(feature = "tls")]
+    fn tls_files(&self) -> Option<(PathBuf, PathBuf)> {
+        self.tls_cert.clone().zip(self.tls_key.clone())
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn tls_files(&self) -> Option<(std::path::PathBuf, std::path::PathBuf)> {
+        None
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
 
-    let app = create_app();
+    // Held until the server drained, so errors logged while shutting down are sent too.
+    let tracing_guard = if cli.report_errors {
+        Some(
+            SentryStrTracingBuilder::from_env()?
+                .with_min_level(tracing::Level::WARN)
+                .init()
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    let mut config = ApiConfig::new(cli.relays.clone())
+        .with_cache_ttl(Duration::from_secs(cli.cache_ttl_secs))
+        .with_cache_max_entries(cli.cache_max_entries)
+        .with_min_search_len(cli.min_search_len)
+        .with_nip50_search(cli.nip50_search)
+        .with_relay_status_refresh(Duration::from_secs(cli.relay_status_refresh_secs))
+        .with_docs(cli.enable_docs)
+        .with_public_metrics(cli.public_metrics)
+        .with_compression(!cli.no_compression, cli.compression_min_bytes)
+        .with_request_timeout(Duration::from_secs(cli.request_timeout_secs))
+        .with_max_body_bytes(cli.max_body_bytes)
+        .with_relay_override(cli.allow_relay_override)
+        .with_count_scan_limit(cli.count_scan_limit)
+        .with_readiness_grace(Duration::from_secs(cli.readiness_grace_secs))
+        .with_timeseries_max_points(cli.timeseries_max_points);
+
+    if let Some(metrics_addr) = cli.metrics_addr {
+        config = config.with_metrics_addr(metrics_addr);
+    }
+
+    if let Some(ref token) = cli.auth_token {
+        config = config.with_auth_token(token);
+    }
+
+    if let Some(ref path) = cli.key_file {
+        let passphrase = sentrystr::PassphraseSource::env(sentrystr::keyfile::PASSPHRASE_VAR);
+        config = config.with_keys(sentrystr::read_key_file(path, &passphrase)?);
+    }
+
+    if let Some(ref path) = cli.filters_file {
+        config = config.with_filters_file(path);
+    }
+
+    if !cli.visible_authors.is_empty() {
+        let authors = cli
+            .visible_authors
+            .iter()
+            .map(|author| nostr::PublicKey::parse(author.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        config = config.with_visible_authors(authors, cli.strict_authors);
+    }
+
+    if cli.warm_cache {
+        config = config.with_warm_cache(WarmCacheConfig {
+            window: cli.warm_window,
+            refresh: cli.warm_refresh,
+            max_events: cli.warm_max_events,
+            max_bytes: cli.warm_max_bytes,
+        });
+    }
+
+    let state = AppState::new(config).await?;
+    let app = create_app(state.clone());
 
     let addr = SocketAddr::new(cli.host.parse()?, cli.port);
 
+    let scheme = if cli.tls_files().is_some() {
+        "https"
+    } else {
+        "http"
+    };
+
     println!("SentryStr API server starting on {}", addr);
-    println!("Health endpoint: http://{}/health", addr);
-    println!("Events endpoint: http://{}/events", addr);
+    println!("Health endpoint: {}://{}/health", scheme, addr);
+    println!("Readiness endpoint: {}://{}/ready", scheme, addr);
+    println!("Events endpoint: {}://{}/events", scheme, addr);
+    println!("OpenAPI document: {}://{}/openapi.json", scheme, addr);
+    if cli.enable_docs {
+        println!("API docs: {}://{}/docs", scheme, addr);
+    }
+
+    match cli.metrics_addr {
+        Some(metrics_addr) => {
+            println!("Metrics endpoint: http://{}/metrics", metrics_addr);
+            let metrics_listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+            let metrics_app = create_metrics_app(state.clone());
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+                    eprintln!("Metrics server error: {}", e);
+                }
+            });
+        }
+        None => println!("Metrics endpoint: {}://{}/metrics", scheme, addr),
+    }
+
+    let (drain_tx, drain_rx) = watch::channel(false);
+
+    let server = match cli.tls_files() {
+        #[cfg(feature = "tls")]
+        Some((cert, key)) => {
+            let tls_config = sentrystr_api::tls::load_config(&cert, &key).await?;
+            if let Some(secs) = cli.tls_reload_secs {
+                sentrystr_api::tls::spawn_reload(
+                    tls_config.clone(),
+                    cert,
+                    key,
+                    Duration::from_secs(secs),
+                )?;
+            }
+
+            let handle = axum_server::Handle::new();
+            let drain_handle = handle.clone();
+            let mut drain_rx = drain_rx;
+            tokio::spawn(async move {
+                let _ = drain_rx.wait_for(|draining| *draining).await;
+                drain_handle.graceful_shutdown(None);
+            });
+
+            tokio::spawn(async move {
+                axum_server::bind_rustls(addr, tls_config)
+                    .handle(handle)
+                    .serve(app.into_make_service())
+                    .await
+            })
+        }
+        _ => spawn_server(tokio::net::TcpListener::bind(addr).await?, app, drain_rx),
+    };
+
+    let drain_timeout = Duration::from_secs(cli.shutdown_timeout_secs);
+    run_until_shutdown(server, drain_tx, &state, shutdown_signal(), drain_timeout).await?;
+
+    if let Some(guard) = tracing_guard {
+        println!("Sending reported errors");
+        guard.close(drain_timeout).await;
+    }
+    println!("Shutdown complete");
+
+    Ok(())
+}
+
+/// Serves `app` on `listener` until `drain` flips to `true`, then lets in-flight requests
+/// finish.
+fn spawn_server(
+    listener: tokio::net::TcpListener,
+    app: Router,
+    mut drain: watch::Receiver<bool>,
+) -> JoinHandle<std::io::Result<()>> {
+    tokio::spawn(async move {
+        axum::serve(listener, app)
+            .with_graceful_shutdown(async move {
+                let _ = drain.wait_for(|draining| *draining).await;
+            })
+            .await
+    })
+}
+
+/// Waits for `server` to fail or for `shutdown`, then drains it: streams are told to
+/// end, in-flight requests get up to `drain_timeout` to finish, and the collector
+/// disconnects from its relays.
+async fn run_until_shutdown(
+    mut server: JoinHandle<std::io::Result<()>>,
+    drain: watch::Sender<bool>,
+    state: &AppState,
+    shutdown: impl Future<Output = ()>,
+    drain_timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tokio::select! {
+        result = &mut server => {
+            result??;
+            return Ok(());
+        }
+        _ = shutdown => {}
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    println!("Shutdown signal received, draining in-flight requests");
+    state.begin_shutdown();
+    let _ = drain.send(true);
 
+    match tokio::time::timeout(drain_timeout, &mut server).await {
+        Ok(result) => {
+            result??;
+            println!("All requests drained");
+        }
+        Err(_) => {
+            eprintln!(
+                "Drain timeout of {}s elapsed, closing remaining connections",
+                drain_timeout.as_secs()
+            );
+            server.abort();
+        }
+    }
+
+    println!("Disconnecting from relays");
+    state.close().await?;
     Ok(())
 }
+
+fn parse_duration_arg(value: &str) -> Result<Duration, String> {
+    sentrystr_collector::parse_duration(value)
+        .and_then(|duration| duration.to_std().ok())
+        .ok_or_else(|| {
+            format!(
+                "Invalid duration '{}'. Expected e.g. 30s, 15m, 1h or 1d",
+                value
+            )
+        })
+}
+
+/// A duration that timers tick at, which must not be zero.
+fn parse_interval_arg(value: &str) -> Result<Duration, String> {
+    let interval = parse_duration_arg(value)?;
+    if interval.is_zero() {
+        return Err(format!("Invalid interval '{}'. Must be more than 0", value));
+    }
+    Ok(interval)
+}
+
+/// Resolves on SIGINT/Ctrl+C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            eprintln!("Failed to listen for Ctrl+C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_lets_in_flight_requests_finish_and_releases_the_port() {
+        let relays = vec!["ws://127.0.0.1:9".to_string()];
+        let collector = sentrystr_collector::EventCollector::new(relays.clone())
+            .await
+            .unwrap();
+        let state = AppState::with_collector(ApiConfig::new(relays), collector);
+        let app = Router::new().route(
+            "/slow",
+            axum::routing::get(|| async {
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                "done"
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (drain_tx, drain_rx) = watch::channel(false);
+        let server = spawn_server(listener, app, drain_rx);
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}/slow", addr)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let shutdown = std::future::ready(());
+        run_until_shutdown(server, drain_tx, &state, shutdown, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "done");
+        assert!(*state.shutdown_signal().borrow());
+        tokio::net::TcpListener::bind(addr).await.unwrap();
+    }
+
+    #[test]
+    fn warm_refresh_must_not_be_zero() {
+        let cli = Cli::try_parse_from(["sentrystr-api", "--warm-refresh", "15m"]).unwrap();
+        assert_eq!(cli.warm_refresh, Duration::from_secs(900));
+        assert!(Cli::try_parse_from(["sentrystr-api", "--warm-refresh", "0s"]).is_err());
+    }
+
+    #[test]
+    fn relay_status_refresh_must_not_be_zero() {
+        let args = ["sentrystr-api", "--relay-status-refresh-secs", "0"];
+        assert!(Cli::try_parse_from(args).is_err());
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn tls_reload_interval_must_not_be_zero() {
+        let args = |secs| {
+            [
+                "sentrystr-api",
+                "--tls-cert",
+                "cert.pem",
+                "--tls-key",
+                "key.pem",
+                "--tls-reload-secs",
+                secs,
+            ]
+        };
+        let cli = Cli::try_parse_from(args("3600")).unwrap();
+        assert_eq!(cli.tls_reload_secs, Some(3600));
+        assert!(Cli::try_parse_from(args("0")).is_err());
+    }
+}
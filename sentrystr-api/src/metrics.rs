@@ -0,0 +1,201 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, Registry, TextEncoder};
+use sentrystr_collector::CollectorStats;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::auth::require_auth;
+use crate::{AppState, Result};
+
+/// Prometheus registry for the API server. Lives in [`AppState`] so handlers can bump
+/// their own counters next to the HTTP metrics recorded by [`track_requests`].
+pub struct Metrics {
+    registry: Registry,
+    pub http_request_duration: HistogramVec,
+    pub cache_hits: IntCounter,
+    pub cache_misses: IntCounter,
+    events_fetched: IntCounter,
+    relay_errors: IntCounter,
+    collector_stats: Arc<CollectorStats>,
+}
+
+impl Metrics {
+    pub fn new(collector_stats: Arc<CollectorStats>) -> Self {
+        let registry = Registry::new();
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "sentrystr_api_http_request_duration_seconds",
+                "HTTP request latency by route and status",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("valid histogram definition");
+        let cache_hits = IntCounter::new(
+            "sentrystr_api_cache_hits_total",
+            "Responses served from the response cache",
+        )
+        .expect("valid counter definition");
+        let cache_misses = IntCounter::new(
+            "sentrystr_api_cache_misses_total",
+            "Responses computed from the relays",
+        )
+        .expect("valid counter definition");
+        let events_fetched = IntCounter::new(
+            "sentrystr_collector_events_fetched_total",
+            "SentryStr events fetched from relays",
+        )
+        .expect("valid counter definition");
+        let relay_errors = IntCounter::new(
+            "sentrystr_collector_relay_errors_total",
+            "Failed relay requests",
+        )
+        .expect("valid counter definition");
+
+        for collector in [
+            Box::new(http_request_duration.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(cache_hits.clone()),
+            Box::new(cache_misses.clone()),
+            Box::new(events_fetched.clone()),
+            Box::new(relay_errors.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+
+        Self {
+            registry,
+            http_request_duration,
+            cache_hits,
+            cache_misses,
+            events_fetched,
+            relay_errors,
+            collector_stats,
+        }
+    }
+
+    /// Renders every registered metric in the Prometheus text format.
+    pub fn render(&self) -> Vec<u8> {
+        // Collector counters live in the collector; catch the exported copies up on scrape.
+        sync_counter(&self.events_fetched, self.collector_stats.events_fetched());
+        sync_counter(&self.relay_errors, self.collector_stats.relay_errors());
+
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            eprintln!("Failed to encode metrics: {}", e);
+        }
+        buffer
+    }
+}
+
+fn sync_counter(counter: &IntCounter, value: u64) {
+    let current = counter.get();
+    if value > current {
+        counter.inc_by(value - current);
+    }
+}
+
+/// Records the latency of every request, labelled by the matched route template so that
+/// path parameters don't explode label cardinality.
+pub async fn track_requests(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let response = next.run(request).await;
+
+    state
+        .metrics
+        .http_request_duration
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .observe(started.elapsed().as_secs_f64());
+
+    response
+}
+
+/// `/metrics` on the main listener: requires the bearer token unless the server runs
+/// with [`ApiConfig::public_metrics`].
+///
+/// [`ApiConfig::public_metrics`]: crate::ApiConfig::public_metrics
+pub async fn gated_metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse> {
+    if !state.config.public_metrics {
+        require_auth(&state, &headers)?;
+    }
+    Ok(metrics(State(state)).await)
+}
+
+pub async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        state.metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ApiConfig, ApiError};
+    use sentrystr_collector::EventCollector;
+
+    async fn state(config: ApiConfig) -> AppState {
+        let collector = EventCollector::new(config.relays.clone()).await.unwrap();
+        AppState::with_collector(config, collector)
+    }
+
+    fn config() -> ApiConfig {
+        ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = HeaderValue::from_str(&format!("Bearer {}", token)).unwrap();
+        headers.insert(header::AUTHORIZATION, value);
+        headers
+    }
+
+    #[tokio::test]
+    async fn metrics_require_the_token_by_default() {
+        let state = state(config().with_auth_token("secret")).await;
+
+        let response = gated_metrics(State(state.clone()), HeaderMap::new()).await;
+        assert!(matches!(response, Err(ApiError::Unauthorized(_))));
+
+        let response = gated_metrics(State(state), bearer("secret")).await.unwrap();
+        assert_eq!(response.into_response().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn metrics_are_disabled_without_a_token() {
+        let state = state(config()).await;
+        let response = gated_metrics(State(state), HeaderMap::new()).await;
+        assert!(matches!(response, Err(ApiError::Forbidden(_))));
+    }
+
+    #[tokio::test]
+    async fn public_metrics_need_no_token() {
+        let state = state(config().with_public_metrics(true)).await;
+        let response = gated_metrics(State(state), HeaderMap::new()).await.unwrap();
+        assert_eq!(response.into_response().status(), StatusCode::OK);
+    }
+}
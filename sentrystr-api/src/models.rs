@@ -1,8 +1,16 @@
 use chrono::{DateTime, Utc};
-use sentrystr::Level;
+use nostr::PublicKey;
+use regex::Regex;
+use sentrystr::{DmEncryption, Exception, Level, Stacktrace};
+use sentrystr_collector::{EventFilter, SortOrder, parse_levels, parse_time};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::cursor::decode_cursor;
+use crate::{ApiConfig, ApiError, Result};
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct EventResponse {
     pub nostr_event_id: String,
     pub author: String,
@@ -10,11 +18,12 @@ pub struct EventResponse {
     pub event: EventData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct EventData {
     pub event_id: String,
     pub timestamp: DateTime<Utc>,
     pub platform: String,
+    #[schema(value_type = String, example = "error")]
     pub level: Level,
     pub logger: Option<String>,
     pub transaction: Option<String>,
@@ -22,31 +31,734 @@ pub struct EventData {
     pub release: Option<String>,
     pub environment: Option<String>,
     pub message: Option<String>,
+    /// Errors with their cause chains, outermost first.
+    #[schema(value_type = Option<Vec<Object>>)]
+    pub exception: Option<Vec<Exception>>,
+    #[schema(value_type = Option<Object>)]
+    pub stacktrace: Option<Stacktrace>,
     pub tags: std::collections::HashMap<String, String>,
+    #[schema(value_type = Object)]
     pub extra: std::collections::HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct EventQuery {
+    /// Author public key (hex or npub).
     pub author: Option<String>,
+    /// Comma-separated list of levels, e.g. `error,fatal`.
     pub level: Option<String>,
+    /// Minimum level; matches that level and everything more severe.
+    pub min_level: Option<String>,
     pub service: Option<String>,
     pub environment: Option<String>,
     pub component: Option<String>,
     pub severity: Option<String>,
-    pub since: Option<DateTime<Utc>>,
-    pub until: Option<DateTime<Utc>>,
+    /// Nostr tag filters in `key:value` form, matched against the tags of the published
+    /// Nostr event (the same tags `service`, `environment`, etc. map onto).
+    #[serde(default)]
+    pub tag: Vec<String>,
+    /// Event tag filters in `key:value` form, matched against the `tags` map inside the
+    /// parsed SentryStr event content rather than the Nostr event's tags.
+    #[serde(default)]
+    pub xtag: Vec<String>,
+    /// Full-text search over event messages, case-insensitive unless `q_regex` is set.
+    pub q: Option<String>,
+    /// Interpret `q` as a regular expression.
+    pub q_regex: Option<bool>,
+    /// Start of the window: RFC 3339, unix epoch seconds, or relative to now (`24h`, `7d`,
+    /// `now-2h`). A bare number is always epoch seconds.
+    pub since: Option<String>,
+    /// End of the window, in the same formats as `since`; must not be before `since`.
+    pub until: Option<String>,
+    /// Maximum number of events to return (default 100; unlimited when streaming).
     pub limit: Option<usize>,
+    /// Sort by time, `desc` (default) or `asc`: by the second of the Nostr `created_at`
+    /// relays page on, then by event timestamp and Nostr event id in the same direction.
+    pub sort: Option<String>,
+    /// `next_cursor` from a previous page. Only valid with the `sort` it was issued for.
+    pub cursor: Option<String>,
+    /// Return counts per `level`, `author`, `service`, `environment` or `fingerprint`
+    /// instead of events.
+    /// Cannot be combined with `limit` or `cursor`.
+    pub group_by: Option<String>,
+    /// Response format; `ndjson` streams events line by line, like
+    /// `Accept: application/x-ndjson`.
+    pub format: Option<String>,
+    /// Comma-separated relay URLs (at most 3) to query instead of the shared relay pool.
+    /// Only accepted when the server runs with `--allow-relay-override`.
+    pub relays: Option<String>,
+    /// Comma-separated event fields to return, e.g. `timestamp,level,message,tags.service`.
+    /// `nostr_event_id`, `author` and `received_at` are always included.
+    pub fields: Option<String>,
+    /// `summary` drops `extra`; `full` (the default) returns everything. Ignored when
+    /// `fields` is set.
+    pub view: Option<String>,
+}
+
+impl EventQuery {
+    /// Normalized representation of the query used to key cached responses, so that
+    /// requests differing only in parameter order or level casing share an entry.
+    pub fn cache_key(&self) -> String {
+        let mut normalized = self.clone();
+        normalized.level = normalized.level.map(|level| level.to_lowercase());
+        normalized.min_level = normalized.min_level.map(|level| level.to_lowercase());
+        serde_json::to_string(&normalized).unwrap_or_default()
+    }
+
+    /// Converts the query parameters into the collector filter they describe. Shared by
+    /// every endpoint that accepts `EventQuery`, so they validate parameters identically.
+    /// `limit`, `group_by`, `format`, `relays`, `fields` and `view` shape the response
+    /// rather than the filter and are left to the caller.
+    pub fn into_filter(self, config: &ApiConfig) -> Result<EventFilter> {
+        let mut filter = EventFilter::new();
+
+        if let Some(author_str) = self.author {
+            let author = PublicKey::parse(&author_str)
+                .map_err(|e| ApiError::InvalidPublicKey(format!("Invalid public key: {}", e)))?;
+            filter = filter.with_author(author);
+        }
+
+        if self.level.is_some() && self.min_level.is_some() {
+            return Err(ApiError::BadRequest(
+                "`level` and `min_level` cannot be combined; use a level list or a minimum level"
+                    .to_string(),
+            ));
+        }
+
+        if let Some(level_str) = self.level {
+            let levels =
+                parse_levels(&level_str).map_err(|message| ApiError::InvalidParameter {
+                    param: "level",
+                    message,
+                })?;
+            for level in levels {
+                filter = filter.with_level(level);
+            }
+        }
+
+        if let Some(min_level_str) = self.min_level {
+            let min_level =
+                min_level_str
+                    .parse::<Level>()
+                    .map_err(|e| ApiError::InvalidParameter {
+                        param: "min_level",
+                        message: e.to_string(),
+                    })?;
+            filter = filter.with_min_level(min_level);
+        }
+
+        if let Some(service) = self.service {
+            filter = filter.with_service_filter(service);
+        }
+
+        if let Some(environment) = self.environment {
+            filter = filter.with_environment_filter(environment);
+        }
+
+        if let Some(component) = self.component {
+            filter = filter.with_component_filter(component);
+        }
+
+        if let Some(severity) = self.severity {
+            filter = filter.with_severity_filter(severity);
+        }
+
+        for tag in &self.tag {
+            let (key, value) = parse_tag_param("tag", tag)?;
+            filter = filter.with_nostr_tag(key, value);
+        }
+
+        for tag in &self.xtag {
+            let (key, value) = parse_tag_param("xtag", tag)?;
+            filter = filter.with_tag(key, value);
+        }
+
+        if let Some(ref query) = self.q {
+            if query.chars().count() < config.min_search_len {
+                return Err(ApiError::InvalidParameter {
+                    param: "q",
+                    message: format!(
+                        "Search query must be at least {} characters",
+                        config.min_search_len
+                    ),
+                });
+            }
+
+            if self.q_regex.unwrap_or(false) {
+                let regex = Regex::new(query).map_err(|e| ApiError::InvalidParameter {
+                    param: "q",
+                    message: format!("Invalid search regex: {}", e),
+                })?;
+                filter = filter.with_message_regex(regex);
+            } else {
+                filter = filter.with_message_contains(query.as_str());
+                if config.nip50_search {
+                    filter = filter.with_nip50_search(query.as_str());
+                }
+            }
+        }
+
+        if let Some(ref since) = self.since {
+            filter = filter.with_since(parse_time_param("since", since)?);
+        }
+
+        if let Some(ref until) = self.until {
+            filter = filter.with_until(parse_time_param("until", until)?);
+        }
+
+        if let (Some(since), Some(until)) = (filter.since, filter.until)
+            && since > until
+        {
+            return Err(ApiError::BadRequest(format!(
+                "`since` ({}) is after `until` ({})",
+                since.to_rfc3339(),
+                until.to_rfc3339()
+            )));
+        }
+
+        if let Some(ref sort) = self.sort {
+            let sort = sort
+                .parse::<SortOrder>()
+                .map_err(|message| ApiError::InvalidParameter {
+                    param: "sort",
+                    message,
+                })?;
+            filter = filter.with_sort(sort);
+        }
+
+        if let Some(ref cursor) = self.cursor {
+            let after = decode_cursor(cursor, filter.sort)?;
+            filter = filter.with_after(after);
+        }
+
+        config.restrict_authors(filter)
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct EventsResponse {
     pub events: Vec<EventResponse>,
     pub total: usize,
+    /// Pass as `cursor` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// Relays actually queried and their status, when `relays` overrode the shared pool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relays: Option<Vec<RelayHealth>>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TimeseriesQuery {
+    /// Bucket width as a duration, e.g. `1m`, `5m` or `1h`.
+    pub bucket: String,
+}
+
+/// `/events/timeseries` response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesResponse {
+    pub bucket_seconds: i64,
+    pub series: Vec<TimeseriesSeries>,
+    /// `false` when more events matched than `scan_limit`; only the newest were counted,
+    /// so the earliest buckets are undercounted.
+    pub exact: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesSeries {
+    /// Value of the `group_by` dimension, or `all` when ungrouped.
+    pub key: String,
+    pub points: Vec<TimeseriesPoint>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesPoint {
+    /// Start of the bucket, aligned to a multiple of the bucket width since the epoch.
+    pub t: DateTime<Utc>,
+    pub count: usize,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TestAlertRequest {
+    /// Recipient public key (hex or npub).
+    pub recipient: String,
+    /// Send as a NIP-17 private message instead of a NIP-44 encrypted DM.
+    #[serde(default)]
+    pub use_nip17: bool,
+    /// `nip04` (legacy), `nip44` or `nip17`; takes precedence over `use_nip17`.
+    #[schema(value_type = Option<String>)]
+    pub encryption: Option<DmEncryption>,
+    /// Replaces the default test alert text.
+    pub message: Option<String>,
+}
+
+impl TestAlertRequest {
+    pub fn encryption(&self) -> DmEncryption {
+        self.encryption
+            .unwrap_or(DmEncryption::from_nip17(self.use_nip17))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TestAlertResponse {
+    /// Key the alert was sent from, as npub.
+    pub sender_npub: String,
+    pub event_id: String,
+    pub relays: Vec<RelayPublishResult>,
+    /// Relays of the recipient's kind 10050 list the NIP-17 alert went to; empty when it
+    /// went to the server's relays.
+    pub inbox_relays: Vec<String>,
+    /// Why the recipient's inbox relays are unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub discovery_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RelayPublishResult {
+    pub url: String,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `/events/count` response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CountResponse {
+    pub count: usize,
+    /// `false` when the relay scan stopped at `scan_limit`; `count` is then a lower bound.
+    pub exact: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupCount {
+    pub key: String,
+    pub count: usize,
+}
+
+/// `/events` response when `group_by` is set.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GroupsResponse {
+    pub groups: Vec<GroupCount>,
+    pub total: usize,
+    /// `false` when more events matched than `scan_limit`; only the newest were counted.
+    pub exact: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuthorsQuery {
+    /// Start of the window: RFC 3339, unix epoch seconds, or relative to now (`7d`).
+    pub since: Option<String>,
+    /// End of the window, in the same formats as `since`; must not be before `since`.
+    pub until: Option<String>,
+    /// Maximum number of authors to return (default 100).
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuthorSummary {
+    pub pubkey_hex: String,
+    pub npub: String,
+    pub event_count: usize,
+    pub last_seen: DateTime<Utc>,
+    pub levels_seen: Vec<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// A named, stored [`EventFilter`]. `limit` and `after` inside the filter act as
+/// defaults and can be overridden per request.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SavedFilter {
+    pub name: String,
+    #[schema(value_type = Object)]
+    pub filter: EventFilter,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateFilterRequest {
+    pub name: String,
+    /// Serialized `EventFilter`; omitted fields match everything.
+    #[schema(value_type = Object)]
+    pub filter: EventFilter,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilterEventsQuery {
+    /// Overrides the stored filter's limit (default 100).
+    pub limit: Option<usize>,
+    /// `next_cursor` from a previous page of this filter.
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    /// http(s) URL each matching event is POSTed to.
+    pub url: String,
+    /// Serialized `EventFilter`; omitted fields match everything.
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub filter: EventFilter,
+    /// Only deliver events at this level or more severe.
+    pub min_level: Option<String>,
+    /// Key for the HMAC-SHA256 signature sent in `X-SentryStr-Signature`.
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookStatus {
+    Active,
+    /// Stopped after too many consecutive failed deliveries.
+    Disabled,
+}
+
+/// A registered webhook and its delivery state. The secret is never returned.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookInfo {
+    pub id: Uuid,
+    pub url: String,
+    #[schema(value_type = Object)]
+    pub filter: EventFilter,
+    pub min_level: Option<String>,
+    pub status: WebhookStatus,
+    pub delivered: u64,
+    pub consecutive_failures: u32,
+    pub last_delivery_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Last line of an NDJSON `/events` stream.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NdjsonTrailer {
+    pub total: usize,
+    /// `false` when the stream was cut short by a server shutdown.
+    pub complete: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub timestamp: DateTime<Utc>,
+    pub relays: Vec<RelayHealth>,
+    /// True when at least one configured relay is not connected.
+    pub degraded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RelayHealth {
+    pub url: String,
+    pub connected: bool,
+    pub last_event_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HealthQuery {
+    /// Include an error string for each disconnected relay.
+    pub verbose: Option<bool>,
+}
+
+/// Body returned with every non-2xx response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    /// Human-readable description; may change between releases, branch on `code`.
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+/// Stable, machine-readable error codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    InvalidParameter,
+    InvalidPublicKey,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    RateLimited,
+    RequestTimeout,
+    RelayError,
+    RelayTimeout,
+    Internal,
+}
+
+pub(crate) fn parse_time_param(param: &'static str, value: &str) -> Result<DateTime<Utc>> {
+    parse_time(value, Utc::now()).map_err(|message| ApiError::InvalidParameter { param, message })
+}
+
+fn parse_tag_param(param: &'static str, tag: &str) -> Result<(String, String)> {
+    match tag.split_once(':') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(ApiError::InvalidParameter {
+            param,
+            message: format!("Invalid {} filter '{}'. Expected 'key:value'", param, tag),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use std::collections::HashSet;
+
+    fn filter(query: EventQuery) -> Result<EventFilter> {
+        query.into_filter(&ApiConfig::new(Vec::new()))
+    }
+
+    fn levels(query: EventQuery) -> HashSet<Level> {
+        filter(query).unwrap().levels.unwrap_or_default()
+    }
+
+    #[test]
+    fn level_accepts_a_comma_separated_list() {
+        let query = EventQuery {
+            level: Some("error, FATAL,".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(levels(query), HashSet::from([Level::Error, Level::Fatal]));
+
+        let query = EventQuery {
+            level: Some("error,critical".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            filter(query),
+            Err(ApiError::InvalidParameter { param: "level", .. })
+        ));
+    }
+
+    #[test]
+    fn min_level_matches_that_level_and_above() {
+        let query = EventQuery {
+            min_level: Some("warning".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            levels(query),
+            HashSet::from([Level::Warning, Level::Error, Level::Fatal])
+        );
+
+        let query = EventQuery {
+            min_level: Some("loud".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            filter(query),
+            Err(ApiError::InvalidParameter {
+                param: "min_level",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn level_and_min_level_cannot_be_combined() {
+        let query = EventQuery {
+            level: Some("error".to_string()),
+            min_level: Some("warning".to_string()),
+            ..Default::default()
+        };
+        let error = filter(query).unwrap_err();
+        assert!(
+            matches!(error, ApiError::BadRequest(ref message) if message.contains("min_level"))
+        );
+    }
+
+    #[test]
+    fn since_after_until_is_rejected() {
+        let query = EventQuery {
+            since: Some("2024-01-02T00:00:00Z".to_string()),
+            until: Some("2024-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(filter(query), Err(ApiError::BadRequest(_))));
+
+        let query = EventQuery {
+            since: Some("2024-01-01T00:00:00Z".to_string()),
+            until: Some("2024-01-01T00:00:00Z".to_string()),
+            ..Default::default()
+        };
+        assert!(filter(query).is_ok());
+    }
+
+    #[test]
+    fn times_that_do_not_parse_are_invalid_parameters() {
+        let query = EventQuery {
+            until: Some("tomorrow".to_string()),
+            ..Default::default()
+        };
+        let error = filter(query).unwrap_err();
+        assert_eq!(error.status(), axum::http::StatusCode::BAD_REQUEST);
+        assert!(matches!(
+            error,
+            ApiError::InvalidParameter { param: "until", ref message } if message.contains("RFC 3339")
+        ));
+    }
+
+    /// A state reading a relay holding, from a publisher tagged `region:eu` and
+    /// `tier:gold`, "billing ledger" (service billing, component ledger) and "shipping
+    /// ledger" (service shipping, component ledger), and from one tagged `region:us`,
+    /// "billing api" (service billing, component api).
+    async fn tagged_events() -> (nostr_relay_builder::MockRelay, crate::AppState) {
+        let relay = nostr_relay_builder::MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let eu = sentrystr::Config::from_keys(&nostr::Keys::generate(), [url.as_str()])
+            .unwrap()
+            .with_nostr_tag("region", "eu")
+            .with_nostr_tag("tier", "gold");
+        let us = sentrystr::Config::from_keys(&nostr::Keys::generate(), [url.as_str()])
+            .unwrap()
+            .with_nostr_tag("region", "us");
+        for (config, message, service, component) in [
+            (eu.clone(), "billing ledger", "billing", "ledger"),
+            (eu, "shipping ledger", "shipping", "ledger"),
+            (us, "billing api", "billing", "api"),
+        ] {
+            sentrystr::NostrSentryClient::new(config)
+                .await
+                .unwrap()
+                .capture_event(
+                    sentrystr::Event::new()
+                        .with_message(message)
+                        .with_tag("service", service)
+                        .with_tag("component", component),
+                )
+                .await
+                .unwrap();
+        }
+
+        let collector = sentrystr_collector::EventCollector::new(vec![url.clone()])
+            .await
+            .unwrap();
+        let state = crate::AppState::with_collector(ApiConfig::new(vec![url]), collector);
+        (relay, state)
+    }
+
+    /// The status of `GET /events?{query}` and the messages of the events it returned.
+    async fn messages(state: &crate::AppState, query: &str) -> (StatusCode, Vec<String>) {
+        use tower::ServiceExt;
+
+        let request = axum::http::Request::get(format!("/events?{}", query))
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = crate::create_app(state.clone())
+            .oneshot(request)
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let page: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let mut messages: Vec<String> = page["events"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|event| event["event"]["message"].as_str().map(str::to_string))
+            .collect();
+        messages.sort();
+        (status, messages)
+    }
+
+    #[tokio::test]
+    async fn repeated_tag_parameters_must_all_match() {
+        let (_relay, state) = tagged_events().await;
+        let ok = |messages: &[&str]| {
+            (
+                StatusCode::OK,
+                messages.iter().map(|message| message.to_string()).collect(),
+            )
+        };
+
+        let cases = [
+            (
+                "xtag=service:billing",
+                ok(&["billing api", "billing ledger"]),
+            ),
+            (
+                "xtag=service:billing&xtag=component:ledger",
+                ok(&["billing ledger"]),
+            ),
+            ("tag=region:eu", ok(&["billing ledger", "shipping ledger"])),
+            (
+                "tag=region:eu&tag=tier:gold",
+                ok(&["billing ledger", "shipping ledger"]),
+            ),
+            ("tag=region:us&tag=tier:gold", ok(&[])),
+            (
+                "tag=region:eu&xtag=service:billing",
+                ok(&["billing ledger"]),
+            ),
+            ("xtag=service", (StatusCode::BAD_REQUEST, Vec::new())),
+            ("tag=:eu", (StatusCode::BAD_REQUEST, Vec::new())),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(messages(&state, query).await, expected, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn search_queries_need_a_minimum_length_and_match_messages() {
+        let (_relay, state) = tagged_events().await;
+        let ok = |messages: &[&str]| {
+            (
+                StatusCode::OK,
+                messages.iter().map(|message| message.to_string()).collect(),
+            )
+        };
+
+        let cases = [
+            ("q=l", (StatusCode::BAD_REQUEST, Vec::new())),
+            ("q=LEDGER", ok(&["billing ledger", "shipping ledger"])),
+            ("q=ing%20led", ok(&["billing ledger", "shipping ledger"])),
+            ("q=refund", ok(&[])),
+            (
+                "q=^bill&q_regex=true",
+                ok(&["billing api", "billing ledger"]),
+            ),
+            ("q=^bill", ok(&[])),
+            ("q=(&q_regex=true", (StatusCode::BAD_REQUEST, Vec::new())),
+            ("q=ledger&xtag=service:shipping", ok(&["shipping ledger"])),
+        ];
+        for (query, expected) in cases {
+            assert_eq!(messages(&state, query).await, expected, "{}", query);
+        }
+
+        let state = crate::AppState::with_collector(
+            state.config.as_ref().clone().with_min_search_len(6),
+            sentrystr_collector::EventCollector::new(state.config.relays.clone())
+                .await
+                .unwrap(),
+        );
+        assert_eq!(messages(&state, "q=ledge").await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            messages(&state, "q=ledger").await,
+            ok(&["billing ledger", "shipping ledger"])
+        );
+    }
 }
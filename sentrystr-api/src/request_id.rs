@@ -0,0 +1,188 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+use tracing::{Instrument, field};
+use uuid::Uuid;
+
+pub const X_REQUEST_ID: &str = "x-request-id";
+
+/// Id of the request being served, available to handlers as a request extension.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Runs each request inside a `request` span carrying `request_id`, `method`, `route`,
+/// `path`, `status` and `latency_ms`, so events emitted while serving it (e.g. through
+/// sentrystr-tracing) can be correlated. The id is taken from an incoming `X-Request-Id`
+/// header when present and always echoed back in the response.
+pub async fn trace_requests(mut request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %request.method(),
+        route = %route,
+        path = %request.uri().path(),
+        status = field::Empty,
+        latency_ms = field::Empty,
+    );
+
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let started = Instant::now();
+    let mut response = next.run(request).instrument(span.clone()).await;
+    let status = response.status();
+
+    span.record("status", status.as_u16());
+    span.record("latency_ms", started.elapsed().as_millis() as u64);
+
+    if status.is_server_error() {
+        span.in_scope(|| {
+            tracing::error!(
+                request_id = %request_id,
+                status = status.as_u16(),
+                "request failed"
+            );
+        });
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(X_REQUEST_ID), value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::{Router, middleware, routing::get};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    type FieldMap = HashMap<String, String>;
+
+    /// Fields of the `request` span and the events emitted, recorded by a test layer.
+    #[derive(Clone, Default)]
+    struct Captured {
+        span: Arc<Mutex<FieldMap>>,
+        events: Arc<Mutex<Vec<(Level, FieldMap)>>>,
+    }
+
+    struct Fields<'a>(&'a mut FieldMap);
+
+    impl Visit for Fields<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for Captured {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            if attrs.metadata().name() == "request" {
+                attrs.record(&mut Fields(&mut self.span.lock().unwrap()));
+            }
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut Fields(&mut self.span.lock().unwrap()));
+        }
+
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut fields = HashMap::new();
+            event.record(&mut Fields(&mut fields));
+            let level = *event.metadata().level();
+            self.events.lock().unwrap().push((level, fields));
+        }
+    }
+
+    async fn serve(uri: &str, request_id: Option<&str>) -> (Response, Captured) {
+        let captured = Captured::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(captured.clone()));
+
+        let app = Router::new()
+            .route("/items/:id", get(|| async { "item" }))
+            .route(
+                "/broken",
+                get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            )
+            .layer(middleware::from_fn(trace_requests));
+        let mut request = axum::http::Request::get(uri);
+        if let Some(request_id) = request_id {
+            request = request.header(X_REQUEST_ID, request_id);
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        (response, captured)
+    }
+
+    #[tokio::test]
+    async fn the_span_carries_the_request_fields_and_the_id_is_echoed() {
+        let (response, captured) = serve("/items/7", Some("abc-123")).await;
+
+        assert_eq!(response.headers()[X_REQUEST_ID], "abc-123");
+        let span = captured.span.lock().unwrap();
+        assert_eq!(span["request_id"], "abc-123");
+        assert_eq!(span["method"], "GET");
+        assert_eq!(span["route"], "/items/:id");
+        assert_eq!(span["path"], "/items/7");
+        assert_eq!(span["status"], "200");
+        assert!(span["latency_ms"].parse::<u64>().is_ok());
+        assert!(captured.events.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_missing_id_is_generated() {
+        let (response, captured) = serve("/items/7", None).await;
+
+        let request_id = response.headers()[X_REQUEST_ID].to_str().unwrap();
+        assert!(Uuid::parse_str(request_id).is_ok());
+        assert_eq!(captured.span.lock().unwrap()["request_id"], request_id);
+    }
+
+    #[tokio::test]
+    async fn server_errors_emit_an_error_event_with_the_id() {
+        let (response, captured) = serve("/broken", Some("abc-123")).await;
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(captured.span.lock().unwrap()["status"], "500");
+        let events = captured.events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let (level, fields) = &events[0];
+        assert_eq!(*level, Level::ERROR);
+        assert_eq!(fields["request_id"], "abc-123");
+        assert_eq!(fields["status"], "500");
+    }
+}
@@ -0,0 +1,686 @@
+use nostr::PublicKey;
+use sentrystr_collector::{EventCollector, EventFilter, RelayStatusInfo};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, watch};
+
+use crate::cache::ResponseCache;
+use crate::filters::FilterStore;
+use crate::grafana::ServiceDiscovery;
+use crate::limits::KeyedRateLimiter;
+use crate::metrics::Metrics;
+use crate::warm::{WarmCache, WarmCacheConfig};
+use crate::webhooks::WebhookRegistry;
+use crate::{ApiError, Result};
+
+/// How often startup polls the relay status while waiting for a relay to connect.
+const STARTUP_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Server-wide settings for the API.
+#[derive(Debug, Clone)]
+pub struct ApiConfig {
+    pub relays: Vec<String>,
+    pub cache_ttl: Duration,
+    pub cache_max_entries: usize,
+    pub min_search_len: usize,
+    pub nip50_search: bool,
+    pub relay_status_refresh: Duration,
+    pub enable_docs: bool,
+    /// When set, `/metrics` is served on this address instead of the main listener.
+    pub metrics_addr: Option<SocketAddr>,
+    /// Serve `/metrics` on the main listener without the bearer token. When unset it
+    /// requires [`ApiConfig::auth_token`], like endpoints that change state.
+    pub public_metrics: bool,
+    pub compression: bool,
+    /// Responses smaller than this many bytes are sent uncompressed.
+    pub compression_min_size: u16,
+    /// Longest time a handler may take to start responding before a 504 is returned.
+    pub request_timeout: Duration,
+    /// Largest accepted request body in bytes; larger bodies get a 413.
+    pub max_body_bytes: usize,
+    /// Bearer token required by endpoints that change server state, such as saving
+    /// filters. Those endpoints are disabled when unset.
+    pub auth_token: Option<String>,
+    /// JSON file saved filters are persisted to.
+    pub filters_file: Option<PathBuf>,
+    /// Accept the `relays` query parameter on `/events`, which queries a caller-chosen
+    /// set of relays instead of the shared pool.
+    pub allow_relay_override: bool,
+    /// Most events `/events/count` scans on the relays before reporting an inexact count.
+    pub count_scan_limit: usize,
+    /// How long after startup `/ready` reports `starting` instead of `unavailable`.
+    pub readiness_grace: Duration,
+    /// Keeps recent events in memory and answers `/events` from them when set.
+    pub warm_cache: Option<WarmCacheConfig>,
+    /// Most buckets a single `/events/timeseries` response may contain.
+    pub timeseries_max_points: usize,
+    /// Minimum time between `POST /alerts/test` calls with the same token.
+    pub alert_test_interval: Duration,
+    /// When set, only events published by these authors are ever returned.
+    pub visible_authors: Option<HashSet<PublicKey>>,
+    /// Reject queries for authors outside [`ApiConfig::visible_authors`] with a 403
+    /// instead of returning no events.
+    pub strict_authors: bool,
+    /// Keys of the shared collector, which decrypts events encrypted for them; generated
+    /// when unset.
+    pub keys: Option<nostr::Keys>,
+}
+
+impl ApiConfig {
+    pub fn new(relays: Vec<String>) -> Self {
+        Self {
+            relays,
+            cache_ttl: Duration::from_secs(15),
+            cache_max_entries: 1000,
+            min_search_len: 2,
+            nip50_search: false,
+            relay_status_refresh: Duration::from_secs(10),
+            enable_docs: false,
+            metrics_addr: None,
+            public_metrics: false,
+            compression: true,
+            compression_min_size: 1024,
+            request_timeout: Duration::from_secs(30),
+            max_body_bytes: 1024 * 1024,
+            auth_token: None,
+            filters_file: None,
+            allow_relay_override: false,
+            count_scan_limit: 5000,
+            readiness_grace: Duration::from_secs(30),
+            warm_cache: None,
+            timeseries_max_points: 1000,
+            alert_test_interval: Duration::from_secs(60),
+            visible_authors: None,
+            strict_authors: false,
+            keys: None,
+        }
+    }
+
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_cache_max_entries(mut self, max_entries: usize) -> Self {
+        self.cache_max_entries = max_entries;
+        self
+    }
+
+    pub fn with_min_search_len(mut self, min_search_len: usize) -> Self {
+        self.min_search_len = min_search_len;
+        self
+    }
+
+    /// Also forwards plain-text `q` searches to relays as NIP-50 search filters.
+    pub fn with_nip50_search(mut self, enabled: bool) -> Self {
+        self.nip50_search = enabled;
+        self
+    }
+
+    /// How often the cached relay status served by `/health` is refreshed. A zero
+    /// interval turns the background refresh off; the status is then only refreshed on
+    /// startup and when the relays change.
+    pub fn with_relay_status_refresh(mut self, interval: Duration) -> Self {
+        self.relay_status_refresh = interval;
+        self
+    }
+
+    /// Serves Swagger UI at `/docs`.
+    pub fn with_docs(mut self, enabled: bool) -> Self {
+        self.enable_docs = enabled;
+        self
+    }
+
+    /// Moves `/metrics` to a separate listener, keeping it off the public address.
+    pub fn with_metrics_addr(mut self, addr: SocketAddr) -> Self {
+        self.metrics_addr = Some(addr);
+        self
+    }
+
+    /// Serves `/metrics` on the main listener to anyone, for scrapers that cannot send
+    /// a bearer token. Metrics on [`ApiConfig::metrics_addr`] never need the token.
+    pub fn with_public_metrics(mut self, enabled: bool) -> Self {
+        self.public_metrics = enabled;
+        self
+    }
+
+    /// Enables gzip/brotli compression of responses larger than `min_size` bytes.
+    pub fn with_compression(mut self, enabled: bool, min_size: u16) -> Self {
+        self.compression = enabled;
+        self.compression_min_size = min_size;
+        self
+    }
+
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    /// Collects with `keys`, e.g. read with `sentrystr::read_key_file`.
+    pub fn with_keys(mut self, keys: nostr::Keys) -> Self {
+        self.keys = Some(keys);
+        self
+    }
+
+    pub fn with_filters_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.filters_file = Some(path.into());
+        self
+    }
+
+    pub fn with_relay_override(mut self, enabled: bool) -> Self {
+        self.allow_relay_override = enabled;
+        self
+    }
+
+    pub fn with_count_scan_limit(mut self, limit: usize) -> Self {
+        self.count_scan_limit = limit;
+        self
+    }
+
+    pub fn with_readiness_grace(mut self, grace: Duration) -> Self {
+        self.readiness_grace = grace;
+        self
+    }
+
+    /// Serves `/events` queries whose `since` falls inside the warm window from memory
+    /// instead of the relays.
+    pub fn with_warm_cache(mut self, config: WarmCacheConfig) -> Self {
+        self.warm_cache = Some(config);
+        self
+    }
+
+    pub fn with_timeseries_max_points(mut self, max_points: usize) -> Self {
+        self.timeseries_max_points = max_points;
+        self
+    }
+
+    pub fn with_alert_test_interval(mut self, interval: Duration) -> Self {
+        self.alert_test_interval = interval;
+        self
+    }
+
+    /// Restricts every query to events published by `authors`. With `strict`, asking for
+    /// any other author is a 403; otherwise those authors simply match nothing.
+    pub fn with_visible_authors(
+        mut self,
+        authors: impl IntoIterator<Item = PublicKey>,
+        strict: bool,
+    ) -> Self {
+        self.visible_authors = Some(authors.into_iter().collect());
+        self.strict_authors = strict;
+        self
+    }
+
+    /// Scopes `filter` to [`ApiConfig::visible_authors`]. Every filter built from a
+    /// request passes through here before reaching the collector, so no parameter
+    /// combination can widen it.
+    pub fn restrict_authors(&self, mut filter: EventFilter) -> Result<EventFilter> {
+        let Some(ref visible) = self.visible_authors else {
+            return Ok(filter);
+        };
+
+        filter.authors = Some(match filter.authors.take() {
+            None => visible.clone(),
+            Some(requested) => {
+                if self.strict_authors && !requested.is_subset(visible) {
+                    return Err(ApiError::Forbidden(
+                        "Events from this author are not exposed by this server".to_string(),
+                    ));
+                }
+                requested.intersection(visible).copied().collect()
+            }
+        });
+
+        Ok(filter)
+    }
+}
+
+/// Shared state handed to every handler: one long-lived collector connected to the
+/// configured relays plus the response cache in front of it.
+#[derive(Clone)]
+pub struct AppState {
+    pub config: Arc<ApiConfig>,
+    pub collector: Arc<EventCollector>,
+    pub cache: Arc<ResponseCache>,
+    pub metrics: Arc<Metrics>,
+    pub filters: Arc<FilterStore>,
+    pub webhooks: Arc<WebhookRegistry>,
+    pub warm_cache: Option<Arc<WarmCache>>,
+    pub alert_test_limiter: Arc<KeyedRateLimiter>,
+    pub grafana_services: Arc<ServiceDiscovery>,
+    relays: Arc<RwLock<Vec<String>>>,
+    relay_status: Arc<RwLock<Vec<RelayStatusInfo>>>,
+    shutdown: Arc<watch::Sender<bool>>,
+    started_at: Instant,
+    setup_complete: Arc<AtomicBool>,
+}
+
+impl AppState {
+    /// Connects the shared collector to the configured relays and starts the
+    /// background relay status refresh.
+    pub async fn new(config: ApiConfig) -> Result<Self> {
+        let collector = match config.keys {
+            Some(ref keys) => {
+                let core_config = sentrystr::Config::from_keys(keys, config.relays.clone())
+                    .map_err(sentrystr_collector::CollectorError::from)?;
+                EventCollector::from_config(&core_config).await
+            }
+            None => EventCollector::new(config.relays.clone()).await,
+        }
+        .map_err(ApiError::from)?;
+
+        let state = Self::with_collector(config, collector);
+        state.filters.load().await?;
+        if let Some(ref warm_cache) = state.warm_cache {
+            warm_cache.spawn(Arc::clone(&state.collector));
+        }
+        let setup = state.clone();
+        tokio::spawn(async move { setup.finish_setup().await });
+
+        Ok(state)
+    }
+
+    /// Completes startup in the background, while `/ready` already answers `starting`:
+    /// polls the relay status until a relay connects and the warm cache is filled, for at
+    /// most [`ApiConfig::readiness_grace`], then marks setup complete.
+    async fn finish_setup(&self) {
+        let deadline = self.started_at + self.config.readiness_grace;
+        loop {
+            self.refresh_relay_status().await;
+            let connected = self
+                .relay_status
+                .read()
+                .await
+                .iter()
+                .any(|relay| relay.connected);
+            let warm = self
+                .warm_cache
+                .as_ref()
+                .is_none_or(|warm_cache| warm_cache.is_ready());
+            if (connected && warm) || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(STARTUP_POLL_INTERVAL).await;
+        }
+        self.mark_setup_complete();
+    }
+
+    /// Builds the state around an already connected `collector`, starting the background
+    /// relay status refresh when called inside a Tokio runtime.
+    pub fn with_collector(config: ApiConfig, collector: EventCollector) -> Self {
+        let cache = ResponseCache::new(config.cache_ttl, config.cache_max_entries);
+        let relays = config.relays.clone();
+        let alert_test_interval = config.alert_test_interval;
+        let metrics = Metrics::new(collector.stats());
+        let filters = FilterStore::new(config.filters_file.clone());
+        let collector = Arc::new(collector);
+        let webhooks = WebhookRegistry::new(Arc::clone(&collector));
+        let warm_cache = config
+            .warm_cache
+            .clone()
+            .map(|warm| Arc::new(WarmCache::new(warm)));
+
+        let state = Self {
+            config: Arc::new(config),
+            collector,
+            cache: Arc::new(cache),
+            metrics: Arc::new(metrics),
+            filters: Arc::new(filters),
+            webhooks: Arc::new(webhooks),
+            warm_cache,
+            alert_test_limiter: Arc::new(KeyedRateLimiter::new(alert_test_interval)),
+            grafana_services: Arc::new(ServiceDiscovery::default()),
+            relays: Arc::new(RwLock::new(relays)),
+            relay_status: Arc::new(RwLock::new(Vec::new())),
+            shutdown: Arc::new(watch::channel(false).0),
+            started_at: Instant::now(),
+            setup_complete: Arc::new(AtomicBool::new(false)),
+        };
+        state.spawn_relay_status_refresh();
+        state
+    }
+
+    /// Marks the initial collector setup as done, allowing `/ready` to report ready.
+    /// [`AppState::new`] calls this once a relay connected, or the readiness grace period
+    /// passed; states built with [`AppState::with_collector`] must call it themselves once
+    /// they are prepared to serve.
+    pub fn mark_setup_complete(&self) {
+        self.setup_complete.store(true, Ordering::Release);
+    }
+
+    pub fn setup_complete(&self) -> bool {
+        self.setup_complete.load(Ordering::Acquire)
+    }
+
+    /// Whether the server is still within [`ApiConfig::readiness_grace`] of starting.
+    pub fn in_readiness_grace(&self) -> bool {
+        self.started_at.elapsed() < self.config.readiness_grace
+    }
+
+    /// Last relay status snapshot, as refreshed in the background. Reading this never
+    /// touches the network, which keeps health checks fast.
+    pub async fn relay_status(&self) -> Vec<RelayStatusInfo> {
+        self.relay_status.read().await.clone()
+    }
+
+    pub async fn refresh_relay_status(&self) {
+        let status = self.collector.relay_status().await;
+        *self.relay_status.write().await = status;
+    }
+
+    /// Refreshes the relay status every [`ApiConfig::relay_status_refresh`] until the
+    /// state is dropped. Does nothing for a zero interval or outside a Tokio runtime.
+    fn spawn_relay_status_refresh(&self) {
+        let period = self.config.relay_status_refresh;
+        if period.is_zero() {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let collector = Arc::downgrade(&self.collector);
+        let relay_status = Arc::downgrade(&self.relay_status);
+
+        runtime.spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                let (Some(collector), Some(relay_status)) =
+                    (collector.upgrade(), relay_status.upgrade())
+                else {
+                    return;
+                };
+                let status = collector.relay_status().await;
+                *relay_status.write().await = status;
+            }
+        });
+    }
+
+    /// Tells long-lived responses (streams) that the server is draining so they can
+    /// finish with a final frame instead of being reset.
+    pub fn begin_shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    /// Receiver that flips to `true` once [`AppState::begin_shutdown`] is called.
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    /// Disconnects the shared collector from its relays. Called after the server drained.
+    pub async fn close(&self) -> Result<()> {
+        self.collector.disconnect().await.map_err(ApiError::from)
+    }
+
+    pub async fn relays(&self) -> Vec<String> {
+        self.relays.read().await.clone()
+    }
+
+    /// Replaces the relay set of the shared collector and invalidates cached responses,
+    /// since they were computed against the previous relays.
+    pub async fn set_relays(&self, relays: Vec<String>) -> Result<()> {
+        let mut current = self.relays.write().await;
+
+        for relay in current.iter().filter(|r| !relays.contains(r)) {
+            self.collector
+                .remove_relay(relay)
+                .await
+                .map_err(ApiError::from)?;
+        }
+
+        for relay in relays.iter().filter(|r| !current.contains(r)) {
+            self.collector
+                .add_relay(relay)
+                .await
+                .map_err(ApiError::from)?;
+        }
+
+        *current = relays;
+        drop(current);
+
+        self.cache.invalidate();
+        self.refresh_relay_status().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn collector() -> EventCollector {
+        EventCollector::new(vec!["wss://relay.example.com".to_string()])
+            .await
+            .unwrap()
+    }
+
+    fn config(refresh: Duration) -> ApiConfig {
+        ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+            .with_relay_status_refresh(refresh)
+    }
+
+    #[tokio::test]
+    async fn states_built_from_a_collector_refresh_relay_status() {
+        let state = AppState::with_collector(config(Duration::from_millis(20)), collector().await);
+        assert!(state.relay_status().await.is_empty());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(state.relay_status().await.len(), 1);
+    }
+
+    #[test]
+    fn lenient_authors_outside_the_allowlist_match_nothing() {
+        let visible = nostr::Keys::generate().public_key();
+        let stranger = nostr::Keys::generate().public_key();
+        let config = ApiConfig::new(vec!["wss://relay.example.com".to_string()])
+            .with_visible_authors([visible], false);
+
+        let scoped = config.restrict_authors(EventFilter::new()).unwrap();
+        assert_eq!(scoped.authors, Some(HashSet::from([visible])));
+        assert!(!scoped.matches_nothing());
+
+        let requested = config
+            .restrict_authors(EventFilter::new().with_author(stranger))
+            .unwrap();
+        assert!(requested.matches_nothing());
+
+        let strict = config.with_visible_authors([visible], true);
+        assert!(matches!(
+            strict.restrict_authors(EventFilter::new().with_author(stranger)),
+            Err(ApiError::Forbidden(_))
+        ));
+    }
+
+    fn relay(url: &str, connected: bool) -> RelayStatusInfo {
+        RelayStatusInfo {
+            url: url.to_string(),
+            connected,
+            status: if connected {
+                "Connected"
+            } else {
+                "Disconnected"
+            }
+            .to_string(),
+            connected_since: None,
+            last_event_at: None,
+        }
+    }
+
+    /// Status code and body of `uri` with `relays` as the cached relay status.
+    async fn health(relays: Vec<RelayStatusInfo>, uri: &str) -> (u16, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let config = config(Duration::ZERO).with_readiness_grace(Duration::ZERO);
+        let state = AppState::with_collector(config, collector().await);
+        state.mark_setup_complete();
+        *state.relay_status.write().await = relays;
+
+        let request = axum::http::Request::get(uri)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = crate::create_app(state).oneshot(request).await.unwrap();
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn readiness_is_unavailable_when_every_relay_is_down() {
+        let down = || {
+            vec![
+                relay("wss://a.example.com", false),
+                relay("wss://b.example.com", false),
+            ]
+        };
+
+        let (status, body) = health(down(), "/ready?verbose=true").await;
+        assert_eq!(status, 503);
+        assert_eq!(body["status"], "unavailable");
+        assert_eq!(body["degraded"], true);
+        assert_eq!(body["relays"][0]["error"], "Relay is Disconnected");
+        assert_eq!(body["relays"][1]["connected"], false);
+
+        let (status, body) = health(down(), "/ready").await;
+        assert_eq!(status, 503);
+        assert!(body["relays"][0].get("error").is_none());
+
+        let (status, body) = health(down(), "/health").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["degraded"], true);
+    }
+
+    #[tokio::test]
+    async fn readiness_is_degraded_while_some_relay_is_up() {
+        let (status, body) = health(
+            vec![
+                relay("wss://a.example.com", false),
+                relay("wss://b.example.com", true),
+            ],
+            "/ready",
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "degraded");
+
+        let (status, body) = health(vec![relay("wss://a.example.com", true)], "/ready").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["degraded"], false);
+    }
+
+    #[tokio::test]
+    async fn zero_refresh_interval_does_not_refresh() {
+        let state = AppState::with_collector(config(Duration::ZERO), collector().await);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(state.relay_status().await.is_empty());
+    }
+
+    #[test]
+    fn requested_authors_are_intersected_with_the_visible_ones() {
+        let [first, second, stranger] =
+            std::array::from_fn(|_| nostr::Keys::generate().public_key());
+        let config = ApiConfig::new(vec!["wss://relay.example.com".to_string()]);
+        let requested = || EventFilter::new().with_author(second).with_author(stranger);
+
+        let unrestricted = config.restrict_authors(requested()).unwrap();
+        assert_eq!(
+            unrestricted.authors,
+            Some(HashSet::from([second, stranger]))
+        );
+
+        let lenient = config.clone().with_visible_authors([first, second], false);
+        let scoped = lenient.restrict_authors(requested()).unwrap();
+        assert_eq!(scoped.authors, Some(HashSet::from([second])));
+        let scoped = lenient
+            .restrict_authors(EventFilter::new().with_author(first).with_author(second))
+            .unwrap();
+        assert_eq!(scoped.authors, Some(HashSet::from([first, second])));
+
+        let strict = config.with_visible_authors([first, second], true);
+        assert!(
+            strict
+                .restrict_authors(EventFilter::new().with_author(first))
+                .is_ok()
+        );
+        assert!(matches!(
+            strict.restrict_authors(requested()),
+            Err(ApiError::Forbidden(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn authors_are_summarized_within_the_visible_ones() {
+        use tower::ServiceExt;
+
+        let relay = nostr_relay_builder::MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let keys: [nostr::Keys; 3] = std::array::from_fn(|_| nostr::Keys::generate());
+        for (author, level) in [
+            (&keys[0], sentrystr::Level::Error),
+            (&keys[0], sentrystr::Level::Warning),
+            (&keys[1], sentrystr::Level::Info),
+            (&keys[2], sentrystr::Level::Fatal),
+        ] {
+            let config = sentrystr::Config::from_keys(author, [url.as_str()]).unwrap();
+            sentrystr::NostrSentryClient::new(config)
+                .await
+                .unwrap()
+                .capture_message(level, "tick")
+                .await
+                .unwrap();
+        }
+        let config = ApiConfig::new(vec![url.clone()])
+            .with_visible_authors([keys[0].public_key(), keys[1].public_key()], false);
+        let state = AppState::with_collector(config, EventCollector::new(vec![url]).await.unwrap());
+
+        let request = axum::http::Request::get("/authors")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = crate::create_app(state).oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let authors: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+
+        let mut summaries: Vec<(String, u64, Vec<String>)> = authors
+            .iter()
+            .map(|author| {
+                (
+                    author["pubkey_hex"].as_str().unwrap().to_string(),
+                    author["event_count"].as_u64().unwrap(),
+                    serde_json::from_value(author["levels_seen"].clone()).unwrap(),
+                )
+            })
+            .collect();
+        summaries.sort();
+        let mut expected = vec![
+            (
+                keys[0].public_key().to_hex(),
+                2,
+                vec!["error".to_string(), "warning".to_string()],
+            ),
+            (keys[1].public_key().to_hex(), 1, vec!["info".to_string()]),
+        ];
+        expected.sort();
+        assert_eq!(summaries, expected);
+    }
+}
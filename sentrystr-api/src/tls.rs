@@ -0,0 +1,164 @@
+use axum_server::tls_rustls::RustlsConfig;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Loads a PEM certificate chain and private key. Parse errors surface here so the
+/// server refuses to start with a broken certificate instead of failing handshakes.
+pub async fn load_config(cert: &Path, key: &Path) -> std::io::Result<RustlsConfig> {
+    install_crypto_provider();
+    RustlsConfig::from_pem_file(cert, key).await.map_err(|e| {
+        std::io::Error::new(
+            e.kind(),
+            format!(
+                "Failed to load TLS certificate {} / key {}: {}",
+                cert.display(),
+                key.display(),
+                e
+            ),
+        )
+    })
+}
+
+/// Makes ring the process-wide rustls provider unless one is installed already. Other
+/// dependencies enable aws-lc-rs as well, and with both rustls cannot pick one itself.
+fn install_crypto_provider() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+}
+
+/// Re-reads the certificate and key every `interval` so renewed certificates are picked
+/// up without a restart. A failed reload keeps serving the previous certificate. Fails
+/// on a zero `interval`.
+pub fn spawn_reload(
+    config: RustlsConfig,
+    cert: PathBuf,
+    key: PathBuf,
+    interval: Duration,
+) -> std::io::Result<()> {
+    if interval.is_zero() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "The TLS reload interval must be more than 0",
+        ));
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+
+        loop {
+            ticker.tick().await;
+            if let Err(e) = config.reload_from_pem_file(&cert, &key).await {
+                eprintln!("Failed to reload TLS certificate: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, routing::get};
+    use std::net::SocketAddr;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    /// Serves a `/health` route over TLS with `config` on a free local port.
+    async fn serve(config: RustlsConfig) -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/health", get(|| async { "ok" }));
+        tokio::spawn(axum_server::from_tcp_rustls(listener, config).serve(app.into_make_service()));
+        addr
+    }
+
+    /// Whether a client trusting only the certificate `trusted` completes a request.
+    async fn handshake(addr: SocketAddr, trusted: &str) -> bool {
+        let certificate = std::fs::read(fixture(trusted)).unwrap();
+        let client = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(&certificate).unwrap())
+            .resolve("localhost", addr)
+            .build()
+            .unwrap();
+        let url = format!("https://localhost:{}/health", addr.port());
+        match client.get(url).send().await {
+            Ok(response) => response.text().await.unwrap() == "ok",
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn self_signed_certificates_are_served() {
+        let config = load_config(&fixture("first.crt"), &fixture("first.key"))
+            .await
+            .unwrap();
+        let addr = serve(config).await;
+
+        assert!(handshake(addr, "first.crt").await);
+        assert!(!handshake(addr, "second.crt").await);
+    }
+
+    #[tokio::test]
+    async fn broken_certificates_are_rejected_at_load() {
+        let error = load_config(&fixture("first.crt"), &fixture("second.crt"))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("first.crt"));
+        assert!(
+            load_config(&fixture("missing.crt"), &fixture("first.key"))
+                .await
+                .is_err()
+        );
+        assert!(
+            spawn_reload(
+                load_config(&fixture("first.crt"), &fixture("first.key"))
+                    .await
+                    .unwrap(),
+                fixture("first.crt"),
+                fixture("first.key"),
+                Duration::ZERO,
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn reloading_picks_up_a_renewed_certificate() {
+        let dir = std::env::temp_dir().join(format!("sentrystr-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cert, key) = (dir.join("server.crt"), dir.join("server.key"));
+        std::fs::copy(fixture("first.crt"), &cert).unwrap();
+        std::fs::copy(fixture("first.key"), &key).unwrap();
+
+        let config = load_config(&cert, &key).await.unwrap();
+        spawn_reload(
+            config.clone(),
+            cert.clone(),
+            key.clone(),
+            Duration::from_millis(50),
+        )
+        .unwrap();
+        let addr = serve(config).await;
+        assert!(handshake(addr, "first.crt").await);
+
+        std::fs::copy(fixture("second.crt"), &cert).unwrap();
+        std::fs::copy(fixture("second.key"), &key).unwrap();
+        let mut renewed = false;
+        for _ in 0..50 {
+            if handshake(addr, "second.crt").await {
+                renewed = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(renewed);
+        assert!(!handshake(addr, "first.crt").await);
+    }
+}
@@ -0,0 +1,391 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use sentrystr_collector::{CollectedEvent, EventCollector, EventFilter, EventPosition, SortOrder};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Bounds and timing of the warm cache, see [`ApiConfig::with_warm_cache`].
+///
+/// [`ApiConfig::with_warm_cache`]: crate::ApiConfig::with_warm_cache
+#[derive(Debug, Clone)]
+pub struct WarmCacheConfig {
+    /// How far back events are kept.
+    pub window: Duration,
+    /// How often expired events are evicted and recent events re-fetched to fill gaps
+    /// left by the live subscription.
+    pub refresh: Duration,
+    pub max_events: usize,
+    /// Approximate upper bound on the memory held, measured as serialized event size.
+    pub max_bytes: usize,
+}
+
+impl Default for WarmCacheConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(3600),
+            refresh: Duration::from_secs(30),
+            max_events: 50_000,
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+struct WarmEntry {
+    event: CollectedEvent,
+    size: usize,
+}
+
+#[derive(Default)]
+struct WarmEvents {
+    events: BTreeMap<EventPosition, WarmEntry>,
+    bytes: usize,
+    /// Every event with a timestamp at or after this instant is held. `None` until the
+    /// backfill completed.
+    complete_since: Option<DateTime<Utc>>,
+}
+
+/// The last [`WarmCacheConfig::window`] of events kept in memory, so that `/events`
+/// queries inside the window are answered without a relay round trip.
+pub struct WarmCache {
+    config: WarmCacheConfig,
+    inner: RwLock<WarmEvents>,
+    ready: AtomicBool,
+}
+
+impl WarmCache {
+    pub fn new(config: WarmCacheConfig) -> Self {
+        Self {
+            config,
+            inner: RwLock::new(WarmEvents::default()),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.inner.read().await.events.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Answers `filter` from memory, or returns `None` when its window is not fully
+    /// covered (no `since`, or `since` older than the events held) and the relays must
+    /// be asked instead.
+    pub async fn query(&self, filter: &EventFilter) -> Option<Vec<CollectedEvent>> {
+        let inner = self.inner.read().await;
+        if !self.covers(&inner, filter) {
+            return None;
+        }
+
+        let matching = inner
+            .events
+            .values()
+            .filter(|entry| filter.matches_collected(&entry.event))
+            .map(|entry| &entry.event);
+        let limit = filter.limit.unwrap_or(usize::MAX);
+
+        let events = match filter.sort {
+            SortOrder::Asc => matching.take(limit).cloned().collect(),
+            SortOrder::Desc => matching.rev().take(limit).cloned().collect(),
+        };
+        Some(events)
+    }
+
+    /// Number of events matching `filter`, ignoring its limit, or `None` when its window
+    /// is not fully covered, as for [`Self::query`].
+    pub async fn count(&self, filter: &EventFilter) -> Option<usize> {
+        let inner = self.inner.read().await;
+        if !self.covers(&inner, filter) {
+            return None;
+        }
+
+        Some(
+            inner
+                .events
+                .values()
+                .filter(|entry| filter.matches_collected(&entry.event))
+                .count(),
+        )
+    }
+
+    /// Whether every event `filter` could match is held.
+    fn covers(&self, inner: &WarmEvents, filter: &EventFilter) -> bool {
+        let Some(since) = filter.since else {
+            return false;
+        };
+        self.is_ready()
+            && inner
+                .complete_since
+                .is_some_and(|complete| since >= complete)
+    }
+
+    /// Backfills the window, then keeps it current from a live subscription and evicts
+    /// expired events every [`WarmCacheConfig::refresh`].
+    pub fn spawn(self: &Arc<Self>, collector: Arc<EventCollector>) {
+        let cache = Arc::clone(self);
+
+        tokio::spawn(async move {
+            let started = Utc::now();
+            let live = collector
+                .subscribe_to_events(EventFilter::new().with_since(started))
+                .await;
+            let mut live = match live {
+                Ok(live) => live,
+                Err(e) => {
+                    eprintln!("Warm cache disabled, live subscription failed: {}", e);
+                    return;
+                }
+            };
+
+            cache.backfill(&collector, started).await;
+
+            let mut refresh = tokio::time::interval(cache.config.refresh);
+            refresh.tick().await;
+            let mut last_refresh = started;
+
+            loop {
+                tokio::select! {
+                    event = live.recv() => {
+                        let Some(event) = event else {
+                            eprintln!("Warm cache live subscription ended");
+                            cache.ready.store(false, Ordering::Release);
+                            return;
+                        };
+                        cache.insert(event).await;
+                        cache.enforce_bounds().await;
+                    }
+                    _ = refresh.tick() => {
+                        let now = Utc::now();
+                        cache.refresh(&collector, last_refresh).await;
+                        last_refresh = now;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Loads the whole window and marks the cache ready on success.
+    async fn backfill(&self, collector: &EventCollector, now: DateTime<Utc>) {
+        let window_start = self.window_start(now);
+
+        match self.fetch_since(collector, window_start).await {
+            Ok(()) => {
+                self.inner
+                    .write()
+                    .await
+                    .complete_since
+                    .get_or_insert(window_start);
+                self.enforce_bounds().await;
+                self.ready.store(true, Ordering::Release);
+            }
+            Err(e) => eprintln!("Warm cache backfill failed: {}", e),
+        }
+    }
+
+    /// Re-fetches events since the previous refresh, catching any the live subscription
+    /// missed, or retries the backfill if it has not succeeded yet.
+    async fn refresh(&self, collector: &EventCollector, last_refresh: DateTime<Utc>) {
+        if !self.is_ready() {
+            self.backfill(collector, Utc::now()).await;
+            return;
+        }
+
+        let overlap = TimeDelta::from_std(self.config.refresh).unwrap_or_default();
+        if let Err(e) = self.fetch_since(collector, last_refresh - overlap).await {
+            eprintln!("Warm cache refresh failed: {}", e);
+        }
+        self.enforce_bounds().await;
+    }
+
+    async fn fetch_since(
+        &self,
+        collector: &EventCollector,
+        since: DateTime<Utc>,
+    ) -> sentrystr_collector::Result<()> {
+        let filter = EventFilter::new()
+            .with_since(since)
+            .with_limit(self.config.max_events);
+        let events = collector.collect_events(filter).await?;
+
+        // A full page means older events in the window were cut off by the limit.
+        if events.len() >= self.config.max_events
+            && let Some(oldest) = events.last()
+        {
+            self.raise_complete_since(oldest.event.timestamp + TimeDelta::microseconds(1))
+                .await;
+        }
+
+        for event in events {
+            self.insert(event).await;
+        }
+        Ok(())
+    }
+
+    async fn insert(&self, event: CollectedEvent) {
+        let size = serde_json::to_vec(&event.event)
+            .map(|body| body.len())
+            .unwrap_or_default();
+        let position = event.position();
+
+        let mut inner = self.inner.write().await;
+        if !inner.events.contains_key(&position) {
+            inner.bytes += size;
+            inner.events.insert(position, WarmEntry { event, size });
+        }
+    }
+
+    /// Evicts events older than the window, then the oldest events until both the count
+    /// and byte bounds hold. Coverage shrinks with every eviction so that queries
+    /// reaching into evicted time fall back to the relays.
+    async fn enforce_bounds(&self) {
+        let window_start = self.window_start(Utc::now());
+        let mut inner = self.inner.write().await;
+
+        if inner.complete_since.is_some() {
+            inner.complete_since = inner.complete_since.max(Some(window_start));
+        }
+
+        while let Some(oldest) = inner
+            .events
+            .first_key_value()
+            .map(|(position, _)| position.timestamp)
+        {
+            let expired = oldest < window_start;
+            let over_bounds =
+                inner.events.len() > self.config.max_events || inner.bytes > self.config.max_bytes;
+            if !expired && !over_bounds {
+                break;
+            }
+
+            if let Some((_, entry)) = inner.events.pop_first() {
+                inner.bytes -= entry.size;
+            }
+            if !expired {
+                let evicted_until = oldest + TimeDelta::microseconds(1);
+                inner.complete_since = inner.complete_since.max(Some(evicted_until));
+            }
+        }
+    }
+
+    async fn raise_complete_since(&self, since: DateTime<Utc>) {
+        let mut inner = self.inner.write().await;
+        inner.complete_since = inner.complete_since.max(Some(since));
+    }
+
+    fn window_start(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        TimeDelta::from_std(self.config.window)
+            .ok()
+            .and_then(|window| now.checked_sub_signed(window))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventId, Keys, Timestamp};
+    use sentrystr::Event;
+
+    fn collected(id: u8, timestamp: DateTime<Utc>) -> CollectedEvent {
+        CollectedEvent {
+            event: Event::new()
+                .with_message(format!("event {}", id))
+                .with_timestamp(timestamp),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::from_byte_array([id; 32]),
+            created_at: Timestamp::from(timestamp.timestamp() as u64),
+            received_at: Utc::now(),
+            nostr_tags: Vec::new(),
+            seq: None,
+        }
+    }
+
+    /// A cache holding events `seconds` ago, backfilled since `window` ago.
+    async fn filled(config: WarmCacheConfig, seconds: &[i64]) -> (WarmCache, DateTime<Utc>) {
+        let now = Utc::now();
+        let cache = WarmCache::new(config);
+        for (id, ago) in seconds.iter().enumerate() {
+            cache
+                .insert(collected(id as u8, now - TimeDelta::seconds(*ago)))
+                .await;
+        }
+        cache.inner.write().await.complete_since = Some(cache.window_start(now));
+        cache.ready.store(true, Ordering::Release);
+        cache.enforce_bounds().await;
+        (cache, now)
+    }
+
+    fn since(now: DateTime<Utc>, seconds: i64) -> EventFilter {
+        EventFilter::new().with_since(now - TimeDelta::seconds(seconds))
+    }
+
+    #[tokio::test]
+    async fn queries_outside_the_covered_window_fall_back_to_the_relays() {
+        let (cache, now) = filled(WarmCacheConfig::default(), &[30, 20, 10]).await;
+
+        assert!(cache.query(&EventFilter::new()).await.is_none());
+        assert!(cache.count(&EventFilter::new()).await.is_none());
+        assert!(cache.query(&since(now, 7200)).await.is_none());
+        assert_eq!(cache.query(&since(now, 25)).await.unwrap().len(), 2);
+        assert_eq!(cache.count(&since(now, 3500)).await, Some(3));
+
+        cache.ready.store(false, Ordering::Release);
+        assert!(cache.query(&since(now, 25)).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn oldest_events_are_evicted_beyond_the_event_bound() {
+        let config = WarmCacheConfig {
+            max_events: 2,
+            ..WarmCacheConfig::default()
+        };
+        let (cache, now) = filled(config, &[30, 20, 10]).await;
+
+        assert_eq!(cache.len().await, 2);
+        assert!(cache.query(&since(now, 30)).await.is_none());
+        let events = cache.query(&since(now, 20)).await.unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(
+            events
+                .iter()
+                .all(|event| event.event.message.as_deref() != Some("event 0"))
+        );
+    }
+
+    #[tokio::test]
+    async fn oldest_events_are_evicted_beyond_the_byte_bound() {
+        let size = serde_json::to_vec(&collected(0, Utc::now()).event)
+            .unwrap()
+            .len();
+        let config = WarmCacheConfig {
+            max_bytes: size * 2,
+            ..WarmCacheConfig::default()
+        };
+        let (cache, now) = filled(config, &[30, 20, 10]).await;
+
+        assert_eq!(cache.len().await, 2);
+        assert!(cache.inner.read().await.bytes <= size * 2);
+        assert!(cache.count(&since(now, 30)).await.is_none());
+        assert_eq!(cache.count(&since(now, 20)).await, Some(2));
+    }
+
+    #[tokio::test]
+    async fn expired_events_are_evicted_without_shrinking_the_window() {
+        let config = WarmCacheConfig {
+            window: Duration::from_secs(60),
+            ..WarmCacheConfig::default()
+        };
+        let (cache, now) = filled(config, &[120, 30]).await;
+
+        assert_eq!(cache.len().await, 1);
+        assert!(cache.query(&since(now, 120)).await.is_none());
+        assert_eq!(cache.query(&since(now, 50)).await.unwrap().len(), 1);
+    }
+}
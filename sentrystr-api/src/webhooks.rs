@@ -0,0 +1,406 @@
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sentrystr_collector::{EventCollector, EventFilter};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+use crate::handlers::to_event_response;
+use crate::models::{WebhookInfo, WebhookStatus};
+use crate::{ApiError, Result};
+
+/// Header carrying `sha256=<hex HMAC-SHA256 of the body keyed with the secret>`.
+pub const SIGNATURE_HEADER: &str = "x-sentrystr-signature";
+
+/// Attempts per event before it counts as a failed delivery.
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+/// Consecutive failed deliveries after which a webhook is disabled.
+const MAX_CONSECUTIVE_FAILURES: u32 = 5;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Webhook {
+    id: Uuid,
+    url: String,
+    secret: String,
+    filter: EventFilter,
+    min_level: Option<String>,
+    created_at: DateTime<Utc>,
+    delivery: Mutex<DeliveryState>,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+#[derive(Default)]
+struct DeliveryState {
+    disabled: bool,
+    delivered: u64,
+    consecutive_failures: u32,
+    last_delivery_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl Webhook {
+    fn info(&self) -> WebhookInfo {
+        let delivery = self.delivery.lock().unwrap();
+
+        WebhookInfo {
+            id: self.id,
+            url: self.url.clone(),
+            filter: self.filter.clone(),
+            min_level: self.min_level.clone(),
+            status: if delivery.disabled {
+                WebhookStatus::Disabled
+            } else {
+                WebhookStatus::Active
+            },
+            delivered: delivery.delivered,
+            consecutive_failures: delivery.consecutive_failures,
+            last_delivery_at: delivery.last_delivery_at,
+            last_error: delivery.last_error.clone(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Webhooks registered through `POST /webhooks`. Each webhook owns a live subscription on
+/// the shared collector and a task that POSTs every matching event to its URL.
+pub struct WebhookRegistry {
+    collector: Arc<EventCollector>,
+    http: reqwest::Client,
+    webhooks: RwLock<HashMap<Uuid, Arc<Webhook>>>,
+    /// Wait before the second attempt of a delivery, doubling for every further one.
+    retry_base_delay: Duration,
+}
+
+impl WebhookRegistry {
+    pub fn new(collector: Arc<EventCollector>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            collector,
+            http,
+            webhooks: RwLock::new(HashMap::new()),
+            retry_base_delay: RETRY_BASE_DELAY,
+        }
+    }
+
+    pub async fn list(&self) -> Vec<WebhookInfo> {
+        let mut webhooks: Vec<WebhookInfo> = self
+            .webhooks
+            .read()
+            .await
+            .values()
+            .map(|webhook| webhook.info())
+            .collect();
+        webhooks.sort_by_key(|webhook| webhook.created_at);
+        webhooks
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<WebhookInfo> {
+        self.webhooks
+            .read()
+            .await
+            .get(&id)
+            .map(|webhook| webhook.info())
+            .ok_or_else(|| ApiError::NotFound(format!("No webhook with id {}", id)))
+    }
+
+    /// Subscribes to events matching `filter` and starts delivering them to `url`.
+    pub async fn register(
+        &self,
+        url: String,
+        secret: String,
+        filter: EventFilter,
+        min_level: Option<String>,
+    ) -> Result<WebhookInfo> {
+        let parsed = reqwest::Url::parse(&url).map_err(|e| ApiError::InvalidParameter {
+            param: "url",
+            message: format!("Invalid webhook URL: {}", e),
+        })?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(ApiError::InvalidParameter {
+                param: "url",
+                message: "Webhook URLs must use http or https".to_string(),
+            });
+        }
+
+        if secret.is_empty() {
+            return Err(ApiError::InvalidParameter {
+                param: "secret",
+                message: "A webhook secret is required to sign deliveries".to_string(),
+            });
+        }
+
+        let webhook = Arc::new(Webhook {
+            id: Uuid::new_v4(),
+            url,
+            secret,
+            filter: filter.clone(),
+            min_level,
+            created_at: Utc::now(),
+            delivery: Mutex::new(DeliveryState::default()),
+            task: Mutex::new(None),
+        });
+
+        let events = self
+            .collector
+            .subscribe_to_events(filter)
+            .await
+            .map_err(ApiError::from)?;
+
+        let task = tokio::spawn(deliver_events(
+            self.http.clone(),
+            Arc::clone(&webhook),
+            events,
+            self.retry_base_delay,
+        ));
+        *webhook.task.lock().unwrap() = Some(task.abort_handle());
+
+        let info = webhook.info();
+        self.webhooks.write().await.insert(webhook.id, webhook);
+        Ok(info)
+    }
+
+    pub async fn remove(&self, id: Uuid) -> Result<()> {
+        let webhook = self
+            .webhooks
+            .write()
+            .await
+            .remove(&id)
+            .ok_or_else(|| ApiError::NotFound(format!("No webhook with id {}", id)))?;
+
+        if let Some(task) = webhook.task.lock().unwrap().take() {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+async fn deliver_events(
+    http: reqwest::Client,
+    webhook: Arc<Webhook>,
+    mut events: tokio::sync::mpsc::Receiver<sentrystr_collector::CollectedEvent>,
+    retry_base_delay: Duration,
+) {
+    while let Some(event) = events.recv().await {
+        let body = match serde_json::to_vec(&to_event_response(event)) {
+            Ok(body) => Bytes::from(body),
+            Err(e) => {
+                eprintln!("Failed to serialize webhook payload: {}", e);
+                continue;
+            }
+        };
+
+        let result = deliver(&http, &webhook, body, retry_base_delay).await;
+
+        let mut delivery = webhook.delivery.lock().unwrap();
+        delivery.last_delivery_at = Some(Utc::now());
+        match result {
+            Ok(()) => {
+                delivery.delivered += 1;
+                delivery.consecutive_failures = 0;
+                delivery.last_error = None;
+            }
+            Err(e) => {
+                delivery.consecutive_failures += 1;
+                delivery.last_error = Some(e);
+
+                if delivery.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    eprintln!(
+                        "Disabling webhook {} after {} consecutive failed deliveries",
+                        webhook.id, delivery.consecutive_failures
+                    );
+                    delivery.disabled = true;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// POSTs `body` with bounded retries and exponential backoff.
+async fn deliver(
+    http: &reqwest::Client,
+    webhook: &Webhook,
+    body: Bytes,
+    retry_base_delay: Duration,
+) -> std::result::Result<(), String> {
+    let signature = format!("sha256={}", sign(&webhook.secret, &body));
+    let mut last_error = String::new();
+
+    for attempt in 0..MAX_ATTEMPTS {
+        if attempt > 0 {
+            tokio::time::sleep(retry_base_delay * 2u32.pow(attempt - 1)).await;
+        }
+
+        let response = http
+            .post(&webhook.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        match response {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            Ok(response) => {
+                last_error = format!("Receiver responded with {}", response.status());
+            }
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Hex-encoded HMAC-SHA256 of `body` keyed with `secret`, as sent in
+/// [`SIGNATURE_HEADER`]. Receivers recompute it to verify a delivery.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use nostr_relay_builder::MockRelay;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    /// Deliveries received by a [`receiver`]: signature header and body.
+    type Received = Arc<Mutex<Vec<(Option<String>, Bytes)>>>;
+
+    /// An HTTP endpoint recording deliveries and answering them with `status`, and its
+    /// URL.
+    async fn receiver(status: u16) -> (String, Received) {
+        async fn record(
+            State((received, status)): State<(Received, Arc<AtomicU16>)>,
+            headers: HeaderMap,
+            body: Bytes,
+        ) -> StatusCode {
+            let signature = headers
+                .get(SIGNATURE_HEADER)
+                .map(|value| value.to_str().unwrap().to_string());
+            received.lock().unwrap().push((signature, body));
+            StatusCode::from_u16(status.load(Ordering::Relaxed)).unwrap()
+        }
+
+        let received = Received::default();
+        let app = axum::Router::new()
+            .route("/hook", post(record))
+            .with_state((Arc::clone(&received), Arc::new(AtomicU16::new(status))));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, received)
+    }
+
+    /// A registry on a collector of `relay`, retrying deliveries without delay.
+    async fn registry(relay: &MockRelay) -> WebhookRegistry {
+        let collector = EventCollector::new(vec![relay.url().to_string()])
+            .await
+            .unwrap();
+        WebhookRegistry {
+            retry_base_delay: Duration::from_millis(1),
+            ..WebhookRegistry::new(Arc::new(collector))
+        }
+    }
+
+    async fn publish(relay: &MockRelay, messages: &[&str]) {
+        let config =
+            sentrystr::Config::from_keys(&nostr::Keys::generate(), [relay.url().to_string()])
+                .unwrap();
+        let client = sentrystr::NostrSentryClient::new(config).await.unwrap();
+        for message in messages {
+            client
+                .capture_message(sentrystr::Level::Error, *message)
+                .await
+                .unwrap();
+        }
+    }
+
+    async fn wait_for(condition: impl AsyncFn() -> bool) {
+        for _ in 0..250 {
+            if condition().await {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("condition not met in time");
+    }
+
+    #[test]
+    fn signatures_are_hmac_sha256() {
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_signed_with_the_secret() {
+        let relay = MockRelay::run().await.unwrap();
+        let (url, received) = receiver(200).await;
+        let registry = registry(&relay).await;
+        let webhook = registry
+            .register(url, "s3cret".to_string(), EventFilter::new(), None)
+            .await
+            .unwrap();
+
+        publish(&relay, &["payment failed"]).await;
+        wait_for(async || !received.lock().unwrap().is_empty()).await;
+
+        let (signature, body) = received.lock().unwrap()[0].clone();
+        assert_eq!(signature, Some(format!("sha256={}", sign("s3cret", &body))));
+        assert_ne!(signature, Some(format!("sha256={}", sign("other", &body))));
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["event"]["message"], "payment failed");
+        wait_for(async || registry.get(webhook.id).await.unwrap().delivered == 1).await;
+    }
+
+    #[tokio::test]
+    async fn webhooks_are_disabled_after_repeated_failures() {
+        let relay = MockRelay::run().await.unwrap();
+        let (url, received) = receiver(500).await;
+        let registry = registry(&relay).await;
+        let webhook = registry
+            .register(url, "s3cret".to_string(), EventFilter::new(), None)
+            .await
+            .unwrap();
+
+        let messages: Vec<String> = (0..=MAX_CONSECUTIVE_FAILURES)
+            .map(|n| format!("failure {}", n))
+            .collect();
+        publish(
+            &relay,
+            &messages.iter().map(String::as_str).collect::<Vec<_>>(),
+        )
+        .await;
+        wait_for(async || {
+            registry.get(webhook.id).await.unwrap().status == WebhookStatus::Disabled
+        })
+        .await;
+
+        let info = registry.get(webhook.id).await.unwrap();
+        assert_eq!(info.consecutive_failures, MAX_CONSECUTIVE_FAILURES);
+        assert_eq!(info.delivered, 0);
+        assert!(info.last_error.unwrap().contains("500"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(
+            received.lock().unwrap().len(),
+            (MAX_CONSECUTIVE_FAILURES * MAX_ATTEMPTS) as usize
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! The relay and key options shared by the commands that publish.
+
+use clap::Args;
+use sentrystr::keyfile::PASSPHRASE_VAR;
+use sentrystr::{
+    Config, MinRelaySuccess, PassphraseSource, Percentage, Result, SentryStrError, read_key_file,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SECRET_KEY_VAR: &str = "SENTRYSTR_SECRET_KEY";
+const RELAYS_VAR: &str = "SENTRYSTR_RELAYS";
+
+#[derive(Args)]
+pub struct ClientArgs {
+    #[arg(short, long, help = "Relay URLs (default: SENTRYSTR_RELAYS)")]
+    relays: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = SECRET_KEY_VAR,
+        help = "Variable holding the secret key to publish with (hex or nsec)"
+    )]
+    nsec_env: String,
+
+    #[arg(
+        long,
+        help = "File with the secret key (hex, nsec or NIP-49 ncryptsec), instead of --nsec-env",
+        conflicts_with = "nsec_env"
+    )]
+    key_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Relays that must accept each event, a count or a percentage like 50% (default: 1)",
+        value_parser = parse_min_relay_success
+    )]
+    min_relay_success: Option<MinRelaySuccess>,
+
+    #[arg(
+        long,
+        help = "Events published per second at most",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rate_limit: Option<u32>,
+}
+
+fn parse_min_relay_success(s: &str) -> std::result::Result<MinRelaySuccess, String> {
+    let invalid = |_| format!("Invalid relay count '{}'. Expected e.g. 2 or 50%", s);
+    match s.strip_suffix('%') {
+        Some(percentage) => Ok(Percentage(percentage.parse().map_err(invalid)?).into()),
+        None => Ok(MinRelaySuccess::Count(s.parse().map_err(invalid)?)),
+    }
+}
+
+/// The passphrase of key files: `SENTRYSTR_KEY_PASSPHRASE` when set, otherwise asked for
+/// on the terminal with the `prompt` feature.
+fn passphrase_source() -> PassphraseSource {
+    #[cfg(feature = "prompt")]
+    if std::env::var_os(PASSPHRASE_VAR).is_none() {
+        return PassphraseSource::Prompt;
+    }
+    PassphraseSource::env(PASSPHRASE_VAR)
+}
+
+impl ClientArgs {
+    /// The relays of `--relays`, else of `SENTRYSTR_RELAYS`.
+    fn relays(&self) -> Result<Vec<String>> {
+        let relays: Vec<String> = if self.relays.is_empty() {
+            std::env::var(RELAYS_VAR)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|relay| !relay.is_empty())
+                .map(str::to_string)
+                .collect()
+        } else {
+            self.relays.clone()
+        };
+
+        if relays.is_empty() {
+            return Err(SentryStrError::Config(format!(
+                "No relays: pass --relays or set {}",
+                RELAYS_VAR
+            )));
+        }
+        Ok(relays)
+    }
+
+    /// The client configuration, publishing within `timeout`.
+    pub fn config(&self, timeout: Duration) -> Result<Config> {
+        let relays = self.relays()?;
+        let config = match self.key_file {
+            Some(ref key_file) => {
+                Config::from_keys(&read_key_file(key_file, &passphrase_source())?, relays)?
+            }
+            None => {
+                let secret_key = std::env::var(&self.nsec_env).map_err(|_| {
+                    SentryStrError::Config(format!(
+                        "No secret key: set {} or pass --key-file",
+                        self.nsec_env
+                    ))
+                })?;
+                Config::try_new(secret_key, relays)?
+            }
+        };
+
+        let mut config = config.with_publish_timeout(timeout);
+        if let Some(min) = self.min_relay_success {
+            config = config.with_min_relay_success(min);
+        }
+        if let Some(max_events) = self.rate_limit {
+            config = config.with_rate_limit(max_events, Duration::from_secs(1));
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        client: ClientArgs,
+    }
+
+    #[test]
+    fn rate_limit_must_be_positive() {
+        let cli = Cli::try_parse_from(["sentrystr", "--rate-limit", "5"]).unwrap();
+        assert_eq!(cli.client.rate_limit, Some(5));
+        assert!(Cli::try_parse_from(["sentrystr", "--rate-limit", "0"]).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use crate::connection::ClientArgs;
+use crate::ndjson::{self, FieldMap, IngestSummary};
+use clap::Args;
+use sentrystr::{CaptureOutcome, Event, Level, NostrSentryClient, Result, SentryStrError};
+use std::io::Read;
+use std::time::Duration;
+use tokio::io::AsyncBufRead;
+
+/// Message value reading the message from stdin.
+const STDIN: &str = "-";
+
+#[derive(Args)]
+pub struct EmitArgs {
+    #[arg(
+        short,
+        long,
+        default_value = "error",
+        value_parser = parse_level,
+        help = "Event level (debug, info, warning, error, fatal)"
+    )]
+    level: Level,
+
+    #[arg(
+        short,
+        long,
+        help = "Event message, or - to read it from stdin",
+        required_unless_present = "stdin_ndjson"
+    )]
+    message: Option<String>,
+
+    #[arg(
+        long,
+        help = "Publish an event per NDJSON line of stdin instead of one event",
+        conflicts_with = "message"
+    )]
+    stdin_ndjson: bool,
+
+    #[arg(
+        long,
+        help = "Event field filled from a field of the NDJSON lines (format: field=source), e.g. level=severity",
+        value_parser = parse_key_value,
+        // Not `requires = "stdin_ndjson"`, which the flag's default `false` satisfies;
+        // without --stdin-ndjson, --message is required.
+        conflicts_with = "message"
+    )]
+    map: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        help = "Events of --stdin-ndjson held in memory while waiting for the relays",
+        default_value = "1000"
+    )]
+    queue_size: usize,
+
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Seconds between the progress summaries of --stdin-ndjson"
+    )]
+    progress_interval: u64,
+
+    #[arg(long, help = "Event tag (format: key=value)", value_parser = parse_key_value)]
+    tag: Vec<(String, String)>,
+
+    #[arg(
+        long,
+        help = "Event extra (format: key=value); JSON values such as 2 or true keep their type",
+        value_parser = parse_key_value
+    )]
+    extra: Vec<(String, String)>,
+
+    #[arg(long, help = "Environment of the event, e.g. production")]
+    environment: Option<String>,
+
+    #[arg(long, help = "Release of the event, e.g. a version or commit")]
+    release: Option<String>,
+
+    #[arg(
+        long,
+        help = "Fingerprint part grouping the event; repeat for several parts"
+    )]
+    fingerprint: Vec<String>,
+
+    #[command(flatten)]
+    client: ClientArgs,
+
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Seconds to wait for the relays, or for the queue once stdin ends"
+    )]
+    timeout: u64,
+}
+
+fn parse_level(s: &str) -> std::result::Result<Level, String> {
+    s.parse()
+        .map_err(|e: sentrystr::ParseLevelError| e.to_string())
+}
+
+fn parse_key_value(s: &str) -> std::result::Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("Invalid format '{}'. Expected 'key=value'", s)),
+    }
+}
+
+/// An extra's value as JSON when it parses as JSON, e.g. `2` or `true`, otherwise as
+/// a string.
+fn parse_extra(value: &str) -> serde_json::Value {
+    serde_json::from_str(value).unwrap_or_else(|_| serde_json::Value::String(value.to_string()))
+}
+
+/// Reads the whole of stdin, without the trailing newline. Fails when it is empty.
+fn read_stdin() -> Result<String> {
+    let mut message = String::new();
+    std::io::stdin()
+        .read_to_string(&mut message)
+        .map_err(|e| SentryStrError::Config(format!("Failed to read stdin: {}", e)))?;
+
+    let message = message.trim_end_matches(['\r', '\n']);
+    if message.trim().is_empty() {
+        return Err(SentryStrError::Config("No message on stdin".to_string()));
+    }
+    Ok(message.to_string())
+}
+
+impl EmitArgs {
+    /// Whether the events come from NDJSON lines, see [`run_ndjson`].
+    pub fn is_ndjson(&self) -> bool {
+        self.stdin_ndjson
+    }
+
+    fn event(&self, message: String) -> Event {
+        let mut event = Event::new()
+            .with_message(message)
+            .with_level(self.level.clone());
+        for (key, value) in &self.tag {
+            event = event.with_tag(key, value);
+        }
+        for (key, value) in &self.extra {
+            event = event.with_extra(key, parse_extra(value));
+        }
+        if !self.fingerprint.is_empty() {
+            event = event.with_fingerprint(self.fingerprint.iter());
+        }
+        event.environment = self.environment.clone();
+        event.release = self.release.clone();
+        event
+    }
+}
+
+/// Publishes the event of `args` and waits for the relays' answers.
+pub async fn run(args: EmitArgs) -> Result<CaptureOutcome> {
+    let message = match args.message.as_deref() {
+        Some(STDIN) => read_stdin()?,
+        Some(message) => message.to_string(),
+        None => {
+            return Err(SentryStrError::Config(
+                "No message: pass --message".to_string(),
+            ));
+        }
+    };
+    let event = args.event(message);
+
+    let config = args.client.config(Duration::from_secs(args.timeout))?;
+    let client = NostrSentryClient::new(config).await?;
+    let outcome = client.capture_event(event).await;
+    client.close(Duration::from_secs(1)).await;
+    outcome
+}
+
+/// Publishes an event per NDJSON line of stdin through the client's queue, which waits
+/// for the rate limit instead of dropping events. The command line's level, tags,
+/// extras, environment, release and fingerprint are the defaults of every event.
+pub async fn run_ndjson(args: EmitArgs) -> Result<IngestSummary> {
+    let map = FieldMap::new(args.map.iter().cloned())?;
+    let timeout = Duration::from_secs(args.timeout);
+    let config = args
+        .client
+        .config(timeout)?
+        .with_queue(args.queue_size.max(1), Duration::from_secs(1));
+
+    let client = NostrSentryClient::new(config).await?;
+    let summary = ingest(
+        &args,
+        &map,
+        &client,
+        tokio::io::BufReader::new(tokio::io::stdin()),
+    )
+    .await;
+    client.close(Duration::from_secs(1)).await;
+    summary
+}
+
+/// Publishes the NDJSON lines of `reader` as [`run_ndjson`] does those of stdin.
+async fn ingest(
+    args: &EmitArgs,
+    map: &FieldMap,
+    client: &NostrSentryClient,
+    reader: impl AsyncBufRead + Unpin,
+) -> Result<IngestSummary> {
+    ndjson::ingest(
+        client,
+        map,
+        reader,
+        |message| args.event(message),
+        Duration::from_secs(args.progress_interval.max(1)),
+        Duration::from_secs(args.timeout),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use nostr::Keys;
+    use nostr_relay_builder::MockRelay;
+    use sentrystr::Config;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        emit: EmitArgs,
+    }
+
+    fn parse(args: &[&str]) -> std::result::Result<EmitArgs, clap::Error> {
+        Cli::try_parse_from(["sentrystr"].iter().chain(args)).map(|cli| cli.emit)
+    }
+
+    #[test]
+    fn arguments_fill_the_event() {
+        let args = parse(&[
+            "--message",
+            "disk full",
+            "--level",
+            "warning",
+            "--tag",
+            "service=db",
+            "--extra",
+            "free_mb=12",
+            "--extra",
+            "mount=/var",
+            "--fingerprint",
+            "disk",
+            "--fingerprint",
+            "full",
+            "--environment",
+            "production",
+            "--release",
+            "1.2.0",
+        ])
+        .unwrap();
+        assert!(!args.is_ndjson());
+
+        let event = args.event("disk full".to_string());
+        assert_eq!(event.message.as_deref(), Some("disk full"));
+        assert_eq!(event.level, Level::Warning);
+        assert_eq!(event.tags["service"], "db");
+        assert_eq!(event.extra["free_mb"], 12);
+        assert_eq!(event.extra["mount"], "/var");
+        assert_eq!(
+            event.fingerprint,
+            Some(vec!["disk".to_string(), "full".to_string()])
+        );
+        assert_eq!(event.environment.as_deref(), Some("production"));
+        assert_eq!(event.release.as_deref(), Some("1.2.0"));
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        // A message or --stdin-ndjson, not both.
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--message", "m", "--stdin-ndjson"]).is_err());
+        // --map only applies to NDJSON lines.
+        assert!(parse(&["--message", "m", "--map", "level=severity"]).is_err());
+        assert!(parse(&["--message", "m", "--level", "loud"]).is_err());
+        assert!(parse(&["--message", "m", "--tag", "service"]).is_err());
+        assert!(parse(&["--message", "m", "--tag", "=db"]).is_err());
+
+        let args = parse(&["--stdin-ndjson", "--map", "level=severity"]).unwrap();
+        assert!(args.is_ndjson());
+        assert_eq!(args.map, [("level".to_string(), "severity".to_string())]);
+        assert_eq!(args.level, Level::Error);
+    }
+
+    #[tokio::test]
+    async fn ndjson_lines_are_published_with_the_command_line_defaults() {
+        let relay = MockRelay::run().await.unwrap();
+        let config = Config::from_keys(&Keys::generate(), [relay.url().to_string()])
+            .unwrap()
+            .with_queue(16, Duration::from_secs(1));
+        let client = NostrSentryClient::new(config).await.unwrap();
+        let args = parse(&[
+            "--stdin-ndjson",
+            "--map",
+            "message=msg",
+            "--tag",
+            "host=web-1",
+            "--timeout",
+            "5",
+        ])
+        .unwrap();
+        let map = FieldMap::new(args.map.iter().cloned()).unwrap();
+        let input = concat!(
+            r#"{"msg":"first","level":"warning"}"#,
+            "\n",
+            "not json\n",
+            r#"{"msg":"second"}"#,
+            "\n",
+        );
+
+        let summary = ingest(&args, &map, &client, input.as_bytes())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            summary,
+            IngestSummary {
+                published: 2,
+                failed: 0,
+                skipped: 1,
+            }
+        );
+        assert_eq!(summary.to_string(), "2 published, 0 failed, 1 skipped");
+    }
+}
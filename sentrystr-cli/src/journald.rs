@@ -0,0 +1,644 @@
+//! `sentrystr journald`, which forwards systemd journal entries as events, behind the
+//! `journald` feature.
+//!
+//! Entries are read from `journalctl -o json -f`, or from stdin with `--stdin`, and go
+//! through the client's queue. With `--state-file` the queue is persisted next to the
+//! state file and the journal cursor of the last queued entry is saved after each entry,
+//! so a restart resumes right after it without publishing entries twice. With `--stdin`
+//! the entries up to the saved cursor are skipped instead.
+
+use crate::connection::ClientArgs;
+use chrono::{TimeZone, Utc};
+use clap::Args;
+use sentrystr::{Event, Level, NostrSentryClient, Result, SentryStrError};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+/// Journal fields copied to the extras when `--field` is not given.
+const DEFAULT_FIELDS: &[&str] = &["_PID", "_COMM", "CODE_FILE", "CODE_LINE", "CODE_FUNC"];
+
+/// Size of the persisted queue next to the state file.
+const QUEUE_FILE_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Args)]
+pub struct JournaldArgs {
+    #[arg(
+        short,
+        long,
+        help = "Only forward entries of this systemd unit; repeat for several units"
+    )]
+    unit: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "3",
+        help = "Only forward entries of this syslog priority or more severe (0 emerg to 7 debug)"
+    )]
+    priority: u8,
+
+    #[arg(
+        long,
+        help = "Journal field copied to the extras, e.g. _PID; repeat for several fields"
+    )]
+    field: Vec<String>,
+
+    #[arg(
+        long,
+        help = "File keeping the journal cursor, to resume after a restart"
+    )]
+    state_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Read journalctl -o json output from stdin instead of following the journal"
+    )]
+    stdin: bool,
+
+    #[arg(long, help = "Environment of the events, e.g. production")]
+    environment: Option<String>,
+
+    #[arg(long, help = "Release of the events")]
+    release: Option<String>,
+
+    #[arg(
+        long,
+        help = "Events held in memory while waiting for the relays",
+        default_value = "1000"
+    )]
+    queue_size: usize,
+
+    #[command(flatten)]
+    client: ClientArgs,
+
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Seconds to wait for the relays, or for the queue when stopping"
+    )]
+    timeout: u64,
+}
+
+/// The level of a syslog priority: emergencies to critical errors are fatal, notices and
+/// informational messages info.
+pub fn level_for_priority(priority: u8) -> Level {
+    match priority {
+        0..=2 => Level::Fatal,
+        3 => Level::Error,
+        4 => Level::Warning,
+        5 | 6 => Level::Info,
+        _ => Level::Debug,
+    }
+}
+
+/// The text of a journal field. journalctl writes fields that are not valid UTF-8 as
+/// arrays of bytes, and fields set several times as arrays of their values, of which
+/// the first is taken.
+fn field_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Array(values) if values.iter().all(Value::is_u64) => {
+            let bytes: Vec<u8> = values
+                .iter()
+                .filter_map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect();
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        Value::Array(values) => values.first().and_then(field_text),
+        _ => None,
+    }
+}
+
+/// The sequence number id (`s=`) and sequence number (`i=`, hexadecimal) of a journal
+/// cursor such as `s=739a...;i=4ece7;b=...`.
+fn sequence_number(cursor: &str) -> Option<(&str, u64)> {
+    let field = |name: &str| {
+        cursor
+            .split(';')
+            .find_map(|part| part.strip_prefix(name)?.strip_prefix('='))
+    };
+    Some((field("s")?, u64::from_str_radix(field("i")?, 16).ok()?))
+}
+
+/// An entry of `journalctl -o json`.
+#[derive(Debug, Clone)]
+pub struct JournalEntry {
+    fields: Map<String, Value>,
+}
+
+impl JournalEntry {
+    /// Parses a line of `journalctl -o json`.
+    pub fn parse(line: &str) -> Result<Self> {
+        match serde_json::from_str(line)? {
+            Value::Object(fields) => Ok(Self { fields }),
+            _ => Err(SentryStrError::Config(
+                "Journal entry is not a JSON object".to_string(),
+            )),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.fields.get(name).and_then(field_text)
+    }
+
+    pub fn cursor(&self) -> Option<String> {
+        self.field("__CURSOR")
+    }
+
+    /// Whether the entry comes after the one of `cursor`: it is from another journal, or
+    /// has a higher sequence number in the same one. Entries without a cursor do.
+    pub fn is_after(&self, cursor: &str) -> bool {
+        let Some(own) = self.cursor() else {
+            return true;
+        };
+        match (sequence_number(&own), sequence_number(cursor)) {
+            (Some((id, number)), Some((saved_id, saved))) if id == saved_id => number > saved,
+            _ => own != cursor,
+        }
+    }
+
+    pub fn unit(&self) -> Option<String> {
+        self.field("_SYSTEMD_UNIT")
+    }
+
+    /// The syslog priority; entries without one are informational.
+    pub fn priority(&self) -> u8 {
+        self.field("PRIORITY")
+            .and_then(|priority| priority.parse().ok())
+            .unwrap_or(6)
+    }
+
+    /// The event of the entry: `MESSAGE` as message, the level of `PRIORITY`,
+    /// `_SYSTEMD_UNIT` as `service` tag, `SYSLOG_IDENTIFIER` as logger, `_HOSTNAME` as
+    /// server name, the entry's time as timestamp and `fields` as extras. `None` for
+    /// entries without a message.
+    pub fn to_event(&self, fields: &[String]) -> Option<Event> {
+        let mut event = Event::new()
+            .with_message(self.field("MESSAGE")?)
+            .with_level(level_for_priority(self.priority()))
+            .with_tag("source", "journald");
+        if let Some(unit) = self.unit() {
+            event = event.with_tag("service", unit);
+        }
+        event.logger = self.field("SYSLOG_IDENTIFIER");
+        event.server_name = self.field("_HOSTNAME");
+        if let Some(timestamp) = self
+            .field("__REALTIME_TIMESTAMP")
+            .and_then(|micros| micros.parse::<i64>().ok())
+            .and_then(|micros| Utc.timestamp_micros(micros).single())
+        {
+            event.timestamp = timestamp;
+        }
+        for name in fields {
+            if let Some(value) = self.field(name) {
+                event = event.with_extra(name, Value::String(value));
+            }
+        }
+        Some(event)
+    }
+}
+
+/// The entries `sentrystr journald` forwards.
+#[derive(Debug, Clone, Default)]
+pub struct JournalFilter {
+    /// Units whose entries are forwarded; all when empty.
+    pub units: Vec<String>,
+    /// The least severe priority forwarded.
+    pub max_priority: u8,
+}
+
+impl JournalFilter {
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        if entry.priority() > self.max_priority {
+            return false;
+        }
+        self.units.is_empty() || entry.unit().is_some_and(|unit| self.units.contains(&unit))
+    }
+}
+
+/// The journal cursor of the last forwarded entry, kept in a file.
+pub struct CursorFile {
+    path: PathBuf,
+}
+
+impl CursorFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The saved cursor; `None` on the first run.
+    pub async fn load(&self) -> Result<Option<String>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(cursor) => Ok(Some(cursor.trim().to_string()).filter(|c| !c.is_empty())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(state_error(&self.path, e)),
+        }
+    }
+
+    /// Saves `cursor` through a temporary file that is synced before it replaces the
+    /// state file, and syncs the directory after, so a crash leaves the old or the new
+    /// cursor but never half of one.
+    pub async fn save(&self, cursor: &str) -> Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        let mut file = tokio::fs::File::create(&temporary)
+            .await
+            .map_err(|e| state_error(&temporary, e))?;
+        file.write_all(cursor.as_bytes())
+            .await
+            .map_err(|e| state_error(&temporary, e))?;
+        file.sync_all()
+            .await
+            .map_err(|e| state_error(&temporary, e))?;
+        tokio::fs::rename(&temporary, &self.path)
+            .await
+            .map_err(|e| state_error(&self.path, e))?;
+
+        #[cfg(unix)]
+        {
+            let directory = match self.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            let directory_file = tokio::fs::File::open(directory)
+                .await
+                .map_err(|e| state_error(directory, e))?;
+            directory_file
+                .sync_all()
+                .await
+                .map_err(|e| state_error(directory, e))?;
+        }
+        Ok(())
+    }
+}
+
+fn state_error(path: &Path, error: std::io::Error) -> SentryStrError {
+    SentryStrError::Config(format!(
+        "Failed to access the state file {}: {}",
+        path.display(),
+        error
+    ))
+}
+
+/// Counts of [`forward`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ForwardSummary {
+    /// Entries the client accepted.
+    pub forwarded: u64,
+    /// Entries the client failed to capture.
+    pub failed: u64,
+    /// Entries left out by the filter or without a message.
+    pub filtered: u64,
+    /// Lines that are not journal entries.
+    pub malformed: u64,
+}
+
+impl std::fmt::Display for ForwardSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} forwarded, {} failed, {} filtered, {} malformed",
+            self.forwarded, self.failed, self.filtered, self.malformed
+        )
+    }
+}
+
+/// Captures the entries of `reader` that pass `filter` until it ends or the process is
+/// interrupted, saving the cursor of each entry to `cursor_file` once it is queued or
+/// filtered out. The cursor of an entry the client failed to capture is not saved.
+/// Entries up to `after`, a saved cursor, are skipped.
+pub async fn forward(
+    client: &NostrSentryClient,
+    reader: impl AsyncBufRead + Unpin,
+    filter: &JournalFilter,
+    fields: &[String],
+    cursor_file: Option<&CursorFile>,
+    after: Option<&str>,
+) -> Result<ForwardSummary> {
+    let mut lines = reader.lines();
+    let mut summary = ForwardSummary::default();
+    let interrupted = tokio::signal::ctrl_c();
+    tokio::pin!(interrupted);
+
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line.map_err(|e| {
+                SentryStrError::Config(format!("Failed to read the journal: {}", e))
+            })?,
+            _ = &mut interrupted => break,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = match JournalEntry::parse(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping journal line: {}", e);
+                summary.malformed += 1;
+                continue;
+            }
+        };
+        if after.is_some_and(|cursor| !entry.is_after(cursor)) {
+            continue;
+        }
+
+        let event = filter
+            .matches(&entry)
+            .then(|| entry.to_event(fields))
+            .flatten();
+        match event {
+            Some(event) => match client.capture_event(event).await {
+                Ok(_) => summary.forwarded += 1,
+                Err(e) => {
+                    eprintln!("Failed to capture journal entry: {}", e);
+                    summary.failed += 1;
+                    continue;
+                }
+            },
+            None => summary.filtered += 1,
+        }
+
+        if let Some(cursor_file) = cursor_file
+            && let Some(cursor) = entry.cursor()
+        {
+            cursor_file.save(&cursor).await?;
+        }
+    }
+    Ok(summary)
+}
+
+/// The journalctl arguments following the journal from after `cursor`, or from now on
+/// without one, with the units and priorities of `filter` selected by journalctl.
+fn journalctl_args(cursor: Option<&str>, filter: &JournalFilter) -> Vec<String> {
+    let mut args = vec!["--output=json".to_string(), "--follow".to_string()];
+    args.push(match cursor {
+        Some(cursor) => format!("--after-cursor={}", cursor),
+        None => "--lines=0".to_string(),
+    });
+    for unit in &filter.units {
+        args.push("-u".to_string());
+        args.push(unit.clone());
+    }
+    args.push("-p".to_string());
+    args.push(filter.max_priority.to_string());
+    args
+}
+
+fn spawn_journalctl(cursor: Option<&str>, filter: &JournalFilter) -> Result<tokio::process::Child> {
+    Command::new("journalctl")
+        .args(journalctl_args(cursor, filter))
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| SentryStrError::Config(format!("Failed to start journalctl: {}", e)))
+}
+
+/// Runs `sentrystr journald` until the journal ends or the process is interrupted.
+pub async fn run(args: JournaldArgs) -> Result<ForwardSummary> {
+    let timeout = Duration::from_secs(args.timeout);
+    let mut config = args
+        .client
+        .config(timeout)?
+        .with_queue(args.queue_size.max(1), Duration::from_secs(1));
+    if let Some(ref state_file) = args.state_file {
+        config =
+            config.with_persistent_queue(state_file.with_extension("queue"), QUEUE_FILE_BYTES)?;
+    }
+    if let Some(ref environment) = args.environment {
+        config = config.with_environment(environment);
+    }
+    if let Some(ref release) = args.release {
+        config = config.with_release(release);
+    }
+
+    let cursor_file = args.state_file.as_deref().map(CursorFile::new);
+    let cursor = match cursor_file {
+        Some(ref cursor_file) => cursor_file.load().await?,
+        None => None,
+    };
+    let filter = JournalFilter {
+        units: args.unit.clone(),
+        max_priority: args.priority,
+    };
+    let fields: Vec<String> = if args.field.is_empty() {
+        DEFAULT_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .collect()
+    } else {
+        args.field.clone()
+    };
+
+    let client = NostrSentryClient::new(config).await?;
+    let summary = if args.stdin {
+        let reader = BufReader::new(tokio::io::stdin());
+        let after = cursor.as_deref();
+        forward(
+            &client,
+            reader,
+            &filter,
+            &fields,
+            cursor_file.as_ref(),
+            after,
+        )
+        .await
+    } else {
+        // journalctl already starts after the cursor.
+        let mut journalctl = spawn_journalctl(cursor.as_deref(), &filter)?;
+        let stdout = journalctl
+            .stdout
+            .take()
+            .ok_or_else(|| SentryStrError::Config("journalctl has no output".to_string()))?;
+        let reader = BufReader::new(stdout);
+        forward(
+            &client,
+            reader,
+            &filter,
+            &fields,
+            cursor_file.as_ref(),
+            None,
+        )
+        .await
+    };
+
+    let report = client.close(timeout).await;
+    if report.abandoned > 0 {
+        eprintln!(
+            "{} events still pending after {:?}{}",
+            report.abandoned,
+            timeout,
+            if args.state_file.is_some() {
+                ", kept in the queue file"
+            } else {
+                ""
+            }
+        );
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+    use sentrystr::Config;
+
+    const ENTRIES: &str = include_str!("../tests/fixtures/journald/entries.json");
+
+    fn entries() -> Vec<JournalEntry> {
+        ENTRIES
+            .lines()
+            .filter_map(|line| JournalEntry::parse(line).ok())
+            .collect()
+    }
+
+    fn fields() -> Vec<String> {
+        DEFAULT_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .collect()
+    }
+
+    async fn client() -> NostrSentryClient {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(16, Duration::from_secs(1));
+        NostrSentryClient::new(config).await.unwrap()
+    }
+
+    fn state_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sentrystr-{}-{}.cursor", name, std::process::id()))
+    }
+
+    #[test]
+    fn entries_become_events() {
+        let entries = entries();
+        assert_eq!(entries.len(), 4);
+
+        let event = entries[0].to_event(&fields()).unwrap();
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.tags["service"], "postgresql.service");
+        assert_eq!(event.logger.as_deref(), Some("postgres"));
+        assert_eq!(event.server_name.as_deref(), Some("db-1"));
+        assert_eq!(event.timestamp.timestamp(), 1_759_320_000);
+        assert_eq!(event.extra["_PID"], "812");
+
+        // journalctl writes messages that are not UTF-8 as byte arrays.
+        let event = entries[2].to_event(&fields()).unwrap();
+        assert_eq!(event.message.as_deref(), Some("worker crashed"));
+        assert_eq!(event.level, Level::Fatal);
+        assert!(entries[3].to_event(&fields()).is_none());
+    }
+
+    #[test]
+    fn filter_selects_units_and_priorities() {
+        let filter = JournalFilter {
+            units: vec!["postgresql.service".to_string()],
+            max_priority: 3,
+        };
+        let matched: Vec<bool> = entries()
+            .iter()
+            .map(|entry| filter.matches(entry))
+            .collect();
+        assert_eq!(matched, [true, false, false, false]);
+    }
+
+    #[test]
+    fn journalctl_selects_units_and_priorities() {
+        let filter = JournalFilter {
+            units: vec![
+                "nginx.service".to_string(),
+                "postgresql.service".to_string(),
+            ],
+            max_priority: 4,
+        };
+        assert_eq!(
+            journalctl_args(Some("s=1;i=2"), &filter),
+            [
+                "--output=json",
+                "--follow",
+                "--after-cursor=s=1;i=2",
+                "-u",
+                "nginx.service",
+                "-u",
+                "postgresql.service",
+                "-p",
+                "4",
+            ]
+        );
+        assert_eq!(
+            journalctl_args(None, &JournalFilter::default())[2],
+            "--lines=0"
+        );
+    }
+
+    #[test]
+    fn entries_are_ordered_by_sequence_number() {
+        let entries = entries();
+        let cursor = entries[1].cursor().unwrap();
+        assert!(!entries[0].is_after(&cursor));
+        assert!(!entries[1].is_after(&cursor));
+        assert!(entries[2].is_after(&cursor));
+        assert!(entries[0].is_after("s=another-journal;i=ffffff"));
+    }
+
+    #[tokio::test]
+    async fn cursor_file_round_trips() {
+        let path = state_path("round-trip");
+        let cursor_file = CursorFile::new(&path);
+        assert_eq!(cursor_file.load().await.unwrap(), None);
+
+        cursor_file.save("s=1;i=2").await.unwrap();
+        cursor_file.save("s=1;i=3").await.unwrap();
+        assert_eq!(
+            cursor_file.load().await.unwrap().as_deref(),
+            Some("s=1;i=3")
+        );
+        assert!(!path.with_extension("tmp").exists());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stdin_resumes_after_the_saved_cursor() {
+        let path = state_path("resume");
+        let cursor_file = CursorFile::new(&path);
+        let saved = entries()[0].cursor().unwrap();
+        let filter = JournalFilter {
+            units: Vec::new(),
+            max_priority: 3,
+        };
+
+        let summary = forward(
+            &client().await,
+            ENTRIES.as_bytes(),
+            &filter,
+            &fields(),
+            Some(&cursor_file),
+            Some(&saved),
+        )
+        .await
+        .unwrap();
+
+        // The first entry was forwarded before; the info entry and the one without a
+        // message are filtered.
+        assert_eq!(
+            summary,
+            ForwardSummary {
+                forwarded: 1,
+                failed: 0,
+                filtered: 2,
+                malformed: 1,
+            }
+        );
+        assert_eq!(cursor_file.load().await.unwrap(), entries()[3].cursor());
+        std::fs::remove_file(path).unwrap();
+    }
+}
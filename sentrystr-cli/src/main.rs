@@ -0,0 +1,91 @@
+mod connection;
+mod emit;
+#[cfg(feature = "journald")]
+mod journald;
+mod ndjson;
+
+use clap::{Parser, Subcommand};
+use emit::EmitArgs;
+use sentrystr::SentryStrError;
+use std::process::ExitCode;
+
+/// Exit code when the event could not be built or the client could not start.
+const EXIT_ERROR: u8 = 1;
+/// Exit code when fewer relays accepted the event than `--min-relay-success` requires,
+/// or, with `--stdin-ndjson`, when any event failed to publish.
+const EXIT_NOT_DELIVERED: u8 = 3;
+
+#[derive(Parser)]
+#[command(name = "sentrystr")]
+#[command(about = "Command line tools for SentryStr")]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Publishes one event and prints its id
+    Emit(EmitArgs),
+    /// Forwards systemd journal entries as events
+    #[cfg(feature = "journald")]
+    Journald(journald::JournaldArgs),
+}
+
+fn exit_code(error: &SentryStrError) -> u8 {
+    match error {
+        SentryStrError::PublishFailed { .. }
+        | SentryStrError::PartialDelivery { .. }
+        | SentryStrError::NotConnected { .. } => EXIT_NOT_DELIVERED,
+        _ => EXIT_ERROR,
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Commands::Emit(args) if args.is_ndjson() => match emit::run_ndjson(args).await {
+            Ok(summary) => {
+                println!("{}", summary);
+                if summary.failed > 0 {
+                    ExitCode::from(EXIT_NOT_DELIVERED)
+                } else {
+                    ExitCode::SUCCESS
+                }
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::from(exit_code(&e))
+            }
+        },
+        Commands::Emit(args) => match emit::run(args).await {
+            Ok(outcome) => match outcome.event_id() {
+                Some(event_id) => {
+                    println!("{}", event_id.to_hex());
+                    ExitCode::SUCCESS
+                }
+                None => {
+                    eprintln!("Event not published: {}", outcome);
+                    ExitCode::from(EXIT_NOT_DELIVERED)
+                }
+            },
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::from(exit_code(&e))
+            }
+        },
+        #[cfg(feature = "journald")]
+        Commands::Journald(args) => match journald::run(args).await {
+            Ok(summary) => {
+                eprintln!("{}", summary);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                ExitCode::from(exit_code(&e))
+            }
+        },
+    }
+}
@@ -0,0 +1,386 @@
+//! Bulk publishing of NDJSON log lines, for `sentrystr emit --stdin-ndjson`.
+//!
+//! Each line is a JSON object mapped onto an [`Event`] by a [`FieldMap`]. Lines that are
+//! not UTF-8, not JSON objects or have no message are skipped and counted, never fatal,
+//! so a stream with the odd garbled line keeps flowing.
+
+use chrono::{DateTime, Utc};
+use sentrystr::{Event, NostrSentryClient, Result, SentryStrError};
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// Event fields `--map` can fill, besides `tag.<name>` and `extra.<name>`.
+const FIELDS: &[&str] = &[
+    "level",
+    "message",
+    "environment",
+    "release",
+    "logger",
+    "server_name",
+    "transaction",
+    "timestamp",
+    "fingerprint",
+];
+
+/// Which field of a line fills which event field. Event fields that are not mapped are
+/// read from the line's field of the same name, e.g. `level` from `level`.
+#[derive(Debug, Clone, Default)]
+pub struct FieldMap {
+    sources: HashMap<String, String>,
+}
+
+impl FieldMap {
+    /// Builds the map from `(event field, source field)` pairs, as given by
+    /// `--map level=severity`. Source fields may be dotted paths into nested objects,
+    /// e.g. `log.level`.
+    pub fn new(mappings: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut sources = HashMap::new();
+        for (field, source) in mappings {
+            let known = FIELDS.contains(&field.as_str())
+                || field
+                    .strip_prefix("tag.")
+                    .is_some_and(|name| !name.is_empty())
+                || field
+                    .strip_prefix("extra.")
+                    .is_some_and(|name| !name.is_empty());
+            if !known {
+                return Err(SentryStrError::Config(format!(
+                    "Unknown event field '{}' in --map. Expected one of {}, tag.<name> or extra.<name>",
+                    field,
+                    FIELDS.join(", ")
+                )));
+            }
+            sources.insert(field, source);
+        }
+        Ok(Self { sources })
+    }
+
+    fn source<'a>(&'a self, field: &'a str) -> &'a str {
+        self.sources.get(field).map(String::as_str).unwrap_or(field)
+    }
+}
+
+/// Why a line was not published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Skip {
+    /// Not UTF-8 or not a JSON object.
+    Malformed(String),
+    /// No value for the message field.
+    NoMessage,
+}
+
+impl std::fmt::Display for Skip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Malformed(reason) => write!(f, "malformed line: {}", reason),
+            Self::NoMessage => f.write_str("no message"),
+        }
+    }
+}
+
+/// The value at `path` in `object`: the field named `path` if there is one, else the
+/// nested field its dots lead to.
+fn lookup<'a>(object: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    if let Some(value) = object.get(path) {
+        return Some(value);
+    }
+    let mut segments = path.split('.');
+    let mut value = object.get(segments.next()?)?;
+    for segment in segments {
+        value = value.as_object()?.get(segment)?;
+    }
+    Some(value)
+}
+
+/// Strings as they are and numbers and booleans in their JSON form; `None` for the rest.
+fn as_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Builds the event of `line`. `new_event` gives an event with the command line's
+/// defaults for a message, which the line's fields then override. A level the line
+/// holds but [`sentrystr::Level`] does not know keeps the default. The line's fields
+/// that fill no event field become extras.
+pub fn map_line(
+    map: &FieldMap,
+    line: &str,
+    new_event: impl Fn(String) -> Event,
+) -> std::result::Result<Event, Skip> {
+    let object = match serde_json::from_str(line) {
+        Ok(Value::Object(object)) => object,
+        Ok(_) => return Err(Skip::Malformed("not a JSON object".to_string())),
+        Err(e) => return Err(Skip::Malformed(e.to_string())),
+    };
+    let text = |field: &str| lookup(&object, map.source(field)).and_then(as_text);
+
+    let mut event = new_event(text("message").ok_or(Skip::NoMessage)?);
+    if let Some(level) = text("level").and_then(|level| level.parse().ok()) {
+        event.level = level;
+    }
+    for (field, value) in [
+        ("environment", &mut event.environment),
+        ("release", &mut event.release),
+        ("logger", &mut event.logger),
+        ("server_name", &mut event.server_name),
+        ("transaction", &mut event.transaction),
+    ] {
+        if let Some(text) = text(field) {
+            *value = Some(text);
+        }
+    }
+    if let Some(timestamp) = text("timestamp").and_then(|t| DateTime::parse_from_rfc3339(&t).ok()) {
+        event.timestamp = timestamp.with_timezone(&Utc);
+    }
+    match lookup(&object, map.source("fingerprint")) {
+        Some(Value::Array(parts)) => {
+            event.fingerprint = Some(parts.iter().filter_map(as_text).collect());
+        }
+        Some(value) => {
+            if let Some(part) = as_text(value) {
+                event.fingerprint = Some(vec![part]);
+            }
+        }
+        None => {}
+    }
+
+    for (field, source) in &map.sources {
+        if let Some(name) = field.strip_prefix("tag.") {
+            if let Some(value) = lookup(&object, source).and_then(as_text) {
+                event.tags.insert(name.to_string(), value);
+            }
+        } else if let Some(name) = field.strip_prefix("extra.")
+            && let Some(value) = lookup(&object, source)
+        {
+            event.extra.insert(name.to_string(), value.clone());
+        }
+    }
+
+    let used: HashSet<&str> = FIELDS
+        .iter()
+        .map(|field| map.source(field))
+        .chain(map.sources.values().map(String::as_str))
+        .collect();
+    for (key, value) in object.iter() {
+        if !used.contains(key.as_str()) && !event.extra.contains_key(key) {
+            event.extra.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(event)
+}
+
+/// Counts of [`ingest`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestSummary {
+    /// Events the relays accepted.
+    pub published: u64,
+    /// Events that failed to publish, were dropped by the full queue, or were still
+    /// pending when the timeout passed.
+    pub failed: u64,
+    /// Lines that were malformed or had no message, and events the client chose not to
+    /// queue, e.g. sampled out.
+    pub skipped: u64,
+}
+
+impl std::fmt::Display for IngestSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} published, {} failed, {} skipped",
+            self.published, self.failed, self.skipped
+        )
+    }
+}
+
+/// The next line of `reader`, without its line ending, or `None` once the input ends. A
+/// line that is not UTF-8 is a [`Skip`], since one bad byte should not end the stream.
+/// Cancel safe: bytes of a partly read line stay in `buf` for the next call.
+async fn next_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    buf: &mut Vec<u8>,
+) -> std::io::Result<Option<std::result::Result<String, Skip>>> {
+    if reader.read_until(b'\n', buf).await? == 0 && buf.is_empty() {
+        return Ok(None);
+    }
+    let mut line = std::mem::take(buf);
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    Ok(Some(
+        String::from_utf8(line).map_err(|e| Skip::Malformed(e.to_string())),
+    ))
+}
+
+/// Publishes the events of the NDJSON lines of `reader` through the queue of `client`,
+/// printing a summary to stderr every `progress_every`. Once the input ends, waits up to
+/// `timeout` for the queue. Fails only when `reader` does.
+pub async fn ingest(
+    client: &NostrSentryClient,
+    map: &FieldMap,
+    mut reader: impl AsyncBufRead + Unpin,
+    new_event: impl Fn(String) -> Event,
+    progress_every: Duration,
+    timeout: Duration,
+) -> Result<IngestSummary> {
+    let mut buf = Vec::new();
+    let mut progress =
+        tokio::time::interval_at(tokio::time::Instant::now() + progress_every, progress_every);
+    let mut line_number = 0u64;
+    let mut failed = 0;
+    let mut skipped = 0;
+
+    loop {
+        tokio::select! {
+            line = next_line(&mut reader, &mut buf) => {
+                let line = line.map_err(|e| {
+                    SentryStrError::Config(format!("Failed to read stdin: {}", e))
+                })?;
+                let Some(line) = line else {
+                    break;
+                };
+                line_number += 1;
+                if line.as_ref().is_ok_and(|line| line.trim().is_empty()) {
+                    continue;
+                }
+
+                match line.and_then(|line| map_line(map, &line, &new_event)) {
+                    Ok(event) => match client.capture_event(event).await {
+                        Ok(outcome) if outcome.receipt().is_some() => {}
+                        Ok(_) => skipped += 1,
+                        Err(e) => {
+                            eprintln!("Line {}: {}", line_number, e);
+                            failed += 1;
+                        }
+                    },
+                    Err(skip) => {
+                        eprintln!("Skipping line {}: {}", line_number, skip);
+                        skipped += 1;
+                    }
+                }
+            }
+            _ = progress.tick() => {
+                eprintln!("{}", summary(client, failed, skipped));
+            }
+        }
+    }
+
+    let pending = client.flush(timeout).await;
+    if pending > 0 {
+        eprintln!("{} events still pending after {:?}", pending, timeout);
+    }
+    Ok(summary(client, failed + pending as u64, skipped))
+}
+
+fn summary(client: &NostrSentryClient, failed: u64, skipped: u64) -> IngestSummary {
+    let stats = client.queue_stats().unwrap_or_default();
+    IngestSummary {
+        published: stats.published,
+        failed: failed + stats.failed + stats.dropped,
+        skipped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+    use sentrystr::{Config, Level};
+
+    const MIXED: &[u8] = include_bytes!("../tests/fixtures/ndjson/mixed.ndjson");
+    const MAPPED: &str = include_str!("../tests/fixtures/ndjson/mapped.ndjson");
+
+    fn new_event(message: String) -> Event {
+        Event::new().with_message(message).with_level(Level::Info)
+    }
+
+    #[tokio::test]
+    async fn lines_that_are_not_utf8_are_skipped() {
+        let mut reader = MIXED;
+        let mut buf = Vec::new();
+        let mut lines = Vec::new();
+        while let Some(line) = next_line(&mut reader, &mut buf).await.unwrap() {
+            lines.push(line);
+        }
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[1], Ok(String::new()));
+        assert_eq!(lines[2], Ok("not json".to_string()));
+        assert!(matches!(lines[4], Err(Skip::Malformed(_))));
+        assert_eq!(
+            lines[5],
+            Ok(r#"{"message":"last line","release":"1.2.0"}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn mapped_fields_fill_the_event() {
+        let map = FieldMap::new([
+            ("level".to_string(), "severity".to_string()),
+            ("message".to_string(), "msg".to_string()),
+            ("logger".to_string(), "log.logger".to_string()),
+            ("timestamp".to_string(), "ts".to_string()),
+            ("tag.service".to_string(), "svc".to_string()),
+        ])
+        .unwrap();
+        let mut lines = MAPPED.lines();
+
+        let event = map_line(&map, lines.next().unwrap(), new_event).unwrap();
+        assert_eq!(event.message.as_deref(), Some("slow query"));
+        assert_eq!(event.level, Level::Warning);
+        assert_eq!(event.logger.as_deref(), Some("pg"));
+        assert_eq!(event.tags["service"], "billing");
+        assert_eq!(event.timestamp.to_rfc3339(), "2026-10-01T12:00:00+00:00");
+        assert_eq!(event.extra["duration_ms"], 812);
+        assert!(!event.extra.contains_key("msg"));
+
+        let event = map_line(&map, lines.next().unwrap(), new_event).unwrap();
+        assert_eq!(event.level, Level::Info);
+        assert_eq!(
+            event.fingerprint,
+            Some(vec!["pg".to_string(), "slow".to_string()])
+        );
+    }
+
+    #[test]
+    fn unknown_map_fields_are_rejected() {
+        assert!(FieldMap::new([("severity".to_string(), "level".to_string())]).is_err());
+        assert!(FieldMap::new([("tag.".to_string(), "service".to_string())]).is_err());
+    }
+
+    #[tokio::test]
+    async fn ingest_counts_skipped_and_undelivered_lines() {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(16, Duration::from_secs(1));
+        let client = NostrSentryClient::new(config).await.unwrap();
+
+        let summary = ingest(
+            &client,
+            &FieldMap::default(),
+            MIXED,
+            new_event,
+            Duration::from_secs(60),
+            Duration::from_millis(200),
+        )
+        .await
+        .unwrap();
+
+        // Not JSON, no message and not UTF-8; the two events never reach a relay.
+        assert_eq!(
+            summary,
+            IngestSummary {
+                published: 0,
+                failed: 2,
+                skipped: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Exit statuses and output of the `sentrystr emit` binary.
+
+use nostr::Keys;
+use nostr::ToBech32;
+use nostr_relay_builder::MockRelay;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// A relay nothing listens on.
+const UNREACHABLE: &str = "ws://127.0.0.1:9";
+
+/// Runs `sentrystr emit` with `args`, a fresh secret key and `stdin` as input. Blocks, so
+/// tests running a relay use a multi-threaded runtime.
+fn emit(args: &[&str], stdin: &str) -> Output {
+    let secret_key = Keys::generate().secret_key().to_bech32().unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sentrystr"))
+        .arg("emit")
+        .args(args)
+        .env("SENTRYSTR_SECRET_KEY", secret_key)
+        .env_remove("SENTRYSTR_RELAYS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_published_event_prints_its_id() {
+    let relay = MockRelay::run().await.unwrap();
+
+    let output = emit(
+        &[
+            "--relays",
+            &relay.url().to_string(),
+            "--message",
+            "disk full",
+        ],
+        "",
+    );
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let id = stdout(&output);
+    assert_eq!(id.trim().len(), 64);
+    assert!(id.trim().chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[test]
+fn an_undelivered_event_exits_with_3() {
+    let output = emit(
+        &[
+            "--relays",
+            UNREACHABLE,
+            "--message",
+            "disk full",
+            "--timeout",
+            "1",
+        ],
+        "",
+    );
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).starts_with("Error: "));
+    assert!(stdout(&output).is_empty());
+}
+
+#[test]
+fn a_missing_key_or_message_exits_with_1() {
+    let output = emit(&["--relays", UNREACHABLE, "--message", "-"], "\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stderr(&output).trim(),
+        "Error: Configuration error: No message on stdin"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sentrystr"))
+        .args(["emit", "--relays", UNREACHABLE, "--message", "disk full"])
+        .args(["--nsec-env", "SENTRYSTR_TEST_UNSET_KEY"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("No secret key: set SENTRYSTR_TEST_UNSET_KEY"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn ndjson_input_prints_a_summary_and_skipped_lines() {
+    let relay = MockRelay::run().await.unwrap();
+
+    let output = emit(
+        &["--relays", &relay.url().to_string(), "--stdin-ndjson"],
+        "{\"message\":\"disk full\"}\nnot json\n{\"level\":\"info\"}\n{\"message\":\"disk ok\"}\n",
+    );
+
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(stdout(&output).trim(), "2 published, 0 failed, 2 skipped");
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("Skipping line 2: malformed line"),
+        "{}",
+        stderr
+    );
+    assert!(stderr.contains("Skipping line 3: no message"), "{}", stderr);
+}
+
+#[test]
+fn undelivered_ndjson_lines_exit_with_3() {
+    let output = emit(
+        &["--relays", UNREACHABLE, "--stdin-ndjson", "--timeout", "1"],
+        "{\"message\":\"disk full\"}\n",
+    );
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(stdout(&output).trim(), "0 published, 1 failed, 0 skipped");
+}
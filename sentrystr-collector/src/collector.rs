@@ -1,23 +1,225 @@
-use crate::{EventFilter, Result};
+#[cfg(feature = "email")]
+use crate::email::{EmailConfig, EmailSink};
+use crate::sequence::{GapDetected, GapHandler, SequenceTracker};
+use crate::{EventFilter, EventPosition, Result, SortOrder};
 use chrono::{DateTime, Utc};
 use nostr::prelude::*;
 use nostr_sdk::prelude::*;
-use sentrystr::{DirectMessageBuilder, DirectMessageSender, Event, Level, MessageEvent};
+use sentrystr::{
+    ATTACHMENT_KIND, Attachment, ContentEncryption, DirectMessageBuilder, DirectMessageSender,
+    DmEncryption, Event, Level, MessageEvent, MessageFormatter, ProxyConfig, SEQUENCE_EXTRA,
+    SEQUENCE_TAG, proxy,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
-#[derive(Debug)]
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// Hard deadline around a fetch. `FETCH_TIMEOUT` only bounds waiting for relays to
+/// finish sending stored events, so a hung connection could otherwise block forever.
+const FETCH_DEADLINE: std::time::Duration = std::time::Duration::from_secs(20);
+/// Extra events requested when resuming from a cursor, since relay time bounds are
+/// inclusive and already-returned events sharing the cursor's second are dropped.
+const CURSOR_OVERLAP: usize = 50;
+
+#[derive(Debug, Clone)]
 pub struct CollectedEvent {
     pub event: Event,
     pub author: PublicKey,
     pub nostr_event_id: EventId,
+    /// `created_at` of the Nostr event, which relays filter and page on. Publishers set
+    /// it from the event timestamp, within their clock skew limits.
+    pub created_at: Timestamp,
     pub received_at: DateTime<Utc>,
+    /// Tags of the Nostr event carrying this SentryStr event (`service`, `env`, ...).
+    pub nostr_tags: Vec<Tag>,
+    /// Sequence number stamped by a publisher with `Config::with_sequence_numbers`.
+    pub seq: Option<u64>,
+}
+
+impl CollectedEvent {
+    /// `event`, parsed from `nostr_event`, received now.
+    fn new(event: Event, nostr_event: &nostr::Event) -> Self {
+        let nostr_tags: Vec<Tag> = nostr_event.tags.iter().cloned().collect();
+        let seq = nostr_tags
+            .iter()
+            .find_map(|tag| match tag.as_slice() {
+                [key, value, ..] if key == SEQUENCE_TAG => value.parse().ok(),
+                _ => None,
+            })
+            .or_else(|| event.extra.get(SEQUENCE_EXTRA).and_then(|seq| seq.as_u64()));
+
+        Self {
+            event,
+            author: nostr_event.pubkey,
+            nostr_event_id: nostr_event.id,
+            created_at: nostr_event.created_at,
+            received_at: Utc::now(),
+            nostr_tags,
+            seq,
+        }
+    }
+
+    /// Value of the first Nostr tag named `key`.
+    pub fn nostr_tag(&self, key: &str) -> Option<&str> {
+        self.nostr_tags.iter().find_map(|tag| match tag.as_slice() {
+            [tag_key, value, ..] if tag_key == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Grouping key of the event: the publisher's fingerprint when present, otherwise
+    /// the normalized message and logger.
+    pub fn fingerprint(&self) -> Vec<String> {
+        self.event
+            .fingerprint
+            .clone()
+            .unwrap_or_else(|| sentrystr::default_fingerprint(&self.event))
+    }
+
+    pub fn position(&self) -> EventPosition {
+        EventPosition {
+            created_at: self.created_at,
+            timestamp: self.event.timestamp,
+            event_id: self.nostr_event_id,
+        }
+    }
+
+    /// Fetches the attachments recorded in `event.attachment_refs` through `collector`,
+    /// in the same order. Fails when one is missing from the relays, cannot be decrypted
+    /// or does not have the recorded size.
+    pub async fn fetch_attachments(&self, collector: &EventCollector) -> Result<Vec<Attachment>> {
+        let refs = &self.event.attachment_refs;
+        if refs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids = refs
+            .iter()
+            .map(|attachment| EventId::from_hex(&attachment.event_id))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| {
+                crate::CollectorError::Collection(format!("Invalid attachment id: {}", e))
+            })?;
+        let filter = Filter::new()
+            .ids(ids)
+            .kind(Kind::Custom(ATTACHMENT_KIND))
+            .author(self.author);
+        let events = fetch_with_deadline(&collector.client, filter).await?;
+
+        refs.iter()
+            .map(|attachment_ref| {
+                let nostr_event = events
+                    .iter()
+                    .find(|event| event.id.to_hex() == attachment_ref.event_id)
+                    .ok_or_else(|| {
+                        crate::CollectorError::Collection(format!(
+                            "Attachment '{}' not found on the relays",
+                            attachment_ref.name
+                        ))
+                    })?;
+                let attachment =
+                    Attachment::from_nostr_event(&collector.decryption_keys, nostr_event)?;
+                if attachment.bytes.len() != attachment_ref.size {
+                    return Err(crate::CollectorError::Collection(format!(
+                        "Attachment '{}' has {} bytes, expected {}",
+                        attachment_ref.name,
+                        attachment.bytes.len(),
+                        attachment_ref.size
+                    )));
+                }
+                Ok(attachment)
+            })
+            .collect()
+    }
 }
 
+/// Connection state of a single relay as seen by the collector.
 #[derive(Debug, Clone)]
+pub struct RelayStatusInfo {
+    pub url: String,
+    pub connected: bool,
+    pub status: String,
+    pub connected_since: Option<DateTime<Utc>>,
+    /// When the last matching event arrived from this relay through a subscription.
+    pub last_event_at: Option<DateTime<Utc>>,
+}
+
+/// Running counters for a collector, shared through [`EventCollector::stats`] so that
+/// servers embedding the collector can export them.
+#[derive(Debug, Default)]
+pub struct CollectorStats {
+    events_fetched: AtomicU64,
+    events_received: AtomicU64,
+    relay_errors: AtomicU64,
+    parse_warnings: AtomicU64,
+    pub(crate) emails_sent: AtomicU64,
+    pub(crate) email_failures: AtomicU64,
+    pub(crate) emails_dropped: AtomicU64,
+}
+
+impl CollectorStats {
+    /// Number of SentryStr events that passed the filter in `collect_events`.
+    pub fn events_fetched(&self) -> u64 {
+        self.events_fetched.load(Ordering::Relaxed)
+    }
+
+    /// Number of SentryStr events that passed the filter of a subscription, see
+    /// `subscribe_to_events`.
+    pub fn events_received(&self) -> u64 {
+        self.events_received.load(Ordering::Relaxed)
+    }
+
+    /// Number of relay requests that failed.
+    pub fn relay_errors(&self) -> u64 {
+        self.relay_errors.load(Ordering::Relaxed)
+    }
+
+    /// Fields skipped while parsing events from other schema versions, see
+    /// `Event::parse_lenient`.
+    pub fn parse_warnings(&self) -> u64 {
+        self.parse_warnings.load(Ordering::Relaxed)
+    }
+
+    /// Emails sent by the sink of `with_email`.
+    pub fn emails_sent(&self) -> u64 {
+        self.emails_sent.load(Ordering::Relaxed)
+    }
+
+    /// Emails that still failed after their retries.
+    pub fn email_failures(&self) -> u64 {
+        self.email_failures.load(Ordering::Relaxed)
+    }
+
+    /// Events not emailed because the email sink was too far behind.
+    pub fn emails_dropped(&self) -> u64 {
+        self.emails_dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone)]
 pub struct PrivateMessageConfig {
     pub recipient_pubkey: PublicKey,
     pub min_level: Option<Level>,
-    pub use_nip17: bool,
+    pub encryption: DmEncryption,
+    /// Passed to `DirectMessageBuilder::with_template`.
+    pub template: Option<String>,
+    /// Passed to `DirectMessageBuilder::with_formatter`.
+    pub formatter: Option<MessageFormatter>,
+}
+
+impl fmt::Debug for PrivateMessageConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrivateMessageConfig")
+            .field("recipient_pubkey", &self.recipient_pubkey)
+            .field("min_level", &self.min_level)
+            .field("encryption", &self.encryption)
+            .field("template", &self.template)
+            .field("formatter", &self.formatter.as_ref().map(|_| ".."))
+            .finish()
+    }
 }
 
 /// Collects and monitors SentryStr events from Nostr relays.
@@ -39,17 +241,77 @@ pub struct PrivateMessageConfig {
 pub struct EventCollector {
     client: Client,
     keys: Keys,
+    /// Reader keys for encrypted event content; the collector's own keys by default.
+    decryption_keys: Keys,
     event_kind: u16,
     dm_sender: Option<DirectMessageSender>,
+    #[cfg(feature = "email")]
+    email: Option<EmailSink>,
+    gap_handler: Option<GapHandler>,
+    proxy: Option<ProxyConfig>,
+    last_event_at: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+    stats: Arc<CollectorStats>,
 }
 
 impl EventCollector {
     /// Creates a new EventCollector connected to the specified relays.
     pub async fn new(relays: Vec<String>) -> Result<Self> {
-        let keys = Keys::generate();
-        let client = Client::new(keys.clone());
+        Self::connect(
+            Keys::generate(),
+            &relays,
+            sentrystr::DEFAULT_EVENT_KIND,
+            None,
+        )
+        .await
+    }
+
+    /// Creates a collector connecting to `relays` through a SOCKS5 proxy, which also
+    /// allows `.onion` relays.
+    pub async fn with_proxy(relays: Vec<String>, proxy: ProxyConfig) -> Result<Self> {
+        Self::connect(
+            Keys::generate(),
+            &relays,
+            sentrystr::DEFAULT_EVENT_KIND,
+            Some(&proxy),
+        )
+        .await
+    }
+
+    /// Creates a collector with the keys, read relays, event kind and proxy of a core
+    /// `Config`. Write-only relays are not read from.
+    pub async fn from_config(config: &sentrystr::Config) -> Result<Self> {
+        let relays = config.read_relays();
+        if relays.is_empty() {
+            return Err(crate::CollectorError::Collection(
+                "At least one read relay is required".to_string(),
+            ));
+        }
+        Self::connect(
+            config.get_keys()?,
+            &relays,
+            config.event_kind,
+            config.proxy.as_ref(),
+        )
+        .await
+    }
 
-        for relay in &relays {
+    /// Creates a collector from the `SENTRYSTR_*` variables read by
+    /// [`sentrystr::Config::from_env`].
+    pub async fn from_env() -> Result<Self> {
+        let config = sentrystr::Config::from_env().map_err(sentrystr::SentryStrError::from)?;
+        Self::from_config(&config).await
+    }
+
+    async fn connect(
+        keys: Keys,
+        relays: &[String],
+        event_kind: u16,
+        proxy: Option<&ProxyConfig>,
+    ) -> Result<Self> {
+        proxy::check_onion_relays(relays, proxy)?;
+        let client = proxy::build_client(keys.clone(), proxy).await?;
+
+        for relay in relays {
             client.add_relay(relay).await?;
         }
 
@@ -57,65 +319,204 @@ impl EventCollector {
 
         Ok(Self {
             client,
+            decryption_keys: keys.clone(),
             keys,
-            event_kind: 9898,
+            event_kind,
             dm_sender: None,
+            #[cfg(feature = "email")]
+            email: None,
+            gap_handler: None,
+            proxy: proxy.cloned(),
+            last_event_at: Arc::new(Mutex::new(HashMap::new())),
+            stats: Arc::new(CollectorStats::default()),
         })
     }
 
-    pub fn with_private_messaging(mut self, config: PrivateMessageConfig) -> Result<Self> {
-        let dm_sender = DirectMessageBuilder::new()
+    /// Collects events of `event_kind` instead of the default, matching publishers
+    /// configured with `Config::with_event_kind`.
+    pub fn with_event_kind(mut self, event_kind: u16) -> Self {
+        self.event_kind = event_kind;
+        self
+    }
+
+    pub fn event_kind(&self) -> u16 {
+        self.event_kind
+    }
+
+    /// Decrypts events published with `Config::with_content_encryption` as the reader
+    /// `keys`. Encrypted events not meant for this reader are skipped.
+    pub fn with_decryption_keys(mut self, keys: Keys) -> Self {
+        self.decryption_keys = keys;
+        self
+    }
+
+    /// Calls `handler` when a subscription sees the sequence numbers of an author skip,
+    /// meaning events were lost between the publisher and this collector.
+    pub fn with_gap_handler(
+        mut self,
+        handler: impl Fn(GapDetected) + Send + Sync + 'static,
+    ) -> Self {
+        self.gap_handler = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn with_private_messaging(self, config: PrivateMessageConfig) -> Result<Self> {
+        self.with_throttled_private_messaging(config, None, None)
+    }
+
+    /// [`Self::with_private_messaging`] sending at most `rate_limit`'s message count per
+    /// window and the same alert at most once per `cooldown`, see
+    /// `DirectMessageBuilder::with_rate_limit` and `DirectMessageBuilder::with_cooldown`.
+    pub fn with_throttled_private_messaging(
+        mut self,
+        config: PrivateMessageConfig,
+        rate_limit: Option<(u32, std::time::Duration)>,
+        cooldown: Option<std::time::Duration>,
+    ) -> Result<Self> {
+        let mut builder = DirectMessageBuilder::new()
             .with_client(self.client.clone())
             .with_keys(self.keys.clone())
             .with_recipient(config.recipient_pubkey)
             .with_min_level(config.min_level.unwrap_or(Level::Debug))
-            .with_nip17(config.use_nip17)
-            .build()
-            .map_err(|e| {
-                crate::CollectorError::Collection(format!("Failed to create DM sender: {}", e))
-            })?;
+            .with_encryption(config.encryption);
+        if let Some(ref template) = config.template {
+            builder = builder.with_template(template);
+        }
+        if let Some(formatter) = config.formatter {
+            builder = builder.with_shared_formatter(formatter);
+        }
+        if let Some((max_messages, per)) = rate_limit {
+            builder = builder.with_rate_limit(max_messages, per);
+        }
+        if let Some(cooldown) = cooldown {
+            builder = builder.with_cooldown(cooldown);
+        }
+
+        let dm_sender = builder.build().map_err(|e| {
+            crate::CollectorError::Collection(format!("Failed to create DM sender: {}", e))
+        })?;
 
         self.dm_sender = Some(dm_sender);
         Ok(self)
     }
 
-    pub async fn collect_events(&self, filter: EventFilter) -> Result<Vec<CollectedEvent>> {
+    /// Emails the events received through [`EventCollector::subscribe_to_events`] at or
+    /// above `config.min_level`, one per event or as a digest per `config.digest`.
+    /// Sending happens in the background and never slows the subscription; outcomes are
+    /// counted in [`CollectorStats`]. Needs a Tokio runtime.
+    #[cfg(feature = "email")]
+    pub fn with_email(mut self, config: EmailConfig) -> Result<Self> {
+        self.email = Some(EmailSink::spawn(config, Arc::clone(&self.stats))?);
+        Ok(self)
+    }
+
+    /// A DM sender that publishes through this collector's relays and keys, the same
+    /// path [`EventCollector::with_private_messaging`] alerts take.
+    pub fn direct_message_sender(
+        &self,
+        recipient: PublicKey,
+        encryption: DmEncryption,
+    ) -> Result<DirectMessageSender> {
+        DirectMessageBuilder::new()
+            .with_client(self.client.clone())
+            .with_keys(self.keys.clone())
+            .with_recipient(recipient)
+            .with_encryption(encryption)
+            .build()
+            .map_err(|e| {
+                crate::CollectorError::Collection(format!("Failed to create DM sender: {}", e))
+            })
+    }
+
+    fn nostr_filter(&self, filter: &EventFilter) -> Filter {
         let mut nostr_filter = Filter::new().kind(Kind::Custom(self.event_kind));
 
-        if let Some(ref authors) = filter.authors {
+        if let Some(ref authors) = filter.authors
+            && !authors.is_empty()
+        {
             let author_keys: Vec<PublicKey> = authors.iter().cloned().collect();
             nostr_filter = nostr_filter.authors(author_keys);
         }
 
-        if let Some(since) = filter.since {
-            nostr_filter = nostr_filter.since(Timestamp::from_secs(since.timestamp() as u64));
+        let mut since = filter
+            .since
+            .map(|since| Timestamp::from_secs(since.timestamp() as u64));
+        let mut until = filter
+            .until
+            .map(|until| Timestamp::from_secs(until.timestamp() as u64));
+
+        // Narrow the relay window to the cursor's `created_at`, which relays compare
+        // inclusively; the exact position is checked locally.
+        if let Some(ref after) = filter.after {
+            match filter.sort {
+                SortOrder::Asc => {
+                    since = Some(since.map_or(after.created_at, |s| s.max(after.created_at)));
+                }
+                SortOrder::Desc => {
+                    until = Some(until.map_or(after.created_at, |u| u.min(after.created_at)));
+                }
+            }
         }
 
-        if let Some(until) = filter.until {
-            nostr_filter = nostr_filter.until(Timestamp::from_secs(until.timestamp() as u64));
+        if let Some(since) = since {
+            nostr_filter = nostr_filter.since(since);
         }
 
-        if let Some(limit) = filter.limit {
-            nostr_filter = nostr_filter.limit(limit);
+        if let Some(until) = until {
+            nostr_filter = nostr_filter.until(until);
         }
 
-        let events = self
-            .client
-            .fetch_events(nostr_filter, std::time::Duration::from_secs(10))
-            .await?;
+        if let Some(ref search) = filter.search {
+            nostr_filter = nostr_filter.search(search);
+        }
+
+        // Relays only index single-letter tags; the others are matched locally.
+        for (key, value) in filter.nostr_tags.iter().flatten() {
+            let mut letters = key.chars();
+            if let (Some(letter), None) = (letters.next(), letters.next())
+                && let Ok(tag) = SingleLetterTag::from_char(letter)
+            {
+                nostr_filter = nostr_filter.custom_tag(tag, value.clone());
+            }
+        }
+
+        nostr_filter
+    }
+
+    /// Fetches matching events ordered by `filter.sort` and truncated to `filter.limit`.
+    ///
+    /// Relays answer limited queries with the newest events, so ascending queries fetch
+    /// the whole `since`..`until` window before truncating; bound it with `since`.
+    pub async fn collect_events(&self, filter: EventFilter) -> Result<Vec<CollectedEvent>> {
+        if filter.matches_nothing() {
+            return Ok(Vec::new());
+        }
+        let mut nostr_filter = self.nostr_filter(&filter);
+
+        if let Some(limit) = filter.limit
+            && filter.sort == SortOrder::Desc
+        {
+            let overlap = if filter.after.is_some() {
+                CURSOR_OVERLAP
+            } else {
+                0
+            };
+            nostr_filter = nostr_filter.limit(limit + overlap);
+        }
+
+        let events = fetch_with_deadline(&self.client, nostr_filter)
+            .await
+            .inspect_err(|_| {
+                self.stats.relay_errors.fetch_add(1, Ordering::Relaxed);
+            })?;
 
         let mut collected_events = Vec::new();
 
         for event in events {
-            if let Ok(parsed_event) = serde_json::from_str::<Event>(&event.content)
+            if let Some(parsed_event) = parse_event(&self.decryption_keys, &event, &self.stats)
                 && filter.matches_nostr_event(&parsed_event, &event.pubkey, &event)
             {
-                let collected_event = CollectedEvent {
-                    event: parsed_event.clone(),
-                    author: event.pubkey,
-                    nostr_event_id: event.id,
-                    received_at: Utc::now(),
-                };
+                let collected_event = CollectedEvent::new(parsed_event.clone(), &event);
 
                 // Send private message if configured
                 if let Some(ref dm_sender) = self.dm_sender {
@@ -135,18 +536,144 @@ impl EventCollector {
             }
         }
 
+        collected_events.sort_by_key(|event| event.position());
+        if filter.sort == SortOrder::Desc {
+            collected_events.reverse();
+        }
+
+        if let Some(limit) = filter.limit {
+            collected_events.truncate(limit);
+        }
+
+        self.stats
+            .events_fetched
+            .fetch_add(collected_events.len() as u64, Ordering::Relaxed);
+
         Ok(collected_events)
     }
 
+    /// Fetches matching events page by page, newest first regardless of `filter.sort`,
+    /// and sends each event as soon as its page arrives. `filter.limit` caps the total
+    /// across pages; without it the stream runs until the relays have no older events. A
+    /// relay error ends the stream with an `Err` item.
+    pub fn stream_events(
+        &self,
+        filter: EventFilter,
+        page_size: usize,
+    ) -> mpsc::Receiver<Result<CollectedEvent>> {
+        let (tx, rx) = mpsc::channel(page_size.max(1));
+        if filter.matches_nothing() {
+            return rx;
+        }
+
+        let client = self.client.clone();
+        let base_filter = self.nostr_filter(&filter);
+        let dm_sender = self.dm_sender.clone();
+        let decryption_keys = self.decryption_keys.clone();
+        let stats = Arc::clone(&self.stats);
+
+        tokio::spawn(async move {
+            let mut remaining = filter.limit.unwrap_or(usize::MAX);
+            let mut until = filter
+                .until
+                .map(|until| Timestamp::from_secs(until.timestamp() as u64));
+            let mut seen = HashSet::new();
+
+            while remaining > 0 {
+                // Whole pages, even near the limit: events of the boundary second that
+                // were already sent come back and take room in them.
+                let page_limit = page_size.max(1);
+                let mut page_filter = base_filter.clone().limit(page_limit);
+                if let Some(until) = until {
+                    page_filter = page_filter.until(until);
+                }
+
+                let events = match fetch_with_deadline(&client, page_filter).await {
+                    Ok(events) => events,
+                    Err(e) => {
+                        stats.relay_errors.fetch_add(1, Ordering::Relaxed);
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+                let fetched = events.len();
+                let oldest = events.iter().map(|event| event.created_at).min();
+                let mut new_events = 0;
+
+                for event in events {
+                    // Pages overlap on the boundary second, so skip events already sent.
+                    if !seen.insert(event.id) {
+                        continue;
+                    }
+                    new_events += 1;
+
+                    let Some(parsed_event) = parse_event(&decryption_keys, &event, &stats) else {
+                        continue;
+                    };
+                    if !filter.matches_nostr_event(&parsed_event, &event.pubkey, &event) {
+                        continue;
+                    }
+
+                    if let Some(ref dm_sender) = dm_sender {
+                        let message_event = MessageEvent {
+                            event: parsed_event.clone(),
+                            author: event.pubkey,
+                            nostr_event_id: event.id,
+                            received_at: Utc::now(),
+                        };
+
+                        if let Err(e) = dm_sender.send_message_for_event(&message_event).await {
+                            eprintln!("Failed to send direct message: {}", e);
+                        }
+                    }
+
+                    stats.events_fetched.fetch_add(1, Ordering::Relaxed);
+                    let collected_event = CollectedEvent::new(parsed_event, &event);
+
+                    if tx.send(Ok(collected_event)).await.is_err() {
+                        return;
+                    }
+
+                    remaining -= 1;
+                    if remaining == 0 {
+                        break;
+                    }
+                }
+
+                until = match oldest {
+                    Some(oldest) if new_events > 0 => Some(oldest),
+                    // A full page of one second already sent: the rest of that second
+                    // cannot be paged to, so carry on before it.
+                    Some(oldest) if fetched >= page_limit && oldest.as_u64() > 0 => {
+                        Some(Timestamp::from_secs(oldest.as_u64() - 1))
+                    }
+                    _ => break,
+                };
+            }
+        });
+
+        rx
+    }
+
+    /// Streams live events matching `filter`. The relay subscription is closed once the
+    /// receiver is dropped.
     pub async fn subscribe_to_events(
         &self,
         filter: EventFilter,
     ) -> Result<mpsc::Receiver<CollectedEvent>> {
         let (tx, rx) = mpsc::channel(1000);
+        if filter.matches_nothing() {
+            // Stays open like any quiet subscription until the receiver is dropped.
+            tokio::spawn(async move { tx.closed().await });
+            return Ok(rx);
+        }
 
         let mut nostr_filter = Filter::new().kind(Kind::Custom(self.event_kind));
 
-        if let Some(ref authors) = filter.authors {
+        if let Some(ref authors) = filter.authors
+            && !authors.is_empty()
+        {
             let author_keys: Vec<PublicKey> = authors.iter().cloned().collect();
             nostr_filter = nostr_filter.authors(author_keys);
         }
@@ -159,28 +686,51 @@ impl EventCollector {
 
         let client_clone = self.client.clone();
         let _keys_clone = self.keys.clone();
+        let decryption_keys = self.decryption_keys.clone();
         let filter_clone = filter.clone();
         let dm_sender_clone = self.dm_sender.clone();
+        #[cfg(feature = "email")]
+        let email = self.email.clone();
+        let last_event_at = Arc::clone(&self.last_event_at);
+        let gap_handler = self.gap_handler.clone();
+        let stats = Arc::clone(&self.stats);
 
         tokio::spawn(async move {
             let mut notifications = client_clone.notifications();
+            let mut sequences = SequenceTracker::new();
+
+            loop {
+                // Stop as soon as the receiver is dropped, not on the next matching event.
+                let notification = tokio::select! {
+                    _ = tx.closed() => break,
+                    notification = notifications.recv() => match notification {
+                        Ok(notification) => notification,
+                        Err(_) => break,
+                    },
+                };
 
-            while let Ok(notification) = notifications.recv().await {
                 if let RelayPoolNotification::Event {
+                    relay_url,
                     subscription_id: sub_id,
                     event,
                     ..
                 } = notification
                     && sub_id == subscription_id.val
-                    && let Ok(parsed_event) = serde_json::from_str::<Event>(&event.content)
+                    && let Some(parsed_event) = parse_event(&decryption_keys, &event, &stats)
                     && filter_clone.matches_nostr_event(&parsed_event, &event.pubkey, &event)
                 {
-                    let collected_event = CollectedEvent {
-                        event: parsed_event.clone(),
-                        author: event.pubkey,
-                        nostr_event_id: event.id,
-                        received_at: Utc::now(),
-                    };
+                    last_event_at
+                        .lock()
+                        .unwrap()
+                        .insert(relay_url.to_string(), Utc::now());
+                    stats.events_received.fetch_add(1, Ordering::Relaxed);
+
+                    let collected_event = CollectedEvent::new(parsed_event.clone(), &event);
+                    if let Some(gap) = sequences.observe(&collected_event)
+                        && let Some(ref gap_handler) = gap_handler
+                    {
+                        gap_handler(gap);
+                    }
 
                     if let Some(ref dm_sender) = dm_sender_clone {
                         let message_event = MessageEvent {
@@ -195,11 +745,18 @@ impl EventCollector {
                         }
                     }
 
+                    #[cfg(feature = "email")]
+                    if let Some(ref email) = email {
+                        email.notify(&collected_event);
+                    }
+
                     if tx.send(collected_event).await.is_err() {
                         break;
                     }
                 }
             }
+
+            client_clone.unsubscribe(&subscription_id.val).await;
         });
 
         Ok(rx)
@@ -217,8 +774,396 @@ impl EventCollector {
         self.collect_events(filter).await
     }
 
+    pub fn stats(&self) -> Arc<CollectorStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Reports the connection state of every relay in the pool.
+    pub async fn relay_status(&self) -> Vec<RelayStatusInfo> {
+        let last_event_at = self.last_event_at.lock().unwrap().clone();
+
+        self.client
+            .relays()
+            .await
+            .into_iter()
+            .map(|(url, relay)| {
+                let status = relay.status();
+                let connected_at = relay.stats().connected_at().as_u64();
+                let url = url.to_string();
+
+                RelayStatusInfo {
+                    connected: relay.is_connected(),
+                    status: status.to_string(),
+                    connected_since: if connected_at > 0 {
+                        DateTime::from_timestamp(connected_at as i64, 0)
+                    } else {
+                        None
+                    },
+                    last_event_at: last_event_at.get(&url).cloned(),
+                    url,
+                }
+            })
+            .collect()
+    }
+
+    pub async fn add_relay(&self, relay: &str) -> Result<()> {
+        proxy::check_onion_relays([&relay.to_string()], self.proxy.as_ref())?;
+        self.client.add_relay(relay).await?;
+        self.client.connect_relay(relay).await?;
+        Ok(())
+    }
+
+    pub async fn remove_relay(&self, relay: &str) -> Result<()> {
+        self.client.remove_relay(relay).await?;
+        Ok(())
+    }
+
     pub async fn disconnect(&self) -> Result<()> {
         self.client.disconnect().await;
         Ok(())
     }
 }
+
+/// Parses the SentryStr event in `event` leniently, decrypting its content when it is
+/// encrypted, so events of older and newer publishers are kept; skipped fields are
+/// counted in `stats`.
+fn parse_event(
+    decryption_keys: &Keys,
+    event: &nostr::Event,
+    stats: &CollectorStats,
+) -> Option<Event> {
+    let content = if ContentEncryption::scheme(event).is_some() {
+        ContentEncryption::decrypt_content(decryption_keys, event).ok()?
+    } else {
+        event.content.clone()
+    };
+
+    let (parsed, warnings) = Event::parse_lenient(&content).ok()?;
+    stats
+        .parse_warnings
+        .fetch_add(warnings.len() as u64, Ordering::Relaxed);
+    Some(parsed)
+}
+
+async fn fetch_with_deadline(client: &Client, filter: Filter) -> Result<Events> {
+    match tokio::time::timeout(FETCH_DEADLINE, client.fetch_events(filter, FETCH_TIMEOUT)).await {
+        Ok(events) => Ok(events?),
+        Err(_) => Err(crate::CollectorError::Timeout(format!(
+            "No response from relays within {}s",
+            FETCH_DEADLINE.as_secs()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr_relay_builder::MockRelay;
+    use sentrystr::{Config, NostrSentryClient, RelaySpec};
+
+    #[tokio::test]
+    async fn encrypted_events_are_collected_by_their_readers() {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let reader = Keys::generate();
+        let config = Config::from_keys(&Keys::generate(), [url.as_str()])
+            .unwrap()
+            .with_content_encryption(ContentEncryption::Nip44 {
+                reader_pubkeys: vec![reader.public_key()],
+            })
+            .with_plaintext_tags(["level"]);
+        let client = NostrSentryClient::new(config).await.unwrap();
+        client
+            .capture_event(
+                Event::new()
+                    .with_message("card declined")
+                    .with_level(Level::Error),
+            )
+            .await
+            .unwrap();
+
+        let filter = EventFilter::new().with_plaintext_tag("level", "error");
+        let collected = EventCollector::new(vec![url.clone()])
+            .await
+            .unwrap()
+            .with_decryption_keys(reader)
+            .collect_events(filter.clone())
+            .await
+            .unwrap();
+        assert_eq!(collected.len(), 1);
+        assert_eq!(collected[0].event.message.as_deref(), Some("card declined"));
+
+        let outsider = EventCollector::new(vec![url])
+            .await
+            .unwrap()
+            .collect_events(filter)
+            .await
+            .unwrap();
+        assert!(outsider.is_empty());
+    }
+
+    #[tokio::test]
+    async fn collectors_from_a_config_read_its_read_relays_only() {
+        let writable = MockRelay::run().await.unwrap();
+        let readable = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let publisher =
+            NostrSentryClient::new(Config::from_keys(&keys, [writable.url().to_string()]).unwrap())
+                .await
+                .unwrap();
+        publisher
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+
+        let roles = Config::from_keys(
+            &keys,
+            [
+                RelaySpec::write_only(writable.url().to_string()),
+                RelaySpec::read_only(readable.url().to_string()),
+            ],
+        )
+        .unwrap();
+        let collected = EventCollector::from_config(&roles)
+            .await
+            .unwrap()
+            .collect_events(EventFilter::new())
+            .await
+            .unwrap();
+        assert!(collected.is_empty());
+
+        let swapped = Config::from_keys(
+            &keys,
+            [
+                RelaySpec::read_only(writable.url().to_string()),
+                RelaySpec::write_only(readable.url().to_string()),
+            ],
+        )
+        .unwrap();
+        let collected = EventCollector::from_config(&swapped)
+            .await
+            .unwrap()
+            .collect_events(EventFilter::new())
+            .await
+            .unwrap();
+        assert_eq!(collected.len(), 1);
+        assert_eq!(
+            collected[0].event.message.as_deref(),
+            Some("payment failed")
+        );
+
+        let write_only = Config::from_keys(&keys, [RelaySpec::write_only("wss://relay.one")]);
+        assert!(
+            EventCollector::from_config(&write_only.unwrap())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_event_kinds_are_published_and_collected_apart() {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let keys = Keys::generate();
+        let custom = Config::from_keys(&keys, [url.as_str()])
+            .unwrap()
+            .with_event_kind(9123);
+        for (config, message) in [
+            (custom.clone(), "custom kind"),
+            (
+                Config::from_keys(&keys, [url.as_str()]).unwrap(),
+                "default kind",
+            ),
+        ] {
+            NostrSentryClient::new(config)
+                .await
+                .unwrap()
+                .capture_message(Level::Error, message)
+                .await
+                .unwrap();
+        }
+
+        let messages = |collected: Vec<CollectedEvent>| -> Vec<Option<String>> {
+            collected
+                .into_iter()
+                .map(|collected| collected.event.message)
+                .collect()
+        };
+        let from_config = EventCollector::from_config(&custom).await.unwrap();
+        assert_eq!(
+            messages(
+                from_config
+                    .collect_events(EventFilter::new())
+                    .await
+                    .unwrap()
+            ),
+            [Some("custom kind".to_string())]
+        );
+        let by_kind = EventCollector::new(vec![url.clone()])
+            .await
+            .unwrap()
+            .with_event_kind(9123);
+        assert_eq!(
+            messages(by_kind.collect_events(EventFilter::new()).await.unwrap()),
+            [Some("custom kind".to_string())]
+        );
+        let default = EventCollector::new(vec![url]).await.unwrap();
+        assert_eq!(
+            messages(default.collect_events(EventFilter::new()).await.unwrap()),
+            [Some("default kind".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn dropping_the_receiver_closes_the_subscription() {
+        let collector = EventCollector::new(vec!["wss://relay.example.com".to_string()])
+            .await
+            .unwrap();
+
+        let events = collector
+            .subscribe_to_events(EventFilter::new())
+            .await
+            .unwrap();
+        assert_eq!(collector.client.subscriptions().await.len(), 1);
+
+        drop(events);
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(collector.client.subscriptions().await.is_empty());
+    }
+
+    async fn streamed_messages(
+        collector: &EventCollector,
+        filter: EventFilter,
+        page_size: usize,
+    ) -> Vec<String> {
+        let mut events = collector.stream_events(filter, page_size);
+        let mut messages = Vec::new();
+        while let Some(event) = events.recv().await {
+            messages.extend(event.unwrap().event.message);
+        }
+        messages
+    }
+
+    #[tokio::test]
+    async fn streams_page_through_every_event() {
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        let config = Config::from_keys(&Keys::generate(), [url.as_str()]).unwrap();
+        let client = NostrSentryClient::new(config).await.unwrap();
+        let now = Utc::now();
+        for age in 1..=5 {
+            client
+                .capture_event(
+                    Event::new()
+                        .with_message(format!("{}s ago", age))
+                        .with_timestamp(now - chrono::Duration::seconds(age)),
+                )
+                .await
+                .unwrap();
+        }
+        let collector = EventCollector::new(vec![url]).await.unwrap();
+
+        assert_eq!(
+            streamed_messages(&collector, EventFilter::new(), 2).await,
+            ["1s ago", "2s ago", "3s ago", "4s ago", "5s ago"]
+        );
+        assert_eq!(
+            streamed_messages(&collector, EventFilter::new().with_limit(3), 2).await,
+            ["1s ago", "2s ago", "3s ago"]
+        );
+    }
+
+    #[tokio::test]
+    async fn cursors_narrow_the_relay_window_by_created_at() {
+        let collector = EventCollector::new(vec!["wss://relay.example.com".to_string()])
+            .await
+            .unwrap();
+        // Clamped by the publisher's skew limit, so `created_at` is a day later.
+        let after = EventPosition {
+            created_at: Timestamp::from_secs(1_704_153_600),
+            timestamp: "2024-01-01T00:00:00Z".parse().unwrap(),
+            event_id: EventId::all_zeros(),
+        };
+
+        let desc = EventFilter::new().with_after(after.clone());
+        assert_eq!(collector.nostr_filter(&desc).until, Some(after.created_at));
+
+        let asc = EventFilter::new()
+            .with_sort(SortOrder::Asc)
+            .with_after(after.clone());
+        assert_eq!(collector.nostr_filter(&asc).since, Some(after.created_at));
+    }
+
+    #[tokio::test]
+    async fn filters_without_authors_skip_the_relays() {
+        let collector = EventCollector::new(vec!["wss://relay.example.com".to_string()])
+            .await
+            .unwrap();
+        let mut filter = EventFilter::new();
+        filter.authors = Some(HashSet::new());
+
+        assert!(collector.nostr_filter(&filter).authors.is_none());
+        let collected = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            collector.collect_events(filter.clone()),
+        )
+        .await
+        .expect("no relay was queried");
+        assert!(collected.unwrap().is_empty());
+        assert!(
+            collector
+                .stream_events(filter.clone(), 10)
+                .recv()
+                .await
+                .is_none()
+        );
+
+        let mut live = collector.subscribe_to_events(filter).await.unwrap();
+        assert!(collector.client.subscriptions().await.is_empty());
+        assert!(live.try_recv().is_err());
+        assert!(!live.is_closed());
+    }
+
+    #[tokio::test]
+    async fn fingerprint_filters_are_sent_to_relays() {
+        let collector = EventCollector::new(vec!["wss://relay.example.com".to_string()])
+            .await
+            .unwrap();
+        let filter = EventFilter::new()
+            .with_fingerprint_filter("payments|provider-timeout".to_string())
+            .with_service_filter("billing".to_string());
+
+        let nostr_filter = collector.nostr_filter(&filter);
+
+        let tag = SingleLetterTag::lowercase(Alphabet::F);
+        assert_eq!(
+            nostr_filter.generic_tags.get(&tag),
+            Some(&std::collections::BTreeSet::from([
+                "payments|provider-timeout".to_string()
+            ]))
+        );
+        // Multi-letter tags are not indexed by relays.
+        assert_eq!(nostr_filter.generic_tags.len(), 1);
+    }
+
+    #[test]
+    fn the_publisher_fingerprint_is_preferred() {
+        let nostr_event = EventBuilder::text_note("")
+            .sign_with_keys(&Keys::generate())
+            .unwrap();
+        let published = CollectedEvent::new(
+            Event::new()
+                .with_message("timeout after 30s")
+                .with_fingerprint(["payments"]),
+            &nostr_event,
+        );
+        let derived =
+            CollectedEvent::new(Event::new().with_message("timeout after 30s"), &nostr_event);
+
+        assert_eq!(published.fingerprint(), ["payments"]);
+        assert_eq!(
+            derived.fingerprint(),
+            sentrystr::default_fingerprint(&derived.event)
+        );
+    }
+}
@@ -0,0 +1,571 @@
+//! Email alerts for collected events, see [`crate::EventCollector::with_email`].
+//!
+//! Events are handed to a background task over a bounded channel, so a slow or
+//! unreachable SMTP server never holds up the subscription: when the channel is full the
+//! event is dropped from email and counted in [`CollectorStats::emails_dropped`].
+
+use crate::{CollectedEvent, CollectorError, CollectorStats, Result};
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use sentrystr::{EventDigest, Level, RetryPolicy};
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Events waiting for the email task before new ones are dropped.
+const EMAIL_QUEUE_SIZE: usize = 1000;
+
+/// Distinct messages listed in a digest email.
+const DIGEST_MAX_ITEMS: usize = 20;
+
+/// Characters of the message kept in a subject line.
+const SUBJECT_MESSAGE_CHARS: usize = 80;
+
+/// How the connection to the SMTP server is secured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTls {
+    /// Plain text, for local relays only.
+    None,
+    /// Upgrade a plain connection with STARTTLS, usually on port 587.
+    #[default]
+    StartTls,
+    /// TLS from the start, usually on port 465.
+    Tls,
+}
+
+impl FromStr for EmailTls {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "starttls" => Ok(Self::StartTls),
+            "tls" => Ok(Self::Tls),
+            _ => Err(format!(
+                "Invalid email TLS mode '{}'. Expected none, starttls or tls",
+                s
+            )),
+        }
+    }
+}
+
+/// SMTP login. Its `Debug` output hides the password.
+#[derive(Clone, Deserialize)]
+pub struct EmailCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl std::fmt::Debug for EmailCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailCredentials")
+            .field("username", &self.username)
+            .field("password", &"[redacted]")
+            .finish()
+    }
+}
+
+/// Where and when [`crate::EventCollector::with_email`] sends emails. Deserializes from
+/// the JSON of `--email-config`, with `digest` as a duration such as `15m`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmailConfig {
+    pub smtp_host: String,
+    /// The default port of `tls` when unset.
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: EmailTls,
+    #[serde(default)]
+    pub credentials: Option<EmailCredentials>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Events below this level are not emailed; all are when unset.
+    #[serde(default)]
+    pub min_level: Option<Level>,
+    /// Sends one digest email per interval instead of one email per event. Must not be
+    /// zero.
+    #[serde(default, deserialize_with = "deserialize_interval")]
+    pub digest: Option<Duration>,
+    /// Retries of a failed send. Not read from config files.
+    #[serde(skip)]
+    pub retry: RetryPolicy,
+}
+
+impl EmailConfig {
+    /// Reads the JSON config file at `path`.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            CollectorError::Email(format!("Failed to read {}: {}", path.display(), e))
+        })?;
+        Ok(serde_json::from_str(&text)?)
+    }
+}
+
+fn deserialize_interval<'de, D>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_interval(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Parses a digest interval such as `15m` or `1h`, see [`crate::parse_duration`].
+pub fn parse_interval(value: &str) -> std::result::Result<Duration, String> {
+    crate::parse_duration(value)
+        .and_then(|interval| interval.to_std().ok())
+        .filter(|interval| !interval.is_zero())
+        .ok_or_else(|| {
+            format!(
+                "Invalid digest interval '{}'. Expected a duration like 15m or 1h",
+                value
+            )
+        })
+}
+
+/// Sends emails for the events it is notified of, see
+/// [`crate::EventCollector::with_email`]. Clones share the same background task, which
+/// ends, sending what is left of a digest, once every clone is dropped.
+#[derive(Clone)]
+pub struct EmailSink {
+    tx: mpsc::Sender<CollectedEvent>,
+    min_level: Level,
+    stats: Arc<CollectorStats>,
+}
+
+impl EmailSink {
+    /// Checks `config` and starts the task sending the emails. Needs a Tokio runtime.
+    pub fn spawn(config: EmailConfig, stats: Arc<CollectorStats>) -> Result<Self> {
+        if config.digest.is_some_and(|interval| interval.is_zero()) {
+            return Err(CollectorError::Email(
+                "The digest interval must be more than 0".to_string(),
+            ));
+        }
+        let mailer = Mailer::new(&config)?;
+        let (tx, rx) = mpsc::channel(EMAIL_QUEUE_SIZE);
+
+        tokio::spawn(run(
+            rx,
+            mailer,
+            config.retry,
+            config.digest,
+            Arc::clone(&stats),
+        ));
+
+        Ok(Self {
+            tx,
+            min_level: config.min_level.unwrap_or(Level::Debug),
+            stats,
+        })
+    }
+
+    /// Queues `event` for email if it is at or above the minimum level. Never waits.
+    pub fn notify(&self, event: &CollectedEvent) {
+        if event.event.level < self.min_level {
+            return;
+        }
+        if self.tx.try_send(event.clone()).is_err() {
+            self.stats.emails_dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn run(
+    mut rx: mpsc::Receiver<CollectedEvent>,
+    mailer: Mailer,
+    retry: RetryPolicy,
+    digest_interval: Option<Duration>,
+    stats: Arc<CollectorStats>,
+) {
+    let Some(interval) = digest_interval else {
+        while let Some(event) = rx.recv().await {
+            mailer
+                .send(&subject(&event), body(&event), &retry, &stats)
+                .await;
+        }
+        return;
+    };
+
+    let mut digest = EventDigest::new(DIGEST_MAX_ITEMS);
+    let mut ticks = tokio::time::interval(interval);
+    ticks.tick().await;
+    loop {
+        tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => digest.record(&event.event),
+                None => break,
+            },
+            _ = ticks.tick() => {
+                if let Some(text) = digest.take() {
+                    mailer.send(&digest_subject(&text), text, &retry, &stats).await;
+                }
+            }
+        }
+    }
+    if let Some(text) = digest.take() {
+        mailer
+            .send(&digest_subject(&text), text, &retry, &stats)
+            .await;
+    }
+}
+
+/// The SMTP transport and the addresses of every email.
+struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Mailer {
+    fn new(config: &EmailConfig) -> Result<Self> {
+        let from = parse_mailbox(&config.from)?;
+        let to = config
+            .to
+            .iter()
+            .map(|address| parse_mailbox(address))
+            .collect::<Result<Vec<_>>>()?;
+        if to.is_empty() {
+            return Err(CollectorError::Email(
+                "At least one recipient is required".to_string(),
+            ));
+        }
+
+        let host = &config.smtp_host;
+        let mut builder = match config.tls {
+            EmailTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            EmailTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| CollectorError::Email(e.to_string()))?,
+            EmailTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .map_err(|e| CollectorError::Email(e.to_string()))?,
+        };
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let Some(ref credentials) = config.credentials {
+            builder = builder.credentials(Credentials::new(
+                credentials.username.clone(),
+                credentials.password.clone(),
+            ));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+        })
+    }
+
+    /// Sends an email, retrying with the backoff of `retry`, and counts the outcome.
+    async fn send(&self, subject: &str, body: String, retry: &RetryPolicy, stats: &CollectorStats) {
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        let message = match builder.body(body) {
+            Ok(message) => message,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to build email");
+                stats.email_failures.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let attempts = retry.max_attempts.max(1);
+        for attempt in 1..=attempts {
+            match self.transport.send(message.clone()).await {
+                Ok(_) => {
+                    stats.emails_sent.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Err(e) if attempt < attempts => {
+                    tracing::warn!(attempt, error = %e, "Failed to send email, retrying");
+                    tokio::time::sleep(retry.delay(attempt)).await;
+                }
+                Err(e) => {
+                    tracing::error!(attempts, error = %e, "Failed to send email, giving up");
+                }
+            }
+        }
+        stats.email_failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox> {
+    address
+        .parse()
+        .map_err(|e| CollectorError::Email(format!("Invalid email address '{}': {}", address, e)))
+}
+
+/// The service of `event`: its `service` tag, then its logger.
+fn service(event: &CollectedEvent) -> Option<&str> {
+    event
+        .nostr_tag("service")
+        .or_else(|| event.event.tags.get("service").map(String::as_str))
+        .or(event.event.logger.as_deref())
+}
+
+/// `[ERROR] service: message`, the message cut to one line of at most
+/// [`SUBJECT_MESSAGE_CHARS`] characters.
+fn subject(event: &CollectedEvent) -> String {
+    let message = event.event.message.as_deref().unwrap_or("(no message)");
+    let mut lines = message.lines();
+    let line = lines.next().unwrap_or_default();
+    let mut message: String = line.chars().take(SUBJECT_MESSAGE_CHARS).collect();
+    if message.len() < line.len() || lines.next().is_some() {
+        message.push_str("...");
+    }
+
+    let level = event.event.level.to_string().to_uppercase();
+    match service(event) {
+        Some(service) => format!("[{}] {}: {}", level, service, message),
+        None => format!("[{}] {}", level, message),
+    }
+}
+
+/// The first line of a digest, which counts its events.
+fn digest_subject(text: &str) -> String {
+    text.lines()
+        .next()
+        .unwrap_or("SentryStr Digest")
+        .to_string()
+}
+
+/// The fields, tags, extras and exceptions of `event` as plain text.
+fn body(event: &CollectedEvent) -> String {
+    let e = &event.event;
+    let mut body = format!(
+        "{}\n\nLevel: {}\nTime: {}\n",
+        e.message.as_deref().unwrap_or("(no message)"),
+        e.level,
+        e.timestamp
+    );
+    for (name, value) in [
+        ("Service", service(event)),
+        ("Environment", e.environment.as_deref()),
+        ("Release", e.release.as_deref()),
+        ("Server", e.server_name.as_deref()),
+        ("Logger", e.logger.as_deref()),
+        ("Transaction", e.transaction.as_deref()),
+    ] {
+        if let Some(value) = value {
+            body.push_str(&format!("{}: {}\n", name, value));
+        }
+    }
+    body.push_str(&format!(
+        "Author: {}\nNostr event: {}\n",
+        event.author.to_hex(),
+        event.nostr_event_id.to_hex()
+    ));
+
+    if let Some(ref exceptions) = e.exception {
+        body.push_str("\nExceptions:\n");
+        for exception in exceptions {
+            body.push_str(&format!(
+                "  {}: {}\n",
+                exception.exception_type, exception.value
+            ));
+        }
+    }
+
+    let mut tags: Vec<_> = e.tags.iter().collect();
+    tags.sort();
+    if !tags.is_empty() {
+        body.push_str("\nTags:\n");
+        for (key, value) in tags {
+            body.push_str(&format!("  {}: {}\n", key, value));
+        }
+    }
+
+    let mut extra: Vec<_> = e.extra.iter().collect();
+    extra.sort_by(|a, b| a.0.cmp(b.0));
+    if !extra.is_empty() {
+        body.push_str("\nExtra:\n");
+        for (key, value) in extra {
+            body.push_str(&format!("  {}: {}\n", key, value));
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventId, Keys, Timestamp};
+    use sentrystr::Event;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    /// A local SMTP server accepting every message, or rejecting every recipient with
+    /// `reject`. Sends the data of each accepted message.
+    async fn smtp_server(reject: bool) -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let (read, mut write) = stream.into_split();
+                    let mut lines = BufReader::new(read).lines();
+                    write.write_all(b"220 mock ESMTP\r\n").await.unwrap();
+                    let mut data: Option<String> = None;
+
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        if let Some(ref mut message) = data {
+                            if line != "." {
+                                message.push_str(&line);
+                                message.push('\n');
+                                continue;
+                            }
+                            tx.send(data.take().unwrap_or_default()).ok();
+                            write.write_all(b"250 queued\r\n").await.unwrap();
+                            continue;
+                        }
+
+                        let command = line.get(..4).unwrap_or_default().to_ascii_uppercase();
+                        let reply: &[u8] = match command.as_str() {
+                            "EHLO" | "HELO" => b"250 mock\r\n",
+                            "RCPT" if reject => b"550 no such user\r\n",
+                            "DATA" => {
+                                data = Some(String::new());
+                                b"354 go ahead\r\n"
+                            }
+                            "QUIT" => {
+                                write.write_all(b"221 bye\r\n").await.ok();
+                                return;
+                            }
+                            _ => b"250 OK\r\n",
+                        };
+                        write.write_all(reply).await.unwrap();
+                    }
+                });
+            }
+        });
+        (port, rx)
+    }
+
+    fn config(port: u16) -> EmailConfig {
+        EmailConfig {
+            smtp_host: "127.0.0.1".to_string(),
+            port: Some(port),
+            tls: EmailTls::None,
+            credentials: None,
+            from: "alerts@example.com".to_string(),
+            to: vec!["oncall@example.com".to_string()],
+            min_level: Some(Level::Error),
+            digest: None,
+            retry: RetryPolicy {
+                max_attempts: 1,
+                ..RetryPolicy::default()
+            },
+        }
+    }
+
+    fn collected(message: &str, level: Level) -> CollectedEvent {
+        CollectedEvent {
+            event: Event::new()
+                .with_message(message)
+                .with_level(level)
+                .with_tag("service", "billing"),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            created_at: Timestamp::now(),
+            received_at: chrono::Utc::now(),
+            nostr_tags: Vec::new(),
+            seq: None,
+        }
+    }
+
+    async fn wait_for(condition: impl Fn() -> bool) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn sends_an_email_per_event() {
+        let (port, mut messages) = smtp_server(false).await;
+        let stats = Arc::new(CollectorStats::default());
+        let sink = EmailSink::spawn(config(port), Arc::clone(&stats)).unwrap();
+
+        sink.notify(&collected("cache warmed", Level::Info));
+        sink.notify(&collected("card declined", Level::Error));
+
+        let message = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(message.contains("Subject: [ERROR] billing: card declined"));
+        assert!(message.contains("To: oncall@example.com"));
+        wait_for(|| stats.emails_sent() == 1).await;
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn digest_sends_one_email_per_interval() {
+        let (port, mut messages) = smtp_server(false).await;
+        let stats = Arc::new(CollectorStats::default());
+        let config = EmailConfig {
+            digest: Some(Duration::from_millis(100)),
+            ..config(port)
+        };
+        let sink = EmailSink::spawn(config, Arc::clone(&stats)).unwrap();
+
+        for _ in 0..3 {
+            sink.notify(&collected("card declined", Level::Error));
+        }
+
+        let message = tokio::time::timeout(Duration::from_secs(5), messages.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(message.contains("card declined"));
+        wait_for(|| stats.emails_sent() == 1).await;
+    }
+
+    #[tokio::test]
+    async fn rejected_emails_are_counted_as_failures() {
+        let (port, _messages) = smtp_server(true).await;
+        let stats = Arc::new(CollectorStats::default());
+        let sink = EmailSink::spawn(config(port), Arc::clone(&stats)).unwrap();
+
+        sink.notify(&collected("card declined", Level::Error));
+        wait_for(|| stats.email_failures() == 1).await;
+        assert_eq!(stats.emails_sent(), 0);
+    }
+
+    #[tokio::test]
+    async fn zero_digest_interval_is_rejected() {
+        let config = EmailConfig {
+            digest: Some(Duration::ZERO),
+            ..config(25)
+        };
+        let spawned = EmailSink::spawn(config, Arc::new(CollectorStats::default()));
+        assert!(matches!(spawned, Err(CollectorError::Email(_))));
+    }
+
+    #[test]
+    fn credentials_debug_hides_the_password() {
+        let credentials = EmailCredentials {
+            username: "alerts".to_string(),
+            password: "hunter2".to_string(),
+        };
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("alerts"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -22,4 +22,10 @@ pub enum CollectorError {
 
     #[error("Filter error: {0}")]
     Filter(String),
+
+    #[error("Relay timeout: {0}")]
+    Timeout(String),
+
+    #[error("Email error: {0}")]
+    Email(String),
 }
@@ -1,9 +1,102 @@
 use chrono::{DateTime, Utc};
-use nostr::PublicKey;
+use nostr::{EventId, PublicKey, Timestamp};
+use regex::Regex;
 use sentrystr::{Event, Level};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashSet;
+use std::str::FromStr;
 
-#[derive(Debug, Clone)]
+use crate::collector::CollectedEvent;
+
+/// How an event's message is matched by [`EventFilter`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessagePattern {
+    /// Case-insensitive substring match. The needle is stored lowercased.
+    Contains(#[serde(deserialize_with = "deserialize_lowercase")] String),
+    /// Serialized as the pattern source.
+    Regex(
+        #[serde(
+            serialize_with = "serialize_regex",
+            deserialize_with = "deserialize_regex"
+        )]
+        Regex,
+    ),
+}
+
+fn deserialize_lowercase<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<String, D::Error> {
+    String::deserialize(deserializer).map(|s| s.to_lowercase())
+}
+
+fn serialize_regex<S: Serializer>(
+    regex: &Regex,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+fn deserialize_regex<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+impl MessagePattern {
+    pub fn matches(&self, message: &str) -> bool {
+        match self {
+            MessagePattern::Contains(needle) => message.to_lowercase().contains(needle),
+            MessagePattern::Regex(regex) => regex.is_match(message),
+        }
+    }
+}
+
+/// Order of collected events. Events are ordered by their timestamp; events sharing a
+/// timestamp are ordered by Nostr event id in the same direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            _ => Err(format!("Invalid sort '{}'. Expected 'asc' or 'desc'", s)),
+        }
+    }
+}
+
+/// Position of an event in the sort order, used to resume paging after it. Events are
+/// ordered by the `created_at` second relays page on, then by event timestamp and id.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EventPosition {
+    pub created_at: Timestamp,
+    pub timestamp: DateTime<Utc>,
+    pub event_id: EventId,
+}
+
+/// Which events to collect. Serializes to JSON so filters can be stored and shared;
+/// missing fields take their defaults when deserializing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct EventFilter {
     pub authors: Option<HashSet<PublicKey>>,
     pub levels: Option<HashSet<Level>>,
@@ -12,6 +105,14 @@ pub struct EventFilter {
     pub tags: Option<Vec<(String, String)>>,
     pub nostr_tags: Option<Vec<(String, String)>>,
     pub limit: Option<usize>,
+    pub message_pattern: Option<MessagePattern>,
+    pub search: Option<String>,
+    /// Only match events with a `duration_ms` of at least this, see
+    /// [`EventFilter::with_min_duration_ms`].
+    pub min_duration_ms: Option<u64>,
+    pub sort: SortOrder,
+    /// Only match events that come strictly after this position in `sort` order.
+    pub after: Option<EventPosition>,
 }
 
 impl Default for EventFilter {
@@ -30,6 +131,11 @@ impl EventFilter {
             tags: None,
             nostr_tags: None,
             limit: None,
+            message_pattern: None,
+            search: None,
+            min_duration_ms: None,
+            sort: SortOrder::Desc,
+            after: None,
         }
     }
 
@@ -61,6 +167,13 @@ impl EventFilter {
         self
     }
 
+    /// Matches events at `min_level` or any more severe level.
+    pub fn with_min_level(self, min_level: Level) -> Self {
+        crate::level::levels_at_or_above(&min_level)
+            .into_iter()
+            .fold(self, |filter, level| filter.with_level(level))
+    }
+
     pub fn with_since(mut self, since: DateTime<Utc>) -> Self {
         self.since = Some(since);
         self
@@ -79,11 +192,60 @@ impl EventFilter {
         self
     }
 
+    /// Matches performance events, such as those of `Event::transaction_event`, that took
+    /// at least `min_duration_ms`. Events without a duration never match.
+    pub fn with_min_duration_ms(mut self, min_duration_ms: u64) -> Self {
+        self.min_duration_ms = Some(min_duration_ms);
+        self
+    }
+
     pub fn with_limit(mut self, limit: usize) -> Self {
         self.limit = Some(limit);
         self
     }
 
+    /// Matches events whose message contains `text`, ignoring case.
+    pub fn with_message_contains(mut self, text: impl Into<String>) -> Self {
+        self.message_pattern = Some(MessagePattern::Contains(text.into().to_lowercase()));
+        self
+    }
+
+    pub fn with_message_regex(mut self, regex: Regex) -> Self {
+        self.message_pattern = Some(MessagePattern::Regex(regex));
+        self
+    }
+
+    /// Forwards `query` to relays as a NIP-50 `search` filter. Relays without NIP-50
+    /// support may ignore it or return nothing, so only enable this for relays known to
+    /// implement search; local message matching still applies on top.
+    pub fn with_nip50_search(mut self, query: impl Into<String>) -> Self {
+        self.search = Some(query.into());
+        self
+    }
+
+    pub fn with_sort(mut self, sort: SortOrder) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// Resumes after `position`: older events for [`SortOrder::Desc`], newer ones for
+    /// [`SortOrder::Asc`].
+    pub fn with_after(mut self, position: EventPosition) -> Self {
+        self.after = Some(position);
+        self
+    }
+
+    /// Whether `position` lies past the `after` cursor in the filter's sort order.
+    pub fn is_after_cursor(&self, position: &EventPosition) -> bool {
+        match self.after {
+            Some(ref after) => match self.sort {
+                SortOrder::Asc => position > after,
+                SortOrder::Desc => position < after,
+            },
+            None => true,
+        }
+    }
+
     pub fn with_nostr_tag(mut self, key: String, value: String) -> Self {
         match self.nostr_tags {
             Some(ref mut tags) => tags.push((key, value)),
@@ -108,6 +270,28 @@ impl EventFilter {
         self.with_nostr_tag("severity".to_string(), severity)
     }
 
+    /// Matches encrypted events whose field `name` was kept readable with
+    /// `Config::with_plaintext_tags`, see [`sentrystr::PLAINTEXT_TAG`]. Relays index it.
+    pub fn with_plaintext_tag(self, name: &str, value: &str) -> Self {
+        self.with_nostr_tag(
+            sentrystr::PLAINTEXT_TAG.to_string(),
+            sentrystr::plaintext_tag_value(name, value),
+        )
+    }
+
+    /// Matches the [`sentrystr::FINGERPRINT_TAG`], i.e. the parts joined by
+    /// [`sentrystr::FINGERPRINT_SEPARATOR`]. Relays index it, so they only return the
+    /// group. Encrypted events carry no such tag.
+    pub fn with_fingerprint_filter(self, fingerprint: String) -> Self {
+        self.with_nostr_tag(sentrystr::FINGERPRINT_TAG.to_string(), fingerprint)
+    }
+
+    /// Whether `authors` is an empty set, which no event matches. Collectors answer such
+    /// filters without asking relays, some of which read `authors: []` as any author.
+    pub fn matches_nothing(&self) -> bool {
+        self.authors.as_ref().is_some_and(HashSet::is_empty)
+    }
+
     pub fn matches(&self, event: &Event, author: &PublicKey) -> bool {
         if let Some(ref authors) = self.authors
             && !authors.contains(author)
@@ -133,6 +317,21 @@ impl EventFilter {
             return false;
         }
 
+        if let Some(min_duration_ms) = self.min_duration_ms
+            && event
+                .duration_ms
+                .is_none_or(|duration_ms| duration_ms < min_duration_ms)
+        {
+            return false;
+        }
+
+        if let Some(ref pattern) = self.message_pattern {
+            match event.message {
+                Some(ref message) if pattern.matches(message) => {}
+                _ => return false,
+            }
+        }
+
         if let Some(ref filter_tags) = self.tags {
             for (key, value) in filter_tags {
                 if let Some(event_value) = event.tags.get(key) {
@@ -148,6 +347,23 @@ impl EventFilter {
         true
     }
 
+    /// Applies the whole filter, including Nostr tags and the cursor, to an event that was
+    /// already collected, e.g. one held in memory rather than fetched from a relay.
+    pub fn matches_collected(&self, event: &CollectedEvent) -> bool {
+        if !self.matches(&event.event, &event.author) || !self.is_after_cursor(&event.position()) {
+            return false;
+        }
+
+        self.nostr_tags.as_ref().is_none_or(|filter_nostr_tags| {
+            filter_nostr_tags.iter().all(|(key, value)| {
+                event.nostr_tags.iter().any(|tag| match tag.as_slice() {
+                    [tag_key, tag_value, ..] => tag_key == key && tag_value == value,
+                    _ => false,
+                })
+            })
+        })
+    }
+
     pub fn matches_nostr_event(
         &self,
         parsed_event: &Event,
@@ -158,6 +374,15 @@ impl EventFilter {
             return false;
         }
 
+        let position = EventPosition {
+            created_at: nostr_event.created_at,
+            timestamp: parsed_event.timestamp,
+            event_id: nostr_event.id,
+        };
+        if !self.is_after_cursor(&position) {
+            return false;
+        }
+
         if let Some(ref filter_nostr_tags) = self.nostr_tags {
             for (key, value) in filter_nostr_tags {
                 let mut found = false;
@@ -0,0 +1,23 @@
+use sentrystr::Level;
+
+/// All levels ordered from least to most severe.
+pub const LEVELS: [Level; 5] = Level::ALL;
+
+/// Parses a comma-separated list of levels such as `error,fatal`, shared by the CLI and
+/// the API so both accept the same strings.
+pub fn parse_levels(levels_str: &str) -> std::result::Result<Vec<Level>, String> {
+    levels_str
+        .split(',')
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| part.parse::<Level>().map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Returns `min_level` and every level more severe than it.
+pub fn levels_at_or_above(min_level: &Level) -> Vec<Level> {
+    LEVELS
+        .iter()
+        .filter(|level| *level >= min_level)
+        .cloned()
+        .collect()
+}
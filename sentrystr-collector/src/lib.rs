@@ -55,11 +55,25 @@
 //! ```
 
 pub mod collector;
+#[cfg(feature = "email")]
+pub mod email;
 pub mod error;
 pub mod filter;
+pub mod level;
+pub mod sequence;
+pub mod time;
+#[cfg(feature = "tui")]
+pub mod tui;
 
-pub use collector::{EventCollector, PrivateMessageConfig};
+pub use collector::{
+    CollectedEvent, CollectorStats, EventCollector, PrivateMessageConfig, RelayStatusInfo,
+};
+#[cfg(feature = "email")]
+pub use email::{EmailConfig, EmailCredentials, EmailSink, EmailTls};
 pub use error::CollectorError;
-pub use filter::EventFilter;
+pub use filter::{EventFilter, EventPosition, MessagePattern, SortOrder};
+pub use level::{levels_at_or_above, parse_levels};
+pub use sequence::{GapDetected, GapHandler, SequenceTracker};
+pub use time::{parse_duration, parse_time};
 
 pub type Result<T> = std::result::Result<T, CollectorError>;
@@ -1,6 +1,20 @@
 use clap::{Args, Parser, Subcommand};
-use nostr::PublicKey;
-use sentrystr_collector::{EventCollector, EventFilter, PrivateMessageConfig, Result};
+use nostr::prelude::ToBech32;
+use nostr::{Keys, PublicKey};
+use sentrystr::keyfile::PASSPHRASE_VAR;
+use sentrystr::{
+    DmEncryption, Level, PassphraseSource, read_key_file, write_encrypted_key_file, write_key_file,
+};
+use sentrystr_collector::{
+    CollectedEvent, EventCollector, EventFilter, PrivateMessageConfig, Result, parse_levels,
+    parse_time,
+};
+#[cfg(feature = "email")]
+use sentrystr_collector::{CollectorError, EmailConfig, EmailCredentials, EmailTls};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+
+const DEFAULT_RELAY: &str = "wss://relay.damus.io";
 
 fn parse_tag(s: &str) -> std::result::Result<(String, String), String> {
     match s.split_once('=') {
@@ -9,21 +23,36 @@ fn parse_tag(s: &str) -> std::result::Result<(String, String), String> {
     }
 }
 
-fn parse_level(level_str: &str) -> std::result::Result<sentrystr::Level, String> {
-    match level_str.to_lowercase().as_str() {
-        "debug" => Ok(sentrystr::Level::Debug),
-        "info" => Ok(sentrystr::Level::Info),
-        "warning" => Ok(sentrystr::Level::Warning),
-        "error" => Ok(sentrystr::Level::Error),
-        "fatal" => Ok(sentrystr::Level::Fatal),
-        _ => Err("Invalid level".to_string()),
-    }
+fn parse_time_arg(s: &str) -> std::result::Result<chrono::DateTime<chrono::Utc>, String> {
+    parse_time(s, chrono::Utc::now())
+}
+
+fn parse_dm_encryption(s: &str) -> std::result::Result<DmEncryption, String> {
+    s.parse()
+        .map_err(|e: sentrystr::ParseDmEncryptionError| e.to_string())
+}
+
+/// Parses a DM window such as `5m`, see [`sentrystr_collector::parse_duration`].
+fn parse_dm_window(s: &str) -> std::result::Result<std::time::Duration, String> {
+    sentrystr_collector::parse_duration(s)
+        .and_then(|window| window.to_std().ok())
+        .filter(|window| !window.is_zero())
+        .ok_or_else(|| format!("Invalid duration '{}'. Expected e.g. 30s or 5m", s))
+}
+
+/// Parses a DM rate limit such as `10/1m`: at most 10 messages a minute.
+fn parse_dm_rate_limit(s: &str) -> std::result::Result<(u32, std::time::Duration), String> {
+    let invalid = || format!("Invalid rate limit '{}'. Expected e.g. 10/1m", s);
+    let (max_messages, window) = s.split_once('/').ok_or_else(invalid)?;
+    let max_messages = max_messages.parse().map_err(|_| invalid())?;
+    Ok((max_messages, parse_dm_window(window)?))
 }
 
 fn build_private_message_config(
     send_to: Option<String>,
     send_min_level: Option<String>,
-    use_nip17: bool,
+    encryption: DmEncryption,
+    send_template: Option<String>,
 ) -> Result<Option<PrivateMessageConfig>> {
     if let Some(recipient_str) = send_to {
         let recipient_pubkey = PublicKey::parse(&recipient_str).map_err(|e| {
@@ -34,7 +63,11 @@ fn build_private_message_config(
         })?;
 
         let min_level = if let Some(level_str) = send_min_level {
-            Some(parse_level(&level_str).map_err(sentrystr_collector::CollectorError::Collection)?)
+            Some(
+                level_str
+                    .parse::<Level>()
+                    .map_err(|e| sentrystr_collector::CollectorError::Collection(e.to_string()))?,
+            )
         } else {
             None
         };
@@ -42,17 +75,112 @@ fn build_private_message_config(
         Ok(Some(PrivateMessageConfig {
             recipient_pubkey,
             min_level,
-            use_nip17,
+            encryption,
+            template: send_template,
+            formatter: None,
         }))
     } else {
         Ok(None)
     }
 }
 
+/// The passphrase of key files: `SENTRYSTR_KEY_PASSPHRASE` when set, otherwise asked for
+/// on the terminal with the `prompt` feature.
+fn passphrase_source() -> PassphraseSource {
+    #[cfg(feature = "prompt")]
+    if std::env::var_os(PASSPHRASE_VAR).is_none() {
+        return PassphraseSource::Prompt;
+    }
+    PassphraseSource::env(PASSPHRASE_VAR)
+}
+
+/// Connects with the keys of `--key-file` when given. Otherwise connects to `--relays`
+/// when given, or to the core configuration read from the `SENTRYSTR_*` variables,
+/// falling back to a public relay and generated keys.
+async fn connect_collector(relays: Vec<String>, key_file: Option<&Path>) -> Result<EventCollector> {
+    if let Some(key_file) = key_file {
+        let keys = read_key_file(key_file, &passphrase_source())?;
+        let relays = if relays.is_empty() {
+            vec![DEFAULT_RELAY.to_string()]
+        } else {
+            relays
+        };
+        return EventCollector::from_config(&sentrystr::Config::from_keys(&keys, relays)?).await;
+    }
+
+    if !relays.is_empty() {
+        return EventCollector::new(relays).await;
+    }
+
+    let fallback = sentrystr::Config::try_new(
+        Keys::generate().secret_key().display_secret().to_string(),
+        vec![DEFAULT_RELAY.to_string()],
+    )?;
+    let config =
+        sentrystr::Config::from_env_or(fallback).map_err(sentrystr::SentryStrError::from)?;
+    EventCollector::from_config(&config).await
+}
+
+/// Generates keys and saves them to `out`, encrypted with a passphrase when `encrypt`.
+fn generate_key(out: &Path, encrypt: bool) -> Result<()> {
+    let keys = Keys::generate();
+
+    if encrypt {
+        let source = passphrase_source();
+        let passphrase = source.read()?;
+        #[cfg(feature = "prompt")]
+        if matches!(source, PassphraseSource::Prompt) && source.read()? != passphrase {
+            return Err(sentrystr_collector::CollectorError::Collection(
+                "Passphrases do not match".to_string(),
+            ));
+        }
+        write_encrypted_key_file(&keys, out, &passphrase)?;
+    } else {
+        write_key_file(&keys, out)?;
+    }
+
+    println!(
+        "Wrote the key of {} to {}",
+        keys.public_key().to_bech32().unwrap_or_default(),
+        out.display()
+    );
+    Ok(())
+}
+
+fn print_exceptions(event: &sentrystr::Event) {
+    for exception in event.exception.iter().flatten() {
+        println!(
+            "Exception: {}: {}",
+            exception.exception_type, exception.value
+        );
+        for cause in &exception.chain {
+            println!("  Caused by: {}", cause);
+        }
+    }
+
+    if let Some(ref stacktrace) = event.stacktrace {
+        println!("Stacktrace:");
+        for frame in stacktrace.frames.iter().rev() {
+            let function = frame.function.as_deref().unwrap_or("<unknown>");
+            match frame.lineno {
+                Some(lineno) => println!("  {} at {}:{}", function, frame.filename, lineno),
+                None => println!("  {}", function),
+            }
+        }
+    }
+}
+
 #[derive(Parser)]
 #[command(name = "sentrystr-collector")]
 #[command(about = "A collector for SentryStr events from Nostr network")]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "File with the secret key to collect with (hex, nsec or NIP-49 ncryptsec)"
+    )]
+    key_file: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -61,6 +189,26 @@ struct Cli {
 enum Commands {
     Collect(CollectArgs),
     Subscribe(SubscribeArgs),
+    /// Shows subscribed events in a live terminal dashboard
+    #[cfg(feature = "tui")]
+    Tui(SubscribeArgs),
+    #[command(subcommand)]
+    Key(KeyCommands),
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Generates a secret key and saves it to a file
+    Generate {
+        #[arg(long, help = "File to write the key to")]
+        out: PathBuf,
+
+        #[arg(
+            long,
+            help = "Encrypt the key as a NIP-49 ncryptsec with a passphrase (SENTRYSTR_KEY_PASSPHRASE or prompted)"
+        )]
+        encrypt: bool,
+    },
 }
 
 #[derive(Args)]
@@ -76,12 +224,16 @@ struct CollectArgs {
     )]
     limit: usize,
 
-    #[arg(short, long, help = "Relay URLs", default_values = &["wss://relay.damus.io"])]
+    #[arg(
+        short,
+        long,
+        help = "Relay URLs (default: SENTRYSTR_RELAYS, then wss://relay.damus.io)"
+    )]
     relays: Vec<String>,
 
     #[arg(
         long,
-        help = "Filter by log level (debug, info, warning, error, fatal)"
+        help = "Filter by log level, comma-separated (debug, info, warning, error, fatal)"
     )]
     level: Option<String>,
 
@@ -100,6 +252,20 @@ struct CollectArgs {
     #[arg(long, help = "Filter by custom Nostr tag (format: key=value)", value_parser = parse_tag)]
     tag: Vec<(String, String)>,
 
+    #[arg(
+        long,
+        help = "Only events at or after this time (RFC 3339, epoch seconds, or relative like 24h)",
+        value_parser = parse_time_arg
+    )]
+    since: Option<chrono::DateTime<chrono::Utc>>,
+
+    #[arg(
+        long,
+        help = "Only events at or before this time (RFC 3339, epoch seconds, or relative like 1h)",
+        value_parser = parse_time_arg
+    )]
+    until: Option<chrono::DateTime<chrono::Utc>>,
+
     #[arg(long, help = "Send events as private messages to this public key")]
     send_to: Option<String>,
 
@@ -109,8 +275,39 @@ struct CollectArgs {
     )]
     send_min_level: Option<String>,
 
-    #[arg(long, help = "Use NIP-17 for private messages (default: NIP-44)")]
+    #[arg(
+        long,
+        help = "Use NIP-17 for private messages (same as --dm-encryption nip17)"
+    )]
     use_nip17: bool,
+
+    #[arg(
+        long,
+        help = "Private message encryption: nip44 (default), nip17, or nip04 (legacy, for old clients)",
+        value_parser = parse_dm_encryption,
+        conflicts_with = "use_nip17"
+    )]
+    dm_encryption: Option<DmEncryption>,
+
+    #[arg(
+        long,
+        help = "Private message template, e.g. \"[{level}] {tag:service}: {message}\""
+    )]
+    send_template: Option<String>,
+
+    #[arg(
+        long,
+        help = "At most this many private messages per window, e.g. 10/1m",
+        value_parser = parse_dm_rate_limit
+    )]
+    send_rate_limit: Option<(u32, std::time::Duration)>,
+
+    #[arg(
+        long,
+        help = "Send the same alert at most once per this duration, e.g. 5m",
+        value_parser = parse_dm_window
+    )]
+    send_cooldown: Option<std::time::Duration>,
 }
 
 #[derive(Args)]
@@ -118,12 +315,16 @@ struct SubscribeArgs {
     #[arg(short, long, help = "Public key to subscribe to events from")]
     author: Option<String>,
 
-    #[arg(short, long, help = "Relay URLs", default_values = &["wss://relay.damus.io"])]
+    #[arg(
+        short,
+        long,
+        help = "Relay URLs (default: SENTRYSTR_RELAYS, then wss://relay.damus.io)"
+    )]
     relays: Vec<String>,
 
     #[arg(
         long,
-        help = "Filter by log level (debug, info, warning, error, fatal)"
+        help = "Filter by log level, comma-separated (debug, info, warning, error, fatal)"
     )]
     level: Option<String>,
 
@@ -142,6 +343,13 @@ struct SubscribeArgs {
     #[arg(long, help = "Filter by custom Nostr tag (format: key=value)", value_parser = parse_tag)]
     tag: Vec<(String, String)>,
 
+    #[arg(
+        long,
+        help = "Only events at or after this time (RFC 3339, epoch seconds, or relative like 24h)",
+        value_parser = parse_time_arg
+    )]
+    since: Option<chrono::DateTime<chrono::Utc>>,
+
     #[arg(long, help = "Send events as private messages to this public key")]
     send_to: Option<String>,
 
@@ -151,8 +359,223 @@ struct SubscribeArgs {
     )]
     send_min_level: Option<String>,
 
-    #[arg(long, help = "Use NIP-17 for private messages (default: NIP-44)")]
+    #[arg(
+        long,
+        help = "Use NIP-17 for private messages (same as --dm-encryption nip17)"
+    )]
     use_nip17: bool,
+
+    #[arg(
+        long,
+        help = "Private message encryption: nip44 (default), nip17, or nip04 (legacy, for old clients)",
+        value_parser = parse_dm_encryption,
+        conflicts_with = "use_nip17"
+    )]
+    dm_encryption: Option<DmEncryption>,
+
+    #[arg(
+        long,
+        help = "Private message template, e.g. \"[{level}] {tag:service}: {message}\""
+    )]
+    send_template: Option<String>,
+
+    #[arg(
+        long,
+        help = "At most this many private messages per window, e.g. 10/1m",
+        value_parser = parse_dm_rate_limit
+    )]
+    send_rate_limit: Option<(u32, std::time::Duration)>,
+
+    #[arg(
+        long,
+        help = "Send the same alert at most once per this duration, e.g. 5m",
+        value_parser = parse_dm_window
+    )]
+    send_cooldown: Option<std::time::Duration>,
+
+    #[cfg(feature = "email")]
+    #[command(flatten)]
+    email: EmailArgs,
+}
+
+/// Environment variable holding the SMTP password of `--email-username`.
+#[cfg(feature = "email")]
+const SMTP_PASSWORD_VAR: &str = "SENTRYSTR_SMTP_PASSWORD";
+
+#[cfg(feature = "email")]
+#[derive(Args)]
+struct EmailArgs {
+    #[arg(
+        long,
+        help = "JSON file with the email settings; the other --email-* flags override it"
+    )]
+    email_config: Option<PathBuf>,
+
+    #[arg(long, help = "SMTP server to send alert emails through")]
+    email_smtp_host: Option<String>,
+
+    #[arg(long, help = "SMTP port (default: the port of --email-tls)")]
+    email_port: Option<u16>,
+
+    #[arg(long, help = "SMTP security: starttls (default), tls, or none")]
+    email_tls: Option<EmailTls>,
+
+    #[arg(
+        long,
+        help = "SMTP username; the password is read from SENTRYSTR_SMTP_PASSWORD"
+    )]
+    email_username: Option<String>,
+
+    #[arg(long, help = "Sender address of alert emails")]
+    email_from: Option<String>,
+
+    #[arg(long, help = "Recipient address of alert emails (repeatable)")]
+    email_to: Vec<String>,
+
+    #[arg(
+        long,
+        help = "Minimum level to email (debug, info, warning, error, fatal)"
+    )]
+    email_min_level: Option<Level>,
+
+    #[arg(
+        long,
+        help = "Send one digest email per interval (e.g. 15m) instead of one per event",
+        value_parser = sentrystr_collector::email::parse_interval
+    )]
+    email_digest: Option<std::time::Duration>,
+}
+
+#[cfg(feature = "email")]
+impl EmailArgs {
+    /// The email settings of the config file and flags, `None` when neither was given.
+    fn config(self) -> Result<Option<EmailConfig>> {
+        let mut config = match self.email_config {
+            Some(ref path) => EmailConfig::from_file(path)?,
+            None => {
+                let Some(smtp_host) = self.email_smtp_host.clone() else {
+                    return Ok(None);
+                };
+                EmailConfig {
+                    smtp_host,
+                    port: None,
+                    tls: EmailTls::default(),
+                    credentials: None,
+                    from: String::new(),
+                    to: Vec::new(),
+                    min_level: None,
+                    digest: None,
+                    retry: Default::default(),
+                }
+            }
+        };
+
+        if let Some(smtp_host) = self.email_smtp_host {
+            config.smtp_host = smtp_host;
+        }
+        if let Some(port) = self.email_port {
+            config.port = Some(port);
+        }
+        if let Some(tls) = self.email_tls {
+            config.tls = tls;
+        }
+        if let Some(username) = self.email_username {
+            let password = std::env::var(SMTP_PASSWORD_VAR).map_err(|_| {
+                CollectorError::Email(format!("--email-username needs {}", SMTP_PASSWORD_VAR))
+            })?;
+            config.credentials = Some(EmailCredentials { username, password });
+        }
+        if let Some(from) = self.email_from {
+            config.from = from;
+        }
+        if !self.email_to.is_empty() {
+            config.to = self.email_to;
+        }
+        if let Some(min_level) = self.email_min_level {
+            config.min_level = Some(min_level);
+        }
+        if let Some(digest) = self.email_digest {
+            config.digest = Some(digest);
+        }
+
+        if config.from.is_empty() {
+            return Err(CollectorError::Email(
+                "Alert emails need a sender, see --email-from".to_string(),
+            ));
+        }
+        Ok(Some(config))
+    }
+}
+
+/// Connects like [`connect_collector`] and subscribes to the events matching `args`.
+async fn subscribe(
+    args: SubscribeArgs,
+    key_file: Option<&Path>,
+) -> Result<(EventCollector, mpsc::Receiver<CollectedEvent>)> {
+    let mut collector = connect_collector(args.relays, key_file).await?;
+
+    if let Some(pm_config) = build_private_message_config(
+        args.send_to,
+        args.send_min_level,
+        args.dm_encryption
+            .unwrap_or(DmEncryption::from_nip17(args.use_nip17)),
+        args.send_template,
+    )? {
+        collector = collector.with_throttled_private_messaging(
+            pm_config,
+            args.send_rate_limit,
+            args.send_cooldown,
+        )?;
+    }
+
+    #[cfg(feature = "email")]
+    if let Some(email_config) = args.email.config()? {
+        collector = collector.with_email(email_config)?;
+    }
+
+    let mut filter = EventFilter::new();
+
+    if let Some(author_str) = args.author {
+        let author = PublicKey::parse(&author_str).map_err(|e| {
+            sentrystr_collector::CollectorError::Collection(format!("Invalid public key: {}", e))
+        })?;
+        filter = filter.with_author(author);
+    }
+
+    if let Some(level_str) = args.level {
+        let levels =
+            parse_levels(&level_str).map_err(sentrystr_collector::CollectorError::Collection)?;
+        for level in levels {
+            filter = filter.with_level(level);
+        }
+    }
+
+    if let Some(service) = args.service {
+        filter = filter.with_service_filter(service);
+    }
+
+    if let Some(environment) = args.environment {
+        filter = filter.with_environment_filter(environment);
+    }
+
+    if let Some(component) = args.component {
+        filter = filter.with_component_filter(component);
+    }
+
+    if let Some(severity) = args.severity {
+        filter = filter.with_severity_filter(severity);
+    }
+
+    for (key, value) in args.tag {
+        filter = filter.with_nostr_tag(key, value);
+    }
+
+    if let Some(since) = args.since {
+        filter = filter.with_since(since);
+    }
+
+    let rx = collector.subscribe_to_events(filter).await?;
+    Ok((collector, rx))
 }
 
 #[tokio::main]
@@ -161,12 +584,20 @@ async fn main() -> Result<()> {
 
     match cli.command {
         Commands::Collect(args) => {
-            let mut collector = EventCollector::new(args.relays).await?;
-
-            if let Some(pm_config) =
-                build_private_message_config(args.send_to, args.send_min_level, args.use_nip17)?
-            {
-                collector = collector.with_private_messaging(pm_config)?;
+            let mut collector = connect_collector(args.relays, cli.key_file.as_deref()).await?;
+
+            if let Some(pm_config) = build_private_message_config(
+                args.send_to,
+                args.send_min_level,
+                args.dm_encryption
+                    .unwrap_or(DmEncryption::from_nip17(args.use_nip17)),
+                args.send_template,
+            )? {
+                collector = collector.with_throttled_private_messaging(
+                    pm_config,
+                    args.send_rate_limit,
+                    args.send_cooldown,
+                )?;
             }
 
             let mut filter = EventFilter::new().with_limit(args.limit);
@@ -182,9 +613,11 @@ async fn main() -> Result<()> {
             }
 
             if let Some(level_str) = args.level {
-                let level = parse_level(&level_str)
+                let levels = parse_levels(&level_str)
                     .map_err(sentrystr_collector::CollectorError::Collection)?;
-                filter = filter.with_level(level);
+                for level in levels {
+                    filter = filter.with_level(level);
+                }
             }
 
             if let Some(service) = args.service {
@@ -207,6 +640,14 @@ async fn main() -> Result<()> {
                 filter = filter.with_nostr_tag(key, value);
             }
 
+            if let Some(since) = args.since {
+                filter = filter.with_since(since);
+            }
+
+            if let Some(until) = args.until {
+                filter = filter.with_until(until);
+            }
+
             println!("Collecting events...");
             let events = collector.collect_events(filter).await?;
 
@@ -219,59 +660,14 @@ async fn main() -> Result<()> {
                 println!("Level: {:?}", event.event.level);
                 println!("Message: {:?}", event.event.message);
                 println!("Tags: {:?}", event.event.tags);
+                print_exceptions(&event.event);
             }
 
             collector.disconnect().await?;
         }
         Commands::Subscribe(args) => {
-            let mut collector = EventCollector::new(args.relays).await?;
-
-            if let Some(pm_config) =
-                build_private_message_config(args.send_to, args.send_min_level, args.use_nip17)?
-            {
-                collector = collector.with_private_messaging(pm_config)?;
-            }
-
-            let mut filter = EventFilter::new();
-
-            if let Some(author_str) = args.author {
-                let author = PublicKey::parse(&author_str).map_err(|e| {
-                    sentrystr_collector::CollectorError::Collection(format!(
-                        "Invalid public key: {}",
-                        e
-                    ))
-                })?;
-                filter = filter.with_author(author);
-            }
-
-            if let Some(level_str) = args.level {
-                let level = parse_level(&level_str)
-                    .map_err(sentrystr_collector::CollectorError::Collection)?;
-                filter = filter.with_level(level);
-            }
-
-            if let Some(service) = args.service {
-                filter = filter.with_service_filter(service);
-            }
-
-            if let Some(environment) = args.environment {
-                filter = filter.with_environment_filter(environment);
-            }
-
-            if let Some(component) = args.component {
-                filter = filter.with_component_filter(component);
-            }
-
-            if let Some(severity) = args.severity {
-                filter = filter.with_severity_filter(severity);
-            }
-
-            for (key, value) in args.tag {
-                filter = filter.with_nostr_tag(key, value);
-            }
-
             println!("Subscribing to events... (Press Ctrl+C to stop)");
-            let mut rx = collector.subscribe_to_events(filter).await?;
+            let (collector, mut rx) = subscribe(args, cli.key_file.as_deref()).await?;
 
             while let Some(event) = rx.recv().await {
                 println!("---");
@@ -281,12 +677,76 @@ async fn main() -> Result<()> {
                 println!("Level: {:?}", event.event.level);
                 println!("Message: {:?}", event.event.message);
                 println!("Tags: {:?}", event.event.tags);
+                print_exceptions(&event.event);
                 println!("Received at: {}", event.received_at);
             }
 
             collector.disconnect().await?;
         }
+        #[cfg(feature = "tui")]
+        Commands::Tui(args) => {
+            let (collector, rx) = subscribe(args, cli.key_file.as_deref()).await?;
+            sentrystr_collector::tui::run(
+                rx,
+                collector.stats(),
+                sentrystr_collector::tui::DEFAULT_CAPACITY,
+            )
+            .await?;
+            collector.disconnect().await?;
+        }
+        Commands::Key(KeyCommands::Generate { out, encrypt }) => {
+            generate_key(&out, encrypt)?;
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dm_rate_limits_and_cooldowns_are_parsed() {
+        assert_eq!(
+            parse_dm_rate_limit("10/1m"),
+            Ok((10, Duration::from_secs(60)))
+        );
+        assert!(parse_dm_rate_limit("10").is_err());
+        assert!(parse_dm_rate_limit("ten/1m").is_err());
+        assert!(parse_dm_rate_limit("10/0s").is_err());
+        assert_eq!(parse_dm_window("5m"), Ok(Duration::from_secs(300)));
+        assert!(parse_dm_window("5").is_err());
+    }
+
+    #[test]
+    fn subscribe_takes_dm_throttling_flags() {
+        let cli = Cli::try_parse_from([
+            "sentrystr-collector",
+            "subscribe",
+            "--send-rate-limit",
+            "10/1m",
+            "--send-cooldown",
+            "5m",
+        ])
+        .unwrap();
+
+        let Commands::Subscribe(args) = cli.command else {
+            panic!("expected the subscribe command");
+        };
+        assert_eq!(args.send_rate_limit, Some((10, Duration::from_secs(60))));
+        assert_eq!(args.send_cooldown, Some(Duration::from_secs(300)));
+    }
+
+    #[test]
+    fn collect_rejects_an_invalid_rate_limit() {
+        let parsed = Cli::try_parse_from([
+            "sentrystr-collector",
+            "collect",
+            "--send-rate-limit",
+            "10 per minute",
+        ]);
+        assert!(parsed.is_err());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::CollectedEvent;
+use nostr::{EventId, PublicKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Sequence numbers of an author skipped from `expected` to `got`: the events in between
+/// never reached the collector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GapDetected {
+    pub author: PublicKey,
+    pub expected: u64,
+    pub got: u64,
+}
+
+impl GapDetected {
+    /// How many events are missing.
+    pub fn missing(&self) -> u64 {
+        self.got - self.expected
+    }
+}
+
+/// Called with every gap found by `EventCollector::subscribe_to_events`.
+pub type GapHandler = Arc<dyn Fn(GapDetected) + Send + Sync>;
+
+/// Follows the sequence numbers of each author, set with
+/// `Config::with_sequence_numbers`, and reports skipped ones.
+///
+/// Events must be observed in publishing order, as a live subscription sees them. An
+/// event arriving late after a gap was reported, or a second copy from another relay,
+/// is ignored. A publisher restarted without a state file starts over at 1, which is
+/// taken as a new baseline rather than a gap; another copy of the event numbered 1 is
+/// not.
+#[derive(Debug, Clone, Default)]
+pub struct SequenceTracker {
+    next: HashMap<PublicKey, u64>,
+    /// Event each author numbered 1 last, telling copies of it from restarts.
+    starts: HashMap<PublicKey, EventId>,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `event`, returning the gap before it if sequence numbers skipped.
+    pub fn observe(&mut self, event: &CollectedEvent) -> Option<GapDetected> {
+        let seq = event.seq?;
+        if seq == 1 {
+            let start = self.starts.insert(event.author, event.nostr_event_id);
+            if start == Some(event.nostr_event_id) {
+                return None;
+            }
+        }
+        let next = self.next.entry(event.author).or_insert(seq);
+
+        let gap = if seq > *next {
+            Some(GapDetected {
+                author: event.author,
+                expected: *next,
+                got: seq,
+            })
+        } else if seq < *next && seq != 1 {
+            return None;
+        } else {
+            None
+        };
+
+        *next = seq + 1;
+        gap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::prelude::*;
+    use sentrystr::Event;
+
+    /// The `copy`th event published by `author`, numbered `seq`.
+    fn numbered(author: PublicKey, seq: Option<u64>, copy: u8) -> CollectedEvent {
+        let mut id = [copy; 32];
+        id[..8].copy_from_slice(&seq.unwrap_or_default().to_be_bytes());
+        CollectedEvent {
+            event: Event::new(),
+            author,
+            nostr_event_id: EventId::from_byte_array(id),
+            created_at: Timestamp::now(),
+            received_at: chrono::Utc::now(),
+            nostr_tags: Vec::new(),
+            seq,
+        }
+    }
+
+    /// The `(expected, got)` gaps a tracker reports for one author's events, given as
+    /// `(seq, copy)`: the same pair is the same event.
+    fn gaps(events: &[(u64, u8)]) -> Vec<(u64, u64)> {
+        let author = Keys::generate().public_key();
+        let mut tracker = SequenceTracker::new();
+        events
+            .iter()
+            .filter_map(|&(seq, copy)| tracker.observe(&numbered(author, Some(seq), copy)))
+            .map(|gap| (gap.expected, gap.got))
+            .collect()
+    }
+
+    #[test]
+    fn skipped_numbers_are_reported_once() {
+        type Case<'a> = (&'a str, &'a [(u64, u8)], &'a [(u64, u64)]);
+        let cases: [Case; 8] = [
+            ("in order", &[(1, 0), (2, 0), (3, 0)], &[]),
+            ("first seen midway", &[(10, 0), (11, 0)], &[]),
+            (
+                "skipped",
+                &[(1, 0), (2, 0), (5, 0), (6, 0), (9, 0)],
+                &[(3, 5), (7, 9)],
+            ),
+            (
+                "late after a gap",
+                &[(1, 0), (3, 0), (2, 0), (4, 0)],
+                &[(2, 3)],
+            ),
+            (
+                "copies from another relay",
+                &[(1, 0), (2, 0), (2, 0), (1, 0), (3, 0)],
+                &[],
+            ),
+            (
+                "restarted without a state file",
+                &[(1, 0), (2, 0), (3, 0), (1, 1), (2, 1)],
+                &[],
+            ),
+            (
+                "restarted then skipped",
+                &[(4, 0), (5, 0), (1, 1), (3, 1)],
+                &[(2, 3)],
+            ),
+            (
+                "copy of the first run after a restart",
+                &[(1, 0), (2, 0), (1, 1), (1, 0), (2, 1)],
+                &[],
+            ),
+        ];
+
+        for (name, events, expected) in cases {
+            assert_eq!(gaps(events), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn authors_are_followed_apart() {
+        let (first, second) = (Keys::generate().public_key(), Keys::generate().public_key());
+        let mut tracker = SequenceTracker::new();
+
+        assert_eq!(tracker.observe(&numbered(first, Some(1), 0)), None);
+        assert_eq!(tracker.observe(&numbered(second, Some(7), 0)), None);
+        assert_eq!(tracker.observe(&numbered(first, None, 0)), None);
+        assert_eq!(tracker.observe(&numbered(first, Some(2), 0)), None);
+
+        let gap = tracker.observe(&numbered(second, Some(10), 0)).unwrap();
+        assert_eq!(
+            gap,
+            GapDetected {
+                author: second,
+                expected: 8,
+                got: 10,
+            }
+        );
+        assert_eq!(gap.missing(), 2);
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Duration, Utc};
+
+const ACCEPTED_FORMATS: &str = "RFC 3339 (2024-01-01T00:00:00Z), unix epoch seconds (1704067200), \
+     a relative duration (15m, 24h, 7d) or now/now-2h";
+
+/// Parses a point in time for `since`/`until` style options, shared by the CLI and the
+/// API. Accepts RFC 3339 timestamps, unix epoch seconds, `now`, and durations counted
+/// back from `now` (`30s`, `15m`, `24h`, `7d`, `2w`, optionally written as `now-24h`).
+///
+/// A bare number is always epoch seconds; relative durations need a unit.
+pub fn parse_time(value: &str, now: DateTime<Utc>) -> std::result::Result<DateTime<Utc>, String> {
+    let trimmed = value.trim();
+    let invalid = || format!("Invalid time '{}'. Expected {}", value, ACCEPTED_FORMATS);
+
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+
+    if !trimmed.is_empty() && trimmed.chars().all(|c| c.is_ascii_digit()) {
+        let seconds: i64 = trimmed.parse().map_err(|_| invalid())?;
+        return DateTime::from_timestamp(seconds, 0).ok_or_else(invalid);
+    }
+
+    if trimmed.eq_ignore_ascii_case("now") {
+        return Ok(now);
+    }
+
+    let relative = trimmed
+        .strip_prefix("now-")
+        .or_else(|| trimmed.strip_prefix("NOW-"))
+        .unwrap_or(trimmed);
+
+    parse_duration(relative)
+        .and_then(|duration| now.checked_sub_signed(duration))
+        .ok_or_else(invalid)
+}
+
+/// Parses `<amount><unit>` with units `s`, `m`, `h`, `d` and `w`, as used for relative
+/// times and for window options such as `--warm-window 1h`.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().ok()?;
+
+    match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        "w" => Duration::try_weeks(amount),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn parse(value: &str) -> DateTime<Utc> {
+        parse_time(value, now()).unwrap()
+    }
+
+    #[test]
+    fn rfc3339_timestamps_are_converted_to_utc() {
+        assert_eq!(
+            parse("2024-01-01T02:00:00+02:00"),
+            DateTime::from_timestamp(1_704_067_200, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn bare_numbers_are_epoch_seconds() {
+        assert_eq!(
+            parse(" 1704067200 "),
+            DateTime::from_timestamp(1_704_067_200, 0).unwrap()
+        );
+        // Not 15 seconds ago: relative durations need a unit.
+        assert_eq!(parse("15"), DateTime::from_timestamp(15, 0).unwrap());
+    }
+
+    #[test]
+    fn durations_count_back_from_now() {
+        assert_eq!(parse("now"), now());
+        assert_eq!(parse("15m"), now() - Duration::minutes(15));
+        assert_eq!(parse("24h"), now() - Duration::hours(24));
+        assert_eq!(parse("7d"), now() - Duration::days(7));
+        assert_eq!(parse("now-2h"), now() - Duration::hours(2));
+        assert_eq!(parse("NOW-2w"), now() - Duration::weeks(2));
+    }
+
+    #[test]
+    fn invalid_times_name_the_accepted_formats() {
+        for value in ["", "yesterday", "15y", "now+2h", "2024-01-01", "-5m"] {
+            let message = parse_time(value, now()).unwrap_err();
+            assert!(message.contains(ACCEPTED_FORMATS), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! `sentrystr-collector tui`, a live dashboard of subscribed events, behind the `tui`
+//! feature.
+//!
+//! [`TuiState`] holds what the dashboard shows and only changes through
+//! [`TuiState::apply`], so it works without a terminal. [`run`] feeds it the events of a
+//! subscription, key presses and the collector's stats, and draws it.
+
+use crate::{CollectedEvent, CollectorError, CollectorStats, EventFilter, Result};
+use base64::Engine;
+use chrono::Utc;
+use crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Sparkline, Wrap};
+use sentrystr::Level;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Events kept in the list; the oldest are dropped beyond it.
+pub const DEFAULT_CAPACITY: usize = 1000;
+/// Minutes shown by the events-per-minute sparkline.
+const RATE_MINUTES: usize = 60;
+/// How often the dashboard is redrawn and key presses are read.
+const TICK: Duration = Duration::from_millis(100);
+/// Events skipped by page up and down.
+const PAGE: usize = 10;
+const LEVELS: [Level; 5] = [
+    Level::Debug,
+    Level::Info,
+    Level::Warning,
+    Level::Error,
+    Level::Fatal,
+];
+
+/// What changes the dashboard.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// An event arrived from the subscription.
+    Received(Box<CollectedEvent>),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    /// Cycles the minimum level shown through the levels, then back to all.
+    CycleLevel,
+    /// Cycles the service shown through the services seen so far, then back to all.
+    CycleService,
+    /// Holds new events back, or adds the held ones to the list.
+    TogglePause,
+    /// The collector had received `received` events at `minute`, in minutes since the
+    /// unix epoch.
+    Tick {
+        minute: i64,
+        received: u64,
+    },
+}
+
+/// The dashboard's events, filters, selection and counters. Events are counted as they
+/// arrive; the subscription of [`crate::EventCollector::subscribe_to_events`] already
+/// delivers each one once, however many relays send it.
+#[derive(Debug)]
+pub struct TuiState {
+    capacity: usize,
+    /// Newest first.
+    events: VecDeque<CollectedEvent>,
+    /// Events that arrived while paused, oldest first. At most `capacity` are held,
+    /// since resuming would drop the older ones from the list anyway.
+    held: VecDeque<CollectedEvent>,
+    paused: bool,
+    min_level: Option<Level>,
+    service: Option<String>,
+    services: BTreeSet<String>,
+    level_counts: HashMap<Level, u64>,
+    /// Index of the selected event among the visible ones.
+    selected: usize,
+    minute: Option<i64>,
+    minute_start: u64,
+    last_received: u64,
+    /// Events per completed minute, oldest first.
+    per_minute: VecDeque<u64>,
+}
+
+impl TuiState {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            events: VecDeque::new(),
+            held: VecDeque::new(),
+            paused: false,
+            min_level: None,
+            service: None,
+            services: BTreeSet::new(),
+            level_counts: HashMap::new(),
+            selected: 0,
+            minute: None,
+            minute_start: 0,
+            last_received: 0,
+            per_minute: VecDeque::new(),
+        }
+    }
+
+    pub fn apply(&mut self, action: Action) {
+        match action {
+            Action::Received(event) => self.receive(*event),
+            Action::Up => self.select(self.selected.saturating_sub(1)),
+            Action::Down => self.select(self.selected + 1),
+            Action::PageUp => self.select(self.selected.saturating_sub(PAGE)),
+            Action::PageDown => self.select(self.selected + PAGE),
+            Action::Top => self.select(0),
+            Action::Bottom => self.select(usize::MAX),
+            Action::CycleLevel => {
+                self.min_level = match self.min_level {
+                    None => Some(LEVELS[0].clone()),
+                    Some(ref level) => LEVELS
+                        .iter()
+                        .skip_while(|candidate| *candidate != level)
+                        .nth(1)
+                        .cloned(),
+                };
+                self.selected = 0;
+            }
+            Action::CycleService => {
+                self.service = match self.service {
+                    None => self.services.iter().next().cloned(),
+                    Some(ref service) => self
+                        .services
+                        .range::<String, _>((
+                            std::ops::Bound::Excluded(service),
+                            std::ops::Bound::Unbounded,
+                        ))
+                        .next()
+                        .cloned(),
+                };
+                self.selected = 0;
+            }
+            Action::TogglePause => {
+                self.paused = !self.paused;
+                if !self.paused {
+                    for event in std::mem::take(&mut self.held) {
+                        self.push(event);
+                    }
+                }
+            }
+            Action::Tick { minute, received } => self.tick(minute, received),
+        }
+    }
+
+    fn receive(&mut self, event: CollectedEvent) {
+        *self
+            .level_counts
+            .entry(event.event.level.clone())
+            .or_default() += 1;
+        if let Some(service) = event.nostr_tag("service") {
+            self.services.insert(service.to_string());
+        }
+
+        if self.paused {
+            self.held.push_back(event);
+            if self.held.len() > self.capacity {
+                self.held.pop_front();
+            }
+        } else {
+            self.push(event);
+        }
+    }
+
+    /// Adds `event` at the top of the list, keeping the selection on the same event
+    /// unless the newest one was selected.
+    fn push(&mut self, event: CollectedEvent) {
+        if self.selected > 0 && self.filter().matches_collected(&event) {
+            self.selected += 1;
+        }
+        self.events.push_front(event);
+        self.events.truncate(self.capacity);
+        self.select(self.selected);
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.visible().len().saturating_sub(1));
+    }
+
+    fn tick(&mut self, minute: i64, received: u64) {
+        match self.minute {
+            None => {
+                self.minute = Some(minute);
+                self.minute_start = received;
+            }
+            Some(current) if minute > current => {
+                self.per_minute
+                    .push_back(self.last_received.saturating_sub(self.minute_start));
+                let idle = (minute - current - 1).min(RATE_MINUTES as i64);
+                self.per_minute.extend((0..idle).map(|_| 0));
+                while self.per_minute.len() > RATE_MINUTES {
+                    self.per_minute.pop_front();
+                }
+                self.minute = Some(minute);
+                self.minute_start = self.last_received;
+            }
+            Some(_) => {}
+        }
+        self.last_received = received;
+    }
+
+    /// The filter of the level and service picked with [`Action::CycleLevel`] and
+    /// [`Action::CycleService`].
+    pub fn filter(&self) -> EventFilter {
+        let mut filter = EventFilter::new();
+        if let Some(ref level) = self.min_level {
+            filter = filter.with_min_level(level.clone());
+        }
+        if let Some(ref service) = self.service {
+            filter = filter.with_service_filter(service.clone());
+        }
+        filter
+    }
+
+    /// The listed events that pass [`Self::filter`], newest first.
+    pub fn visible(&self) -> Vec<&CollectedEvent> {
+        let filter = self.filter();
+        self.events
+            .iter()
+            .filter(|event| filter.matches_collected(event))
+            .collect()
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn selected_event(&self) -> Option<&CollectedEvent> {
+        self.visible().get(self.selected).copied()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Events that arrived while paused, up to the capacity.
+    pub fn held(&self) -> usize {
+        self.held.len()
+    }
+
+    pub fn min_level(&self) -> Option<&Level> {
+        self.min_level.as_ref()
+    }
+
+    pub fn service(&self) -> Option<&str> {
+        self.service.as_deref()
+    }
+
+    /// Events received of `level`, whatever the filter.
+    pub fn level_count(&self, level: &Level) -> u64 {
+        self.level_counts.get(level).copied().unwrap_or(0)
+    }
+
+    /// Events per minute, oldest first, ending with the current minute so far.
+    pub fn rate(&self) -> Vec<u64> {
+        self.per_minute
+            .iter()
+            .copied()
+            .chain(
+                self.minute
+                    .map(|_| self.last_received.saturating_sub(self.minute_start)),
+            )
+            .collect()
+    }
+
+    /// The selected event as pretty JSON, with its tags and extras.
+    pub fn detail(&self) -> Option<String> {
+        let event = self.selected_event()?;
+        let detail = serde_json::json!({
+            "nostr_event_id": event.nostr_event_id.to_hex(),
+            "author": event.author.to_hex(),
+            "timestamp": event.event.timestamp,
+            "level": event.event.level.to_string(),
+            "message": event.event.message,
+            "logger": event.event.logger,
+            "environment": event.event.environment,
+            "release": event.event.release,
+            "tags": event.event.tags,
+            "extra": event.event.extra,
+            "exception": event.event.exception,
+        });
+        serde_json::to_string_pretty(&detail).ok()
+    }
+}
+
+fn action_for(code: KeyCode) -> Option<Action> {
+    match code {
+        KeyCode::Up | KeyCode::Char('k') => Some(Action::Up),
+        KeyCode::Down | KeyCode::Char('j') => Some(Action::Down),
+        KeyCode::PageUp => Some(Action::PageUp),
+        KeyCode::PageDown => Some(Action::PageDown),
+        KeyCode::Home | KeyCode::Char('g') => Some(Action::Top),
+        KeyCode::End | KeyCode::Char('G') => Some(Action::Bottom),
+        KeyCode::Char('l') => Some(Action::CycleLevel),
+        KeyCode::Char('s') => Some(Action::CycleService),
+        KeyCode::Char('p') | KeyCode::Char(' ') => Some(Action::TogglePause),
+        _ => None,
+    }
+}
+
+fn terminal_error(error: std::io::Error) -> CollectorError {
+    CollectorError::Collection(format!("Terminal error: {}", error))
+}
+
+/// Shows `events` until `q` is pressed, using `stats` for the events-per-minute
+/// sparkline. Keeps at most `capacity` events.
+pub async fn run(
+    mut events: mpsc::Receiver<CollectedEvent>,
+    stats: Arc<CollectorStats>,
+    capacity: usize,
+) -> Result<()> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, &mut events, &stats, capacity).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_loop(
+    terminal: &mut DefaultTerminal,
+    events: &mut mpsc::Receiver<CollectedEvent>,
+    stats: &CollectorStats,
+    capacity: usize,
+) -> Result<()> {
+    let mut state = TuiState::new(capacity);
+    let mut status = String::new();
+    let mut subscribed = true;
+    let mut ticks = tokio::time::interval(TICK);
+
+    loop {
+        tokio::select! {
+            event = events.recv(), if subscribed => match event {
+                Some(event) => state.apply(Action::Received(Box::new(event))),
+                None => {
+                    subscribed = false;
+                    status = "The subscription ended".to_string();
+                }
+            },
+            _ = ticks.tick() => {
+                state.apply(Action::Tick {
+                    minute: Utc::now().timestamp().div_euclid(60),
+                    received: stats.events_received(),
+                });
+
+                while event::poll(Duration::ZERO).map_err(terminal_error)? {
+                    let TermEvent::Key(key) = event::read().map_err(terminal_error)? else {
+                        continue;
+                    };
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                            return Ok(());
+                        }
+                        KeyCode::Char('c') => status = copy_selected(&state),
+                        code => {
+                            if let Some(action) = action_for(code) {
+                                state.apply(action);
+                            }
+                        }
+                    }
+                }
+
+                terminal
+                    .draw(|frame| draw(frame, &state, &status))
+                    .map_err(terminal_error)?;
+            }
+        }
+    }
+}
+
+/// Copies the id of the selected event to the clipboard through the terminal (OSC 52).
+fn copy_selected(state: &TuiState) -> String {
+    let Some(event) = state.selected_event() else {
+        return "No event selected".to_string();
+    };
+    let id = event.nostr_event_id.to_hex();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(id.as_bytes());
+    let mut stdout = std::io::stdout();
+    match write!(stdout, "\x1b]52;c;{}\x07", encoded).and_then(|()| stdout.flush()) {
+        Ok(()) => format!("Copied {}", id),
+        Err(e) => format!("Failed to copy the event id: {}", e),
+    }
+}
+
+fn level_style(level: &Level) -> Style {
+    match level {
+        Level::Fatal => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Level::Error => Style::new().fg(Color::Red),
+        Level::Warning => Style::new().fg(Color::Yellow),
+        Level::Info => Style::new().fg(Color::Green),
+        Level::Debug => Style::new().fg(Color::DarkGray),
+    }
+}
+
+fn draw(frame: &mut Frame, state: &TuiState, status: &str) {
+    let [top, body, footer] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [counts_area, rate_area] =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(top);
+    let [list_area, detail_area] =
+        Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+
+    let counts: Vec<Span> = LEVELS
+        .iter()
+        .flat_map(|level| {
+            [
+                Span::styled(
+                    format!("{} {}", level, state.level_count(level)),
+                    level_style(level),
+                ),
+                Span::raw("  "),
+            ]
+        })
+        .collect();
+    frame.render_widget(
+        Paragraph::new(Line::from(counts)).block(Block::bordered().title("Events by level")),
+        counts_area,
+    );
+
+    let rate = state.rate();
+    let width = usize::from(rate_area.width.saturating_sub(2));
+    let rate = &rate[rate.len().saturating_sub(width)..];
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(format!(
+                "Events per minute ({} this minute)",
+                rate.last().copied().unwrap_or(0)
+            )))
+            .data(rate),
+        rate_area,
+    );
+
+    let visible = state.visible();
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|event| {
+            let message = event.event.message.as_deref().unwrap_or_default();
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{} ", event.event.timestamp.format("%H:%M:%S"))),
+                Span::styled(
+                    format!("{:<8}", event.event.level.to_string()),
+                    level_style(&event.event.level),
+                ),
+                Span::styled(
+                    format!("{} ", event.nostr_tag("service").unwrap_or("-")),
+                    Style::new().fg(Color::Cyan),
+                ),
+                Span::raw(message.lines().next().unwrap_or_default().to_string()),
+            ]))
+        })
+        .collect();
+    let mut title = format!("Events ({})", visible.len());
+    if let Some(level) = state.min_level() {
+        title.push_str(&format!(" level>={}", level));
+    }
+    if let Some(service) = state.service() {
+        title.push_str(&format!(" service={}", service));
+    }
+    if state.is_paused() {
+        title.push_str(&format!(" PAUSED, {} new", state.held()));
+    }
+    let mut list_state =
+        ListState::default().with_selected((!visible.is_empty()).then_some(state.selected()));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+        list_area,
+        &mut list_state,
+    );
+
+    frame.render_widget(
+        Paragraph::new(state.detail().unwrap_or_default())
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Details")),
+        detail_area,
+    );
+
+    let help = "q quit  ↑↓ select  l level  s service  p pause  c copy id";
+    frame.render_widget(
+        Paragraph::new(if status.is_empty() { help } else { status }),
+        footer,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::{EventId, Keys, Tag, TagKind, Timestamp};
+    use sentrystr::Event;
+
+    fn collected(message: &str, level: Level, service: &str) -> Box<CollectedEvent> {
+        Box::new(CollectedEvent {
+            event: Event::new().with_message(message).with_level(level),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            created_at: Timestamp::now(),
+            received_at: Utc::now(),
+            nostr_tags: vec![Tag::custom(TagKind::custom("service"), [service])],
+            seq: None,
+        })
+    }
+
+    fn messages(state: &TuiState) -> Vec<&str> {
+        state
+            .visible()
+            .iter()
+            .map(|event| event.event.message.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    #[test]
+    fn the_oldest_events_leave_the_list_beyond_capacity() {
+        let mut state = TuiState::new(2);
+        for message in ["first", "second", "third"] {
+            state.apply(Action::Received(collected(message, Level::Error, "api")));
+        }
+
+        assert_eq!(messages(&state), vec!["third", "second"]);
+        assert_eq!(state.level_count(&Level::Error), 3);
+    }
+
+    #[test]
+    fn paused_events_are_held_up_to_capacity() {
+        let mut state = TuiState::new(2);
+        state.apply(Action::Received(collected("before", Level::Info, "api")));
+        state.apply(Action::TogglePause);
+        for message in ["one", "two", "three"] {
+            state.apply(Action::Received(collected(message, Level::Info, "api")));
+        }
+
+        assert!(state.is_paused());
+        assert_eq!(state.held(), 2);
+        assert_eq!(messages(&state), vec!["before"]);
+
+        state.apply(Action::TogglePause);
+        assert_eq!(state.held(), 0);
+        assert_eq!(messages(&state), vec!["three", "two"]);
+    }
+
+    #[test]
+    fn the_selection_stays_on_its_event_when_others_arrive() {
+        let mut state = TuiState::new(10);
+        state.apply(Action::Received(collected("old", Level::Error, "api")));
+        state.apply(Action::Received(collected("new", Level::Error, "api")));
+        state.apply(Action::Down);
+        assert_eq!(state.selected(), 1);
+
+        state.apply(Action::Received(collected("newest", Level::Error, "api")));
+
+        let selected = state.selected_event().unwrap();
+        assert_eq!(selected.event.message.as_deref(), Some("old"));
+    }
+
+    #[test]
+    fn level_and_service_cycles_filter_the_list() {
+        let mut state = TuiState::new(10);
+        state.apply(Action::Received(collected("debug", Level::Debug, "api")));
+        state.apply(Action::Received(collected("error", Level::Error, "web")));
+
+        // Debug, Info, Warning, then Error.
+        for _ in 0..4 {
+            state.apply(Action::CycleLevel);
+        }
+        assert_eq!(state.min_level(), Some(&Level::Error));
+        assert_eq!(messages(&state), vec!["error"]);
+
+        for _ in 0..2 {
+            state.apply(Action::CycleLevel);
+        }
+        assert_eq!(state.min_level(), None);
+
+        state.apply(Action::CycleService);
+        assert_eq!(state.service(), Some("api"));
+        assert_eq!(messages(&state), vec!["debug"]);
+        state.apply(Action::CycleService);
+        state.apply(Action::CycleService);
+        assert_eq!(state.service(), None);
+    }
+
+    #[test]
+    fn ticks_count_events_per_minute() {
+        let mut state = TuiState::new(10);
+        state.apply(Action::Tick {
+            minute: 100,
+            received: 5,
+        });
+        state.apply(Action::Tick {
+            minute: 100,
+            received: 8,
+        });
+        // One idle minute passes between 101 and 103.
+        state.apply(Action::Tick {
+            minute: 102,
+            received: 9,
+        });
+
+        assert_eq!(state.rate(), vec![3, 0, 1]);
+    }
+}
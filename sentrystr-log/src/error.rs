@@ -0,0 +1,10 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum LogError {
+    #[error("SentryStr core error: {0}")]
+    SentryStr(#[from] sentrystr::SentryStrError),
+
+    #[error("A logger is already installed: {0}")]
+    SetLogger(#[from] log::SetLoggerError),
+}
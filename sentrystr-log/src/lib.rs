@@ -0,0 +1,63 @@
+//! # SentryStr Log
+//!
+//! Sends the records of the `log` facade to Nostr relays as SentryStr events, for
+//! dependencies that do not use `tracing`.
+//!
+//! ## Quick Start
+//!
+//! ```rust,no_run
+//! use sentrystr::Config;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let config = Config::from_env()?;
+//!     let client = sentrystr_log::init_boxed_logger(config).await?;
+//!
+//!     log::info!("Application started");
+//!     log::error!("Database connection failed");
+//!
+//!     client.close(std::time::Duration::from_secs(5)).await;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Next to env_logger
+//!
+//! ```rust,ignore
+//! use sentrystr_log::SentryStrLogger;
+//!
+//! SentryStrLogger::new(client)
+//!     .with_max_level(log::LevelFilter::Warn)
+//!     .chain(env_logger::Builder::from_default_env().build())
+//!     .install()?;
+//! ```
+
+pub mod error;
+pub mod logger;
+
+pub use error::LogError;
+pub use logger::{FLUSH_TIMEOUT, IGNORED_TARGETS, SentryStrLogger, record_event};
+
+use sentrystr::{Config, Level, NostrSentryClient};
+use std::sync::Arc;
+
+pub type Result<T> = std::result::Result<T, LogError>;
+
+pub fn convert_log_level(level: log::Level) -> Level {
+    match level {
+        log::Level::Trace => Level::Debug,
+        log::Level::Debug => Level::Debug,
+        log::Level::Info => Level::Info,
+        log::Level::Warn => Level::Warning,
+        log::Level::Error => Level::Error,
+    }
+}
+
+/// Connects a client with `config` and installs a [`SentryStrLogger`] for it with the
+/// default filters. Returns the client, to close before exiting so records still being
+/// sent are not lost.
+pub async fn init_boxed_logger(config: Config) -> Result<Arc<NostrSentryClient>> {
+    let client = Arc::new(NostrSentryClient::new(config).await?);
+    SentryStrLogger::new(Arc::clone(&client)).install()?;
+    Ok(client)
+}
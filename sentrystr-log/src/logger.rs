@@ -0,0 +1,340 @@
+use crate::{Result, convert_log_level};
+use log::{LevelFilter, Log, Metadata, Record};
+use sentrystr::{ClientTransport, Event, NostrSentryClient, Transport};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Target prefixes never captured unless configured otherwise: the client's own
+/// dependencies log while publishing, and capturing them would feed the logger its own
+/// output.
+pub const IGNORED_TARGETS: &[&str] = &["nostr", "sentrystr", "tungstenite", "tokio_tungstenite"];
+
+/// How long [`Log::flush`] waits for the records still being sent.
+pub const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Counts the records being sent, so that flushing can wait for them.
+#[derive(Default)]
+struct InFlight {
+    count: Mutex<usize>,
+    done: Condvar,
+}
+
+impl InFlight {
+    fn start(self: &Arc<Self>) -> Sending {
+        *self.count.lock().unwrap() += 1;
+        Sending(Arc::clone(self))
+    }
+
+    /// Waits until no record is being sent, for at most `timeout`.
+    fn wait(&self, timeout: Duration) {
+        let count = self.count.lock().unwrap();
+        let _ = self
+            .done
+            .wait_timeout_while(count, timeout, |count| *count > 0);
+    }
+}
+
+/// A record being sent, until dropped.
+struct Sending(Arc<InFlight>);
+
+impl Drop for Sending {
+    fn drop(&mut self) {
+        let mut count = self.0.count.lock().unwrap();
+        *count -= 1;
+        if *count == 0 {
+            self.0.done.notify_all();
+        }
+    }
+}
+
+/// A `log::Log` turning records into [`sentrystr::Event`]s, with its own level and
+/// target filters.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use log::LevelFilter;
+/// use sentrystr::NostrSentryClient;
+/// use sentrystr_log::SentryStrLogger;
+/// use std::sync::Arc;
+///
+/// # fn example(client: Arc<NostrSentryClient>) -> sentrystr_log::Result<()> {
+/// SentryStrLogger::new(client)
+///     .with_max_level(LevelFilter::Warn)
+///     .with_target_level("hyper", LevelFilter::Error)
+///     .install()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct SentryStrLogger {
+    transport: Arc<dyn Transport>,
+    max_level: LevelFilter,
+    /// Levels of the targets starting with a prefix; the longest matching prefix wins.
+    targets: Vec<(String, LevelFilter)>,
+    next: Option<Box<dyn Log>>,
+    in_flight: Arc<InFlight>,
+}
+
+impl SentryStrLogger {
+    /// Captures records at `Info` and above with `client`, except those of
+    /// [`IGNORED_TARGETS`].
+    pub fn new(client: Arc<NostrSentryClient>) -> Self {
+        Self::with_transport(Arc::new(ClientTransport::new(client)))
+    }
+
+    /// [`Self::new`] sending events to `transport` instead of a client, e.g. one
+    /// recording them in tests.
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            max_level: LevelFilter::Info,
+            targets: IGNORED_TARGETS
+                .iter()
+                .map(|target| (target.to_string(), LevelFilter::Off))
+                .collect(),
+            next: None,
+            in_flight: Arc::default(),
+        }
+    }
+
+    /// Most verbose level captured for targets without their own level.
+    pub fn with_max_level(mut self, level: LevelFilter) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Most verbose level captured for targets starting with `target`, e.g. `"hyper"`
+    /// for `hyper::client` too. The longest matching prefix wins, and a level set for an
+    /// ignored target replaces the ignore.
+    pub fn with_target_level(mut self, target: impl Into<String>, level: LevelFilter) -> Self {
+        let target = target.into();
+        self.targets.retain(|(known, _)| *known != target);
+        self.targets.push((target, level));
+        self
+    }
+
+    /// Captures nothing from targets starting with `target`.
+    pub fn ignore_target(self, target: impl Into<String>) -> Self {
+        self.with_target_level(target, LevelFilter::Off)
+    }
+
+    /// Hands every record to `next` as well, such as an `env_logger` logger, which
+    /// applies its own filters. Records this logger ignores still reach `next`.
+    pub fn chain(mut self, next: impl Log + 'static) -> Self {
+        self.next = Some(Box::new(next));
+        self
+    }
+
+    /// The most verbose level a record may need to reach this logger, `Trace` with a
+    /// chained logger, whose filters are unknown.
+    pub fn max_level(&self) -> LevelFilter {
+        if self.next.is_some() {
+            return LevelFilter::Trace;
+        }
+        self.targets
+            .iter()
+            .map(|(_, level)| *level)
+            .chain([self.max_level])
+            .max()
+            .unwrap_or(LevelFilter::Off)
+    }
+
+    /// Makes this the logger of the `log` facade, with `log::set_max_level` at
+    /// [`Self::max_level`]. Fails when a logger is already installed.
+    pub fn install(self) -> Result<()> {
+        let max_level = self.max_level();
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(max_level);
+        Ok(())
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.targets
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.max_level, |(_, level)| *level)
+    }
+
+    fn captures(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+}
+
+impl Log for SentryStrLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.captures(metadata)
+            || self
+                .next
+                .as_ref()
+                .is_some_and(|next| next.enabled(metadata))
+    }
+
+    /// Passes `record` on to the chained logger, then sends its event on a spawned task,
+    /// so logging never waits for relays. Records logged outside a Tokio runtime are
+    /// dropped.
+    fn log(&self, record: &Record<'_>) {
+        if let Some(ref next) = self.next {
+            next.log(record);
+        }
+        if !self.captures(record.metadata()) {
+            return;
+        }
+
+        let event = record_event(record);
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let transport = Arc::clone(&self.transport);
+                let sending = self.in_flight.start();
+                runtime.spawn(async move {
+                    let _sending = sending;
+                    if let Err(e) = transport.send(&event).await {
+                        eprintln!("Failed to send log record to SentryStr: {}", e);
+                    }
+                });
+            }
+            Err(_) => eprintln!(
+                "Dropped log record of {}: no Tokio runtime to send it on",
+                record.target()
+            ),
+        }
+    }
+
+    /// Flushes the chained logger, then waits up to [`FLUSH_TIMEOUT`] for the records
+    /// still being sent. Inside a current-thread runtime, whose tasks cannot run while it
+    /// waits, it returns at once.
+    fn flush(&self) {
+        if let Some(ref next) = self.next {
+            next.flush();
+        }
+        let wait = || self.in_flight.wait(FLUSH_TIMEOUT);
+        match tokio::runtime::Handle::try_current().map(|runtime| runtime.runtime_flavor()) {
+            Ok(tokio::runtime::RuntimeFlavor::MultiThread) => tokio::task::block_in_place(wait),
+            Ok(_) => {}
+            Err(_) => wait(),
+        }
+    }
+}
+
+/// The event of `record`: its message and level, its target as the logger, and its
+/// source location as the `meta_*` extras of the tracing layer's events.
+pub fn record_event(record: &Record<'_>) -> Event {
+    let mut event = Event::new()
+        .with_message(record.args().to_string())
+        .with_level(convert_log_level(record.level()))
+        .with_extra("meta_target", record.target().into())
+        .with_extra("meta_level", record.level().as_str().into());
+    event.logger = Some(record.target().to_string());
+
+    if let Some(module_path) = record.module_path() {
+        event = event.with_extra("meta_module_path", module_path.into());
+    }
+    if let Some(file) = record.file() {
+        event = event.with_extra("meta_file", file.into());
+    }
+    if let Some(line) = record.line() {
+        event = event.with_extra("meta_line", line.into());
+    }
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sentrystr::Level;
+    use sentrystr::transport::TransportFuture;
+
+    /// Records the events it is sent, after a delay, as a slow relay would.
+    #[derive(Debug, Default)]
+    struct SlowRecording(Mutex<Vec<Event>>);
+
+    impl Transport for SlowRecording {
+        fn name(&self) -> &str {
+            "slow-recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                self.0.lock().unwrap().push(event.clone());
+                Ok(())
+            })
+        }
+    }
+
+    impl SlowRecording {
+        fn levels(&self) -> Vec<(String, Level)> {
+            let mut levels: Vec<(String, Level)> = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|event| (event.message.clone().unwrap(), event.level.clone()))
+                .collect();
+            levels.sort_by(|a, b| a.0.cmp(&b.0));
+            levels
+        }
+    }
+
+    /// The only test installing the logger, which can be done once per process. Records
+    /// name a target, as this module's own path starts with the ignored `sentrystr`.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn installed_records_are_mapped_filtered_and_flushed() {
+        let transport = Arc::new(SlowRecording::default());
+        SentryStrLogger::with_transport(transport.clone())
+            .with_max_level(LevelFilter::Trace)
+            .with_target_level("noisy", LevelFilter::Warn)
+            .install()
+            .unwrap();
+
+        log::trace!(target: "my_app", "1 trace");
+        log::debug!(target: "my_app", "2 debug");
+        log::info!(target: "my_app", "3 info");
+        log::warn!(target: "my_app", "4 warn");
+        log::error!(target: "my_app", "5 error");
+        log::info!(target: "noisy::pool", "6 quiet");
+        log::warn!(target: "noisy::pool", "7 loud");
+        log::error!(target: "nostr_relay_pool", "8 ignored");
+        log::logger().flush();
+
+        assert_eq!(
+            transport.levels(),
+            [
+                ("1 trace".to_string(), Level::Debug),
+                ("2 debug".to_string(), Level::Debug),
+                ("3 info".to_string(), Level::Info),
+                ("4 warn".to_string(), Level::Warning),
+                ("5 error".to_string(), Level::Error),
+                ("7 loud".to_string(), Level::Warning),
+            ]
+        );
+
+        let events = transport.0.lock().unwrap();
+        let info = events
+            .iter()
+            .find(|event| event.message.as_deref() == Some("3 info"))
+            .unwrap();
+        assert_eq!(info.logger.as_deref(), Some("my_app"));
+        assert_eq!(info.extra["meta_target"], "my_app");
+        assert_eq!(info.extra["meta_module_path"], module_path!());
+        assert_eq!(info.extra["meta_level"], "INFO");
+        assert_eq!(info.extra["meta_file"], file!());
+    }
+
+    #[test]
+    fn the_facade_level_covers_every_target_level() {
+        let transport = Arc::new(SlowRecording::default());
+        let logger = SentryStrLogger::with_transport(transport.clone());
+        assert_eq!(logger.max_level(), LevelFilter::Info);
+
+        let logger = logger
+            .with_max_level(LevelFilter::Warn)
+            .with_target_level("my_app", LevelFilter::Debug);
+        assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+        let metadata = |target, level| Metadata::builder().target(target).level(level).build();
+        assert!(logger.enabled(&metadata("my_app::db", log::Level::Debug)));
+        assert!(!logger.enabled(&metadata("hyper", log::Level::Info)));
+        assert!(!logger.enabled(&metadata("sentrystr::client", log::Level::Error)));
+    }
+}
@@ -42,7 +42,9 @@ impl PyNostrSentryClient {
         })
     }
 
-    pub fn capture_event(&self, py: Python<'_>, event: &PyEvent) -> PyResult<()> {
+    /// Returns the hex id of the published Nostr event, or `None` when the event was
+    /// sampled out, throttled or filtered.
+    pub fn capture_event(&self, py: Python<'_>, event: &PyEvent) -> PyResult<Option<String>> {
         // Clone out of the pyclasses so the GIL-released closure captures only
         // `Send + Ungil` data. `PyErr` is `!Ungil`, so the blocking work returns
         // the native error and we build the `PyErr` after re-acquiring the GIL.
@@ -52,7 +54,8 @@ impl PyNostrSentryClient {
         py.detach(move || {
             runtime.block_on(async move {
                 let client = inner.lock().unwrap();
-                client.capture_event(event).await.map(|_| ())
+                let outcome = client.capture_event(event).await?;
+                Ok::<_, sentrystr::SentryStrError>(outcome.event_id().map(|id| id.to_hex()))
             })
         })
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
@@ -64,7 +67,10 @@ impl PyNostrSentryClient {
         py.detach(move || {
             runtime.block_on(async move {
                 let client = inner.lock().unwrap();
-                client.capture_message(&message).await.map(|_| ())
+                client
+                    .capture_message(sentrystr::Level::Info, &message)
+                    .await
+                    .map(|_| ())
             })
         })
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
@@ -76,7 +82,7 @@ impl PyNostrSentryClient {
         py.detach(move || {
             runtime.block_on(async move {
                 let client = inner.lock().unwrap();
-                client.capture_error(&error).await.map(|_| ())
+                client.capture_error_message(&error).await.map(|_| ())
             })
         })
         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
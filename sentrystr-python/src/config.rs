@@ -1,5 +1,5 @@
 use pyo3::prelude::*;
-use sentrystr::{Config, EncryptionVersion};
+use sentrystr::{Config, EncryptionVersion, RelaySpec};
 
 #[pyclass(name = "Config")]
 #[derive(Debug, Clone)]
@@ -11,18 +11,23 @@ pub struct PyConfig {
 impl PyConfig {
     #[new]
     pub fn new(private_key: String, relays: Vec<String>) -> PyResult<Self> {
-        let config = Config::new(private_key, relays);
+        let config = Config::try_new(private_key, relays)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
         Ok(Self { inner: config })
     }
 
     #[getter]
     pub fn relays(&self) -> Vec<String> {
-        self.inner.relays.clone()
+        self.inner
+            .relays
+            .iter()
+            .map(|relay| relay.url.clone())
+            .collect()
     }
 
     #[setter]
     pub fn set_relays(&mut self, relays: Vec<String>) {
-        self.inner.relays = relays;
+        self.inner.relays = relays.into_iter().map(RelaySpec::from).collect();
     }
 
     #[getter]
@@ -80,7 +85,8 @@ impl PyConfig {
     fn __repr__(&self) -> String {
         format!(
             "Config(relays={:?}, encrypt_events={})",
-            self.inner.relays, self.inner.encrypt_events
+            self.relays(),
+            self.inner.encrypt_events
         )
     }
 }
@@ -22,26 +22,14 @@ pub enum PyLevel {
 impl PyLevel {
     #[new]
     pub fn new(level: &str) -> PyResult<Self> {
-        match level.to_lowercase().as_str() {
-            "debug" => Ok(PyLevel::Debug),
-            "info" => Ok(PyLevel::Info),
-            "warning" | "warn" => Ok(PyLevel::Warning),
-            "error" => Ok(PyLevel::Error),
-            "fatal" => Ok(PyLevel::Fatal),
-            _ => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
-                "Invalid level. Must be one of: debug, info, warning, error, fatal",
-            )),
-        }
+        level
+            .parse::<Level>()
+            .map(PyLevel::from)
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
     }
 
     fn __str__(&self) -> &'static str {
-        match self {
-            PyLevel::Debug => "debug",
-            PyLevel::Info => "info",
-            PyLevel::Warning => "warning",
-            PyLevel::Error => "error",
-            PyLevel::Fatal => "fatal",
-        }
+        Level::from(self.clone()).as_str()
     }
 
     fn __repr__(&self) -> String {
@@ -207,6 +195,7 @@ impl PyException {
                 value,
                 module: None,
                 stacktrace: None,
+                chain: Vec::new(),
             },
         }
     }
@@ -0,0 +1,48 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{Router, routing::get};
+use nostr::Keys;
+use sentrystr::{Config, Level, NostrSentryClient};
+use sentrystr_tower::SentryStrHttpLayer;
+use std::sync::Arc;
+
+/// Captures an event from inside the handler; it carries the request from the scope the
+/// layer sets up.
+async fn get_user(
+    State(client): State<Arc<NostrSentryClient>>,
+    Path(id): Path<u64>,
+) -> Result<String, StatusCode> {
+    if id == 0 {
+        let _ = client
+            .capture_message(Level::Warning, "Lookup of user 0")
+            .await;
+        // Reported by the layer as a failed request.
+        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    }
+    Ok(format!("user {}", id))
+}
+
+async fn crash() -> &'static str {
+    panic!("handler crashed");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let keys = Keys::generate();
+    let config = Config::from_keys(&keys, vec!["wss://relay.damus.io".to_string()])?;
+    let client = Arc::new(NostrSentryClient::new(config).await?);
+
+    let app = Router::new()
+        .route("/", get(|| async { "ok" }))
+        .route("/users/:id", get(get_user))
+        .route("/crash", get(crash))
+        .layer(SentryStrHttpLayer::new(Arc::clone(&client)).with_breadcrumbs(true))
+        .with_state(Arc::clone(&client));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+    println!("Listening on http://127.0.0.1:3000, try /users/0 and /crash");
+    axum::serve(listener, app).await?;
+
+    client.close(std::time::Duration::from_secs(5)).await;
+    Ok(())
+}
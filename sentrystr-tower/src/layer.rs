@@ -0,0 +1,320 @@
+use crate::context::RequestContext;
+use futures_util::FutureExt;
+use http::{Request, Response};
+use sentrystr::http::DEFAULT_HEADER_ALLOWLIST;
+use sentrystr::{Breadcrumb, Level, NostrSentryClient, with_scope};
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+
+/// A `tower::Layer` reporting failed HTTP requests: responses with a 5xx status,
+/// service errors and panics are captured as Error events with the request, its id,
+/// the matched route and the allowlisted headers. Handlers run in a
+/// [`sentrystr::with_scope`] holding the request, so the events they capture carry it
+/// too.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use axum::{Router, routing::get};
+/// use sentrystr::NostrSentryClient;
+/// use sentrystr_tower::SentryStrHttpLayer;
+/// use std::sync::Arc;
+///
+/// # fn example(client: Arc<NostrSentryClient>) {
+/// let app: Router = Router::new()
+///     .route("/users/:id", get(|| async { "user" }))
+///     .layer(SentryStrHttpLayer::new(client).with_breadcrumbs(true));
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SentryStrHttpLayer {
+    client: Arc<NostrSentryClient>,
+    breadcrumbs: bool,
+    header_allowlist: Vec<String>,
+}
+
+impl SentryStrHttpLayer {
+    pub fn new(client: Arc<NostrSentryClient>) -> Self {
+        Self {
+            client,
+            breadcrumbs: false,
+            header_allowlist: DEFAULT_HEADER_ALLOWLIST
+                .iter()
+                .map(|header| header.to_string())
+                .collect(),
+        }
+    }
+
+    /// Records a breadcrumb on the client for every request, with its method, path,
+    /// status and latency, so captured events show the requests that led up to them.
+    pub fn with_breadcrumbs(mut self, record: bool) -> Self {
+        self.breadcrumbs = record;
+        self
+    }
+
+    /// Headers recorded on captured events, instead of [`DEFAULT_HEADER_ALLOWLIST`].
+    /// Credentials such as `authorization` and `cookie` are always filtered.
+    pub fn with_header_allowlist(mut self, headers: Vec<String>) -> Self {
+        self.header_allowlist = headers;
+        self
+    }
+
+    /// Records the end of a request, capturing an event on a spawned task when it failed.
+    fn finish(
+        &self,
+        context: &RequestContext,
+        status: Option<u16>,
+        failure: Option<String>,
+        latency: Duration,
+    ) {
+        if self.breadcrumbs {
+            let mut breadcrumb = Breadcrumb::new(format!("{} {}", context.method, context.path))
+                .with_category("http")
+                .with_level(if failure.is_some() {
+                    Level::Error
+                } else {
+                    Level::Info
+                })
+                .with_data("latency_ms", (latency.as_millis() as u64).into())
+                .with_data("request_id", context.request_id.clone().into());
+            if let Some(status) = status {
+                breadcrumb = breadcrumb.with_data("status_code", status.into());
+            }
+            self.client.add_breadcrumb(breadcrumb);
+        }
+
+        if let Some(message) = failure {
+            let event = context.event(message, status, latency);
+            let client = Arc::clone(&self.client);
+            tokio::spawn(async move {
+                if let Err(e) = client.capture_event(event).await {
+                    eprintln!("Failed to capture HTTP error: {}", e);
+                }
+            });
+        }
+    }
+}
+
+impl<S> Layer<S> for SentryStrHttpLayer {
+    type Service = SentryStrHttpService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SentryStrHttpService {
+            inner,
+            layer: Arc::new(self.clone()),
+        }
+    }
+}
+
+/// The service of [`SentryStrHttpLayer`].
+#[derive(Clone)]
+pub struct SentryStrHttpService<S> {
+    inner: S,
+    layer: Arc<SentryStrHttpLayer>,
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SentryStrHttpService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: fmt::Display,
+    ReqBody: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        // The clone may not be ready; call the one `poll_ready` was called on.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = Arc::clone(&self.layer);
+        let context = RequestContext::new(&request, &layer.header_allowlist);
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = with_scope(
+                |scope| context.configure(scope),
+                AssertUnwindSafe(inner.call(request)).catch_unwind(),
+            )
+            .await;
+            let latency = started.elapsed();
+            let name = format!("{} {}", context.method, context.route_or_path());
+
+            match result {
+                Ok(Ok(response)) => {
+                    let status = response.status();
+                    let failure = status
+                        .is_server_error()
+                        .then(|| format!("{} returned {}", name, status));
+                    layer.finish(&context, Some(status.as_u16()), failure, latency);
+                    Ok(response)
+                }
+                Ok(Err(e)) => {
+                    layer.finish(
+                        &context,
+                        None,
+                        Some(format!("{} failed: {}", name, e)),
+                        latency,
+                    );
+                    Err(e)
+                }
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| "unknown panic".to_string());
+                    layer.finish(
+                        &context,
+                        None,
+                        Some(format!("{} panicked: {}", name, message)),
+                        latency,
+                    );
+                    std::panic::resume_unwind(panic)
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use nostr::Keys;
+    use sentrystr::transport::TransportFuture;
+    use sentrystr::{Config, Event, Transport};
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+    use tower::{ServiceExt, service_fn};
+
+    #[derive(Debug)]
+    struct RecordingTransport(Arc<Mutex<Vec<Event>>>);
+
+    impl Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn layer(recorded: &Arc<Mutex<Vec<Event>>>) -> SentryStrHttpLayer {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(10, Duration::from_secs(1))
+            .with_additional_transport(RecordingTransport(Arc::clone(recorded)));
+        SentryStrHttpLayer::new(Arc::new(NostrSentryClient::new(config).await.unwrap()))
+    }
+
+    /// The events recorded once `count` arrived.
+    async fn wait_for(recorded: &Mutex<Vec<Event>>, count: usize) -> Vec<Event> {
+        for _ in 0..100 {
+            if recorded.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        recorded.lock().unwrap().clone()
+    }
+
+    /// A service answering every request with `status`.
+    fn answering(
+        status: StatusCode,
+    ) -> impl Service<Request<()>, Response = Response<()>, Error = Infallible, Future: Send>
+    + Clone
+    + Send
+    + 'static {
+        service_fn(move |_: Request<()>| async move {
+            Ok(Response::builder().status(status).body(()).unwrap())
+        })
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_captured() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let service = layer(&recorded)
+            .await
+            .layer(answering(StatusCode::BAD_GATEWAY));
+
+        let request = Request::get("/users/7")
+            .header("x-request-id", "req-1")
+            .body(())
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let events = wait_for(&recorded, 1).await;
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(
+            event.message.as_deref(),
+            Some("GET /users/7 returned 502 Bad Gateway")
+        );
+        assert_eq!(event.tags["http.status_code"], "502");
+        assert_eq!(event.tags["request_id"], "req-1");
+    }
+
+    #[tokio::test]
+    async fn panics_are_captured_and_raised_again() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let service = layer(&recorded)
+            .await
+            .layer(service_fn(|_: Request<()>| async {
+                if true {
+                    panic!("boom");
+                }
+                Ok::<_, Infallible>(Response::new(()))
+            }));
+
+        let request = Request::post("/orders").body(()).unwrap();
+        let handle = tokio::spawn(service.oneshot(request));
+        assert!(handle.await.unwrap_err().is_panic());
+
+        let events = wait_for(&recorded, 1).await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].message.as_deref(),
+            Some("POST /orders panicked: boom")
+        );
+        assert!(!events[0].tags.contains_key("http.status_code"));
+    }
+
+    #[tokio::test]
+    async fn successes_and_client_errors_are_not_captured() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let layer = layer(&recorded).await;
+
+        for status in [
+            StatusCode::OK,
+            StatusCode::NO_CONTENT,
+            StatusCode::NOT_FOUND,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ] {
+            let service = layer.layer(answering(status));
+            let response = service
+                .oneshot(Request::get("/ok").body(()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(recorded.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,32 @@
+//! # SentryStr Tower
+//!
+//! A `tower` middleware reporting failed HTTP requests to SentryStr, for axum and other
+//! tower-based servers.
+//!
+//! ## Quick Start
+//!
+//! ```rust,no_run
+//! use axum::{Router, routing::get};
+//! use sentrystr::{Config, NostrSentryClient};
+//! use sentrystr_tower::SentryStrHttpLayer;
+//! use std::sync::Arc;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let client = Arc::new(NostrSentryClient::new(Config::from_env()?).await?);
+//!
+//!     let app: Router = Router::new()
+//!         .route("/", get(|| async { "hello" }))
+//!         .layer(SentryStrHttpLayer::new(client));
+//!
+//!     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000").await?;
+//!     axum::serve(listener, app).await?;
+//!     Ok(())
+//! }
+//! ```
+
+mod context;
+pub mod layer;
+
+pub use layer::{SentryStrHttpLayer, SentryStrHttpService};
+pub use sentrystr::http::{DEFAULT_HEADER_ALLOWLIST, REQUEST_ID_HEADER};
@@ -8,7 +8,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "wss://nos.lol".to_string(),
     ];
 
-    SentryStrTracingBuilder::new()
+    let _guard = SentryStrTracingBuilder::new()
         .with_generated_keys_and_relays(relays)
         .with_min_level(tracing::Level::INFO)
         .init()
@@ -29,8 +29,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "Failed to connect to database"
     );
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-
     info!("Application shutting down");
 
     Ok(())
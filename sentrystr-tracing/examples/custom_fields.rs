@@ -94,11 +94,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         "wss://nos.lol".to_string(),
     ];
 
-    SentryStrTracingBuilder::new()
+    let _guard = SentryStrTracingBuilder::new()
         .with_generated_keys_and_relays(relays)
         .with_min_level(tracing::Level::INFO)
         .with_fields(true)
         .with_metadata(true)
+        .with_span_events(true)
         .init()
         .await?;
 
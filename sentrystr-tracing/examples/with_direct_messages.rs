@@ -17,7 +17,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .with_min_level(Level::Warning)
         .with_nip17(true);
 
-    SentryStrTracingBuilder::new()
+    let _guard = SentryStrTracingBuilder::new()
         .with_generated_keys_and_relays(relays)
         .with_direct_messaging(dm_config)
         .with_min_level(tracing::Level::INFO)
@@ -0,0 +1,333 @@
+//! The hand-off between [`crate::SentryStrLayer`] and the client: the layer pushes the
+//! events it builds without waiting, and a single background worker captures them in
+//! batches. Publishing is left to the client's queue, see `Config::with_queue`, which
+//! [`crate::SentryStrTracingBuilder`] turns on with these settings.
+
+use crate::route::{RoutedSender, recipients};
+use nostr::EventId;
+use sentrystr::{MessageEvent, NostrSentryClient};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+
+/// Events the layer sends in one batch unless set otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// How long an event waits for its batch to fill unless set otherwise.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Events the layer holds before dropping unless set otherwise.
+pub const DEFAULT_MAX_PENDING: usize = 10_000;
+
+/// The shortest flush interval; shorter ones, zero included, are raised to it.
+pub const MIN_FLUSH_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Which event the layer drops when its queue is full. Either way the drop is counted,
+/// see [`crate::SentryStrLayer::dropped_events`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BatchOverflow {
+    /// Keep the queued events and drop the new one.
+    #[default]
+    DropNewest,
+    /// Drop the oldest queued event to make room for the new one.
+    DropOldest,
+}
+
+/// Settings of the queue, kept by the layer until its first event starts the worker.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BatchSettings {
+    pub(crate) batch_size: usize,
+    pub(crate) flush_interval: Duration,
+    pub(crate) max_pending: usize,
+    pub(crate) overflow: BatchOverflow,
+}
+
+impl BatchSettings {
+    /// The settings the worker runs with: at least one event per batch and pending, no
+    /// batch larger than the events that may be pending, since it would never fill, and
+    /// at least [`MIN_FLUSH_INTERVAL`] between flushes.
+    pub(crate) fn normalized(self) -> Self {
+        let max_pending = self.max_pending.max(1);
+        Self {
+            batch_size: self.batch_size.clamp(1, max_pending),
+            flush_interval: self.flush_interval.max(MIN_FLUSH_INTERVAL),
+            max_pending,
+            overflow: self.overflow,
+        }
+    }
+
+    /// The client queue publishing the batches: as many events, flushed as often. With
+    /// [`BatchOverflow::DropNewest`] it waits for room, so the layer's own queue fills
+    /// and drops the newest events.
+    pub(crate) fn queue_config(self) -> sentrystr::QueueConfig {
+        let settings = self.normalized();
+        let mut queue = sentrystr::QueueConfig::new(settings.max_pending, settings.flush_interval);
+        queue.overflow = match settings.overflow {
+            BatchOverflow::DropNewest => sentrystr::OverflowPolicy::Block,
+            BatchOverflow::DropOldest => sentrystr::OverflowPolicy::DropOldest,
+        };
+        queue
+    }
+}
+
+impl Default for BatchSettings {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            max_pending: DEFAULT_MAX_PENDING,
+            overflow: BatchOverflow::default(),
+        }
+    }
+}
+
+pub(crate) struct Batcher {
+    client: Arc<RwLock<NostrSentryClient>>,
+    dm_senders: Vec<RoutedSender>,
+    settings: BatchSettings,
+    /// Events with the target they were logged under.
+    queue: Mutex<VecDeque<(sentrystr::Event, &'static str)>>,
+    /// Wakes the worker once a full batch is queued.
+    wake: Arc<Notify>,
+    dropped: Arc<AtomicU64>,
+    /// Held while a batch is taken and sent, so batches go out in order.
+    sending: tokio::sync::Mutex<()>,
+}
+
+impl Batcher {
+    /// Starts the worker on the current Tokio runtime. It ends once the batcher is
+    /// dropped.
+    pub(crate) fn spawn(
+        client: Arc<RwLock<NostrSentryClient>>,
+        dm_senders: Vec<RoutedSender>,
+        settings: BatchSettings,
+        dropped: Arc<AtomicU64>,
+    ) -> Arc<Self> {
+        let settings = settings.normalized();
+        let wake = Arc::new(Notify::new());
+        let batcher = Arc::new(Self {
+            client,
+            dm_senders,
+            settings,
+            queue: Mutex::new(VecDeque::new()),
+            wake: Arc::clone(&wake),
+            dropped,
+            sending: tokio::sync::Mutex::new(()),
+        });
+
+        tokio::spawn(run(Arc::downgrade(&batcher), wake, settings.flush_interval));
+        batcher
+    }
+
+    /// Queues `event`, logged under `target`, dropping an event instead when the queue
+    /// is full.
+    pub(crate) fn push(&self, event: sentrystr::Event, target: &'static str) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.settings.max_pending {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            match self.settings.overflow {
+                BatchOverflow::DropNewest => return,
+                BatchOverflow::DropOldest => {
+                    queue.pop_front();
+                }
+            }
+        }
+        queue.push_back((event, target));
+        if queue.len() == self.settings.batch_size {
+            self.wake.notify_one();
+        }
+    }
+
+    /// Sends every queued event.
+    pub(crate) async fn flush(&self) {
+        while self.send_batch(1).await {}
+    }
+
+    /// Sends up to a batch of queued events if at least `min` are queued. Returns
+    /// whether it sent any.
+    async fn send_batch(&self, min: usize) -> bool {
+        let _sending = self.sending.lock().await;
+        let batch: Vec<(sentrystr::Event, &'static str)> = {
+            let mut queue = self.queue.lock().unwrap();
+            if queue.is_empty() || queue.len() < min {
+                return false;
+            }
+            let len = queue.len().min(self.settings.batch_size);
+            queue.drain(..len).collect()
+        };
+
+        let client = self.client.read().await;
+
+        for (sentrystr_event, target) in batch {
+            // Events sampled out or filtered are still alerted about, without a link.
+            let nostr_event_id = match client.capture_event(sentrystr_event.clone()).await {
+                Ok(outcome) => outcome.event_id().unwrap_or(EventId::all_zeros()),
+                Err(e) => {
+                    eprintln!("Failed to send event to SentryStr: {}", e);
+                    continue;
+                }
+            };
+
+            // Alerts are routed and throttled by target, which is only added to the
+            // alert's copy of the event, not to the published one.
+            let mut alert = sentrystr_event;
+            alert.logger.get_or_insert_with(|| target.to_string());
+            let dm_senders = recipients(&self.dm_senders, &alert);
+            if dm_senders.is_empty() {
+                continue;
+            }
+            let message_event = Arc::new(MessageEvent {
+                event: alert,
+                author: client.public_key(),
+                nostr_event_id,
+                received_at: chrono::Utc::now(),
+            });
+
+            // Recipients are alerted concurrently, so a slow one does not hold up the rest.
+            let mut sends = tokio::task::JoinSet::new();
+            for routed in dm_senders {
+                let sender = Arc::clone(&routed.sender);
+                let message_event = Arc::clone(&message_event);
+                sends.spawn(async move {
+                    let dm_sender = sender.read().await;
+                    if let Err(e) = dm_sender.send_message_for_event(&message_event).await {
+                        eprintln!("Failed to send direct message: {}", e);
+                    }
+                });
+            }
+            while sends.join_next().await.is_some() {}
+        }
+        true
+    }
+}
+
+/// Sends full batches as they fill and whatever is queued every `flush_interval`.
+async fn run(batcher: Weak<Batcher>, wake: Arc<Notify>, flush_interval: Duration) {
+    let mut ticks = tokio::time::interval(flush_interval);
+    ticks.tick().await;
+    loop {
+        let min = tokio::select! {
+            _ = wake.notified() => None,
+            _ = ticks.tick() => Some(1),
+        };
+        let Some(batcher) = batcher.upgrade() else {
+            return;
+        };
+        let min = min.unwrap_or(batcher.settings.batch_size);
+        while batcher.send_batch(min).await {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+    use sentrystr::Config;
+
+    #[test]
+    fn settings_are_normalized() {
+        let settings = BatchSettings {
+            batch_size: 500,
+            flush_interval: Duration::ZERO,
+            max_pending: 10,
+            overflow: BatchOverflow::DropOldest,
+        }
+        .normalized();
+        assert_eq!(settings.batch_size, 10);
+        assert_eq!(settings.flush_interval, MIN_FLUSH_INTERVAL);
+
+        let queue = settings.queue_config();
+        assert_eq!(queue.max_pending, 10);
+        assert_eq!(queue.overflow, sentrystr::OverflowPolicy::DropOldest);
+    }
+
+    #[derive(Debug)]
+    struct RecordingTransport(Arc<Mutex<Vec<sentrystr::Event>>>);
+
+    impl sentrystr::Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(
+            &'a self,
+            event: &'a sentrystr::Event,
+        ) -> sentrystr::transport::TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn the_target_is_not_added_to_published_events() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(10, Duration::from_secs(1))
+            .with_additional_transport(RecordingTransport(Arc::clone(&recorded)));
+        let client = Arc::new(RwLock::new(NostrSentryClient::new(config).await.unwrap()));
+        let batcher = Batcher::spawn(
+            client,
+            Vec::new(),
+            BatchSettings::default(),
+            Arc::new(AtomicU64::new(0)),
+        );
+
+        batcher.push(
+            sentrystr::Event::new().with_message("payment failed"),
+            "billing",
+        );
+        batcher.flush().await;
+
+        for _ in 0..100 {
+            if !recorded.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].logger, None);
+    }
+
+    /// Thousands of events logged at once reach the client's queue without drops, even
+    /// with a zero flush interval.
+    #[tokio::test]
+    async fn load_is_handed_to_the_client_queue() {
+        const EVENTS: u64 = 2_000;
+        let settings = BatchSettings {
+            flush_interval: Duration::ZERO,
+            ..BatchSettings::default()
+        };
+        let mut config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap();
+        config.queue = Some(settings.queue_config());
+        let client = Arc::new(RwLock::new(NostrSentryClient::new(config).await.unwrap()));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let batcher = Batcher::spawn(
+            Arc::clone(&client),
+            Vec::new(),
+            settings,
+            Arc::clone(&dropped),
+        );
+
+        for i in 0..EVENTS {
+            batcher.push(
+                sentrystr::Event::new().with_message(format!("request {} failed", i)),
+                "app",
+            );
+        }
+        tokio::time::timeout(Duration::from_secs(60), batcher.flush())
+            .await
+            .unwrap();
+
+        assert_eq!(dropped.load(Ordering::Relaxed), 0);
+        assert!(batcher.queue.lock().unwrap().is_empty());
+        let stats = client.read().await.queue_stats().unwrap();
+        assert_eq!(
+            stats.pending as u64 + stats.published + stats.failed,
+            EVENTS
+        );
+    }
+}
@@ -1,7 +1,20 @@
-use crate::{Result, SentryStrLayer, TracingError};
+use crate::batch::{
+    BatchOverflow, BatchSettings, DEFAULT_BATCH_SIZE, DEFAULT_FLUSH_INTERVAL, DEFAULT_MAX_PENDING,
+};
+use crate::filter::TargetFilter;
+use crate::layer::LevelMapping;
+use crate::route::DmRoute;
+use crate::scrub::Scrubber;
+use crate::{Result, SentryStrGuard, SentryStrLayer, TracingError};
 use nostr::prelude::*;
 use nostr_sdk::prelude::*;
-use sentrystr::{Config, DirectMessageBuilder, NostrSentryClient};
+use regex::Regex;
+use sentrystr::{
+    BeforeSend, Config, DirectMessageBuilder, DmEncryption, MessageEvent, MessageFormatter,
+    NostrSentryClient,
+};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_subscriber::prelude::*;
 
 /// Builder for configuring SentryStr tracing integration.
@@ -13,7 +26,7 @@ use tracing_subscriber::prelude::*;
 ///
 /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 /// // Basic setup
-/// SentryStrTracingBuilder::new()
+/// let _guard = SentryStrTracingBuilder::new()
 ///     .with_generated_keys_and_relays(vec!["wss://relay.damus.io".to_string()])
 ///     .with_min_level(tracing::Level::INFO)
 ///     .init()
@@ -22,11 +35,32 @@ use tracing_subscriber::prelude::*;
 /// # }
 /// ```
 pub struct SentryStrTracingBuilder {
-    config: Option<Config>,
-    dm_config: Option<DirectMessageConfig>,
+    /// Validation errors from the key and relay helpers surface from `build`.
+    config: Option<sentrystr::Result<Config>>,
+    dm_configs: Vec<DirectMessageConfig>,
     min_level: Option<tracing::Level>,
+    target_filter: Option<TargetFilter>,
+    level_mapping: Option<LevelMapping>,
+    environment: Option<String>,
+    release: Option<String>,
+    tags: Vec<(String, String)>,
     include_fields: bool,
     include_metadata: bool,
+    record_breadcrumbs: bool,
+    include_span_fields: bool,
+    span_events: bool,
+    min_span_duration: Duration,
+    event_ttl: Option<Duration>,
+    level_ttls: Vec<(sentrystr::Level, Duration)>,
+    before_send: Option<BeforeSend>,
+    batch_size: Option<usize>,
+    flush_interval: Option<Duration>,
+    max_pending: Option<usize>,
+    overflow: BatchOverflow,
+    shutdown_timeout: Option<Duration>,
+    sample_rate: Option<f64>,
+    level_sample_rates: Vec<(tracing::Level, f64)>,
+    scrubber: Scrubber,
 }
 
 /// Configuration for direct message alerts in tracing.
@@ -52,8 +86,25 @@ pub struct SentryStrTracingBuilder {
 pub struct DirectMessageConfig {
     pub recipient_pubkey: PublicKey,
     pub min_level: Option<sentrystr::Level>,
-    pub use_nip17: bool,
+    pub encryption: DmEncryption,
     pub relays: Vec<String>,
+    /// Passed to `DirectMessageBuilder::with_template`.
+    pub template: Option<String>,
+    /// Passed to `DirectMessageBuilder::with_formatter`.
+    pub formatter: Option<MessageFormatter>,
+    /// Interval and item limit passed to `DirectMessageBuilder::with_digest`.
+    pub digest: Option<(Duration, usize)>,
+    /// Message limit and window passed to `DirectMessageBuilder::with_rate_limit`.
+    pub rate_limit: Option<(u32, Duration)>,
+    /// Passed to `DirectMessageBuilder::with_cooldown`.
+    pub cooldown: Option<Duration>,
+    /// Passed to `DirectMessageBuilder::with_njump_links`.
+    pub njump_links: bool,
+    /// The events this recipient is alerted about; empty for those no other route
+    /// matches.
+    route: DmRoute,
+    /// Keys alerts are sent from; the keys of the SentryStr config when `None`.
+    sender_keys: Option<Keys>,
 }
 
 impl SentryStrTracingBuilder {
@@ -61,45 +112,69 @@ impl SentryStrTracingBuilder {
     pub fn new() -> Self {
         Self {
             config: None,
-            dm_config: None,
+            dm_configs: Vec::new(),
             min_level: None,
+            target_filter: None,
+            level_mapping: None,
+            environment: None,
+            release: None,
+            tags: Vec::new(),
             include_fields: true,
             include_metadata: true,
+            record_breadcrumbs: false,
+            include_span_fields: true,
+            span_events: false,
+            min_span_duration: Duration::ZERO,
+            event_ttl: None,
+            level_ttls: Vec::new(),
+            before_send: None,
+            batch_size: None,
+            flush_interval: None,
+            max_pending: None,
+            overflow: BatchOverflow::default(),
+            shutdown_timeout: None,
+            sample_rate: None,
+            level_sample_rates: Vec::new(),
+            scrubber: Scrubber::new(),
         }
     }
 
     pub fn with_config(mut self, config: Config) -> Self {
-        self.config = Some(config);
+        self.config = Some(Ok(config));
         self
     }
 
+    /// Uses [`Config::from_env`], so the `SENTRYSTR_*` variables configure the client.
+    pub fn from_env() -> Result<Self> {
+        let config = Config::from_env().map_err(sentrystr::SentryStrError::from)?;
+        Ok(Self::new().with_config(config))
+    }
+
     pub fn with_secret_key_and_relays(mut self, secret_key: String, relays: Vec<String>) -> Self {
-        self.config = Some(Config::new(secret_key, relays));
+        self.config = Some(Config::try_new(secret_key, relays));
         self
     }
 
     pub fn with_generated_keys_and_relays(mut self, relays: Vec<String>) -> Self {
         let keys = Keys::generate();
-        self.config = Some(Config::new(
+        self.config = Some(Config::try_new(
             keys.secret_key().display_secret().to_string(),
             relays,
         ));
         self
     }
 
+    /// Adds a DM recipient. Call it once per recipient, routing each with
+    /// [`DirectMessageConfig::with_target_prefix`] or
+    /// [`DirectMessageConfig::with_field_equals`]; configs without a route get the
+    /// events no route matches.
     pub fn with_direct_messaging(mut self, dm_config: DirectMessageConfig) -> Self {
-        self.dm_config = Some(dm_config);
+        self.dm_configs.push(dm_config);
         self
     }
 
-    pub fn with_dm_recipient(mut self, recipient_pubkey: PublicKey, relays: Vec<String>) -> Self {
-        self.dm_config = Some(DirectMessageConfig {
-            recipient_pubkey,
-            min_level: None,
-            use_nip17: true,
-            relays,
-        });
-        self
+    pub fn with_dm_recipient(self, recipient_pubkey: PublicKey, relays: Vec<String>) -> Self {
+        self.with_direct_messaging(DirectMessageConfig::new(recipient_pubkey, relays))
     }
 
     pub fn with_min_level(mut self, level: tracing::Level) -> Self {
@@ -107,6 +182,44 @@ impl SentryStrTracingBuilder {
         self
     }
 
+    /// See [`SentryStrLayer::with_level_mapping`].
+    pub fn with_level_mapping(
+        mut self,
+        mapping: impl Fn(&tracing::Level) -> sentrystr::Level + Send + Sync + 'static,
+    ) -> Self {
+        self.level_mapping = Some(Arc::new(mapping));
+        self
+    }
+
+    /// See [`SentryStrLayer::with_service`].
+    pub fn with_service(self, service: &str) -> Self {
+        self.with_tag("service", service)
+    }
+
+    /// See [`SentryStrLayer::with_environment`].
+    pub fn with_environment(mut self, environment: &str) -> Self {
+        self.environment = Some(environment.to_string());
+        self
+    }
+
+    /// See [`SentryStrLayer::with_release`].
+    pub fn with_release(mut self, release: &str) -> Self {
+        self.release = Some(release.to_string());
+        self
+    }
+
+    /// See [`SentryStrLayer::with_tag`].
+    pub fn with_tag(mut self, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((tag.into(), value.into()));
+        self
+    }
+
+    /// See [`SentryStrLayer::with_target_filter`].
+    pub fn with_target_filter(mut self, filter: TargetFilter) -> Self {
+        self.target_filter = Some(filter);
+        self
+    }
+
     pub fn with_fields(mut self, include: bool) -> Self {
         self.include_fields = include;
         self
@@ -117,57 +230,281 @@ impl SentryStrTracingBuilder {
         self
     }
 
+    /// Keeps events below the minimum level as breadcrumbs for the next captured event.
+    pub fn with_breadcrumbs(mut self, record: bool) -> Self {
+        self.record_breadcrumbs = record;
+        self
+    }
+
+    /// See [`SentryStrLayer::with_span_fields`].
+    pub fn with_span_fields(mut self, include: bool) -> Self {
+        self.include_span_fields = include;
+        self
+    }
+
+    /// See [`SentryStrLayer::with_span_timing`]. The same as `with_span_events(true)`
+    /// and `with_min_span_duration(min_duration)`.
+    pub fn with_span_timing(mut self, min_duration: Duration) -> Self {
+        self.span_events = true;
+        self.min_span_duration = min_duration;
+        self
+    }
+
+    /// Sends an Info event for each span when it closes, with the span's name as the
+    /// transaction, its duration as `duration_ms` and its fields as extras. Off by
+    /// default. See [`SentryStrLayer::with_span_timing`].
+    pub fn with_span_events(mut self, enabled: bool) -> Self {
+        self.span_events = enabled;
+        self
+    }
+
+    /// Only spans lasting at least `min_duration` are sent by `with_span_events`.
+    pub fn with_min_span_duration(mut self, min_duration: Duration) -> Self {
+        self.min_span_duration = min_duration;
+        self
+    }
+
+    /// Forwarded to [`Config::with_event_ttl`].
+    pub fn with_event_ttl(mut self, ttl: Duration) -> Self {
+        self.event_ttl = Some(ttl);
+        self
+    }
+
+    /// Forwarded to [`Config::with_level_ttl`].
+    pub fn with_level_ttl(mut self, level: sentrystr::Level, ttl: Duration) -> Self {
+        self.level_ttls.push((level, ttl));
+        self
+    }
+
+    /// See [`SentryStrLayer::with_before_send`].
+    pub fn with_before_send(
+        mut self,
+        hook: impl Fn(sentrystr::Event) -> Option<sentrystr::Event> + Send + Sync + 'static,
+    ) -> Self {
+        self.before_send = Some(BeforeSend::new(hook));
+        self
+    }
+
+    /// See [`SentryStrLayer::with_batch_size`].
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// See [`SentryStrLayer::with_flush_interval`].
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = Some(flush_interval);
+        self
+    }
+
+    /// See [`SentryStrLayer::with_max_pending`].
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.max_pending = Some(max_pending);
+        self
+    }
+
+    /// See [`SentryStrLayer::with_overflow`].
+    pub fn with_overflow(mut self, overflow: BatchOverflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Masks the values of fields with these names, compared case-insensitively, see
+    /// [`SentryStrLayer::with_scrubber`].
+    pub fn with_scrub_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.scrubber = self.scrubber.with_fields(fields);
+        self
+    }
+
+    /// Masks the matches of `patterns` in messages and string values.
+    pub fn with_scrub_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.scrubber = self.scrubber.with_patterns(patterns);
+        self
+    }
+
+    /// Also masks the fields of [`crate::scrub::DEFAULT_SCRUB_FIELDS`], such as
+    /// `password`, `token` and `authorization`.
+    pub fn with_default_scrubbing(mut self, enabled: bool) -> Self {
+        if enabled {
+            self.scrubber = self.scrubber.with_defaults();
+        }
+        self
+    }
+
+    /// See [`SentryStrLayer::with_sample_rate`].
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate);
+        self
+    }
+
+    /// See [`SentryStrLayer::with_level_sample_rate`].
+    pub fn with_level_sample_rate(mut self, level: tracing::Level, rate: f64) -> Self {
+        self.level_sample_rates.push((level, rate));
+        self
+    }
+
+    /// See [`SentryStrLayer::with_shutdown_timeout`].
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = Some(timeout);
+        self
+    }
+
     pub async fn build(self) -> Result<SentryStrLayer> {
-        let config = self
+        let mut config = self
             .config
-            .ok_or_else(|| TracingError::Config("SentryStr config is required".to_string()))?;
+            .ok_or_else(|| TracingError::Config("SentryStr config is required".to_string()))??;
+
+        if let Some(ttl) = self.event_ttl {
+            config = config.with_event_ttl(ttl);
+        }
+        for (level, ttl) in self.level_ttls {
+            config = config.with_level_ttl(level, ttl);
+        }
+        // The layer's batches are published by the client's queue, unless the config
+        // brings its own.
+        if config.queue.is_none() {
+            let settings = BatchSettings {
+                batch_size: self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+                flush_interval: self.flush_interval.unwrap_or(DEFAULT_FLUSH_INTERVAL),
+                max_pending: self.max_pending.unwrap_or(DEFAULT_MAX_PENDING),
+                overflow: self.overflow,
+            };
+            config.queue = Some(settings.queue_config());
+        }
 
+        // DMs go through their own relays, so alerts link to the event's relays instead.
+        let event_relays = config.write_relays();
+        // Alerts come from the publishing identity, so recipients can trust the sender.
+        let event_keys = config.get_keys().map_err(sentrystr::SentryStrError::from)?;
         let client = NostrSentryClient::new(config).await?;
 
         let mut layer = SentryStrLayer::new(client)
             .with_fields(self.include_fields)
-            .with_metadata(self.include_metadata);
+            .with_metadata(self.include_metadata)
+            .with_breadcrumbs(self.record_breadcrumbs)
+            .with_span_fields(self.include_span_fields)
+            .with_overflow(self.overflow)
+            .with_scrubber(self.scrubber);
 
         if let Some(min_level) = self.min_level {
             layer = layer.with_min_level(min_level);
         }
+        if let Some(mapping) = self.level_mapping {
+            layer = layer.with_level_mapping(move |level| mapping(level));
+        }
+        if let Some(environment) = self.environment {
+            layer = layer.with_environment(environment);
+        }
+        if let Some(release) = self.release {
+            layer = layer.with_release(release);
+        }
+        for (tag, value) in self.tags {
+            layer = layer.with_tag(tag, value);
+        }
+        if let Some(target_filter) = self.target_filter {
+            layer = layer.with_target_filter(target_filter);
+        }
+        if let Some(rate) = self.sample_rate {
+            layer = layer.with_sample_rate(rate);
+        }
+        for (level, rate) in self.level_sample_rates {
+            layer = layer.with_level_sample_rate(level, rate);
+        }
+        if let Some(timeout) = self.shutdown_timeout {
+            layer = layer.with_shutdown_timeout(timeout);
+        }
+        if let Some(batch_size) = self.batch_size {
+            layer = layer.with_batch_size(batch_size);
+        }
+        if let Some(flush_interval) = self.flush_interval {
+            layer = layer.with_flush_interval(flush_interval);
+        }
+        if let Some(max_pending) = self.max_pending {
+            layer = layer.with_max_pending(max_pending);
+        }
+        if self.span_events {
+            layer = layer.with_span_timing(self.min_span_duration);
+        }
+        if let Some(before_send) = self.before_send {
+            layer = layer.with_shared_before_send(before_send);
+        }
 
-        if let Some(dm_config) = self.dm_config {
-            let dm_keys = Keys::generate();
+        for dm_config in self.dm_configs {
+            let dm_keys = dm_config
+                .sender_keys
+                .clone()
+                .unwrap_or_else(|| event_keys.clone());
+            // A client of its own, even with the event keys: the sender adds inbox and
+            // indexer relays to its pool while it sends, which must not receive events.
             let dm_client = Client::new(dm_keys.clone());
-
             for relay in &dm_config.relays {
                 dm_client.add_relay(relay).await?;
             }
             dm_client.connect().await;
 
-            let dm_sender = DirectMessageBuilder::new()
+            let mut dm_builder = DirectMessageBuilder::new()
                 .with_client(dm_client)
                 .with_keys(dm_keys)
                 .with_recipient(dm_config.recipient_pubkey)
                 .with_min_level(dm_config.min_level.unwrap_or(sentrystr::Level::Warning))
-                .with_nip17(dm_config.use_nip17)
-                .build()?;
+                .with_encryption(dm_config.encryption)
+                .with_relay_hints(event_relays.clone())
+                .with_njump_links(dm_config.njump_links);
+            if let Some(ref template) = dm_config.template {
+                dm_builder = dm_builder.with_template(template);
+            }
+            if let Some(formatter) = dm_config.formatter {
+                dm_builder = dm_builder.with_shared_formatter(formatter);
+            }
+            if let Some((interval, max_items)) = dm_config.digest {
+                dm_builder = dm_builder.with_digest(interval, max_items);
+            }
+            if let Some((max_messages, per)) = dm_config.rate_limit {
+                dm_builder = dm_builder.with_rate_limit(max_messages, per);
+            }
+            if let Some(cooldown) = dm_config.cooldown {
+                dm_builder = dm_builder.with_cooldown(cooldown);
+            }
+            let dm_sender = dm_builder.build()?;
 
-            layer = layer.with_direct_messaging(dm_sender);
+            layer = layer.with_routed_direct_messaging(dm_config.route, dm_sender);
         }
 
         Ok(layer)
     }
 
-    pub async fn init(self) -> Result<()> {
+    /// Installs the layer as the global subscriber, returning a guard to hold until the
+    /// end of `main`: dropping it waits for the events logged so far to be sent.
+    ///
+    /// Since 0.2.0 this returns the guard instead of `()`. Callers that matched on
+    /// `Result<()>` need updating, and `init().await?;` now drops the guard at once;
+    /// bind it with `let _guard = ...` instead.
+    pub async fn init(self) -> Result<SentryStrGuard> {
         let layer = self.build().await?;
+        let guard = layer.guard();
 
         tracing_subscriber::registry()
             .with(layer)
             .with(tracing_subscriber::fmt::layer())
             .init();
 
-        Ok(())
+        Ok(guard)
     }
 
-    pub async fn init_with_env_filter(self, env_filter: &str) -> Result<()> {
+    /// The same as [`Self::init`], from before it returned the guard.
+    #[deprecated(since = "0.2.0", note = "`init` returns the guard now")]
+    pub async fn init_with_guard(self) -> Result<SentryStrGuard> {
+        self.init().await
+    }
+
+    /// [`Self::init`], with only the events `env_filter` enables.
+    pub async fn init_with_env_filter(self, env_filter: &str) -> Result<SentryStrGuard> {
         let layer = self.build().await?;
+        let guard = layer.guard();
 
         tracing_subscriber::registry()
             .with(tracing_subscriber::EnvFilter::new(env_filter))
@@ -175,7 +512,7 @@ impl SentryStrTracingBuilder {
             .with(tracing_subscriber::fmt::layer())
             .init();
 
-        Ok(())
+        Ok(guard)
     }
 }
 
@@ -190,18 +527,144 @@ impl DirectMessageConfig {
         Self {
             recipient_pubkey,
             min_level: None,
-            use_nip17: true,
+            encryption: DmEncryption::Nip17GiftWrap,
             relays,
+            template: None,
+            formatter: None,
+            digest: None,
+            rate_limit: None,
+            cooldown: None,
+            njump_links: false,
+            route: DmRoute::new(),
+            sender_keys: None,
         }
     }
 
+    /// Sends alerts from `keys`, a dedicated alerting identity, instead of the keys
+    /// events are published with.
+    pub fn with_sender_keys(mut self, keys: Keys) -> Self {
+        self.sender_keys = Some(keys);
+        self
+    }
+
+    /// Alerts this recipient only about events whose target starts with `prefix`, see
+    /// [`DmRoute::with_target_prefix`].
+    pub fn with_target_prefix(mut self, prefix: &str) -> Self {
+        self.route = self.route.with_target_prefix(prefix);
+        self
+    }
+
+    /// Alerts this recipient only about events whose tag or field `name` is `value`,
+    /// see [`DmRoute::with_field_equals`].
+    pub fn with_field_equals(mut self, name: &str, value: &str) -> Self {
+        self.route = self.route.with_field_equals(name, value);
+        self
+    }
+
+    /// Links alerts to the event on `njump.me` instead of with a `nostr:` URI.
+    pub fn with_njump_links(mut self, njump_links: bool) -> Self {
+        self.njump_links = njump_links;
+        self
+    }
+
+    /// Sends one summary DM per `interval` instead of one DM per event.
+    pub fn with_digest(mut self, interval: Duration, max_items: usize) -> Self {
+        self.digest = Some((interval, max_items));
+        self
+    }
+
+    /// Sends at most `max_messages` DMs per `per_window`, then one summary of those
+    /// held back at the end of the window.
+    pub fn with_rate_limit(mut self, max_messages: u32, per_window: Duration) -> Self {
+        self.rate_limit = Some((max_messages, per_window));
+        self
+    }
+
+    /// Sends the same alert, by message, target and level, at most once per
+    /// `cooldown`, summarizing the repeats.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Words alerts with a template such as `"[{level}] {message}"`, see
+    /// [`sentrystr::MessageTemplate`].
+    pub fn with_template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Words alerts with `formatter`; takes precedence over a template.
+    pub fn with_formatter(
+        mut self,
+        formatter: impl Fn(&MessageEvent) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.formatter = Some(Arc::new(formatter));
+        self
+    }
+
     pub fn with_min_level(mut self, level: sentrystr::Level) -> Self {
         self.min_level = Some(level);
         self
     }
 
-    pub fn with_nip17(mut self, use_nip17: bool) -> Self {
-        self.use_nip17 = use_nip17;
+    /// How alerts are encrypted, NIP-17 by default. `DmEncryption::Nip04` is legacy,
+    /// for clients that cannot read anything newer.
+    pub fn with_encryption(mut self, encryption: DmEncryption) -> Self {
+        self.encryption = encryption;
         self
     }
+
+    /// Superseded by [`Self::with_encryption`]: `false` selects NIP-44.
+    pub fn with_nip17(self, use_nip17: bool) -> Self {
+        self.with_encryption(DmEncryption::from_nip17(use_nip17))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alerts are signed with the event keys, but through a client of their own, so the
+    /// relays the DM sender adds never receive events.
+    #[tokio::test]
+    async fn dm_alerts_share_keys_but_not_the_client() {
+        let keys = Keys::generate();
+        let relays = vec!["wss://relay.example.com".to_string()];
+        let config = Config::from_keys(&keys, relays.clone()).unwrap();
+        let layer = SentryStrTracingBuilder::new()
+            .with_config(config)
+            .with_dm_recipient(Keys::generate().public_key(), relays)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(layer.dm_sender_pubkeys().await, vec![keys.public_key()]);
+
+        let dm_keys = Keys::generate();
+        let dedicated = SentryStrTracingBuilder::new()
+            .with_config(Config::from_keys(&keys, ["wss://relay.example.com"]).unwrap())
+            .with_direct_messaging(
+                DirectMessageConfig::new(
+                    Keys::generate().public_key(),
+                    vec!["wss://dm.example.com".to_string()],
+                )
+                .with_sender_keys(dm_keys.clone()),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            dedicated.dm_sender_pubkeys().await,
+            vec![dm_keys.public_key()]
+        );
+        let event_relays = dedicated.client().read().await.relays().await;
+        assert_eq!(event_relays.len(), 1);
+        assert!(
+            event_relays
+                .iter()
+                .all(|relay| !relay.url.contains("dm.example.com"))
+        );
+    }
 }
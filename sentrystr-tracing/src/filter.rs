@@ -0,0 +1,81 @@
+/// Which targets a [`crate::SentryStrLayer`] sends, see
+/// [`crate::SentryStrLayer::with_target_filter`]. Targets are matched by prefix, like
+/// the target directives of `tracing_subscriber::EnvFilter`: `hyper` covers
+/// `hyper::client`.
+#[derive(Debug, Clone, Default)]
+pub struct TargetFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl TargetFilter {
+    /// A filter sending every target.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sends only targets starting with one of `prefixes`, unless excluded. Without any
+    /// included prefix, every target not excluded is sent.
+    pub fn with_include<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.include.extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Never sends targets starting with one of `prefixes`, even when included.
+    pub fn with_exclude<I, S>(mut self, prefixes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude.extend(prefixes.into_iter().map(Into::into));
+        self
+    }
+
+    /// Whether events of `target` are sent.
+    pub fn matches(&self, target: &str) -> bool {
+        if self
+            .exclude
+            .iter()
+            .any(|prefix| target.starts_with(prefix.as_str()))
+        {
+            return false;
+        }
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|prefix| target.starts_with(prefix.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusions_win_over_inclusions() {
+        let filter = TargetFilter::new()
+            .with_include(["my_app"])
+            .with_exclude(["my_app::health"]);
+
+        assert!(filter.matches("my_app"));
+        assert!(filter.matches("my_app::payments"));
+        assert!(!filter.matches("my_app::health::probe"));
+        assert!(!filter.matches("hyper::client"));
+    }
+
+    #[test]
+    fn without_inclusions_everything_not_excluded_is_sent() {
+        let filter = TargetFilter::new().with_exclude(["hyper", "h2", "tokio_util"]);
+
+        assert!(filter.matches("my_app"));
+        assert!(filter.matches("reqwest::connect"));
+        assert!(!filter.matches("hyper::client::pool"));
+        assert!(!filter.matches("h2::codec"));
+        assert!(TargetFilter::new().matches("anything"));
+    }
+}
@@ -1,31 +1,110 @@
+use crate::batch::{BatchOverflow, BatchSettings, Batcher};
+use crate::filter::TargetFilter;
+use crate::route::{DmRoute, RoutedSender};
+use crate::sample::LayerSampler;
+use crate::scrub::Scrubber;
 use crate::{FieldVisitor, convert_tracing_level, create_sentrystr_event, extract_event_metadata};
-use sentrystr::{DirectMessageSender, MessageEvent, NostrSentryClient};
-use std::sync::Arc;
+use sentrystr::{
+    BeforeSend, Breadcrumb, CloseReport, DirectMessageSender, NostrSentryClient, RelayStatusInfo,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
+use tracing::span::{Attributes, Id, Record};
 use tracing::{Event, Subscriber};
 use tracing_subscriber::{Layer, layer::Context};
 
+/// How long dropping a [`SentryStrGuard`] waits for events unless set otherwise.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Maps tracing levels to SentryStr levels, see [`SentryStrLayer::with_level_mapping`].
+pub type LevelMapping = Arc<dyn Fn(&tracing::Level) -> sentrystr::Level + Send + Sync>;
+
+/// Boolean event field that, when `true`, raises the event to `Level::Fatal`.
+pub const FATAL_FIELD: &str = "fatal";
+
+/// Event field naming the SentryStr level of the event, e.g. `"fatal"`.
+pub const LEVEL_FIELD: &str = "sentrystr.level";
+
+/// When a span was created, kept in its extensions for span timing.
+struct SpanStart(Instant);
+
+/// The fields recorded on a span, kept in its extensions for the events inside it.
+struct SpanFields(BTreeMap<String, serde_json::Value>);
+
 pub struct SentryStrLayer {
     client: Arc<RwLock<NostrSentryClient>>,
-    dm_sender: Option<Arc<RwLock<DirectMessageSender>>>,
+    dm_senders: Vec<RoutedSender>,
     min_level: Option<tracing::Level>,
+    target_filter: Option<TargetFilter>,
     include_fields: bool,
     include_metadata: bool,
+    record_breadcrumbs: bool,
+    include_span_fields: bool,
+    /// Spans lasting at least this long are captured, see [`Self::with_span_timing`].
+    span_timing: Option<Duration>,
+    before_send: Option<BeforeSend>,
+    batch_settings: BatchSettings,
+    /// Started by the first event, and shared by clones of the layer and its guards.
+    batcher: Arc<OnceLock<Arc<Batcher>>>,
+    dropped: Arc<AtomicU64>,
+    shutdown_timeout: Duration,
+    sampler: LayerSampler,
+    scrubber: Option<Arc<Scrubber>>,
+    level_mapping: Option<LevelMapping>,
+    environment: Option<String>,
+    release: Option<String>,
+    /// Set on every event as both an event tag and a Nostr tag.
+    tags: BTreeMap<String, String>,
 }
 
 impl SentryStrLayer {
     pub fn new(client: NostrSentryClient) -> Self {
         Self {
             client: Arc::new(RwLock::new(client)),
-            dm_sender: None,
+            dm_senders: Vec::new(),
             min_level: None,
+            target_filter: None,
             include_fields: true,
             include_metadata: true,
+            record_breadcrumbs: false,
+            include_span_fields: true,
+            span_timing: None,
+            before_send: None,
+            batch_settings: BatchSettings::default(),
+            batcher: Arc::new(OnceLock::new()),
+            dropped: Arc::new(AtomicU64::new(0)),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            sampler: LayerSampler::default(),
+            scrubber: None,
+            level_mapping: None,
+            environment: None,
+            release: None,
+            tags: BTreeMap::new(),
         }
     }
 
-    pub fn with_direct_messaging(mut self, dm_sender: DirectMessageSender) -> Self {
-        self.dm_sender = Some(Arc::new(RwLock::new(dm_sender)));
+    /// Alerts through `dm_sender` about the events no route of
+    /// [`Self::with_routed_direct_messaging`] matches, or about every event without
+    /// routes. Can be called again for more recipients.
+    pub fn with_direct_messaging(self, dm_sender: DirectMessageSender) -> Self {
+        self.with_routed_direct_messaging(DmRoute::new(), dm_sender)
+    }
+
+    /// Alerts through `dm_sender` about the events `route` matches, e.g. database errors
+    /// to the infrastructure on-call. An event matching several routes alerts each of
+    /// their recipients. Events are matched as built, after scrubbing and static tags.
+    pub fn with_routed_direct_messaging(
+        mut self,
+        route: DmRoute,
+        dm_sender: DirectMessageSender,
+    ) -> Self {
+        self.dm_senders.push(RoutedSender {
+            route,
+            sender: Arc::new(RwLock::new(dm_sender)),
+        });
         self
     }
 
@@ -34,6 +113,52 @@ impl SentryStrLayer {
         self
     }
 
+    /// Sends only the events and spans whose target `filter` matches. Unlike
+    /// `init_with_env_filter`, this filters only this layer: other layers such as the
+    /// console output still see every event. Composes with [`Self::with_min_level`]:
+    /// events must pass both. Events filtered out are not recorded as breadcrumbs either.
+    pub fn with_target_filter(mut self, filter: TargetFilter) -> Self {
+        self.target_filter = Some(filter);
+        self
+    }
+
+    /// Maps the level of each tracing event to the level of its SentryStr event, instead
+    /// of [`convert_tracing_level`], e.g. to send `ERROR` as `Level::Fatal`. The
+    /// [`FATAL_FIELD`] and [`LEVEL_FIELD`] fields of an event still override it.
+    pub fn with_level_mapping(
+        mut self,
+        mapping: impl Fn(&tracing::Level) -> sentrystr::Level + Send + Sync + 'static,
+    ) -> Self {
+        self.level_mapping = Some(Arc::new(mapping));
+        self
+    }
+
+    /// Sets `tag` to `value` on every event, in `Event::tags` and as a Nostr tag, so
+    /// relays and the collector's tag filters can select them. Tags the event already
+    /// has win.
+    pub fn with_tag(mut self, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(tag.into(), value.into());
+        self
+    }
+
+    /// [`Self::with_tag`] for the `service` tag, which `--service` filters on.
+    pub fn with_service(self, service: impl Into<String>) -> Self {
+        self.with_tag("service", service)
+    }
+
+    /// Sets `Event::environment` on events without one, and tags them with `env`, which
+    /// `--env` filters on.
+    pub fn with_environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    /// Sets `Event::release` on events without one.
+    pub fn with_release(mut self, release: impl Into<String>) -> Self {
+        self.release = Some(release.into());
+        self
+    }
+
     pub fn with_fields(mut self, include: bool) -> Self {
         self.include_fields = include;
         self
@@ -44,6 +169,163 @@ impl SentryStrLayer {
         self
     }
 
+    /// Records events below the minimum level as breadcrumbs on the client instead of
+    /// dropping them, so captured events show what led up to them.
+    pub fn with_breadcrumbs(mut self, record: bool) -> Self {
+        self.record_breadcrumbs = record;
+        self
+    }
+
+    /// Adds the fields of the spans an event is in to its extras, as
+    /// `span.<span name>.<field>`. Inner spans win over outer spans with the same name,
+    /// and the event's own fields over span fields. On by default.
+    pub fn with_span_fields(mut self, include: bool) -> Self {
+        self.include_span_fields = include;
+        self
+    }
+
+    /// Captures each span lasting at least `min_duration`, from creation to close, as a
+    /// `sentrystr::Event::transaction_event` named after the span, with its target as
+    /// the logger, its fields and `duration_ms` as extras. Time spent outside the span
+    /// across await points counts, and nested spans are reported each on their own.
+    /// Collectors can select them with `EventFilter::with_min_duration_ms`.
+    pub fn with_span_timing(mut self, min_duration: Duration) -> Self {
+        self.span_timing = Some(min_duration);
+        self
+    }
+
+    /// Runs `hook` on each event the layer builds, before handing it to the client.
+    /// Returning `None` drops the event. The client's own `Config::with_before_send`
+    /// hook still runs afterwards, so the layer hook sees the event without client
+    /// defaults such as release or server name, and the client hook sees the result of
+    /// the layer hook.
+    pub fn with_before_send(
+        self,
+        hook: impl Fn(sentrystr::Event) -> Option<sentrystr::Event> + Send + Sync + 'static,
+    ) -> Self {
+        self.with_shared_before_send(BeforeSend::new(hook))
+    }
+
+    /// [`Self::with_before_send`] with a hook that is already shared.
+    pub fn with_shared_before_send(mut self, hook: BeforeSend) -> Self {
+        self.before_send = Some(hook);
+        self
+    }
+
+    /// Hands events to the client in batches of up to `batch_size`, holding it once per
+    /// batch, and at most `max_pending`. Defaults to [`crate::batch::DEFAULT_BATCH_SIZE`].
+    /// With a client queue (`Config::with_queue`, set up by
+    /// [`crate::SentryStrTracingBuilder`]) capturing only queues each event and the queue
+    /// publishes them; without one, the events of a batch are published in turn.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_settings.batch_size = batch_size;
+        self
+    }
+
+    /// Sends a batch that is not full after `flush_interval`, at least
+    /// [`crate::batch::MIN_FLUSH_INTERVAL`]. Defaults to
+    /// [`crate::batch::DEFAULT_FLUSH_INTERVAL`].
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.batch_settings.flush_interval = flush_interval;
+        self
+    }
+
+    /// Holds at most `max_pending` events waiting for a batch before dropping some, see
+    /// [`Self::with_overflow`]. Defaults to [`crate::batch::DEFAULT_MAX_PENDING`].
+    pub fn with_max_pending(mut self, max_pending: usize) -> Self {
+        self.batch_settings.max_pending = max_pending;
+        self
+    }
+
+    /// Which event to drop when `max_pending` events are waiting.
+    pub fn with_overflow(mut self, overflow: BatchOverflow) -> Self {
+        self.batch_settings.overflow = overflow;
+        self
+    }
+
+    /// Masks sensitive values with `scrubber` in each event and breadcrumb, see
+    /// [`Scrubber::scrub_event`]. Events are scrubbed last, after scopes, static tags and
+    /// `before_send`, just before the client sees them. DM alerts are built from the
+    /// scrubbed event, so they are masked too.
+    pub fn with_scrubber(mut self, scrubber: Scrubber) -> Self {
+        self.scrubber = (!scrubber.is_empty()).then(|| Arc::new(scrubber));
+        self
+    }
+
+    /// Keeps a share `rate`, from 0 to 1, of the events at or above the minimum level.
+    /// The decision is made before the event's fields are read, so sampled-out events
+    /// cost next to nothing. Events kept are chosen by their order, evenly and the
+    /// same way on every run; 0 keeps none and 1 all. The client's own
+    /// `Config::with_sample_rate` still applies to the events kept.
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sampler.set_rate(rate);
+        self
+    }
+
+    /// [`Self::with_sample_rate`] for the events at `level`, e.g. 1.0 for `ERROR` and
+    /// 0.05 for `INFO`. Levels without a rate of their own use the overall one.
+    pub fn with_level_sample_rate(mut self, level: tracing::Level, rate: f64) -> Self {
+        self.sampler.set_level_rate(level, rate);
+        self
+    }
+
+    /// Events discarded by [`Self::with_sample_rate`].
+    pub fn sampled_out_events(&self) -> u64 {
+        self.sampler.sampled_out.load(Ordering::Relaxed)
+    }
+
+    /// Events dropped because the queue was full, see [`Self::with_max_pending`].
+    pub fn dropped_events(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// How long dropping the layer's [`SentryStrGuard`] waits for pending events.
+    /// Defaults to [`DEFAULT_SHUTDOWN_TIMEOUT`].
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// A guard for flushing and closing the layer's client once the layer is installed.
+    /// Call it inside a Tokio runtime, which the guard flushes on when it is dropped.
+    pub fn guard(&self) -> SentryStrGuard {
+        SentryStrGuard {
+            inner: Arc::new(GuardInner {
+                client: Arc::clone(&self.client),
+                batcher: Arc::clone(&self.batcher),
+                dropped: Arc::clone(&self.dropped),
+                sampled_out: Arc::clone(&self.sampler.sampled_out),
+                runtime: tokio::runtime::Handle::try_current().ok(),
+                shutdown_timeout: self.shutdown_timeout,
+            }),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn client(&self) -> Arc<RwLock<NostrSentryClient>> {
+        Arc::clone(&self.client)
+    }
+
+    #[cfg(test)]
+    pub(crate) async fn dm_sender_pubkeys(&self) -> Vec<nostr::PublicKey> {
+        let mut pubkeys = Vec::new();
+        for routed in &self.dm_senders {
+            pubkeys.push(routed.sender.read().await.sender_pubkey());
+        }
+        pubkeys
+    }
+
+    fn sends_target(&self, target: &str) -> bool {
+        self.target_filter
+            .as_ref()
+            .is_none_or(|filter| filter.matches(target))
+    }
+
+    /// Whether spans keep their fields, for the events inside them or their own.
+    fn keeps_span_fields(&self) -> bool {
+        self.include_span_fields || self.span_timing.is_some()
+    }
+
     fn should_process_event(&self, event_level: &tracing::Level) -> bool {
         if let Some(min_level) = &self.min_level {
             event_level <= min_level
@@ -53,18 +335,198 @@ impl SentryStrLayer {
     }
 }
 
+/// Keeps the events of a [`SentryStrLayer`] from being lost when the process exits.
+/// Hold it until the end of `main`: dropping the last clone waits up to the layer's
+/// shutdown timeout for the events logged so far to be sent, like
+/// [`SentryStrGuard::flush`]. Dropping it does not close the client, so events logged
+/// afterwards are still sent.
+#[derive(Clone)]
+#[must_use = "dropping the guard right away flushes too early; hold it until the end of main"]
+pub struct SentryStrGuard {
+    inner: Arc<GuardInner>,
+}
+
+struct GuardInner {
+    client: Arc<RwLock<NostrSentryClient>>,
+    batcher: Arc<OnceLock<Arc<Batcher>>>,
+    dropped: Arc<AtomicU64>,
+    sampled_out: Arc<AtomicU64>,
+    /// The runtime the guard was created on, for flushing from threads without one.
+    runtime: Option<tokio::runtime::Handle>,
+    shutdown_timeout: Duration,
+}
+
+/// Whether the calling thread is inside a current-thread runtime, which a blocking flush
+/// would stall: the runtime's tasks only run while this thread drives them.
+fn on_current_thread_runtime() -> bool {
+    tokio::runtime::Handle::try_current().is_ok_and(|runtime| {
+        runtime.runtime_flavor() == tokio::runtime::RuntimeFlavor::CurrentThread
+    })
+}
+
+/// Sends the events waiting in `batcher` and flushes `client`, waiting for at most
+/// `timeout`. Returns whether nothing is left pending.
+async fn flush_pending(
+    client: Arc<RwLock<NostrSentryClient>>,
+    batcher: Option<Arc<Batcher>>,
+    timeout: Duration,
+) -> bool {
+    tokio::time::timeout(timeout, async {
+        if let Some(batcher) = batcher {
+            batcher.flush().await;
+        }
+        client.read().await.flush(timeout).await == 0
+    })
+    .await
+    .unwrap_or(false)
+}
+
+impl GuardInner {
+    async fn flush_async(&self, timeout: Duration) -> bool {
+        flush_pending(
+            Arc::clone(&self.client),
+            self.batcher.get().cloned(),
+            timeout,
+        )
+        .await
+    }
+
+    /// [`Self::flush_async`], blocking for at most `timeout`. Gives up at once inside a
+    /// current-thread runtime, which blocking would stall.
+    fn flush(&self, timeout: Duration) -> bool {
+        if on_current_thread_runtime() {
+            return false;
+        }
+        let current = tokio::runtime::Handle::try_current().ok();
+        let in_runtime = current.is_some();
+        let Some(runtime) = current.or_else(|| self.runtime.clone()) else {
+            return false;
+        };
+
+        let client = Arc::clone(&self.client);
+        let batcher = self.batcher.get().cloned();
+        let (tx, rx) = std::sync::mpsc::channel();
+        // `Handle::block_on` panics on a runtime thread, and the guard may be dropped on
+        // one. A runtime that has shut down panics the helper thread, not this one.
+        std::thread::spawn(move || {
+            let _ = tx.send(runtime.block_on(flush_pending(client, batcher, timeout)));
+        });
+        let wait = || rx.recv_timeout(timeout).unwrap_or(false);
+        if in_runtime {
+            // Lets the runtime move this worker's other tasks elsewhere while it waits.
+            tokio::task::block_in_place(wait)
+        } else {
+            wait()
+        }
+    }
+}
+
+impl Drop for GuardInner {
+    fn drop(&mut self) {
+        if on_current_thread_runtime() {
+            eprintln!(
+                "SentryStr events may be lost: the guard was dropped inside a current-thread \
+                 runtime, which it cannot block on; call SentryStrGuard::close first"
+            );
+            return;
+        }
+        if !self.flush(self.shutdown_timeout) {
+            eprintln!(
+                "SentryStr events may be lost: not all were sent within {:?}",
+                self.shutdown_timeout
+            );
+        }
+    }
+}
+
+impl SentryStrGuard {
+    /// Sends the events logged so far, blocking for at most `timeout`. Returns whether
+    /// all of them were sent in time. Works on any thread, with or without a Tokio
+    /// runtime, except inside a current-thread runtime, which blocking would stall:
+    /// there it returns `false` at once, and [`Self::flush_async`] is the way to flush.
+    pub fn flush(&self, timeout: Duration) -> bool {
+        self.inner.flush(timeout)
+    }
+
+    /// Sends the events logged so far, waiting for at most `timeout` without blocking
+    /// the runtime. Returns whether all of them were sent in time.
+    pub async fn flush_async(&self, timeout: Duration) -> bool {
+        self.inner.flush_async(timeout).await
+    }
+
+    /// Sends the events waiting for a batch, then closes the client, see
+    /// [`NostrSentryClient::close`]. Events logged afterwards are not sent.
+    pub async fn close(&self, timeout: Duration) -> CloseReport {
+        if let Some(batcher) = self.inner.batcher.get() {
+            batcher.flush().await;
+        }
+        self.inner.client.read().await.close(timeout).await
+    }
+
+    /// See [`SentryStrLayer::dropped_events`].
+    pub fn dropped_events(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// See [`SentryStrLayer::sampled_out_events`].
+    pub fn sampled_out_events(&self) -> u64 {
+        self.inner.sampled_out.load(Ordering::Relaxed)
+    }
+
+    /// See [`NostrSentryClient::relay_status`], e.g. for a health check.
+    pub async fn relay_status(&self) -> Vec<RelayStatusInfo> {
+        self.inner.client.read().await.relay_status().await
+    }
+
+    /// See [`NostrSentryClient::wait_for_connection`].
+    pub async fn wait_for_connection(
+        &self,
+        min_relays: usize,
+        timeout: Duration,
+    ) -> sentrystr::Result<usize> {
+        self.inner
+            .client
+            .read()
+            .await
+            .wait_for_connection(min_relays, timeout)
+            .await
+    }
+}
+
 impl<S> Layer<S> for SentryStrLayer
 where
     S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
 {
-    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // When it was logged, not when its batch is sent.
+        let timestamp = chrono::Utc::now();
+        if !self.sends_target(event.metadata().target()) {
+            return;
+        }
+        let process = self.should_process_event(event.metadata().level());
+        if process && !self.sampler.keep(event.metadata().level()) {
+            return;
+        }
+        if !process && !self.record_breadcrumbs {
+            return;
+        }
+
         let mut visitor = FieldVisitor::new();
         event.record(&mut visitor);
+        if self.include_span_fields {
+            self.add_span_fields(&ctx, event, &mut visitor.fields);
+        }
 
-        let message = visitor.extract_message();
-        let level = convert_tracing_level(event.metadata().level());
+        let level = self.level_of(event.metadata().level(), &mut visitor.fields);
+        let mut message = visitor.extract_message();
 
-        if !self.should_process_event(event.metadata().level()) {
+        if !process {
+            // Breadcrumbs are kept until a later event, so mask them now.
+            if let Some(ref scrubber) = self.scrubber {
+                message = scrubber.scrub_text(&message);
+                scrubber.scrub_fields(&mut visitor.fields);
+            }
+            self.add_breadcrumb(message, level, event.metadata().target(), visitor.fields);
             return;
         }
 
@@ -80,32 +542,250 @@ where
             std::collections::BTreeMap::new()
         };
 
-        let sentrystr_event = create_sentrystr_event(message, level, fields, metadata_fields);
+        let mut sentrystr_event = create_sentrystr_event(message, level, fields, metadata_fields)
+            .with_timestamp(timestamp);
 
-        let client = Arc::clone(&self.client);
-        let dm_sender = self.dm_sender.as_ref().map(Arc::clone);
+        // The batch is sent outside the caller's task-local scope.
+        sentrystr::Scope::current().apply_to(&mut sentrystr_event);
+
+        // Snapshot now: the event is sent later, and later breadcrumbs must not leak in.
+        if self.record_breadcrumbs
+            && let Ok(client) = self.client.try_read()
+        {
+            sentrystr_event.breadcrumbs = client.breadcrumbs();
+        }
+
+        self.dispatch(sentrystr_event, event.metadata().target());
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if self.span_timing.is_some() {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+        if self.keeps_span_fields() {
+            let mut visitor = FieldVisitor::new();
+            attrs.record(&mut visitor);
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
 
-        tokio::spawn(async move {
-            let client = client.read().await;
-            if let Err(e) = client.capture_event(sentrystr_event.clone()).await {
-                eprintln!("Failed to send event to SentryStr: {}", e);
-                return;
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if !self.keeps_span_fields() {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = FieldVisitor::new();
+        values.record(&mut visitor);
+
+        let mut extensions = span.extensions_mut();
+        match extensions.get_mut::<SpanFields>() {
+            Some(fields) => fields.0.extend(visitor.fields),
+            None => extensions.insert(SpanFields(visitor.fields)),
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(min_duration) = self.span_timing else {
+            return;
+        };
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span
+            .extensions()
+            .get::<SpanStart>()
+            .map(|start| start.0.elapsed())
+        else {
+            return;
+        };
+        if elapsed < min_duration || !self.sends_target(span.metadata().target()) {
+            return;
+        }
+
+        let metadata = span.metadata();
+        let mut sentrystr_event = sentrystr::Event::transaction_event(metadata.name(), elapsed);
+        sentrystr_event.logger = Some(metadata.target().to_string());
+        if self.include_fields
+            && let Some(SpanFields(fields)) = span.extensions().get::<SpanFields>()
+        {
+            sentrystr_event.extra.extend(fields.clone());
+        }
+        sentrystr_event.extra.insert(
+            "duration_ms".to_string(),
+            sentrystr_event.duration_ms.into(),
+        );
+        sentrystr::Scope::current().apply_to(&mut sentrystr_event);
+        self.dispatch(sentrystr_event, metadata.target());
+    }
+}
+
+impl SentryStrLayer {
+    /// The SentryStr level of an event at `level` with `fields`: the one its
+    /// [`LEVEL_FIELD`] names, else `Fatal` if its [`FATAL_FIELD`] is `true`, else the
+    /// mapped level. Takes both control fields out of `fields`.
+    fn level_of(
+        &self,
+        level: &tracing::Level,
+        fields: &mut BTreeMap<String, serde_json::Value>,
+    ) -> sentrystr::Level {
+        let named = match fields.get(LEVEL_FIELD) {
+            Some(serde_json::Value::String(name)) => match name.parse() {
+                Ok(level) => {
+                    fields.remove(LEVEL_FIELD);
+                    Some(level)
+                }
+                Err(_) => None,
+            },
+            _ => None,
+        };
+        let fatal = match fields.get(FATAL_FIELD) {
+            Some(serde_json::Value::Bool(fatal)) => {
+                let fatal = *fatal;
+                fields.remove(FATAL_FIELD);
+                fatal
             }
+            _ => false,
+        };
+
+        named
+            .or(fatal.then_some(sentrystr::Level::Fatal))
+            .unwrap_or_else(|| match self.level_mapping {
+                Some(ref mapping) => mapping(level),
+                None => convert_tracing_level(level),
+            })
+    }
+
+    /// Fills in the environment, release and tags of [`SentryStrLayer::with_tag`] and
+    /// friends, keeping those the event already has.
+    fn apply_static_tags(&self, sentrystr_event: &mut sentrystr::Event) {
+        if sentrystr_event.environment.is_none() {
+            sentrystr_event.environment = self.environment.clone();
+        }
+        if sentrystr_event.release.is_none() {
+            sentrystr_event.release = self.release.clone();
+        }
 
-            if let Some(dm_sender) = dm_sender {
-                let dm_sender = dm_sender.read().await;
-                let message_event = MessageEvent {
-                    event: sentrystr_event,
-                    author: nostr::Keys::generate().public_key(),
-                    nostr_event_id: nostr::EventId::all_zeros(),
-                    received_at: chrono::Utc::now(),
-                };
-
-                if let Err(e) = dm_sender.send_message_for_event(&message_event).await {
-                    eprintln!("Failed to send direct message: {}", e);
+        let mut nostr_tags = self.tags.clone();
+        if self.environment.is_some()
+            && let Some(ref environment) = sentrystr_event.environment
+        {
+            nostr_tags.insert("env".to_string(), environment.clone());
+        }
+        for (tag, value) in &self.tags {
+            sentrystr_event
+                .tags
+                .entry(tag.clone())
+                .or_insert_with(|| value.clone());
+        }
+        for (tag, value) in nostr_tags {
+            let value = sentrystr_event.tags.get(&tag).cloned().unwrap_or(value);
+            let tagged = sentrystr_event
+                .nostr_tags
+                .iter()
+                .any(|existing| existing.as_slice().first() == Some(&tag));
+            if !tagged {
+                sentrystr_event
+                    .nostr_tags
+                    .push(nostr::Tag::custom(nostr::TagKind::custom(tag), [value]));
+            }
+        }
+    }
+
+    /// Runs the layer's `before_send` hook on `sentrystr_event`, scrubs it, then queues it
+    /// for the batch worker, which captures it and sends the DM alert.
+    /// Hands the event, logged under `target`, to the batcher.
+    fn dispatch(&self, mut sentrystr_event: sentrystr::Event, target: &'static str) {
+        self.apply_static_tags(&mut sentrystr_event);
+
+        if let Some(ref before_send) = self.before_send {
+            match before_send.apply(sentrystr_event) {
+                Ok(Some(kept)) => sentrystr_event = kept,
+                Ok(None) => return,
+                Err(panic) => {
+                    eprintln!("Layer before_send hook panicked: {}", panic);
+                    return;
                 }
             }
-        });
+        }
+        // Last, so scope values, static tags and the hook's changes are masked too.
+        if let Some(ref scrubber) = self.scrubber {
+            scrubber.scrub_event(&mut sentrystr_event);
+        }
+
+        let batcher = match self.batcher.get() {
+            Some(batcher) => batcher,
+            None => {
+                if tokio::runtime::Handle::try_current().is_err() {
+                    eprintln!("SentryStrLayer needs a Tokio runtime; event not sent");
+                    return;
+                }
+                self.batcher.get_or_init(|| {
+                    Batcher::spawn(
+                        Arc::clone(&self.client),
+                        self.dm_senders.clone(),
+                        self.batch_settings,
+                        Arc::clone(&self.dropped),
+                    )
+                })
+            }
+        };
+        batcher.push(sentrystr_event, target);
+    }
+
+    /// Adds the fields of the spans `event` is in to `fields`, outermost first so inner
+    /// spans overwrite outer ones, without replacing the event's own fields.
+    fn add_span_fields<S>(
+        &self,
+        ctx: &Context<'_, S>,
+        event: &Event<'_>,
+        fields: &mut BTreeMap<String, serde_json::Value>,
+    ) where
+        S: Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        let Some(scope) = ctx.event_scope(event) else {
+            return;
+        };
+
+        let mut span_fields = BTreeMap::new();
+        for span in scope.from_root() {
+            let extensions = span.extensions();
+            let Some(SpanFields(recorded)) = extensions.get::<SpanFields>() else {
+                continue;
+            };
+            for (name, value) in recorded {
+                span_fields.insert(format!("span.{}.{}", span.name(), name), value.clone());
+            }
+        }
+
+        for (name, value) in span_fields {
+            fields.entry(name).or_insert(value);
+        }
+    }
+
+    fn add_breadcrumb(
+        &self,
+        message: String,
+        level: sentrystr::Level,
+        target: &str,
+        fields: std::collections::BTreeMap<String, serde_json::Value>,
+    ) {
+        // The client is only ever read-locked, so this does not miss breadcrumbs.
+        let Ok(client) = self.client.try_read() else {
+            return;
+        };
+
+        let mut breadcrumb = Breadcrumb::new(message)
+            .with_level(level)
+            .with_category(target);
+        if self.include_fields {
+            breadcrumb.data.extend(fields);
+        }
+        client.add_breadcrumb(breadcrumb);
     }
 }
 
@@ -113,10 +793,461 @@ impl Clone for SentryStrLayer {
     fn clone(&self) -> Self {
         Self {
             client: Arc::clone(&self.client),
-            dm_sender: self.dm_sender.as_ref().map(Arc::clone),
+            dm_senders: self.dm_senders.clone(),
             min_level: self.min_level,
+            target_filter: self.target_filter.clone(),
             include_fields: self.include_fields,
             include_metadata: self.include_metadata,
+            record_breadcrumbs: self.record_breadcrumbs,
+            include_span_fields: self.include_span_fields,
+            span_timing: self.span_timing,
+            before_send: self.before_send.clone(),
+            batch_settings: self.batch_settings,
+            batcher: Arc::clone(&self.batcher),
+            dropped: Arc::clone(&self.dropped),
+            shutdown_timeout: self.shutdown_timeout,
+            sampler: self.sampler.clone(),
+            scrubber: self.scrubber.clone(),
+            level_mapping: self.level_mapping.clone(),
+            environment: self.environment.clone(),
+            release: self.release.clone(),
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TargetFilter;
+    use nostr::Keys;
+    use sentrystr::Config;
+    use std::collections::BTreeSet;
+    use std::sync::Mutex;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    async fn layer() -> SentryStrLayer {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap();
+        SentryStrLayer::new(NostrSentryClient::new(config).await.unwrap())
+    }
+
+    async fn guard() -> SentryStrGuard {
+        layer().await.guard()
+    }
+
+    type Recorded = Arc<Mutex<Vec<sentrystr::Event>>>;
+
+    /// A layer keeping the events it would send in its `before_send` hook instead.
+    async fn recording_layer(
+        configure: impl FnOnce(SentryStrLayer) -> SentryStrLayer,
+    ) -> (SentryStrLayer, Recorded) {
+        let recorded = Recorded::default();
+        let recording = Arc::clone(&recorded);
+        let layer = configure(layer().await).with_before_send(move |event| {
+            recording.lock().unwrap().push(event);
+            None
+        });
+        (layer, recorded)
+    }
+
+    /// Runs `log` with `layer` as the only layer of the subscriber.
+    fn with_layer(layer: SentryStrLayer, log: impl FnOnce()) {
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), log);
+    }
+
+    fn messages(recorded: &Recorded) -> Vec<String> {
+        recorded
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| event.message.clone())
+            .collect()
+    }
+
+    /// A field value counting how often it is formatted.
+    struct Formatted(Arc<AtomicU64>);
+
+    impl std::fmt::Debug for Formatted {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            f.write_str("formatted")
+        }
+    }
+
+    #[tokio::test]
+    async fn sampling_is_decided_per_level_before_fields_are_read() {
+        let formatted = Arc::new(AtomicU64::new(0));
+        let (layer, recorded) = recording_layer(|layer| {
+            layer
+                .with_sample_rate(0.0)
+                .with_level_sample_rate(tracing::Level::ERROR, 1.0)
+        })
+        .await;
+        let counters = layer.clone();
+
+        with_layer(layer, || {
+            for _ in 0..10 {
+                tracing::info!(payload = ?Formatted(Arc::clone(&formatted)), "heartbeat");
+            }
+            tracing::error!("payment failed");
+        });
+
+        assert_eq!(messages(&recorded), vec!["payment failed"]);
+        assert_eq!(counters.sampled_out_events(), 10);
+        assert_eq!(counters.guard().sampled_out_events(), 10);
+        assert_eq!(formatted.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn sampling_keeps_the_same_events_on_every_run() {
+        async fn kept() -> Vec<serde_json::Value> {
+            let (layer, recorded) = recording_layer(|layer| layer.with_sample_rate(0.25)).await;
+            with_layer(layer, || {
+                for tick in 0..400 {
+                    tracing::info!(tick, "tick");
+                }
+            });
+            let recorded = recorded.lock().unwrap();
+            recorded
+                .iter()
+                .map(|event| event.extra["tick"].clone())
+                .collect()
+        }
+
+        let first = kept().await;
+        assert_eq!(first, kept().await);
+        assert!((70..=130).contains(&first.len()), "{} kept", first.len());
+    }
+
+    /// An even spread over `[0, 1)`, the same on every run.
+    fn seeded_sampler() -> f64 {
+        static DRAWS: AtomicU64 = AtomicU64::new(0);
+        (DRAWS.fetch_add(1, Ordering::Relaxed) as f64 * 0.618_033_988_749_895).fract()
+    }
+
+    /// The layer samples before building events and the client samples what the layer
+    /// hands it, so the two rates multiply. A layer rate of 1 for a level does not
+    /// exempt its events from the client's rate.
+    #[tokio::test]
+    async fn layer_and_client_sampling_compose() {
+        let captured = Recorded::default();
+        let capturing = Arc::clone(&captured);
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_sample_rate(0.5)
+            .with_sampler(seeded_sampler)
+            .with_before_send(move |event| {
+                capturing.lock().unwrap().push(event);
+                None
+            });
+        let layer = SentryStrLayer::new(NostrSentryClient::new(config).await.unwrap())
+            .with_sample_rate(0.5)
+            .with_level_sample_rate(tracing::Level::ERROR, 1.0);
+        let guard = layer.guard();
+
+        with_layer(layer, || {
+            for _ in 0..800 {
+                tracing::info!("tick");
+            }
+            for _ in 0..400 {
+                tracing::error!("payment failed");
+            }
+        });
+        assert!(guard.flush_async(Duration::from_secs(5)).await);
+
+        let layer_kept = 1_200 - guard.sampled_out_events();
+        assert!((750..=850).contains(&layer_kept), "{} kept", layer_kept);
+        let messages = messages(&captured);
+        let ticks = messages.iter().filter(|message| *message == "tick").count();
+        let errors = messages.len() - ticks;
+        assert!((150..=250).contains(&ticks), "{} of 800 ticks", ticks);
+        assert!((150..=250).contains(&errors), "{} of 400 errors", errors);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn flush_does_not_block_a_current_thread_runtime() {
+        let guard = guard().await;
+
+        let started = std::time::Instant::now();
+        assert!(!guard.flush(Duration::from_secs(5)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        assert!(guard.flush_async(Duration::from_secs(5)).await);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn flush_blocks_on_a_multi_thread_runtime() {
+        let guard = guard().await;
+        assert!(guard.flush(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn flush_works_without_a_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let guard = runtime.block_on(guard());
+        assert!(guard.flush(Duration::from_secs(5)));
+    }
+
+    #[tokio::test]
+    async fn events_carry_the_fields_of_their_spans() {
+        let (layer, recorded) = recording_layer(|layer| layer.with_metadata(false)).await;
+
+        with_layer(layer, || {
+            let outer = tracing::info_span!("request", user_id = 7, route = "/pay");
+            let _outer = outer.enter();
+            let inner =
+                tracing::info_span!("request", user_id = 8, attempt = tracing::field::Empty);
+            let _inner = inner.enter();
+            inner.record("attempt", 2);
+            tracing::info!(span.request.route = "/refund", "charging");
+        });
+
+        let recorded = recorded.lock().unwrap();
+        let extra = &recorded[0].extra;
+        assert_eq!(extra["span.request.user_id"], 8);
+        assert_eq!(extra["span.request.attempt"], 2);
+        assert_eq!(extra["span.request.route"], "/refund");
+    }
+
+    #[tokio::test]
+    async fn span_fields_can_be_left_out() {
+        let (layer, recorded) =
+            recording_layer(|layer| layer.with_metadata(false).with_span_fields(false)).await;
+
+        with_layer(layer, || {
+            let _span = tracing::info_span!("authenticate_user", user_id = 7).entered();
+            tracing::info!(step = "password", "checking");
+        });
+
+        let recorded = recorded.lock().unwrap();
+        let fields: BTreeSet<&str> = recorded[0].extra.keys().map(String::as_str).collect();
+        assert_eq!(fields, BTreeSet::from(["message", "step"]));
+    }
+
+    #[tokio::test]
+    async fn slow_spans_are_sent_with_their_duration() {
+        use tracing::Instrument;
+
+        let (layer, recorded) =
+            recording_layer(|layer| layer.with_span_timing(Duration::from_millis(20))).await;
+        let _default = tracing::subscriber::set_default(tracing_subscriber::registry().with(layer));
+
+        async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            async { tokio::time::sleep(Duration::from_millis(30)).await }
+                .instrument(tracing::info_span!("charge_card"))
+                .await;
+            async {}.instrument(tracing::info_span!("validate")).await;
+        }
+        .instrument(tracing::info_span!("process_payment", order = 1234))
+        .await;
+
+        // The client's relay tasks log on this thread too.
+        let recorded: Vec<sentrystr::Event> = recorded
+            .lock()
+            .unwrap()
+            .drain(..)
+            .filter(|event| event.transaction.is_some())
+            .collect();
+        let spans: Vec<(&str, u64)> = recorded
+            .iter()
+            .map(|event| {
+                (
+                    event.transaction.as_deref().unwrap(),
+                    event.duration_ms.unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(spans.len(), 2, "{spans:?}");
+        assert_eq!(spans[0].0, "charge_card");
+        assert!(spans[0].1 >= 30);
+        assert_eq!(spans[1].0, "process_payment");
+        assert!(spans[1].1 >= 40);
+
+        let payment = &recorded[1];
+        assert_eq!(payment.level, sentrystr::Level::Info);
+        assert_eq!(payment.extra["order"], 1234);
+        assert_eq!(payment.extra["duration_ms"], spans[1].1);
+        assert_eq!(payment.logger.as_deref(), Some(module_path!()));
+    }
+
+    #[tokio::test]
+    async fn excluded_targets_never_reach_the_send_path() {
+        let (layer, recorded) = recording_layer(|layer| {
+            layer
+                .with_min_level(tracing::Level::WARN)
+                .with_breadcrumbs(true)
+                .with_target_filter(
+                    TargetFilter::new()
+                        .with_include(["my_app", "hyper"])
+                        .with_exclude(["hyper::client"]),
+                )
+        })
+        .await;
+
+        let client = layer.client();
+
+        with_layer(layer, || {
+            tracing::info!(target: "hyper::client", "pool idle");
+            tracing::error!(target: "hyper::client", "connection reset");
+            tracing::error!(target: "hyper::server", "bad request");
+            tracing::error!(target: "tokio_util", "codec error");
+            tracing::info!(target: "my_app::payments", "charging");
+            tracing::warn!(target: "my_app::payments", "card declined");
+        });
+
+        assert_eq!(messages(&recorded), vec!["bad request", "card declined"]);
+        let breadcrumbs: Vec<String> = client
+            .read()
+            .await
+            .breadcrumbs()
+            .into_iter()
+            .filter_map(|breadcrumb| breadcrumb.message)
+            .collect();
+        assert_eq!(breadcrumbs, vec!["charging"]);
+    }
+
+    #[tokio::test]
+    async fn static_tags_fill_in_events_and_their_nostr_tags() {
+        let (layer, recorded) = recording_layer(|layer| {
+            layer
+                .with_service("my-api")
+                .with_environment("staging")
+                .with_release("1.4.2")
+                .with_tag("component", "billing")
+        })
+        .await;
+
+        with_layer(layer, || tracing::error!("payment failed"));
+
+        let recorded = recorded.lock().unwrap();
+        let event = &recorded[0];
+        assert_eq!(event.environment.as_deref(), Some("staging"));
+        assert_eq!(event.release.as_deref(), Some("1.4.2"));
+        assert!(!event.extra.contains_key("environment"));
+        assert_eq!(event.tags["service"], "my-api");
+        assert_eq!(event.tags["component"], "billing");
+        let mut nostr_tags: Vec<&[String]> =
+            event.nostr_tags.iter().map(|tag| tag.as_slice()).collect();
+        nostr_tags.sort();
+        assert_eq!(
+            nostr_tags,
+            [
+                ["component", "billing"],
+                ["env", "staging"],
+                ["service", "my-api"],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn collectors_select_events_by_the_layer_service() {
+        use nostr_relay_builder::MockRelay;
+        use sentrystr_collector::{EventCollector, EventFilter};
+
+        let relay = MockRelay::run().await.unwrap();
+        let url = relay.url().to_string();
+        for (service, message) in [("my-api", "payment failed"), ("worker", "job failed")] {
+            let config = Config::from_keys(&Keys::generate(), [url.as_str()]).unwrap();
+            let layer = SentryStrLayer::new(NostrSentryClient::new(config).await.unwrap())
+                .with_service(service);
+            let guard = layer.guard();
+            with_layer(layer, || tracing::error!("{}", message));
+            assert!(guard.flush_async(Duration::from_secs(5)).await);
+        }
+
+        let collected = EventCollector::new(vec![url])
+            .await
+            .unwrap()
+            .collect_events(EventFilter::new().with_service_filter("my-api".to_string()))
+            .await
+            .unwrap();
+
+        let messages: Vec<_> = collected
+            .iter()
+            .filter_map(|event| event.event.message.as_deref())
+            .collect();
+        assert_eq!(messages, vec!["payment failed"]);
+    }
+
+    fn levels(recorded: &Recorded) -> Vec<sentrystr::Level> {
+        recorded
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| event.level.clone())
+            .collect()
+    }
+
+    fn every_level() {
+        tracing::trace!("trace");
+        tracing::debug!("debug");
+        tracing::info!("info");
+        tracing::warn!("warn");
+        tracing::error!("error");
+    }
+
+    #[tokio::test]
+    async fn levels_map_as_before_unless_configured() {
+        use sentrystr::Level;
+
+        let (layer, recorded) = recording_layer(|layer| layer).await;
+        with_layer(layer, every_level);
+        assert_eq!(
+            levels(&recorded),
+            [
+                Level::Debug,
+                Level::Debug,
+                Level::Info,
+                Level::Warning,
+                Level::Error
+            ]
+        );
+
+        let (layer, recorded) = recording_layer(|layer| {
+            layer.with_level_mapping(|level| match *level {
+                tracing::Level::TRACE => Level::Debug,
+                tracing::Level::DEBUG => Level::Info,
+                tracing::Level::ERROR => Level::Fatal,
+                ref level => convert_tracing_level(level),
+            })
+        })
+        .await;
+        with_layer(layer, every_level);
+        assert_eq!(
+            levels(&recorded),
+            [
+                Level::Debug,
+                Level::Info,
+                Level::Info,
+                Level::Warning,
+                Level::Fatal
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn control_fields_escalate_the_level_and_are_stripped() {
+        use sentrystr::Level;
+
+        let (layer, recorded) = recording_layer(|layer| layer.with_metadata(false)).await;
+
+        with_layer(layer, || {
+            tracing::error!(fatal = true, "disk full");
+            tracing::warn!(sentrystr.level = "fatal", "replica lost");
+            tracing::error!(fatal = false, "retrying");
+            tracing::warn!(sentrystr.level = "catastrophic", "unknown level");
+        });
+
+        assert_eq!(
+            levels(&recorded),
+            [Level::Fatal, Level::Fatal, Level::Error, Level::Warning]
+        );
+        let recorded = recorded.lock().unwrap();
+        for event in &recorded[..3] {
+            assert!(!event.extra.contains_key(FATAL_FIELD));
+            assert!(!event.extra.contains_key(LEVEL_FIELD));
         }
+        assert_eq!(recorded[3].extra[LEVEL_FIELD], "catastrophic");
     }
 }
@@ -13,7 +13,7 @@
 //!     let relays = vec!["wss://relay.damus.io".to_string()];
 //!
 //!     // Initialize tracing
-//!     SentryStrTracingBuilder::new()
+//!     let _guard = SentryStrTracingBuilder::new()
 //!         .with_generated_keys_and_relays(relays)
 //!         .with_min_level(tracing::Level::INFO)
 //!         .init()
@@ -46,7 +46,7 @@
 //!         .with_min_level(Level::Error)
 //!         .with_nip17(true);
 //!
-//!     SentryStrTracingBuilder::new()
+//!     let _guard = SentryStrTracingBuilder::new()
 //!         .with_generated_keys_and_relays(relays)
 //!         .with_direct_messaging(dm_config)
 //!         .init()
@@ -79,7 +79,7 @@
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     SentryStrTracingBuilder::new()
+//!     let _guard = SentryStrTracingBuilder::new()
 //!         .with_generated_keys_and_relays(vec!["wss://relay.damus.io".to_string()])
 //!         .init_with_env_filter("info,my_app=debug")
 //!         .await?;
@@ -92,14 +92,25 @@
 //! }
 //! ```
 
+pub mod batch;
 pub mod builder;
 pub mod error;
+pub mod filter;
 pub mod layer;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod route;
+mod sample;
+pub mod scrub;
 pub mod visitor;
 
+pub use batch::BatchOverflow;
 pub use builder::SentryStrTracingBuilder;
 pub use error::TracingError;
-pub use layer::SentryStrLayer;
+pub use filter::TargetFilter;
+pub use layer::{FATAL_FIELD, LEVEL_FIELD, LevelMapping, SentryStrGuard, SentryStrLayer};
+pub use route::DmRoute;
+pub use scrub::Scrubber;
 pub use visitor::FieldVisitor;
 
 use sentrystr::{Event, Level};
@@ -0,0 +1,291 @@
+//! An OpenTelemetry log exporter, for services logging through the OpenTelemetry SDK
+//! instead of `tracing`. Requires the `otel` feature.
+
+use crate::Result;
+use opentelemetry::InstrumentationScope;
+use opentelemetry::logs::{AnyValue, Severity};
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::logs::{LogBatch, LogExporter, SdkLogRecord, SdkLoggerProvider};
+use sentrystr::{ClientTransport, Config, Event, Level, NostrSentryClient, Transport};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+/// A `LogExporter` turning OpenTelemetry log records into [`sentrystr::Event`]s.
+///
+/// Batches are sent on a Tokio runtime, so the exporter works with the SDK's batch
+/// processor, which exports from its own thread.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use opentelemetry_sdk::logs::SdkLoggerProvider;
+/// use sentrystr::{Config, NostrSentryClient};
+/// use sentrystr_tracing::otel::SentryStrLogExporter;
+/// use std::sync::Arc;
+///
+/// # async fn example(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Arc::new(NostrSentryClient::new(config).await?);
+/// let provider = SdkLoggerProvider::builder()
+///     .with_batch_exporter(SentryStrLogExporter::new(client))
+///     .build();
+/// # Ok(())
+/// # }
+/// ```
+pub struct SentryStrLogExporter {
+    transport: Arc<dyn Transport>,
+    /// Flushed on shutdown; `None` with [`Self::with_transport`].
+    client: Option<Arc<NostrSentryClient>>,
+    runtime: Handle,
+}
+
+impl SentryStrLogExporter {
+    /// Exports with `client` on the current Tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// When called outside a Tokio runtime.
+    pub fn new(client: Arc<NostrSentryClient>) -> Self {
+        Self {
+            transport: Arc::new(ClientTransport::new(Arc::clone(&client))),
+            client: Some(client),
+            runtime: Handle::current(),
+        }
+    }
+
+    /// [`Self::new`] sending events to `transport` instead of a client, e.g. one
+    /// recording them in tests.
+    ///
+    /// # Panics
+    ///
+    /// When called outside a Tokio runtime.
+    pub fn with_transport(transport: Arc<dyn Transport>) -> Self {
+        Self {
+            transport,
+            client: None,
+            runtime: Handle::current(),
+        }
+    }
+
+    /// Sends batches on `runtime` instead of the runtime the exporter was created on.
+    pub fn with_runtime(mut self, runtime: Handle) -> Self {
+        self.runtime = runtime;
+        self
+    }
+}
+
+impl fmt::Debug for SentryStrLogExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentryStrLogExporter")
+            .field("transport", &self.transport)
+            .finish_non_exhaustive()
+    }
+}
+
+impl LogExporter for SentryStrLogExporter {
+    /// Sends the batch's events one after another, failing when any was not sent.
+    async fn export(&self, batch: LogBatch<'_>) -> OTelSdkResult {
+        let events: Vec<Event> = batch
+            .iter()
+            .map(|(record, scope)| record_event(record, scope))
+            .collect();
+
+        let transport = Arc::clone(&self.transport);
+        let sends = self.runtime.spawn(async move {
+            let mut failed = 0;
+            for event in &events {
+                if let Err(e) = transport.send(event).await {
+                    eprintln!("Failed to send log record to SentryStr: {}", e);
+                    failed += 1;
+                }
+            }
+            failed
+        });
+
+        match sends.await {
+            Ok(0) => Ok(()),
+            Ok(failed) => Err(OTelSdkError::InternalFailure(format!(
+                "{} log records were not sent",
+                failed
+            ))),
+            Err(e) => Err(OTelSdkError::InternalFailure(e.to_string())),
+        }
+    }
+
+    /// Flushes the client's queue on the exporter's runtime, waiting up to `timeout`.
+    /// Fails at once inside a current-thread runtime, which could not run the flush
+    /// while it waits; shut the provider down from outside that runtime.
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        let Some(ref client) = self.client else {
+            return Ok(());
+        };
+
+        let current = Handle::try_current().ok();
+        if current
+            .as_ref()
+            .is_some_and(|current| current.runtime_flavor() == RuntimeFlavor::CurrentThread)
+        {
+            return Err(OTelSdkError::InternalFailure(
+                "Cannot flush inside a current-thread runtime".to_string(),
+            ));
+        }
+
+        let client = Arc::clone(client);
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.runtime.spawn(async move {
+            let _ = tx.send(client.flush(timeout).await);
+        });
+        let wait = || {
+            rx.recv_timeout(timeout).map_err(|_| {
+                OTelSdkError::InternalFailure(format!("Flush did not finish within {:?}", timeout))
+            })
+        };
+        let pending = if current.is_some() {
+            // Lets the runtime move this worker's other tasks elsewhere while it waits.
+            tokio::task::block_in_place(wait)?
+        } else {
+            wait()?
+        };
+
+        if pending > 0 {
+            return Err(OTelSdkError::InternalFailure(format!(
+                "{} events still pending after the flush",
+                pending
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The event of `record`: its body as the message, its severity as the level, its
+/// target (or else the instrumentation scope's name) as the logger, its attributes as
+/// extras, and its trace context as the `trace_id` and `span_id` extras.
+pub fn record_event(record: &SdkLogRecord, scope: &InstrumentationScope) -> Event {
+    let level = record
+        .severity_number()
+        .map_or(Level::Info, convert_severity);
+    let mut event = Event::new().with_level(level);
+
+    if let Some(body) = record.body() {
+        let message = match body {
+            AnyValue::String(text) => text.as_str().to_string(),
+            other => any_value_to_json(other).to_string(),
+        };
+        event = event.with_message(message);
+    }
+    if let Some(timestamp) = record.timestamp().or(record.observed_timestamp()) {
+        event = event.with_timestamp(timestamp.into());
+    }
+
+    let target = record
+        .target()
+        .map_or_else(|| scope.name().to_string(), |target| target.to_string());
+    event = event.with_extra("meta_target", target.as_str().into());
+    event.logger = Some(target);
+    if let Some(severity_text) = record.severity_text() {
+        event = event.with_extra("meta_level", severity_text.into());
+    }
+
+    for (key, value) in record.attributes_iter() {
+        event = event.with_extra(key.as_str(), any_value_to_json(value));
+    }
+
+    if let Some(trace_context) = record.trace_context() {
+        event = event
+            .with_extra("trace_id", trace_context.trace_id.to_string().into())
+            .with_extra("span_id", trace_context.span_id.to_string().into());
+    }
+    event
+}
+
+/// Each severity maps to the level of the same name, with `Trace*` as `Debug` like
+/// [`crate::convert_tracing_level`].
+pub fn convert_severity(severity: Severity) -> Level {
+    match severity {
+        Severity::Trace
+        | Severity::Trace2
+        | Severity::Trace3
+        | Severity::Trace4
+        | Severity::Debug
+        | Severity::Debug2
+        | Severity::Debug3
+        | Severity::Debug4 => Level::Debug,
+        Severity::Info | Severity::Info2 | Severity::Info3 | Severity::Info4 => Level::Info,
+        Severity::Warn | Severity::Warn2 | Severity::Warn3 | Severity::Warn4 => Level::Warning,
+        Severity::Error | Severity::Error2 | Severity::Error3 | Severity::Error4 => Level::Error,
+        Severity::Fatal | Severity::Fatal2 | Severity::Fatal3 | Severity::Fatal4 => Level::Fatal,
+    }
+}
+
+fn any_value_to_json(value: &AnyValue) -> serde_json::Value {
+    match value {
+        AnyValue::Int(n) => (*n).into(),
+        AnyValue::Double(n) => (*n).into(),
+        AnyValue::String(text) => text.as_str().into(),
+        AnyValue::Boolean(b) => (*b).into(),
+        AnyValue::Bytes(bytes) => bytes.as_slice().into(),
+        AnyValue::ListAny(values) => values.iter().map(any_value_to_json).collect(),
+        AnyValue::Map(map) => map
+            .iter()
+            .map(|(key, value)| (key.to_string(), any_value_to_json(value)))
+            .collect(),
+        _ => serde_json::Value::Null,
+    }
+}
+
+/// Connects a client with `config` and builds a logger provider exporting to it through
+/// a batch processor. Shutting the provider down exports what is buffered and flushes
+/// the client's queue.
+pub async fn build_logger_provider(config: Config) -> Result<SdkLoggerProvider> {
+    let client = Arc::new(NostrSentryClient::new(config).await?);
+    Ok(SdkLoggerProvider::builder()
+        .with_batch_exporter(SentryStrLogExporter::new(client))
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+
+    async fn exporter() -> SentryStrLogExporter {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap();
+        let client = Arc::new(NostrSentryClient::new(config).await.unwrap());
+        SentryStrLogExporter::new(client)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_flushes_on_a_multi_thread_runtime() {
+        let exporter = exporter().await;
+        assert!(
+            exporter
+                .shutdown_with_timeout(Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_fails_at_once_on_a_current_thread_runtime() {
+        let exporter = exporter().await;
+        assert!(
+            exporter
+                .shutdown_with_timeout(Duration::from_secs(1))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn shutdown_flushes_outside_a_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let exporter = runtime.block_on(exporter());
+        assert!(
+            exporter
+                .shutdown_with_timeout(Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Which recipients get the DM alert of an event, see
+//! [`crate::SentryStrLayer::with_routed_direct_messaging`].
+
+use sentrystr::DirectMessageSender;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// The events a DM recipient is alerted about. An event matches when its target starts
+/// with one of the target prefixes, if any are set, and it has every field set. Empty
+/// routes match no event: their recipients get the events no other route matched.
+#[derive(Debug, Clone, Default)]
+pub struct DmRoute {
+    target_prefixes: Vec<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl DmRoute {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches targets starting with `prefix`, e.g. `my_app::db` for `my_app::db::pool`.
+    pub fn with_target_prefix(mut self, prefix: &str) -> Self {
+        self.target_prefixes.push(prefix.to_string());
+        self
+    }
+
+    /// Matches events whose tag or field `name` is `value`, such as the `service` tag of
+    /// [`crate::SentryStrLayer::with_service`]. Non-string fields are compared by their
+    /// JSON text, e.g. `"42"` or `"true"`.
+    pub fn with_field_equals(mut self, name: &str, value: &str) -> Self {
+        self.fields.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.target_prefixes.is_empty() && self.fields.is_empty()
+    }
+
+    /// Whether `event` goes to this route's recipient. Its target is its logger.
+    pub fn matches(&self, event: &sentrystr::Event) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let target = event.logger.as_deref().unwrap_or_default();
+        let target_matches = self.target_prefixes.is_empty()
+            || self
+                .target_prefixes
+                .iter()
+                .any(|prefix| target.starts_with(prefix.as_str()));
+
+        target_matches
+            && self
+                .fields
+                .iter()
+                .all(|(name, value)| field_equals(event, name, value))
+    }
+}
+
+fn field_equals(event: &sentrystr::Event, name: &str, value: &str) -> bool {
+    if event.tags.get(name).is_some_and(|tag| tag == value) {
+        return true;
+    }
+    match event.extra.get(name) {
+        Some(serde_json::Value::String(field)) => field == value,
+        Some(field) => serde_json::to_string(field).is_ok_and(|text| text == value),
+        None => false,
+    }
+}
+
+/// A DM sender of the layer and the events it is sent.
+#[derive(Clone)]
+pub(crate) struct RoutedSender {
+    pub(crate) route: DmRoute,
+    pub(crate) sender: Arc<RwLock<DirectMessageSender>>,
+}
+
+/// The senders alerted about `event`: those whose route matches it, or else those
+/// without a route.
+pub(crate) fn recipients<'a>(
+    senders: &'a [RoutedSender],
+    event: &sentrystr::Event,
+) -> Vec<&'a RoutedSender> {
+    let routed: Vec<&RoutedSender> = senders
+        .iter()
+        .filter(|routed| routed.route.matches(event))
+        .collect();
+    if !routed.is_empty() {
+        return routed;
+    }
+    senders
+        .iter()
+        .filter(|routed| routed.route.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+    use sentrystr::DirectMessageBuilder;
+
+    fn routed(route: DmRoute) -> RoutedSender {
+        let keys = Keys::generate();
+        let sender = DirectMessageBuilder::new()
+            .with_client(nostr_sdk::Client::new(keys.clone()))
+            .with_keys(keys)
+            .with_recipient(Keys::generate().public_key())
+            .build()
+            .unwrap();
+        RoutedSender {
+            route,
+            sender: Arc::new(RwLock::new(sender)),
+        }
+    }
+
+    fn event(target: &str) -> sentrystr::Event {
+        let mut event = sentrystr::Event::new().with_message("failed");
+        event.logger = Some(target.to_string());
+        event
+    }
+
+    fn is(recipients: &[&RoutedSender], expected: &[&RoutedSender]) -> bool {
+        recipients.len() == expected.len()
+            && recipients
+                .iter()
+                .zip(expected)
+                .all(|(a, b)| Arc::ptr_eq(&a.sender, &b.sender))
+    }
+
+    #[test]
+    fn events_go_to_the_matching_routes_or_else_the_fallback() {
+        let db = routed(DmRoute::new().with_target_prefix("my_app::db"));
+        let billing = routed(DmRoute::new().with_field_equals("service", "billing"));
+        let fallback = routed(DmRoute::new());
+        let senders = vec![db.clone(), billing.clone(), fallback.clone()];
+
+        assert!(is(
+            &recipients(&senders, &event("my_app::db::pool")),
+            &[&db]
+        ));
+        assert!(is(
+            &recipients(
+                &senders,
+                &event("my_app::api").with_tag("service", "billing")
+            ),
+            &[&billing]
+        ));
+        assert!(is(
+            &recipients(
+                &senders,
+                &event("my_app::db::pool").with_tag("service", "billing")
+            ),
+            &[&db, &billing]
+        ));
+        assert!(is(
+            &recipients(&senders, &event("my_app::api")),
+            &[&fallback]
+        ));
+    }
+
+    #[test]
+    fn without_a_fallback_unmatched_events_go_nowhere() {
+        let senders = vec![routed(DmRoute::new().with_target_prefix("my_app::db"))];
+        assert!(recipients(&senders, &event("my_app::api")).is_empty());
+    }
+}
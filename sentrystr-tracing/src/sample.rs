@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Sampling of the layer's events, see [`crate::SentryStrLayer::with_sample_rate`].
+/// Clones share the sequence and the count of events sampled out.
+#[derive(Clone, Default)]
+pub(crate) struct LayerSampler {
+    rate: Option<f64>,
+    level_rates: HashMap<tracing::Level, f64>,
+    /// Numbers the events sampled, each drawn from its number.
+    sequence: Arc<AtomicU64>,
+    pub(crate) sampled_out: Arc<AtomicU64>,
+}
+
+impl LayerSampler {
+    pub(crate) fn set_rate(&mut self, rate: f64) {
+        self.rate = Some(rate.clamp(0.0, 1.0));
+    }
+
+    pub(crate) fn set_level_rate(&mut self, level: tracing::Level, rate: f64) {
+        self.level_rates.insert(level, rate.clamp(0.0, 1.0));
+    }
+
+    /// Whether to keep an event at `level`, counting it when it is sampled out. Rates
+    /// of 1 keep every event and rates of 0 none, without drawing.
+    pub(crate) fn keep(&self, level: &tracing::Level) -> bool {
+        let rate = self
+            .level_rates
+            .get(level)
+            .copied()
+            .or(self.rate)
+            .unwrap_or(1.0);
+        let keep = if rate.is_nan() || rate >= 1.0 {
+            true
+        } else if rate <= 0.0 {
+            false
+        } else {
+            draw(self.sequence.fetch_add(1, Ordering::Relaxed)) < rate
+        };
+
+        if !keep {
+            self.sampled_out.fetch_add(1, Ordering::Relaxed);
+        }
+        keep
+    }
+}
+
+/// A number in `[0, 1)` spread evenly by SplitMix64 from the event number `n`, so the
+/// same sequence of events is always sampled the same way.
+fn draw(n: u64) -> f64 {
+    let mut z = n.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_of_zero_and_one_are_exact_and_do_not_draw() {
+        let mut sampler = LayerSampler::default();
+        sampler.set_rate(1.5);
+        sampler.set_level_rate(tracing::Level::DEBUG, -1.0);
+
+        assert!((0..1_000).all(|_| sampler.keep(&tracing::Level::INFO)));
+        assert!((0..1_000).all(|_| !sampler.keep(&tracing::Level::DEBUG)));
+        assert_eq!(sampler.sampled_out.load(Ordering::Relaxed), 1_000);
+        assert_eq!(sampler.sequence.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn draws_are_spread_evenly() {
+        let below_half = (0..10_000).filter(|&n| draw(n) < 0.5).count();
+        assert!((4_800..=5_200).contains(&below_half), "{below_half}");
+        assert!((0..10_000).map(draw).all(|x| (0.0..1.0).contains(&x)));
+    }
+}
@@ -0,0 +1,312 @@
+//! Masking of sensitive values before events leave the process, see
+//! [`crate::SentryStrLayer::with_scrubber`]. Relays are public, so a `password` field
+//! logged by mistake would otherwise be readable by anyone.
+
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// What scrubbed values are replaced with.
+pub const REDACTED: &str = "[redacted]";
+
+/// Field names masked by [`Scrubber::with_defaults`].
+pub const DEFAULT_SCRUB_FIELDS: &[&str] = &[
+    "password",
+    "passwd",
+    "pwd",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "api_key",
+    "apikey",
+    "auth",
+    "authorization",
+    "proxy_authorization",
+    "cookie",
+    "set_cookie",
+    "session",
+    "session_id",
+    "private_key",
+    "nsec",
+    "credit_card",
+    "card_number",
+    "cvv",
+    "ssn",
+];
+
+/// Masks the values of fields with sensitive names, at any depth, and the parts of
+/// string values matching sensitive patterns. A name is sensitive when it, its last
+/// dotted segment or its last `_`/`-` suffix is a masked field, so `password` also
+/// masks `user.password`, `db_password` and `span.login.password`.
+#[derive(Debug, Clone, Default)]
+pub struct Scrubber {
+    /// Lowercase field names.
+    fields: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Scrubber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Masks the whole value of the fields named `fields`, compared case-insensitively,
+    /// and of the dotted and suffixed names ending in them.
+    pub fn with_fields<I, S>(mut self, fields: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.fields.extend(
+            fields
+                .into_iter()
+                .map(|field| field.as_ref().to_lowercase()),
+        );
+        self
+    }
+
+    /// Masks each match of `patterns` in string values and messages, e.g. card numbers
+    /// or bearer tokens.
+    pub fn with_patterns(mut self, patterns: Vec<Regex>) -> Self {
+        self.patterns.extend(patterns);
+        self
+    }
+
+    /// Adds [`DEFAULT_SCRUB_FIELDS`] to the masked fields.
+    pub fn with_defaults(self) -> Self {
+        self.with_fields(DEFAULT_SCRUB_FIELDS)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.patterns.is_empty()
+    }
+
+    /// Whether the value of the field `name` is masked.
+    pub fn is_sensitive(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let last = name.rsplit('.').next().unwrap_or_default();
+        self.fields.contains(last)
+            || self.fields.iter().any(|field| {
+                last.strip_suffix(field.as_str())
+                    .is_some_and(|rest| rest.ends_with(['_', '-']))
+            })
+    }
+
+    /// `text` with every match of the patterns masked.
+    pub fn scrub_text(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for pattern in &self.patterns {
+            if pattern.is_match(&text) {
+                text = pattern.replace_all(&text, REDACTED).into_owned();
+            }
+        }
+        text
+    }
+
+    /// Masks the sensitive fields of `fields` and of the objects nested in them.
+    pub fn scrub_fields(&self, fields: &mut BTreeMap<String, Value>) {
+        for (name, value) in fields.iter_mut() {
+            self.scrub_field(name, value);
+        }
+    }
+
+    fn scrub_field(&self, name: &str, value: &mut Value) {
+        if self.is_sensitive(name) {
+            *value = Value::String(REDACTED.to_string());
+        } else {
+            self.scrub_value(value);
+        }
+    }
+
+    /// Masks every value of `event` that leaves the process: its message, exceptions,
+    /// fields, tags, user, request and breadcrumbs. Run it last, once scopes and static
+    /// tags have been applied, so their values are masked too.
+    pub fn scrub_event(&self, event: &mut sentrystr::Event) {
+        if let Some(ref mut message) = event.message {
+            *message = self.scrub_text(message);
+        }
+        for exception in event.exception.iter_mut().flatten() {
+            exception.value = self.scrub_text(&exception.value);
+            for cause in &mut exception.chain {
+                *cause = self.scrub_text(cause);
+            }
+        }
+        for (name, value) in event.extra.iter_mut() {
+            self.scrub_field(name, value);
+        }
+        self.scrub_strings(&mut event.tags);
+        event.nostr_tags = event
+            .nostr_tags
+            .drain(..)
+            .map(|tag| self.scrub_nostr_tag(tag))
+            .collect();
+
+        if let Some(ref mut user) = event.user {
+            for (name, value) in [
+                ("id", &mut user.id),
+                ("username", &mut user.username),
+                ("email", &mut user.email),
+                ("ip_address", &mut user.ip_address),
+            ] {
+                if let Some(value) = value {
+                    *value = self.scrub_string(&format!("user.{}", name), value);
+                }
+            }
+        }
+        if let Some(ref mut request) = event.request {
+            for value in [
+                &mut request.url,
+                &mut request.query_string,
+                &mut request.cookies,
+            ]
+            .into_iter()
+            .flatten()
+            {
+                *value = self.scrub_text(value);
+            }
+            if let Some(ref mut headers) = request.headers {
+                self.scrub_strings(headers);
+            }
+            if let Some(ref mut data) = request.data {
+                self.scrub_value(data);
+            }
+        }
+
+        for breadcrumb in &mut event.breadcrumbs {
+            if let Some(ref mut message) = breadcrumb.message {
+                *message = self.scrub_text(message);
+            }
+            for (name, value) in breadcrumb.data.iter_mut() {
+                self.scrub_field(name, value);
+            }
+        }
+    }
+
+    fn scrub_string(&self, name: &str, value: &str) -> String {
+        if self.is_sensitive(name) {
+            REDACTED.to_string()
+        } else {
+            self.scrub_text(value)
+        }
+    }
+
+    fn scrub_strings(&self, strings: &mut HashMap<String, String>) {
+        for (name, value) in strings.iter_mut() {
+            *value = self.scrub_string(name, value);
+        }
+    }
+
+    /// `tag` with its values masked when its name is sensitive, else with the pattern
+    /// matches in them masked.
+    fn scrub_nostr_tag(&self, tag: nostr::Tag) -> nostr::Tag {
+        let Some((name, values)) = tag.as_slice().split_first() else {
+            return tag;
+        };
+        let scrubbed: Vec<String> = values
+            .iter()
+            .map(|value| self.scrub_string(name, value))
+            .collect();
+        if scrubbed == values {
+            return tag;
+        }
+        nostr::Tag::parse(std::iter::once(name.clone()).chain(scrubbed)).unwrap_or(tag)
+    }
+
+    /// Masks the sensitive fields of the objects in `value` and the pattern matches in
+    /// its strings.
+    pub fn scrub_value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.scrub_text(text),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.scrub_value(item)),
+            Value::Object(object) => {
+                for (name, value) in object.iter_mut() {
+                    self.scrub_field(name, value);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scrubber() -> Scrubber {
+        Scrubber::new()
+            .with_defaults()
+            .with_patterns(vec![Regex::new(r"Bearer [A-Za-z0-9._~+/-]+=*").unwrap()])
+    }
+
+    #[test]
+    fn dotted_and_suffixed_names_are_sensitive() {
+        let scrubber = scrubber();
+        for name in [
+            "password",
+            "Password",
+            "user.password",
+            "db_password",
+            "DB-PASSWORD",
+            "span.login.password",
+            "span.login.csrf_token",
+        ] {
+            assert!(scrubber.is_sensitive(name), "{name}");
+        }
+        for name in ["passwords", "oauth", "password.hint", "tokenizer"] {
+            assert!(!scrubber.is_sensitive(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn hunter2_is_never_published() {
+        let mut scope = sentrystr::Scope::default();
+        scope.set_tag("token", "hunter2");
+        scope.set_extra("db_password", json!("hunter2"));
+        scope.set_extra("login", json!({ "user.password": "hunter2" }));
+        scope.set_user(Some(sentrystr::User {
+            id: Some("42".to_string()),
+            username: Some("Bearer hunter2".to_string()),
+            email: None,
+            ip_address: None,
+        }));
+
+        let mut event = sentrystr::Event::new()
+            .with_message("Login failed with Authorization: Bearer hunter2")
+            .with_extra("password", json!("hunter2"))
+            .with_extra("span.login.password", json!("hunter2"))
+            .with_nostr_tag(nostr::Tag::custom(
+                nostr::TagKind::custom("api_key"),
+                ["hunter2"],
+            ))
+            .with_breadcrumbs(vec![
+                sentrystr::Breadcrumb::new("Bearer hunter2").with_data("pwd", json!("hunter2")),
+            ]);
+        scope.apply_to(&mut event);
+        scrubber().scrub_event(&mut event);
+
+        let published = serde_json::to_string(&event).unwrap();
+        assert!(!published.contains("hunter2"), "{published}");
+        assert_eq!(event.user.and_then(|user| user.id).as_deref(), Some("42"));
+        assert_eq!(event.extra["password"], json!(REDACTED));
+    }
+
+    #[test]
+    fn nested_fields_and_patterns_are_masked() {
+        let scrubber = scrubber();
+        let mut fields = BTreeMap::from([
+            (
+                "request".to_string(),
+                json!({ "headers": { "Cookie": "a=b" } }),
+            ),
+            ("note".to_string(), json!(["Bearer abc.def", "plain"])),
+        ]);
+        scrubber.scrub_fields(&mut fields);
+
+        assert_eq!(fields["request"]["headers"]["Cookie"], json!(REDACTED));
+        assert_eq!(fields["note"], json!([REDACTED, "plain"]));
+    }
+}
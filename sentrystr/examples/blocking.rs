@@ -0,0 +1,42 @@
+use nostr::Keys;
+use sentrystr::blocking::Client;
+use sentrystr::{Config, Event, Level};
+use std::time::Duration;
+
+/// A plain synchronous program, without `#[tokio::main]`, reporting from several threads.
+fn main() -> sentrystr::Result<()> {
+    println!("SentryStr Blocking Client Example");
+    println!("=================================");
+
+    let keys = Keys::generate();
+    let config = Config::from_keys(&keys, vec!["wss://relay.damus.io".to_string()])?;
+    let client = Client::new(config)?;
+    println!("Publishing as {}", client.public_key());
+
+    let outcome = client.capture_message(Level::Info, "Batch job started")?;
+    println!("Start event: {}", outcome);
+
+    std::thread::scope(|scope| {
+        for worker in 0..3 {
+            let client = &client;
+            scope.spawn(move || {
+                let event = Event::new()
+                    .with_message(format!("Worker {} finished", worker))
+                    .with_level(Level::Info);
+                if let Err(e) = client.capture_event_detached(event) {
+                    eprintln!("Failed to queue event: {}", e);
+                }
+            });
+        }
+    });
+
+    let error = std::io::Error::other("input file is truncated");
+    client.capture_error(&error)?;
+
+    let report = client.close(Duration::from_secs(5));
+    println!(
+        "Closed: {} delivered, {} abandoned",
+        report.delivered, report.abandoned
+    );
+    Ok(())
+}
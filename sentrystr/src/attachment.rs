@@ -0,0 +1,198 @@
+use crate::{ContentEncryption, Result, SentryStrError};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Kind of the companion events carrying attachments.
+pub const ATTACHMENT_KIND: u16 = 9899;
+/// Tag naming an attachment and its MIME type: `["attachment", name, mime]`.
+pub const ATTACHMENT_TAG: &str = "attachment";
+/// Tag linking an attachment to the `event_id` of the event it belongs to.
+pub const PARENT_TAG: &str = "parent";
+
+/// A small payload published with an event, such as a request body or a config dump.
+/// Each attachment becomes a companion Nostr event of [`ATTACHMENT_KIND`] with the
+/// base64 bytes as content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub name: String,
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Where an attachment of an event was published, recorded on the event itself.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AttachmentRef {
+    /// Hex id of the companion Nostr event.
+    pub event_id: String,
+    pub name: String,
+    pub mime: String,
+    /// Size of the bytes, before encoding.
+    pub size: usize,
+}
+
+impl Attachment {
+    pub fn new(
+        name: impl Into<String>,
+        mime: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            mime: mime.into(),
+            bytes: bytes.into(),
+        }
+    }
+
+    /// The content of a companion event: the base64 bytes.
+    pub(crate) fn encode(&self) -> String {
+        BASE64.encode(&self.bytes)
+    }
+
+    /// Tags of a companion event for the event with `parent_id`.
+    pub(crate) fn tags(&self, parent_id: &str) -> Vec<Tag> {
+        vec![
+            Tag::custom(
+                TagKind::custom(ATTACHMENT_TAG),
+                [self.name.clone(), self.mime.clone()],
+            ),
+            Tag::custom(TagKind::custom(PARENT_TAG), [parent_id.to_string()]),
+        ]
+    }
+
+    /// Reads the attachment carried by a companion event, decrypting it as the reader
+    /// `keys` when it was published with `Config::with_content_encryption`.
+    pub fn from_nostr_event(keys: &Keys, nostr_event: &nostr::Event) -> Result<Self> {
+        let (name, mime) = nostr_event
+            .tags
+            .iter()
+            .find_map(|tag| match tag.as_slice() {
+                [key, name, mime, ..] if key == ATTACHMENT_TAG => {
+                    Some((name.clone(), mime.clone()))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| {
+                SentryStrError::Config(format!("Event {} carries no attachment", nostr_event.id))
+            })?;
+
+        let content = if ContentEncryption::scheme(nostr_event).is_some() {
+            ContentEncryption::decrypt_content(keys, nostr_event)?
+        } else {
+            nostr_event.content.clone()
+        };
+        let bytes = BASE64.decode(content.trim()).map_err(|e| {
+            SentryStrError::Config(format!("Attachment '{}' is not valid base64: {}", name, e))
+        })?;
+
+        Ok(Self { name, mime, bytes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, Event, NostrSentryClient};
+    use nostr_relay_builder::MockRelay;
+    use nostr_sdk::Client;
+    use std::time::Duration;
+
+    /// The companion events of `author` that `relay` holds.
+    async fn companions(relay: &MockRelay, author: &Keys) -> Vec<nostr::Event> {
+        let reader = Client::default();
+        reader.add_relay(relay.url().to_string()).await.unwrap();
+        reader.connect().await;
+        let filter = Filter::new()
+            .author(author.public_key())
+            .kind(Kind::Custom(ATTACHMENT_KIND));
+        reader
+            .fetch_events(filter, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    fn dump() -> Attachment {
+        Attachment::new(
+            "config.toml",
+            "application/toml",
+            b"retries = 3\n\xff".to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn attachments_are_published_and_decoded() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let reader = Keys::generate();
+        let config = Config::from_keys(&keys, [relay.url().to_string()])
+            .unwrap()
+            .with_content_encryption(ContentEncryption::Nip44 {
+                reader_pubkeys: vec![reader.public_key()],
+            });
+        let client = NostrSentryClient::new(config).await.unwrap();
+        let attachment = dump();
+        let event = Event::new().with_message("bad config").with_attachment(
+            attachment.name.clone(),
+            attachment.mime.clone(),
+            attachment.bytes.clone(),
+        );
+        let parent_id = event.event_id.clone();
+
+        client.capture_event(event).await.unwrap();
+
+        let companions = companions(&relay, &keys).await;
+        assert_eq!(companions.len(), 1);
+        let companion = &companions[0];
+        assert!(companion.tags.iter().any(|tag| {
+            tag.as_slice() == [PARENT_TAG.to_string(), parent_id.clone()].as_slice()
+        }));
+        assert_eq!(
+            Attachment::from_nostr_event(&reader, companion).unwrap(),
+            attachment
+        );
+        assert!(Attachment::from_nostr_event(&Keys::generate(), companion).is_err());
+    }
+
+    #[tokio::test]
+    async fn oversized_attachments_are_refused_before_publishing() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let config = Config::from_keys(&keys, [relay.url().to_string()])
+            .unwrap()
+            .with_attachment_limits(8, 12);
+        let client = NostrSentryClient::new(config).await.unwrap();
+
+        let single = Event::new().with_message("too large").with_attachment(
+            "body.json",
+            "application/json",
+            vec![b'x'; 9],
+        );
+        assert!(matches!(
+            client.capture_event(single).await,
+            Err(SentryStrError::AttachmentTooLarge {
+                size: 9,
+                max: 8,
+                ..
+            })
+        ));
+
+        let total = Event::new()
+            .with_message("too large together")
+            .with_attachment("a.txt", "text/plain", vec![b'a'; 8])
+            .with_attachment("b.txt", "text/plain", vec![b'b'; 5]);
+        assert!(matches!(
+            client.capture_event(total).await,
+            Err(SentryStrError::AttachmentsTooLarge { size: 13, max: 12 })
+        ));
+
+        let fits =
+            Event::new()
+                .with_message("fits")
+                .with_attachment("a.txt", "text/plain", vec![b'a'; 8]);
+        client.capture_event(fits).await.unwrap();
+        assert_eq!(companions(&relay, &keys).await.len(), 1);
+    }
+}
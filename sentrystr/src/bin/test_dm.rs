@@ -15,14 +15,14 @@ async fn main() -> Result<()> {
     let keys = Keys::generate();
     println!("Sender pubkey: {}", keys.public_key());
 
-    let config = Config::new(
+    let config = Config::try_new(
         keys.secret_key().display_secret().to_string(),
         vec![
             "wss://relay.damus.io".to_string(),
             "wss://nos.lol".to_string(),
             "wss://relay.nostr.info".to_string(),
         ],
-    );
+    )?;
 
     // Create the main NostrSentryClient
     let mut client = NostrSentryClient::new(config).await?;
@@ -118,7 +118,7 @@ async fn main() -> Result<()> {
     client.set_direct_messaging(dm_sender_warning);
 
     // These should NOT trigger DMs
-    client.capture_message("Debug message").await?;
+    client.capture_message(Level::Info, "Debug message").await?;
     println!("📝 Sent debug message (no DM expected)");
 
     let info_event2 = Event::new()
@@ -135,7 +135,7 @@ async fn main() -> Result<()> {
     println!("📨 Sent warning event with DM");
 
     client
-        .capture_error("Critical database connection error")
+        .capture_error_message("Critical database connection error")
         .await?;
     println!("📨 Sent error event with DM");
 
@@ -154,7 +154,7 @@ async fn main() -> Result<()> {
     // Disable DMs
     client.remove_direct_messaging();
     client
-        .capture_error("This error should NOT send a DM")
+        .capture_error_message("This error should NOT send a DM")
         .await?;
     println!("📝 Sent error with DMs disabled (no DM expected)");
 
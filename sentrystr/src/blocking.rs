@@ -0,0 +1,416 @@
+//! A synchronous client for applications without an async runtime.
+//!
+//! [`Client`] runs a [`NostrSentryClient`] on a current-thread Tokio runtime of its own,
+//! on a dedicated thread, and hands it events over a channel. Its methods mirror the
+//! async client's, minus the `.await`.
+
+use crate::{
+    Breadcrumb, CaptureOutcome, ClientMetrics, CloseReport, Config, Event, Level,
+    NostrSentryClient, Result, Scope, SentryStrError,
+};
+use nostr::PublicKey;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+/// How long dropping a [`Client`] that was not closed waits for its events.
+pub const DROP_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+enum Command {
+    Capture {
+        event: Box<Event>,
+        reply: Option<oneshot::Sender<Result<CaptureOutcome>>>,
+    },
+    Flush {
+        timeout: Duration,
+        reply: oneshot::Sender<usize>,
+    },
+    Close {
+        timeout: Duration,
+        reply: oneshot::Sender<CloseReport>,
+    },
+}
+
+/// A blocking [`NostrSentryClient`]. It can be shared between threads, but its methods
+/// must not be called from async code, where they would block the executor. Dropping it
+/// there is fine: it then closes in the background without waiting.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use sentrystr::blocking::Client;
+/// use sentrystr::{Config, Level};
+/// use std::time::Duration;
+///
+/// # fn example(config: Config) -> sentrystr::Result<()> {
+/// let client = Client::new(config)?;
+/// client.capture_message(Level::Error, "Nightly import failed")?;
+/// client.close(Duration::from_secs(5));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Client {
+    inner: Arc<NostrSentryClient>,
+    commands: mpsc::UnboundedSender<Command>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Client {
+    /// Starts the background thread and connects to the relays of `config` like
+    /// [`NostrSentryClient::new`], blocking until the client is ready.
+    pub fn new(config: Config) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| {
+                SentryStrError::Config(format!("Failed to start the blocking client: {}", e))
+            })?;
+        let (commands, mut receiver) = mpsc::unbounded_channel();
+        let (ready, started) = std::sync::mpsc::channel();
+
+        let thread = std::thread::Builder::new()
+            .name("sentrystr-blocking".to_string())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    let client = match NostrSentryClient::new(config).await {
+                        Ok(client) => Arc::new(client),
+                        Err(e) => {
+                            let _ = ready.send(Err(e));
+                            return;
+                        }
+                    };
+                    if ready.send(Ok(Arc::clone(&client))).is_err() {
+                        return;
+                    }
+                    run(client, &mut receiver).await;
+                });
+            })
+            .map_err(|e| {
+                SentryStrError::Config(format!("Failed to start the blocking client: {}", e))
+            })?;
+
+        let inner = started.recv().map_err(|_| SentryStrError::ClientClosed)??;
+        Ok(Self {
+            inner,
+            commands,
+            thread: Mutex::new(Some(thread)),
+        })
+    }
+
+    /// Captures `event` and waits until it was published, like
+    /// [`NostrSentryClient::capture_event`].
+    pub fn capture_event(&self, event: Event) -> Result<CaptureOutcome> {
+        let (reply, outcome) = oneshot::channel();
+        self.send(Command::Capture {
+            event: Box::new(event),
+            reply: Some(reply),
+        })?;
+        outcome
+            .blocking_recv()
+            .map_err(|_| SentryStrError::ClientClosed)?
+    }
+
+    #[deprecated(note = "`capture_event` returns the outcome now")]
+    pub fn capture_event_with_outcome(&self, event: Event) -> Result<CaptureOutcome> {
+        self.capture_event(event)
+    }
+
+    /// Hands `event` to the background thread without waiting for it to be published.
+    /// Failures are logged there; [`Self::flush`] or [`Self::close`] wait for it.
+    pub fn capture_event_detached(&self, event: Event) -> Result<()> {
+        self.send(Command::Capture {
+            event: Box::new(event),
+            reply: None,
+        })
+    }
+
+    /// See [`NostrSentryClient::capture_message`].
+    pub fn capture_message(
+        &self,
+        level: Level,
+        message: impl Into<String>,
+    ) -> Result<CaptureOutcome> {
+        self.capture_message_with_tags(level, message, std::iter::empty())
+    }
+
+    /// See [`NostrSentryClient::capture_message_with_tags`].
+    pub fn capture_message_with_tags(
+        &self,
+        level: Level,
+        message: impl Into<String>,
+        tags: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<CaptureOutcome> {
+        let mut event = Event::new().with_message(message).with_level(level);
+        event.tags.extend(tags);
+        self.capture_event(event)
+    }
+
+    /// See [`NostrSentryClient::capture_error_message`].
+    pub fn capture_error_message(&self, error: impl Into<String>) -> Result<CaptureOutcome> {
+        let event = Event::new()
+            .with_message(error)
+            .with_level(Level::Error)
+            .with_force_send();
+        self.capture_event(event)
+    }
+
+    /// See [`NostrSentryClient::capture_error`]. The backtrace, if any, is taken on the
+    /// calling thread.
+    pub fn capture_error(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+    ) -> Result<CaptureOutcome> {
+        self.capture_event(Event::from_error(error).with_force_send())
+    }
+
+    /// See [`NostrSentryClient::capture_error_with`].
+    pub fn capture_error_with(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+        customize: impl FnOnce(Event) -> Event,
+    ) -> Result<CaptureOutcome> {
+        self.capture_event(customize(Event::from_error(error)).with_force_send())
+    }
+
+    /// See [`NostrSentryClient::capture_anyhow`].
+    #[cfg(feature = "anyhow")]
+    pub fn capture_anyhow(&self, error: &anyhow::Error) -> Result<CaptureOutcome> {
+        self.capture_event(Event::from_anyhow(error).with_force_send())
+    }
+
+    /// Waits up to `timeout` for detached captures and the queue, like
+    /// [`NostrSentryClient::flush`]. Returns how many events are still pending; 0 once
+    /// the client is closed.
+    pub fn flush(&self, timeout: Duration) -> usize {
+        let (reply, pending) = oneshot::channel();
+        if self.send(Command::Flush { timeout, reply }).is_err() {
+            return 0;
+        }
+        pending.blocking_recv().unwrap_or(0)
+    }
+
+    /// Shuts the client down like [`NostrSentryClient::close`] and stops the background
+    /// thread. Later captures fail with `SentryStrError::ClientClosed`, and closing again
+    /// reports nothing.
+    pub fn close(&self, timeout: Duration) -> CloseReport {
+        let (reply, report) = oneshot::channel();
+        let report = match self.send(Command::Close { timeout, reply }) {
+            Ok(()) => report.blocking_recv().unwrap_or_default(),
+            Err(_) => CloseReport::default(),
+        };
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        report
+    }
+
+    /// See [`NostrSentryClient::add_breadcrumb`].
+    pub fn add_breadcrumb(&self, breadcrumb: Breadcrumb) {
+        self.inner.add_breadcrumb(breadcrumb);
+    }
+
+    /// See [`NostrSentryClient::configure_scope`].
+    pub fn configure_scope(&self, configure: impl FnOnce(&mut Scope)) {
+        self.inner.configure_scope(configure);
+    }
+
+    /// See [`NostrSentryClient::metrics`].
+    pub fn metrics(&self) -> ClientMetrics {
+        self.inner.metrics()
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        self.inner.public_key()
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.commands
+            .send(command)
+            .map_err(|_| SentryStrError::ClientClosed)
+    }
+}
+
+impl Drop for Client {
+    /// Closes the client with [`DROP_CLOSE_TIMEOUT`] unless [`Client::close`] did. Inside
+    /// a Tokio runtime, where waiting would block or panic, the background thread is only
+    /// told to close and finishes on its own.
+    fn drop(&mut self) {
+        if tokio::runtime::Handle::try_current().is_ok() {
+            let (reply, _) = oneshot::channel();
+            let _ = self.send(Command::Close {
+                timeout: DROP_CLOSE_TIMEOUT,
+                reply,
+            });
+            return;
+        }
+        self.close(DROP_CLOSE_TIMEOUT);
+    }
+}
+
+/// Runs the commands of a [`Client`] until it is closed. Captures run concurrently, so
+/// a slow relay does not hold up the events behind it.
+async fn run(client: Arc<NostrSentryClient>, commands: &mut mpsc::UnboundedReceiver<Command>) {
+    let mut captures = tokio::task::JoinSet::new();
+
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Capture { event, reply } => {
+                while captures.try_join_next().is_some() {}
+                let client = Arc::clone(&client);
+                captures.spawn(async move {
+                    let outcome = client.capture_event(*event).await;
+                    match reply {
+                        Some(reply) => {
+                            let _ = reply.send(outcome);
+                        }
+                        None => {
+                            if let Err(e) = outcome {
+                                eprintln!("Failed to capture event: {}", e);
+                            }
+                        }
+                    }
+                });
+            }
+            Command::Flush { timeout, reply } => {
+                let pending = flush(&client, &mut captures, timeout).await;
+                let _ = reply.send(pending);
+            }
+            Command::Close { timeout, reply } => {
+                let deadline = tokio::time::Instant::now() + timeout;
+                flush(&client, &mut captures, timeout).await;
+                let report = client
+                    .close(deadline.saturating_duration_since(tokio::time::Instant::now()))
+                    .await;
+                let _ = reply.send(report);
+                return;
+            }
+        }
+    }
+}
+
+/// Waits up to `timeout` for the running captures, then for the client's queue. Returns
+/// how many events are still pending.
+async fn flush(
+    client: &NostrSentryClient,
+    captures: &mut tokio::task::JoinSet<()>,
+    timeout: Duration,
+) -> usize {
+    let deadline = tokio::time::Instant::now() + timeout;
+    let drained = tokio::time::timeout_at(deadline, async {
+        while captures.join_next().await.is_some() {}
+    })
+    .await;
+    let running = if drained.is_ok() { 0 } else { captures.len() };
+    running
+        + client
+            .flush(deadline.saturating_duration_since(tokio::time::Instant::now()))
+            .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::{Transport, TransportFuture};
+    use nostr::Keys;
+    use std::time::Instant;
+
+    #[derive(Debug, Default, Clone)]
+    struct RecordingTransport(Arc<Mutex<Vec<Event>>>);
+
+    impl Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    impl RecordingTransport {
+        /// The messages of the first `count` events sent, once that many were.
+        fn wait_for(&self, count: usize) -> Vec<String> {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                let events = self.0.lock().unwrap();
+                if events.len() >= count || Instant::now() >= deadline {
+                    let mut messages: Vec<String> = events
+                        .iter()
+                        .filter_map(|event| event.message.clone())
+                        .collect();
+                    messages.sort();
+                    return messages;
+                }
+                drop(events);
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+    }
+
+    /// A client forwarding to `transport`, whose queue publishes to an unreachable relay.
+    fn client(transport: &RecordingTransport) -> Client {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(100, Duration::from_secs(1))
+            .with_additional_transport(transport.clone());
+        Client::new(config).unwrap()
+    }
+
+    #[test]
+    fn captures_from_plain_threads_reach_the_transport() {
+        let transport = RecordingTransport::default();
+        let client = client(&transport);
+
+        std::thread::scope(|scope| {
+            for worker in 0..4 {
+                let client = &client;
+                scope.spawn(move || {
+                    let outcome = client
+                        .capture_message(Level::Info, format!("worker {} waited", worker))
+                        .unwrap();
+                    assert!(outcome.event_id().is_some());
+                    client
+                        .capture_event_detached(
+                            Event::new().with_message(format!("worker {} detached", worker)),
+                        )
+                        .unwrap();
+                });
+            }
+        });
+
+        let mut expected: Vec<String> = (0..4)
+            .flat_map(|worker| {
+                [
+                    format!("worker {} detached", worker),
+                    format!("worker {} waited", worker),
+                ]
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(transport.wait_for(8), expected);
+
+        client.close(Duration::from_secs(1));
+        assert!(matches!(
+            client.capture_message(Level::Info, "too late"),
+            Err(SentryStrError::ClientClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn dropping_inside_a_runtime_closes_in_the_background() {
+        let transport = RecordingTransport::default();
+        let client = client(&transport);
+
+        client
+            .capture_event_detached(Event::new().with_message("before drop"))
+            .unwrap();
+        drop(client);
+
+        let delivered = tokio::task::spawn_blocking(move || transport.wait_for(1))
+            .await
+            .unwrap();
+        assert_eq!(delivered, vec!["before drop"]);
+    }
+}
@@ -1,10 +1,40 @@
+use crate::attachment::{ATTACHMENT_KIND, AttachmentRef};
+use crate::config::{validate_event_kind, validate_relay};
+use crate::dedup::Deduplicator;
+use crate::delivery::{MinRelaySuccess, RelayDeliveryStatus, RelayOutcome, deliver};
+use crate::pow::{self, PowPolicy};
+use crate::proxy::{build_client, check_onion_relays};
+use crate::queue::{EventQueue, OverflowPolicy, QueueStats, QueuedEvent};
+use crate::rate_limit::{RateLimit, TokenBucket};
+use crate::retry::{RetryPolicy, is_retryable};
+use crate::rt::Instant;
+use crate::sample::{self, SampleRng};
+use crate::scope::Scope;
+use crate::sequence::{SEQUENCE_EXTRA, SEQUENCE_TAG, SequenceCounter};
+use crate::transaction::TransactionGuard;
+use crate::transport::{Transport, TransportMetrics};
+use crate::truncate::{Truncation, limit_depth, nip44_len, truncate_event};
 use crate::{
-    Config, DirectMessageSender, EncryptionVersion, Event, MessageEvent, Result, SentryStrError,
-    validate_encryption_keys,
+    Breadcrumb, Config, ContentEncryption, DirectMessageSender, EncryptionVersion, Event, Level,
+    MessageEvent, Result, SentryStrError, default_fingerprint, validate_encryption_keys,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use nostr::prelude::*;
 use nostr_sdk::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+/// Upper bound on the serialized size of the breadcrumbs attached to one event. The
+/// oldest breadcrumbs are left out beyond it.
+const MAX_BREADCRUMB_BYTES: usize = 32 * 1024;
+
+/// Extra recording how far a clamped event's timestamp was from its `created_at`.
+const CLOCK_SKEW_EXTRA: &str = "clock_skew_ms";
 
 /// Main client for sending events to Nostr relays with optional direct messaging.
 ///
@@ -16,7 +46,7 @@ use nostr_sdk::prelude::*;
 ///
 /// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
 /// let keys = Keys::generate();
-/// let config = Config::new(keys.secret_key().display_secret().to_string(), vec!["wss://relay.damus.io".to_string()]);
+/// let config = Config::try_new(keys.secret_key().display_secret().to_string(), vec!["wss://relay.damus.io".to_string()])?;
 /// let client = NostrSentryClient::new(config).await?;
 ///
 /// let event = Event::new().with_message("Error occurred").with_level(Level::Error);
@@ -25,69 +55,786 @@ use nostr_sdk::prelude::*;
 /// # }
 /// ```
 pub struct NostrSentryClient {
+    inner: Arc<ClientInner>,
+}
+
+/// The state of a [`NostrSentryClient`], shared with its deduplication timer.
+struct ClientInner {
     client: Client,
+    publisher: Publisher,
     config: Config,
     keys: Keys,
-    dm_sender: Option<DirectMessageSender>,
+    dm_sender: Arc<RwLock<Option<DirectMessageSender>>>,
+    /// `Config::tags` and those added with [`NostrSentryClient::add_tag`].
+    tags: RwLock<Vec<Tag>>,
+    /// Resolved once by `new`, see [`Config::resolve_release`].
+    release: Option<String>,
+    /// Resolved once by `new`, see [`Config::resolve_server_name`].
+    default_server_name: Option<String>,
+    breadcrumbs: Mutex<VecDeque<Breadcrumb>>,
+    scope: Mutex<Arc<Scope>>,
+    queue: Option<(Arc<EventQueue>, AbortHandle)>,
+    /// Set by [`Self::close`]; captures then fail with `SentryStrError::ClientClosed`.
+    closed: AtomicBool,
+    /// Draws sampling decisions unless `Config::sampler` is set.
+    rng: SampleRng,
+    /// Set with `Config::with_sequence_numbers`.
+    sequence: Option<SequenceCounter>,
+    /// Set with `Config::with_dedup`.
+    dedup: Option<Deduplicator>,
+    /// Captures running right now, awaited by [`Self::close`].
+    captures: AtomicUsize,
+    /// Signalled when the last running capture finishes.
+    idle: Notify,
+    /// Sends to additional transports running in the background in queued mode.
+    forwards: Arc<BackgroundForwards>,
 }
 
 impl NostrSentryClient {
     /// Creates a new NostrSentryClient with the given configuration.
     ///
-    /// This will connect to all specified relays automatically.
+    /// This will connect to the write relays of the configuration automatically; read-only
+    /// relays are left to the collector.
     pub async fn new(config: Config) -> Result<Self> {
+        validate_event_kind(config.event_kind).map_err(SentryStrError::Config)?;
         let keys = config.get_keys()?;
-        let client = Client::new(keys.clone());
+        let write_relays = config.write_relays();
+        if write_relays.is_empty() {
+            return Err(SentryStrError::Config(
+                "At least one write relay is required".to_string(),
+            ));
+        }
+        check_onion_relays(&write_relays, config.proxy.as_ref())?;
+        // Events dropped from the queue were numbered, so collectors would report them
+        // as lost by relays.
+        if config.sequence_numbers
+            && config
+                .queue
+                .as_ref()
+                .is_some_and(|queue| queue.overflow == OverflowPolicy::DropOldest)
+        {
+            return Err(SentryStrError::Config(
+                "Sequence numbers need the Block queue overflow policy".to_string(),
+            ));
+        }
+        #[cfg(target_arch = "wasm32")]
+        check_wasm_support(&config)?;
+        let client = build_client(keys.clone(), config.proxy.as_ref()).await?;
 
-        for relay in &config.relays {
+        for relay in &write_relays {
             client.add_relay(relay).await?;
         }
 
         client.connect().await;
 
-        Ok(Self {
-            client,
-            config,
-            keys,
-            dm_sender: None,
-        })
+        // A limit of 0 events would hold the queue forever, so it means no limit.
+        let rate_limit = Self::resolve_rate_limit(&config)
+            .await
+            .filter(|limit| limit.max_events > 0);
+
+        let publisher = Publisher {
+            client: client.clone(),
+            relays: Arc::new(RwLock::new(write_relays)),
+            retry: config.retry.clone(),
+            timeout: config.publish_timeout,
+            min_relay_success: config.min_relay_success.unwrap_or_default(),
+            stats: Arc::new(PublishCounters::default()),
+            rate_limiter: rate_limit.map(|limit| Arc::new(TokenBucket::new(limit))),
+        };
+
+        // Shared with the queue, which sends the DMs of replayed events with it.
+        let dm_sender = Arc::new(RwLock::new(None));
+        let queue = match config.queue.clone() {
+            Some(queue_config) => {
+                let queue = Arc::new(EventQueue::new(queue_config)?);
+                let task = queue.spawn(publisher.clone(), Arc::clone(&dm_sender));
+                Some((queue, task))
+            }
+            None => None,
+        };
+
+        let sequence = if config.sequence_numbers {
+            Some(SequenceCounter::load(config.sequence_state.clone())?)
+        } else {
+            None
+        };
+
+        let dedup = config
+            .dedup_window
+            .map(|window| Deduplicator::new(window, config.dedup_max_entries));
+
+        let release = config.resolve_release();
+        let default_server_name = config.resolve_server_name();
+
+        let client = Self {
+            inner: Arc::new(ClientInner {
+                client,
+                publisher,
+                tags: RwLock::new(config.tags.clone().unwrap_or_default()),
+                config,
+                keys,
+                dm_sender,
+                release,
+                default_server_name,
+                breadcrumbs: Mutex::new(VecDeque::new()),
+                scope: Mutex::new(Arc::default()),
+                queue,
+                closed: AtomicBool::new(false),
+                rng: SampleRng::from_clock(),
+                sequence,
+                dedup,
+                captures: AtomicUsize::new(0),
+                idle: Notify::new(),
+                forwards: Arc::default(),
+            }),
+        };
+        if let Some(window) = client.inner.config.dedup_window {
+            client.spawn_dedup_timer(window);
+        }
+        Ok(client)
+    }
+
+    /// Publishes the summaries of closed deduplication windows every `window`, so they
+    /// go out without waiting for another capture. Stops once the client is closed or
+    /// dropped.
+    fn spawn_dedup_timer(&self, window: Duration) {
+        // Every capture closes the windows then, so there is nothing to wait for.
+        if window.is_zero() {
+            return;
+        }
+        let inner = Arc::downgrade(&self.inner);
+        crate::rt::spawn(async move {
+            let mut ticks = crate::rt::interval(window);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(inner) = inner.upgrade() else {
+                    return;
+                };
+                let client = NostrSentryClient { inner };
+                if client.inner.closed.load(Ordering::SeqCst) {
+                    return;
+                }
+                if let Some(ref dedup) = client.inner.dedup {
+                    client.send_summaries(dedup.close_expired()).await;
+                }
+            }
+        });
+    }
+
+    /// Records a breadcrumb, dropping the oldest once `Config::max_breadcrumbs` are held.
+    pub fn add_breadcrumb(&self, breadcrumb: Breadcrumb) {
+        let max = self.inner.config.max_breadcrumbs;
+        if max == 0 {
+            return;
+        }
+
+        let mut breadcrumbs = self.inner.breadcrumbs.lock().unwrap();
+        while breadcrumbs.len() >= max {
+            breadcrumbs.pop_front();
+        }
+        breadcrumbs.push_back(breadcrumb);
+    }
+
+    /// The newest breadcrumbs, oldest first, as attached to the next event: at most
+    /// `Config::max_breadcrumbs` and at most `MAX_BREADCRUMB_BYTES` serialized.
+    pub fn breadcrumbs(&self) -> Vec<Breadcrumb> {
+        let breadcrumbs = self.inner.breadcrumbs.lock().unwrap();
+
+        let mut size = 0;
+        let mut attached: Vec<Breadcrumb> = breadcrumbs
+            .iter()
+            .rev()
+            .take_while(|breadcrumb| {
+                size += serde_json::to_vec(breadcrumb).map_or(0, |json| json.len());
+                size <= MAX_BREADCRUMB_BYTES
+            })
+            .cloned()
+            .collect();
+        attached.reverse();
+        attached
+    }
+
+    pub fn clear_breadcrumbs(&self) {
+        self.inner.breadcrumbs.lock().unwrap().clear();
+    }
+
+    /// Kind of the events this client publishes, see [`Config::with_event_kind`].
+    pub fn event_kind(&self) -> u16 {
+        self.inner.config.event_kind
+    }
+
+    /// Release given to events without one: `Config::release`, or with
+    /// `Config::with_auto_release` the one detected at startup.
+    pub fn release(&self) -> Option<&str> {
+        self.inner.release.as_deref()
+    }
+
+    /// Server name given to events without one: `Config::server_name`, else the hostname
+    /// detected at startup unless `Config::with_auto_server_name(false)`.
+    pub fn default_server_name(&self) -> Option<&str> {
+        self.inner.default_server_name.as_deref()
+    }
+
+    /// Changes the client's scope, merged into every event it captures from any task.
+    /// For context of a single task, such as a request, use [`crate::with_scope`].
+    ///
+    /// ```rust,no_run
+    /// # fn example(client: &sentrystr::NostrSentryClient) {
+    /// client.configure_scope(|scope| {
+    ///     scope.set_tag("region", "eu-west-1");
+    ///     scope.set_extra("build", serde_json::json!(42));
+    /// });
+    /// # }
+    /// ```
+    pub fn configure_scope(&self, configure: impl FnOnce(&mut Scope)) {
+        let mut scope = self.inner.scope.lock().unwrap();
+        configure(Arc::make_mut(&mut scope));
+    }
+
+    /// Publishes `event`. Unless it already carries breadcrumbs, a snapshot of the
+    /// client's breadcrumbs is attached; the buffer is kept for later events. Tags,
+    /// extras and the user of the task's scope, then of the client's scope, fill in what
+    /// the event does not set. Events without a fingerprint get one from
+    /// `Config::fingerprinter`, and the environment, release, server name and user from
+    /// `Config` unless they set their own; the server name defaults to the hostname, see
+    /// [`Self::default_server_name`]. Events larger than `Config::max_event_bytes` are
+    /// truncated before signing.
+    ///
+    /// Returns what became of the event: [`CaptureOutcome::Sent`] with the id of the
+    /// signed Nostr event and what each relay did with it, or why it was dropped before
+    /// publishing. Events dropped by `Config::with_sample_rate` are not processed at all
+    /// and give `CaptureOutcome::Sampled`, and repeats suppressed by `Config::with_dedup`
+    /// give `CaptureOutcome::Deduplicated`. In queued mode (`Config::with_queue`) the
+    /// signed event is queued and its id returned right away; publish errors are then
+    /// only counted in [`Self::queue_stats`].
+    pub async fn capture_event(&self, mut event: Event) -> Result<CaptureOutcome> {
+        let _capture = RunningCapture::start(self);
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return Err(SentryStrError::ClientClosed);
+        }
+        self.inner
+            .publisher
+            .stats
+            .captured
+            .fetch_add(1, Ordering::Relaxed);
+
+        if let Some(ref dedup) = self.inner.dedup
+            && !event.force_send
+        {
+            // The first event of a window is the base of its summary, published later
+            // from another task, so it keeps the scope it was captured in.
+            Scope::current().apply_to(&mut event);
+            let (duplicate, summaries) = dedup.observe(&event);
+            self.send_summaries(summaries).await;
+            if duplicate {
+                self.inner
+                    .publisher
+                    .stats
+                    .deduplicated
+                    .fetch_add(1, Ordering::Relaxed);
+                return Ok(CaptureOutcome::Deduplicated);
+            }
+        }
+
+        if !event.force_send && !self.sampled_in(&event.level) {
+            self.inner
+                .publisher
+                .stats
+                .sampled
+                .fetch_add(1, Ordering::Relaxed);
+            return Ok(CaptureOutcome::Sampled);
+        }
+
+        // Queued events wait for the rate limit in the queue instead.
+        if self.inner.queue.is_none()
+            && !self
+                .inner
+                .publisher
+                .try_acquire(1 + event.attachments.len())
+        {
+            return Ok(CaptureOutcome::Throttled);
+        }
+
+        let result = self.capture(event).await;
+        // Publish failures are recorded where they happen, which covers queued events.
+        if let Err(ref e) = result
+            && !matches!(
+                e,
+                SentryStrError::PublishFailed { .. } | SentryStrError::PartialDelivery { .. }
+            )
+        {
+            self.inner.publisher.stats.record_failure(e);
+        }
+        result
+    }
+
+    /// What each relay did with the event; dropped events give a receipt with the
+    /// all-zeros id and no relays.
+    #[deprecated(note = "`capture_event` returns the outcome; use `CaptureOutcome::receipt`")]
+    pub async fn capture_event_with_receipt(&self, event: Event) -> Result<PublishReceipt> {
+        self.capture_event(event)
+            .await
+            .map(|outcome| match outcome {
+                CaptureOutcome::Sent(receipt) => receipt,
+                CaptureOutcome::Sampled
+                | CaptureOutcome::Throttled
+                | CaptureOutcome::Filtered
+                | CaptureOutcome::Deduplicated => PublishReceipt::dropped(),
+            })
+    }
+
+    #[deprecated(note = "`capture_event` returns the outcome now")]
+    pub async fn capture_event_with_outcome(&self, event: Event) -> Result<CaptureOutcome> {
+        self.capture_event(event).await
+    }
+
+    /// The configured rate limit, made stricter by the NIP-11 limits of the relays with
+    /// `Config::with_auto_rate_limit`.
+    async fn resolve_rate_limit(config: &Config) -> Option<RateLimit> {
+        if !config.auto_rate_limit {
+            return config.rate_limit;
+        }
+
+        #[cfg(feature = "nip11")]
+        {
+            let advertised = crate::rate_limit::fetch_nip11_limit(&config.write_relays()).await;
+            match (config.rate_limit, advertised) {
+                (Some(limit), Some(advertised)) => Some(limit.stricter(advertised)),
+                (limit, advertised) => limit.or(advertised),
+            }
+        }
+        #[cfg(not(feature = "nip11"))]
+        {
+            eprintln!("Config::with_auto_rate_limit needs the nip11 feature; ignoring it");
+            config.rate_limit
+        }
+    }
+
+    /// The rate limit the client enforces, if any.
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        self.inner
+            .publisher
+            .rate_limiter
+            .as_ref()
+            .map(|bucket| bucket.limit())
+    }
+
+    /// Publishes the summaries of closed deduplication windows, bypassing sampling and
+    /// the rate limit. They carry the scope of the event they summarize, and nothing
+    /// from the scope of the task that happens to publish them.
+    async fn send_summaries(&self, summaries: Vec<Event>) {
+        if summaries.is_empty() {
+            return;
+        }
+        let send = async {
+            for summary in summaries {
+                if let Err(e) = self.capture(summary).await {
+                    eprintln!("Failed to publish deduplication summary: {}", e);
+                }
+            }
+        };
+        crate::scope::with_scope(Scope::clear, send).await;
+    }
+
+    /// Whether an event at `level` survives sampling.
+    fn sampled_in(&self, level: &Level) -> bool {
+        let rate = self.inner.config.sample_rate_for(level);
+        if rate >= 1.0 {
+            return true;
+        }
+        let draw = match self.inner.config.sampler {
+            Some(sampler) => sampler(),
+            None => self.inner.rng.next(),
+        };
+        sample::keep(rate, draw)
+    }
+
+    async fn capture(&self, mut event: Event) -> Result<CaptureOutcome> {
+        self.check_attachments(&event)?;
+
+        Scope::current().apply_to(&mut event);
+        let client_scope = Arc::clone(&self.inner.scope.lock().unwrap());
+        client_scope.apply_to(&mut event);
+
+        if event.environment.is_none() {
+            event.environment = self.inner.config.environment.clone();
+        }
+        if event.release.is_none() {
+            event.release = self.inner.release.clone();
+        }
+        if event.server_name.is_none() {
+            event.server_name = self.inner.default_server_name.clone();
+        }
+        if event.user.is_none() {
+            event.user = self.inner.config.user.clone();
+        }
+
+        if event.breadcrumbs.is_empty() {
+            event.breadcrumbs = self.breadcrumbs();
+        }
+
+        if event.fingerprint.is_none() {
+            let fingerprinter = self
+                .inner
+                .config
+                .fingerprinter
+                .unwrap_or(default_fingerprint);
+            event.fingerprint = Some(fingerprinter(&event));
+        }
+
+        if let Some(ref before_send) = self.inner.config.before_send {
+            match before_send.apply(event) {
+                Ok(Some(kept)) => event = kept,
+                Ok(None) => {
+                    self.inner
+                        .publisher
+                        .stats
+                        .filtered
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Ok(CaptureOutcome::Filtered);
+                }
+                Err(panic) => {
+                    self.inner
+                        .publisher
+                        .stats
+                        .before_send_panics
+                        .fetch_add(1, Ordering::Relaxed);
+                    return Err(SentryStrError::BeforeSendPanicked(panic));
+                }
+            }
+        }
+
+        // Other transports get the event as the hook left it, before any Nostr limit.
+        let forwarded =
+            (!self.inner.config.additional_transports.is_empty()).then(|| event.clone());
+
+        let created_at = self.created_at(&mut event);
+
+        let expires_at = self.expiration(&event)?;
+        let attachments = self.build_attachment_events(&mut event, expires_at)?;
+
+        for value in event.extra.values_mut() {
+            limit_depth(value, self.inner.config.max_extra_depth);
+        }
+        if let Some(truncation) =
+            truncate_event(&mut event, self.inner.config.max_event_bytes, |json_len| {
+                self.content_len(json_len)
+            })?
+        {
+            self.inner.publisher.stats.record_truncation(truncation);
+        }
+
+        // Numbered last, so events dropped above leave no gap.
+        if let Some(ref sequence) = self.inner.sequence {
+            let seq = sequence.next();
+            event
+                .extra
+                .insert(SEQUENCE_EXTRA.to_string(), serde_json::json!(seq));
+            event.nostr_tags.push(Tag::custom(
+                TagKind::custom(SEQUENCE_TAG),
+                [seq.to_string()],
+            ));
+        }
+
+        let mut nostr_event = self.build_nostr_event(&event, expires_at, created_at)?;
+
+        if let Some(ref pow_config) = self.inner.config.pow
+            && let Some(difficulty) = pow_config.difficulty_for(&event.level)
+        {
+            match pow::mine(
+                &self.inner.keys,
+                nostr_event.clone(),
+                difficulty,
+                pow_config.time_budget,
+            )
+            .await?
+            {
+                Some(mined) => nostr_event = mined,
+                None if pow_config.policy == PowPolicy::Fail => {
+                    return Err(SentryStrError::PowTimeout {
+                        difficulty,
+                        budget: pow_config.time_budget,
+                    });
+                }
+                None => {}
+            }
+        }
+
+        let mut receipt = PublishReceipt {
+            event_id: nostr_event.id,
+            relays: Vec::new(),
+            expires_at,
+            pow_difficulty: pow::achieved_difficulty(&nostr_event.id),
+        };
+
+        if let Some((ref queue, _)) = self.inner.queue {
+            // Queued captures return once the event is queued, so a slow transport must
+            // not hold them either.
+            if let Some(forwarded) = forwarded {
+                self.forward_in_background(forwarded, queue.max_pending());
+            }
+            queue
+                .push(QueuedEvent {
+                    nostr_event,
+                    attachments,
+                    event,
+                    dm_sender: self.dm_sender(),
+                    replay_dm: false,
+                    journal_id: None,
+                })
+                .await;
+            return Ok(CaptureOutcome::Sent(receipt));
+        }
+
+        let dm_sender = self.dm_sender();
+        let publish =
+            self.inner
+                .publisher
+                .publish(dm_sender.as_ref(), &attachments, &nostr_event, event);
+        let forward = async {
+            if let Some(ref forwarded) = forwarded {
+                forward(
+                    &self.inner.config.additional_transports,
+                    &self.inner.publisher.stats,
+                    forwarded,
+                )
+                .await;
+            }
+        };
+        let (published, ()) = tokio::join!(publish, forward);
+        receipt.relays = published?;
+        Ok(CaptureOutcome::Sent(receipt))
+    }
+
+    /// Sends `event` to the additional transports from a background task. With `limit`
+    /// sends already running, the event is counted as failed for every transport instead,
+    /// so a stalled destination cannot pile up tasks.
+    fn forward_in_background(&self, event: Event, limit: usize) {
+        let Some(running) = RunningForward::start(&self.inner.forwards, limit) else {
+            for transport in &self.inner.config.additional_transports {
+                let error = SentryStrError::Publishing(format!(
+                    "{} events are already being forwarded",
+                    limit
+                ));
+                eprintln!("Failed to send event to {}: {}", transport.name(), error);
+                self.inner
+                    .publisher
+                    .stats
+                    .record_transport(transport.name(), &Err(error));
+            }
+            return;
+        };
+
+        let transports = self.inner.config.additional_transports.clone();
+        let stats = Arc::clone(&self.inner.publisher.stats);
+        crate::rt::spawn(async move {
+            forward(&transports, &stats, &event).await;
+            drop(running);
+        });
+    }
+
+    /// Fails when an attachment of `event` exceeds `Config::max_attachment_bytes` or all
+    /// of them exceed `Config::max_attachments_bytes`.
+    fn check_attachments(&self, event: &Event) -> Result<()> {
+        let mut total = 0;
+        for attachment in &event.attachments {
+            let size = attachment.bytes.len();
+            if size > self.inner.config.max_attachment_bytes {
+                return Err(SentryStrError::AttachmentTooLarge {
+                    name: attachment.name.clone(),
+                    size,
+                    max: self.inner.config.max_attachment_bytes,
+                });
+            }
+            total += size;
+        }
+
+        if total > self.inner.config.max_attachments_bytes {
+            return Err(SentryStrError::AttachmentsTooLarge {
+                size: total,
+                max: self.inner.config.max_attachments_bytes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Signs a companion event for each attachment of `event`, encrypted like the event
+    /// and expiring with it, and records them in `event.attachment_refs`.
+    fn build_attachment_events(
+        &self,
+        event: &mut Event,
+        expiration: Option<Timestamp>,
+    ) -> Result<Vec<nostr::Event>> {
+        let attachments = std::mem::take(&mut event.attachments);
+        let mut companions = Vec::with_capacity(attachments.len());
+
+        for attachment in attachments {
+            let (content, mut tags) = self.seal_attachment(attachment.encode())?;
+            tags.extend(attachment.tags(&event.event_id));
+            tags.extend(expiration.map(Tag::expiration));
+
+            let companion = EventBuilder::new(Kind::Custom(ATTACHMENT_KIND), content)
+                .tags(tags)
+                .sign_with_keys(&self.inner.keys)?;
+            event.attachment_refs.push(AttachmentRef {
+                event_id: companion.id.to_hex(),
+                name: attachment.name,
+                mime: attachment.mime,
+                size: attachment.bytes.len(),
+            });
+            companions.push(companion);
+        }
+        Ok(companions)
+    }
+
+    /// Length of the content published for an event JSON of `json_len` bytes, encrypted
+    /// the way [`Self::build_nostr_event`] encrypts it.
+    fn content_len(&self, json_len: usize) -> usize {
+        if let Some(ContentEncryption::Nip44 { ref reader_pubkeys }) =
+            self.inner.config.content_encryption
+        {
+            // {"<hex key>":"<ciphertext>",...}, one entry per reader.
+            let readers = reader_pubkeys.len().max(1);
+            return 2 + readers * (66 + 1 + 2 + nip44_len(json_len)) + (readers - 1);
+        }
+        if self.inner.config.encrypt_events && self.inner.config.recipient_pubkey.is_some() {
+            return nip44_len(json_len);
+        }
+        json_len
+    }
+
+    /// Encrypts the content of a companion event the way the event itself is, returning
+    /// the content and the tags describing the encryption.
+    fn seal_attachment(&self, content: String) -> Result<(String, Vec<Tag>)> {
+        if let Some(ref encryption) = self.inner.config.content_encryption {
+            return Ok((
+                encryption.encrypt(&self.inner.keys, &content)?,
+                vec![encryption.tag()],
+            ));
+        }
+
+        if self.inner.config.encrypt_events
+            && let Some(recipient_pubkey) = self.inner.config.get_recipient_pubkey()?
+        {
+            let encrypted_content = nostr::nips::nip44::encrypt(
+                self.inner.keys.secret_key(),
+                &recipient_pubkey,
+                &content,
+                nostr::nips::nip44::Version::V2,
+            )?;
+            return Ok((encrypted_content, Vec::new()));
+        }
+
+        Ok((content, Vec::new()))
+    }
+
+    /// The expiration of `event` under `Config::event_ttl` and `Config::level_ttls`,
+    /// counted from the event's timestamp.
+    fn expiration(&self, event: &Event) -> Result<Option<Timestamp>> {
+        let Some(ttl) = self.inner.config.ttl_for(&event.level) else {
+            return Ok(None);
+        };
+
+        u64::try_from(event.timestamp.timestamp())
+            .ok()
+            .and_then(|timestamp| timestamp.checked_add(ttl.as_secs()))
+            .map(|expires_at| Some(Timestamp::from_secs(expires_at)))
+            .ok_or_else(|| {
+                SentryStrError::Config(format!(
+                    "Event TTL of {}s overflows the expiration timestamp",
+                    ttl.as_secs()
+                ))
+            })
     }
 
-    pub async fn capture_event(&self, event: Event) -> Result<EventId> {
-        let content = serde_json::to_string(&event)?;
+    /// The Nostr `created_at` of `event`: its timestamp, clamped into the window of
+    /// `Config::with_timestamp_window` around now. A clamped event gets the difference in
+    /// its `clock_skew_ms` extra; its own timestamp is left as it was.
+    fn created_at(&self, event: &mut Event) -> Timestamp {
+        let now = Utc::now();
+        let earliest = chrono::Duration::from_std(self.inner.config.max_past_skew)
+            .ok()
+            .and_then(|skew| now.checked_sub_signed(skew))
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let latest = chrono::Duration::from_std(self.inner.config.max_future_skew)
+            .ok()
+            .and_then(|skew| now.checked_add_signed(skew))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
 
-        let nostr_event = if self.config.encrypt_events {
-            match self.config.encryption_version {
+        let clamped = event.timestamp.clamp(earliest, latest.max(earliest));
+        if clamped != event.timestamp {
+            let skew = (event.timestamp - clamped).num_milliseconds();
+            event
+                .extra
+                .insert(CLOCK_SKEW_EXTRA.to_string(), serde_json::json!(skew));
+        }
+        Timestamp::from_secs(u64::try_from(clamped.timestamp()).unwrap_or_default())
+    }
+
+    /// Signs `event` as the Nostr event to publish, encrypted as configured and with the
+    /// configured tags, plus an `expiration` tag (NIP-40) when `expiration` is set.
+    fn build_nostr_event(
+        &self,
+        event: &Event,
+        expiration: Option<Timestamp>,
+        created_at: Timestamp,
+    ) -> Result<nostr::Event> {
+        let content = serde_json::to_string(event)?;
+
+        let nostr_event = if let Some(ref encryption) = self.inner.config.content_encryption {
+            let encrypted_content = encryption.encrypt(&self.inner.keys, &content)?;
+            let mut builder = EventBuilder::new(
+                Kind::Custom(self.inner.config.event_kind),
+                encrypted_content,
+            );
+
+            let mut all_tags = event.nostr_tags.clone();
+            all_tags.extend(self.configured_nostr_tags(event, false));
+            all_tags.push(encryption.tag());
+            all_tags.extend(expiration.map(Tag::expiration));
+            all_tags.extend(self.plaintext_tags(event));
+            all_tags.extend(self.inner.tags.read().unwrap().iter().cloned());
+            builder = builder.tags(all_tags);
+
+            builder
+                .custom_created_at(created_at)
+                .sign_with_keys(&self.inner.keys)?
+        } else if self.inner.config.encrypt_events {
+            match self.inner.config.encryption_version {
                 EncryptionVersion::None => {
                     return Err(SentryStrError::Config(
                         "Encryption enabled but version not specified".to_string(),
                     ));
                 }
                 EncryptionVersion::Nip44V2 => {
-                    if let Some(recipient_pubkey) = self.config.get_recipient_pubkey()? {
-                        validate_encryption_keys(&self.keys, &recipient_pubkey)?;
+                    if let Some(recipient_pubkey) = self.inner.config.get_recipient_pubkey()? {
+                        validate_encryption_keys(&self.inner.keys, &recipient_pubkey)?;
 
                         let encrypted_content = nostr::nips::nip44::encrypt(
-                            self.keys.secret_key(),
+                            self.inner.keys.secret_key(),
                             &recipient_pubkey,
                             &content,
                             nostr::nips::nip44::Version::V2,
                         )?;
 
                         let mut builder = EventBuilder::new(
-                            Kind::Custom(self.config.event_kind),
+                            Kind::Custom(self.inner.config.event_kind),
                             encrypted_content,
                         );
 
+                        // No fingerprint tag: it would expose the message in plaintext.
                         let mut all_tags = event.nostr_tags.clone();
-                        if let Some(ref config_tags) = self.config.tags {
-                            all_tags.extend(config_tags.clone());
-                        }
+                        all_tags.extend(self.configured_nostr_tags(event, false));
+                        all_tags.extend(expiration.map(Tag::expiration));
+                        all_tags.extend(self.inner.tags.read().unwrap().iter().cloned());
                         if !all_tags.is_empty() {
                             builder = builder.tags(all_tags);
                         }
 
-                        builder.sign_with_keys(&self.keys)?
+                        builder
+                            .custom_created_at(created_at)
+                            .sign_with_keys(&self.inner.keys)?
                     } else {
                         return Err(SentryStrError::Config(
                             "Encryption enabled but no recipient public key provided".to_string(),
@@ -96,77 +843,406 @@ impl NostrSentryClient {
                 }
             }
         } else {
-            let mut builder = EventBuilder::new(Kind::Custom(self.config.event_kind), content);
+            let mut builder =
+                EventBuilder::new(Kind::Custom(self.inner.config.event_kind), content);
 
             let mut all_tags = event.nostr_tags.clone();
-            if let Some(ref config_tags) = self.config.tags {
-                all_tags.extend(config_tags.clone());
-            }
+            all_tags.extend(self.configured_nostr_tags(event, true));
+            all_tags.extend(event.fingerprint_tag());
+            all_tags.extend(expiration.map(Tag::expiration));
+            all_tags.extend(self.inner.tags.read().unwrap().iter().cloned());
             if !all_tags.is_empty() {
                 builder = builder.tags(all_tags);
             }
 
-            builder.sign_with_keys(&self.keys)?
+            builder
+                .custom_created_at(created_at)
+                .sign_with_keys(&self.inner.keys)?
         };
 
-        let output = self.client.send_event(&nostr_event).await?;
+        Ok(nostr_event)
+    }
 
-        // Send direct message if configured
-        if let Some(ref dm_sender) = self.dm_sender {
-            let message_event = MessageEvent {
-                event: event.clone(),
-                author: self.keys.public_key(),
-                nostr_event_id: output.val,
-                received_at: Utc::now(),
-            };
+    /// The `Config::with_nostr_tag` tags. For `plaintext` events, with the values of
+    /// `event` where it has the same keys, plus the ones of
+    /// `Config::with_standard_nostr_tags`; encrypted events keep the configured values,
+    /// so nothing of the encrypted content shows in its tags. Keys `event.nostr_tags`
+    /// already holds are left to it.
+    fn configured_nostr_tags(&self, event: &Event, plaintext: bool) -> Vec<Tag> {
+        let mut tags = self.inner.config.nostr_tags.clone();
+        if plaintext {
+            for (key, value) in tags.iter_mut() {
+                if let Some(event_value) = event.tags.get(key) {
+                    value.clone_from(event_value);
+                }
+            }
+        }
 
-            if let Err(e) = dm_sender.send_message_for_event(&message_event).await {
-                eprintln!("Failed to send direct message: {}", e);
+        if plaintext && self.inner.config.standard_nostr_tags {
+            for key in ["service", "component"] {
+                if let Some(value) = event.tags.get(key) {
+                    tags.insert(key.to_string(), value.clone());
+                }
+            }
+            if let Some(ref environment) = event.environment {
+                tags.insert("env".to_string(), environment.clone());
             }
+            tags.insert("severity".to_string(), event.level.as_str().to_string());
         }
 
-        Ok(output.val)
+        tags.into_iter()
+            .filter(|(key, _)| {
+                !event
+                    .nostr_tags
+                    .iter()
+                    .any(|tag| tag.as_slice().first() == Some(key))
+            })
+            .map(|(key, value)| Tag::custom(TagKind::custom(key), [value]))
+            .collect()
+    }
+
+    /// [`crate::PLAINTEXT_TAG`]s for the fields of `event` named in
+    /// `Config::plaintext_tags`.
+    fn plaintext_tags(&self, event: &Event) -> Vec<Tag> {
+        self.inner
+            .config
+            .plaintext_tags
+            .iter()
+            .filter_map(|name| {
+                let value = match name.as_str() {
+                    "level" => event.level.to_string(),
+                    _ => event.tags.get(name)?.clone(),
+                };
+                Some(Tag::custom(
+                    TagKind::custom(crate::PLAINTEXT_TAG),
+                    [crate::plaintext_tag_value(name, &value)],
+                ))
+            })
+            .collect()
+    }
+
+    /// Publishes everything queued and waits up to `timeout` for it to go out, and for
+    /// the sends to additional transports running in the background. Returns how many
+    /// events are still pending, always 0 when the client is not queued.
+    pub async fn flush(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let pending = match self.inner.queue {
+            Some((ref queue, _)) => queue.flush(timeout).await,
+            None => 0,
+        };
+        self.inner.forwards.wait(deadline).await;
+        pending
+    }
+
+    /// Counters of publish attempts, including retries.
+    pub fn publish_stats(&self) -> PublishStats {
+        self.inner.publisher.stats.snapshot()
+    }
+
+    /// Snapshot of the client's counters, per-relay outcomes and last error.
+    pub fn metrics(&self) -> ClientMetrics {
+        let queue_depth = self
+            .inner
+            .queue
+            .as_ref()
+            .map_or(0, |(queue, _)| queue.stats().pending);
+        self.inner.publisher.stats.metrics(queue_depth)
+    }
+
+    /// Counters of the queue, or `None` when the client publishes directly.
+    pub fn queue_stats(&self) -> Option<QueueStats> {
+        self.inner.queue.as_ref().map(|(queue, _)| queue.stats())
+    }
+
+    /// Captures `message` at `level`. The event goes through [`Self::capture_event`], so
+    /// it gets the same client defaults (environment, release, server name, user,
+    /// breadcrumbs and fingerprint).
+    pub async fn capture_message(
+        &self,
+        level: Level,
+        message: impl Into<String>,
+    ) -> Result<CaptureOutcome> {
+        self.capture_message_with_tags(level, message, std::iter::empty())
+            .await
     }
 
-    pub async fn capture_message(&self, message: impl Into<String>) -> Result<EventId> {
-        let event = Event::new().with_message(message);
+    /// [`Self::capture_message`] with event tags, e.g. `[("job".to_string(), "sync".to_string())]`.
+    pub async fn capture_message_with_tags(
+        &self,
+        level: Level,
+        message: impl Into<String>,
+        tags: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<CaptureOutcome> {
+        let mut event = Event::new().with_message(message).with_level(level);
+        event.tags.extend(tags);
         self.capture_event(event).await
     }
 
-    pub async fn capture_error(&self, error: impl Into<String>) -> Result<EventId> {
+    /// Captures an Error-level event with just a message. Use [`Self::capture_error`]
+    /// when an error value is at hand, to keep its cause chain.
+    pub async fn capture_error_message(&self, error: impl Into<String>) -> Result<CaptureOutcome> {
         let event = Event::new()
             .with_message(error)
-            .with_level(crate::event::Level::Error);
+            .with_level(crate::event::Level::Error)
+            .with_force_send();
         self.capture_event(event).await
     }
 
-    pub async fn disconnect(&self) -> Result<()> {
-        self.client.disconnect().await;
+    /// Captures `error` as an Error-level event with its `source()` chain and, when
+    /// `RUST_BACKTRACE` is set, the current stack. See [`Event::from_error`]. Like the
+    /// other `capture_error*` methods, it is exempt from sampling.
+    pub async fn capture_error(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+    ) -> Result<CaptureOutcome> {
+        self.capture_event(Event::from_error(error).with_force_send())
+            .await
+    }
+
+    /// [`Self::capture_error`] with the event passed through `customize` first, e.g. to
+    /// add tags or extras.
+    pub async fn capture_error_with(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+        customize: impl FnOnce(Event) -> Event,
+    ) -> Result<CaptureOutcome> {
+        self.capture_event(customize(Event::from_error(error)).with_force_send())
+            .await
+    }
+
+    /// Captures an `anyhow::Error` with its context chain and the backtrace anyhow
+    /// captured where the error was created.
+    #[cfg(feature = "anyhow")]
+    pub async fn capture_anyhow(&self, error: &anyhow::Error) -> Result<CaptureOutcome> {
+        self.capture_event(Event::from_anyhow(error).with_force_send())
+            .await
+    }
+
+    /// Starts timing the transaction `name`. Finishing or dropping the guard captures an
+    /// [`Event::transaction_event`] with the time since this call.
+    ///
+    /// Takes the client in an `Arc`, which the guard keeps so that it can capture on drop,
+    /// after the caller's borrow ended; a client owned directly is wrapped with
+    /// `Arc::new` first.
+    pub fn start_transaction(self: &Arc<Self>, name: impl Into<String>) -> TransactionGuard {
+        TransactionGuard::new(Arc::clone(self), name.into())
+    }
+
+    /// Adds `relay` (validated like `Config::try_new` relays) and connects to it. Later
+    /// publishes, including retries and queued events, go to it as well. Adding a relay
+    /// already in use does nothing.
+    pub async fn add_relay(&self, relay: &str) -> Result<()> {
+        let url = validate_relay(relay).map_err(SentryStrError::Config)?;
+        check_onion_relays([&url], self.inner.config.proxy.as_ref())?;
+        if self.inner.publisher.relays.read().unwrap().contains(&url) {
+            return Ok(());
+        }
+
+        self.inner.client.add_relay(&url).await?;
+        self.inner.client.connect_relay(&url).await?;
+        let mut relays = self.inner.publisher.relays.write().unwrap();
+        if !relays.contains(&url) {
+            relays.push(url);
+        }
+        Ok(())
+    }
+
+    /// Stops publishing to `relay` and disconnects from it. From then on it no longer
+    /// counts toward `Config::with_min_relay_success`. The last relay cannot be removed.
+    pub async fn remove_relay(&self, relay: &str) -> Result<()> {
+        let url = validate_relay(relay).map_err(SentryStrError::Config)?;
+        {
+            let mut relays = self.inner.publisher.relays.write().unwrap();
+            let Some(index) = relays.iter().position(|known| *known == url) else {
+                return Err(SentryStrError::Config(format!(
+                    "Relay '{}' is not in use",
+                    url
+                )));
+            };
+            if relays.len() == 1 {
+                return Err(SentryStrError::Config(
+                    "Cannot remove the last relay".to_string(),
+                ));
+            }
+            relays.remove(index);
+        }
+
+        self.inner.client.remove_relay(&url).await?;
         Ok(())
     }
 
-    pub fn add_tag(&mut self, tag: Tag) {
-        match self.config.tags {
-            Some(ref mut tags) => tags.push(tag),
-            None => self.config.tags = Some(vec![tag]),
+    /// The relays events are published to, with their connection state.
+    pub async fn relays(&self) -> Vec<RelayInfo> {
+        let pool = self.inner.client.relays().await;
+        let relays = self.inner.publisher.relays.read().unwrap().clone();
+
+        relays
+            .into_iter()
+            .map(|url| {
+                let relay = pool
+                    .iter()
+                    .find(|(relay_url, _)| relay_url.to_string() == url)
+                    .map(|(_, relay)| relay);
+                RelayInfo {
+                    connected: relay.is_some_and(|relay| relay.is_connected()),
+                    status: relay
+                        .map_or_else(|| "Removed".to_string(), |relay| relay.status().to_string()),
+                    url,
+                }
+            })
+            .collect()
+    }
+
+    /// Health of each relay events are published to: its connection state from the
+    /// relay pool, and the publish outcomes the client recorded for it.
+    pub async fn relay_status(&self) -> Vec<RelayStatusInfo> {
+        let pool = self.inner.client.relays().await;
+        let relays = self.inner.publisher.relays.read().unwrap().clone();
+        let health = self.inner.publisher.stats.health.lock().unwrap().clone();
+
+        relays
+            .into_iter()
+            .map(|url| {
+                let relay = pool
+                    .iter()
+                    .find(|(relay_url, _)| relay_url.to_string() == url)
+                    .map(|(_, relay)| relay);
+                let connected_at = relay.map_or(0, |relay| relay.stats().connected_at().as_u64());
+                let health = health.get(&url).copied().unwrap_or_default();
+
+                RelayStatusInfo {
+                    connected: relay.is_some_and(|relay| relay.is_connected()),
+                    status: relay
+                        .map_or_else(|| "Removed".to_string(), |relay| relay.status().to_string()),
+                    connected_since: if connected_at > 0 {
+                        DateTime::from_timestamp(connected_at as i64, 0)
+                    } else {
+                        None
+                    },
+                    last_publish_ok_at: health.last_ok_at,
+                    consecutive_failures: health.consecutive_failures,
+                    url,
+                }
+            })
+            .collect()
+    }
+
+    /// Waits until at least `min_relays` relays are connected, or all of them when there
+    /// are fewer, e.g. at startup before the first capture. Returns how many are
+    /// connected, or [`SentryStrError::NotConnected`] when `timeout` passes first.
+    pub async fn wait_for_connection(&self, min_relays: usize, timeout: Duration) -> Result<usize> {
+        const POLL_INTERVAL: Duration = Duration::from_millis(100);
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let relays = self.relays().await;
+            let required = min_relays.min(relays.len());
+            let connected = relays.iter().filter(|relay| relay.connected).count();
+            if connected >= required {
+                return Ok(connected);
+            }
+            if Instant::now() >= deadline {
+                return Err(SentryStrError::NotConnected {
+                    connected,
+                    required,
+                    timeout,
+                });
+            }
+            crate::rt::sleep(POLL_INTERVAL.min(deadline - Instant::now())).await;
+        }
+    }
+
+    /// Shuts the client down: new captures fail with `SentryStrError::ClientClosed`,
+    /// running captures and the queue get up to `timeout` to deliver their events, open
+    /// deduplication windows are summarized, a DM digest is sent, and the relays are
+    /// disconnected. Closing again only disconnects.
+    pub async fn close(&self, timeout: Duration) -> CloseReport {
+        let deadline = Instant::now() + timeout;
+        let published_before = self.inner.publisher.stats.published.load(Ordering::Relaxed);
+        self.inner.closed.store(true, Ordering::SeqCst);
+
+        loop {
+            let mut idle = pin!(self.inner.idle.notified());
+            idle.as_mut().enable();
+            if self.inner.captures.load(Ordering::SeqCst) == 0
+                || crate::rt::timeout_at(deadline, idle).await.is_err()
+            {
+                break;
+            }
+        }
+        let running = self.inner.captures.load(Ordering::SeqCst);
+        self.inner.forwards.wait(deadline).await;
+
+        if let Some(ref dedup) = self.inner.dedup {
+            let _ = crate::rt::timeout_at(deadline, self.send_summaries(dedup.drain())).await;
+        }
+
+        let pending = match self.inner.queue {
+            Some((ref queue, ref task)) => {
+                let pending = queue
+                    .flush(deadline.saturating_duration_since(Instant::now()))
+                    .await;
+                task.abort();
+                pending
+            }
+            None => 0,
+        };
+
+        if let Some(ref sequence) = self.inner.sequence {
+            sequence.flush(deadline).await;
+        }
+
+        if let Some(dm_sender) = self.dm_sender()
+            && let Ok(Err(e)) = crate::rt::timeout_at(deadline, dm_sender.flush()).await
+        {
+            eprintln!("Failed to send direct message digest: {}", e);
         }
+
+        self.inner.client.disconnect().await;
+
+        CloseReport {
+            delivered: self.inner.publisher.stats.published.load(Ordering::Relaxed)
+                - published_before,
+            abandoned: (running + pending) as u64,
+        }
+    }
+
+    /// Disconnects from the relays. Call [`Self::flush`] first in queued mode, or queued
+    /// events are lost; [`Self::close`] does both.
+    pub async fn disconnect(&self) -> Result<()> {
+        self.inner.client.disconnect().await;
+        Ok(())
+    }
+
+    /// Adds a tag to every event published from now on.
+    pub fn add_tag(&self, tag: Tag) {
+        self.inner.tags.write().unwrap().push(tag);
+    }
+
+    /// Public key events are signed with.
+    pub fn public_key(&self) -> PublicKey {
+        self.inner.keys.public_key()
     }
 
-    pub fn with_direct_messaging(mut self, dm_sender: DirectMessageSender) -> Self {
-        self.dm_sender = Some(dm_sender);
+    pub fn with_direct_messaging(self, dm_sender: DirectMessageSender) -> Self {
+        *self.inner.dm_sender.write().unwrap() = Some(dm_sender);
         self
     }
 
     pub fn set_direct_messaging(&mut self, dm_sender: DirectMessageSender) {
-        self.dm_sender = Some(dm_sender);
+        *self.inner.dm_sender.write().unwrap() = Some(dm_sender);
     }
 
     pub fn remove_direct_messaging(&mut self) {
-        self.dm_sender = None;
+        *self.inner.dm_sender.write().unwrap() = None;
+    }
+
+    fn dm_sender(&self) -> Option<DirectMessageSender> {
+        self.inner.dm_sender.read().unwrap().clone()
     }
 
     pub async fn send_direct_message(&self, content: &str) -> Result<()> {
-        if let Some(ref dm_sender) = self.dm_sender {
+        if let Some(dm_sender) = self.dm_sender() {
             dm_sender.send_custom_message(content).await
         } else {
             Err(SentryStrError::Config(
@@ -175,3 +1251,1932 @@ impl NostrSentryClient {
         }
     }
 }
+
+impl Drop for ClientInner {
+    fn drop(&mut self) {
+        if let Some((_, ref task)) = self.queue {
+            task.abort();
+        }
+    }
+}
+
+/// Sends `event` to `transports` in turn, recording the outcomes in `stats`.
+async fn forward(transports: &[Arc<dyn Transport>], stats: &PublishCounters, event: &Event) {
+    for transport in transports {
+        let result = transport.send(event).await;
+        if let Err(ref e) = result {
+            eprintln!("Failed to send event to {}: {}", transport.name(), e);
+        }
+        stats.record_transport(transport.name(), &result);
+    }
+}
+
+/// Background sends to additional transports, awaited by [`NostrSentryClient::flush`]
+/// and [`NostrSentryClient::close`].
+#[derive(Default)]
+struct BackgroundForwards {
+    running: AtomicUsize,
+    /// Signalled when the last running send finishes.
+    idle: Notify,
+}
+
+impl BackgroundForwards {
+    /// Waits until no send is running, or until `deadline`.
+    async fn wait(&self, deadline: Instant) {
+        loop {
+            let mut idle = pin!(self.idle.notified());
+            idle.as_mut().enable();
+            if self.running.load(Ordering::SeqCst) == 0
+                || crate::rt::timeout_at(deadline, idle).await.is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// Counts a background send as running while alive.
+struct RunningForward(Arc<BackgroundForwards>);
+
+impl RunningForward {
+    /// `None` when `limit` sends are running already.
+    fn start(forwards: &Arc<BackgroundForwards>, limit: usize) -> Option<Self> {
+        forwards
+            .running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                (running < limit).then_some(running + 1)
+            })
+            .ok()?;
+        Some(Self(Arc::clone(forwards)))
+    }
+}
+
+impl Drop for RunningForward {
+    fn drop(&mut self) {
+        if self.0.running.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Counts a capture as running for [`NostrSentryClient::close`] while alive.
+struct RunningCapture<'a>(&'a NostrSentryClient);
+
+impl<'a> RunningCapture<'a> {
+    fn start(client: &'a NostrSentryClient) -> Self {
+        client.inner.captures.fetch_add(1, Ordering::SeqCst);
+        Self(client)
+    }
+}
+
+impl Drop for RunningCapture<'_> {
+    fn drop(&mut self) {
+        if self.0.inner.captures.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.inner.idle.notify_waiters();
+        }
+    }
+}
+
+/// Fails on options a browser cannot provide: the queue publishes from a Tokio task, and
+/// sequence state is kept in a file.
+#[cfg(target_arch = "wasm32")]
+fn check_wasm_support(config: &Config) -> Result<()> {
+    let unsupported = if config.queue.is_some() {
+        "Queued mode"
+    } else if config.sequence_state.is_some() {
+        "Sequence state files"
+    } else {
+        return Ok(());
+    };
+    Err(SentryStrError::Config(format!(
+        "{} is not supported on wasm32",
+        unsupported
+    )))
+}
+
+/// What [`NostrSentryClient::close`] managed to deliver.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CloseReport {
+    /// Events published while closing, counting the companion events of attachments.
+    pub delivered: u64,
+    /// Captures still running and queued events left when the timeout passed.
+    pub abandoned: u64,
+}
+
+/// Health of a relay of the client, see [`NostrSentryClient::relay_status`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayStatusInfo {
+    pub url: String,
+    pub connected: bool,
+    /// The connection state, e.g. `Connected`, `Connecting` or `Disconnected`.
+    pub status: String,
+    pub connected_since: Option<DateTime<Utc>>,
+    /// When the relay last accepted an event from this client.
+    pub last_publish_ok_at: Option<DateTime<Utc>>,
+    /// Send attempts the relay rejected or left unanswered since it last accepted one.
+    pub consecutive_failures: u32,
+}
+
+/// Publish outcomes of one relay, behind [`RelayStatusInfo`].
+#[derive(Debug, Clone, Copy, Default)]
+struct RelayHealth {
+    last_ok_at: Option<DateTime<Utc>>,
+    consecutive_failures: u32,
+}
+
+/// A relay of the client, see [`NostrSentryClient::relays`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayInfo {
+    pub url: String,
+    pub connected: bool,
+    /// The connection state, e.g. `Connected`, `Connecting` or `Disconnected`.
+    pub status: String,
+}
+
+/// What became of an event passed to [`NostrSentryClient::capture_event`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    /// Published, or queued in queued mode.
+    Sent(PublishReceipt),
+    /// Dropped by `Config::with_sample_rate` without being published.
+    Sampled,
+    /// Dropped by `Config::with_rate_limit` without being published.
+    Throttled,
+    /// Dropped by the hook of `Config::with_before_send`.
+    Filtered,
+    /// Suppressed by `Config::with_dedup` as a repeat of an event published within the
+    /// window; counted in the summary published when the window closes.
+    Deduplicated,
+}
+
+impl CaptureOutcome {
+    /// Id of the published event; `None` when it was sampled out, throttled, filtered or
+    /// deduplicated.
+    pub fn event_id(&self) -> Option<EventId> {
+        self.receipt().map(|receipt| receipt.event_id)
+    }
+
+    pub fn receipt(&self) -> Option<&PublishReceipt> {
+        match self {
+            Self::Sent(receipt) => Some(receipt),
+            Self::Sampled | Self::Throttled | Self::Filtered | Self::Deduplicated => None,
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Self::Sampled)
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        matches!(self, Self::Throttled)
+    }
+
+    pub fn is_filtered(&self) -> bool {
+        matches!(self, Self::Filtered)
+    }
+
+    pub fn is_deduplicated(&self) -> bool {
+        matches!(self, Self::Deduplicated)
+    }
+}
+
+/// The event id, `sampled`, `throttled`, `filtered` or `deduplicated`.
+impl std::fmt::Display for CaptureOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sent(receipt) => write!(f, "{}", receipt.event_id),
+            Self::Sampled => f.write_str("sampled"),
+            Self::Throttled => f.write_str("throttled"),
+            Self::Filtered => f.write_str("filtered"),
+            Self::Deduplicated => f.write_str("deduplicated"),
+        }
+    }
+}
+
+/// What [`NostrSentryClient::capture_event`] published.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishReceipt {
+    /// Id of the signed Nostr event, as relays and the collector see it.
+    pub event_id: EventId,
+    /// What each relay did on the last send attempt. Empty in queued mode, where the
+    /// event is published after `capture_event` returns.
+    pub relays: Vec<RelayOutcome>,
+    /// When relays may drop the event, from `Config::with_event_ttl` or
+    /// `Config::with_level_ttl`.
+    pub expires_at: Option<Timestamp>,
+    /// Leading zero bits of the event id (NIP-13), mined with `Config::with_pow` or not.
+    pub pow_difficulty: u8,
+}
+
+impl PublishReceipt {
+    /// The receipt of an event dropped before publishing.
+    fn dropped() -> Self {
+        Self {
+            event_id: EventId::all_zeros(),
+            relays: Vec::new(),
+            expires_at: None,
+            pow_difficulty: 0,
+        }
+    }
+}
+
+/// Counters of a client's publishes, see [`NostrSentryClient::publish_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PublishStats {
+    /// Events accepted by as many relays as [`Config::with_min_relay_success`] requires,
+    /// counting the companion events of attachments.
+    pub published: u64,
+    /// Send attempts after a retryable failure.
+    pub retries: u64,
+    /// Events given up on.
+    pub failed: u64,
+    /// Events truncated to fit [`Config::with_max_event_bytes`].
+    pub truncated: u64,
+    /// Serialized size of the truncated events before truncation, summed.
+    pub bytes_before_truncation: u64,
+    /// Serialized size of the truncated events as published, summed.
+    pub bytes_after_truncation: u64,
+}
+
+/// What the client did since it was created, see [`NostrSentryClient::metrics`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientMetrics {
+    /// Calls to `capture_event` and its variants, except on a closed client.
+    pub events_captured: u64,
+    /// Captured events dropped by sampling.
+    pub events_sampled: u64,
+    /// Events the rate limit delayed in the queue or, without a queue, dropped.
+    pub events_throttled: u64,
+    /// Events dropped by the `before_send` hook.
+    pub events_filtered: u64,
+    /// Repeats suppressed by `Config::with_dedup`.
+    pub events_deduplicated: u64,
+    /// Panics of the `before_send` hook; the events are dropped and counted as failed.
+    pub before_send_panics: u64,
+    /// Events accepted by enough relays, counting the companion events of attachments.
+    pub events_sent: u64,
+    /// Events that failed before or while publishing, partial deliveries included.
+    pub events_failed: u64,
+    /// Serialized size of the sent events.
+    pub bytes_sent: u64,
+    /// Send attempts after a retryable failure.
+    pub retries: u64,
+    /// Events waiting in the queue or being published from it.
+    pub queue_depth: usize,
+    /// Outcomes of every send attempt, by relay URL.
+    pub relays: HashMap<String, RelayMetrics>,
+    /// Outcomes of the sends to `Config::with_additional_transport` destinations, by
+    /// transport name.
+    pub transports: HashMap<String, TransportMetrics>,
+    /// When the last failure happened, and what it was.
+    pub last_error: Option<(DateTime<Utc>, String)>,
+}
+
+/// Outcomes of the send attempts to one relay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RelayMetrics {
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Attempts without an answer before the publish timeout.
+    pub pending: u64,
+}
+
+#[derive(Default)]
+struct PublishCounters {
+    captured: AtomicU64,
+    sampled: AtomicU64,
+    throttled: AtomicU64,
+    filtered: AtomicU64,
+    deduplicated: AtomicU64,
+    before_send_panics: AtomicU64,
+    published: AtomicU64,
+    retries: AtomicU64,
+    failed: AtomicU64,
+    bytes_sent: AtomicU64,
+    truncated: AtomicU64,
+    bytes_before_truncation: AtomicU64,
+    bytes_after_truncation: AtomicU64,
+    relays: Mutex<HashMap<String, RelayMetrics>>,
+    health: Mutex<HashMap<String, RelayHealth>>,
+    transports: Mutex<HashMap<String, TransportMetrics>>,
+    last_error: Mutex<Option<(DateTime<Utc>, String)>>,
+}
+
+impl PublishCounters {
+    fn snapshot(&self) -> PublishStats {
+        PublishStats {
+            published: self.published.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            truncated: self.truncated.load(Ordering::Relaxed),
+            bytes_before_truncation: self.bytes_before_truncation.load(Ordering::Relaxed),
+            bytes_after_truncation: self.bytes_after_truncation.load(Ordering::Relaxed),
+        }
+    }
+
+    fn metrics(&self, queue_depth: usize) -> ClientMetrics {
+        ClientMetrics {
+            events_captured: self.captured.load(Ordering::Relaxed),
+            events_sampled: self.sampled.load(Ordering::Relaxed),
+            events_throttled: self.throttled.load(Ordering::Relaxed),
+            events_filtered: self.filtered.load(Ordering::Relaxed),
+            events_deduplicated: self.deduplicated.load(Ordering::Relaxed),
+            before_send_panics: self.before_send_panics.load(Ordering::Relaxed),
+            events_sent: self.published.load(Ordering::Relaxed),
+            events_failed: self.failed.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            queue_depth,
+            relays: self.relays.lock().unwrap().clone(),
+            transports: self.transports.lock().unwrap().clone(),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+
+    fn record_outcomes(&self, outcomes: &[RelayOutcome]) {
+        let mut relays = self.relays.lock().unwrap();
+        let mut health = self.health.lock().unwrap();
+        for outcome in outcomes {
+            let relay = relays.entry(outcome.relay.clone()).or_default();
+            match outcome.status {
+                RelayDeliveryStatus::Accepted => relay.accepted += 1,
+                RelayDeliveryStatus::Rejected(_) => relay.rejected += 1,
+                RelayDeliveryStatus::Pending => relay.pending += 1,
+            }
+
+            let health = health.entry(outcome.relay.clone()).or_default();
+            if outcome.status == RelayDeliveryStatus::Accepted {
+                health.last_ok_at = Some(Utc::now());
+                health.consecutive_failures = 0;
+            } else {
+                health.consecutive_failures += 1;
+            }
+        }
+    }
+
+    fn record_transport(&self, name: &str, result: &Result<()>) {
+        let mut transports = self.transports.lock().unwrap();
+        let transport = transports.entry(name.to_string()).or_default();
+        match result {
+            Ok(()) => transport.sent += 1,
+            Err(SentryStrError::RateLimited { .. }) => transport.rate_limited += 1,
+            Err(_) => transport.failed += 1,
+        }
+    }
+
+    fn record_failure(&self, error: &SentryStrError) {
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some((Utc::now(), error.to_string()));
+    }
+
+    fn record_truncation(&self, truncation: Truncation) {
+        self.truncated.fetch_add(1, Ordering::Relaxed);
+        self.bytes_before_truncation
+            .fetch_add(truncation.original as u64, Ordering::Relaxed);
+        self.bytes_after_truncation
+            .fetch_add(truncation.truncated as u64, Ordering::Relaxed);
+    }
+}
+
+/// Sends signed events with the client's retry policy. Shared by direct publishing and
+/// the queue's background task.
+#[derive(Clone)]
+pub(crate) struct Publisher {
+    client: Client,
+    /// Shared with the client, which adds and removes relays while publishing.
+    relays: Arc<RwLock<Vec<String>>>,
+    retry: Option<RetryPolicy>,
+    timeout: Option<Duration>,
+    min_relay_success: MinRelaySuccess,
+    stats: Arc<PublishCounters>,
+    /// Shared by direct and queued publishing.
+    rate_limiter: Option<Arc<TokenBucket>>,
+}
+
+impl Publisher {
+    /// Takes `tokens` from the rate limit without waiting; `false` counts as throttled.
+    fn try_acquire(&self, tokens: usize) -> bool {
+        let Some(ref bucket) = self.rate_limiter else {
+            return true;
+        };
+        let acquired = bucket.try_acquire(tokens);
+        if !acquired {
+            self.stats.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+        acquired
+    }
+
+    /// Waits for `tokens` from the rate limit, counting the wait as throttling.
+    pub(crate) async fn throttle(&self, tokens: usize) {
+        if let Some(ref bucket) = self.rate_limiter
+            && bucket.acquire(tokens).await
+        {
+            self.stats.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Sends the companion events of attachments, then the signed event and, if
+    /// configured, the matching direct message. Returns what the relays did with the
+    /// signed event.
+    pub(crate) async fn publish(
+        &self,
+        dm_sender: Option<&DirectMessageSender>,
+        attachments: &[nostr::Event],
+        nostr_event: &nostr::Event,
+        event: Event,
+    ) -> Result<Vec<RelayOutcome>> {
+        for attachment in attachments {
+            self.send_with_retry(attachment).await?;
+        }
+
+        let outcomes = self.send_with_retry(nostr_event).await?;
+
+        if let Some(dm_sender) = dm_sender {
+            let message_event = MessageEvent {
+                event,
+                author: nostr_event.pubkey,
+                nostr_event_id: nostr_event.id,
+                received_at: Utc::now(),
+            };
+
+            if let Err(e) = dm_sender.send_message_for_event(&message_event).await {
+                eprintln!("Failed to send direct message: {}", e);
+            }
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Sends until enough relays accept the event, every rejection is permanent, the
+    /// attempts run out or the publish timeout passes. Without a retry policy there is a
+    /// single attempt.
+    async fn send_with_retry(&self, nostr_event: &nostr::Event) -> Result<Vec<RelayOutcome>> {
+        let max_attempts = self
+            .retry
+            .as_ref()
+            .map_or(1, |retry| retry.max_attempts.max(1));
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut attempt = 1;
+
+        loop {
+            let remaining =
+                deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            // Taken per attempt, so retries follow added and removed relays.
+            let relays = self.relays.read().unwrap().clone();
+            let required = self.min_relay_success.required(relays.len());
+            let outcomes = deliver(&self.client, &relays, nostr_event, required, remaining).await;
+            self.stats.record_outcomes(&outcomes);
+
+            let accepted = outcomes
+                .iter()
+                .filter(|outcome| outcome.status == RelayDeliveryStatus::Accepted)
+                .count();
+            if accepted >= required {
+                self.stats.published.fetch_add(1, Ordering::Relaxed);
+                self.stats
+                    .bytes_sent
+                    .fetch_add(nostr_event.as_json().len() as u64, Ordering::Relaxed);
+                return Ok(outcomes);
+            }
+
+            // Retry unless every relay that did not accept rejected the event for good.
+            let retryable = outcomes.iter().any(|outcome| match outcome.status {
+                RelayDeliveryStatus::Accepted => false,
+                RelayDeliveryStatus::Rejected(ref message) => is_retryable(message),
+                RelayDeliveryStatus::Pending => true,
+            });
+
+            let delay = match self.retry {
+                Some(ref retry) if retryable && attempt < max_attempts => {
+                    Some(retry.delay(attempt)).filter(|delay| {
+                        deadline.is_none_or(|deadline| Instant::now() + *delay < deadline)
+                    })
+                }
+                _ => None,
+            };
+            let Some(delay) = delay else {
+                let error = if accepted == 0 {
+                    SentryStrError::PublishFailed {
+                        attempts: attempt,
+                        responses: outcomes.iter().map(ToString::to_string).collect(),
+                    }
+                } else {
+                    SentryStrError::PartialDelivery {
+                        attempts: attempt,
+                        required,
+                        accepted,
+                        outcomes,
+                    }
+                };
+                self.stats.record_failure(&error);
+                return Err(error);
+            };
+
+            self.stats.retries.fetch_add(1, Ordering::Relaxed);
+            crate::rt::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RelaySpec;
+    use nostr_relay_builder::MockRelay;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// Connecting never blocks, so clients build without a reachable relay.
+    async fn client(config: Config) -> NostrSentryClient {
+        NostrSentryClient::new(config).await.unwrap()
+    }
+
+    fn config() -> Config {
+        Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap()
+    }
+
+    fn tag_values(nostr_event: &nostr::Event) -> Vec<String> {
+        nostr_event
+            .tags
+            .iter()
+            .flat_map(|tag| tag.as_slice().iter().skip(1).cloned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn encrypted_event_carries_no_event_tag_values() {
+        let recipient = Keys::generate().public_key().to_hex();
+        let client = client(
+            config()
+                .with_nostr_tag("service", "default")
+                .with_standard_nostr_tags(true)
+                .with_encryption(recipient),
+        )
+        .await;
+        let event = Event::new()
+            .with_message("card declined")
+            .with_tag("service", "billing-secret")
+            .with_tag("component", "ledger-secret");
+
+        let nostr_event = client
+            .build_nostr_event(&event, None, Timestamp::now())
+            .unwrap();
+
+        let values = tag_values(&nostr_event);
+        assert!(values.iter().all(|value| !value.contains("secret")));
+        assert!(values.contains(&"default".to_string()));
+    }
+
+    #[tokio::test]
+    async fn plaintext_event_tags_override_configured_values() {
+        let client = client(
+            config()
+                .with_nostr_tag("service", "default")
+                .with_standard_nostr_tags(true),
+        )
+        .await;
+        let event = Event::new().with_tag("service", "billing");
+
+        let nostr_event = client
+            .build_nostr_event(&event, None, Timestamp::now())
+            .unwrap();
+
+        let values = tag_values(&nostr_event);
+        assert!(values.contains(&"billing".to_string()));
+        assert!(!values.contains(&"default".to_string()));
+    }
+
+    #[tokio::test]
+    async fn tags_added_at_runtime_are_published() {
+        let recipient = Keys::generate().public_key().to_hex();
+        for config in [config(), config().with_encryption(recipient)] {
+            let client = client(config.with_tags(vec![Tag::hashtag("configured")])).await;
+            client.add_tag(Tag::hashtag("runtime"));
+
+            let nostr_event = client
+                .build_nostr_event(&Event::new(), None, Timestamp::now())
+                .unwrap();
+
+            let values = tag_values(&nostr_event);
+            assert!(values.contains(&"configured".to_string()));
+            assert!(values.contains(&"runtime".to_string()));
+        }
+    }
+
+    #[tokio::test]
+    async fn timestamps_outside_the_window_are_clamped() {
+        let client = client(
+            config().with_timestamp_window(Duration::from_secs(60), Duration::from_secs(60)),
+        )
+        .await;
+        let timestamp = Utc::now() - chrono::Duration::hours(1);
+        let mut event = Event::new().with_timestamp(timestamp);
+
+        let created_at = client.created_at(&mut event);
+
+        let expected = (Utc::now() - chrono::Duration::seconds(60)).timestamp() as u64;
+        assert!(created_at.as_u64().abs_diff(expected) <= 1);
+        assert_eq!(event.timestamp, timestamp);
+        let skew = event.extra[CLOCK_SKEW_EXTRA].as_i64().unwrap();
+        assert!(skew < -3_000_000);
+    }
+
+    #[tokio::test]
+    async fn timestamps_inside_the_window_are_kept() {
+        let client = client(config()).await;
+        let mut event = Event::new();
+
+        let created_at = client.created_at(&mut event);
+
+        assert_eq!(created_at.as_u64(), event.timestamp.timestamp() as u64);
+        assert!(!event.extra.contains_key(CLOCK_SKEW_EXTRA));
+    }
+
+    /// A transport whose sends finish only once the test hands out permits.
+    #[derive(Debug)]
+    struct GatedTransport(Arc<tokio::sync::Semaphore>);
+
+    impl Transport for GatedTransport {
+        fn name(&self) -> &str {
+            "gated"
+        }
+
+        fn send<'a>(&'a self, _event: &'a Event) -> crate::transport::TransportFuture<'a> {
+            Box::pin(async move {
+                self.0.acquire().await.unwrap().forget();
+                Ok(())
+            })
+        }
+    }
+
+    fn gated_metrics(client: &NostrSentryClient) -> TransportMetrics {
+        client
+            .metrics()
+            .transports
+            .get("gated")
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// A transport keeping every event it is sent.
+    #[derive(Debug)]
+    struct RecordingTransport(Arc<Mutex<Vec<Event>>>);
+
+    impl Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> crate::transport::TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    async fn wait_for_summary(recorded: &Mutex<Vec<Event>>) -> Event {
+        let summary = async {
+            loop {
+                let summary = recorded
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .find(|event| event.extra.contains_key("occurrences"))
+                    .cloned();
+                if let Some(summary) = summary {
+                    return summary;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), summary)
+            .await
+            .expect("no deduplication summary was published")
+    }
+
+    #[tokio::test]
+    async fn dedup_summaries_are_published_by_a_timer() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_dedup(Duration::from_millis(100))
+                .with_additional_transport(RecordingTransport(Arc::clone(&recorded))),
+        )
+        .await;
+
+        let captures = async {
+            for _ in 0..3 {
+                client
+                    .capture_message(Level::Error, "payment failed")
+                    .await
+                    .unwrap();
+            }
+        };
+        crate::with_scope(|scope| scope.set_tag("request_id", "first"), captures).await;
+
+        // No capture follows: the timer publishes the summary.
+        let summary = wait_for_summary(&recorded).await;
+        assert_eq!(summary.extra["occurrences"], serde_json::json!(3));
+        assert_eq!(
+            summary.tags.get("request_id").map(String::as_str),
+            Some("first")
+        );
+    }
+
+    #[tokio::test]
+    async fn dedup_summaries_ignore_the_scope_of_the_publishing_task() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_dedup(Duration::from_secs(3600))
+                .with_dedup_max_entries(1)
+                .with_additional_transport(RecordingTransport(Arc::clone(&recorded))),
+        )
+        .await;
+
+        for _ in 0..2 {
+            client
+                .capture_message(Level::Error, "payment failed")
+                .await
+                .unwrap();
+        }
+        // Evicts the first window, whose summary is published by this capture.
+        let other = client.capture_message(Level::Error, "disk full");
+        crate::with_scope(|scope| scope.set_tag("handler", "disk"), other)
+            .await
+            .unwrap();
+
+        let summary = wait_for_summary(&recorded).await;
+        assert_eq!(summary.message.as_deref(), Some("payment failed"));
+        assert!(!summary.tags.contains_key("handler"));
+    }
+
+    fn messages(breadcrumbs: &[Breadcrumb]) -> Vec<&str> {
+        breadcrumbs
+            .iter()
+            .filter_map(|breadcrumb| breadcrumb.message.as_deref())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn breadcrumbs_keep_the_newest_up_to_the_limit() {
+        let client = client(config().with_max_breadcrumbs(3)).await;
+        for message in ["1", "2", "3", "4", "5"] {
+            client.add_breadcrumb(Breadcrumb::new(message));
+        }
+        assert_eq!(messages(&client.breadcrumbs()), ["3", "4", "5"]);
+
+        client.clear_breadcrumbs();
+        assert!(client.breadcrumbs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_zero_breadcrumb_limit_records_nothing() {
+        let client = client(config().with_max_breadcrumbs(0)).await;
+        client.add_breadcrumb(Breadcrumb::new("ignored"));
+        assert!(client.breadcrumbs().is_empty());
+    }
+
+    #[tokio::test]
+    async fn attached_breadcrumbs_are_capped_in_size() {
+        let client = client(config()).await;
+        let large = "x".repeat(MAX_BREADCRUMB_BYTES / 4);
+        for message in ["oldest", "older", "newer", "newest"] {
+            client.add_breadcrumb(
+                Breadcrumb::new(message).with_data("payload", large.clone().into()),
+            );
+        }
+
+        assert_eq!(
+            messages(&client.breadcrumbs()),
+            ["older", "newer", "newest"]
+        );
+    }
+
+    #[tokio::test]
+    async fn every_capture_carries_a_snapshot_of_the_breadcrumbs() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_additional_transport(RecordingTransport(Arc::clone(&recorded))),
+        )
+        .await;
+
+        client.add_breadcrumb(Breadcrumb::new("cart loaded"));
+        client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+        client.add_breadcrumb(Breadcrumb::new("retrying"));
+        client
+            .capture_message(Level::Error, "payment failed again")
+            .await
+            .unwrap();
+        client
+            .capture_event(
+                Event::new()
+                    .with_message("own trail")
+                    .with_breadcrumbs(vec![Breadcrumb::new("explicit")]),
+            )
+            .await
+            .unwrap();
+        client
+            .inner
+            .forwards
+            .wait(Instant::now() + Duration::from_secs(5))
+            .await;
+
+        let recorded = recorded.lock().unwrap();
+        let trails: Vec<Vec<&str>> = recorded
+            .iter()
+            .map(|event| messages(&event.breadcrumbs))
+            .collect();
+        assert_eq!(
+            trails,
+            [
+                vec!["cart loaded"],
+                vec!["cart loaded", "retrying"],
+                vec!["explicit"]
+            ]
+        );
+    }
+
+    #[derive(Debug)]
+    struct ChargeFailed(std::num::ParseIntError);
+
+    impl std::fmt::Display for ChargeFailed {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("charge failed")
+        }
+    }
+
+    impl std::error::Error for ChargeFailed {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    /// Captures through `capture` on a client recording what it forwards, sampling
+    /// everything out so only forced events get through.
+    async fn recorded_capture<F, Fut>(capture: F) -> Vec<Event>
+    where
+        F: FnOnce(NostrSentryClient) -> Fut,
+        Fut: std::future::Future<Output = NostrSentryClient>,
+    {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_sample_rate(0.0)
+                .with_additional_transport(RecordingTransport(Arc::clone(&recorded))),
+        )
+        .await;
+
+        let client = capture(client).await;
+        client
+            .inner
+            .forwards
+            .wait(Instant::now() + Duration::from_secs(5))
+            .await;
+        recorded.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn captured_errors_carry_their_chain_and_level() {
+        let error = ChargeFailed("12x".parse::<u32>().unwrap_err());
+
+        let recorded = recorded_capture(|client| async {
+            client.capture_error(&error).await.unwrap();
+            client
+                .capture_error_with(&error, |event| event.with_tag("order", "1234"))
+                .await
+                .unwrap();
+            client
+        })
+        .await;
+
+        assert_eq!(recorded.len(), 2);
+        for event in &recorded {
+            assert_eq!(event.level, Level::Error);
+            assert_eq!(event.message.as_deref(), Some("charge failed"));
+            let exception = &event.exception.as_ref().unwrap()[0];
+            assert_eq!(exception.exception_type, "ChargeFailed");
+            assert_eq!(exception.chain, ["invalid digit found in string"]);
+        }
+        assert_eq!(recorded[1].tags["order"], "1234");
+    }
+
+    #[cfg(feature = "anyhow")]
+    #[tokio::test]
+    async fn anyhow_errors_carry_their_context_chain() {
+        use anyhow::Context;
+
+        let error = "12x"
+            .parse::<u32>()
+            .context("reading the amount")
+            .context("charge failed")
+            .unwrap_err();
+
+        let recorded = recorded_capture(|client| async {
+            client.capture_anyhow(&error).await.unwrap();
+            client
+        })
+        .await;
+
+        let exception = &recorded[0].exception.as_ref().unwrap()[0];
+        assert_eq!(recorded[0].level, Level::Error);
+        assert_eq!(exception.value, "charge failed");
+        assert_eq!(
+            exception.chain,
+            ["reading the amount", "invalid digit found in string"]
+        );
+    }
+
+    /// How a [`scripted_relay`] answers one published event.
+    #[derive(Debug, Clone, Copy)]
+    enum Answer {
+        Accept,
+        Reject(&'static str),
+        Ignore,
+    }
+
+    /// A relay answering published events with `answers` in turn, then with the last
+    /// of them, and its URL.
+    async fn scripted_relay(answers: Vec<Answer>) -> String {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let answers = Arc::new(Mutex::new(VecDeque::from(answers)));
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let answers = Arc::clone(&answers);
+                tokio::spawn(async move {
+                    let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                        return;
+                    };
+                    while let Some(Ok(message)) = ws.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let Ok(ClientMessage::Event(event)) = ClientMessage::from_json(&*text)
+                        else {
+                            continue;
+                        };
+                        let answer = {
+                            let mut answers = answers.lock().unwrap();
+                            match answers.len() {
+                                0 => Answer::Accept,
+                                1 => answers[0],
+                                _ => answers.pop_front().unwrap(),
+                            }
+                        };
+                        let reply = match answer {
+                            Answer::Accept => RelayMessage::ok(event.id, true, ""),
+                            Answer::Reject(message) => RelayMessage::ok(event.id, false, message),
+                            Answer::Ignore => continue,
+                        };
+                        if ws.send(Message::text(reply.as_json())).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    /// A client publishing to `relays` directly, once connected to all of them.
+    async fn relay_client(
+        relays: &[String],
+        configure: impl FnOnce(Config) -> Config,
+    ) -> NostrSentryClient {
+        let config =
+            Config::from_keys(&Keys::generate(), relays.iter().map(String::as_str)).unwrap();
+        let client = client(configure(config)).await;
+        client
+            .wait_for_connection(relays.len(), Duration::from_secs(5))
+            .await
+            .unwrap();
+        client
+    }
+
+    fn no_jitter(max_attempts: u32, base_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay,
+            max_delay: Duration::from_secs(1),
+            jitter: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_rejections_are_retried_with_backoff() {
+        let relay = scripted_relay(vec![
+            Answer::Reject("rate-limited: slow down"),
+            Answer::Reject("error: database busy"),
+            Answer::Accept,
+        ])
+        .await;
+        let client = relay_client(&[relay], |config| {
+            config.with_retry(no_jitter(3, Duration::from_millis(100)))
+        })
+        .await;
+
+        let started = Instant::now();
+        client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+
+        // 100ms before the first retry, doubled to 200ms before the second.
+        assert!(started.elapsed() >= Duration::from_millis(300));
+        let metrics = client.metrics();
+        assert_eq!((metrics.events_sent, metrics.retries), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn permanent_rejections_and_exhausted_attempts_fail_with_the_responses() {
+        let relay = scripted_relay(vec![Answer::Reject("blocked: not on the allow list")]).await;
+        let client = relay_client(&[relay], |config| {
+            config.with_retry(no_jitter(3, Duration::from_millis(10)))
+        })
+        .await;
+
+        let error = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            SentryStrError::PublishFailed { attempts: 1, ref responses }
+                if responses[0].contains("blocked: not on the allow list")
+        ));
+
+        let relay = scripted_relay(vec![Answer::Reject("rate-limited: slow down")]).await;
+        let client = relay_client(&[relay], |config| {
+            config.with_retry(no_jitter(3, Duration::from_millis(10)))
+        })
+        .await;
+
+        let error = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            SentryStrError::PublishFailed { attempts: 3, .. }
+        ));
+        assert_eq!(client.metrics().retries, 2);
+    }
+
+    #[tokio::test]
+    async fn publishing_resolves_once_enough_relays_accepted() {
+        let accepting = scripted_relay(vec![Answer::Accept]).await;
+        let silent = scripted_relay(vec![Answer::Ignore]).await;
+        let client = relay_client(&[accepting.clone(), silent.clone()], |config| {
+            config.with_min_relay_success(1)
+        })
+        .await;
+
+        let started = Instant::now();
+        client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+        let metrics = client.metrics();
+        assert_eq!(metrics.relays[&accepting].accepted, 1);
+        assert_eq!(metrics.relays[&silent].pending, 1);
+    }
+
+    #[tokio::test]
+    async fn missing_the_relay_threshold_is_a_partial_delivery() {
+        let accepting = scripted_relay(vec![Answer::Accept]).await;
+        let rejecting =
+            scripted_relay(vec![Answer::Reject("blocked: not on the allow list")]).await;
+        let client = relay_client(&[accepting, rejecting.clone()], |config| {
+            config.with_min_relay_success(2)
+        })
+        .await;
+
+        let error = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap_err();
+
+        let SentryStrError::PartialDelivery {
+            required: 2,
+            accepted: 1,
+            outcomes,
+            ..
+        } = error
+        else {
+            panic!("expected a partial delivery, got {error:?}");
+        };
+        let rejected = outcomes.iter().find(|outcome| outcome.relay == rejecting);
+        assert_eq!(
+            rejected.unwrap().status,
+            RelayDeliveryStatus::Rejected("blocked: not on the allow list".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn the_publish_timeout_bounds_retries_against_silent_relays() {
+        let silent = scripted_relay(vec![Answer::Ignore]).await;
+        let client = relay_client(std::slice::from_ref(&silent), |config| {
+            config
+                .with_publish_timeout(Duration::from_millis(300))
+                .with_retry(no_jitter(10, Duration::from_millis(50)))
+        })
+        .await;
+
+        let started = Instant::now();
+        let error = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap_err();
+
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            error,
+            SentryStrError::PublishFailed { ref responses, .. }
+                if responses == &[format!("{silent}: no response")]
+        ));
+        assert!(client.metrics().relays[&silent].pending >= 1);
+    }
+
+    #[tokio::test]
+    async fn published_events_are_mined_or_handled_by_the_pow_policy() {
+        let relay = scripted_relay(vec![Answer::Accept]).await;
+        let mining = relay_client(std::slice::from_ref(&relay), |config| config.with_pow(8)).await;
+        let outcome = mining
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+        assert!(outcome.receipt().unwrap().pow_difficulty >= 8);
+
+        let sending_unmined = relay_client(std::slice::from_ref(&relay), |config| {
+            config
+                .with_pow(64)
+                .with_pow_budget(Duration::ZERO, PowPolicy::SendUnmined)
+        })
+        .await;
+        let outcome = sending_unmined
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+        assert!(outcome.receipt().unwrap().pow_difficulty < 64);
+
+        let failing = relay_client(&[relay], |config| {
+            config
+                .with_pow(64)
+                .with_pow_budget(Duration::ZERO, PowPolicy::Fail)
+        })
+        .await;
+        let error = failing
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            SentryStrError::PowTimeout { difficulty: 64, budget } if budget.is_zero()
+        ));
+        assert_eq!(failing.metrics().events_sent, 0);
+    }
+
+    #[tokio::test]
+    async fn metrics_count_every_capture_attempt_and_relay_outcome() {
+        let steady = scripted_relay(vec![Answer::Accept]).await;
+        let flaky = scripted_relay(vec![
+            Answer::Reject("rate-limited: slow down"),
+            Answer::Accept,
+            Answer::Reject("blocked: spam"),
+        ])
+        .await;
+        let client = relay_client(&[steady.clone(), flaky.clone()], |config| {
+            config
+                .with_min_relay_success(2)
+                .with_retry(no_jitter(3, Duration::from_millis(10)))
+                .with_before_send(|event| {
+                    (event.message.as_deref() != Some("health check")).then_some(event)
+                })
+        })
+        .await;
+
+        client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+        client
+            .capture_message(Level::Error, "refund failed")
+            .await
+            .unwrap_err();
+        client
+            .capture_message(Level::Info, "health check")
+            .await
+            .unwrap();
+
+        let metrics = client.metrics();
+        assert_eq!(
+            (
+                metrics.events_captured,
+                metrics.events_filtered,
+                metrics.events_sent,
+                metrics.events_failed,
+                metrics.retries,
+            ),
+            (3, 1, 1, 1, 1)
+        );
+        assert!(metrics.bytes_sent > 0);
+        assert_eq!(
+            metrics.relays[&steady],
+            RelayMetrics {
+                accepted: 3,
+                rejected: 0,
+                pending: 0,
+            }
+        );
+        assert_eq!(
+            metrics.relays[&flaky],
+            RelayMetrics {
+                accepted: 1,
+                rejected: 2,
+                pending: 0,
+            }
+        );
+        let (_, last_error) = metrics.last_error.unwrap();
+        assert!(last_error.contains("blocked: spam"), "{last_error}");
+    }
+
+    #[tokio::test]
+    async fn queued_captures_do_not_wait_for_transports() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_additional_transport(GatedTransport(Arc::clone(&gate))),
+        )
+        .await;
+
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            client.capture_message(Level::Error, "payment failed"),
+        )
+        .await
+        .expect("the capture waited for the transport")
+        .unwrap();
+        assert_eq!(gated_metrics(&client).sent, 0);
+
+        gate.add_permits(1);
+        client
+            .inner
+            .forwards
+            .wait(Instant::now() + Duration::from_secs(5))
+            .await;
+        assert_eq!(gated_metrics(&client).sent, 1);
+    }
+
+    #[tokio::test]
+    async fn background_forwards_are_bounded_by_the_queue_size() {
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let client = client(
+            config()
+                .with_queue(1, Duration::from_secs(1))
+                .with_queue_overflow(crate::OverflowPolicy::DropOldest)
+                .with_additional_transport(GatedTransport(Arc::clone(&gate))),
+        )
+        .await;
+
+        for message in ["first", "second"] {
+            client.capture_message(Level::Error, message).await.unwrap();
+        }
+        assert_eq!(gated_metrics(&client).failed, 1);
+
+        gate.add_permits(1);
+        client
+            .inner
+            .forwards
+            .wait(Instant::now() + Duration::from_secs(5))
+            .await;
+        assert_eq!(gated_metrics(&client).sent, 1);
+    }
+
+    #[tokio::test]
+    async fn dropped_events_have_no_id() {
+        let client = client(config().with_sample_rate(0.0)).await;
+
+        let outcome = client
+            .capture_event(Event::new().with_message("sampled out"))
+            .await
+            .unwrap();
+
+        assert!(outcome.is_sampled());
+        assert_eq!(outcome.event_id(), None);
+        assert_eq!(outcome.receipt(), None);
+    }
+
+    /// The events `relay` holds, fetched by a client of its own.
+    async fn stored_events(relay: &MockRelay, filter: Filter) -> Vec<nostr::Event> {
+        let reader = Client::default();
+        reader.add_relay(relay.url().to_string()).await.unwrap();
+        reader.connect().await;
+        reader
+            .fetch_events(filter, Duration::from_secs(5))
+            .await
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn the_returned_id_is_the_id_the_relay_received() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = client(Config::from_keys(&keys, [relay.url().to_string()]).unwrap()).await;
+
+        let outcome = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+
+        let receipt = outcome.receipt().unwrap();
+        assert_eq!(receipt.relays.len(), 1);
+        assert_eq!(receipt.relays[0].status, RelayDeliveryStatus::Accepted);
+        let stored = stored_events(&relay, Filter::new().author(keys.public_key())).await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!(Some(stored[0].id), outcome.event_id());
+    }
+
+    #[tokio::test]
+    async fn event_kinds_outside_the_range_are_rejected() {
+        let nostr_event = client(config().with_event_kind(9123))
+            .await
+            .build_nostr_event(&Event::new(), None, Timestamp::now())
+            .unwrap();
+        assert_eq!(nostr_event.kind, Kind::Custom(9123));
+
+        for kind in [999, ATTACHMENT_KIND, 10000] {
+            assert!(matches!(
+                NostrSentryClient::new(config().with_event_kind(kind)).await,
+                Err(SentryStrError::Config(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn captured_messages_carry_their_level_tags_and_the_client_defaults() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = client(
+            Config::from_keys(&keys, [relay.url().to_string()])
+                .unwrap()
+                .with_environment("production")
+                .with_release("1.4.2"),
+        )
+        .await;
+
+        client
+            .capture_message(Level::Warning, "disk almost full")
+            .await
+            .unwrap();
+        client
+            .capture_message_with_tags(
+                Level::Error,
+                "sync failed",
+                [("job".to_string(), "sync".to_string())],
+            )
+            .await
+            .unwrap();
+
+        let mut captured: Vec<Event> =
+            stored_events(&relay, Filter::new().author(keys.public_key()))
+                .await
+                .iter()
+                .map(|stored| serde_json::from_str(&stored.content).unwrap())
+                .collect();
+        captured.sort_by(|a, b| a.level.cmp(&b.level));
+        let summary: Vec<_> = captured
+            .iter()
+            .map(|event| {
+                (
+                    event.level.clone(),
+                    event.message.as_deref(),
+                    event.tags.get("job").map(String::as_str),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Level::Warning, Some("disk almost full"), None),
+                (Level::Error, Some("sync failed"), Some("sync")),
+            ]
+        );
+        for event in &captured {
+            assert_eq!(event.environment.as_deref(), Some("production"));
+            assert_eq!(event.release.as_deref(), Some("1.4.2"));
+        }
+    }
+
+    /// The events `before_send` sees of `events` captured by a client built from
+    /// `config`, with the client defaults applied.
+    async fn prepared(config: Config, events: Vec<Event>) -> Vec<Event> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let client = client(config.with_before_send(move |event| {
+            recorded.lock().unwrap().push(event);
+            None
+        }))
+        .await;
+        for event in events {
+            client.capture_event(event).await.unwrap();
+        }
+        seen.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn events_without_a_server_name_get_the_hostname() {
+        let detected = config().with_hostname_provider(|| Some("web-1".to_string()));
+        assert_eq!(
+            client(detected.clone()).await.default_server_name(),
+            Some("web-1")
+        );
+
+        let cases = [
+            (detected.clone(), [Some("web-1"), Some("db-1")]),
+            (
+                detected.clone().with_server_name("api-1"),
+                [Some("api-1"), Some("db-1")],
+            ),
+            (detected.with_auto_server_name(false), [None, Some("db-1")]),
+        ];
+        for (config, expected) in cases {
+            let events = prepared(
+                config,
+                vec![
+                    Event::new().with_message("unnamed"),
+                    Event::new().with_message("named").with_server_name("db-1"),
+                ],
+            )
+            .await;
+            let server_names: Vec<Option<&str>> = events
+                .iter()
+                .map(|event| event.server_name.as_deref())
+                .collect();
+            assert_eq!(server_names, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn events_without_a_release_get_the_resolved_one() {
+        let config = config()
+            .with_auto_release(true)
+            .with_release_fallback("my-app@1.0.0");
+        let client = client(config.clone()).await;
+        let expected = config.resolve_release();
+        assert!(expected.is_some());
+        assert_eq!(client.release(), expected.as_deref());
+
+        let mut released = Event::new().with_message("released");
+        released.release = Some("hotfix-7".to_string());
+        let events = prepared(
+            config,
+            vec![Event::new().with_message("unreleased"), released],
+        )
+        .await;
+        let releases: Vec<Option<&str>> = events
+            .iter()
+            .map(|event| event.release.as_deref())
+            .collect();
+        assert_eq!(releases, [expected.as_deref(), Some("hotfix-7")]);
+    }
+
+    #[tokio::test]
+    async fn sequence_numbers_need_a_blocking_queue() {
+        let numbered = config()
+            .with_sequence_numbers(true)
+            .with_queue(10, Duration::from_secs(1));
+
+        assert!(NostrSentryClient::new(numbered.clone()).await.is_ok());
+        assert!(matches!(
+            NostrSentryClient::new(numbered.with_queue_overflow(OverflowPolicy::DropOldest)).await,
+            Err(SentryStrError::Config(_))
+        ));
+    }
+
+    /// Draws from a sequence seeded the same on every run.
+    fn seeded_sampler() -> f64 {
+        static RNG: SampleRng = SampleRng::seeded(7);
+        RNG.next()
+    }
+
+    /// How many of `count` events at `level` survive the sampling of `client`.
+    async fn kept(client: &NostrSentryClient, level: Level, count: usize) -> usize {
+        let mut kept = 0;
+        for _ in 0..count {
+            let outcome = client
+                .capture_event(Event::new().with_message("tick").with_level(level.clone()))
+                .await
+                .unwrap();
+            if !outcome.is_sampled() {
+                kept += 1;
+            }
+        }
+        kept
+    }
+
+    #[tokio::test]
+    async fn sampling_keeps_the_configured_share_of_each_level() {
+        let client = client(
+            config()
+                .with_queue(5_000, Duration::from_secs(1))
+                .with_sample_rate(0.2)
+                .with_level_sample_rates(HashMap::from([
+                    (Level::Error, 1.0),
+                    (Level::Warning, 0.5),
+                    (Level::Debug, 0.0),
+                ]))
+                .with_sampler(seeded_sampler),
+        )
+        .await;
+
+        let info = kept(&client, Level::Info, 2_000).await;
+        assert!((360..=440).contains(&info), "{} of 2000 info kept", info);
+        let warnings = kept(&client, Level::Warning, 2_000).await;
+        assert!(
+            (900..=1_100).contains(&warnings),
+            "{} of 2000 warnings kept",
+            warnings
+        );
+        assert_eq!(kept(&client, Level::Error, 200).await, 200);
+        assert_eq!(kept(&client, Level::Debug, 200).await, 0);
+    }
+
+    #[tokio::test]
+    async fn forced_events_bypass_sampling() {
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_sample_rate(0.0),
+        )
+        .await;
+
+        let forced = client
+            .capture_event(Event::new().with_message("forced").with_force_send())
+            .await
+            .unwrap();
+        assert!(!forced.is_sampled());
+        assert!(forced.event_id().is_some());
+
+        let error = client
+            .capture_error_message("payment failed")
+            .await
+            .unwrap();
+        assert!(!error.is_sampled());
+
+        let plain = client
+            .capture_event(Event::new().with_message("plain"))
+            .await
+            .unwrap();
+        assert!(plain.is_sampled());
+    }
+
+    #[tokio::test]
+    async fn before_send_drops_mutates_and_survives_panics() {
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let client = client(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_additional_transport(RecordingTransport(Arc::clone(&recorded)))
+                .with_before_send(|mut event| match event.message.as_deref() {
+                    Some("health check") => None,
+                    Some("poison") => panic!("hook failed"),
+                    _ => {
+                        event
+                            .extra
+                            .insert("password".to_string(), "[Filtered]".into());
+                        event
+                            .tags
+                            .insert("scrubbed".to_string(), "true".to_string());
+                        Some(event)
+                    }
+                }),
+        )
+        .await;
+        let login = |message: &str| {
+            Event::new()
+                .with_message(message)
+                .with_extra("password", "hunter2".into())
+        };
+
+        let dropped = client.capture_event(login("health check")).await.unwrap();
+        assert!(matches!(dropped, CaptureOutcome::Filtered));
+        assert!(matches!(
+            client.capture_event(login("poison")).await,
+            Err(SentryStrError::BeforeSendPanicked(panic)) if panic.contains("hook failed")
+        ));
+        let kept = client.capture_event(login("login failed")).await.unwrap();
+        assert!(kept.event_id().is_some());
+
+        client
+            .inner
+            .forwards
+            .wait(Instant::now() + Duration::from_secs(5))
+            .await;
+        let recorded = recorded.lock().unwrap();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(recorded[0].message.as_deref(), Some("login failed"));
+        assert_eq!(recorded[0].extra["password"], "[Filtered]");
+        assert_eq!(recorded[0].tags["scrubbed"], "true");
+
+        let metrics = client.metrics();
+        assert_eq!(
+            (metrics.events_filtered, metrics.before_send_panics),
+            (1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn closing_delivers_the_queue_then_refuses_captures() {
+        let relay = scripted_relay(vec![Answer::Accept]).await;
+        let client = relay_client(&[relay], |config| {
+            config.with_queue(10, Duration::from_secs(1))
+        })
+        .await;
+
+        for n in 0..3 {
+            client
+                .capture_message(Level::Error, format!("failure {}", n))
+                .await
+                .unwrap();
+        }
+        let report = client.close(Duration::from_secs(5)).await;
+
+        assert_eq!(
+            report,
+            CloseReport {
+                delivered: 3,
+                abandoned: 0,
+            }
+        );
+        assert!(matches!(
+            client.capture_message(Level::Error, "too late").await,
+            Err(SentryStrError::ClientClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn closing_counts_the_events_left_when_the_timeout_passes() {
+        let relay = scripted_relay(vec![Answer::Ignore]).await;
+        let client = relay_client(&[relay], |config| {
+            config
+                .with_queue(10, Duration::from_secs(1))
+                .with_publish_timeout(Duration::from_secs(30))
+        })
+        .await;
+
+        for n in 0..3 {
+            client
+                .capture_message(Level::Error, format!("failure {}", n))
+                .await
+                .unwrap();
+        }
+        let report = client.close(Duration::from_millis(300)).await;
+
+        assert_eq!(
+            report,
+            CloseReport {
+                delivered: 0,
+                abandoned: 3,
+            }
+        );
+    }
+
+    /// Ids of the events of `keys` that `relay` holds.
+    async fn stored_ids(relay: &MockRelay, keys: &Keys) -> BTreeSet<EventId> {
+        stored_events(relay, Filter::new().author(keys.public_key()))
+            .await
+            .into_iter()
+            .map(|event| event.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn added_relays_receive_events_and_removed_ones_stop() {
+        let first = MockRelay::run().await.unwrap();
+        let second = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = client(Config::from_keys(&keys, [first.url().to_string()]).unwrap()).await;
+        client
+            .wait_for_connection(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let client = &client;
+        let capture = |message: &'static str| async move {
+            client
+                .capture_message(Level::Error, message)
+                .await
+                .unwrap()
+                .event_id()
+                .unwrap()
+        };
+
+        let before = capture("before adding").await;
+        client.add_relay(&second.url().to_string()).await.unwrap();
+        client
+            .wait_for_connection(2, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let added = capture("after adding").await;
+        client.remove_relay(&first.url().to_string()).await.unwrap();
+        let removed = capture("after removing").await;
+
+        assert_eq!(
+            stored_ids(&first, &keys).await,
+            BTreeSet::from([before, added])
+        );
+        assert_eq!(
+            stored_ids(&second, &keys).await,
+            BTreeSet::from([added, removed])
+        );
+        let relays: Vec<String> = client
+            .relays()
+            .await
+            .into_iter()
+            .map(|relay| relay.url)
+            .collect();
+        assert_eq!(relays, [second.url().to_string()]);
+        assert!(matches!(
+            client.remove_relay(&second.url().to_string()).await,
+            Err(SentryStrError::Config(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn relay_status_reports_each_relay() {
+        let relay = MockRelay::run().await.unwrap();
+        let accepting = relay.url().to_string();
+        let rejecting = scripted_relay(vec![Answer::Reject("blocked: spam")]).await;
+        let down = "ws://127.0.0.1:9".to_string();
+        let client = client(
+            Config::from_keys(&Keys::generate(), [&*accepting, &*rejecting, &*down])
+                .unwrap()
+                .with_retry(no_jitter(1, Duration::from_millis(10)))
+                .with_publish_timeout(Duration::from_secs(2)),
+        )
+        .await;
+        client
+            .wait_for_connection(2, Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        let started = Utc::now();
+        client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+        client
+            .capture_message(Level::Error, "refund failed")
+            .await
+            .unwrap();
+
+        let status: HashMap<String, RelayStatusInfo> = client
+            .relay_status()
+            .await
+            .into_iter()
+            .map(|relay| (relay.url.clone(), relay))
+            .collect();
+        assert_eq!(status.len(), 3);
+
+        let ok = &status[&accepting];
+        assert!(ok.connected);
+        assert_eq!(ok.status, "Connected");
+        assert!(ok.connected_since.is_some());
+        assert!(ok.last_publish_ok_at.unwrap() >= started - chrono::Duration::seconds(1));
+        assert_eq!(ok.consecutive_failures, 0);
+
+        let rejected = &status[&rejecting];
+        assert!(rejected.connected);
+        assert_eq!(rejected.last_publish_ok_at, None);
+        assert_eq!(rejected.consecutive_failures, 2);
+
+        let unreachable = &status[&down];
+        assert!(!unreachable.connected);
+        assert_eq!(unreachable.connected_since, None);
+        assert_eq!(unreachable.last_publish_ok_at, None);
+    }
+
+    #[tokio::test]
+    async fn events_are_published_to_write_relays_only() {
+        let writable = MockRelay::run().await.unwrap();
+        let readable = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let config = Config::from_keys(
+            &keys,
+            [
+                RelaySpec::write_only(writable.url().to_string()),
+                RelaySpec::read_only(readable.url().to_string()),
+            ],
+        )
+        .unwrap();
+        let client = client(config).await;
+
+        let outcome = client
+            .capture_message(Level::Error, "payment failed")
+            .await
+            .unwrap();
+
+        let receipt = outcome.receipt().unwrap();
+        assert_eq!(receipt.relays.len(), 1);
+        assert_eq!(receipt.relays[0].relay, writable.url().to_string());
+        let relays: Vec<String> = client
+            .relays()
+            .await
+            .into_iter()
+            .map(|relay| relay.url)
+            .collect();
+        assert_eq!(relays, [writable.url().to_string()]);
+        assert_eq!(
+            stored_ids(&writable, &keys).await,
+            BTreeSet::from([outcome.event_id().unwrap()])
+        );
+        assert!(stored_ids(&readable, &keys).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expirations_count_the_ttl_of_the_level_from_the_event_timestamp() {
+        let relay = MockRelay::run().await.unwrap();
+        let keys = Keys::generate();
+        let client = client(
+            Config::from_keys(&keys, [relay.url().to_string()])
+                .unwrap()
+                .with_event_ttl(Duration::from_secs(3600))
+                .with_level_ttl(Level::Debug, Duration::from_secs(600))
+                .with_level_ttl(Level::Fatal, Duration::ZERO),
+        )
+        .await;
+        let timestamp = Utc::now() - chrono::Duration::seconds(30);
+
+        for level in [Level::Debug, Level::Error, Level::Fatal] {
+            client
+                .capture_event(
+                    Event::new()
+                        .with_message(level.to_string())
+                        .with_level(level)
+                        .with_timestamp(timestamp),
+                )
+                .await
+                .unwrap();
+        }
+
+        let expirations: BTreeMap<String, Option<u64>> =
+            stored_events(&relay, Filter::new().author(keys.public_key()))
+                .await
+                .into_iter()
+                .map(|stored| {
+                    let event: Event = serde_json::from_str(&stored.content).unwrap();
+                    (
+                        event.message.unwrap(),
+                        stored.tags.expiration().map(Timestamp::as_u64),
+                    )
+                })
+                .collect();
+        let expected_at = |ttl: u64| Some(timestamp.timestamp() as u64 + ttl);
+        assert_eq!(
+            expirations,
+            BTreeMap::from([
+                (Level::Debug.to_string(), expected_at(600)),
+                (Level::Error.to_string(), expected_at(3600)),
+                (Level::Fatal.to_string(), None),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn expirations_are_tagged_on_encrypted_events_too() {
+        let recipient = Keys::generate().public_key().to_hex();
+        let client = client(
+            config()
+                .with_event_ttl(Duration::from_secs(60))
+                .with_encryption(recipient),
+        )
+        .await;
+        let event = Event::new();
+
+        let expiration = client.expiration(&event).unwrap();
+        let nostr_event = client
+            .build_nostr_event(&event, expiration, Timestamp::now())
+            .unwrap();
+
+        assert_eq!(
+            nostr_event.tags.expiration().map(Timestamp::as_u64),
+            Some(event.timestamp.timestamp() as u64 + 60)
+        );
+    }
+}
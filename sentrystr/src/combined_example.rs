@@ -8,14 +8,14 @@ use nostr_sdk::prelude::*;
 pub async fn run_combined_example() -> Result<()> {
     // Setup configuration for the main client
     let keys = Keys::generate();
-    let config = Config::new(
+    let config = Config::try_new(
         keys.secret_key().display_secret().to_string(),
         vec![
             "wss://relay.damus.io".to_string(),
             "wss://nos.lol".to_string(),
             "wss://nostr.chaima.info".to_string(),
         ],
-    );
+    )?;
 
     // Create the main NostrSentryClient
     let mut client = NostrSentryClient::new(config).await?;
@@ -87,10 +87,10 @@ pub async fn run_combined_example() -> Result<()> {
 /// Example of creating a client with direct messaging using the builder pattern
 pub async fn create_client_with_dm_builder() -> Result<NostrSentryClient> {
     let keys = Keys::generate();
-    let config = Config::new(
+    let config = Config::try_new(
         keys.secret_key().display_secret().to_string(),
         vec!["wss://relay.damus.io".to_string()],
-    );
+    )?;
 
     let keys = Keys::generate();
     let nostr_client = Client::new(keys.clone());
@@ -138,7 +138,9 @@ pub async fn switch_dm_configurations(client: &mut NostrSentryClient) -> Result<
     client.set_direct_messaging(dm_sender1);
 
     // Test with first configuration
-    client.capture_error("Error with first DM config").await?;
+    client
+        .capture_error_message("Error with first DM config")
+        .await?;
 
     // Switch to second configuration: NIP-44 for errors only
     let recipient2 =
@@ -156,13 +158,15 @@ pub async fn switch_dm_configurations(client: &mut NostrSentryClient) -> Result<
     client.set_direct_messaging(dm_sender2);
 
     // Test with second configuration
-    client.capture_error("Error with second DM config").await?;
+    client
+        .capture_error_message("Error with second DM config")
+        .await?;
 
     // Disable direct messaging
     client.remove_direct_messaging();
 
     // This won't send a DM
-    client.capture_error("Error without DM").await?;
+    client.capture_error_message("Error without DM").await?;
 
     Ok(())
 }
@@ -0,0 +1,540 @@
+//! Sentry-compatible representations of events, for running SentryStr next to a hosted
+//! Sentry while migrating and comparing both.
+
+use crate::{Event, Exception, Stacktrace};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+/// Platforms Sentry knows; anything else is sent as `other`.
+const SENTRY_PLATFORMS: &[&str] = &[
+    "as3",
+    "c",
+    "cfml",
+    "cocoa",
+    "csharp",
+    "elixir",
+    "go",
+    "groovy",
+    "haskell",
+    "java",
+    "javascript",
+    "native",
+    "node",
+    "objc",
+    "other",
+    "perl",
+    "php",
+    "python",
+    "ruby",
+];
+
+/// The `event` as a Sentry event payload. Ids lose their dashes, the timestamp is in
+/// Unix seconds, Rust events use the `native` platform, and exceptions are listed
+/// innermost cause first with the event stacktrace on the outermost one, as Sentry
+/// expects. Unset fields and the Nostr tags are left out.
+pub fn to_sentry_event(event: &Event) -> Value {
+    let mut payload = Map::new();
+
+    payload.insert(
+        "event_id".to_string(),
+        json!(event.event_id.replace('-', "").to_lowercase()),
+    );
+    payload.insert(
+        "timestamp".to_string(),
+        json!(event.timestamp.timestamp_micros() as f64 / 1_000_000.0),
+    );
+    payload.insert("platform".to_string(), json!(platform(&event.platform)));
+    payload.insert("level".to_string(), json!(event.level.as_str()));
+    insert(&mut payload, "logger", &event.logger);
+    insert(&mut payload, "transaction", &event.transaction);
+    insert(&mut payload, "server_name", &event.server_name);
+    insert(&mut payload, "release", &event.release);
+    insert(&mut payload, "environment", &event.environment);
+    insert(&mut payload, "message", &event.message);
+
+    if !event.tags.is_empty() {
+        payload.insert("tags".to_string(), json!(event.tags));
+    }
+    if !event.extra.is_empty() {
+        payload.insert("extra".to_string(), json!(event.extra));
+    }
+    insert(&mut payload, "fingerprint", &event.fingerprint);
+    insert(&mut payload, "modules", &event.modules);
+    insert(&mut payload, "user", &event.user);
+    insert(&mut payload, "request", &event.request);
+
+    let exceptions = exception_values(event);
+    if !exceptions.is_empty() {
+        payload.insert("exception".to_string(), json!({ "values": exceptions }));
+    } else if let Some(ref stacktrace) = event.stacktrace {
+        payload.insert("stacktrace".to_string(), stacktrace_value(stacktrace));
+    }
+
+    if !event.breadcrumbs.is_empty() {
+        let values: Vec<Value> = event
+            .breadcrumbs
+            .iter()
+            .map(|breadcrumb| {
+                let mut value = Map::new();
+                value.insert(
+                    "timestamp".to_string(),
+                    json!(breadcrumb.timestamp.timestamp_micros() as f64 / 1_000_000.0),
+                );
+                value.insert("level".to_string(), json!(breadcrumb.level.as_str()));
+                insert(&mut value, "category", &breadcrumb.category);
+                insert(&mut value, "message", &breadcrumb.message);
+                if !breadcrumb.data.is_empty() {
+                    value.insert("data".to_string(), json!(breadcrumb.data));
+                }
+                Value::Object(value)
+            })
+            .collect();
+        payload.insert("breadcrumbs".to_string(), json!({ "values": values }));
+    }
+
+    payload.insert(
+        "sdk".to_string(),
+        json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") }),
+    );
+
+    Value::Object(payload)
+}
+
+/// The `event` as a Sentry envelope holding one `event` item, ready to POST to the
+/// envelope endpoint of a project.
+pub fn to_sentry_envelope(event: &Event) -> String {
+    let payload = to_sentry_event(event);
+    let body = payload.to_string();
+    let header = json!({
+        "event_id": payload["event_id"],
+        "sent_at": Utc::now().to_rfc3339(),
+    });
+    let item_header = json!({
+        "type": "event",
+        "length": body.len(),
+        "content_type": "application/json",
+    });
+
+    format!("{}\n{}\n{}\n", header, item_header, body)
+}
+
+fn platform(platform: &str) -> &str {
+    match platform {
+        "rust" => "native",
+        platform if SENTRY_PLATFORMS.contains(&platform) => platform,
+        _ => "other",
+    }
+}
+
+fn insert<T: Serialize>(map: &mut Map<String, Value>, key: &str, value: &Option<T>) {
+    if let Some(value) = value
+        && let Ok(value) = serde_json::to_value(value)
+    {
+        map.insert(key.to_string(), without_nulls(value));
+    }
+}
+
+fn without_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(without_nulls).collect()),
+        value => value,
+    }
+}
+
+fn stacktrace_value(stacktrace: &Stacktrace) -> Value {
+    let frames: Vec<Value> = stacktrace
+        .frames
+        .iter()
+        .filter_map(|frame| serde_json::to_value(frame).ok())
+        .map(without_nulls)
+        .collect();
+    json!({ "frames": frames })
+}
+
+/// Sentry exception values, innermost first so that the event's first exception comes
+/// last. The `chain` of each exception only has messages, so causes are sent with a
+/// value but no type.
+fn exception_values(event: &Event) -> Vec<Value> {
+    let Some(ref exceptions) = event.exception else {
+        return Vec::new();
+    };
+
+    let mut values = Vec::new();
+    for (index, exception) in exceptions.iter().enumerate().rev() {
+        for cause in exception.chain.iter().rev() {
+            values.push(json!({ "value": cause }));
+        }
+
+        let stacktrace = match exception.stacktrace {
+            Some(ref stacktrace) => Some(stacktrace),
+            None if index == 0 => event.stacktrace.as_ref(),
+            None => None,
+        };
+        values.push(exception_value(exception, stacktrace));
+    }
+    values
+}
+
+fn exception_value(exception: &Exception, stacktrace: Option<&Stacktrace>) -> Value {
+    let mut value = Map::new();
+    value.insert("type".to_string(), json!(exception.exception_type));
+    value.insert("value".to_string(), json!(exception.value));
+    insert(&mut value, "module", &exception.module);
+    if let Some(stacktrace) = stacktrace {
+        value.insert("stacktrace".to_string(), stacktrace_value(stacktrace));
+    }
+    Value::Object(value)
+}
+
+#[cfg(feature = "sentry-compat")]
+pub use forwarder::{SentryDsnTransport, SentryForwarder};
+
+#[cfg(feature = "sentry-compat")]
+mod forwarder {
+    use super::to_sentry_envelope;
+    use crate::rt::Instant;
+    use crate::transport::{Transport, TransportFuture};
+    use crate::{Event, Result, SentryStrError};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Back-off after a 429 without a usable `Retry-After` header.
+    const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+    /// How long a request may take, so an unresponsive Sentry cannot hold a capture.
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Sends events to a hosted Sentry project, next to publishing them to Nostr.
+    #[derive(Debug, Clone)]
+    pub struct SentryForwarder {
+        http: reqwest::Client,
+        endpoint: String,
+        auth: String,
+    }
+
+    impl SentryForwarder {
+        /// Parses a DSN such as `https://<public key>@o0.ingest.sentry.io/<project id>`.
+        pub fn new(dsn: &str) -> Result<Self> {
+            let invalid = |reason: &str| {
+                SentryStrError::Config(format!("Invalid Sentry DSN '{}': {}", dsn, reason))
+            };
+
+            let (scheme, rest) = dsn
+                .trim()
+                .split_once("://")
+                .ok_or_else(|| invalid("missing scheme"))?;
+            let (public_key, rest) = rest
+                .split_once('@')
+                .ok_or_else(|| invalid("missing public key"))?;
+            let public_key = public_key.split(':').next().unwrap_or_default();
+            let (host, project_id) = rest
+                .trim_end_matches('/')
+                .rsplit_once('/')
+                .ok_or_else(|| invalid("missing project id"))?;
+            if public_key.is_empty() || host.is_empty() || project_id.is_empty() {
+                return Err(invalid("expected <scheme>://<key>@<host>/<project id>"));
+            }
+
+            let http = reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .map_err(|e| {
+                    SentryStrError::Config(format!("Failed to build the HTTP client: {}", e))
+                })?;
+
+            Ok(Self {
+                http,
+                endpoint: format!("{}://{}/api/{}/envelope/", scheme, host, project_id),
+                auth: format!(
+                    "Sentry sentry_version=7, sentry_key={}, sentry_client={}/{}",
+                    public_key,
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                ),
+            })
+        }
+
+        /// POSTs `event` as an envelope to the project, giving up after 10 seconds. A 429
+        /// fails with [`SentryStrError::RateLimited`], holding the `Retry-After` delay.
+        pub async fn forward(&self, event: &Event) -> Result<()> {
+            let response = self
+                .http
+                .post(&self.endpoint)
+                .header("X-Sentry-Auth", &self.auth)
+                .header("Content-Type", "application/x-sentry-envelope")
+                .body(to_sentry_envelope(event))
+                .send()
+                .await
+                .map_err(|e| SentryStrError::Publishing(format!("Sentry request failed: {}", e)))?;
+
+            if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse().ok())
+                    .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs);
+                return Err(SentryStrError::RateLimited {
+                    destination: "Sentry".to_string(),
+                    retry_after,
+                });
+            }
+            if !response.status().is_success() {
+                return Err(SentryStrError::Publishing(format!(
+                    "Sentry rejected the event with status {}",
+                    response.status()
+                )));
+            }
+            Ok(())
+        }
+    }
+
+    /// [`SentryForwarder`] as a transport of `Config::with_additional_transport`, so every
+    /// captured event is also written to a hosted Sentry. After a 429 it drops events
+    /// until the `Retry-After` delay passed, instead of hammering the project.
+    #[derive(Debug)]
+    pub struct SentryDsnTransport {
+        forwarder: SentryForwarder,
+        backoff_until: Mutex<Option<Instant>>,
+    }
+
+    impl SentryDsnTransport {
+        /// See [`SentryForwarder::new`] for the DSN format.
+        pub fn new(dsn: &str) -> Result<Self> {
+            Ok(Self {
+                forwarder: SentryForwarder::new(dsn)?,
+                backoff_until: Mutex::new(None),
+            })
+        }
+
+        async fn forward(&self, event: &Event) -> Result<()> {
+            let backoff_until = *self.backoff_until.lock().unwrap();
+            if let Some(until) = backoff_until {
+                let now = Instant::now();
+                if until > now {
+                    return Err(SentryStrError::RateLimited {
+                        destination: "Sentry".to_string(),
+                        retry_after: until - now,
+                    });
+                }
+            }
+
+            let result = self.forwarder.forward(event).await;
+            if let Err(SentryStrError::RateLimited { retry_after, .. }) = result {
+                *self.backoff_until.lock().unwrap() = Some(Instant::now() + retry_after);
+            }
+            result
+        }
+    }
+
+    impl Transport for SentryDsnTransport {
+        fn name(&self) -> &str {
+            "sentry"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            Box::pin(self.forward(event))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::{Config, NostrSentryClient};
+        use nostr::Keys;
+        use nostr_relay_builder::MockRelay;
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        /// A request the stub received.
+        #[derive(Debug, Clone)]
+        struct Received {
+            path: String,
+            headers: HashMap<String, String>,
+            body: String,
+        }
+
+        type Requests = Arc<Mutex<Vec<Received>>>;
+
+        /// An HTTP endpoint answering each request with the next of `answers`, a status
+        /// and a `Retry-After` value, repeating the last. Returns its address.
+        async fn stub(answers: Vec<(u16, Option<&'static str>)>) -> (String, Requests) {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap().to_string();
+            let requests = Requests::default();
+            let received = Arc::clone(&requests);
+
+            tokio::spawn(async move {
+                let mut answers = answers.into_iter();
+                let mut last = (200, None);
+                while let Ok((stream, _)) = listener.accept().await {
+                    if let Some(answer) = answers.next() {
+                        last = answer;
+                    }
+                    let (status, retry_after) = last;
+                    let mut stream = BufReader::new(stream);
+
+                    let mut request_line = String::new();
+                    stream.read_line(&mut request_line).await.unwrap();
+                    let mut headers = HashMap::new();
+                    loop {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        let Some((name, value)) = line.trim_end().split_once(':') else {
+                            break;
+                        };
+                        headers.insert(name.to_lowercase(), value.trim().to_string());
+                    }
+                    let length = headers["content-length"].parse().unwrap();
+                    let mut body = vec![0; length];
+                    stream.read_exact(&mut body).await.unwrap();
+                    received.lock().unwrap().push(Received {
+                        path: request_line.split(' ').nth(1).unwrap().to_string(),
+                        headers,
+                        body: String::from_utf8(body).unwrap(),
+                    });
+
+                    let retry_after = retry_after
+                        .map(|seconds| format!("retry-after: {}\r\n", seconds))
+                        .unwrap_or_default();
+                    let response = format!(
+                        "HTTP/1.1 {} Stub\r\n{}content-length: 0\r\nconnection: close\r\n\r\n",
+                        status, retry_after
+                    );
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                }
+            });
+            (address, requests)
+        }
+
+        fn dsn(address: &str) -> String {
+            format!("http://public-key@{}/42", address)
+        }
+
+        #[tokio::test]
+        async fn envelopes_are_posted_with_the_auth_header() {
+            let (address, requests) = stub(vec![(200, None)]).await;
+            let transport = SentryDsnTransport::new(&dsn(&address)).unwrap();
+            let event = Event::new().with_message("payment failed");
+
+            transport.send(&event).await.unwrap();
+
+            let requests = requests.lock().unwrap();
+            assert_eq!(requests.len(), 1);
+            let request = &requests[0];
+            assert_eq!(request.path, "/api/42/envelope/");
+            assert_eq!(
+                request.headers["content-type"],
+                "application/x-sentry-envelope"
+            );
+            let auth = &request.headers["x-sentry-auth"];
+            assert!(auth.starts_with("Sentry sentry_version=7, sentry_key=public-key,"));
+            assert!(auth.contains(concat!(
+                "sentry_client=sentrystr/",
+                env!("CARGO_PKG_VERSION")
+            )));
+
+            let lines: Vec<&str> = request.body.lines().collect();
+            assert_eq!(lines.len(), 3);
+            let item_header: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+            assert_eq!(item_header["length"], lines[2].len());
+            let payload: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+            assert_eq!(payload["message"], "payment failed");
+            assert_eq!(payload["event_id"], event.event_id.replace('-', ""));
+        }
+
+        #[tokio::test]
+        async fn rate_limits_are_honoured_until_retry_after() {
+            let (address, requests) = stub(vec![(429, Some("30")), (200, None)]).await;
+            let transport = SentryDsnTransport::new(&dsn(&address)).unwrap();
+            let event = Event::new().with_message("payment failed");
+
+            assert!(matches!(
+                transport.send(&event).await,
+                Err(SentryStrError::RateLimited { retry_after, .. })
+                    if retry_after == Duration::from_secs(30)
+            ));
+            assert!(matches!(
+                transport.send(&event).await,
+                Err(SentryStrError::RateLimited { retry_after, .. })
+                    if retry_after <= Duration::from_secs(30)
+            ));
+            assert_eq!(requests.lock().unwrap().len(), 1);
+        }
+
+        #[tokio::test]
+        async fn captures_go_to_the_relays_and_to_sentry() {
+            let relay = MockRelay::run().await.unwrap();
+            let (address, requests) = stub(vec![(200, None), (500, None), (429, Some("30"))]).await;
+            let config = Config::from_keys(&Keys::generate(), [relay.url().to_string()])
+                .unwrap()
+                .with_queue(10, Duration::from_secs(1))
+                .with_additional_transport(SentryDsnTransport::new(&dsn(&address)).unwrap());
+            let client = NostrSentryClient::new(config).await.unwrap();
+
+            for message in ["sent", "failed", "rate limited", "backing off"] {
+                client
+                    .capture_event(Event::new().with_message(message))
+                    .await
+                    .unwrap();
+                assert_eq!(client.flush(Duration::from_secs(5)).await, 0);
+            }
+
+            assert_eq!(requests.lock().unwrap().len(), 3);
+            let metrics = client.metrics();
+            assert_eq!(metrics.events_sent, 4);
+            assert_eq!(
+                metrics.transports["sentry"],
+                crate::TransportMetrics {
+                    sent: 1,
+                    failed: 1,
+                    rate_limited: 2,
+                }
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EVENT: &str = include_str!("../tests/fixtures/compat/event.json");
+    const SENTRY_EVENT: &str = include_str!("../tests/fixtures/compat/sentry_event.json");
+
+    fn event() -> Event {
+        serde_json::from_str(EVENT).unwrap()
+    }
+
+    #[test]
+    fn events_have_the_shape_sentry_expects() {
+        let mut payload = to_sentry_event(&event());
+        assert_eq!(payload["sdk"]["version"], env!("CARGO_PKG_VERSION"));
+        payload["sdk"]["version"] = json!("<version>");
+
+        let expected: Value = serde_json::from_str(SENTRY_EVENT).unwrap();
+        assert_eq!(payload, expected);
+    }
+
+    #[test]
+    fn envelopes_hold_one_event_item() {
+        let envelope = to_sentry_envelope(&event());
+        let lines: Vec<&str> = envelope.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        let header: Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(header["event_id"], "5c1f3e2a9b7d4e618f0a2d3c4b5a6e7f");
+        let item_header: Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(item_header["type"], "event");
+        assert_eq!(item_header["length"], lines[2].len());
+        let body: Value = serde_json::from_str(lines[2]).unwrap();
+        assert_eq!(body, to_sentry_event(&event()));
+    }
+}
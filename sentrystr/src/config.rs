@@ -1,15 +1,356 @@
+use crate::dedup::DEFAULT_DEDUP_MAX_ENTRIES;
+use crate::delivery::MinRelaySuccess;
+use crate::encryption::ContentEncryption;
+use crate::error::{EnvConfigError, EnvVarError};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::keyfile::PassphraseSource;
+use crate::pow::{PowConfig, PowPolicy};
+use crate::proxy::ProxyConfig;
+use crate::queue::{OverflowPolicy, QueueConfig, QueuePersistence};
+use crate::rate_limit::RateLimit;
+use crate::retry::RetryPolicy;
+use crate::sample::Sampler;
+use crate::transport::Transport;
+use crate::{Level, SentryStrError};
 use nostr::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SECRET_KEY_VAR: &str = "SENTRYSTR_SECRET_KEY";
+const RELAYS_VAR: &str = "SENTRYSTR_RELAYS";
+const ENVIRONMENT_VAR: &str = "SENTRYSTR_ENVIRONMENT";
+const RELEASE_VAR: &str = "SENTRYSTR_RELEASE";
+const SERVER_NAME_VAR: &str = "SENTRYSTR_SERVER_NAME";
+const EVENT_KIND_VAR: &str = "SENTRYSTR_EVENT_KIND";
+const AUTO_SERVER_NAME_VAR: &str = "SENTRYSTR_AUTO_SERVER_NAME";
+
+/// Commit variables set by common CI and hosting platforms, checked in order by
+/// [`Config::resolve_release`].
+const COMMIT_VARS: &[&str] = &[
+    "GIT_SHA",
+    "SOURCE_COMMIT",
+    "GITHUB_SHA",
+    "CI_COMMIT_SHA",
+    "VERCEL_GIT_COMMIT_SHA",
+    "HEROKU_SLUG_COMMIT",
+];
+
+/// Kind of SentryStr events unless configured otherwise.
+pub const DEFAULT_EVENT_KIND: u16 = 9898;
+/// Kinds accepted for SentryStr events: the NIP-01 range of regular events, which relays
+/// store, outside of the kinds below 1000 that NIPs already assign.
+pub const EVENT_KIND_RANGE: std::ops::RangeInclusive<u16> = 1000..=9999;
+
+/// Largest serialized event the client publishes unless configured otherwise, in line
+/// with the message limits common relays enforce.
+pub const DEFAULT_MAX_EVENT_BYTES: usize = 64 * 1024;
+
+/// Deepest nesting of arrays and objects in an extra unless configured otherwise.
+pub const DEFAULT_MAX_EXTRA_DEPTH: usize = 16;
+
+/// How far in the future the Nostr `created_at` of an event may be unless configured
+/// otherwise. Common relays reject events over 15 minutes ahead of their clock.
+pub const DEFAULT_MAX_FUTURE_SKEW: Duration = Duration::from_secs(10 * 60);
+/// How far in the past the Nostr `created_at` of an event may be unless configured
+/// otherwise.
+pub const DEFAULT_MAX_PAST_SKEW: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Largest attachment, before encoding, unless configured otherwise. Base64 makes it a
+/// third larger on the relay.
+pub const DEFAULT_MAX_ATTACHMENT_BYTES: usize = 32 * 1024;
+/// Largest total of the attachments of one event unless configured otherwise.
+pub const DEFAULT_MAX_ATTACHMENTS_BYTES: usize = 128 * 1024;
+
+/// Chooses the fingerprint of events captured without one.
+pub type Fingerprinter = fn(&crate::Event) -> Vec<String>;
+
+/// Rewrites or drops events before they are published, see [`Config::with_before_send`].
+#[derive(Clone)]
+pub struct BeforeSend(Arc<dyn Fn(crate::Event) -> Option<crate::Event> + Send + Sync>);
+
+impl BeforeSend {
+    pub fn new(
+        hook: impl Fn(crate::Event) -> Option<crate::Event> + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(hook))
+    }
+
+    /// Runs the hook, catching a panic so that it cannot take the caller down. The panic
+    /// message is returned as the error.
+    pub fn apply(&self, event: crate::Event) -> Result<Option<crate::Event>, String> {
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (self.0)(event))).map_err(
+            |panic| {
+                panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_string())
+            },
+        )
+    }
+}
+
+impl std::fmt::Debug for BeforeSend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BeforeSend")
+    }
+}
+
+/// Looks up the name of this machine, see [`Config::with_hostname_provider`].
+pub type HostnameProvider = fn() -> Option<String>;
+
+/// A relay and what it is used for, see [`Config::relays`].
+///
+/// Deserializes from a plain URL, used for both reading and writing, or from a table such
+/// as `{ url = "wss://relay.example", read = true, write = false }` where omitted roles
+/// default to `true`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "RelaySpecRepr")]
+pub struct RelaySpec {
+    pub url: String,
+    /// `EventCollector::from_config` fetches and subscribes from it.
+    pub read: bool,
+    /// `NostrSentryClient` publishes to it.
+    pub write: bool,
+}
+
+impl RelaySpec {
+    /// A relay used for both reading and writing.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            read: true,
+            write: true,
+        }
+    }
+
+    /// A relay only read from, such as a public aggregator.
+    pub fn read_only(url: impl Into<String>) -> Self {
+        Self {
+            write: false,
+            ..Self::new(url)
+        }
+    }
+
+    /// A relay only published to.
+    pub fn write_only(url: impl Into<String>) -> Self {
+        Self {
+            read: false,
+            ..Self::new(url)
+        }
+    }
+}
+
+impl From<String> for RelaySpec {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for RelaySpec {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+/// The accepted serialized forms of a [`RelaySpec`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RelaySpecRepr {
+    Url(String),
+    Table {
+        url: String,
+        #[serde(default = "default_relay_role")]
+        read: bool,
+        #[serde(default = "default_relay_role")]
+        write: bool,
+    },
+}
+
+impl From<RelaySpecRepr> for RelaySpec {
+    fn from(repr: RelaySpecRepr) -> Self {
+        match repr {
+            RelaySpecRepr::Url(url) => Self::new(url),
+            RelaySpecRepr::Table { url, read, write } => Self { url, read, write },
+        }
+    }
+}
+
+fn default_relay_role() -> bool {
+    true
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub secret_key: String,
-    pub relays: Vec<String>,
+    /// Relays with their roles: the client publishes to the write relays and
+    /// `EventCollector::from_config` reads from the read relays.
+    pub relays: Vec<RelaySpec>,
     pub encrypt_events: bool,
     pub recipient_pubkey: Option<String>,
     pub event_kind: u16,
     pub tags: Option<Vec<Tag>>,
+    /// `[key, value]` tags on every published event, see [`Config::with_nostr_tag`].
+    #[serde(default)]
+    pub nostr_tags: BTreeMap<String, String>,
+    /// Derive `service`, `env`, `component` and `severity` tags from each event.
+    #[serde(default)]
+    pub standard_nostr_tags: bool,
     pub encryption_version: EncryptionVersion,
+    /// How many breadcrumbs the client keeps and attaches to each event.
+    #[serde(default = "default_max_breadcrumbs")]
+    pub max_breadcrumbs: usize,
+    /// Events whose content is larger are truncated before signing, see
+    /// [`Config::with_max_event_bytes`].
+    #[serde(default = "default_max_event_bytes")]
+    pub max_event_bytes: usize,
+    /// Extras nested deeper are cut, see [`Config::with_max_extra_depth`].
+    #[serde(default = "default_max_extra_depth")]
+    pub max_extra_depth: usize,
+    /// Earliest `created_at` relative to now, see [`Config::with_timestamp_window`].
+    #[serde(default = "default_max_past_skew")]
+    pub max_past_skew: Duration,
+    /// Latest `created_at` relative to now, see [`Config::with_timestamp_window`].
+    #[serde(default = "default_max_future_skew")]
+    pub max_future_skew: Duration,
+    /// Largest single attachment, see [`Config::with_attachment_limits`].
+    #[serde(default = "default_max_attachment_bytes")]
+    pub max_attachment_bytes: usize,
+    /// Largest total of the attachments of one event.
+    #[serde(default = "default_max_attachments_bytes")]
+    pub max_attachments_bytes: usize,
+    /// Most events published per period, see [`Config::with_rate_limit`].
+    #[serde(default)]
+    pub rate_limit: Option<RateLimit>,
+    /// Derives a rate limit from the NIP-11 documents of the relays at connect time.
+    #[serde(default)]
+    pub auto_rate_limit: bool,
+    /// Share of events kept, from 0 to 1, see [`Config::with_sample_rate`].
+    #[serde(default)]
+    pub sample_rate: Option<f64>,
+    /// Sample rates overriding `sample_rate` for some levels.
+    #[serde(default)]
+    pub level_sample_rates: HashMap<Level, f64>,
+    /// Draws the numbers deciding which events are kept; random when unset.
+    #[serde(skip)]
+    pub sampler: Option<Sampler>,
+    /// Window within which repeated events are suppressed, see [`Config::with_dedup`].
+    #[serde(default)]
+    pub dedup_window: Option<Duration>,
+    /// Most fingerprints tracked at once by the deduplication.
+    #[serde(default = "default_dedup_max_entries")]
+    pub dedup_max_entries: usize,
+    /// Defaults to [`crate::default_fingerprint`] when unset.
+    #[serde(skip)]
+    pub fingerprinter: Option<Fingerprinter>,
+    /// Last look at every event before it is published.
+    #[serde(skip)]
+    pub before_send: Option<BeforeSend>,
+    /// Destinations every captured event is also sent to.
+    #[serde(skip)]
+    pub additional_transports: Vec<Arc<dyn Transport>>,
+    /// Stamps a per-client sequence number on every published event.
+    #[serde(default)]
+    pub sequence_numbers: bool,
+    /// File keeping the last sequence number across restarts.
+    #[serde(default)]
+    pub sequence_state: Option<PathBuf>,
+    /// Set on captured events that do not name their own environment.
+    #[serde(default)]
+    pub environment: Option<String>,
+    /// Set on captured events that do not name their own release.
+    #[serde(default)]
+    pub release: Option<String>,
+    /// Detect the release when `release` is unset, see [`Config::resolve_release`].
+    #[serde(default)]
+    pub auto_release: bool,
+    /// Last resort of release detection, typically the crate name and version.
+    #[serde(skip)]
+    pub release_fallback: Option<&'static str>,
+    /// Set on captured events that do not name their own server.
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Use the hostname as `server_name` when neither the event nor the config sets one.
+    #[serde(default = "default_auto_server_name")]
+    pub auto_server_name: bool,
+    /// Defaults to [`detect_hostname`] when unset.
+    #[serde(skip)]
+    pub hostname_provider: Option<HostnameProvider>,
+    /// Set on captured events that do not name their own user.
+    #[serde(default)]
+    pub user: Option<crate::User>,
+    /// Publish from a background queue instead of in `capture_event`.
+    #[serde(default)]
+    pub queue: Option<QueueConfig>,
+    /// Retries of failed publishes; `None` makes a single attempt.
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+    /// Longest `capture_event` waits for relays, retries included; `None` waits for
+    /// every relay to answer.
+    #[serde(default)]
+    pub publish_timeout: Option<Duration>,
+    /// Relays that must accept an event; one when unset.
+    #[serde(default)]
+    pub min_relay_success: Option<MinRelaySuccess>,
+    /// Encrypts event content for chosen readers; takes precedence over `encrypt_events`.
+    #[serde(default)]
+    pub content_encryption: Option<ContentEncryption>,
+    /// Event fields published as [`crate::PLAINTEXT_TAG`]s next to encrypted content, so
+    /// relays can still filter on them: `level` or the key of any event tag, such as
+    /// `service`.
+    #[serde(default)]
+    pub plaintext_tags: Vec<String>,
+    /// How long relays should keep events (NIP-40); `None` keeps them indefinitely.
+    #[serde(default)]
+    pub event_ttl: Option<Duration>,
+    /// Per-level TTLs, taking precedence over `event_ttl`.
+    #[serde(default)]
+    pub level_ttls: HashMap<Level, Duration>,
+    /// NIP-13 proof of work mined into published events.
+    #[serde(default)]
+    pub pow: Option<PowConfig>,
+    /// SOCKS5 proxy for relay connections; required for `.onion` relays.
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+}
+
+fn default_max_breadcrumbs() -> usize {
+    100
+}
+
+fn default_max_event_bytes() -> usize {
+    DEFAULT_MAX_EVENT_BYTES
+}
+
+fn default_max_extra_depth() -> usize {
+    DEFAULT_MAX_EXTRA_DEPTH
+}
+
+fn default_dedup_max_entries() -> usize {
+    DEFAULT_DEDUP_MAX_ENTRIES
+}
+
+fn default_max_past_skew() -> Duration {
+    DEFAULT_MAX_PAST_SKEW
+}
+
+fn default_max_future_skew() -> Duration {
+    DEFAULT_MAX_FUTURE_SKEW
+}
+
+fn default_max_attachment_bytes() -> usize {
+    DEFAULT_MAX_ATTACHMENT_BYTES
+}
+
+fn default_max_attachments_bytes() -> usize {
+    DEFAULT_MAX_ATTACHMENTS_BYTES
+}
+
+fn default_auto_server_name() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,18 +360,181 @@ pub enum EncryptionVersion {
 }
 
 impl Config {
-    pub fn new(secret_key: String, relays: Vec<String>) -> Self {
+    /// Validates `secret_key` (64-character hex or nsec, surrounding whitespace ignored)
+    /// and `relays` (ws/wss URLs with a host, at least one) and merges duplicate relays.
+    /// Relays are URLs, used for reading and writing, or [`RelaySpec`]s. Key errors never
+    /// include the key itself.
+    pub fn try_new(
+        secret_key: String,
+        relays: impl IntoIterator<Item = impl Into<RelaySpec>>,
+    ) -> Result<Self, SentryStrError> {
+        let secret_key = parse_secret_key(&secret_key).map_err(SentryStrError::InvalidKey)?;
+        let relays = validate_relays(relays).map_err(SentryStrError::Config)?;
+        Ok(Self::unchecked(secret_key, relays))
+    }
+
+    /// [`Config::try_new`] for callers already holding `Keys`.
+    pub fn from_keys(
+        keys: &Keys,
+        relays: impl IntoIterator<Item = impl Into<RelaySpec>>,
+    ) -> Result<Self, SentryStrError> {
+        let relays = validate_relays(relays).map_err(SentryStrError::Config)?;
+        Ok(Self::unchecked(String::new(), relays).with_keys(keys))
+    }
+
+    #[deprecated(note = "use `Config::try_new`, which rejects invalid keys and relays")]
+    pub fn new(secret_key: String, relays: impl IntoIterator<Item = impl Into<RelaySpec>>) -> Self {
+        Self::unchecked(secret_key, relays.into_iter().map(Into::into).collect())
+    }
+
+    fn unchecked(secret_key: String, relays: Vec<RelaySpec>) -> Self {
         Self {
             secret_key,
             relays,
             encrypt_events: false,
             recipient_pubkey: None,
-            event_kind: 9898,
+            event_kind: DEFAULT_EVENT_KIND,
             tags: None,
+            nostr_tags: BTreeMap::new(),
+            standard_nostr_tags: false,
             encryption_version: EncryptionVersion::None,
+            max_breadcrumbs: default_max_breadcrumbs(),
+            max_extra_depth: default_max_extra_depth(),
+            max_past_skew: default_max_past_skew(),
+            max_future_skew: default_max_future_skew(),
+            max_event_bytes: default_max_event_bytes(),
+            max_attachment_bytes: default_max_attachment_bytes(),
+            max_attachments_bytes: default_max_attachments_bytes(),
+            rate_limit: None,
+            auto_rate_limit: false,
+            sample_rate: None,
+            level_sample_rates: HashMap::new(),
+            sampler: None,
+            dedup_window: None,
+            dedup_max_entries: default_dedup_max_entries(),
+            fingerprinter: None,
+            before_send: None,
+            additional_transports: Vec::new(),
+            sequence_numbers: false,
+            sequence_state: None,
+            environment: None,
+            release: None,
+            auto_release: false,
+            release_fallback: None,
+            server_name: None,
+            auto_server_name: default_auto_server_name(),
+            hostname_provider: None,
+            user: None,
+            queue: None,
+            retry: None,
+            publish_timeout: None,
+            min_relay_success: None,
+            content_encryption: None,
+            plaintext_tags: Vec::new(),
+            event_ttl: None,
+            level_ttls: HashMap::new(),
+            pow: None,
+            proxy: None,
         }
     }
 
+    /// Reads the configuration from `SENTRYSTR_SECRET_KEY` (hex or nsec) and
+    /// `SENTRYSTR_RELAYS` (comma-separated), both required, plus the optional
+    /// `SENTRYSTR_ENVIRONMENT`, `SENTRYSTR_RELEASE`, `SENTRYSTR_SERVER_NAME`,
+    /// `SENTRYSTR_AUTO_SERVER_NAME` and `SENTRYSTR_EVENT_KIND`. Every missing or invalid
+    /// variable is reported at once.
+    pub fn from_env() -> Result<Self, EnvConfigError> {
+        Self::from_env_with(|name| std::env::var(name).ok())
+    }
+
+    /// [`Config::from_env`] with variables looked up through `lookup` instead of the
+    /// process environment.
+    pub fn from_env_with(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, EnvConfigError> {
+        let mut errors = Vec::new();
+        for name in [SECRET_KEY_VAR, RELAYS_VAR] {
+            if env_value(&lookup, name).is_none() {
+                errors.push(EnvVarError::Missing(name));
+            }
+        }
+
+        let config = Self::unchecked(String::new(), Vec::new()).apply_env(&lookup, &mut errors);
+        EnvConfigError::check(errors).map(|()| config)
+    }
+
+    /// Overrides `fallback` with whichever `SENTRYSTR_*` variables are set; none are
+    /// required. Invalid values are still reported.
+    pub fn from_env_or(fallback: Config) -> Result<Self, EnvConfigError> {
+        Self::from_env_or_with(fallback, |name| std::env::var(name).ok())
+    }
+
+    /// [`Config::from_env_or`] with variables looked up through `lookup`.
+    pub fn from_env_or_with(
+        fallback: Config,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, EnvConfigError> {
+        let mut errors = Vec::new();
+        let config = fallback.apply_env(&lookup, &mut errors);
+        EnvConfigError::check(errors).map(|()| config)
+    }
+
+    fn apply_env(
+        mut self,
+        lookup: &impl Fn(&str) -> Option<String>,
+        errors: &mut Vec<EnvVarError>,
+    ) -> Self {
+        if let Some(secret_key) = env_value(lookup, SECRET_KEY_VAR) {
+            match parse_secret_key(&secret_key) {
+                Ok(secret_key) => self.secret_key = secret_key,
+                Err(e) => errors.push(EnvVarError::invalid(SECRET_KEY_VAR, e)),
+            }
+        }
+
+        if let Some(relays) = env_value(lookup, RELAYS_VAR) {
+            let relays = relays
+                .split(',')
+                .map(str::trim)
+                .filter(|relay| !relay.is_empty());
+
+            match validate_relays(relays) {
+                Ok(relays) => self.relays = relays,
+                Err(e) => errors.push(EnvVarError::invalid(RELAYS_VAR, e)),
+            }
+        }
+
+        if let Some(event_kind) = env_value(lookup, EVENT_KIND_VAR) {
+            match event_kind
+                .parse()
+                .map_err(|e: std::num::ParseIntError| e.to_string())
+                .and_then(|event_kind| validate_event_kind(event_kind).map(|()| event_kind))
+            {
+                Ok(event_kind) => self.event_kind = event_kind,
+                Err(e) => errors.push(EnvVarError::invalid(EVENT_KIND_VAR, e)),
+            }
+        }
+
+        if let Some(environment) = env_value(lookup, ENVIRONMENT_VAR) {
+            self.environment = Some(environment);
+        }
+        if let Some(release) = env_value(lookup, RELEASE_VAR) {
+            self.release = Some(release);
+        }
+        if let Some(server_name) = env_value(lookup, SERVER_NAME_VAR) {
+            self.server_name = Some(server_name);
+        }
+        if let Some(auto_server_name) = env_value(lookup, AUTO_SERVER_NAME_VAR) {
+            match auto_server_name.to_lowercase().as_str() {
+                "true" | "1" | "yes" => self.auto_server_name = true,
+                "false" | "0" | "no" => self.auto_server_name = false,
+                _ => errors.push(EnvVarError::invalid(
+                    AUTO_SERVER_NAME_VAR,
+                    "expected true or false",
+                )),
+            }
+        }
+
+        self
+    }
+
     pub fn with_encryption(mut self, recipient_pubkey: String) -> Self {
         self.encrypt_events = true;
         self.recipient_pubkey = Some(recipient_pubkey);
@@ -45,13 +549,452 @@ impl Config {
         self
     }
 
+    /// Publishes event content that only the given readers can decrypt, see
+    /// [`ContentEncryption::decrypt`].
+    pub fn with_content_encryption(mut self, encryption: ContentEncryption) -> Self {
+        self.content_encryption = Some(encryption);
+        self
+    }
+
+    /// Keeps `level` or the named event tags readable next to encrypted content.
+    pub fn with_plaintext_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.plaintext_tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Publishes events with `event_kind` instead of [`DEFAULT_EVENT_KIND`], to keep
+    /// deployments apart. It must lie in [`EVENT_KIND_RANGE`], which
+    /// `NostrSentryClient::new` checks.
+    pub fn with_event_kind(mut self, event_kind: u16) -> Self {
+        self.event_kind = event_kind;
+        self
+    }
+
+    /// Publishes with `keys` instead of the configured secret key.
+    pub fn with_keys(mut self, keys: &Keys) -> Self {
+        self.secret_key = keys.secret_key().display_secret().to_string();
+        self
+    }
+
+    /// Uses the key in the file at `path`, a NIP-49 `ncryptsec` decrypted with the
+    /// passphrase from `passphrase` or a plaintext hex or nsec key. See
+    /// [`crate::keyfile::read_key_file`] for the errors. Not available on wasm32.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_encrypted_key_file(
+        self,
+        path: impl AsRef<std::path::Path>,
+        passphrase: PassphraseSource,
+    ) -> Result<Self, SentryStrError> {
+        let keys = crate::keyfile::read_key_file(path, &passphrase)?;
+        Ok(self.with_keys(&keys))
+    }
+
     pub fn with_tags(mut self, tags: Vec<Tag>) -> Self {
         self.tags = Some(tags);
         self
     }
 
+    /// Adds a `[key, value]` tag to every published event, so relays and the collector's
+    /// tag filters (`--service`, `--env`) can select them. An event tag with the same
+    /// key, in `Event::tags` or `Event::nostr_tags`, overrides the value.
+    pub fn with_nostr_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.nostr_tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Tags every published event with `service` and `component` from its tags of the
+    /// same name, `env` from its environment and `severity` from its level, overriding
+    /// [`Config::with_nostr_tag`] values. Left out of encrypted events, where
+    /// [`Config::with_plaintext_tags`] chooses what stays readable.
+    pub fn with_standard_nostr_tags(mut self, enabled: bool) -> Self {
+        self.standard_nostr_tags = enabled;
+        self
+    }
+
+    /// Sets the breadcrumb buffer size; `0` disables breadcrumbs.
+    pub fn with_max_breadcrumbs(mut self, max_breadcrumbs: usize) -> Self {
+        self.max_breadcrumbs = max_breadcrumbs;
+        self
+    }
+
+    /// Caps the content of published events at `max_event_bytes`, after encryption and
+    /// its base64 encoding when the content is encrypted.
+    /// Larger events lose string extras, then whole extras (listed in the
+    /// `truncated_keys` extra), then their oldest breadcrumbs, then the end of their
+    /// message. Events still too large fail with `SentryStrError::EventTooLarge`.
+    pub fn with_max_event_bytes(mut self, max_event_bytes: usize) -> Self {
+        self.max_event_bytes = max_event_bytes;
+        self
+    }
+
+    /// Caps the nesting of arrays and objects in extras at `max_extra_depth` levels,
+    /// the extra itself being the first. Anything deeper is replaced with a
+    /// `…[max depth]` marker before the event is serialized.
+    pub fn with_max_extra_depth(mut self, max_extra_depth: usize) -> Self {
+        self.max_extra_depth = max_extra_depth;
+        self
+    }
+
+    /// Publishes events with a Nostr `created_at` of their timestamp, moved into the
+    /// window from `max_past` before now to `max_future` after now so relays accept it.
+    /// The content keeps the original timestamp, and a moved event gets a
+    /// `clock_skew_ms` extra: how far its timestamp was ahead of the `created_at`,
+    /// negative when it was older.
+    pub fn with_timestamp_window(mut self, max_past: Duration, max_future: Duration) -> Self {
+        self.max_past_skew = max_past;
+        self.max_future_skew = max_future;
+        self
+    }
+
+    /// Caps each attachment at `per_attachment` bytes and those of one event at `total`.
+    /// Captures exceeding either fail before anything is published.
+    pub fn with_attachment_limits(mut self, per_attachment: usize, total: usize) -> Self {
+        self.max_attachment_bytes = per_attachment;
+        self.max_attachments_bytes = total;
+        self
+    }
+
+    pub fn with_environment(mut self, environment: impl Into<String>) -> Self {
+        self.environment = Some(environment.into());
+        self
+    }
+
+    pub fn with_release(mut self, release: impl Into<String>) -> Self {
+        self.release = Some(release.into());
+        self
+    }
+
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    /// Whether events without a release get a detected one, see
+    /// [`Config::resolve_release`]. Off by default.
+    pub fn with_auto_release(mut self, auto_release: bool) -> Self {
+        self.auto_release = auto_release;
+        self
+    }
+
+    /// Release used by automatic detection when no variable names one. Pass
+    /// `concat!(env!("CARGO_PKG_NAME"), "@", env!("CARGO_PKG_VERSION"))` from the
+    /// application so it names the application rather than this crate.
+    pub fn with_release_fallback(mut self, release: &'static str) -> Self {
+        self.release_fallback = Some(release);
+        self
+    }
+
+    /// The release set on events without one: `release`, else with `auto_release` the
+    /// first of `SENTRYSTR_RELEASE`, a CI commit variable (`GIT_SHA`, `SOURCE_COMMIT`,
+    /// `GITHUB_SHA`, ...) and the release fallback.
+    pub fn resolve_release(&self) -> Option<String> {
+        self.resolve_release_with(|name| std::env::var(name).ok())
+    }
+
+    /// [`Config::resolve_release`] with variables looked up through `lookup`.
+    pub fn resolve_release_with(&self, lookup: impl Fn(&str) -> Option<String>) -> Option<String> {
+        if self.release.is_some() || !self.auto_release {
+            return self.release.clone();
+        }
+
+        std::iter::once(RELEASE_VAR)
+            .chain(COMMIT_VARS.iter().copied())
+            .find_map(|name| env_value(&lookup, name))
+            .or_else(|| self.release_fallback.map(str::to_string))
+    }
+
+    /// Whether events without a server name get the hostname (the default). Turn it off
+    /// where the hostname must not leave the machine.
+    pub fn with_auto_server_name(mut self, auto_server_name: bool) -> Self {
+        self.auto_server_name = auto_server_name;
+        self
+    }
+
+    /// Replaces [`detect_hostname`] as the source of the automatic server name.
+    pub fn with_hostname_provider(mut self, provider: HostnameProvider) -> Self {
+        self.hostname_provider = Some(provider);
+        self
+    }
+
+    /// The server name set on events without one: `server_name`, else the hostname
+    /// unless `auto_server_name` is off. Looks the hostname up on every call.
+    pub fn resolve_server_name(&self) -> Option<String> {
+        if self.server_name.is_some() || !self.auto_server_name {
+            return self.server_name.clone();
+        }
+        (self.hostname_provider.unwrap_or(detect_hostname))()
+    }
+
+    pub fn with_user(mut self, user: crate::User) -> Self {
+        self.user = Some(user);
+        self
+    }
+
+    /// Makes `capture_event` queue events, holding at most `max_pending`, for a
+    /// background task that publishes them in order at least every `flush_interval`.
+    /// Use `NostrSentryClient::flush` before shutting down. `NostrSentryClient::new`
+    /// fails on a zero `flush_interval`.
+    pub fn with_queue(mut self, max_pending: usize, flush_interval: Duration) -> Self {
+        self.queue = Some(QueueConfig::new(max_pending, flush_interval));
+        self
+    }
+
+    /// Publishes up to `concurrency` queued events at once. Requires [`Config::with_queue`].
+    pub fn with_queue_concurrency(mut self, concurrency: usize) -> Self {
+        if let Some(ref mut queue) = self.queue {
+            queue.concurrency = concurrency.max(1);
+        }
+        self
+    }
+
+    /// Sets what happens when the queue is full. Requires [`Config::with_queue`].
+    pub fn with_queue_overflow(mut self, overflow: OverflowPolicy) -> Self {
+        if let Some(ref mut queue) = self.queue {
+            queue.overflow = overflow;
+        }
+        self
+    }
+
+    /// Keeps queued events in an append-only file at `path` until they reach
+    /// `min_relay_success` relays, so events captured before a crash or restart are
+    /// published by the next client opening the file, before any new event. The file
+    /// holds at most `max_bytes`; events beyond that are only queued in memory. Fails
+    /// without [`Config::with_queue`], which must come first.
+    pub fn with_persistent_queue(
+        mut self,
+        path: impl Into<PathBuf>,
+        max_bytes: u64,
+    ) -> Result<Self, SentryStrError> {
+        let Some(ref mut queue) = self.queue else {
+            return Err(SentryStrError::Config(
+                "A persistent queue requires Config::with_queue first".to_string(),
+            ));
+        };
+        queue.persistence = Some(QueuePersistence {
+            path: path.into(),
+            max_bytes,
+        });
+        Ok(self)
+    }
+
+    /// Retries publishes that fail for transient reasons (connection errors, timeouts,
+    /// rate limiting) with exponential backoff.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Bounds how long publishing an event may take. Relays that have not answered by
+    /// then count as not accepting it.
+    pub fn with_publish_timeout(mut self, timeout: Duration) -> Self {
+        self.publish_timeout = Some(timeout);
+        self
+    }
+
+    /// Requires `min` relays to accept each event, as a count (`2`) or a share of the
+    /// configured relays (`Percentage(50)`). Publishing resolves as soon as enough relays
+    /// have accepted; if they do not, it fails with [`SentryStrError::PartialDelivery`].
+    pub fn with_min_relay_success(mut self, min: impl Into<MinRelaySuccess>) -> Self {
+        self.min_relay_success = Some(min.into());
+        self
+    }
+
+    /// Tags published events with a NIP-40 `expiration` of their timestamp plus `ttl`,
+    /// so relays may drop them afterwards. `Duration::ZERO` disables expiration.
+    pub fn with_event_ttl(mut self, ttl: Duration) -> Self {
+        self.event_ttl = Some(ttl);
+        self
+    }
+
+    /// Overrides the event TTL for events of `level`; `Duration::ZERO` keeps them
+    /// indefinitely.
+    pub fn with_level_ttl(mut self, level: Level, ttl: Duration) -> Self {
+        self.level_ttls.insert(level, ttl);
+        self
+    }
+
+    /// The TTL applying to events of `level`, if they expire.
+    pub fn ttl_for(&self, level: &Level) -> Option<Duration> {
+        self.level_ttls
+            .get(level)
+            .copied()
+            .or(self.event_ttl)
+            .filter(|ttl| !ttl.is_zero())
+    }
+
+    /// Connects to relays through a SOCKS5 proxy, such as Tor at
+    /// `socks5://127.0.0.1:9050`, which also allows `.onion` relays.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Mines a NIP-13 nonce giving event ids `difficulty` leading zero bits, as some
+    /// relays require. Mining runs on the blocking pool within
+    /// [`Config::with_pow_budget`].
+    pub fn with_pow(mut self, difficulty: u8) -> Self {
+        self.pow.get_or_insert_with(PowConfig::default).difficulty = difficulty;
+        self
+    }
+
+    /// Overrides the difficulty for events of `level`; `0` skips mining them.
+    pub fn with_level_pow(mut self, level: Level, difficulty: u8) -> Self {
+        self.pow
+            .get_or_insert_with(PowConfig::default)
+            .level_difficulty
+            .insert(level, difficulty);
+        self
+    }
+
+    /// Limits the time spent mining one event, and what happens when it runs out.
+    pub fn with_pow_budget(mut self, time_budget: Duration, policy: PowPolicy) -> Self {
+        let pow = self.pow.get_or_insert_with(PowConfig::default);
+        pow.time_budget = time_budget;
+        pow.policy = policy;
+        self
+    }
+
+    /// Publishes at most `max_events` Nostr events every `per`, attachments included,
+    /// across all capture paths. Bursts of up to `max_events` go out at once. When the
+    /// limit is reached, queued events wait their turn and, without a queue,
+    /// the event is dropped, giving `CaptureOutcome::Throttled`. A
+    /// `max_events` of 0 removes the limit.
+    pub fn with_rate_limit(mut self, max_events: u32, per: Duration) -> Self {
+        self.rate_limit = (max_events > 0).then(|| RateLimit::new(max_events, per));
+        self
+    }
+
+    /// Fetches the NIP-11 document of each relay when the client connects and applies
+    /// the strictest limit they advertise, or [`Config::with_rate_limit`] if stricter.
+    /// Needs the `nip11` feature.
+    pub fn with_auto_rate_limit(mut self, enabled: bool) -> Self {
+        self.auto_rate_limit = enabled;
+        self
+    }
+
+    /// Keeps only a share of the captured events, from 0 (none) to 1 (all, the
+    /// default). Dropped events are never serialized and give
+    /// `CaptureOutcome::Sampled`. Events marked with `Event::with_force_send`,
+    /// such as those of `capture_error`, are always kept.
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = Some(rate.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Sample rates for particular levels, e.g. to keep every error but a tenth of the
+    /// debug events. Levels not listed use the rate of [`Config::with_sample_rate`].
+    pub fn with_level_sample_rates(mut self, rates: HashMap<Level, f64>) -> Self {
+        self.level_sample_rates = rates
+            .into_iter()
+            .map(|(level, rate)| (level, rate.clamp(0.0, 1.0)))
+            .collect();
+        self
+    }
+
+    /// Replaces the random numbers deciding which events are kept, e.g. with a seeded
+    /// generator in tests. `sampler` returns numbers in `[0, 1)`; an event is kept when
+    /// the number is below its rate.
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Suppresses events repeating an earlier one within `window` of its first
+    /// occurrence; they give `CaptureOutcome::Deduplicated`.
+    /// Events match on their fingerprint when they set one, else on level, message and
+    /// logger. Once the window closes, a copy of the first event is published with the
+    /// `occurrences`, `first_seen` and `last_seen` extras, unless nothing was suppressed.
+    /// A timer publishes it within another `window`, with the scope of the first event.
+    /// Events marked with `Event::with_force_send` are never suppressed.
+    pub fn with_dedup(mut self, window: Duration) -> Self {
+        self.dedup_window = Some(window);
+        self
+    }
+
+    /// Most fingerprints [`Config::with_dedup`] tracks at once, [`DEFAULT_DEDUP_MAX_ENTRIES`]
+    /// by default. When full, the oldest window is closed early.
+    pub fn with_dedup_max_entries(mut self, max_entries: usize) -> Self {
+        self.dedup_max_entries = max_entries;
+        self
+    }
+
+    /// URLs of the relays events are published to.
+    pub fn write_relays(&self) -> Vec<String> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.write)
+            .map(|relay| relay.url.clone())
+            .collect()
+    }
+
+    /// URLs of the relays events are collected from.
+    pub fn read_relays(&self) -> Vec<String> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.read)
+            .map(|relay| relay.url.clone())
+            .collect()
+    }
+
+    /// The share of events at `level` that are kept.
+    pub fn sample_rate_for(&self, level: &Level) -> f64 {
+        self.level_sample_rates
+            .get(level)
+            .copied()
+            .or(self.sample_rate)
+            .unwrap_or(1.0)
+    }
+
+    /// Stamps every published event with the next number of a counter starting at 1, as
+    /// the `seq` extra and the `n` Nostr tag, so collectors can detect events lost on the
+    /// way. Sampled, throttled and filtered events take no number. Queued clients must
+    /// keep the `Block` overflow policy.
+    pub fn with_sequence_numbers(mut self, enabled: bool) -> Self {
+        self.sequence_numbers = enabled;
+        self
+    }
+
+    /// Saves the last sequence number to `path` so that it survives restarts, and
+    /// enables sequence numbers.
+    pub fn with_sequence_state(mut self, path: impl Into<PathBuf>) -> Self {
+        self.sequence_numbers = true;
+        self.sequence_state = Some(path.into());
+        self
+    }
+
+    pub fn with_fingerprinter(mut self, fingerprinter: Fingerprinter) -> Self {
+        self.fingerprinter = Some(fingerprinter);
+        self
+    }
+
+    /// Runs `hook` on every captured event once the client defaults, scopes and
+    /// fingerprint are applied, before size limits and serialization: the place for
+    /// scrubbing policies. Returning `None` drops the event, which gives
+    /// `CaptureOutcome::Filtered`. A panicking hook drops the event with
+    /// `SentryStrError::BeforeSendPanicked` and leaves the client usable.
+    pub fn with_before_send(
+        mut self,
+        hook: impl Fn(crate::Event) -> Option<crate::Event> + Send + Sync + 'static,
+    ) -> Self {
+        self.before_send = Some(BeforeSend::new(hook));
+        self
+    }
+
+    /// Sends every captured event to `transport` as well, such as
+    /// `compat::SentryDsnTransport` to write to a hosted Sentry while migrating. Events
+    /// go out after the `before_send` hook, concurrently with the Nostr publish; transport
+    /// failures are logged and counted in `ClientMetrics::transports` only.
+    pub fn with_additional_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.additional_transports.push(Arc::new(transport));
+        self
+    }
+
     pub fn get_keys(&self) -> Result<Keys, nostr::key::Error> {
-        Keys::parse(&self.secret_key)
+        Keys::parse(self.secret_key.trim())
     }
 
     pub fn get_recipient_pubkey(&self) -> Result<Option<PublicKey>, nostr::key::Error> {
@@ -61,3 +1004,344 @@ impl Config {
         }
     }
 }
+
+/// The hostname of this machine, falling back to the `HOSTNAME` and `COMPUTERNAME`
+/// variables when the system call fails or returns nothing usable. Always `None` on
+/// wasm32, where browsers expose neither.
+pub fn detect_hostname() -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let hostname = gethostname::gethostname().into_string().ok();
+    #[cfg(target_arch = "wasm32")]
+    let hostname: Option<String> = None;
+    hostname
+        .into_iter()
+        .chain(
+            ["HOSTNAME", "COMPUTERNAME"]
+                .into_iter()
+                .filter_map(|name| std::env::var(name).ok()),
+        )
+        .map(|hostname| hostname.trim().to_string())
+        .find(|hostname| !hostname.is_empty())
+}
+
+/// The trimmed value of `name`, treating empty values as unset.
+fn env_value(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Option<String> {
+    lookup(name)
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Trims `secret_key` and checks that it is a 64-character hex or nsec key. Errors
+/// describe what is wrong with the key without including it.
+fn parse_secret_key(secret_key: &str) -> Result<String, String> {
+    let secret_key = secret_key.trim();
+    let length = secret_key.chars().count();
+
+    if secret_key.starts_with("npub1") {
+        return Err("got a public key (npub), expected the nsec".to_string());
+    }
+
+    if secret_key.starts_with("nsec1") {
+        SecretKey::parse(secret_key).map_err(|e| format!("malformed nsec: {}", e))?;
+    } else if !secret_key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "expected 64 hex characters or an nsec1 string, got {} other characters",
+            length
+        ));
+    } else if length != 64 {
+        return Err(format!("expected 64 hex characters, got {}", length));
+    } else {
+        SecretKey::parse(secret_key).map_err(|e| format!("malformed hex key: {}", e))?;
+    }
+
+    Ok(secret_key.to_string())
+}
+
+/// Parses each relay as a ws/wss URL with a host and merges duplicates into the first
+/// occurrence, with the roles of all of them.
+fn validate_relays(
+    relays: impl IntoIterator<Item = impl Into<RelaySpec>>,
+) -> Result<Vec<RelaySpec>, String> {
+    let mut valid: Vec<RelaySpec> = Vec::new();
+
+    for relay in relays {
+        let relay = relay.into();
+        if !relay.read && !relay.write {
+            return Err(format!("Relay '{}' is neither read nor written", relay.url));
+        }
+        let url = validate_relay(&relay.url)?;
+        match valid.iter_mut().find(|known| known.url == url) {
+            Some(known) => {
+                known.read |= relay.read;
+                known.write |= relay.write;
+            }
+            None => valid.push(RelaySpec { url, ..relay }),
+        }
+    }
+
+    if valid.is_empty() {
+        return Err("At least one relay URL is required".to_string());
+    }
+    Ok(valid)
+}
+
+/// The normalized form of `relay`, a ws/wss URL with a host.
+pub(crate) fn validate_relay(relay: &str) -> Result<String, String> {
+    nostr::RelayUrl::parse(relay)
+        .map(|url| url.to_string())
+        .map_err(|e| format!("Invalid relay URL '{}': {}", relay, e))
+}
+
+pub(crate) fn validate_event_kind(event_kind: u16) -> Result<(), String> {
+    if event_kind == crate::ATTACHMENT_KIND {
+        Err(format!(
+            "Event kind {} is reserved for attachments",
+            event_kind
+        ))
+    } else if EVENT_KIND_RANGE.contains(&event_kind) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Event kind {} is outside {}..={}",
+            event_kind,
+            EVENT_KIND_RANGE.start(),
+            EVENT_KIND_RANGE.end()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    fn required(secret_key: &str) -> Vec<(&'static str, String)> {
+        vec![
+            (SECRET_KEY_VAR, secret_key.to_string()),
+            (RELAYS_VAR, "wss://relay.one, wss://relay.two,".to_string()),
+        ]
+    }
+
+    fn from_vars(vars: &[(&str, String)]) -> Result<Config, EnvConfigError> {
+        let vars: Vec<(&str, &str)> = vars
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        Config::from_env_with(lookup(&vars))
+    }
+
+    #[test]
+    fn secret_key_is_read_as_hex_or_nsec() {
+        let keys = Keys::generate();
+        let hex = keys.secret_key().to_secret_hex();
+        let nsec = keys.secret_key().to_bech32().unwrap();
+
+        for secret_key in [&hex, &nsec] {
+            let config = from_vars(&required(secret_key)).unwrap();
+            assert_eq!(&config.secret_key, secret_key);
+            assert_eq!(config.get_keys().unwrap().public_key(), keys.public_key());
+        }
+    }
+
+    #[test]
+    fn relays_are_split_on_commas() {
+        let hex = Keys::generate().secret_key().to_secret_hex();
+        let config = from_vars(&required(&hex)).unwrap();
+
+        assert_eq!(config.relays.len(), 2);
+        assert!(config.relays[0].url.starts_with("wss://relay.one"));
+        assert!(config.relays[1].url.starts_with("wss://relay.two"));
+        assert!(config.relays.iter().all(|relay| relay.read && relay.write));
+    }
+
+    #[test]
+    fn optional_variables_are_applied() {
+        let hex = Keys::generate().secret_key().to_secret_hex();
+        let mut vars = required(&hex);
+        vars.extend([
+            (ENVIRONMENT_VAR, "production".to_string()),
+            (RELEASE_VAR, "1.4.2".to_string()),
+            (SERVER_NAME_VAR, " web-1 ".to_string()),
+            (EVENT_KIND_VAR, "9999".to_string()),
+            (AUTO_SERVER_NAME_VAR, "no".to_string()),
+        ]);
+
+        let config = from_vars(&vars).unwrap();
+
+        assert_eq!(config.environment.as_deref(), Some("production"));
+        assert_eq!(config.release.as_deref(), Some("1.4.2"));
+        assert_eq!(config.server_name.as_deref(), Some("web-1"));
+        assert_eq!(config.event_kind, 9999);
+        assert!(!config.auto_server_name);
+
+        let config = from_vars(&required(&hex)).unwrap();
+        assert_eq!(config.environment, None);
+        assert_eq!(config.release, None);
+        assert_eq!(config.server_name, None);
+        assert_eq!(config.event_kind, DEFAULT_EVENT_KIND);
+    }
+
+    #[test]
+    fn every_missing_or_invalid_variable_is_reported() {
+        let error = Config::from_env_with(|_| None).unwrap_err();
+        assert_eq!(
+            error.errors,
+            vec![
+                EnvVarError::Missing(SECRET_KEY_VAR),
+                EnvVarError::Missing(RELAYS_VAR),
+            ]
+        );
+
+        let error = Config::from_env_with(lookup(&[
+            (SECRET_KEY_VAR, "not-a-key"),
+            (RELAYS_VAR, "https://relay.one"),
+            (EVENT_KIND_VAR, "kind"),
+            (AUTO_SERVER_NAME_VAR, "maybe"),
+        ]))
+        .unwrap_err();
+        let invalid: Vec<&str> = error
+            .errors
+            .iter()
+            .map(|error| match error {
+                EnvVarError::Invalid { name, .. } => *name,
+                EnvVarError::Missing(name) => panic!("{} reported missing", name),
+            })
+            .collect();
+        assert_eq!(
+            invalid,
+            vec![
+                SECRET_KEY_VAR,
+                RELAYS_VAR,
+                EVENT_KIND_VAR,
+                AUTO_SERVER_NAME_VAR
+            ]
+        );
+        assert!(!error.to_string().contains("not-a-key"));
+    }
+
+    #[test]
+    fn set_variables_override_the_fallback() {
+        let keys = Keys::generate();
+        let fallback = Config::from_keys(&keys, ["wss://relay.one"])
+            .unwrap()
+            .with_environment("staging");
+
+        let config = Config::from_env_or_with(
+            fallback.clone(),
+            lookup(&[(RELEASE_VAR, "2.0.0"), (ENVIRONMENT_VAR, "")]),
+        )
+        .unwrap();
+        assert_eq!(config.environment.as_deref(), Some("staging"));
+        assert_eq!(config.release.as_deref(), Some("2.0.0"));
+        assert_eq!(config.relays, fallback.relays);
+        assert_eq!(config.get_keys().unwrap().public_key(), keys.public_key());
+
+        let error =
+            Config::from_env_or_with(fallback, lookup(&[(EVENT_KIND_VAR, "1")])).unwrap_err();
+        assert!(matches!(
+            error.errors.as_slice(),
+            [EnvVarError::Invalid {
+                name: EVENT_KIND_VAR,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn releases_are_resolved_in_order() {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.one"])
+            .unwrap()
+            .with_auto_release(true)
+            .with_release_fallback("my-app@1.0.0");
+        let all = [
+            (RELEASE_VAR, "2.0.0"),
+            ("GIT_SHA", "abc123"),
+            ("GITHUB_SHA", "def456"),
+        ];
+
+        let resolve =
+            |config: &Config, vars: &[(&str, &str)]| config.resolve_release_with(lookup(vars));
+        let explicit = config.clone().with_release("3.0.0");
+        assert_eq!(resolve(&explicit, &all).as_deref(), Some("3.0.0"));
+        assert_eq!(resolve(&config, &all).as_deref(), Some("2.0.0"));
+        assert_eq!(resolve(&config, &all[1..]).as_deref(), Some("abc123"));
+        assert_eq!(resolve(&config, &all[2..]).as_deref(), Some("def456"));
+        assert_eq!(
+            resolve(&config, &[(RELEASE_VAR, " "), ("GIT_SHA", "")]).as_deref(),
+            Some("my-app@1.0.0")
+        );
+        assert_eq!(
+            resolve(&config.clone().with_auto_release(false), &all),
+            None
+        );
+    }
+
+    fn hex_key() -> String {
+        Keys::generate().secret_key().to_secret_hex()
+    }
+
+    fn rejection(secret_key: &str, relays: &[&str]) -> String {
+        Config::try_new(secret_key.to_string(), relays.iter().copied())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn malformed_secret_keys_are_rejected_without_echoing_them() {
+        let npub = Keys::generate().public_key().to_bech32().unwrap();
+        let short = &hex_key()[..63];
+        let not_hex = format!("{}zz", &hex_key()[..62]);
+
+        for secret_key in [npub.as_str(), short, &not_hex, "nsec1qqqqqq"] {
+            let message = rejection(secret_key, &["wss://relay.one"]);
+            assert!(message.starts_with("Invalid secret key"), "{}", message);
+            assert!(!message.contains(secret_key), "{}", message);
+        }
+    }
+
+    #[test]
+    fn secret_keys_are_trimmed() {
+        let hex = hex_key();
+        let config = Config::try_new(format!("  {}\n", hex), ["wss://relay.one"]).unwrap();
+        assert_eq!(config.secret_key, hex);
+    }
+
+    #[test]
+    fn relays_must_be_websocket_urls_with_a_host() {
+        let hex = hex_key();
+
+        for relay in ["https://relay.one", "relay.one", "wss://"] {
+            let message = rejection(&hex, &[relay]);
+            assert!(message.contains(relay), "{}", message);
+        }
+    }
+
+    #[test]
+    fn an_empty_relay_list_is_rejected() {
+        let message = rejection(&hex_key(), &[]);
+        assert!(message.contains("At least one relay URL is required"));
+    }
+
+    #[test]
+    fn duplicate_relays_are_merged() {
+        let config = Config::try_new(
+            hex_key(),
+            [
+                RelaySpec::read_only("wss://relay.one"),
+                RelaySpec::new("wss://relay.two"),
+                RelaySpec::write_only("wss://relay.one"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.relays.len(), 2);
+        assert!(config.relays[0].read && config.relays[0].write);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::rt::Instant;
+use crate::{Event, FINGERPRINT_SEPARATOR};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Fingerprints tracked at once unless configured otherwise.
+pub const DEFAULT_DEDUP_MAX_ENTRIES: usize = 1000;
+
+/// Occurrences of one fingerprint within its window.
+struct Window {
+    opened: Instant,
+    /// The first event, published as it came and the base of the summary.
+    first: Event,
+    last_seen: DateTime<Utc>,
+    occurrences: u64,
+}
+
+impl Window {
+    /// The summary of the window, or `None` when nothing was suppressed.
+    fn into_summary(self) -> Option<Event> {
+        if self.occurrences < 2 {
+            return None;
+        }
+
+        let first_seen = self.first.timestamp;
+        let mut summary = self.first;
+        summary.event_id = Uuid::new_v4().to_string();
+        summary.timestamp = self.last_seen;
+        summary
+            .extra
+            .insert("occurrences".to_string(), json!(self.occurrences));
+        summary
+            .extra
+            .insert("first_seen".to_string(), json!(first_seen.to_rfc3339()));
+        summary
+            .extra
+            .insert("last_seen".to_string(), json!(self.last_seen.to_rfc3339()));
+        Some(summary)
+    }
+}
+
+/// Suppresses events repeating a fingerprint within `window` of its first occurrence,
+/// see `Config::with_dedup`.
+pub(crate) struct Deduplicator {
+    window: Duration,
+    max_entries: usize,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(window: Duration, max_entries: usize) -> Self {
+        Self {
+            window,
+            max_entries: max_entries.max(1),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records `event`. Returns whether it repeats an open window and must be
+    /// suppressed, and the summaries of windows that closed meanwhile or were evicted to
+    /// stay within `max_entries`.
+    pub(crate) fn observe(&self, event: &Event) -> (bool, Vec<Event>) {
+        let now = Instant::now();
+        let mut summaries = self.take_closed(now);
+        let key = dedup_key(event);
+
+        let mut windows = self.windows.lock().unwrap();
+        if let Some(window) = windows.get_mut(&key) {
+            window.occurrences += 1;
+            window.last_seen = event.timestamp;
+            return (true, summaries);
+        }
+
+        if windows.len() >= self.max_entries
+            && let Some(oldest) = windows
+                .iter()
+                .min_by_key(|(_, window)| window.opened)
+                .map(|(key, _)| key.clone())
+        {
+            summaries.extend(windows.remove(&oldest).and_then(Window::into_summary));
+        }
+        windows.insert(
+            key,
+            Window {
+                opened: now,
+                first: event.clone(),
+                last_seen: event.timestamp,
+                occurrences: 1,
+            },
+        );
+        (false, summaries)
+    }
+
+    /// Closes every window, returning the summaries of those with suppressed events.
+    pub(crate) fn drain(&self) -> Vec<Event> {
+        let mut windows = self.windows.lock().unwrap();
+        windows
+            .drain()
+            .filter_map(|(_, window)| window.into_summary())
+            .collect()
+    }
+
+    /// Closes the windows whose time is up, returning the summaries of those with
+    /// suppressed events.
+    pub(crate) fn close_expired(&self) -> Vec<Event> {
+        self.take_closed(Instant::now())
+    }
+
+    fn take_closed(&self, now: Instant) -> Vec<Event> {
+        let mut windows = self.windows.lock().unwrap();
+        let closed: Vec<String> = windows
+            .iter()
+            .filter(|(_, window)| now.duration_since(window.opened) >= self.window)
+            .map(|(key, _)| key.clone())
+            .collect();
+        closed
+            .into_iter()
+            .filter_map(|key| windows.remove(&key)?.into_summary())
+            .collect()
+    }
+}
+
+/// The explicit fingerprint of `event`, or its level, message and logger.
+fn dedup_key(event: &Event) -> String {
+    match event.fingerprint {
+        Some(ref fingerprint) => fingerprint.join(FINGERPRINT_SEPARATOR),
+        None => [
+            event.level.as_str(),
+            event.message.as_deref().unwrap_or_default(),
+            event.logger.as_deref().unwrap_or_default(),
+        ]
+        .join(FINGERPRINT_SEPARATOR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Level;
+
+    fn message(text: &str) -> Event {
+        Event::new().with_message(text).with_level(Level::Error)
+    }
+
+    fn logged(text: &str, logger: &str) -> Event {
+        let mut event = message(text);
+        event.logger = Some(logger.to_string());
+        event
+    }
+
+    fn occurrences(summary: &Event) -> u64 {
+        summary.extra["occurrences"].as_u64().unwrap()
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_counted_into_one_summary() {
+        let dedup = Deduplicator::new(Duration::from_secs(3600), DEFAULT_DEDUP_MAX_ENTRIES);
+        let first = message("timeout");
+        let last =
+            message("timeout").with_timestamp(first.timestamp + chrono::Duration::seconds(5));
+
+        let (suppressed, summaries) = dedup.observe(&first);
+        assert!(!suppressed && summaries.is_empty());
+        assert!(dedup.observe(&message("timeout")).0);
+        assert!(dedup.observe(&last).0);
+        assert!(!dedup.observe(&message("refused")).0);
+
+        let summaries = dedup.drain();
+        assert_eq!(summaries.len(), 1);
+        let summary = &summaries[0];
+        assert_eq!(summary.message.as_deref(), Some("timeout"));
+        assert_eq!(occurrences(summary), 3);
+        assert_eq!(summary.extra["first_seen"], first.timestamp.to_rfc3339());
+        assert_eq!(summary.extra["last_seen"], last.timestamp.to_rfc3339());
+        assert_eq!(summary.timestamp, last.timestamp);
+        assert_ne!(summary.event_id, first.event_id);
+        assert!(dedup.drain().is_empty());
+    }
+
+    #[test]
+    fn fingerprints_and_loggers_decide_what_repeats() {
+        let dedup = Deduplicator::new(Duration::from_secs(3600), DEFAULT_DEDUP_MAX_ENTRIES);
+
+        assert!(
+            !dedup
+                .observe(&message("card 1234 declined").with_fingerprint(["declined"]))
+                .0
+        );
+        assert!(
+            dedup
+                .observe(&message("card 5678 declined").with_fingerprint(["declined"]))
+                .0
+        );
+        assert!(!dedup.observe(&logged("timeout", "db")).0);
+        assert!(dedup.observe(&logged("timeout", "db")).0);
+        assert!(!dedup.observe(&logged("timeout", "http")).0);
+        assert!(
+            !dedup
+                .observe(&logged("timeout", "db").with_level(Level::Warning))
+                .0
+        );
+    }
+
+    #[test]
+    fn windows_roll_over_once_their_time_is_up() {
+        let dedup = Deduplicator::new(Duration::from_millis(50), DEFAULT_DEDUP_MAX_ENTRIES);
+        dedup.observe(&message("timeout"));
+        dedup.observe(&message("timeout"));
+        dedup.observe(&message("refused"));
+
+        std::thread::sleep(Duration::from_millis(80));
+
+        let (suppressed, summaries) = dedup.observe(&message("timeout"));
+        assert!(!suppressed);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(occurrences(&summaries[0]), 2);
+        assert!(dedup.observe(&message("timeout")).0);
+
+        std::thread::sleep(Duration::from_millis(80));
+        let summaries = dedup.close_expired();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(occurrences(&summaries[0]), 2);
+        assert!(dedup.drain().is_empty());
+    }
+
+    #[test]
+    fn the_oldest_window_is_evicted_at_the_limit() {
+        let dedup = Deduplicator::new(Duration::from_secs(3600), 2);
+        dedup.observe(&message("timeout"));
+        dedup.observe(&message("timeout"));
+        std::thread::sleep(Duration::from_millis(5));
+        dedup.observe(&message("refused"));
+        std::thread::sleep(Duration::from_millis(5));
+
+        let (suppressed, summaries) = dedup.observe(&message("reset"));
+        assert!(!suppressed);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message.as_deref(), Some("timeout"));
+        assert_eq!(occurrences(&summaries[0]), 2);
+
+        assert!(!dedup.observe(&message("timeout")).0);
+        assert!(dedup.drain().is_empty());
+    }
+}
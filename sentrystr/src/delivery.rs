@@ -0,0 +1,156 @@
+use nostr_sdk::Client;
+use std::fmt;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Share of the configured relays, from 0 to 100, for [`MinRelaySuccess::Percentage`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentage(pub u8);
+
+/// How many relays must accept an event for a publish to succeed, see
+/// [`crate::Config::with_min_relay_success`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MinRelaySuccess {
+    Count(usize),
+    Percentage(u8),
+}
+
+impl Default for MinRelaySuccess {
+    fn default() -> Self {
+        Self::Count(1)
+    }
+}
+
+impl From<usize> for MinRelaySuccess {
+    fn from(count: usize) -> Self {
+        Self::Count(count)
+    }
+}
+
+impl From<Percentage> for MinRelaySuccess {
+    fn from(percentage: Percentage) -> Self {
+        Self::Percentage(percentage.0.min(100))
+    }
+}
+
+impl MinRelaySuccess {
+    /// Relays out of `relays` that must accept, at least one and at most all.
+    pub fn required(&self, relays: usize) -> usize {
+        let required = match *self {
+            Self::Count(count) => count,
+            Self::Percentage(percentage) => (relays * percentage as usize).div_ceil(100),
+        };
+        required.clamp(1, relays.max(1))
+    }
+}
+
+/// What one relay did with a published event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayOutcome {
+    pub relay: String,
+    pub status: RelayDeliveryStatus,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RelayDeliveryStatus {
+    Accepted,
+    /// The relay refused the event, or sending to it failed.
+    Rejected(String),
+    /// No answer before the publish timeout, or before the threshold was met elsewhere.
+    Pending,
+}
+
+impl fmt::Display for RelayOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            RelayDeliveryStatus::Accepted => write!(f, "{}: accepted", self.relay),
+            RelayDeliveryStatus::Rejected(ref message) => write!(f, "{}: {}", self.relay, message),
+            RelayDeliveryStatus::Pending => write!(f, "{}: no response", self.relay),
+        }
+    }
+}
+
+/// Sends `event` to each relay concurrently and returns once `required` relays accepted
+/// it, every relay answered, or `timeout` passed. Sends still running at that point
+/// carry on in the background and are reported as pending.
+pub(crate) async fn deliver(
+    client: &Client,
+    relays: &[String],
+    event: &nostr::Event,
+    required: usize,
+    timeout: Option<Duration>,
+) -> Vec<RelayOutcome> {
+    let (results, mut rx) = mpsc::channel(relays.len().max(1));
+
+    for relay in relays {
+        let client = client.clone();
+        let event = event.clone();
+        let relay = relay.clone();
+        let results = results.clone();
+
+        crate::rt::spawn(async move {
+            let status = match client.send_event_to([relay.as_str()], &event).await {
+                Ok(output) if !output.success.is_empty() => RelayDeliveryStatus::Accepted,
+                Ok(output) => RelayDeliveryStatus::Rejected(
+                    output
+                        .failed
+                        .into_values()
+                        .next()
+                        .unwrap_or_else(|| "not sent".to_string()),
+                ),
+                Err(e) => RelayDeliveryStatus::Rejected(e.to_string()),
+            };
+            let _ = results.send(RelayOutcome { relay, status }).await;
+        });
+    }
+    drop(results);
+
+    let deadline = timeout.map(|timeout| crate::rt::Instant::now() + timeout);
+    let mut outcomes: Vec<RelayOutcome> = Vec::with_capacity(relays.len());
+    let mut accepted = 0;
+
+    while accepted < required {
+        let next = match deadline {
+            Some(deadline) => match crate::rt::timeout_at(deadline, rx.recv()).await {
+                Ok(next) => next,
+                Err(_) => break,
+            },
+            None => rx.recv().await,
+        };
+        let Some(outcome) = next else {
+            break;
+        };
+
+        if outcome.status == RelayDeliveryStatus::Accepted {
+            accepted += 1;
+        }
+        outcomes.push(outcome);
+    }
+
+    for relay in relays {
+        if !outcomes.iter().any(|outcome| &outcome.relay == relay) {
+            outcomes.push(RelayOutcome {
+                relay: relay.clone(),
+                status: RelayDeliveryStatus::Pending,
+            });
+        }
+    }
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn required_relays_stay_between_one_and_all() {
+        assert_eq!(MinRelaySuccess::default().required(3), 1);
+        assert_eq!(MinRelaySuccess::Count(2).required(3), 2);
+        assert_eq!(MinRelaySuccess::Count(5).required(3), 3);
+        assert_eq!(MinRelaySuccess::Count(0).required(3), 1);
+        assert_eq!(MinRelaySuccess::from(Percentage(50)).required(3), 2);
+        assert_eq!(MinRelaySuccess::from(Percentage(0)).required(3), 1);
+        assert_eq!(MinRelaySuccess::from(Percentage(250)).required(3), 3);
+        assert_eq!(MinRelaySuccess::Count(2).required(0), 1);
+    }
+}
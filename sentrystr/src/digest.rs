@@ -0,0 +1,240 @@
+use crate::{DirectMessageSender, Event, Level, MessageEvent, Result};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+/// A summary of events: counts per level and per message, and the time span they cover.
+/// Bounded: only `max_items` distinct messages are kept, later ones are only counted.
+/// Used by the digest mode of [`DirectMessageSender`], and by sinks elsewhere that send
+/// digests of their own.
+#[derive(Debug, Clone)]
+pub struct EventDigest {
+    max_items: usize,
+    levels: BTreeMap<Level, u64>,
+    messages: HashMap<String, u64>,
+    other: u64,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl EventDigest {
+    /// An empty digest keeping at most `max_items` distinct messages.
+    pub fn new(max_items: usize) -> Self {
+        Self {
+            max_items: max_items.max(1),
+            levels: BTreeMap::new(),
+            messages: HashMap::new(),
+            other: 0,
+            first: None,
+            last: None,
+        }
+    }
+
+    /// Counts `event`.
+    pub fn record(&mut self, event: &Event) {
+        let timestamp = event.timestamp;
+
+        *self.levels.entry(event.level.clone()).or_default() += 1;
+        self.first = Some(self.first.map_or(timestamp, |first| first.min(timestamp)));
+        self.last = Some(self.last.map_or(timestamp, |last| last.max(timestamp)));
+
+        let message = event
+            .message
+            .clone()
+            .unwrap_or_else(|| "(no message)".to_string());
+        if let Some(count) = self.messages.get_mut(&message) {
+            *count += 1;
+        } else if self.messages.len() < self.max_items {
+            self.messages.insert(message, 1);
+        } else {
+            self.other += 1;
+        }
+    }
+
+    /// The number of events recorded.
+    pub fn total(&self) -> u64 {
+        self.levels.values().sum()
+    }
+
+    /// Whether no event was recorded.
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// The digest text of the events recorded so far, and empties the digest. `None`
+    /// if there were none.
+    pub fn take(&mut self) -> Option<String> {
+        let digest = std::mem::replace(self, Self::new(self.max_items));
+        (!digest.is_empty()).then(|| digest.render())
+    }
+
+    /// The digest text, most frequent messages first.
+    pub fn render(&self) -> String {
+        let levels: Vec<String> = self
+            .levels
+            .iter()
+            .rev()
+            .map(|(level, count)| format!("{} {}", level, count))
+            .collect();
+
+        let mut digest = format!(
+            "SentryStr Digest: {} event(s)\nLevels: {}\n",
+            self.total(),
+            levels.join(", ")
+        );
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            digest.push_str(&format!("From {} to {}\n", first, last));
+        }
+
+        let mut messages: Vec<(&String, &u64)> = self.messages.iter().collect();
+        messages.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        digest.push_str("\nTop messages:\n");
+        for (message, count) in messages {
+            digest.push_str(&format!("{}x {}\n", count, message));
+        }
+        if self.other > 0 {
+            digest.push_str(&format!("{}x other messages\n", self.other));
+        }
+        digest
+    }
+}
+
+/// Digest mode of a [`DirectMessageSender`], see
+/// [`crate::DirectMessageBuilder::with_digest`]. Shared by clones of the sender; the
+/// last one dropped sends what is left.
+pub(crate) struct Digest {
+    buffer: Mutex<EventDigest>,
+    /// Sends the digests, in immediate mode.
+    sender: DirectMessageSender,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl Digest {
+    /// Starts sending a digest through `sender` every `interval`, which must not be zero.
+    /// Needs a Tokio runtime.
+    pub(crate) fn spawn(
+        sender: DirectMessageSender,
+        interval: Duration,
+        max_items: usize,
+    ) -> Result<Arc<Self>> {
+        if interval.is_zero() {
+            return Err(crate::SentryStrError::Config(
+                "The digest interval must be more than 0".to_string(),
+            ));
+        }
+        let runtime = tokio::runtime::Handle::try_current().map_err(|_| {
+            crate::SentryStrError::Config("Digest mode needs a Tokio runtime".to_string())
+        })?;
+
+        let digest = Arc::new(Self {
+            buffer: Mutex::new(EventDigest::new(max_items)),
+            sender,
+            task: Mutex::new(None),
+        });
+
+        let weak: Weak<Self> = Arc::downgrade(&digest);
+        let task = runtime.spawn(async move {
+            let mut ticks = crate::rt::interval(interval);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(digest) = weak.upgrade() else {
+                    return;
+                };
+                if let Err(e) = digest.flush().await {
+                    eprintln!("Failed to send digest: {}", e);
+                }
+            }
+        });
+        *digest.task.lock().unwrap() = Some(task.abort_handle());
+
+        Ok(digest)
+    }
+
+    pub(crate) fn record(&self, event: &MessageEvent) {
+        self.buffer.lock().unwrap().record(&event.event);
+    }
+
+    /// Sends a digest of the buffered events, if there are any.
+    pub(crate) async fn flush(&self) -> Result<()> {
+        let Some(text) = self.take() else {
+            return Ok(());
+        };
+        self.sender.send_custom_message(&text).await
+    }
+
+    fn take(&self) -> Option<String> {
+        self.buffer.lock().unwrap().take()
+    }
+}
+
+impl Drop for Digest {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+
+        if let Some(text) = self.take()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            let sender = self.sender.clone();
+            runtime.spawn(async move {
+                if let Err(e) = sender.send_custom_message(&text).await {
+                    eprintln!("Failed to send digest: {}", e);
+                }
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DirectMessageBuilder, SentryStrError};
+    use nostr::Keys;
+    use nostr_sdk::Client;
+
+    fn builder() -> DirectMessageBuilder {
+        let keys = Keys::generate();
+        DirectMessageBuilder::new()
+            .with_client(Client::new(keys.clone()))
+            .with_keys(keys)
+            .with_recipient(Keys::generate().public_key())
+    }
+
+    #[tokio::test]
+    async fn zero_digest_interval_is_rejected() {
+        let built = builder().with_digest(Duration::ZERO, 10).build();
+        assert!(matches!(built, Err(SentryStrError::Config(_))));
+        assert!(
+            builder()
+                .with_digest(Duration::from_secs(60), 10)
+                .build()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn digest_counts_levels_and_bounds_messages() {
+        let mut digest = EventDigest::new(1);
+        digest.record(
+            &Event::new()
+                .with_message("disk full")
+                .with_level(Level::Error),
+        );
+        digest.record(
+            &Event::new()
+                .with_message("disk full")
+                .with_level(Level::Error),
+        );
+        digest.record(
+            &Event::new()
+                .with_message("slow query")
+                .with_level(Level::Warning),
+        );
+        assert_eq!(digest.total(), 3);
+    }
+}
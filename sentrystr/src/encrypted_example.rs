@@ -18,10 +18,10 @@ async fn main() -> sentrystr::Result<()> {
         "wss://nostr.chaima.info".to_string(),
     ];
 
-    let config = Config::new(
+    let config = Config::try_new(
         sender_keys.secret_key().display_secret().to_string(),
         relays,
-    )
+    )?
     .with_nip44_encryption(receiver_keys.public_key().to_string());
 
     let client = NostrSentryClient::new(config).await?;
@@ -39,10 +39,10 @@ async fn main() -> sentrystr::Result<()> {
     println!("Published encrypted event: {}", event_id);
 
     client
-        .capture_message("Encrypted message from SentryStr")
+        .capture_message(Level::Info, "Encrypted message from SentryStr")
         .await?;
     client
-        .capture_error("Encrypted error from SentryStr")
+        .capture_error_message("Encrypted error from SentryStr")
         .await?;
 
     println!();
@@ -1,5 +1,105 @@
-use crate::{Result, SentryStrError};
+use crate::{Event, Result, SentryStrError};
 use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Tag marking events whose content is encrypted with a [`ContentEncryption`] scheme.
+pub const ENCRYPTION_TAG: &str = "encryption";
+/// Value of [`ENCRYPTION_TAG`] for [`ContentEncryption::Nip44`].
+pub const NIP44_V2_SCHEME: &str = "nip44-v2";
+/// Tag holding the fields kept readable next to encrypted content, as
+/// `["l", "<name>=<value>"]`. Relays only index single-letter tags, so only those can
+/// be filtered on.
+pub const PLAINTEXT_TAG: &str = "l";
+
+/// Value of the [`PLAINTEXT_TAG`] of the field `name`, e.g. `level=error`.
+pub fn plaintext_tag_value(name: &str, value: &str) -> String {
+    format!("{}={}", name, value)
+}
+
+/// How `capture_event` encrypts event content so that only chosen readers can read it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ContentEncryption {
+    /// The event JSON is encrypted with NIP-44 v2 once per reader; the content is a JSON
+    /// object mapping each reader's hex public key to its ciphertext. Without readers,
+    /// the publishing key is the only one.
+    Nip44 { reader_pubkeys: Vec<PublicKey> },
+}
+
+impl ContentEncryption {
+    /// Encrypts `content` from `keys` for every reader.
+    pub fn encrypt(&self, keys: &Keys, content: &str) -> Result<String> {
+        match self {
+            Self::Nip44 { reader_pubkeys } => {
+                let own = [keys.public_key()];
+                let readers = if reader_pubkeys.is_empty() {
+                    &own[..]
+                } else {
+                    &reader_pubkeys[..]
+                };
+
+                let mut envelope = BTreeMap::new();
+                for reader in readers {
+                    let ciphertext =
+                        EncryptionHelper::encrypt_nip44(keys.secret_key(), reader, content)?;
+                    envelope.insert(reader.to_hex(), ciphertext);
+                }
+                Ok(serde_json::to_string(&envelope)?)
+            }
+        }
+    }
+
+    /// The scheme named in the [`ENCRYPTION_TAG`] of `nostr_event`, if any.
+    pub fn scheme(nostr_event: &nostr::Event) -> Option<&str> {
+        nostr_event
+            .tags
+            .iter()
+            .find_map(|tag| match tag.as_slice() {
+                [key, scheme, ..] if key == ENCRYPTION_TAG => Some(scheme.as_str()),
+                _ => None,
+            })
+    }
+
+    /// Decrypts the SentryStr event in `nostr_event` as the reader `keys`. Fails when the
+    /// event is not encrypted with a known scheme or not for this reader.
+    pub fn decrypt(keys: &Keys, nostr_event: &nostr::Event) -> Result<Event> {
+        Ok(serde_json::from_str(&Self::decrypt_content(
+            keys,
+            nostr_event,
+        )?)?)
+    }
+
+    /// Decrypts the content of `nostr_event` as the reader `keys`, whatever it holds.
+    /// Failures are `SentryStrError::Decryption`.
+    pub fn decrypt_content(keys: &Keys, nostr_event: &nostr::Event) -> Result<String> {
+        match Self::scheme(nostr_event) {
+            Some(NIP44_V2_SCHEME) => {
+                let envelope: BTreeMap<String, String> = serde_json::from_str(&nostr_event.content)
+                    .map_err(|e| {
+                        SentryStrError::Decryption(format!("Malformed encrypted content: {}", e))
+                    })?;
+                let ciphertext = envelope.get(&keys.public_key().to_hex()).ok_or_else(|| {
+                    SentryStrError::Decryption("Event is not encrypted for this reader".to_string())
+                })?;
+                nostr::nips::nip44::decrypt(keys.secret_key(), &nostr_event.pubkey, ciphertext)
+                    .map_err(|e| SentryStrError::Decryption(e.to_string()))
+            }
+            Some(scheme) => Err(SentryStrError::Decryption(format!(
+                "Unsupported content encryption '{}'",
+                scheme
+            ))),
+            None => Err(SentryStrError::Decryption(
+                "Event content is not encrypted".to_string(),
+            )),
+        }
+    }
+
+    pub(crate) fn tag(&self) -> Tag {
+        match self {
+            Self::Nip44 { .. } => Tag::custom(TagKind::custom(ENCRYPTION_TAG), [NIP44_V2_SCHEME]),
+        }
+    }
+}
 
 pub struct EncryptionHelper;
 
@@ -18,6 +118,28 @@ impl EncryptionHelper {
         Ok(encrypted)
     }
 
+    /// Legacy NIP-04 encryption, deprecated by NIP-17; only for clients that cannot
+    /// read anything newer.
+    pub fn encrypt_nip04(
+        sender_secret_key: &SecretKey,
+        recipient_public_key: &PublicKey,
+        content: &str,
+    ) -> Result<String> {
+        let encrypted =
+            nostr::nips::nip04::encrypt(sender_secret_key, recipient_public_key, content)?;
+        Ok(encrypted)
+    }
+
+    pub fn decrypt_nip04(
+        receiver_secret_key: &SecretKey,
+        sender_public_key: &PublicKey,
+        encrypted_content: &str,
+    ) -> Result<String> {
+        let decrypted =
+            nostr::nips::nip04::decrypt(receiver_secret_key, sender_public_key, encrypted_content)?;
+        Ok(decrypted)
+    }
+
     pub fn decrypt_nip44(
         receiver_secret_key: &SecretKey,
         sender_public_key: &PublicKey,
@@ -45,3 +167,45 @@ pub fn validate_encryption_keys(sender_keys: &Keys, recipient_pubkey: &PublicKey
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sealed(publisher: &Keys, readers: Vec<PublicKey>, event: &Event) -> nostr::Event {
+        let encryption = ContentEncryption::Nip44 {
+            reader_pubkeys: readers,
+        };
+        let content = encryption
+            .encrypt(publisher, &serde_json::to_string(event).unwrap())
+            .unwrap();
+        EventBuilder::new(Kind::Custom(9898), content)
+            .tag(encryption.tag())
+            .sign_with_keys(publisher)
+            .unwrap()
+    }
+
+    #[test]
+    fn readers_decrypt_the_event() {
+        let publisher = Keys::generate();
+        let reader = Keys::generate();
+        let event = Event::new().with_message("card declined");
+
+        let nostr_event = sealed(&publisher, vec![reader.public_key()], &event);
+
+        let decrypted = ContentEncryption::decrypt(&reader, &nostr_event).unwrap();
+        assert_eq!(decrypted.message.as_deref(), Some("card declined"));
+    }
+
+    #[test]
+    fn other_keys_get_a_decryption_error() {
+        let publisher = Keys::generate();
+        let nostr_event = sealed(&publisher, Vec::new(), &Event::new());
+
+        assert!(ContentEncryption::decrypt(&publisher, &nostr_event).is_ok());
+        assert!(matches!(
+            ContentEncryption::decrypt(&Keys::generate(), &nostr_event),
+            Err(SentryStrError::Decryption(_))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::delivery::RelayOutcome;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,6 +13,9 @@ pub enum SentryStrError {
     #[error("Nostr event builder error: {0}")]
     NostrEventBuilder(#[from] nostr::event::builder::Error),
 
+    #[error("NIP-04 encryption error: {0}")]
+    Nip04Encryption(#[from] nostr::nips::nip04::Error),
+
     #[error("NIP-44 encryption error: {0}")]
     Nip44Encryption(#[from] nostr::nips::nip44::Error),
 
@@ -20,6 +25,123 @@ pub enum SentryStrError {
     #[error("Configuration error: {0}")]
     Config(String),
 
+    #[error("Invalid secret key: {0}")]
+    InvalidKey(String),
+
+    #[error("Key file {} not found", .0.display())]
+    KeyFileNotFound(std::path::PathBuf),
+
+    #[error("Key file error: {0}")]
+    KeyFile(String),
+
+    #[error("Failed to decrypt key: {0}")]
+    KeyDecryption(String),
+
+    #[error("Failed to decrypt event: {0}")]
+    Decryption(String),
+
+    #[error("No key passphrase: {0}")]
+    Passphrase(String),
+
     #[error("Publishing error: {0}")]
     Publishing(String),
+
+    #[error("Publishing failed after {attempts} attempt(s): {}", .responses.join("; "))]
+    PublishFailed {
+        attempts: u32,
+        /// Rejections or send errors of the last attempt, as `relay: message`.
+        responses: Vec<String>,
+    },
+
+    #[error("Only {accepted} of {required} required relay(s) accepted the event after {attempts} attempt(s): {}", .outcomes.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    PartialDelivery {
+        attempts: u32,
+        required: usize,
+        accepted: usize,
+        /// What each relay did on the last attempt.
+        outcomes: Vec<RelayOutcome>,
+    },
+
+    #[error("Event of {size} bytes exceeds the maximum of {max} bytes even after truncation")]
+    EventTooLarge { size: usize, max: usize },
+
+    #[error("Attachment '{name}' of {size} bytes exceeds the maximum of {max} bytes")]
+    AttachmentTooLarge {
+        name: String,
+        size: usize,
+        max: usize,
+    },
+
+    #[error("Attachments of {size} bytes in total exceed the maximum of {max} bytes")]
+    AttachmentsTooLarge { size: usize, max: usize },
+
+    #[error("No proof of work of difficulty {difficulty} found within {budget:?}")]
+    PowTimeout { difficulty: u8, budget: Duration },
+
+    #[error("Only {connected} of {required} required relay(s) connected within {timeout:?}")]
+    NotConnected {
+        connected: usize,
+        required: usize,
+        timeout: Duration,
+    },
+
+    #[error("Client is closed")]
+    ClientClosed,
+
+    #[error("before_send hook panicked: {0}")]
+    BeforeSendPanicked(String),
+
+    #[error("{destination} asked to back off for {retry_after:?}")]
+    RateLimited {
+        destination: String,
+        retry_after: Duration,
+    },
+
+    #[error(transparent)]
+    Env(#[from] EnvConfigError),
+}
+
+/// A problem with one `SENTRYSTR_*` environment variable.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum EnvVarError {
+    #[error("{0} is not set")]
+    Missing(&'static str),
+
+    #[error("{name} is invalid: {message}")]
+    Invalid { name: &'static str, message: String },
+}
+
+impl EnvVarError {
+    pub(crate) fn invalid(name: &'static str, message: impl std::fmt::Display) -> Self {
+        Self::Invalid {
+            name,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// A string that is not a level name, from `str::parse::<Level>()`.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid level '{0}'. Expected one of: debug, info, warning, error, fatal")]
+pub struct ParseLevelError(pub String);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid DM encryption '{0}'. Expected one of: nip04, nip44, nip17")]
+pub struct ParseDmEncryptionError(pub String);
+
+/// Every problem found while reading the configuration from the environment.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("Invalid environment configuration: {}", .errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+pub struct EnvConfigError {
+    pub errors: Vec<EnvVarError>,
+}
+
+impl EnvConfigError {
+    pub(crate) fn check(errors: Vec<EnvVarError>) -> Result<(), Self> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Self { errors })
+        }
+    }
 }
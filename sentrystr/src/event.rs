@@ -1,17 +1,44 @@
+use crate::attachment::{Attachment, AttachmentRef};
+use crate::error::ParseLevelError;
 use chrono::{DateTime, Utc};
 use nostr::prelude::Tag;
 use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Separator between fingerprint parts in the [`FINGERPRINT_TAG`] Nostr tag.
+pub const FINGERPRINT_SEPARATOR: &str = "|";
+
+/// Nostr tag holding the fingerprint. Relays only index single-letter tags, so only
+/// those can be queried for.
+pub const FINGERPRINT_TAG: &str = "f";
+
+/// Version of the event JSON written by this crate. Version 1 is the format from before
+/// the version was recorded.
+pub const SCHEMA_VERSION: u8 = 2;
+
+/// Fields [`Event::parse_lenient`] cannot do without.
+const REQUIRED_FIELDS: &[&str] = &["event_id", "timestamp", "platform", "level"];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Event {
+    /// [`SCHEMA_VERSION`] for events created here; 1 for events without the field.
+    #[serde(default = "legacy_schema_version")]
+    pub schema_version: u8,
     pub event_id: String,
     pub timestamp: DateTime<Utc>,
     pub platform: String,
     pub level: Level,
     pub logger: Option<String>,
     pub transaction: Option<String>,
+    /// How long the transaction took, for performance events such as those of
+    /// [`Event::transaction_event`]. Absent from events of older clients.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
     pub server_name: Option<String>,
     pub release: Option<String>,
     pub environment: Option<String>,
@@ -20,14 +47,64 @@ pub struct Event {
     pub stacktrace: Option<Stacktrace>,
     pub user: Option<User>,
     pub request: Option<Request>,
+    #[serde(default)]
     pub tags: HashMap<String, String>,
+    #[serde(default)]
     pub extra: HashMap<String, serde_json::Value>,
+    /// Grouping key chosen by the publisher; events with equal fingerprints are
+    /// occurrences of the same problem.
     pub fingerprint: Option<Vec<String>>,
     pub modules: Option<HashMap<String, String>>,
+    #[serde(default)]
     pub nostr_tags: Vec<Tag>,
+    /// What happened before this event, oldest first. Defaults to empty so events from
+    /// older clients still parse.
+    #[serde(default)]
+    pub breadcrumbs: Vec<Breadcrumb>,
+    /// Payloads to publish as companion events on capture; not part of the event JSON.
+    #[serde(skip)]
+    pub attachments: Vec<Attachment>,
+    /// The published attachments, set by the client on capture.
+    #[serde(default)]
+    pub attachment_refs: Vec<AttachmentRef>,
+    /// Sent regardless of the client's sample rates; not part of the event JSON.
+    #[serde(skip)]
+    pub force_send: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+fn legacy_schema_version() -> u8 {
+    1
+}
+
+/// Something [`Event::parse_lenient`] skipped to parse an event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// A field this version does not know, e.g. from a newer publisher.
+    UnknownField(String),
+    /// A known field whose value does not have the expected shape.
+    IncompatibleField { field: String, error: String },
+    /// The event was written by a newer schema version than [`SCHEMA_VERSION`].
+    NewerSchema(u8),
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::UnknownField(field) => write!(f, "unknown field '{}' skipped", field),
+            ParseWarning::IncompatibleField { field, error } => {
+                write!(f, "incompatible field '{}' skipped: {}", field, error)
+            }
+            ParseWarning::NewerSchema(version) => write!(
+                f,
+                "schema version {} is newer than {}",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+/// Severity of an event, ordered from `Debug` (least severe) to `Fatal`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Level {
     Debug,
@@ -37,6 +114,50 @@ pub enum Level {
     Fatal,
 }
 
+impl Level {
+    /// Every level, from least to most severe.
+    pub const ALL: [Level; 5] = [
+        Level::Debug,
+        Level::Info,
+        Level::Warning,
+        Level::Error,
+        Level::Fatal,
+    ];
+
+    /// The lowercase name, as serialized.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+            Level::Fatal => "fatal",
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Case-insensitive, also accepting `warn` and `err`.
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "debug" => Ok(Level::Debug),
+            "info" => Ok(Level::Info),
+            "warning" | "warn" => Ok(Level::Warning),
+            "error" | "err" => Ok(Level::Error),
+            "fatal" => Ok(Level::Fatal),
+            _ => Err(ParseLevelError(s.to_string())),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Exception {
     #[serde(rename = "type")]
@@ -44,6 +165,10 @@ pub struct Exception {
     pub value: String,
     pub module: Option<String>,
     pub stacktrace: Option<Stacktrace>,
+    /// Messages of the errors that caused this one, outermost first. Defaults to empty
+    /// so events from older clients still parse.
+    #[serde(default)]
+    pub chain: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +191,17 @@ pub struct Frame {
     pub vars: Option<HashMap<String, String>>,
 }
 
+/// A step leading up to an event, such as a log line or an outgoing request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Breadcrumb {
+    pub timestamp: DateTime<Utc>,
+    pub level: Level,
+    pub category: Option<String>,
+    pub message: Option<String>,
+    #[serde(default)]
+    pub data: HashMap<String, serde_json::Value>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: Option<String>,
@@ -85,6 +221,186 @@ pub struct Request {
     pub env: Option<HashMap<String, String>>,
 }
 
+impl Exception {
+    /// Builds an exception from `error`, walking `source()` into [`Exception::chain`].
+    /// The type is taken from the leading identifier of the `Debug` output, the closest
+    /// thing to a type name a `dyn Error` offers.
+    pub fn from_error(error: &dyn std::error::Error) -> Self {
+        let mut chain = Vec::new();
+        let mut source = error.source();
+        while let Some(cause) = source {
+            chain.push(cause.to_string());
+            source = cause.source();
+        }
+
+        Self {
+            exception_type: type_from_debug(&format!("{:?}", error)),
+            value: error.to_string(),
+            module: None,
+            stacktrace: None,
+            chain,
+        }
+    }
+}
+
+fn type_from_debug(debug: &str) -> String {
+    let name: String = debug
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_' || *c == ':')
+        .collect();
+
+    if name.is_empty() {
+        "Error".to_string()
+    } else {
+        name
+    }
+}
+
+impl Stacktrace {
+    /// Parses the frames of a captured backtrace. Returns `None` when the backtrace was
+    /// not captured (see `RUST_BACKTRACE`) or has no frames.
+    pub fn from_backtrace(backtrace: &Backtrace) -> Option<Self> {
+        Self::parse(&backtrace.to_string())
+    }
+
+    /// Parses the `Display` output of `std::backtrace::Backtrace`, where each frame is a
+    /// `N: function` line optionally followed by an `at file:line:col` line. Frames are
+    /// returned outermost caller first.
+    pub fn parse(formatted: &str) -> Option<Self> {
+        let mut frames: Vec<Frame> = Vec::new();
+
+        for line in formatted.lines().map(str::trim) {
+            if let Some(location) = line.strip_prefix("at ") {
+                if let Some(frame) = frames.last_mut() {
+                    frame.set_location(location);
+                }
+                continue;
+            }
+
+            if let Some((index, function)) = line.split_once(": ")
+                && !index.is_empty()
+                && index.chars().all(|c| c.is_ascii_digit())
+            {
+                frames.push(Frame::for_function(function));
+            }
+        }
+
+        if frames.is_empty() {
+            return None;
+        }
+
+        frames.reverse();
+        Some(Self { frames })
+    }
+}
+
+impl Frame {
+    fn for_function(function: &str) -> Self {
+        Self {
+            filename: "<unknown>".to_string(),
+            function: Some(strip_symbol_hash(function).to_string()),
+            module: None,
+            lineno: None,
+            colno: None,
+            abs_path: None,
+            context_line: None,
+            pre_context: None,
+            post_context: None,
+            in_app: None,
+            vars: None,
+        }
+    }
+
+    /// Applies a `file:line:col` location, where line and column may be missing.
+    fn set_location(&mut self, location: &str) {
+        let (rest, last) = split_trailing_number(location);
+        let (filename, lineno, colno) = match split_trailing_number(rest) {
+            (filename, Some(lineno)) => (filename, Some(lineno), last),
+            (filename, None) => (filename, last, None),
+        };
+
+        self.filename = filename.to_string();
+        self.lineno = lineno;
+        self.colno = colno;
+        if std::path::Path::new(filename).is_absolute() {
+            self.abs_path = Some(filename.to_string());
+        }
+    }
+}
+
+fn split_trailing_number(s: &str) -> (&str, Option<u32>) {
+    match s.rsplit_once(':') {
+        Some((head, number)) => match number.parse() {
+            Ok(number) => (head, Some(number)),
+            Err(_) => (s, None),
+        },
+        None => (s, None),
+    }
+}
+
+/// Drops the `::h<16 hex digits>` suffix of mangled-then-demangled symbols.
+fn strip_symbol_hash(function: &str) -> &str {
+    match function.rsplit_once("::h") {
+        Some((name, hash)) if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+            name
+        }
+        _ => function,
+    }
+}
+
+/// Masks the variable parts of a message (anything containing a digit, and long hex
+/// strings) so that messages differing only in ids or counts normalize alike.
+pub fn normalize_message(message: &str) -> String {
+    message
+        .split_whitespace()
+        .map(|word| {
+            let is_hex = word.len() >= 8 && word.chars().all(|c| c.is_ascii_hexdigit());
+            if is_hex || word.chars().any(|c| c.is_ascii_digit()) {
+                "<*>"
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fingerprint used when neither the event nor `Config::with_fingerprinter` chooses one:
+/// the normalized message and the logger.
+pub fn default_fingerprint(event: &Event) -> Vec<String> {
+    vec![
+        normalize_message(event.message.as_deref().unwrap_or_default()),
+        event.logger.clone().unwrap_or_default(),
+    ]
+}
+
+impl Breadcrumb {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            level: Level::Info,
+            category: None,
+            message: Some(message.into()),
+            data: HashMap::new(),
+        }
+    }
+
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub fn with_category(mut self, category: impl Into<String>) -> Self {
+        self.category = Some(category.into());
+        self
+    }
+
+    pub fn with_data(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.data.insert(key.into(), value);
+        self
+    }
+}
+
 impl Default for Event {
     fn default() -> Self {
         Self::new()
@@ -98,12 +414,14 @@ impl Event {
 
     pub fn with_platform(platform: &str) -> Self {
         Self {
+            schema_version: SCHEMA_VERSION,
             event_id: Uuid::new_v4().to_string(),
             timestamp: Utc::now(),
             platform: platform.to_string(),
             level: Level::Info,
             logger: None,
             transaction: None,
+            duration_ms: None,
             server_name: None,
             release: None,
             environment: None,
@@ -117,7 +435,67 @@ impl Event {
             fingerprint: None,
             modules: None,
             nostr_tags: Vec::new(),
+            breadcrumbs: Vec::new(),
+            attachments: Vec::new(),
+            attachment_refs: Vec::new(),
+            force_send: false,
+        }
+    }
+
+    /// Parses event JSON of any schema version, skipping what does not fit instead of
+    /// failing: unknown fields, and known fields whose values have another shape, each
+    /// reported as a [`ParseWarning`]. Only JSON that is not an object, or lacks a valid
+    /// `event_id`, `timestamp`, `platform` or `level`, is an error.
+    pub fn parse_lenient(json: &str) -> Result<(Event, Vec<ParseWarning>), crate::SentryStrError> {
+        let serde_json::Value::Object(mut fields) = serde_json::from_str(json)? else {
+            return Err(crate::SentryStrError::Config(
+                "Event JSON is not an object".to_string(),
+            ));
+        };
+
+        let mut warnings = Vec::new();
+        let serde_json::Value::Object(known) = serde_json::to_value(Event::new())? else {
+            unreachable!("events serialize to objects");
+        };
+        fields.retain(|field, _| {
+            let keep = known.contains_key(field);
+            if !keep {
+                warnings.push(ParseWarning::UnknownField(field.clone()));
+            }
+            keep
+        });
+
+        // Check each optional field on its own next to the required ones, so one bad
+        // field does not hide the others.
+        let required: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .filter(|(field, _)| REQUIRED_FIELDS.contains(&field.as_str()))
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect();
+        serde_json::from_value::<Event>(serde_json::Value::Object(required.clone()))?;
+
+        let optional: Vec<String> = fields
+            .keys()
+            .filter(|field| !REQUIRED_FIELDS.contains(&field.as_str()))
+            .cloned()
+            .collect();
+        for field in optional {
+            let mut probe = required.clone();
+            probe.insert(field.clone(), fields[&field].clone());
+            if let Err(e) = serde_json::from_value::<Event>(serde_json::Value::Object(probe)) {
+                fields.remove(&field);
+                warnings.push(ParseWarning::IncompatibleField {
+                    field,
+                    error: e.to_string(),
+                });
+            }
+        }
+
+        let event: Event = serde_json::from_value(serde_json::Value::Object(fields))?;
+        if event.schema_version > SCHEMA_VERSION {
+            warnings.push(ParseWarning::NewerSchema(event.schema_version));
         }
+        Ok((event, warnings))
     }
 
     pub fn with_message(mut self, message: impl Into<String>) -> Self {
@@ -130,6 +508,14 @@ impl Event {
         self
     }
 
+    /// Sets when the event happened, for events recorded earlier than they are
+    /// captured. See `Config::with_timestamp_window` for how it becomes the Nostr
+    /// `created_at`.
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
     pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
         self.tags.insert(key.into(), value.into());
         self
@@ -140,6 +526,34 @@ impl Event {
         self
     }
 
+    /// Serializes `value` into the extra `key`. Fails when `value` cannot be represented
+    /// as JSON, e.g. a map with non-string keys such as tuples.
+    pub fn try_with_extra_ser<T: Serialize + ?Sized>(
+        self,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Result<Self, crate::SentryStrError> {
+        Ok(self.with_extra(key, serde_json::to_value(value)?))
+    }
+
+    /// [`Self::try_with_extra_ser`], storing the `Debug` output of `value` as a string
+    /// when it cannot be serialized.
+    pub fn with_extra_ser<T: Serialize + fmt::Debug + ?Sized>(
+        self,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Self {
+        let value = serde_json::to_value(value)
+            .unwrap_or_else(|_| serde_json::Value::String(format!("{:?}", value)));
+        self.with_extra(key, value)
+    }
+
+    /// Names the server explicitly; the client's default server name is then not used.
+    pub fn with_server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
     pub fn with_user(mut self, user: User) -> Self {
         self.user = Some(user);
         self
@@ -153,6 +567,102 @@ impl Event {
         self
     }
 
+    /// Exempts the event from sampling (`Config::with_sample_rate`), so it is always sent.
+    pub fn with_force_send(mut self) -> Self {
+        self.force_send = true;
+        self
+    }
+
+    /// An Info-level performance event: the transaction `name` took `duration`, e.g.
+    /// "checkout" and 1.8 seconds. See `NostrSentryClient::start_transaction` to measure
+    /// the duration.
+    pub fn transaction_event(name: impl Into<String>, duration: Duration) -> Self {
+        Self::new().with_transaction(name).with_duration(duration)
+    }
+
+    pub fn with_transaction(mut self, transaction: impl Into<String>) -> Self {
+        self.transaction = Some(transaction.into());
+        self
+    }
+
+    /// Sets [`Event::duration_ms`], rounded down to the millisecond.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration_ms = Some(duration.as_millis().try_into().unwrap_or(u64::MAX));
+        self
+    }
+
+    /// An Error-level event for `error`, with its `source()` chain as the exception and
+    /// the current stack when backtraces are enabled.
+    pub fn from_error(error: &(dyn std::error::Error + 'static)) -> Self {
+        Self::new()
+            .with_level(Level::Error)
+            .with_error(error)
+            .with_backtrace(&Backtrace::capture())
+    }
+
+    /// An Error-level event for an `anyhow::Error`. Its context layers become the chain,
+    /// and the backtrace is the one anyhow captured when the error was created.
+    #[cfg(feature = "anyhow")]
+    pub fn from_anyhow(error: &anyhow::Error) -> Self {
+        let root: &(dyn std::error::Error + 'static) = error.as_ref();
+        Self::new()
+            .with_level(Level::Error)
+            .with_error(root)
+            .with_backtrace(error.backtrace())
+    }
+
+    /// Records `error` and its `source()` chain as an exception, and uses it as the
+    /// message when none is set.
+    pub fn with_error(mut self, error: &dyn std::error::Error) -> Self {
+        if self.message.is_none() {
+            self.message = Some(error.to_string());
+        }
+        self.with_exception(Exception::from_error(error))
+    }
+
+    /// Attaches the frames of a captured backtrace, e.g. `Backtrace::capture()`. Does
+    /// nothing when the backtrace is disabled or unsupported.
+    pub fn with_backtrace(mut self, backtrace: &Backtrace) -> Self {
+        if let Some(stacktrace) = Stacktrace::from_backtrace(backtrace) {
+            self.stacktrace = Some(stacktrace);
+        }
+        self
+    }
+
+    pub fn with_fingerprint<I, S>(mut self, parts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.fingerprint = Some(parts.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The fingerprint as a `["f", "<parts joined by |>"]` Nostr tag, see
+    /// [`FINGERPRINT_TAG`], so relays can be queried for one group.
+    pub fn fingerprint_tag(&self) -> Option<Tag> {
+        let fingerprint = self.fingerprint.as_ref()?.join(FINGERPRINT_SEPARATOR);
+        Tag::parse(vec![FINGERPRINT_TAG, &fingerprint]).ok()
+    }
+
+    pub fn with_breadcrumbs(mut self, breadcrumbs: Vec<Breadcrumb>) -> Self {
+        self.breadcrumbs = breadcrumbs;
+        self
+    }
+
+    /// Attaches `bytes`, published as a separate event on capture. Attachments larger
+    /// than `Config::max_attachment_bytes`, or together larger than
+    /// `Config::max_attachments_bytes`, fail the capture.
+    pub fn with_attachment(
+        mut self,
+        name: impl Into<String>,
+        mime: impl Into<String>,
+        bytes: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.attachments.push(Attachment::new(name, mime, bytes));
+        self
+    }
+
     pub fn with_nostr_tag(mut self, tag: Tag) -> Self {
         self.nostr_tags.push(tag);
         self
@@ -176,15 +686,8 @@ impl Event {
     }
 
     pub fn with_severity_tag(mut self, level: &Level) -> Self {
-        let severity = match level {
-            Level::Debug => "debug",
-            Level::Info => "info",
-            Level::Warning => "warning",
-            Level::Error => "error",
-            Level::Fatal => "fatal",
-        };
         self.nostr_tags
-            .push(Tag::parse(vec!["severity", severity]).unwrap());
+            .push(Tag::parse(vec!["severity", level.as_str()]).unwrap());
         self
     }
 
@@ -194,3 +697,313 @@ impl Event {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_fingerprint_tag_has_a_single_letter() {
+        let event = Event::new().with_fingerprint(["payments", "provider-timeout"]);
+
+        let tag = event.fingerprint_tag().unwrap();
+
+        assert_eq!(tag.as_slice(), ["f", "payments|provider-timeout"]);
+        assert!(tag.single_letter_tag().is_some());
+        assert!(Event::new().fingerprint_tag().is_none());
+    }
+
+    #[derive(Debug)]
+    struct Layered {
+        message: &'static str,
+        source: Option<Box<Layered>>,
+    }
+
+    impl fmt::Display for Layered {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str(self.message)
+        }
+    }
+
+    impl std::error::Error for Layered {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.source
+                .as_deref()
+                .map(|source| source as &(dyn std::error::Error + 'static))
+        }
+    }
+
+    fn three_level_error() -> Layered {
+        let layer = |message, source: Option<Layered>| Layered {
+            message,
+            source: source.map(Box::new),
+        };
+        layer(
+            "checkout failed",
+            Some(layer(
+                "payment provider unreachable",
+                Some(layer("connection refused", None)),
+            )),
+        )
+    }
+
+    #[test]
+    fn errors_are_recorded_with_their_cause_chain() {
+        let event = Event::from_error(&three_level_error());
+
+        assert_eq!(event.level, Level::Error);
+        assert_eq!(event.message.as_deref(), Some("checkout failed"));
+        let exception = &event.exception.unwrap()[0];
+        assert_eq!(exception.exception_type, "Layered");
+        assert_eq!(exception.value, "checkout failed");
+        assert_eq!(
+            exception.chain,
+            ["payment provider unreachable", "connection refused"]
+        );
+    }
+
+    #[test]
+    fn backtrace_frames_are_parsed_outermost_first() {
+        let formatted = "\
+   0: app::checkout::charge::h0123456789abcdef
+             at ./src/checkout.rs:42:9
+   1: app::main
+             at /home/ci/app/src/main.rs:7
+   2: std::rt::lang_start_internal
+";
+
+        let frames = Stacktrace::parse(formatted).unwrap().frames;
+
+        assert_eq!(frames.len(), 3);
+        assert_eq!(
+            frames[0].function.as_deref(),
+            Some("std::rt::lang_start_internal")
+        );
+        assert_eq!(frames[0].filename, "<unknown>");
+        assert_eq!(frames[1].function.as_deref(), Some("app::main"));
+        assert_eq!(frames[1].filename, "/home/ci/app/src/main.rs");
+        assert_eq!((frames[1].lineno, frames[1].colno), (Some(7), None));
+        assert_eq!(
+            frames[1].abs_path.as_deref(),
+            Some("/home/ci/app/src/main.rs")
+        );
+        assert_eq!(frames[2].function.as_deref(), Some("app::checkout::charge"));
+        assert_eq!(frames[2].filename, "./src/checkout.rs");
+        assert_eq!((frames[2].lineno, frames[2].colno), (Some(42), Some(9)));
+        assert_eq!(frames[2].abs_path, None);
+
+        assert!(Stacktrace::parse("disabled backtrace").is_none());
+    }
+
+    #[test]
+    fn exceptions_stay_compatible_with_older_events() {
+        let old = serde_json::json!({
+            "event_id": "1",
+            "timestamp": "2024-01-01T00:00:00Z",
+            "platform": "rust",
+            "level": "error",
+            "logger": null,
+            "transaction": null,
+            "server_name": null,
+            "release": null,
+            "environment": null,
+            "message": "checkout failed",
+            "exception": [{
+                "type": "Layered",
+                "value": "checkout failed",
+                "module": null,
+                "stacktrace": null,
+                "mechanism": "generic"
+            }],
+            "stacktrace": null,
+            "user": null,
+            "request": null,
+            "fingerprint": null,
+            "modules": null
+        });
+
+        let event: Event = serde_json::from_value(old).unwrap();
+        let exception = &event.exception.unwrap()[0];
+        assert_eq!(exception.value, "checkout failed");
+        assert!(exception.chain.is_empty());
+
+        let new = serde_json::to_value(Event::from_error(&three_level_error())).unwrap();
+        assert_eq!(new["exception"][0]["type"], "Layered");
+        assert_eq!(new["exception"][0]["chain"][1], "connection refused");
+    }
+
+    #[test]
+    fn levels_parse_display_and_order() {
+        for (input, expected) in [
+            ("debug", Some(Level::Debug)),
+            ("info", Some(Level::Info)),
+            ("warning", Some(Level::Warning)),
+            ("warn", Some(Level::Warning)),
+            ("error", Some(Level::Error)),
+            ("err", Some(Level::Error)),
+            ("fatal", Some(Level::Fatal)),
+            (" ERROR ", Some(Level::Error)),
+            ("Warn", Some(Level::Warning)),
+            ("critical", None),
+            ("", None),
+        ] {
+            assert_eq!(input.parse::<Level>().ok(), expected, "{:?}", input);
+        }
+        assert_eq!(
+            "critical".parse::<Level>(),
+            Err(ParseLevelError("critical".to_string()))
+        );
+
+        let levels = [
+            Level::Debug,
+            Level::Info,
+            Level::Warning,
+            Level::Error,
+            Level::Fatal,
+        ];
+        for level in &levels {
+            assert_eq!(level.to_string(), level.as_str());
+            assert_eq!(level.to_string().parse::<Level>().unwrap(), *level);
+            let json = serde_json::to_value(level).unwrap();
+            assert_eq!(json, level.as_str());
+            assert_eq!(serde_json::from_value::<Level>(json).unwrap(), *level);
+        }
+
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+        let mut shuffled = [
+            Level::Error,
+            Level::Debug,
+            Level::Fatal,
+            Level::Info,
+            Level::Warning,
+        ];
+        shuffled.sort();
+        assert_eq!(shuffled, levels);
+        assert_eq!(levels.iter().max(), Some(&Level::Fatal));
+    }
+
+    #[test]
+    fn lenient_parsing_skips_what_it_cannot_read() {
+        type Fields = serde_json::Map<String, serde_json::Value>;
+        type Case = (&'static str, fn(&mut Fields), fn(&Event, &[ParseWarning]));
+        let cases: [Case; 5] = [
+            (
+                "current",
+                |_| {},
+                |_, warnings| assert!(warnings.is_empty()),
+            ),
+            (
+                "legacy",
+                |fields| {
+                    fields.remove("schema_version");
+                    fields.remove("breadcrumbs");
+                    fields.remove("duration_ms");
+                },
+                |event, warnings| {
+                    assert_eq!(event.schema_version, 1);
+                    assert!(warnings.is_empty());
+                },
+            ),
+            (
+                "unknown field",
+                |fields| {
+                    fields.insert("trace_id".to_string(), "abc".into());
+                },
+                |_, warnings| {
+                    assert_eq!(
+                        warnings,
+                        [ParseWarning::UnknownField("trace_id".to_string())]
+                    );
+                },
+            ),
+            (
+                "newer schema",
+                |fields| {
+                    fields.insert("schema_version".to_string(), 9.into());
+                },
+                |event, warnings| {
+                    assert_eq!(event.schema_version, 9);
+                    assert_eq!(warnings, [ParseWarning::NewerSchema(9)]);
+                },
+            ),
+            (
+                "incompatible field",
+                |fields| {
+                    fields.insert("tags".to_string(), "service=billing".into());
+                },
+                |event, warnings| {
+                    assert!(event.tags.is_empty());
+                    assert!(matches!(
+                        warnings,
+                        [ParseWarning::IncompatibleField { field, .. }] if field == "tags"
+                    ));
+                },
+            ),
+        ];
+
+        for (name, edit, check) in cases {
+            let serde_json::Value::Object(mut fields) =
+                serde_json::to_value(Event::new().with_message("checkout failed")).unwrap()
+            else {
+                unreachable!("events serialize to objects");
+            };
+            edit(&mut fields);
+
+            let json = serde_json::Value::Object(fields).to_string();
+            let (event, warnings) = Event::parse_lenient(&json).unwrap();
+            assert_eq!(
+                event.message.as_deref(),
+                Some("checkout failed"),
+                "{}",
+                name
+            );
+            check(&event, &warnings);
+        }
+
+        for unreadable in [
+            "[]",
+            "not json",
+            r#"{"event_id":"1","timestamp":"2024-01-01T00:00:00Z","platform":"rust"}"#,
+            r#"{"event_id":"1","timestamp":"yesterday","platform":"rust","level":"error"}"#,
+        ] {
+            assert!(Event::parse_lenient(unreadable).is_err(), "{}", unreadable);
+        }
+    }
+
+    #[derive(Debug, Serialize)]
+    struct Order {
+        id: u32,
+        items: Vec<&'static str>,
+    }
+
+    #[test]
+    fn typed_extras_are_serialized_or_fall_back_to_debug() {
+        let order = Order {
+            id: 7,
+            items: vec!["book"],
+        };
+        let by_tuple = HashMap::from([((1, 2), "cell")]);
+        let by_number = HashMap::from([(404, "not found")]);
+
+        let event = Event::new()
+            .try_with_extra_ser("order", &order)
+            .unwrap()
+            .try_with_extra_ser("statuses", &by_number)
+            .unwrap()
+            .with_extra_ser("grid", &by_tuple);
+
+        assert_eq!(
+            event.extra["order"],
+            serde_json::json!({"id": 7, "items": ["book"]})
+        );
+        assert_eq!(
+            event.extra["statuses"],
+            serde_json::json!({"404": "not found"})
+        );
+        assert_eq!(event.extra["grid"], r#"{(1, 2): "cell"}"#);
+        assert!(matches!(
+            Event::new().try_with_extra_ser("grid", &by_tuple),
+            Err(crate::SentryStrError::Json(_))
+        ));
+    }
+}
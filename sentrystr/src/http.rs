@@ -0,0 +1,140 @@
+//! Request context shared by the HTTP integrations, sentrystr-tower and sentrystr-actix.
+
+use std::collections::HashMap;
+
+/// Header holding the id of a request; one is generated when it is absent.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Headers recorded on captured events unless configured otherwise.
+pub const DEFAULT_HEADER_ALLOWLIST: &[&str] = &[
+    "accept",
+    "content-length",
+    "content-type",
+    "host",
+    "referer",
+    "user-agent",
+    "x-request-id",
+];
+
+/// Headers whose values are never recorded, even when allowlisted.
+pub const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// Query parameters whose values are never recorded: those whose name contains one of
+/// these, ignoring case, such as `access_token` or `apiKey`.
+pub const SENSITIVE_QUERY_PARAMS: &[&str] = &[
+    "auth",
+    "key",
+    "password",
+    "secret",
+    "session",
+    "signature",
+    "token",
+];
+
+/// Replaces the values of sensitive headers and query parameters.
+pub const FILTERED: &str = "[Filtered]";
+
+/// The `headers` named in `allowlist`, compared case-insensitively, with the values of
+/// [`SENSITIVE_HEADERS`] replaced by [`FILTERED`]. Takes `(name, value)` pairs so
+/// either `http` version's header map can be passed.
+pub fn selected_headers<'a>(
+    headers: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    allowlist: &[String],
+) -> HashMap<String, String> {
+    headers
+        .into_iter()
+        .filter(|(name, _)| {
+            allowlist
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(name))
+        })
+        .map(|(name, value)| {
+            let name = name.to_ascii_lowercase();
+            let value = if SENSITIVE_HEADERS.contains(&name.as_str()) {
+                FILTERED.to_string()
+            } else {
+                String::from_utf8_lossy(value).into_owned()
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+/// `query` with the values of [`SENSITIVE_QUERY_PARAMS`] replaced by [`FILTERED`].
+pub fn scrub_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| {
+            let name = pair.split_once('=').map_or(pair, |(name, _)| name);
+            let lowercase = name.to_ascii_lowercase();
+            if SENSITIVE_QUERY_PARAMS
+                .iter()
+                .any(|sensitive| lowercase.contains(sensitive))
+            {
+                format!("{}={}", name, FILTERED)
+            } else {
+                pair.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// `url` with its query scrubbed by [`scrub_query`].
+pub fn scrub_url(url: &str) -> String {
+    match url.split_once('?') {
+        Some((base, query)) => format!("{}?{}", base, scrub_query(query)),
+        None => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(headers: &[&str]) -> Vec<String> {
+        headers.iter().map(|header| header.to_string()).collect()
+    }
+
+    #[test]
+    fn only_allowlisted_headers_are_selected() {
+        let headers = [
+            ("User-Agent", b"curl/8".as_slice()),
+            ("x-internal", b"secret".as_slice()),
+        ];
+
+        let selected = selected_headers(headers, &allowlist(DEFAULT_HEADER_ALLOWLIST));
+
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected["user-agent"], "curl/8");
+    }
+
+    #[test]
+    fn sensitive_headers_are_filtered_even_when_allowlisted() {
+        let headers = [
+            ("authorization", b"Bearer token".as_slice()),
+            ("Cookie", b"session=1".as_slice()),
+        ];
+
+        let selected = selected_headers(headers, &allowlist(&["Authorization", "cookie"]));
+
+        assert_eq!(selected["authorization"], FILTERED);
+        assert_eq!(selected["cookie"], FILTERED);
+    }
+
+    #[test]
+    fn sensitive_query_parameters_are_filtered() {
+        assert_eq!(
+            scrub_url("/users/7?page=2&access_token=abc&apiKey=def&flag"),
+            "/users/7?page=2&access_token=[Filtered]&apiKey=[Filtered]&flag"
+        );
+        assert_eq!(scrub_query("Password"), "Password=[Filtered]");
+        assert_eq!(scrub_url("/health"), "/health");
+    }
+}
@@ -0,0 +1,247 @@
+use crate::rt::Instant;
+use nostr::prelude::*;
+use nostr_sdk::prelude::*;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Relays indexing the relay lists of most users, asked for a recipient's kind 10050
+/// besides the sender's own relays.
+pub const DEFAULT_INDEXER_RELAYS: &[&str] = &[
+    "wss://purplepag.es",
+    "wss://user.kindpag.es",
+    "wss://indexer.coracle.social",
+];
+/// How long a recipient's inbox relays are reused before being fetched again.
+pub const DEFAULT_INBOX_TTL: Duration = Duration::from_secs(3600);
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where the recipient wants NIP-17 messages, from their kind 10050 relay list.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InboxLookup {
+    /// Empty when the list could not be found; messages then go to the sender's relays.
+    pub(crate) relays: Vec<RelayUrl>,
+    pub(crate) error: Option<String>,
+}
+
+/// Relays added to a client for the requests in flight, such as indexers or the
+/// recipient's inbox relays, with how many requests use each. The last request to
+/// finish removes the relay, so the client's pool goes back to the relays it was built
+/// with, and the client's own publishes never reach them.
+#[derive(Default)]
+pub(crate) struct TransientRelays {
+    in_use: Mutex<HashMap<RelayUrl, usize>>,
+}
+
+impl TransientRelays {
+    /// Adds the `relays` missing from `client`'s pool, write-only or read-only as
+    /// `write` says, and returns those the request may use. Pass them to
+    /// [`Self::release`] once it is done.
+    pub(crate) async fn acquire(
+        &self,
+        client: &Client,
+        relays: &[RelayUrl],
+        write: bool,
+    ) -> Vec<RelayUrl> {
+        let mut in_use = self.in_use.lock().await;
+        let mut acquired = Vec::with_capacity(relays.len());
+        for relay in relays {
+            if let Some(count) = in_use.get_mut(relay) {
+                *count += 1;
+            } else if client.relay(relay).await.is_err() {
+                let added = if write {
+                    client.add_write_relay(relay.clone()).await
+                } else {
+                    client.add_read_relay(relay.clone()).await
+                };
+                if let Err(e) = added {
+                    eprintln!("Failed to add relay {}: {}", relay, e);
+                    continue;
+                }
+                in_use.insert(relay.clone(), 1);
+            }
+            acquired.push(relay.clone());
+        }
+        acquired
+    }
+
+    /// Releases relays returned by [`Self::acquire`], removing those no other request
+    /// uses.
+    pub(crate) async fn release(&self, client: &Client, relays: &[RelayUrl]) {
+        let mut in_use = self.in_use.lock().await;
+        for relay in relays {
+            let Some(count) = in_use.get_mut(relay) else {
+                continue;
+            };
+            *count -= 1;
+            if *count == 0 {
+                in_use.remove(relay);
+                if let Err(e) = client.remove_relay(relay).await {
+                    eprintln!("Failed to remove relay {}: {}", relay, e);
+                }
+            }
+        }
+    }
+}
+
+/// The recipient's inbox relays, fetched on first use and again once `ttl` passed.
+pub(crate) struct InboxCache {
+    indexers: Vec<RelayUrl>,
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, InboxLookup)>>,
+}
+
+impl InboxCache {
+    pub(crate) fn new(indexers: Vec<RelayUrl>, ttl: Duration) -> Self {
+        Self {
+            indexers,
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Indexers of [`DEFAULT_INDEXER_RELAYS`] and a TTL of [`DEFAULT_INBOX_TTL`].
+    pub(crate) fn with_defaults() -> Self {
+        let indexers = DEFAULT_INDEXER_RELAYS
+            .iter()
+            .filter_map(|relay| RelayUrl::parse(relay).ok())
+            .collect();
+        Self::new(indexers, DEFAULT_INBOX_TTL)
+    }
+
+    /// The cached lookup, or a new one when none is fresh. Concurrent sends wait for a
+    /// single fetch. Failed lookups are cached as well, so an unreachable indexer does
+    /// not delay every message.
+    pub(crate) async fn lookup(
+        &self,
+        client: &Client,
+        transient: &TransientRelays,
+        recipient: PublicKey,
+    ) -> InboxLookup {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, ref lookup)) = *cached
+            && fetched_at.elapsed() < self.ttl
+        {
+            return lookup.clone();
+        }
+
+        let indexers = transient.acquire(client, &self.indexers, false).await;
+        let lookup = self.fetch(client, &indexers, recipient).await;
+        transient.release(client, &indexers).await;
+        *cached = Some((Instant::now(), lookup.clone()));
+        lookup
+    }
+
+    /// Asks the client's relays and the `indexers`, read-only relays in its pool for
+    /// this lookup, for the newest kind 10050 of `recipient`.
+    async fn fetch(
+        &self,
+        client: &Client,
+        indexers: &[RelayUrl],
+        recipient: PublicKey,
+    ) -> InboxLookup {
+        for indexer in indexers {
+            if let Err(e) = client
+                .try_connect_relay(indexer.clone(), CONNECT_TIMEOUT)
+                .await
+            {
+                eprintln!("Failed to connect to indexer relay {}: {}", indexer, e);
+            }
+        }
+
+        let filter = Filter::new()
+            .kind(Kind::InboxRelays)
+            .author(recipient)
+            .limit(1);
+        let events = match client.fetch_events(filter, FETCH_TIMEOUT).await {
+            Ok(events) => events,
+            Err(e) => {
+                return InboxLookup {
+                    relays: Vec::new(),
+                    error: Some(format!("Failed to fetch the recipient's relay list: {}", e)),
+                };
+            }
+        };
+
+        let relays: Vec<RelayUrl> = events
+            .into_iter()
+            .max_by_key(|event| event.created_at)
+            .map(|event| relay_tags(&event))
+            .unwrap_or_default();
+        let error = relays
+            .is_empty()
+            .then(|| "The recipient has no kind 10050 relay list".to_string());
+        InboxLookup { relays, error }
+    }
+}
+
+/// Relays of the `relay` tags of a kind 10050 event; malformed URLs are skipped.
+fn relay_tags(event: &nostr::Event) -> Vec<RelayUrl> {
+    event
+        .tags
+        .iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, url, ..] if name == "relay" => RelayUrl::parse(url).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(relay: &str) -> RelayUrl {
+        RelayUrl::parse(relay).unwrap()
+    }
+
+    async fn pool(client: &Client) -> Vec<RelayUrl> {
+        let mut relays: Vec<RelayUrl> = client.relays().await.into_keys().collect();
+        relays.sort();
+        relays
+    }
+
+    #[tokio::test]
+    async fn transient_relays_leave_the_pool_as_configured() {
+        let client = Client::new(Keys::generate());
+        let own = url("wss://relay.example.com");
+        client.add_relay(own.clone()).await.unwrap();
+        let inbox = url("wss://inbox.example.com");
+        let transient = TransientRelays::default();
+
+        let first = transient
+            .acquire(&client, &[own.clone(), inbox.clone()], true)
+            .await;
+        let second = transient
+            .acquire(&client, std::slice::from_ref(&inbox), true)
+            .await;
+        assert_eq!(first, vec![own.clone(), inbox.clone()]);
+        assert_eq!(pool(&client).await.len(), 2);
+
+        // Still used by the second request.
+        transient.release(&client, &first).await;
+        assert_eq!(pool(&client).await.len(), 2);
+
+        transient.release(&client, &second).await;
+        assert_eq!(pool(&client).await, vec![own]);
+    }
+
+    #[tokio::test]
+    async fn indexers_are_read_only_while_in_use() {
+        let client = Client::new(Keys::generate());
+        let indexer = url("wss://indexer.example.com");
+        let transient = TransientRelays::default();
+
+        let acquired = transient
+            .acquire(&client, std::slice::from_ref(&indexer), false)
+            .await;
+        let relay = client.relay(&indexer).await.unwrap();
+        assert!(relay.flags().has_read());
+        assert!(!relay.flags().has_write());
+
+        transient.release(&client, &acquired).await;
+        assert!(client.relays().await.is_empty());
+    }
+}
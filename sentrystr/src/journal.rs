@@ -0,0 +1,346 @@
+use crate::queue::QueuedEvent;
+use crate::{Event, Result, SentryStrError};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Clients an event may fail to publish in before it leaves the file, so an event that
+/// relays always reject is not replayed forever.
+pub(crate) const MAX_FAILURES: u32 = 3;
+
+/// One line of the journal.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Add {
+        id: u64,
+        nostr_event: Box<nostr::Event>,
+        #[serde(default)]
+        attachments: Vec<nostr::Event>,
+        event: Box<Event>,
+        /// Whether the event was queued with a DM sender.
+        #[serde(default)]
+        dm: bool,
+    },
+    Done {
+        id: u64,
+    },
+    /// A failed publish of the event with `id`.
+    Fail {
+        id: u64,
+    },
+}
+
+/// The `add` line of a pending event and how often it failed to publish.
+struct LiveEntry {
+    line: String,
+    failures: u32,
+}
+
+/// Queued events read back from the journal, in the order they were queued.
+pub(crate) struct Backlog {
+    pub(crate) events: Vec<QueuedEvent>,
+    /// Lines that could not be parsed, such as one cut short by a crash.
+    pub(crate) corrupt: u64,
+}
+
+struct JournalState {
+    file: File,
+    size: u64,
+    next_id: u64,
+    /// Events not yet published, by id.
+    live: BTreeMap<u64, LiveEntry>,
+}
+
+/// Append-only file of the events in a client's queue: a line is added when an event is
+/// queued, another when it is published, and one per failed publish, so events still
+/// pending when the process stops are published by the next client on the same file.
+/// The file is rewritten with only the pending events on startup and whenever it
+/// outgrows `max_bytes`. Its methods block on file IO; call them off the runtime.
+pub(crate) struct Journal {
+    path: PathBuf,
+    max_bytes: u64,
+    state: Mutex<JournalState>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, creating it if needed, and returns the events it
+    /// still holds.
+    pub(crate) fn open(path: &Path, max_bytes: u64) -> Result<(Self, Backlog)> {
+        let content = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(io_error(path, e)),
+        };
+
+        let mut live = BTreeMap::new();
+        let mut events = BTreeMap::new();
+        let mut corrupt = 0;
+        let mut next_id = 0;
+
+        for line in content.split(|byte| *byte == b'\n') {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            match serde_json::from_slice::<Record>(line) {
+                Ok(Record::Add {
+                    id,
+                    nostr_event,
+                    attachments,
+                    event,
+                    dm,
+                }) => {
+                    next_id = next_id.max(id + 1);
+                    live.insert(
+                        id,
+                        LiveEntry {
+                            line: String::from_utf8_lossy(line).into_owned(),
+                            failures: 0,
+                        },
+                    );
+                    events.insert(
+                        id,
+                        QueuedEvent {
+                            nostr_event: *nostr_event,
+                            attachments,
+                            event: *event,
+                            dm_sender: None,
+                            replay_dm: dm,
+                            journal_id: Some(id),
+                        },
+                    );
+                }
+                Ok(Record::Done { id }) => {
+                    live.remove(&id);
+                    events.remove(&id);
+                }
+                Ok(Record::Fail { id }) => {
+                    if let Some(entry) = live.get_mut(&id) {
+                        entry.failures += 1;
+                    }
+                }
+                Err(_) => corrupt += 1,
+            }
+        }
+        if corrupt > 0 {
+            eprintln!(
+                "Skipped {} corrupt entries in queue file {}",
+                corrupt,
+                path.display()
+            );
+        }
+
+        let (file, size) = rewrite(path, &live)?;
+        let journal = Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            state: Mutex::new(JournalState {
+                file,
+                size,
+                next_id,
+                live,
+            }),
+        };
+        let backlog = Backlog {
+            events: events.into_values().collect(),
+            corrupt,
+        };
+        Ok((journal, backlog))
+    }
+
+    /// The `add` record of `entry`, without its id, for [`Self::append`]. Cheap enough
+    /// to build on the runtime.
+    pub(crate) fn add_record(entry: &QueuedEvent) -> Value {
+        json!({
+            "op": "add",
+            "nostr_event": entry.nostr_event,
+            "attachments": entry.attachments,
+            "event": entry.event,
+            "dm": entry.dm_sender.is_some(),
+        })
+    }
+
+    /// Writes the `record` from [`Self::add_record`] to the file and returns its id, or
+    /// `None` when it does not fit in `max_bytes` or cannot be written; the event is
+    /// then only kept in memory.
+    pub(crate) fn append(&self, mut record: Value) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        record["id"] = json!(id);
+        let line = record.to_string();
+
+        let needed = line.len() as u64 + 1;
+        if state.size + needed > self.max_bytes {
+            self.compact(&mut state);
+            if state.size + needed > self.max_bytes {
+                return None;
+            }
+        }
+
+        if let Err(e) = writeln!(state.file, "{}", line) {
+            eprintln!("Failed to write queue file {}: {}", self.path.display(), e);
+            return None;
+        }
+        state.size += needed;
+        state.next_id += 1;
+        state.live.insert(id, LiveEntry { line, failures: 0 });
+        Some(id)
+    }
+
+    /// Records a failed publish of the event with `id`. After [`MAX_FAILURES`] it leaves
+    /// the file like a published one; returns whether it did.
+    pub(crate) fn fail(&self, id: u64) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(entry) = state.live.get_mut(&id) else {
+            return false;
+        };
+        entry.failures += 1;
+        if entry.failures >= MAX_FAILURES {
+            drop(state);
+            self.remove(id);
+            return true;
+        }
+
+        let line = json!({ "op": "fail", "id": id }).to_string();
+        match writeln!(state.file, "{}", line) {
+            Ok(()) => state.size += line.len() as u64 + 1,
+            Err(e) => eprintln!("Failed to write queue file {}: {}", self.path.display(), e),
+        }
+        false
+    }
+
+    /// Records that the event with `id` left the queue.
+    pub(crate) fn remove(&self, id: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.live.remove(&id).is_none() {
+            return;
+        }
+
+        // Nothing pending: start the file over instead of growing it.
+        let result = if state.live.is_empty() {
+            state.file.set_len(0).map(|_| state.size = 0)
+        } else {
+            let line = json!({ "op": "done", "id": id }).to_string();
+            writeln!(state.file, "{}", line).map(|_| state.size += line.len() as u64 + 1)
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to write queue file {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Rewrites the file with only the pending events.
+    fn compact(&self, state: &mut JournalState) {
+        match rewrite(&self.path, &state.live) {
+            Ok((file, size)) => {
+                state.file = file;
+                state.size = size;
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// Replaces the file at `path` with the `live` events and their failures, through a
+/// temporary file, and opens it for appending.
+fn rewrite(path: &Path, live: &BTreeMap<u64, LiveEntry>) -> Result<(File, u64)> {
+    let temporary = path.with_extension("tmp");
+    let mut content = String::new();
+    for (id, entry) in live {
+        content.push_str(&entry.line);
+        content.push('\n');
+        for _ in 0..entry.failures {
+            content.push_str(&json!({ "op": "fail", "id": id }).to_string());
+            content.push('\n');
+        }
+    }
+    std::fs::write(&temporary, &content).map_err(|e| io_error(&temporary, e))?;
+    std::fs::rename(&temporary, path).map_err(|e| io_error(path, e))?;
+
+    let file = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map_err(|e| io_error(path, e))?;
+    Ok((file, content.len() as u64))
+}
+
+fn io_error(path: &Path, error: std::io::Error) -> SentryStrError {
+    SentryStrError::Config(format!("Queue file {}: {}", path.display(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectMessageBuilder;
+    use nostr::{EventBuilder, Keys};
+    use nostr_sdk::Client;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sentrystr-journal-{}-{}", name, std::process::id()))
+    }
+
+    fn entry(with_dm: bool) -> QueuedEvent {
+        let keys = Keys::generate();
+        let dm_sender = with_dm.then(|| {
+            DirectMessageBuilder::new()
+                .with_client(Client::new(keys.clone()))
+                .with_keys(keys.clone())
+                .with_recipient(Keys::generate().public_key())
+                .build()
+                .unwrap()
+        });
+        QueuedEvent {
+            nostr_event: EventBuilder::text_note("payment failed")
+                .sign_with_keys(&keys)
+                .unwrap(),
+            attachments: Vec::new(),
+            event: Event::new().with_message("payment failed"),
+            dm_sender,
+            replay_dm: false,
+            journal_id: None,
+        }
+    }
+
+    #[test]
+    fn events_leave_the_file_after_failing_in_max_failures_clients() {
+        let path = path("failures");
+        let _ = std::fs::remove_file(&path);
+
+        let (journal, _) = Journal::open(&path, 1 << 20).unwrap();
+        let id = journal.append(Journal::add_record(&entry(false))).unwrap();
+        for _ in 1..MAX_FAILURES {
+            assert!(!journal.fail(id));
+        }
+        drop(journal);
+
+        // The failures survive the rewrite on startup.
+        let (journal, backlog) = Journal::open(&path, 1 << 20).unwrap();
+        assert_eq!(backlog.events.len(), 1);
+        assert!(journal.fail(id));
+        drop(journal);
+
+        let (_, backlog) = Journal::open(&path, 1 << 20).unwrap();
+        assert!(backlog.events.is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn replayed_events_remember_their_dm() {
+        let path = path("dm");
+        let _ = std::fs::remove_file(&path);
+
+        let (journal, _) = Journal::open(&path, 1 << 20).unwrap();
+        journal.append(Journal::add_record(&entry(true))).unwrap();
+        journal.append(Journal::add_record(&entry(false))).unwrap();
+        drop(journal);
+
+        let (_, backlog) = Journal::open(&path, 1 << 20).unwrap();
+        let replay_dm: Vec<bool> = backlog.events.iter().map(|e| e.replay_dm).collect();
+        assert_eq!(replay_dm, vec![true, false]);
+        assert!(backlog.events.iter().all(|e| e.dm_sender.is_none()));
+        let _ = std::fs::remove_file(&path);
+    }
+}
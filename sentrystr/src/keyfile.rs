@@ -0,0 +1,200 @@
+//! Secret keys stored in files, either in plaintext (hex or nsec) or encrypted with a
+//! passphrase as a NIP-49 `ncryptsec`.
+
+use crate::{Result, SentryStrError};
+use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr::prelude::*;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Prefix of NIP-49 encrypted keys.
+pub const NCRYPTSEC_PREFIX: &str = "ncryptsec1";
+/// Environment variable the CLIs read the key file passphrase from.
+pub const PASSPHRASE_VAR: &str = "SENTRYSTR_KEY_PASSPHRASE";
+
+/// Scrypt work factor of keys written by [`write_encrypted_key_file`], as recommended
+/// by NIP-49.
+const LOG_N: u8 = 16;
+
+/// Where the passphrase of an encrypted key file comes from.
+#[derive(Clone)]
+pub enum PassphraseSource {
+    /// The value of an environment variable.
+    Env(String),
+    /// Asked for on the terminal, without echo.
+    #[cfg(feature = "prompt")]
+    Prompt,
+    /// Returned by a function, e.g. reading a secret manager.
+    Callback(Arc<dyn Fn() -> Option<String> + Send + Sync>),
+}
+
+impl PassphraseSource {
+    pub fn env(name: impl Into<String>) -> Self {
+        Self::Env(name.into())
+    }
+
+    pub fn callback(callback: impl Fn() -> Option<String> + Send + Sync + 'static) -> Self {
+        Self::Callback(Arc::new(callback))
+    }
+
+    /// Reads the passphrase.
+    pub fn read(&self) -> Result<String> {
+        match self {
+            Self::Env(name) => std::env::var(name)
+                .map_err(|_| SentryStrError::Passphrase(format!("{} is not set", name))),
+            #[cfg(feature = "prompt")]
+            Self::Prompt => rpassword::prompt_password("Key passphrase: ")
+                .map_err(|e| SentryStrError::Passphrase(format!("Prompt failed: {}", e))),
+            Self::Callback(callback) => callback().ok_or_else(|| {
+                SentryStrError::Passphrase("The passphrase callback returned none".to_string())
+            }),
+        }
+    }
+}
+
+impl std::fmt::Debug for PassphraseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Env(name) => f.debug_tuple("Env").field(name).finish(),
+            #[cfg(feature = "prompt")]
+            Self::Prompt => f.write_str("Prompt"),
+            Self::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+/// Reads the keys in the file at `path`: an `ncryptsec` decrypted with the passphrase
+/// from `passphrase`, or a plaintext hex or nsec key, for which `passphrase` is not
+/// used. A missing file fails with [`SentryStrError::KeyFileNotFound`], a wrong
+/// passphrase or corrupt key with [`SentryStrError::KeyDecryption`].
+pub fn read_key_file(path: impl AsRef<Path>, passphrase: &PassphraseSource) -> Result<Keys> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => SentryStrError::KeyFileNotFound(path.to_path_buf()),
+        _ => SentryStrError::KeyFile(format!("Failed to read {}: {}", path.display(), e)),
+    })?;
+    let content = content.trim();
+
+    if content.starts_with(NCRYPTSEC_PREFIX) {
+        let encrypted = EncryptedSecretKey::from_bech32(content)
+            .map_err(|e| SentryStrError::KeyDecryption(format!("Malformed ncryptsec: {}", e)))?;
+        let secret_key = encrypted
+            .decrypt(&passphrase.read()?)
+            .map_err(|e| SentryStrError::KeyDecryption(e.to_string()))?;
+        return Ok(Keys::new(secret_key));
+    }
+
+    // Errors from the key parser never include the key.
+    Keys::parse(content).map_err(|_| {
+        SentryStrError::KeyFile(format!(
+            "{} holds neither an ncryptsec nor a hex or nsec key",
+            path.display()
+        ))
+    })
+}
+
+/// Writes the secret key of `keys` to `path` as an `ncryptsec` encrypted with
+/// `passphrase`, readable only by the owner on Unix.
+pub fn write_encrypted_key_file(
+    keys: &Keys,
+    path: impl AsRef<Path>,
+    passphrase: &str,
+) -> Result<()> {
+    let encrypted =
+        EncryptedSecretKey::new(keys.secret_key(), passphrase, LOG_N, KeySecurity::Medium)
+            .map_err(|e| SentryStrError::KeyFile(format!("Failed to encrypt key: {}", e)))?;
+    let content = encrypted
+        .to_bech32()
+        .map_err(|e| SentryStrError::KeyFile(format!("Failed to encode key: {}", e)))?;
+    write_private(path.as_ref(), &content)
+}
+
+/// Writes the secret key of `keys` to `path` as a plaintext nsec, readable only by the
+/// owner on Unix.
+pub fn write_key_file(keys: &Keys, path: impl AsRef<Path>) -> Result<()> {
+    let content = keys
+        .secret_key()
+        .to_bech32()
+        .map_err(|e| SentryStrError::KeyFile(format!("Failed to encode key: {}", e)))?;
+    write_private(path.as_ref(), &content)
+}
+
+fn write_private(path: &Path, content: &str) -> Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let write = |mut file: std::fs::File| {
+        std::io::Write::write_all(&mut file, format!("{}\n", content).as_bytes())
+    };
+    options
+        .open(path)
+        .and_then(write)
+        .map_err(|e| SentryStrError::KeyFile(format!("Failed to write {}: {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// The encrypted key of the NIP-49 specification, for the passphrase `nostr`.
+    const SPEC_NCRYPTSEC: &str = "ncryptsec1qgg9947rlpvqu76pj5ecreduf9jxhselq2nae2kghhvd5g7dgjtcxfqtd67p9m0w57lspw8gsq6yphnm8623nsl8xn9j4jdzz84zm3frztj3z7s35vpzmqf6ksu8r89qk5z2zxfmu5gv8th8wclt0h4p";
+    const SPEC_SECRET_KEY: &str =
+        "3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683";
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sentrystr-key-{}-{}", name, std::process::id()))
+    }
+
+    fn passphrase(passphrase: &'static str) -> PassphraseSource {
+        PassphraseSource::callback(move || Some(passphrase.to_string()))
+    }
+
+    #[test]
+    fn the_specification_key_decrypts() {
+        let path = path("spec");
+        std::fs::write(&path, format!("{}\n", SPEC_NCRYPTSEC)).unwrap();
+
+        let keys = read_key_file(&path, &passphrase("nostr")).unwrap();
+        assert_eq!(keys.secret_key().to_secret_hex(), SPEC_SECRET_KEY);
+
+        assert!(matches!(
+            read_key_file(&path, &passphrase("nostr2")),
+            Err(SentryStrError::KeyDecryption(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn written_keys_read_back() {
+        let keys = Keys::generate();
+        let encrypted = path("encrypted");
+        let plain = path("plain");
+        write_encrypted_key_file(&keys, &encrypted, "correct horse").unwrap();
+        write_key_file(&keys, &plain).unwrap();
+
+        let read = read_key_file(&encrypted, &passphrase("correct horse")).unwrap();
+        assert_eq!(read.public_key(), keys.public_key());
+        assert!(matches!(
+            read_key_file(&encrypted, &passphrase("wrong horse")),
+            Err(SentryStrError::KeyDecryption(_))
+        ));
+        let read = read_key_file(&plain, &passphrase("unused")).unwrap();
+        assert_eq!(read.public_key(), keys.public_key());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&plain).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        std::fs::remove_file(encrypted).unwrap();
+        std::fs::remove_file(plain).unwrap();
+        assert!(matches!(
+            read_key_file(path("missing"), &passphrase("unused")),
+            Err(SentryStrError::KeyFileNotFound(_))
+        ));
+    }
+}
@@ -4,7 +4,7 @@
 //!
 //! ## Quick Start
 //!
-//! ```rust
+//! ```rust,no_run
 //! use sentrystr::{Config, Event, Level, NostrSentryClient};
 //! use nostr::Keys;
 //!
@@ -13,7 +13,7 @@
 //!     // Basic setup
 //!     let keys = Keys::generate();
 //!     let relays = vec!["wss://relay.damus.io".to_string()];
-//!     let config = Config::new(keys.secret_key().display_secret().to_string(), relays);
+//!     let config = Config::try_new(keys.secret_key().display_secret().to_string(), relays)?;
 //!
 //!     // Create client
 //!     let client = NostrSentryClient::new(config).await?;
@@ -24,16 +24,37 @@
 //!         .with_level(Level::Error);
 //!
 //!     client.capture_event(event).await?;
-//!     client.capture_error("Database connection failed").await?;
-//!     client.capture_message("System started").await?;
+//!     client.capture_error_message("Database connection failed").await?;
+//!     client.capture_message(Level::Info, "System started").await?;
 //!
 //!     Ok(())
 //! }
 //! ```
 //!
+//! ## Timing Transactions
+//!
+//! [`NostrSentryClient::start_transaction`] is called on an `Arc<NostrSentryClient>`: the
+//! guard it returns holds the client so that dropping the guard can still capture the
+//! transaction.
+//!
+//! ```rust,no_run
+//! use sentrystr::{Config, NostrSentryClient};
+//! use std::sync::Arc;
+//!
+//! # async fn example(config: Config) -> sentrystr::Result<()> {
+//! let client = Arc::new(NostrSentryClient::new(config).await?);
+//!
+//! let mut transaction = client.start_transaction("nightly-report");
+//! transaction.set_tag("job", "report");
+//! // ...
+//! transaction.finish().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
 //! ## With Direct Messaging
 //!
-//! ```rust
+//! ```rust,no_run
 //! use sentrystr::{Config, DirectMessageBuilder, Event, Level, NostrSentryClient};
 //! use nostr::prelude::*;
 //! use nostr_sdk::prelude::*;
@@ -43,7 +64,7 @@
 //!     // Setup main client
 //!     let keys = Keys::generate();
 //!     let relays = vec!["wss://relay.damus.io".to_string()];
-//!     let config = Config::new(keys.secret_key().display_secret().to_string(), relays.clone());
+//!     let config = Config::try_new(keys.secret_key().display_secret().to_string(), relays.clone())?;
 //!     let mut client = NostrSentryClient::new(config).await?;
 //!
 //!     // Setup direct messaging
@@ -64,25 +85,92 @@
 //!     client.set_direct_messaging(dm_sender);
 //!
 //!     // Now errors will also send DMs
-//!     client.capture_error("Critical system failure").await?;
+//!     client.capture_error_message("Critical system failure").await?;
 //!
 //!     Ok(())
 //! }
 //! ```
 
+pub mod attachment;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod blocking;
 pub mod client;
 pub mod combined_example;
+pub mod compat;
 pub mod config;
+mod dedup;
+pub mod delivery;
+mod digest;
 pub mod encryption;
 pub mod error;
 pub mod event;
+pub mod http;
+mod inbox;
+mod journal;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod keyfile;
 pub mod messaging;
+pub mod pow;
+pub mod proxy;
+pub mod queue;
+pub mod rate_limit;
+pub mod retry;
+mod rt;
+mod sample;
+pub mod scope;
+mod sequence;
+pub mod template;
+mod throttle;
+pub mod transaction;
+pub mod transport;
+mod truncate;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
 
-pub use client::NostrSentryClient;
-pub use config::{Config, EncryptionVersion};
-pub use encryption::{EncryptionHelper, validate_encryption_keys};
-pub use error::SentryStrError;
-pub use event::{Event, Exception, Frame, Level, Request, Stacktrace, User};
-pub use messaging::{DirectMessageBuilder, DirectMessageConfig, DirectMessageSender, MessageEvent};
+pub use attachment::{ATTACHMENT_KIND, Attachment, AttachmentRef};
+pub use client::{
+    CaptureOutcome, ClientMetrics, CloseReport, NostrSentryClient, PublishReceipt, PublishStats,
+    RelayInfo, RelayMetrics, RelayStatusInfo,
+};
+pub use config::{
+    BeforeSend, Config, DEFAULT_EVENT_KIND, DEFAULT_MAX_ATTACHMENT_BYTES,
+    DEFAULT_MAX_ATTACHMENTS_BYTES, DEFAULT_MAX_EVENT_BYTES, DEFAULT_MAX_EXTRA_DEPTH,
+    DEFAULT_MAX_FUTURE_SKEW, DEFAULT_MAX_PAST_SKEW, EVENT_KIND_RANGE, EncryptionVersion,
+    Fingerprinter, HostnameProvider, RelaySpec, detect_hostname,
+};
+pub use dedup::DEFAULT_DEDUP_MAX_ENTRIES;
+pub use delivery::{MinRelaySuccess, Percentage, RelayDeliveryStatus, RelayOutcome};
+pub use digest::EventDigest;
+pub use encryption::{
+    ContentEncryption, ENCRYPTION_TAG, EncryptionHelper, NIP44_V2_SCHEME, PLAINTEXT_TAG,
+    plaintext_tag_value, validate_encryption_keys,
+};
+pub use error::{
+    EnvConfigError, EnvVarError, ParseDmEncryptionError, ParseLevelError, SentryStrError,
+};
+pub use event::{
+    Breadcrumb, Event, Exception, FINGERPRINT_SEPARATOR, FINGERPRINT_TAG, Frame, Level,
+    ParseWarning, Request, SCHEMA_VERSION, Stacktrace, User, default_fingerprint,
+    normalize_message,
+};
+pub use inbox::{DEFAULT_INBOX_TTL, DEFAULT_INDEXER_RELAYS};
+#[cfg(not(target_arch = "wasm32"))]
+pub use keyfile::{PassphraseSource, read_key_file, write_encrypted_key_file, write_key_file};
+pub use messaging::{
+    DirectMessageBuilder, DirectMessageConfig, DirectMessageSender, DmDelivery, DmEncryption,
+    MessageEvent, MessageFormatter, NJUMP_URL, nevent,
+};
+pub use pow::{PowConfig, PowPolicy};
+pub use proxy::ProxyConfig;
+pub use queue::{OverflowPolicy, QueueConfig, QueuePersistence, QueueStats};
+pub use rate_limit::RateLimit;
+pub use retry::RetryPolicy;
+pub use sample::Sampler;
+pub use scope::{Scope, with_scope};
+pub use sequence::{SEQUENCE_EXTRA, SEQUENCE_TAG};
+pub use template::MessageTemplate;
+pub use throttle::DM_THROTTLE_MAX_FINGERPRINTS;
+pub use transaction::TransactionGuard;
+pub use transport::{ClientTransport, Transport, TransportMetrics};
 
 pub type Result<T> = std::result::Result<T, SentryStrError>;
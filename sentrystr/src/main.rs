@@ -9,7 +9,7 @@ async fn main() -> sentrystr::Result<()> {
         "wss://nostr.chaima.info".to_string(),
     ];
 
-    let config = Config::new(secret_key, relays);
+    let config = Config::try_new(secret_key, relays)?;
     let client = NostrSentryClient::new(config).await?;
 
     let event = Event::new()
@@ -21,8 +21,10 @@ async fn main() -> sentrystr::Result<()> {
     let event_id = client.capture_event(event).await?;
     println!("Published event: {}", event_id);
 
-    client.capture_message("Simple message test").await?;
-    client.capture_error("Error message test").await?;
+    client
+        .capture_message(Level::Info, "Simple message test")
+        .await?;
+    client.capture_error_message("Error message test").await?;
 
     client.disconnect().await?;
     Ok(())
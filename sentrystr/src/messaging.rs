@@ -1,14 +1,112 @@
-use crate::{EncryptionHelper, Event, Result, SentryStrError};
+use crate::digest::Digest;
+use crate::inbox::{
+    DEFAULT_INBOX_TTL, DEFAULT_INDEXER_RELAYS, InboxCache, InboxLookup, TransientRelays,
+};
+use crate::template::MessageTemplate;
+use crate::throttle::Throttle;
+use crate::{EncryptionHelper, Event, ParseDmEncryptionError, RateLimit, Result, SentryStrError};
 use chrono::{DateTime, Utc};
 use nostr::prelude::*;
 use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
 use serde_json;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Base of the web links sent with [`DirectMessageBuilder::with_njump_links`].
+pub const NJUMP_URL: &str = "https://njump.me/";
+
+/// Relay hints put in `nevent` links; clients only try the first few.
+const MAX_RELAY_HINTS: usize = 3;
+
+/// Renders the text of a direct message, see [`DirectMessageBuilder::with_formatter`].
+pub type MessageFormatter = Arc<dyn Fn(&MessageEvent) -> String + Send + Sync>;
+
+/// How alerts for events are worded.
+#[derive(Clone)]
+enum MessageFormat {
+    Default,
+    Template(MessageTemplate),
+    Custom(MessageFormatter),
+}
+
+/// How direct messages are encrypted, see [`DirectMessageBuilder::with_encryption`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DmEncryption {
+    /// Kind 4 with NIP-04 content. Legacy and deprecated: the content is weakly
+    /// encrypted and the metadata public. Only for clients that cannot read NIP-44.
+    Nip04,
+    /// Kind 4 with NIP-44 content.
+    #[default]
+    Nip44,
+    /// NIP-17 private message, gift wrapped (NIP-59) so the metadata stays private.
+    #[serde(rename = "nip17")]
+    Nip17GiftWrap,
+}
+
+impl DmEncryption {
+    /// What the boolean of the older `with_nip17` methods selects.
+    pub fn from_nip17(use_nip17: bool) -> Self {
+        if use_nip17 {
+            Self::Nip17GiftWrap
+        } else {
+            Self::Nip44
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Nip04 => "nip04",
+            Self::Nip44 => "nip44",
+            Self::Nip17GiftWrap => "nip17",
+        }
+    }
+}
+
+impl fmt::Display for DmEncryption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DmEncryption {
+    type Err = ParseDmEncryptionError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "nip04" | "nip-04" => Ok(Self::Nip04),
+            "nip44" | "nip-44" => Ok(Self::Nip44),
+            "nip17" | "nip-17" => Ok(Self::Nip17GiftWrap),
+            _ => Err(ParseDmEncryptionError(s.to_string())),
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct DirectMessageConfig {
     pub recipient_pubkey: PublicKey,
     pub min_level: Option<crate::Level>,
-    pub use_nip17: bool,
+    pub encryption: DmEncryption,
+    /// Relays named in the `nevent` link of alerts; empty uses the relays of the client.
+    pub relay_hints: Vec<RelayUrl>,
+    /// Link alerts to `njump.me` instead of with a `nostr:` URI.
+    pub njump_links: bool,
+    /// Deliver NIP-17 messages to the relays of the recipient's kind 10050 list.
+    pub inbox_discovery: bool,
+}
+
+/// Where a direct message went and what relays answered.
+#[derive(Debug, Clone)]
+pub struct DmDelivery {
+    pub output: Output<EventId>,
+    /// Relays of the recipient's kind 10050 list the message was delivered to; empty when
+    /// it went to the sender's relays.
+    pub inbox_relays: Vec<RelayUrl>,
+    /// Why the recipient's inbox relays are unknown, when discovery was attempted.
+    pub discovery_error: Option<String>,
 }
 
 #[derive(Debug)]
@@ -24,6 +122,14 @@ pub struct DirectMessageSender {
     client: Client,
     keys: Keys,
     config: DirectMessageConfig,
+    format: MessageFormat,
+    /// Set in digest mode, where events are summarized periodically.
+    digest: Option<Arc<Digest>>,
+    /// Set with a rate limit or cooldown, which hold back repeated alerts.
+    throttle: Option<Arc<Throttle>>,
+    inbox: Arc<InboxCache>,
+    /// Indexer and inbox relays in the client's pool for the sends in flight.
+    transient: Arc<TransientRelays>,
 }
 
 impl DirectMessageSender {
@@ -32,6 +138,11 @@ impl DirectMessageSender {
             client,
             keys,
             config,
+            format: MessageFormat::Default,
+            digest: None,
+            throttle: None,
+            inbox: Arc::new(InboxCache::with_defaults()),
+            transient: Arc::default(),
         }
     }
 
@@ -40,74 +151,182 @@ impl DirectMessageSender {
             return Ok(());
         }
 
-        let event_json = serde_json::to_string_pretty(&event.event)?;
-        let message_content = format!(
-            "SentryStr Alert\n\nEvent ID: {}\nAuthor: {}\nTimestamp: {}\nLevel: {:?}\n\nEvent Data:\n{}",
-            event.nostr_event_id,
-            event.author,
-            event.event.timestamp,
-            event.event.level,
-            event_json
-        );
+        if let Some(ref digest) = self.digest {
+            digest.record(event);
+            return Ok(());
+        }
+
+        if let Some(ref throttle) = self.throttle
+            && !throttle.allow(event)
+        {
+            return Ok(());
+        }
+
+        let mut message_content = self.format_message(event)?;
+        if let Some(link) = self.event_link(event).await {
+            message_content.push_str("\n\n");
+            message_content.push_str(&link);
+        }
+        self.send_custom_message(&message_content).await
+    }
 
-        if self.config.use_nip17 {
-            self.send_nip17_message(&message_content).await
+    /// The link to `event` appended to alerts, `None` for the all-zeros placeholder id.
+    async fn event_link(&self, event: &MessageEvent) -> Option<String> {
+        let relays: Vec<RelayUrl> = if self.config.relay_hints.is_empty() {
+            self.client.relays().await.into_keys().collect()
         } else {
-            self.send_nip44_message(&message_content).await
+            self.config.relay_hints.clone()
+        };
+
+        let nevent = nevent(event, &relays)?;
+        Some(if self.config.njump_links {
+            format!("{}{}", NJUMP_URL, nevent)
+        } else {
+            format!("nostr:{}", nevent)
+        })
+    }
+
+    /// Sends the digest of events buffered so far right away. Does nothing outside of
+    /// digest mode or when no events are buffered.
+    pub async fn flush(&self) -> Result<()> {
+        match self.digest {
+            Some(ref digest) => digest.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// The text sent for `event`: the template or formatter of the builder, or the
+    /// default alert with the full event JSON.
+    pub fn format_message(&self, event: &MessageEvent) -> Result<String> {
+        match self.format {
+            MessageFormat::Default => {
+                let event_json = serde_json::to_string_pretty(&event.event)?;
+                Ok(format!(
+                    "SentryStr Alert\n\nEvent ID: {}\nAuthor: {}\nTimestamp: {}\nLevel: {:?}\n\nEvent Data:\n{}",
+                    event.nostr_event_id,
+                    event.author,
+                    event.event.timestamp,
+                    event.event.level,
+                    event_json
+                ))
+            }
+            MessageFormat::Template(ref template) => Ok(template.render(event)),
+            MessageFormat::Custom(ref formatter) => Ok(formatter(event)),
         }
     }
 
+    /// Sends `content`. A failed inbox relay discovery is logged; see
+    /// [`DirectMessageSender::send_custom_message_with_output`] to handle it.
     pub async fn send_custom_message(&self, content: &str) -> Result<()> {
-        if self.config.use_nip17 {
-            self.send_nip17_message(content).await
-        } else {
-            self.send_nip44_message(content).await
+        let delivery = self.send_custom_message_with_output(content).await?;
+        if let Some(error) = delivery.discovery_error {
+            eprintln!("Sent direct message to the sender's relays: {}", error);
+        }
+        Ok(())
+    }
+
+    /// Sends `content` like [`DirectMessageSender::send_custom_message`] and returns where
+    /// it went and which relays accepted or rejected it.
+    ///
+    /// With NIP-17 and inbox discovery, the message goes to the relays of the recipient's
+    /// kind 10050 list, looked up on the sender's relays and the indexer relays on first
+    /// send and cached. When no list is found it goes to the sender's relays, with the
+    /// reason in [`DmDelivery::discovery_error`].
+    pub async fn send_custom_message_with_output(&self, content: &str) -> Result<DmDelivery> {
+        if self.config.encryption != DmEncryption::Nip17GiftWrap {
+            let output = self.send_kind4_message(content).await?;
+            return Ok(DmDelivery {
+                output,
+                inbox_relays: Vec::new(),
+                discovery_error: None,
+            });
         }
+
+        let inbox = if self.config.inbox_discovery {
+            self.inbox
+                .lookup(&self.client, &self.transient, self.config.recipient_pubkey)
+                .await
+        } else {
+            InboxLookup::default()
+        };
+        let output = self.send_nip17_message(content, &inbox.relays).await?;
+        Ok(DmDelivery {
+            output,
+            inbox_relays: inbox.relays,
+            discovery_error: inbox.error,
+        })
+    }
+
+    /// Public key the messages are sent from.
+    pub fn sender_pubkey(&self) -> PublicKey {
+        self.keys.public_key()
     }
 
     fn should_send_for_level(&self, event_level: &crate::Level) -> bool {
-        if let Some(ref min_level) = self.config.min_level {
-            match (min_level, event_level) {
-                (crate::Level::Debug, _) => true,
-                (crate::Level::Info, crate::Level::Debug) => false,
-                (crate::Level::Info, _) => true,
-                (crate::Level::Warning, crate::Level::Debug | crate::Level::Info) => false,
-                (crate::Level::Warning, _) => true,
-                (
-                    crate::Level::Error,
-                    crate::Level::Debug | crate::Level::Info | crate::Level::Warning,
-                ) => false,
-                (crate::Level::Error, _) => true,
-                (crate::Level::Fatal, crate::Level::Fatal) => true,
-                (crate::Level::Fatal, _) => false,
+        self.config
+            .min_level
+            .as_ref()
+            .is_none_or(|min_level| event_level >= min_level)
+    }
+
+    /// Gift wraps `content` to `inbox_relays`, or the sender's relays when empty. The
+    /// inbox relays are in the client's pool for this send only, write-only so the
+    /// sender's subscriptions do not move to them.
+    async fn send_nip17_message(
+        &self,
+        content: &str,
+        inbox_relays: &[RelayUrl],
+    ) -> Result<Output<EventId>> {
+        let targets = self
+            .transient
+            .acquire(&self.client, inbox_relays, true)
+            .await;
+        for relay in &targets {
+            if let Err(e) = self.client.connect_relay(relay.clone()).await {
+                eprintln!("Failed to connect to inbox relay {}: {}", relay, e);
             }
-        } else {
-            true
         }
+        let sent = self.send_nip17_with_retries(content, &targets).await;
+        self.transient.release(&self.client, &targets).await;
+        sent
     }
 
-    async fn send_nip17_message(&self, content: &str) -> Result<()> {
+    async fn send_nip17_with_retries(
+        &self,
+        content: &str,
+        inbox_relays: &[RelayUrl],
+    ) -> Result<Output<EventId>> {
         const MAX_RETRIES: u32 = 3;
         const BASE_DELAY_MS: u64 = 1000;
 
         for attempt in 0..MAX_RETRIES {
-            match self
-                .client
-                .send_private_msg(self.config.recipient_pubkey, content, [])
-                .await
-            {
-                Ok(_) => {
+            let sent = if inbox_relays.is_empty() {
+                self.client
+                    .send_private_msg(self.config.recipient_pubkey, content, [])
+                    .await
+            } else {
+                self.client
+                    .send_private_msg_to(
+                        inbox_relays.to_vec(),
+                        self.config.recipient_pubkey,
+                        content,
+                        [],
+                    )
+                    .await
+            };
+            match sent {
+                Ok(output) => {
                     if attempt > 0 {
                         eprintln!("Successfully sent NIP-17 message after {} retries", attempt);
                     }
-                    return Ok(());
+                    return Ok(output);
                 }
                 Err(e) => {
                     eprintln!("NIP-17 send attempt {} failed: {}", attempt + 1, e);
                     if attempt < MAX_RETRIES - 1 {
                         let delay =
                             std::time::Duration::from_millis(BASE_DELAY_MS * (1 << attempt));
-                        tokio::time::sleep(delay).await;
+                        crate::rt::sleep(delay).await;
                     }
                 }
             }
@@ -118,12 +337,19 @@ impl DirectMessageSender {
         ))
     }
 
-    async fn send_nip44_message(&self, content: &str) -> Result<()> {
+    /// Sends `content` as a kind 4 direct message, encrypted with NIP-04 or NIP-44.
+    async fn send_kind4_message(&self, content: &str) -> Result<Output<EventId>> {
         const MAX_RETRIES: u32 = 3;
         const BASE_DELAY_MS: u64 = 1000;
 
+        type Encrypt = fn(&SecretKey, &PublicKey, &str) -> Result<String>;
+        let (encrypt, scheme): (Encrypt, _) = match self.config.encryption {
+            DmEncryption::Nip04 => (EncryptionHelper::encrypt_nip04, "NIP-04"),
+            _ => (EncryptionHelper::encrypt_nip44, "NIP-44"),
+        };
+
         for attempt in 0..MAX_RETRIES {
-            let encrypted_content = EncryptionHelper::encrypt_nip44(
+            let encrypted_content = encrypt(
                 self.keys.secret_key(),
                 &self.config.recipient_pubkey,
                 content,
@@ -134,27 +360,45 @@ impl DirectMessageSender {
                 .sign_with_keys(&self.keys)?;
 
             match self.client.send_event(&dm_event).await {
-                Ok(_) => {
+                Ok(output) => {
                     if attempt > 0 {
-                        eprintln!("Successfully sent NIP-44 message after {} retries", attempt);
+                        eprintln!(
+                            "Successfully sent {} message after {} retries",
+                            scheme, attempt
+                        );
                     }
-                    return Ok(());
+                    return Ok(output);
                 }
                 Err(e) => {
-                    eprintln!("NIP-44 send attempt {} failed: {}", attempt + 1, e);
+                    eprintln!("{} send attempt {} failed: {}", scheme, attempt + 1, e);
                     if attempt < MAX_RETRIES - 1 {
                         let delay =
                             std::time::Duration::from_millis(BASE_DELAY_MS * (1 << attempt));
-                        tokio::time::sleep(delay).await;
+                        crate::rt::sleep(delay).await;
                     }
                 }
             }
         }
 
-        Err(SentryStrError::Config(
-            "Failed to send NIP-44 message after retries".to_string(),
-        ))
+        Err(SentryStrError::Config(format!(
+            "Failed to send {} message after retries",
+            scheme
+        )))
+    }
+}
+
+/// The NIP-19 `nevent1…` of the event behind `event`, with up to three of `relays` as
+/// hints. `None` when the id is the all-zeros placeholder, which would link nowhere.
+pub fn nevent(event: &MessageEvent, relays: &[RelayUrl]) -> Option<String> {
+    if event.nostr_event_id == EventId::all_zeros() {
+        return None;
     }
+
+    Nip19Event::new(event.nostr_event_id)
+        .author(event.author)
+        .relays(relays.iter().take(MAX_RELAY_HINTS).cloned())
+        .to_bech32()
+        .ok()
 }
 
 pub struct DirectMessageBuilder {
@@ -162,7 +406,17 @@ pub struct DirectMessageBuilder {
     keys: Option<Keys>,
     recipient_pubkey: Option<PublicKey>,
     min_level: Option<crate::Level>,
-    use_nip17: bool,
+    encryption: DmEncryption,
+    template: Option<String>,
+    formatter: Option<MessageFormatter>,
+    digest: Option<(Duration, usize)>,
+    rate_limit: Option<RateLimit>,
+    cooldown: Option<Duration>,
+    relay_hints: Vec<String>,
+    njump_links: bool,
+    inbox_discovery: bool,
+    indexer_relays: Vec<String>,
+    inbox_ttl: Duration,
 }
 
 impl DirectMessageBuilder {
@@ -172,7 +426,20 @@ impl DirectMessageBuilder {
             keys: None,
             recipient_pubkey: None,
             min_level: None,
-            use_nip17: false,
+            encryption: DmEncryption::default(),
+            template: None,
+            formatter: None,
+            digest: None,
+            rate_limit: None,
+            cooldown: None,
+            relay_hints: Vec::new(),
+            njump_links: false,
+            inbox_discovery: false,
+            indexer_relays: DEFAULT_INDEXER_RELAYS
+                .iter()
+                .map(|relay| relay.to_string())
+                .collect(),
+            inbox_ttl: DEFAULT_INBOX_TTL,
         }
     }
 
@@ -196,8 +463,102 @@ impl DirectMessageBuilder {
         self
     }
 
-    pub fn with_nip17(mut self, use_nip17: bool) -> Self {
-        self.use_nip17 = use_nip17;
+    /// How messages are encrypted, NIP-44 by default. `DmEncryption::Nip04` is legacy:
+    /// use it only for recipients whose clients cannot read anything newer.
+    pub fn with_encryption(mut self, encryption: DmEncryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Superseded by [`Self::with_encryption`]: `true` selects
+    /// `DmEncryption::Nip17GiftWrap`, `false` `DmEncryption::Nip44`.
+    pub fn with_nip17(self, use_nip17: bool) -> Self {
+        self.with_encryption(DmEncryption::from_nip17(use_nip17))
+    }
+
+    /// Words alerts with a [`MessageTemplate`] such as `"[{level}] {message}"`, checked
+    /// by `build`.
+    pub fn with_template(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Words alerts with `formatter`; takes precedence over a template.
+    pub fn with_formatter(
+        mut self,
+        formatter: impl Fn(&MessageEvent) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.formatter = Some(Arc::new(formatter));
+        self
+    }
+
+    /// Buffers events instead of sending one DM each, and sends a summary every
+    /// `interval`: counts per level, the most frequent messages and the time span.
+    /// Intervals without events send nothing. At most `max_items` distinct messages are
+    /// listed; `build` needs a Tokio runtime to start the timer and fails on a zero
+    /// `interval`.
+    pub fn with_digest(mut self, interval: Duration, max_items: usize) -> Self {
+        self.digest = Some((interval, max_items));
+        self
+    }
+
+    /// Sends at most `max_messages` alerts every `per`. Alerts past the limit are
+    /// counted and summarized in one message at the end of the window, e.g.
+    /// "Suppressed 12 alerts for 'Disk full' over the rate limit". Ignored in
+    /// digest mode; `build` needs a Tokio runtime to start the timer.
+    pub fn with_rate_limit(mut self, max_messages: u32, per: Duration) -> Self {
+        self.rate_limit = Some(RateLimit::new(max_messages, per));
+        self
+    }
+
+    /// Sends an alert at most once per `cooldown`, telling alerts apart by level,
+    /// message and logger. Repeats within the cooldown are summarized like those past
+    /// [`Self::with_rate_limit`], e.g. "Suppressed 127 duplicate alerts for 'Database
+    /// connection failed'". At most [`crate::DM_THROTTLE_MAX_FINGERPRINTS`]
+    /// alerts are remembered, forgetting the least recently seen.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = Some(cooldown);
+        self
+    }
+
+    /// Names `relays` in the `nevent` link of alerts instead of the client's relays, for
+    /// senders whose client does not use the relays events are published to.
+    pub fn with_relay_hints(mut self, relays: Vec<String>) -> Self {
+        self.relay_hints = relays;
+        self
+    }
+
+    /// Links alerts to the event with an `https://njump.me/nevent1…` URL, which opens in
+    /// a browser, instead of a `nostr:nevent1…` URI for Nostr clients.
+    pub fn with_njump_links(mut self, njump_links: bool) -> Self {
+        self.njump_links = njump_links;
+        self
+    }
+
+    /// Whether NIP-17 messages go to the relays of the recipient's kind 10050 list, as
+    /// NIP-17 asks, instead of the sender's relays. Off by default: it connects to
+    /// indexer and inbox relays the sender was not configured with.
+    pub fn with_inbox_discovery(mut self, inbox_discovery: bool) -> Self {
+        self.inbox_discovery = inbox_discovery;
+        self
+    }
+
+    /// Relays asked for the recipient's kind 10050 list besides the client's relays,
+    /// replacing [`DEFAULT_INDEXER_RELAYS`].
+    pub fn with_indexer_relays(mut self, relays: Vec<String>) -> Self {
+        self.indexer_relays = relays;
+        self
+    }
+
+    /// How long discovered inbox relays are reused, [`DEFAULT_INBOX_TTL`] by default.
+    pub fn with_inbox_ttl(mut self, ttl: Duration) -> Self {
+        self.inbox_ttl = ttl;
+        self
+    }
+
+    /// Passes an already shared formatter, as the tracing and collector configs hold.
+    pub fn with_shared_formatter(mut self, formatter: MessageFormatter) -> Self {
+        self.formatter = Some(formatter);
         self
     }
 
@@ -216,18 +577,199 @@ impl DirectMessageBuilder {
             )
         })?;
 
+        let format = match (self.formatter, self.template) {
+            (Some(formatter), _) => MessageFormat::Custom(formatter),
+            (None, Some(template)) => MessageFormat::Template(MessageTemplate::parse(&template)?),
+            (None, None) => MessageFormat::Default,
+        };
+
+        let relay_hints = parse_relays(&self.relay_hints, "relay hint")?;
+        let indexer_relays = parse_relays(&self.indexer_relays, "indexer relay")?;
+
         let config = DirectMessageConfig {
             recipient_pubkey,
             min_level: self.min_level,
-            use_nip17: self.use_nip17,
+            encryption: self.encryption,
+            relay_hints,
+            njump_links: self.njump_links,
+            inbox_discovery: self.inbox_discovery,
         };
 
-        Ok(DirectMessageSender::new(client, keys, config))
+        let mut sender = DirectMessageSender::new(client, keys, config);
+        sender.format = format;
+        sender.inbox = Arc::new(InboxCache::new(indexer_relays, self.inbox_ttl));
+
+        if let Some((interval, max_items)) = self.digest {
+            sender.digest = Some(Digest::spawn(sender.clone(), interval, max_items)?);
+        } else if self.rate_limit.is_some() || self.cooldown.is_some() {
+            sender.throttle = Some(Throttle::spawn(
+                sender.clone(),
+                self.rate_limit,
+                self.cooldown,
+            )?);
+        }
+        Ok(sender)
     }
 }
 
+fn parse_relays(relays: &[String], what: &str) -> Result<Vec<RelayUrl>> {
+    relays
+        .iter()
+        .map(|relay| {
+            RelayUrl::parse(relay)
+                .map_err(|e| SentryStrError::Config(format!("Invalid {} '{}': {}", what, relay, e)))
+        })
+        .collect()
+}
+
 impl Default for DirectMessageBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inbox_discovery_is_opt_in() {
+        let keys = Keys::generate();
+        let sender = DirectMessageBuilder::new()
+            .with_client(Client::new(keys.clone()))
+            .with_keys(keys)
+            .with_recipient(Keys::generate().public_key())
+            .build()
+            .unwrap();
+        assert!(!sender.config.inbox_discovery);
+    }
+
+    fn message_event(nostr_event_id: EventId) -> MessageEvent {
+        MessageEvent {
+            event: Event::new().with_message("payment failed"),
+            author: Keys::generate().public_key(),
+            nostr_event_id,
+            received_at: Utc::now(),
+        }
+    }
+
+    fn relays(count: usize) -> Vec<String> {
+        (1..=count)
+            .map(|n| format!("wss://relay{}.example.com", n))
+            .collect()
+    }
+
+    #[test]
+    fn nevents_name_the_event_its_author_and_the_first_relays() {
+        let event = message_event(EventId::from_byte_array([7; 32]));
+        let hints: Vec<RelayUrl> = relays(5)
+            .iter()
+            .map(|url| RelayUrl::parse(url).unwrap())
+            .collect();
+
+        let decoded = Nip19Event::from_bech32(&nevent(&event, &hints).unwrap()).unwrap();
+
+        assert_eq!(decoded.event_id, event.nostr_event_id);
+        assert_eq!(decoded.author, Some(event.author));
+        assert_eq!(decoded.relays, hints[..MAX_RELAY_HINTS]);
+        assert_eq!(nevent(&message_event(EventId::all_zeros()), &hints), None);
+    }
+
+    #[tokio::test]
+    async fn alert_links_use_the_hints_or_the_relays_of_the_client() {
+        let keys = Keys::generate();
+        let client = Client::new(keys.clone());
+        for relay in relays(2) {
+            client.add_relay(relay).await.unwrap();
+        }
+        let builder = || {
+            DirectMessageBuilder::new()
+                .with_client(client.clone())
+                .with_keys(keys.clone())
+                .with_recipient(Keys::generate().public_key())
+        };
+        let event = message_event(EventId::from_byte_array([7; 32]));
+        let linked_relays = |link: &str, prefix: &str| -> Vec<String> {
+            let nevent = link.strip_prefix(prefix).unwrap();
+            let mut relays: Vec<String> = Nip19Event::from_bech32(nevent)
+                .unwrap()
+                .relays
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            relays.sort();
+            relays
+        };
+
+        let link = builder().build().unwrap().event_link(&event).await.unwrap();
+        assert_eq!(linked_relays(&link, "nostr:"), relays(2));
+
+        let hinted = builder()
+            .with_relay_hints(vec!["wss://hint.example.com".to_string()])
+            .with_njump_links(true)
+            .build()
+            .unwrap();
+        let link = hinted.event_link(&event).await.unwrap();
+        assert_eq!(linked_relays(&link, NJUMP_URL), ["wss://hint.example.com"]);
+
+        let placeholder = message_event(EventId::all_zeros());
+        assert_eq!(hinted.event_link(&placeholder).await, None);
+    }
+
+    #[tokio::test]
+    async fn kind4_messages_decrypt_for_the_recipient() {
+        let relay = nostr_relay_builder::MockRelay::run().await.unwrap();
+        let recipient = Keys::generate();
+        type Decrypt = fn(&SecretKey, &PublicKey, &str) -> String;
+        let schemes: [(DmEncryption, Decrypt); 2] = [
+            (DmEncryption::Nip04, |secret, sender, content| {
+                nip04::decrypt(secret, sender, content).unwrap()
+            }),
+            (DmEncryption::Nip44, |secret, sender, content| {
+                nip44::decrypt(secret, sender, content).unwrap()
+            }),
+        ];
+
+        for (encryption, decrypt) in schemes {
+            let keys = Keys::generate();
+            let client = Client::new(keys.clone());
+            client.add_relay(relay.url()).await.unwrap();
+            client.connect().await;
+            let sender = DirectMessageBuilder::new()
+                .with_client(client.clone())
+                .with_keys(keys.clone())
+                .with_recipient(recipient.public_key())
+                .with_encryption(encryption)
+                .build()
+                .unwrap();
+
+            let delivery = sender
+                .send_custom_message_with_output("disk almost full")
+                .await
+                .unwrap();
+
+            let filter = Filter::new()
+                .author(keys.public_key())
+                .kind(Kind::EncryptedDirectMessage);
+            let stored = client
+                .fetch_events(filter, Duration::from_secs(5))
+                .await
+                .unwrap()
+                .to_vec();
+            assert_eq!(stored.len(), 1, "{}", encryption);
+            let message = &stored[0];
+            assert_eq!(message.id, delivery.output.val);
+            assert_eq!(
+                message.tags.public_keys().collect::<Vec<_>>(),
+                [&recipient.public_key()]
+            );
+            assert_ne!(message.content, "disk almost full");
+            assert_eq!(
+                decrypt(recipient.secret_key(), &keys.public_key(), &message.content),
+                "disk almost full",
+                "{}",
+                encryption
+            );
+        }
+    }
+}
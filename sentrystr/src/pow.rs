@@ -0,0 +1,173 @@
+use crate::rt::Instant;
+use crate::{Level, Result};
+use nostr::nips::nip13;
+use nostr::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Nonces tried between checks of the time budget.
+const NONCES_PER_CHECK: u128 = 4096;
+
+/// What happens when mining does not reach the difficulty within the time budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowPolicy {
+    /// Publish the event without proof of work; relays requiring it will reject it.
+    #[default]
+    SendUnmined,
+    /// Fail the capture with [`crate::SentryStrError::PowTimeout`].
+    Fail,
+}
+
+/// NIP-13 proof of work for published events, see [`crate::Config::with_pow`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PowConfig {
+    /// Leading zero bits required of event ids; `0` disables mining.
+    pub difficulty: u8,
+    /// Per-level difficulties, taking precedence over `difficulty`.
+    #[serde(default)]
+    pub level_difficulty: HashMap<Level, u8>,
+    /// Longest time spent mining one event.
+    pub time_budget: Duration,
+    pub policy: PowPolicy,
+}
+
+impl Default for PowConfig {
+    fn default() -> Self {
+        Self {
+            difficulty: 0,
+            level_difficulty: HashMap::new(),
+            time_budget: Duration::from_secs(5),
+            policy: PowPolicy::default(),
+        }
+    }
+}
+
+impl PowConfig {
+    /// The difficulty to mine events of `level` to, if any.
+    pub fn difficulty_for(&self, level: &Level) -> Option<u8> {
+        let difficulty = self
+            .level_difficulty
+            .get(level)
+            .copied()
+            .unwrap_or(self.difficulty);
+        (difficulty > 0).then_some(difficulty)
+    }
+}
+
+/// Leading zero bits of `id`, the difficulty it achieves.
+pub fn achieved_difficulty(id: &EventId) -> u8 {
+    nip13::get_leading_zero_bits(id.as_bytes())
+}
+
+/// Re-signs `event` with a `nonce` tag giving its id `difficulty` leading zero bits. Runs
+/// on the blocking pool, or in place on wasm32, which has none; returns `None` when
+/// `budget` runs out first.
+pub(crate) async fn mine(
+    keys: &Keys,
+    event: nostr::Event,
+    difficulty: u8,
+    budget: Duration,
+) -> Result<Option<nostr::Event>> {
+    if achieved_difficulty(&event.id) >= difficulty {
+        return Ok(Some(event));
+    }
+
+    let keys = keys.clone();
+    let search = move || -> Result<Option<nostr::Event>> {
+        let deadline = Instant::now() + budget;
+        let mut tags = event.tags.clone();
+
+        for nonce in 0u128.. {
+            if nonce % NONCES_PER_CHECK == 0 && Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            tags.push(Tag::pow(nonce, difficulty));
+            let id = EventId::new(
+                &event.pubkey,
+                &event.created_at,
+                &event.kind,
+                &tags,
+                &event.content,
+            );
+            if id.check_pow(difficulty) {
+                let mined = EventBuilder::new(event.kind, event.content)
+                    .tags(tags)
+                    .custom_created_at(event.created_at)
+                    .sign_with_keys(&keys)?;
+                return Ok(Some(mined));
+            }
+            tags.pop();
+        }
+        Ok(None)
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    return search();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::task::spawn_blocking(search).await.map_err(|e| {
+        crate::SentryStrError::Publishing(format!("Proof of work task failed: {}", e))
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unmined(keys: &Keys) -> nostr::Event {
+        EventBuilder::text_note("payment failed")
+            .sign_with_keys(keys)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn mined_events_reach_the_difficulty_and_stay_valid() {
+        let keys = Keys::generate();
+        let event = unmined(&keys);
+
+        let mined = mine(&keys, event.clone(), 8, Duration::from_secs(30))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(achieved_difficulty(&mined.id) >= 8);
+        assert!(mined.verify().is_ok());
+        assert_eq!(
+            (mined.created_at, mined.content.as_str()),
+            (event.created_at, event.content.as_str())
+        );
+        let nonce = mined
+            .tags
+            .iter()
+            .find(|tag| tag.kind() == TagKind::Nonce)
+            .unwrap();
+        assert_eq!(nonce.as_slice()[2], "8");
+    }
+
+    #[tokio::test]
+    async fn mining_gives_up_when_the_budget_runs_out() {
+        let keys = Keys::generate();
+
+        let mined = mine(&keys, unmined(&keys), 64, Duration::ZERO)
+            .await
+            .unwrap();
+
+        assert!(mined.is_none());
+    }
+
+    #[test]
+    fn level_difficulties_override_the_default() {
+        let config = PowConfig {
+            difficulty: 8,
+            level_difficulty: HashMap::from([(Level::Fatal, 16), (Level::Debug, 0)]),
+            ..PowConfig::default()
+        };
+
+        assert_eq!(config.difficulty_for(&Level::Error), Some(8));
+        assert_eq!(config.difficulty_for(&Level::Fatal), Some(16));
+        assert_eq!(config.difficulty_for(&Level::Debug), None);
+        assert_eq!(PowConfig::default().difficulty_for(&Level::Error), None);
+    }
+}
@@ -0,0 +1,508 @@
+use crate::client::Publisher;
+use crate::journal::{Journal, MAX_FAILURES};
+use crate::{DirectMessageSender, Event, Result, SentryStrError};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::{AbortHandle, JoinSet};
+
+/// What `capture_event` does when the queue already holds `max_pending` events.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OverflowPolicy {
+    /// Wait until the background task frees a slot.
+    #[default]
+    Block,
+    /// Discard the oldest queued event to make room; counted in [`QueueStats::dropped`].
+    DropOldest,
+}
+
+/// Settings of the queued publishing mode, see [`crate::Config::with_queue`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueConfig {
+    pub max_pending: usize,
+    /// How often the background task drains the queue when nothing else wakes it. Must
+    /// not be zero.
+    pub flush_interval: Duration,
+    /// Events published concurrently. With more than one, events of the same batch may
+    /// reach relays out of order.
+    pub concurrency: usize,
+    pub overflow: OverflowPolicy,
+    /// Keep queued events in a file as well, see [`crate::Config::with_persistent_queue`].
+    #[serde(default)]
+    pub persistence: Option<QueuePersistence>,
+}
+
+/// File backing a queue, holding at most `max_bytes`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuePersistence {
+    pub path: PathBuf,
+    pub max_bytes: u64,
+}
+
+impl QueueConfig {
+    pub fn new(max_pending: usize, flush_interval: Duration) -> Self {
+        Self {
+            max_pending: max_pending.max(1),
+            flush_interval,
+            concurrency: 1,
+            overflow: OverflowPolicy::default(),
+            persistence: None,
+        }
+    }
+}
+
+/// Counters of a client's queue.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Events waiting or being published.
+    pub pending: usize,
+    pub published: u64,
+    pub failed: u64,
+    /// Events discarded by [`OverflowPolicy::DropOldest`].
+    pub dropped: u64,
+    /// Entries of the queue file that could not be read back on startup.
+    pub corrupt_entries: u64,
+    /// Events kept only in memory because the queue file was full or failed to write.
+    pub unpersisted: u64,
+}
+
+pub(crate) struct QueuedEvent {
+    pub(crate) nostr_event: nostr::Event,
+    /// Companion events of the attachments, published first.
+    pub(crate) attachments: Vec<nostr::Event>,
+    pub(crate) event: Event,
+    pub(crate) dm_sender: Option<DirectMessageSender>,
+    /// Read back from the queue file, and queued with a DM sender: the client's current
+    /// one sends its DM.
+    pub(crate) replay_dm: bool,
+    /// Id in the queue file, when persisted.
+    pub(crate) journal_id: Option<u64>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<QueuedEvent>,
+    in_flight: usize,
+}
+
+/// Signed events waiting to be published by a background task, so that
+/// `capture_event` does not wait for relays.
+pub(crate) struct EventQueue {
+    config: QueueConfig,
+    journal: Option<Arc<Journal>>,
+    state: Mutex<QueueState>,
+    /// Wakes the background task before its next interval tick.
+    wake: Notify,
+    /// Signalled when the background task takes events, freeing slots.
+    space: Notify,
+    /// Signalled when nothing is pending or in flight.
+    drained: Notify,
+    published: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    corrupt_entries: u64,
+    unpersisted: AtomicU64,
+}
+
+impl EventQueue {
+    /// A queue starting with the events left in its file, if persistent. Those go out
+    /// before new ones, once `flush_interval` passed so the client has its DM sender by
+    /// then. Fails on a zero `flush_interval`, which the background task cannot tick at.
+    pub(crate) fn new(config: QueueConfig) -> Result<Self> {
+        if config.flush_interval.is_zero() {
+            return Err(SentryStrError::Config(
+                "The queue flush interval must be more than 0".to_string(),
+            ));
+        }
+        let (journal, backlog) = match config.persistence {
+            Some(ref persistence) => {
+                let (journal, backlog) = Journal::open(&persistence.path, persistence.max_bytes)?;
+                (Some(Arc::new(journal)), Some(backlog))
+            }
+            None => (None, None),
+        };
+        let (pending, corrupt_entries) = backlog.map_or((VecDeque::new(), 0), |backlog| {
+            (backlog.events.into(), backlog.corrupt)
+        });
+
+        Ok(Self {
+            config,
+            journal,
+            state: Mutex::new(QueueState {
+                pending,
+                in_flight: 0,
+            }),
+            wake: Notify::new(),
+            space: Notify::new(),
+            drained: Notify::new(),
+            published: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            corrupt_entries,
+            unpersisted: AtomicU64::new(0),
+        })
+    }
+
+    /// Starts the background task publishing through `publisher`. Events read back
+    /// from the queue file are sent as DMs by the sender in `dm_sender` when published.
+    pub(crate) fn spawn(
+        self: &Arc<Self>,
+        publisher: Publisher,
+        dm_sender: Arc<RwLock<Option<DirectMessageSender>>>,
+    ) -> AbortHandle {
+        let queue = Arc::clone(self);
+
+        let task = tokio::spawn(async move {
+            let mut interval = crate::rt::interval(queue.config.flush_interval);
+            // The first tick is immediate; skipping it leaves the client time to get
+            // its DM sender before the backlog goes out.
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = queue.wake.notified() => {}
+                }
+                queue.drain(&publisher, &dm_sender).await;
+            }
+        });
+        task.abort_handle()
+    }
+
+    /// Queues `entry`, applying the overflow policy when the queue is full. A persistent
+    /// queue writes it to its file first, on the blocking pool.
+    pub(crate) async fn push(&self, mut entry: QueuedEvent) {
+        if let Some(ref journal) = self.journal {
+            let record = Journal::add_record(&entry);
+            let journal = Arc::clone(journal);
+            entry.journal_id = tokio::task::spawn_blocking(move || journal.append(record))
+                .await
+                .unwrap_or(None);
+            if entry.journal_id.is_none() {
+                self.unpersisted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let mut entry = Some(entry);
+
+        loop {
+            let mut space = pin!(self.space.notified());
+            space.as_mut().enable();
+            let dropped = {
+                let mut state = self.state.lock().unwrap();
+                if state.pending.len() < self.config.max_pending {
+                    state.pending.extend(entry.take());
+                    return;
+                }
+
+                if self.config.overflow == OverflowPolicy::DropOldest {
+                    let oldest = state.pending.pop_front();
+                    state.pending.extend(entry.take());
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    Some(oldest.and_then(|oldest| oldest.journal_id))
+                } else {
+                    None
+                }
+            };
+            if let Some(journal_id) = dropped {
+                self.forget(journal_id).await;
+                return;
+            }
+
+            self.wake.notify_one();
+            space.await;
+        }
+    }
+
+    /// Drains the queue and waits up to `timeout` for it to empty. Returns how many
+    /// events are still pending.
+    pub(crate) async fn flush(&self, timeout: Duration) -> usize {
+        let deadline = crate::rt::Instant::now() + timeout;
+
+        loop {
+            let mut drained = pin!(self.drained.notified());
+            drained.as_mut().enable();
+
+            let remaining = self.remaining();
+            if remaining == 0 {
+                return 0;
+            }
+
+            self.wake.notify_one();
+            if crate::rt::timeout_at(deadline, drained).await.is_err() {
+                return self.remaining();
+            }
+        }
+    }
+
+    /// Events the queue holds at most, see `QueueConfig::max_pending`.
+    pub(crate) fn max_pending(&self) -> usize {
+        self.config.max_pending
+    }
+
+    pub(crate) fn stats(&self) -> QueueStats {
+        QueueStats {
+            pending: self.remaining(),
+            published: self.published.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            corrupt_entries: self.corrupt_entries,
+            unpersisted: self.unpersisted.load(Ordering::Relaxed),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.pending.len() + state.in_flight
+    }
+
+    /// Publishes batches of up to `concurrency` events, oldest first, until the queue is
+    /// empty.
+    async fn drain(&self, publisher: &Publisher, dm_sender: &RwLock<Option<DirectMessageSender>>) {
+        loop {
+            let mut batch: Vec<QueuedEvent> = {
+                let mut state = self.state.lock().unwrap();
+                let count = state.pending.len().min(self.config.concurrency.max(1));
+                state.in_flight += count;
+                state.pending.drain(..count).collect()
+            };
+            for entry in &mut batch {
+                if entry.replay_dm && entry.dm_sender.is_none() {
+                    entry.dm_sender = dm_sender.read().unwrap().clone();
+                }
+            }
+            if batch.is_empty() {
+                self.drained.notify_waiters();
+                return;
+            }
+            self.space.notify_waiters();
+
+            let count = batch.len();
+            if self.config.concurrency <= 1 {
+                for entry in batch {
+                    let journal_id = entry.journal_id;
+                    let published = publish_entry(publisher.clone(), entry).await;
+                    self.record(published, journal_id).await;
+                }
+            } else {
+                let mut sends = JoinSet::new();
+                for entry in batch {
+                    let journal_id = entry.journal_id;
+                    let publisher = publisher.clone();
+                    sends.spawn(async move { (publish_entry(publisher, entry).await, journal_id) });
+                }
+                while let Some(result) = sends.join_next().await {
+                    let (published, journal_id) = result.unwrap_or((false, None));
+                    self.record(published, journal_id).await;
+                }
+            }
+
+            self.state.lock().unwrap().in_flight -= count;
+        }
+    }
+
+    /// Counts a publish. Published events leave the queue file; failed ones stay in it
+    /// and are retried by the next client opening it, until they failed
+    /// [`MAX_FAILURES`] times.
+    async fn record(&self, published: bool, journal_id: Option<u64>) {
+        if published {
+            self.published.fetch_add(1, Ordering::Relaxed);
+            self.forget(journal_id).await;
+            return;
+        }
+
+        self.failed.fetch_add(1, Ordering::Relaxed);
+        if let (Some(journal), Some(id)) = (&self.journal, journal_id) {
+            let journal = Arc::clone(journal);
+            if let Ok(true) = tokio::task::spawn_blocking(move || journal.fail(id)).await {
+                eprintln!(
+                    "Removed a queued event from the queue file after {} failed publishes",
+                    MAX_FAILURES
+                );
+            }
+        }
+    }
+
+    /// Removes the event with `journal_id` from the queue file, on the blocking pool.
+    async fn forget(&self, journal_id: Option<u64>) {
+        if let (Some(journal), Some(id)) = (&self.journal, journal_id) {
+            let journal = Arc::clone(journal);
+            let _ = tokio::task::spawn_blocking(move || journal.remove(id)).await;
+        }
+    }
+}
+
+async fn publish_entry(publisher: Publisher, entry: QueuedEvent) -> bool {
+    publisher.throttle(1 + entry.attachments.len()).await;
+    let result = publisher
+        .publish(
+            entry.dm_sender.as_ref(),
+            &entry.attachments,
+            &entry.nostr_event,
+            entry.event,
+        )
+        .await;
+
+    match result {
+        Ok(_) => true,
+        Err(e) => {
+            eprintln!("Failed to publish queued event: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, NostrSentryClient};
+    use nostr::{ClientMessage, JsonUtil, Keys, RelayMessage};
+
+    fn config() -> Config {
+        Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn zero_flush_interval_is_rejected() {
+        let config = config().with_queue(10, Duration::ZERO);
+        assert!(matches!(
+            NostrSentryClient::new(config).await,
+            Err(SentryStrError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn persistent_queue_requires_a_queue() {
+        let path = std::env::temp_dir().join("sentrystr-unused-queue");
+        assert!(matches!(
+            config().with_persistent_queue(&path, 1024),
+            Err(SentryStrError::Config(_))
+        ));
+        assert!(
+            config()
+                .with_queue(10, Duration::from_secs(1))
+                .with_persistent_queue(&path, 1024)
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn queued_client_starts() {
+        let config = config().with_queue(10, Duration::from_millis(50));
+        let client = NostrSentryClient::new(config).await.unwrap();
+        assert_eq!(client.queue_stats().unwrap_or_default().pending, 0);
+    }
+
+    /// A relay recording the messages of the events published to it in the order they
+    /// arrive, accepting them or, when `answer` is false, never answering.
+    async fn recording_relay(answer: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&received);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(async move {
+                    let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+                        return;
+                    };
+                    while let Some(Ok(message)) = ws.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let Ok(ClientMessage::Event(event)) = ClientMessage::from_json(&*text)
+                        else {
+                            continue;
+                        };
+                        let captured: Event = serde_json::from_str(&event.content).unwrap();
+                        recorded.lock().unwrap().extend(captured.message);
+                        if !answer {
+                            continue;
+                        }
+                        let reply = RelayMessage::ok(event.id, true, "");
+                        if ws.send(Message::text(reply.as_json())).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        (url, received)
+    }
+
+    /// A client queueing up to `max_pending` events for `relay`, which it only drains
+    /// when full or flushed.
+    async fn queued_client(
+        relay: &str,
+        max_pending: usize,
+        overflow: OverflowPolicy,
+    ) -> NostrSentryClient {
+        let config = Config::from_keys(&Keys::generate(), [relay])
+            .unwrap()
+            .with_queue(max_pending, Duration::from_secs(3600))
+            .with_queue_overflow(overflow);
+        let client = NostrSentryClient::new(config).await.unwrap();
+        client
+            .wait_for_connection(1, Duration::from_secs(5))
+            .await
+            .unwrap();
+        client
+    }
+
+    async fn capture_numbered(client: &NostrSentryClient, count: usize) {
+        for n in 0..count {
+            client
+                .capture_message(crate::Level::Error, n.to_string())
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn full_queues_block_and_publish_in_capture_order() {
+        let (relay, received) = recording_relay(true).await;
+        let client = queued_client(&relay, 2, OverflowPolicy::Block).await;
+
+        capture_numbered(&client, 6).await;
+        assert_eq!(client.flush(Duration::from_secs(5)).await, 0);
+
+        assert_eq!(*received.lock().unwrap(), ["0", "1", "2", "3", "4", "5"]);
+        let stats = client.queue_stats().unwrap();
+        assert_eq!((stats.pending, stats.published, stats.dropped), (0, 6, 0));
+    }
+
+    #[tokio::test]
+    async fn overflowing_queues_drop_the_oldest_events() {
+        let (relay, received) = recording_relay(true).await;
+        let client = queued_client(&relay, 2, OverflowPolicy::DropOldest).await;
+
+        capture_numbered(&client, 5).await;
+        let stats = client.queue_stats().unwrap();
+        assert_eq!((stats.pending, stats.dropped), (2, 3));
+        assert!(received.lock().unwrap().is_empty());
+
+        assert_eq!(client.flush(Duration::from_secs(5)).await, 0);
+        assert_eq!(*received.lock().unwrap(), ["3", "4"]);
+        assert_eq!(client.queue_stats().unwrap().published, 2);
+    }
+
+    #[tokio::test]
+    async fn flushing_reports_the_events_still_pending_at_the_timeout() {
+        let (relay, received) = recording_relay(false).await;
+        let client = queued_client(&relay, 10, OverflowPolicy::Block).await;
+
+        capture_numbered(&client, 3).await;
+
+        assert_eq!(client.flush(Duration::from_millis(200)).await, 3);
+        assert_eq!(client.queue_stats().unwrap().pending, 3);
+        assert!(received.lock().unwrap().len() <= 1);
+    }
+}
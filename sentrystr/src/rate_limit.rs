@@ -0,0 +1,198 @@
+use crate::rt::Instant;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// At most `max_events` Nostr events every `per`, companion events of attachments
+/// included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub max_events: u32,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn new(max_events: u32, per: Duration) -> Self {
+        Self { max_events, per }
+    }
+
+    fn events_per_second(&self) -> f64 {
+        self.max_events as f64 / self.per.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    /// Whichever of `self` and `other` allows fewer events per second.
+    pub(crate) fn stricter(self, other: RateLimit) -> RateLimit {
+        if other.events_per_second() < self.events_per_second() {
+            other
+        } else {
+            self
+        }
+    }
+
+    /// The limit advertised in a NIP-11 relay information document. NIP-11 has no rate
+    /// field, so this reads the `limitation` fields relays use in practice:
+    /// `max_events_per_second`, `max_events_per_minute` or `max_events_per_hour`
+    /// (also without the `max_` prefix). The strictest one wins; limits of 0 events are
+    /// ignored.
+    pub fn from_nip11(document: &Value) -> Option<RateLimit> {
+        let limitation = document.get("limitation")?;
+        [
+            ("events_per_second", Duration::from_secs(1)),
+            ("events_per_minute", Duration::from_secs(60)),
+            ("events_per_hour", Duration::from_secs(3600)),
+        ]
+        .into_iter()
+        .filter_map(|(field, per)| {
+            let max_events = limitation
+                .get(format!("max_{}", field))
+                .or_else(|| limitation.get(field))?
+                .as_u64()
+                .filter(|&max_events| max_events > 0)?;
+            Some(RateLimit::new(u32::try_from(max_events).ok()?, per))
+        })
+        .reduce(RateLimit::stricter)
+    }
+}
+
+/// Token bucket enforcing a [`RateLimit`]: starts full, with room for a burst of
+/// `max_events`, and refills continuously.
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            state: Mutex::new((limit.max_events as f64, Instant::now())),
+        }
+    }
+
+    pub(crate) fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Takes `tokens` if available, otherwise returns how long until they will be.
+    fn take(&self, tokens: usize) -> Result<(), Duration> {
+        let capacity = self.limit.max_events as f64;
+        let rate = self.limit.events_per_second();
+        // Events needing more tokens than fit in the bucket go out once it is full.
+        let needed = (tokens as f64).min(capacity);
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let available = (state.0 + now.duration_since(state.1).as_secs_f64() * rate).min(capacity);
+        *state = (available, now);
+
+        if available >= needed {
+            state.0 -= needed;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(Duration::from_secs_f64((needed - available) / rate))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    pub(crate) fn try_acquire(&self, tokens: usize) -> bool {
+        self.take(tokens).is_ok()
+    }
+
+    /// Waits until `tokens` are available and takes them. Returns whether it had to
+    /// wait.
+    pub(crate) async fn acquire(&self, tokens: usize) -> bool {
+        let mut waited = false;
+        while let Err(wait) = self.take(tokens) {
+            waited = true;
+            crate::rt::sleep(wait).await;
+        }
+        waited
+    }
+}
+
+/// The limits of `relays` from their NIP-11 documents, combined into the strictest one
+/// since every event goes to every relay. Relays without a document or a limit are
+/// skipped.
+#[cfg(feature = "nip11")]
+pub(crate) async fn fetch_nip11_limit(relays: &[String]) -> Option<RateLimit> {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .ok()?;
+
+    let mut combined: Option<RateLimit> = None;
+    for relay in relays {
+        let url = relay
+            .replacen("wss://", "https://", 1)
+            .replacen("ws://", "http://", 1);
+        let document = match http
+            .get(&url)
+            .header("Accept", "application/nostr+json")
+            .send()
+            .await
+        {
+            Ok(response) => response.json::<Value>().await,
+            Err(e) => Err(e),
+        };
+
+        match document {
+            Ok(document) => {
+                if let Some(limit) = RateLimit::from_nip11(&document) {
+                    combined = Some(combined.map_or(limit, |known| known.stricter(limit)));
+                }
+            }
+            Err(e) => eprintln!("Failed to fetch NIP-11 document of {}: {}", relay, e),
+        }
+    }
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, NostrSentryClient};
+    use nostr::Keys;
+
+    fn config() -> Config {
+        Config::from_keys(&Keys::generate(), ["wss://relay.example.com"]).unwrap()
+    }
+
+    #[test]
+    fn zero_events_removes_the_limit() {
+        let config = config().with_rate_limit(10, Duration::from_secs(1));
+        assert!(config.rate_limit.is_some());
+        assert_eq!(
+            config.with_rate_limit(0, Duration::from_secs(1)).rate_limit,
+            None
+        );
+    }
+
+    #[test]
+    fn nip11_limits_of_zero_are_ignored() {
+        let document = serde_json::json!({
+            "limitation": { "max_events_per_second": 0, "events_per_minute": 120 }
+        });
+        assert_eq!(
+            RateLimit::from_nip11(&document),
+            Some(RateLimit::new(120, Duration::from_secs(60)))
+        );
+    }
+
+    #[tokio::test]
+    async fn client_ignores_a_zero_limit() {
+        let mut config = config().with_queue(100, Duration::from_secs(1));
+        config.rate_limit = Some(RateLimit::new(0, Duration::from_secs(1)));
+        let client = NostrSentryClient::new(config).await.unwrap();
+        assert_eq!(client.rate_limit(), None);
+    }
+
+    #[tokio::test]
+    async fn bucket_waits_for_refill() {
+        let bucket = TokenBucket::new(RateLimit::new(2, Duration::from_millis(20)));
+        assert!(bucket.try_acquire(2));
+        assert!(!bucket.try_acquire(1));
+        assert!(bucket.acquire(1).await);
+    }
+}
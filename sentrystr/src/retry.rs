@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+/// How failed publishes are retried, see [`crate::Config::with_retry`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each one after.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Waits a random time between half and all of each delay, so that clients failing
+    /// together do not retry together.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry`, starting at 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let exponent = retry.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(1 << exponent)
+            .min(self.max_delay);
+
+        if !self.jitter {
+            return delay;
+        }
+
+        let random = RandomState::new().build_hasher().finish();
+        let fraction = 0.5 + (random % 1_000) as f64 / 2_000.0;
+        delay.mul_f64(fraction)
+    }
+}
+
+/// Whether a relay's rejection message (`OK false` or a client-side send error) is
+/// worth retrying. Rejections with a NIP-01 prefix are retried only when rate limited
+/// or a relay error; `invalid:`, `blocked:`, `restricted:`, `auth-required:`, `pow:`
+/// and `duplicate:` are permanent. Messages without a prefix are connection problems
+/// and timeouts, which are retried.
+pub fn is_retryable(message: &str) -> bool {
+    match message.split_once(':') {
+        Some((prefix, _)) => match prefix.trim() {
+            "rate-limited" | "error" => true,
+            "invalid" | "blocked" | "restricted" | "auth-required" | "pow" | "duplicate" => false,
+            _ => true,
+        },
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter,
+        }
+    }
+
+    #[test]
+    fn delays_double_per_retry_up_to_the_cap() {
+        let delays: Vec<u128> = (1..=6)
+            .map(|retry| policy(false).delay(retry).as_millis())
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy(false).delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_keeps_delays_between_half_and_the_full_delay() {
+        for retry in 1..=6 {
+            let full = policy(false).delay(retry);
+            for _ in 0..50 {
+                let delay = policy(true).delay(retry);
+                assert!(delay >= full / 2 && delay <= full, "{delay:?} for {full:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn only_transient_rejections_are_retryable() {
+        for message in [
+            "rate-limited: slow down",
+            "error: database busy",
+            "relay not connected",
+            "timeout",
+        ] {
+            assert!(is_retryable(message), "{message}");
+        }
+        for message in [
+            "invalid: bad signature",
+            "blocked: not on the allow list",
+            "restricted: paid relay",
+            "auth-required: sign in",
+            "pow: difficulty 20 required",
+            "duplicate: already have it",
+        ] {
+            assert!(!is_retryable(message), "{message}");
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//! What the client needs from its async runtime: Tokio natively, and the browser's
+//! event loop on wasm32, where Tokio has no timers and `std::time` clocks panic.
+
+use std::future::Future;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::SystemTime;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::time::{Instant, interval, sleep, timeout_at};
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasmtimer::std::{Instant, SystemTime};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasmtimer::tokio::{interval, sleep, timeout_at};
+
+/// Runs `future` in the background. Returns false, dropping the future, when called
+/// outside a Tokio runtime.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn(future: impl Future<Output = ()> + Send + 'static) -> bool {
+    match tokio::runtime::Handle::try_current() {
+        Ok(runtime) => {
+            runtime.spawn(future);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Runs `future` in the background on the browser's event loop.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn(future: impl Future<Output = ()> + 'static) -> bool {
+    wasm_bindgen_futures::spawn_local(future);
+    true
+}
@@ -0,0 +1,41 @@
+use crate::rt::SystemTime;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Draws a number in `[0, 1)` deciding whether an event is kept, see
+/// [`crate::Config::with_sampler`].
+pub type Sampler = fn() -> f64;
+
+/// Whether an event sampled at `rate` is kept, given a draw in `[0, 1)`. Rates of 1 or
+/// more, and NaN, keep everything.
+pub(crate) fn keep(rate: f64, draw: f64) -> bool {
+    rate.is_nan() || rate >= 1.0 || draw < rate
+}
+
+/// SplitMix64, seeded from the clock: cheap, lock-free and good enough to sample with.
+pub(crate) struct SampleRng(AtomicU64);
+
+impl SampleRng {
+    pub(crate) fn from_clock() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Self::seeded(seed)
+    }
+
+    /// The same sequence on every run, e.g. for a test's `Config::with_sampler`.
+    pub(crate) const fn seeded(seed: u64) -> Self {
+        Self(AtomicU64::new(seed))
+    }
+
+    /// The next number in `[0, 1)`.
+    pub(crate) fn next(&self) -> f64 {
+        let mut z = self
+            .0
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{Event, Request, User};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static TASK_SCOPE: Arc<Scope>;
+}
+
+/// Tags, extras, a user and a request merged into every event captured while the scope
+/// applies. Values set on the event itself take precedence.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    tags: HashMap<String, String>,
+    extra: HashMap<String, serde_json::Value>,
+    user: Option<User>,
+    request: Option<Request>,
+}
+
+impl Scope {
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.tags.insert(key.into(), value.into());
+    }
+
+    pub fn remove_tag(&mut self, key: &str) {
+        self.tags.remove(key);
+    }
+
+    pub fn set_extra(&mut self, key: impl Into<String>, value: serde_json::Value) {
+        self.extra.insert(key.into(), value);
+    }
+
+    pub fn remove_extra(&mut self, key: &str) {
+        self.extra.remove(key);
+    }
+
+    pub fn set_user(&mut self, user: Option<User>) {
+        self.user = user;
+    }
+
+    /// The HTTP request being handled, e.g. set by a middleware for the events of its
+    /// handler.
+    pub fn set_request(&mut self, request: Option<Request>) {
+        self.request = request;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn tags(&self) -> &HashMap<String, String> {
+        &self.tags
+    }
+
+    pub fn extra(&self) -> &HashMap<String, serde_json::Value> {
+        &self.extra
+    }
+
+    pub fn user(&self) -> Option<&User> {
+        self.user.as_ref()
+    }
+
+    pub fn request(&self) -> Option<&Request> {
+        self.request.as_ref()
+    }
+
+    /// The scope of the current task, set by [`with_scope`]; empty outside of one.
+    pub fn current() -> Arc<Scope> {
+        TASK_SCOPE.try_with(Arc::clone).unwrap_or_default()
+    }
+
+    /// Copies the scope's tags, extras, user and request into `event` where it has none of
+    /// its own.
+    pub fn apply_to(&self, event: &mut Event) {
+        for (key, value) in &self.tags {
+            event
+                .tags
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        for (key, value) in &self.extra {
+            event
+                .extra
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+        if event.user.is_none() {
+            event.user = self.user.clone();
+        }
+        if event.request.is_none() {
+            event.request = self.request.clone();
+        }
+    }
+}
+
+/// Runs `future` with a task-local scope layered over the current one: it starts as a
+/// copy of [`Scope::current`] and is then changed by `configure`. Scopes nest, and
+/// concurrent tasks never see each other's. Tasks spawned inside `future` do not
+/// inherit the scope.
+pub async fn with_scope<F: Future>(configure: impl FnOnce(&mut Scope), future: F) -> F::Output {
+    let mut scope = Scope::clone(&Scope::current());
+    configure(&mut scope);
+    TASK_SCOPE.scope(Arc::new(scope), future).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, NostrSentryClient};
+    use nostr::Keys;
+    use std::sync::Mutex;
+
+    fn user(id: &str) -> User {
+        User {
+            id: Some(id.to_string()),
+            username: None,
+            email: None,
+            ip_address: None,
+        }
+    }
+
+    #[test]
+    fn values_of_the_event_take_precedence() {
+        let mut scope = Scope::default();
+        scope.set_tag("region", "eu");
+        scope.set_tag("service", "billing");
+        scope.set_extra("cart_size", 3.into());
+        scope.set_extra("retries", 0.into());
+        scope.set_user(Some(user("scope-user")));
+
+        let mut bare = Event::new();
+        scope.apply_to(&mut bare);
+        assert_eq!(bare.tags, *scope.tags());
+        assert_eq!(bare.extra, *scope.extra());
+        assert_eq!(bare.user.unwrap().id.as_deref(), Some("scope-user"));
+
+        let mut own = Event::new()
+            .with_tag("region", "us")
+            .with_extra("retries", 2.into())
+            .with_user(user("event-user"));
+        scope.apply_to(&mut own);
+        assert_eq!(own.tags["region"], "us");
+        assert_eq!(own.tags["service"], "billing");
+        assert_eq!(own.extra["retries"], 2);
+        assert_eq!(own.extra["cart_size"], 3);
+        assert_eq!(own.user.unwrap().id.as_deref(), Some("event-user"));
+    }
+
+    #[tokio::test]
+    async fn scopes_merge_into_captured_events() {
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let capturing = Arc::clone(&captured);
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_before_send(move |event| {
+                capturing.lock().unwrap().push(event);
+                None
+            });
+        let client = NostrSentryClient::new(config).await.unwrap();
+        client.configure_scope(|scope| {
+            scope.set_tag("service", "billing");
+            scope.set_tag("region", "client");
+            scope.set_user(Some(user("client-user")));
+        });
+
+        with_scope(
+            |scope| {
+                scope.set_tag("region", "task");
+                scope.set_extra("order_id", 42.into());
+            },
+            async {
+                with_scope(
+                    |scope| scope.set_user(Some(user("nested-user"))),
+                    client.capture_event(Event::new().with_message("nested")),
+                )
+                .await
+                .unwrap();
+                client
+                    .capture_event(
+                        Event::new()
+                            .with_message("own values")
+                            .with_tag("region", "event")
+                            .with_extra("order_id", 7.into()),
+                    )
+                    .await
+                    .unwrap();
+            },
+        )
+        .await;
+        client
+            .capture_event(Event::new().with_message("outside"))
+            .await
+            .unwrap();
+
+        let captured = captured.lock().unwrap();
+        let [nested, own, outside] = captured.as_slice() else {
+            panic!("{} events captured", captured.len());
+        };
+        assert_eq!(nested.tags["region"], "task");
+        assert_eq!(nested.tags["service"], "billing");
+        assert_eq!(nested.extra["order_id"], 42);
+        assert_eq!(
+            nested.user.as_ref().unwrap().id.as_deref(),
+            Some("nested-user")
+        );
+
+        assert_eq!(own.tags["region"], "event");
+        assert_eq!(own.extra["order_id"], 7);
+        assert_eq!(
+            own.user.as_ref().unwrap().id.as_deref(),
+            Some("client-user")
+        );
+
+        assert_eq!(outside.tags["region"], "client");
+        assert!(!outside.extra.contains_key("order_id"));
+    }
+}
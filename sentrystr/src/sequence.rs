@@ -0,0 +1,144 @@
+use crate::rt::Instant;
+use crate::{Result, SentryStrError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::sync::watch;
+
+/// Name of the extra holding the sequence number of an event.
+pub const SEQUENCE_EXTRA: &str = "seq";
+/// Nostr tag holding the sequence number of an event. Single-letter, so relays index it.
+pub const SEQUENCE_TAG: &str = "n";
+
+/// Hands out the sequence numbers of a client, starting at 1, optionally saving the
+/// last one to a state file so that a restarted client continues where it stopped.
+pub(crate) struct SequenceCounter {
+    last: AtomicU64,
+    state: Option<StateFile>,
+}
+
+/// The background task saving numbers to the state file.
+struct StateFile {
+    /// The last number handed out.
+    issued: watch::Sender<u64>,
+    /// The last number written to the file.
+    saved: watch::Receiver<u64>,
+}
+
+impl SequenceCounter {
+    /// A counter continuing from the number saved at `path`, if the file exists. With a
+    /// path, a background task keeps the file up to date.
+    pub(crate) fn load(path: Option<PathBuf>) -> Result<Self> {
+        let last = match path {
+            Some(ref path) if path.exists() => read_state(path)?,
+            _ => 0,
+        };
+        let state = path.map(|path| {
+            let (issued, issued_rx) = watch::channel(last);
+            let (saved_tx, saved) = watch::channel(last);
+            crate::rt::spawn(save_state(path, issued_rx, saved_tx));
+            StateFile { issued, saved }
+        });
+        Ok(Self {
+            last: AtomicU64::new(last),
+            state,
+        })
+    }
+
+    /// The next number. It is saved in the background, coalescing numbers handed out
+    /// while a write runs; after a crash the collector then sees the numbers since the
+    /// last write repeat instead of a gap.
+    pub(crate) fn next(&self) -> u64 {
+        let seq = self.last.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(ref state) = self.state {
+            state.issued.send_if_modified(|issued| {
+                let newer = seq > *issued;
+                if newer {
+                    *issued = seq;
+                }
+                newer
+            });
+        }
+        seq
+    }
+
+    /// Waits until `deadline` for the last number handed out to be saved.
+    pub(crate) async fn flush(&self, deadline: Instant) {
+        if let Some(ref state) = self.state {
+            let issued = *state.issued.borrow();
+            let mut saved = state.saved.clone();
+            let _ = crate::rt::timeout_at(deadline, saved.wait_for(|saved| *saved >= issued)).await;
+        }
+    }
+}
+
+/// Writes the numbers of `issued` to `path` until the counter is dropped.
+async fn save_state(path: PathBuf, mut issued: watch::Receiver<u64>, saved: watch::Sender<u64>) {
+    while issued.changed().await.is_ok() {
+        let last = *issued.borrow_and_update();
+        let target = path.clone();
+        match tokio::task::spawn_blocking(move || write_state(&target, last)).await {
+            Ok(Ok(())) => {
+                saved.send_replace(last);
+            }
+            Ok(Err(e)) => eprintln!(
+                "Failed to save sequence number to {}: {}",
+                path.display(),
+                e
+            ),
+            Err(_) => return,
+        }
+    }
+}
+
+fn read_state(path: &Path) -> Result<u64> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        SentryStrError::Config(format!(
+            "Failed to read sequence state {}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    content.trim().parse().map_err(|e| {
+        SentryStrError::Config(format!("Invalid sequence state {}: {}", path.display(), e))
+    })
+}
+
+/// Writes through a temporary file, so a crash never leaves a truncated state.
+fn write_state(path: &Path, last: u64) -> std::io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, last.to_string())?;
+    std::fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn state_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("sentrystr-seq-{}-{}", name, std::process::id()))
+    }
+
+    #[tokio::test]
+    async fn numbers_continue_after_a_restart() {
+        let path = state_path("restart");
+        let _ = std::fs::remove_file(&path);
+
+        let counter = SequenceCounter::load(Some(path.clone())).unwrap();
+        assert_eq!((counter.next(), counter.next(), counter.next()), (1, 2, 3));
+        counter.flush(Instant::now() + Duration::from_secs(5)).await;
+        drop(counter);
+
+        let restarted = SequenceCounter::load(Some(path.clone())).unwrap();
+        assert_eq!(restarted.next(), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn counters_without_a_file_start_over() {
+        let counter = SequenceCounter::load(None).unwrap();
+        assert_eq!(counter.next(), 1);
+        counter.flush(Instant::now()).await;
+        assert_eq!(SequenceCounter::load(None).unwrap().next(), 1);
+    }
+}
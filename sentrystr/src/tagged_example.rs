@@ -14,10 +14,10 @@ async fn main() -> sentrystr::Result<()> {
         "wss://nostr.chaima.info".to_string(),
     ];
 
-    let config = Config::new(
+    let config = Config::try_new(
         sender_keys.secret_key().display_secret().to_string(),
         relays,
-    );
+    )?;
 
     let client = NostrSentryClient::new(config).await?;
 
@@ -0,0 +1,223 @@
+use crate::{MessageEvent, Result, SentryStrError};
+use nostr::ToBech32;
+
+/// Placeholders accepted by [`MessageTemplate::parse`], besides `{tag:<key>}` and
+/// `{extra:<key>}`.
+const PLACEHOLDERS: &[&str] = &["level", "message", "timestamp", "author_npub", "event_id"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Level,
+    Message,
+    Timestamp,
+    AuthorNpub,
+    EventId,
+    Tag(String),
+    Extra(String),
+}
+
+/// Text of direct messages with placeholders filled from the event, such as
+/// `"[{level}] {tag:service}: {message}"`. `{{` and `}}` stand for literal braces.
+/// Placeholders without a value (no message, a missing tag) render empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageTemplate {
+    segments: Vec<Segment>,
+}
+
+impl MessageTemplate {
+    /// Parses `template`, rejecting unknown placeholders and unbalanced braces.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        name.push(c);
+                    }
+                    if !closed {
+                        return Err(SentryStrError::Config(
+                            "Unclosed '{' in message template; write '{{' for a literal brace"
+                                .to_string(),
+                        ));
+                    }
+
+                    let segment = Self::placeholder(&name).ok_or_else(|| {
+                        SentryStrError::Config(format!(
+                            "Unknown placeholder '{{{}}}' in message template. Expected one of: {}, tag:<key>, extra:<key>",
+                            name,
+                            PLACEHOLDERS.join(", ")
+                        ))
+                    })?;
+                    if !text.is_empty() {
+                        segments.push(Segment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(segment);
+                }
+                '}' => {
+                    return Err(SentryStrError::Config(
+                        "Unmatched '}' in message template; write '}}' for a literal brace"
+                            .to_string(),
+                    ));
+                }
+                c => text.push(c),
+            }
+        }
+
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Ok(Self { segments })
+    }
+
+    /// The segment for the placeholder `name`, if it is one.
+    fn placeholder(name: &str) -> Option<Segment> {
+        let segment = match name {
+            "level" => Segment::Level,
+            "message" => Segment::Message,
+            "timestamp" => Segment::Timestamp,
+            "author_npub" => Segment::AuthorNpub,
+            "event_id" => Segment::EventId,
+            _ => match name.split_once(':') {
+                Some(("tag", key)) if !key.is_empty() => Segment::Tag(key.to_string()),
+                Some(("extra", key)) if !key.is_empty() => Segment::Extra(key.to_string()),
+                _ => return None,
+            },
+        };
+        Some(segment)
+    }
+
+    pub fn render(&self, event: &MessageEvent) -> String {
+        let mut rendered = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Level => rendered.push_str(event.event.level.as_str()),
+                Segment::Message => {
+                    rendered.push_str(event.event.message.as_deref().unwrap_or_default())
+                }
+                Segment::Timestamp => rendered.push_str(&event.event.timestamp.to_rfc3339()),
+                Segment::AuthorNpub => match event.author.to_bech32() {
+                    Ok(npub) => rendered.push_str(&npub),
+                    Err(_) => rendered.push_str(&event.author.to_hex()),
+                },
+                Segment::EventId => rendered.push_str(&event.nostr_event_id.to_hex()),
+                Segment::Tag(key) => {
+                    rendered.push_str(event.event.tags.get(key).map_or("", String::as_str))
+                }
+                Segment::Extra(key) => match event.event.extra.get(key) {
+                    Some(serde_json::Value::String(value)) => rendered.push_str(value),
+                    Some(value) => rendered.push_str(&value.to_string()),
+                    None => {}
+                },
+            }
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, Level};
+    use chrono::{TimeZone, Utc};
+    use nostr::{EventId, Keys};
+
+    fn message_event(event: Event) -> MessageEvent {
+        MessageEvent {
+            event: event.with_timestamp(Utc.with_ymd_and_hms(2026, 10, 1, 12, 0, 0).unwrap()),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            received_at: Utc::now(),
+        }
+    }
+
+    fn render(template: &str, event: Event) -> String {
+        MessageTemplate::parse(template)
+            .unwrap()
+            .render(&message_event(event))
+    }
+
+    #[test]
+    fn placeholders_are_filled_from_the_event() {
+        let event = Event::new()
+            .with_level(Level::Error)
+            .with_message("card declined")
+            .with_tag("service", "billing")
+            .with_extra("order", "A-17".into())
+            .with_extra("attempts", 3.into());
+
+        for (template, rendered) in [
+            ("[{level}] {message}", "[error] card declined"),
+            ("{tag:service}/{extra:order}", "billing/A-17"),
+            ("tries: {extra:attempts}", "tries: 3"),
+            ("at {timestamp}", "at 2026-10-01T12:00:00+00:00"),
+            ("{{literal}} {level}", "{literal} error"),
+            ("no placeholders", "no placeholders"),
+        ] {
+            assert_eq!(render(template, event.clone()), rendered, "{}", template);
+        }
+
+        let message_event = message_event(event);
+        let ids = MessageTemplate::parse("{author_npub} {event_id}")
+            .unwrap()
+            .render(&message_event);
+        assert_eq!(
+            ids,
+            format!(
+                "{} {}",
+                message_event.author.to_bech32().unwrap(),
+                "0".repeat(64)
+            )
+        );
+    }
+
+    #[test]
+    fn missing_values_render_empty() {
+        let event = Event::new().with_level(Level::Warning);
+
+        assert_eq!(
+            render("[{level}] {message}|{tag:service}|{extra:order}", event),
+            "[warning] ||"
+        );
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        for template in [
+            "{unknown}",
+            "{tag:}",
+            "{extra}",
+            "{message",
+            "message}",
+            "{}",
+        ] {
+            assert!(
+                matches!(
+                    MessageTemplate::parse(template),
+                    Err(SentryStrError::Config(_))
+                ),
+                "{}",
+                template
+            );
+        }
+    }
+}
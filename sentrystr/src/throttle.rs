@@ -0,0 +1,301 @@
+use crate::rt::Instant;
+use crate::{DirectMessageSender, MessageEvent, RateLimit, Result};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+/// Alert fingerprints remembered for cooldowns and summaries. Past this, the least
+/// recently seen one is forgotten.
+pub const DM_THROTTLE_MAX_FINGERPRINTS: usize = 1024;
+
+/// An alert fingerprint: when it was last sent and how often it was suppressed since
+/// the last summary.
+struct Alert {
+    message: String,
+    last_sent: Option<Instant>,
+    last_seen: Instant,
+    /// Repeats within the cooldown.
+    duplicates: u64,
+    /// Alerts past the rate limit, repeats or not.
+    over_limit: u64,
+}
+
+struct ThrottleState {
+    window_start: Instant,
+    sent_in_window: u32,
+    alerts: HashMap<String, Alert>,
+    /// Suppressed alerts of fingerprints forgotten before their summary.
+    forgotten: u64,
+}
+
+/// Rate limit and per-fingerprint cooldown of a [`DirectMessageSender`], see
+/// [`crate::DirectMessageBuilder::with_rate_limit`] and
+/// [`crate::DirectMessageBuilder::with_cooldown`]. Suppressed alerts are counted and
+/// summarized in one message per window. Shared by clones of the sender.
+pub(crate) struct Throttle {
+    rate_limit: Option<RateLimit>,
+    cooldown: Option<Duration>,
+    state: Mutex<ThrottleState>,
+    /// Sends the summaries, unthrottled.
+    sender: DirectMessageSender,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl Throttle {
+    /// Starts sending summaries through `sender` at the end of each window: the rate
+    /// limit's, or the cooldown when shorter. Needs a Tokio runtime.
+    pub(crate) fn spawn(
+        sender: DirectMessageSender,
+        rate_limit: Option<RateLimit>,
+        cooldown: Option<Duration>,
+    ) -> Result<Arc<Self>> {
+        let runtime = tokio::runtime::Handle::try_current().map_err(|_| {
+            crate::SentryStrError::Config("DM throttling needs a Tokio runtime".to_string())
+        })?;
+        let window = [rate_limit.map(|limit| limit.per), cooldown]
+            .into_iter()
+            .flatten()
+            .min()
+            .filter(|window| !window.is_zero())
+            .ok_or_else(|| {
+                crate::SentryStrError::Config(
+                    "DM throttling needs a rate limit or a cooldown".to_string(),
+                )
+            })?;
+
+        let throttle = Arc::new(Self {
+            rate_limit,
+            cooldown,
+            state: Mutex::new(ThrottleState {
+                window_start: Instant::now(),
+                sent_in_window: 0,
+                alerts: HashMap::new(),
+                forgotten: 0,
+            }),
+            sender,
+            task: Mutex::new(None),
+        });
+
+        let weak: Weak<Self> = Arc::downgrade(&throttle);
+        let task = runtime.spawn(async move {
+            let mut ticks = crate::rt::interval(window);
+            ticks.tick().await;
+            loop {
+                ticks.tick().await;
+                let Some(throttle) = weak.upgrade() else {
+                    return;
+                };
+                if let Some(summary) = throttle.take_summary()
+                    && let Err(e) = throttle.sender.send_custom_message(&summary).await
+                {
+                    eprintln!("Failed to send suppressed alert summary: {}", e);
+                }
+            }
+        });
+        *throttle.task.lock().unwrap() = Some(task.abort_handle());
+
+        Ok(throttle)
+    }
+
+    /// Whether the alert for `event` may be sent now. Counts it for the next summary
+    /// when not.
+    pub(crate) fn allow(&self, event: &MessageEvent) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(limit) = self.rate_limit
+            && now.duration_since(state.window_start) >= limit.per
+        {
+            state.window_start = now;
+            state.sent_in_window = 0;
+        }
+        let under_limit = self
+            .rate_limit
+            .is_none_or(|limit| state.sent_in_window < limit.max_events);
+
+        let key = fingerprint(event);
+        if !state.alerts.contains_key(&key) && state.alerts.len() >= DM_THROTTLE_MAX_FINGERPRINTS {
+            forget_oldest(&mut state);
+        }
+        let alert = state.alerts.entry(key).or_insert_with(|| Alert {
+            message: event
+                .event
+                .message
+                .clone()
+                .unwrap_or_else(|| "(no message)".to_string()),
+            last_sent: None,
+            last_seen: now,
+            duplicates: 0,
+            over_limit: 0,
+        });
+        alert.last_seen = now;
+
+        let cooled_down = match (self.cooldown, alert.last_sent) {
+            (Some(cooldown), Some(last_sent)) => now.duration_since(last_sent) >= cooldown,
+            _ => true,
+        };
+        if !cooled_down {
+            alert.duplicates += 1;
+            false
+        } else if !under_limit {
+            alert.over_limit += 1;
+            false
+        } else {
+            alert.last_sent = Some(now);
+            state.sent_in_window += 1;
+            true
+        }
+    }
+
+    /// The summary of the alerts suppressed since the last one, most suppressed first.
+    /// `None` if there were none.
+    fn take_summary(&self) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let mut lines: Vec<(u64, String)> = Vec::new();
+        for alert in state.alerts.values_mut() {
+            let duplicates = std::mem::take(&mut alert.duplicates);
+            if duplicates > 0 {
+                lines.push((
+                    duplicates,
+                    format!(
+                        "Suppressed {} duplicate alerts for '{}'",
+                        duplicates, alert.message
+                    ),
+                ));
+            }
+            let over_limit = std::mem::take(&mut alert.over_limit);
+            if over_limit > 0 {
+                lines.push((
+                    over_limit,
+                    format!(
+                        "Suppressed {} alerts for '{}' over the rate limit",
+                        over_limit, alert.message
+                    ),
+                ));
+            }
+        }
+        let forgotten = std::mem::take(&mut state.forgotten);
+        if lines.is_empty() && forgotten == 0 {
+            return None;
+        }
+
+        lines.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        let mut summary: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+        if forgotten > 0 {
+            summary.push(format!("Suppressed {} other alerts", forgotten));
+        }
+        Some(summary.join("\n"))
+    }
+}
+
+impl Drop for Throttle {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+
+        if let Some(summary) = self.take_summary()
+            && let Ok(runtime) = tokio::runtime::Handle::try_current()
+        {
+            let sender = self.sender.clone();
+            runtime.spawn(async move {
+                if let Err(e) = sender.send_custom_message(&summary).await {
+                    eprintln!("Failed to send suppressed alert summary: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// Alerts with the same level, message and logger (the tracing target) are duplicates.
+fn fingerprint(event: &MessageEvent) -> String {
+    format!(
+        "{}\u{1f}{}\u{1f}{}",
+        event.event.level,
+        event.event.message.as_deref().unwrap_or_default(),
+        event.event.logger.as_deref().unwrap_or_default()
+    )
+}
+
+/// Forgets the least recently seen fingerprint, keeping its suppressed counts.
+fn forget_oldest(state: &mut ThrottleState) {
+    let Some(oldest) = state
+        .alerts
+        .iter()
+        .min_by_key(|(_, alert)| alert.last_seen)
+        .map(|(key, _)| key.clone())
+    else {
+        return;
+    };
+    if let Some(alert) = state.alerts.remove(&oldest) {
+        state.forgotten += alert.duplicates + alert.over_limit;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DirectMessageBuilder, Event, Level};
+    use nostr::{EventId, Keys};
+    use nostr_sdk::Client;
+
+    fn sender() -> DirectMessageSender {
+        let keys = Keys::generate();
+        DirectMessageBuilder::new()
+            .with_client(Client::new(keys.clone()))
+            .with_keys(keys)
+            .with_recipient(Keys::generate().public_key())
+            .build()
+            .unwrap()
+    }
+
+    fn alert(message: &str) -> MessageEvent {
+        MessageEvent {
+            event: Event::new().with_message(message).with_level(Level::Error),
+            author: Keys::generate().public_key(),
+            nostr_event_id: EventId::all_zeros(),
+            received_at: chrono::Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn repeats_within_the_cooldown_are_duplicates() {
+        let throttle = Throttle::spawn(sender(), None, Some(Duration::from_secs(3600))).unwrap();
+
+        assert!(throttle.allow(&alert("Database connection failed")));
+        assert!(!throttle.allow(&alert("Database connection failed")));
+        assert!(!throttle.allow(&alert("Database connection failed")));
+        assert!(throttle.allow(&alert("Disk full")));
+
+        assert_eq!(
+            throttle.take_summary().as_deref(),
+            Some("Suppressed 2 duplicate alerts for 'Database connection failed'")
+        );
+        assert_eq!(throttle.take_summary(), None);
+    }
+
+    #[tokio::test]
+    async fn distinct_alerts_past_the_rate_limit_are_not_duplicates() {
+        let limit = RateLimit::new(1, Duration::from_secs(3600));
+        let throttle = Throttle::spawn(sender(), Some(limit), None).unwrap();
+
+        assert!(throttle.allow(&alert("Database connection failed")));
+        assert!(!throttle.allow(&alert("Disk full")));
+
+        assert_eq!(
+            throttle.take_summary().as_deref(),
+            Some("Suppressed 1 alerts for 'Disk full' over the rate limit")
+        );
+    }
+
+    #[tokio::test]
+    async fn alerts_tell_targets_apart() {
+        let throttle = Throttle::spawn(sender(), None, Some(Duration::from_secs(3600))).unwrap();
+        let mut other_target = alert("Database connection failed");
+        other_target.event.logger = Some("billing".to_string());
+
+        assert!(throttle.allow(&alert("Database connection failed")));
+        assert!(throttle.allow(&other_target));
+    }
+}
@@ -0,0 +1,182 @@
+//! Timing of transactions, see [`crate::NostrSentryClient::start_transaction`].
+
+use crate::rt::Instant;
+use crate::{CaptureOutcome, Event, NostrSentryClient, Result};
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Measures a transaction from [`NostrSentryClient::start_transaction`] until
+/// [`TransactionGuard::finish`] or drop, then captures an [`Event::transaction_event`]
+/// with the measured duration and the tags and extras set on the guard.
+///
+/// Dropping the guard captures the event on a spawned task, so it needs a Tokio runtime;
+/// outside one the event is lost. Call `finish` to get the outcome.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use sentrystr::NostrSentryClient;
+/// # use std::sync::Arc;
+/// # async fn example(client: Arc<NostrSentryClient>) -> sentrystr::Result<()> {
+/// let mut transaction = client.start_transaction("checkout");
+/// transaction.set_extra("items", serde_json::json!(3));
+/// // ...
+/// transaction.finish().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct TransactionGuard {
+    client: Arc<NostrSentryClient>,
+    started: Instant,
+    /// Taken when the event is captured, so it is captured once.
+    event: Option<Event>,
+}
+
+impl TransactionGuard {
+    pub(crate) fn new(client: Arc<NostrSentryClient>, name: String) -> Self {
+        Self {
+            client,
+            started: Instant::now(),
+            event: Some(Event::transaction_event(name, Duration::ZERO)),
+        }
+    }
+
+    /// Time since the transaction started.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn set_tag(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        if let Some(ref mut event) = self.event {
+            event.tags.insert(key.into(), value.into());
+        }
+    }
+
+    pub fn set_extra(&mut self, key: impl Into<String>, value: serde_json::Value) {
+        if let Some(ref mut event) = self.event {
+            event.extra.insert(key.into(), value);
+        }
+    }
+
+    /// Captures the transaction now, stamped with its end.
+    pub async fn finish(mut self) -> Result<CaptureOutcome> {
+        let event = self.take_event().expect("the event is only taken once");
+        self.client.capture_event(event).await
+    }
+
+    fn take_event(&mut self) -> Option<Event> {
+        let elapsed = self.elapsed();
+        self.event
+            .take()
+            .map(|event| event.with_duration(elapsed).with_timestamp(Utc::now()))
+    }
+}
+
+impl Drop for TransactionGuard {
+    fn drop(&mut self) {
+        let Some(event) = self.take_event() else {
+            return;
+        };
+
+        let name = event.transaction.clone().unwrap_or_default();
+        let client = Arc::clone(&self.client);
+        let spawned = crate::rt::spawn(async move {
+            if let Err(e) = client.capture_event(event).await {
+                eprintln!("Failed to capture transaction: {}", e);
+            }
+        });
+        if !spawned {
+            eprintln!(
+                "Transaction {} dropped outside a Tokio runtime and not captured",
+                name
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Config;
+    use crate::transport::{Transport, TransportFuture};
+    use nostr::Keys;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default, Clone)]
+    struct RecordingTransport(Arc<Mutex<Vec<Event>>>);
+
+    impl Transport for RecordingTransport {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+            self.0.lock().unwrap().push(event.clone());
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    impl RecordingTransport {
+        /// The events sent, once `count` were or after a few seconds.
+        async fn wait_for(&self, count: usize) -> Vec<Event> {
+            for _ in 0..250 {
+                if self.0.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    /// A client forwarding to `transport`, whose queue publishes to an unreachable relay.
+    async fn client(transport: &RecordingTransport) -> Arc<NostrSentryClient> {
+        let config = Config::from_keys(&Keys::generate(), ["wss://relay.example.com"])
+            .unwrap()
+            .with_queue(10, Duration::from_secs(1))
+            .with_additional_transport(transport.clone());
+        Arc::new(NostrSentryClient::new(config).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn finishing_captures_the_duration_tags_and_extras() {
+        let transport = RecordingTransport::default();
+        let client = client(&transport).await;
+
+        let mut transaction = client.start_transaction("checkout");
+        transaction.set_tag("region", "eu");
+        transaction.set_extra("items", serde_json::json!(3));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let outcome = transaction.finish().await.unwrap();
+        assert!(outcome.event_id().is_some());
+
+        let events = transport.wait_for(1).await;
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.transaction.as_deref(), Some("checkout"));
+        assert!(event.duration_ms.unwrap() >= 50, "{:?}", event.duration_ms);
+        assert_eq!(event.tags["region"], "eu");
+        assert_eq!(event.extra["items"], 3);
+    }
+
+    #[tokio::test]
+    async fn dropping_inside_a_runtime_captures_once() {
+        let transport = RecordingTransport::default();
+        let client = client(&transport).await;
+
+        {
+            let mut transaction = client.start_transaction("nightly-report");
+            transaction.set_tag("job", "report");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        let events = transport.wait_for(1).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(transport.0.lock().unwrap().len(), 1);
+        let event = &events[0];
+        assert_eq!(event.transaction.as_deref(), Some("nightly-report"));
+        assert_eq!(event.tags["job"], "report");
+        assert!(event.duration_ms.unwrap() >= 20);
+    }
+}
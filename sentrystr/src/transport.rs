@@ -0,0 +1,58 @@
+//! Destinations receiving events besides the Nostr relays, see
+//! [`crate::Config::with_additional_transport`].
+
+use crate::{Event, NostrSentryClient, Result};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// What [`Transport::send`] returns.
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>>;
+
+/// A destination every captured event is sent to alongside the relays, such as a hosted
+/// Sentry during a migration (`compat::SentryDsnTransport`).
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    /// Name the destination's metrics are recorded under.
+    fn name(&self) -> &str;
+
+    /// Sends `event`. A failure is logged and counted, and never fails the capture.
+    /// Return `SentryStrError::RateLimited` for events dropped while the destination
+    /// asked to back off.
+    fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a>;
+}
+
+/// Outcomes of the sends to one transport.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransportMetrics {
+    pub sent: u64,
+    pub failed: u64,
+    /// Events dropped because the destination asked to back off.
+    pub rate_limited: u64,
+}
+
+/// A [`Transport`] capturing through a client, so integrations such as a logger share
+/// its relays, queue, sampling and rate limit with the events captured directly.
+pub struct ClientTransport(Arc<NostrSentryClient>);
+
+impl ClientTransport {
+    pub fn new(client: Arc<NostrSentryClient>) -> Self {
+        Self(client)
+    }
+}
+
+impl fmt::Debug for ClientTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClientTransport")
+    }
+}
+
+impl Transport for ClientTransport {
+    fn name(&self) -> &str {
+        "nostr"
+    }
+
+    fn send<'a>(&'a self, event: &'a Event) -> TransportFuture<'a> {
+        Box::pin(async move { self.0.capture_event(event.clone()).await.map(|_| ()) })
+    }
+}
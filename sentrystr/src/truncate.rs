@@ -0,0 +1,302 @@
+use crate::{Event, Result, SentryStrError};
+use serde_json::Value;
+
+/// Appended to strings cut to fit `Config::max_event_bytes`.
+const MARKER: &str = "…[truncated]";
+/// Extra listing the extras dropped to fit `Config::max_event_bytes`.
+const TRUNCATED_KEYS: &str = "truncated_keys";
+
+/// Replaces what is nested deeper than `Config::max_extra_depth`.
+const DEPTH_MARKER: &str = "…[max depth]";
+
+/// Serialized sizes of an event that had to be truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Truncation {
+    pub(crate) original: usize,
+    pub(crate) truncated: usize,
+}
+
+/// Shrinks `event` until its published content fits in `max_bytes`, where
+/// `content_len` gives the length of the content holding a JSON of so many bytes, e.g.
+/// its NIP-44 ciphertext. Sizes are reported as published.
+pub(crate) fn truncate_event(
+    event: &mut Event,
+    max_bytes: usize,
+    content_len: impl Fn(usize) -> usize,
+) -> Result<Option<Truncation>> {
+    let budget = plaintext_budget(max_bytes, &content_len);
+    match truncate_json(event, budget) {
+        Ok(truncation) => Ok(truncation.map(|truncation| Truncation {
+            original: content_len(truncation.original),
+            truncated: content_len(truncation.truncated),
+        })),
+        Err(SentryStrError::EventTooLarge { size, .. }) => Err(SentryStrError::EventTooLarge {
+            size: content_len(size),
+            max: max_bytes,
+        }),
+        Err(e) => Err(e),
+    }
+}
+
+/// The largest JSON whose content is at most `max_bytes` long. `content_len` never
+/// shrinks as the JSON grows.
+fn plaintext_budget(max_bytes: usize, content_len: impl Fn(usize) -> usize) -> usize {
+    let (mut low, mut high) = (0, max_bytes);
+    if content_len(high) <= max_bytes {
+        return high;
+    }
+    while low < high {
+        let middle = low + (high - low).div_ceil(2);
+        if content_len(middle) <= max_bytes {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    low
+}
+
+/// Length of the base64 NIP-44 v2 payload encrypting `plaintext` bytes: a version
+/// byte, the nonce, the length prefix, the padded plaintext and the MAC.
+pub(crate) fn nip44_len(plaintext: usize) -> usize {
+    let padded = if plaintext <= 32 {
+        32
+    } else {
+        let next_power = 1usize << (usize::BITS - (plaintext - 1).leading_zeros());
+        let chunk = if next_power <= 256 {
+            32
+        } else {
+            next_power / 8
+        };
+        chunk * ((plaintext - 1) / chunk + 1)
+    };
+    let payload = 1 + 32 + 2 + padded + 32;
+    payload.div_ceil(3) * 4
+}
+
+/// Shrinks `event` until its JSON fits in `max_bytes`: string extras are cut, largest
+/// first, then extras are dropped into the `truncated_keys` extra, then breadcrumbs are
+/// dropped oldest first, then the message is cut. Other fields are never touched, so an
+/// event that still does not fit is an error. Returns `None` when nothing was cut.
+fn truncate_json(event: &mut Event, max_bytes: usize) -> Result<Option<Truncation>> {
+    let original = size(event)?;
+    if original <= max_bytes {
+        return Ok(None);
+    }
+
+    let mut current = original;
+    for key in extras_by_size(event) {
+        if let Some(Value::String(value)) = event.extra.get_mut(&key)
+            && value.len() > MARKER.len()
+        {
+            *value = cut(value, current - max_bytes);
+            current = size(event)?;
+            if current <= max_bytes {
+                return Ok(Some(Truncation {
+                    original,
+                    truncated: current,
+                }));
+            }
+        }
+    }
+
+    for key in extras_by_size(event) {
+        if key == TRUNCATED_KEYS {
+            continue;
+        }
+        event.extra.remove(&key);
+        let dropped = event
+            .extra
+            .entry(TRUNCATED_KEYS.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(keys) = dropped {
+            keys.push(Value::String(key));
+        }
+
+        current = size(event)?;
+        if current <= max_bytes {
+            return Ok(Some(Truncation {
+                original,
+                truncated: current,
+            }));
+        }
+    }
+
+    while current > max_bytes && !event.breadcrumbs.is_empty() {
+        event.breadcrumbs.remove(0);
+        current = size(event)?;
+    }
+
+    if current > max_bytes
+        && let Some(ref mut message) = event.message
+    {
+        *message = cut(message, current - max_bytes);
+        current = size(event)?;
+    }
+
+    if current > max_bytes {
+        return Err(SentryStrError::EventTooLarge {
+            size: current,
+            max: max_bytes,
+        });
+    }
+    Ok(Some(Truncation {
+        original,
+        truncated: current,
+    }))
+}
+
+/// Replaces the arrays and objects of `value` nested more than `max_depth` levels deep,
+/// counting `value` itself as level 1, with a marker string.
+pub(crate) fn limit_depth(value: &mut Value, max_depth: usize) {
+    match value {
+        Value::Array(_) | Value::Object(_) if max_depth == 0 => {
+            *value = Value::String(DEPTH_MARKER.to_string());
+        }
+        Value::Array(values) => {
+            for value in values {
+                limit_depth(value, max_depth - 1);
+            }
+        }
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                limit_depth(value, max_depth - 1);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn size(event: &Event) -> Result<usize> {
+    Ok(serde_json::to_vec(event)?.len())
+}
+
+/// Extra keys, the largest serialized value first.
+fn extras_by_size(event: &Event) -> Vec<String> {
+    let mut keys: Vec<(usize, &String)> = event
+        .extra
+        .iter()
+        .map(|(key, value)| (value.to_string().len(), key))
+        .collect();
+    keys.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+    keys.into_iter().map(|(_, key)| key.clone()).collect()
+}
+
+/// `value` shortened by at least `excess` bytes plus the marker, at a char boundary.
+fn cut(value: &str, excess: usize) -> String {
+    let mut keep = value.len().saturating_sub(excess + MARKER.len());
+    while !value.is_char_boundary(keep) {
+        keep -= 1;
+    }
+    format!("{}{}", &value[..keep], MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nostr::Keys;
+    use nostr::nips::nip44;
+
+    #[test]
+    fn nip44_len_matches_the_ciphertext() {
+        let sender = Keys::generate();
+        let reader = Keys::generate().public_key();
+        for len in [1, 32, 33, 64, 65, 256, 257, 1000, 4096, 10_000] {
+            let plaintext = "a".repeat(len);
+            let ciphertext =
+                nip44::encrypt(sender.secret_key(), &reader, &plaintext, nip44::Version::V2)
+                    .unwrap();
+            assert_eq!(
+                nip44_len(plaintext.len()),
+                ciphertext.len(),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn encrypted_content_fits_the_limit() {
+        let mut event = Event::new()
+            .with_message("payment failed")
+            .with_extra("body", "x".repeat(20_000).into());
+
+        let truncation = truncate_event(&mut event, 8_000, nip44_len)
+            .unwrap()
+            .unwrap();
+
+        assert!(truncation.truncated <= 8_000);
+        let json = serde_json::to_vec(&event).unwrap();
+        assert!(nip44_len(json.len()) <= 8_000);
+    }
+
+    #[test]
+    fn plaintext_events_fill_the_limit() {
+        let mut event = Event::new().with_extra("body", "x".repeat(20_000).into());
+
+        let truncation = truncate_event(&mut event, 8_000, |len| len)
+            .unwrap()
+            .unwrap();
+
+        assert!(truncation.truncated <= 8_000);
+        assert!(truncation.truncated > 7_900);
+    }
+
+    #[test]
+    fn sizes_of_events_too_large_are_reported_as_published() {
+        let mut event = Event::new().with_message("x".repeat(100));
+
+        let error = truncate_event(&mut event, 50, nip44_len).unwrap_err();
+
+        assert!(matches!(
+            error,
+            SentryStrError::EventTooLarge { size, max: 50 } if size > 50
+        ));
+    }
+
+    #[test]
+    fn values_nested_too_deep_are_replaced() {
+        let nested = serde_json::json!({"a": {"b": {"c": 1}}, "list": [[1], 2], "n": 3});
+        let cases = [
+            (3, nested.clone()),
+            (
+                2,
+                serde_json::json!({"a": {"b": DEPTH_MARKER}, "list": [DEPTH_MARKER, 2], "n": 3}),
+            ),
+            (
+                1,
+                serde_json::json!({"a": DEPTH_MARKER, "list": DEPTH_MARKER, "n": 3}),
+            ),
+            (0, Value::String(DEPTH_MARKER.to_string())),
+        ];
+
+        for (max_depth, expected) in cases {
+            let mut value = nested.clone();
+            limit_depth(&mut value, max_depth);
+            assert_eq!(value, expected, "max depth {}", max_depth);
+        }
+
+        let mut scalar = Value::from("flat");
+        limit_depth(&mut scalar, 0);
+        assert_eq!(scalar, "flat");
+    }
+
+    #[test]
+    fn pathologically_deep_values_are_cut() {
+        let mut deep = Value::Null;
+        for _ in 0..1_000 {
+            deep = Value::Array(vec![deep]);
+        }
+
+        limit_depth(&mut deep, 20);
+
+        let mut depth = 0;
+        let mut current = &deep;
+        while let Value::Array(values) = current {
+            depth += 1;
+            current = &values[0];
+        }
+        assert_eq!(depth, 20);
+        assert_eq!(*current, DEPTH_MARKER);
+    }
+}
@@ -0,0 +1,58 @@
+//! JavaScript bindings for reporting errors from a browser, behind the `wasm` feature.
+//!
+//! ```js
+//! import init_wasm, { init, capture_message } from "./pkg/sentrystr.js";
+//!
+//! await init_wasm();
+//! await init(["wss://relay.damus.io"], "nsec1...");
+//! await capture_message("error", "Checkout failed");
+//! ```
+
+use crate::{Config, Event, Level, NostrSentryClient};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    /// The client of [`init`]; a page has one thread, so one client.
+    static CLIENT: RefCell<Option<Rc<NostrSentryClient>>> = const { RefCell::new(None) };
+}
+
+fn client() -> Result<Rc<NostrSentryClient>, JsError> {
+    CLIENT
+        .with(|client| client.borrow().clone())
+        .ok_or_else(|| JsError::new("SentryStr is not initialized, call init first"))
+}
+
+/// Connects to `relays`, publishing with the secret key `nsec` (bech32 or hex). Calling
+/// it again replaces the client.
+#[wasm_bindgen]
+pub async fn init(relays: Vec<String>, nsec: String) -> Result<(), JsError> {
+    let config = Config::try_new(nsec, relays)?;
+    let client = NostrSentryClient::new(config).await?;
+    CLIENT.with(|current| *current.borrow_mut() = Some(Rc::new(client)));
+    Ok(())
+}
+
+/// Captures `message` at `level` (`debug`, `info`, `warning`, `error` or `fatal`).
+/// Resolves to the id of the published Nostr event, or `undefined` when the event was
+/// not published, e.g. because it was sampled out.
+#[wasm_bindgen]
+pub async fn capture_message(level: String, message: String) -> Result<Option<String>, JsError> {
+    let level: Level = level.parse()?;
+    let event = Event::new().with_message(message).with_level(level);
+    let outcome = client()?.capture_event(event).await?;
+    Ok(outcome.event_id().map(|id| id.to_hex()))
+}
+
+/// Closes the client, giving pending events up to `timeout_ms` to go out.
+#[wasm_bindgen]
+pub async fn close(timeout_ms: u32) -> Result<(), JsError> {
+    let client = client()?;
+    CLIENT.with(|current| *current.borrow_mut() = None);
+    client
+        .close(Duration::from_millis(u64::from(timeout_ms)))
+        .await;
+    Ok(())
+}